@@ -14,6 +14,9 @@ cargo clean
 cargo build --quiet --release --target $1 --features sndfile
 cp target/$1/release/mxyzptlk $base/mxyzptlk-libsndfile
 
+cargo build --quiet --release --target $1 --no-default-features --features log4rs-mdc,crossterm
+cp target/$1/release/mxyzptlk $base/mxyzptlk-crossterm
+
 cargo build --quiet --release --target $1
 cp target/$1/release/mxyzptlk $base/mxyzptlk
 
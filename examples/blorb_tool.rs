@@ -0,0 +1,126 @@
+//! List, extract, and add resources in a Blorb file.
+//!
+//! ```text
+//! cargo run --example blorb_tool -- game.zblorb list
+//! cargo run --example blorb_tool -- game.zblorb extract Snd 3 out.ogg
+//! cargo run --example blorb_tool -- game.zblorb add Pict 1 cover.png
+//! ```
+//!
+//! The Blorb reader lives in the interpreter binary, so it is compiled in here from source.
+#[macro_use]
+extern crate log;
+
+#[allow(dead_code)]
+#[path = "../src/error.rs"]
+mod error;
+
+#[allow(dead_code)]
+#[path = "../src/blorb.rs"]
+mod blorb;
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    process::exit,
+};
+
+use blorb::{Blorb, BlorbBuilder};
+use tempfile::NamedTempFile;
+
+const USAGE: &str = "usage: blorb_tool <blorb> list
+       blorb_tool <blorb> extract <usage> <number> <file>
+       blorb_tool <blorb> add <usage> <number> <file>";
+
+// Chunk id for a resource file, from its extension
+fn format(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("PNG "),
+        "jpg" | "jpeg" => Some("JPEG"),
+        "ogg" => Some("OGGV"),
+        "aif" | "aiff" => Some("AIFF"),
+        "mod" => Some("MOD "),
+        "z3" | "z4" | "z5" | "z7" | "z8" => Some("ZCOD"),
+        _ => None,
+    }
+}
+
+fn read_blorb(path: &str) -> Result<Blorb, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    Blorb::try_from(&mut file).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn resource(usage: &str, number: &str) -> Result<(String, u32), String> {
+    let number = number
+        .parse()
+        .map_err(|_| format!("Invalid resource number: {}", number))?;
+    Ok((usage.to_string(), number))
+}
+
+fn list(path: &str) -> Result<(), String> {
+    let blorb = read_blorb(path)?;
+    println!("Usage  Number  Format      Size  Offset");
+    for r in blorb.chunks() {
+        println!(
+            "{}  {:>6}  {}  {:>8}  ${:06x}",
+            r.usage(),
+            r.number(),
+            r.format(),
+            r.size(),
+            r.offset()
+        );
+    }
+    Ok(())
+}
+
+fn extract(path: &str, usage: &str, number: &str, output: &str) -> Result<(), String> {
+    let blorb = read_blorb(path)?;
+    let (usage, number) = resource(usage, number)?;
+    match blorb.extract(&usage, number) {
+        Some(data) => fs::write(output, data).map_err(|e| format!("{}: {}", output, e)),
+        None => Err(format!("No {} resource {} in {}", usage, number, path)),
+    }
+}
+
+fn add(path: &str, usage: &str, number: &str, input: &str) -> Result<(), String> {
+    let mut builder = BlorbBuilder::from(read_blorb(path)?);
+    let (usage, number) = resource(usage, number)?;
+    let format = format(input).ok_or(format!("Unknown resource format: {}", input))?;
+    let data = fs::read(input).map_err(|e| format!("{}: {}", input, e))?;
+    builder.add(&usage, number, format, data);
+
+    // Write the new file next to the original and rename it into place, so a failed write
+    // leaves the original untouched
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut file = NamedTempFile::new_in(dir).map_err(|e| format!("{}: {}", path, e))?;
+    let mut writer = BufWriter::new(file.as_file_mut());
+    builder
+        .write(&mut writer)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    drop(writer);
+    file.persist(path)
+        .map_err(|e| format!("{}: {}", path, e.error))?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        [path, "list"] => list(path),
+        [path, "extract", usage, number, output] => extract(path, usage, number, output),
+        [path, "add", usage, number, input] => add(path, usage, number, input),
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
@@ -2,7 +2,7 @@ use core::fmt;
 use std::{
     convert::TryFrom,
     fs::File,
-    io::{self, Read},
+    io::{self, Read, Write},
 };
 
 /// An IFF "Chunk"
@@ -42,7 +42,7 @@ impl Chunk {
     /// Arguments:
     /// * `id`: IFF Id of the chunk
     /// * `data`: The chunk data.  Data will be padded with a 0 if needed to ensure
-    /// the vector is an even number of bytes
+    ///   the vector is an even number of bytes
     pub fn new_chunk(offset: u32, id: &str, data: Vec<u8>) -> Chunk {
         let length = data.len() as u32;
         // Pad data, if needed
@@ -100,6 +100,42 @@ impl Chunk {
         &self.data
     }
 
+    /// Writes the chunk, and any child chunks, to `writer` as it would appear in an IFF
+    /// file, without first collecting it into a single buffer.
+    ///
+    /// Returns the number of bytes written, including any padding byte.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&self.id)?;
+        writer.write_all(&unsigned_as_vec(self.length as usize, 4))?;
+        let mut written = 8;
+        if self.sub_id.is_empty() {
+            writer.write_all(&self.data)?;
+            written += self.data.len();
+        } else {
+            writer.write_all(&self.sub_id)?;
+            written += 4;
+            for c in &self.chunks {
+                written += c.write(writer)?;
+            }
+        }
+        if written % 2 == 1 {
+            writer.write_all(&[0])?;
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    /// Consumes the chunk, returning its data without copying it
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Consumes the chunk, returning its child chunks without copying them
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
     /// Finds the (first) direct child chunk with the matching id and sub id.
     ///
     /// Arguments:
@@ -222,20 +258,8 @@ impl fmt::Display for Chunk {
 impl From<&Chunk> for Vec<u8> {
     fn from(value: &Chunk) -> Self {
         let mut data = Vec::new();
-        data.extend(&value.id);
-        data.extend(unsigned_as_vec(value.length() as usize, 4));
-        if value.sub_id() == "" {
-            data.extend(value.data());
-        } else {
-            data.extend(&value.sub_id);
-            for c in value.chunks() {
-                data.extend(Vec::from(c))
-            }
-        }
-        if data.len() % 2 == 1 {
-            data.push(0);
-        }
-
+        // Writing to a vector can't fail
+        value.write(&mut data).unwrap();
         data
     }
 }
@@ -246,15 +270,21 @@ impl From<&Vec<u8>> for Chunk {
     }
 }
 
+/// Parses the chunk at `start`.  Anything that runs past the end of the data is truncated,
+/// so a damaged file produces short or missing chunks instead of a panic.
 impl From<(usize, &Vec<u8>)> for Chunk {
     fn from((start, value): (usize, &Vec<u8>)) -> Self {
-        let id = value[start..start + 4].to_vec();
-        let length = vec_as_unsigned(&value[start + 4..start + 8]) as u32;
+        let bytes = |from: usize, to: usize| {
+            value[usize::min(from, value.len())..usize::min(to, value.len())].to_vec()
+        };
+        let id = bytes(start, start + 4);
+        let length = vec_as_unsigned(&bytes(start + 4, start + 8)) as u32;
         if id == [b'F', b'O', b'R', b'M'] {
-            let sub_id = value[start + 8..start + 12].to_vec();
+            let sub_id = bytes(start + 8, start + 12);
             let mut chunks = Vec::new();
             let mut offset = start + 12;
-            while offset < start + length as usize {
+            // Each chunk needs at least an 8-byte id and length
+            while offset < start + length as usize && offset + 8 <= value.len() {
                 let chunk = Chunk::from((offset, value));
                 offset += 8 + chunk.length() as usize;
                 if offset % 2 == 1 {
@@ -272,11 +302,11 @@ impl From<(usize, &Vec<u8>)> for Chunk {
                 data: Vec::new(),
             }
         } else {
-            let data = value[start + 8..start + 8 + length as usize].to_vec();
+            let data = bytes(start + 8, start + 8 + length as usize);
             Chunk {
                 offset: start as u32,
                 id,
-                length,
+                length: data.len() as u32,
                 sub_id: Vec::new(),
                 chunks: Vec::new(),
                 data,
@@ -346,6 +376,26 @@ mod tests {
         assert!(chunk.data().is_empty())
     }
 
+    #[test]
+    fn test_into_data() {
+        let chunk = Chunk::new_chunk(0, "Test", vec![1, 2, 3, 4]);
+        let ptr = chunk.data().as_ptr();
+        let data = chunk.into_data();
+        assert_eq!(data, vec![1, 2, 3, 4]);
+        assert_eq!(data.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_into_chunks() {
+        let c1 = Chunk::new_chunk(12, "Test", vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        let c2 = Chunk::new_chunk(28, "Foo", vec![4, 3, 2, 1]);
+        let chunk = Chunk::new_form(0, "FTst", vec![c1.clone(), c2.clone()]);
+        let ptr = chunk.chunks()[1].data().as_ptr();
+        let chunks = chunk.into_chunks();
+        assert_eq!(chunks, vec![c1, c2]);
+        assert_eq!(chunks[1].data().as_ptr(), ptr);
+    }
+
     #[test]
     fn test_find_chunk() {
         let c1 = Chunk::new_chunk(12, "Test", vec![1, 2, 3, 4, 5, 6, 7, 8]);
@@ -482,6 +532,49 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_from_vec_u8_truncated() {
+        // The second chunk claims 4 bytes of data, but only 2 are left
+        let v = vec![
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x1A, b'S', b'U', b'B', b' ', b'C', b'h',
+            b'n', b'k', 0x00, 0x00, 0x00, 0x02, 0x01, 0x02, b'C', b'h', b'n', b'k', 0x00, 0x00,
+            0x00, 0x04, 0x03, 0x04,
+        ];
+        let chunk = Chunk::from(&v);
+        assert_eq!(
+            chunk.chunks(),
+            &vec![
+                Chunk::new_chunk(0x0c, "Chnk", vec![0x01, 0x02]),
+                Chunk::new_chunk(0x16, "Chnk", vec![0x03, 0x04]),
+            ]
+        );
+
+        // A FORM header cut short
+        let chunk = Chunk::from(&vec![b'F', b'O', b'R', b'M', 0x00, 0x00, 0x01, 0x00, b'S']);
+        assert_eq!(chunk.sub_id(), "S");
+        assert!(chunk.chunks().is_empty());
+
+        let chunk = Chunk::from(&vec![b'C', b'h']);
+        assert_eq!(chunk.id(), "Ch");
+        assert!(chunk.data().is_empty());
+    }
+
+    #[test]
+    fn test_write() {
+        let v = vec![
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x1A, b'S', b'U', b'B', b' ', b'O', b'd',
+            b'd', b' ', 0x00, 0x00, 0x00, 0x03, 0x01, 0x02, 0x03, 0x00, b'C', b'h', b'n', b'k',
+            0x00, 0x00, 0x00, 0x02, 0x04, 0x05,
+        ];
+        let chunk = Chunk::from(&v);
+        // The odd-length child is read without its padding byte, which is written back
+        assert_eq!(chunk.chunks()[0].data(), &vec![0x01, 0x02, 0x03]);
+        let mut data = Vec::new();
+        assert_eq!(chunk.write(&mut data).unwrap(), v.len());
+        assert_eq!(data, v);
+        assert_eq!(Vec::from(&chunk), v);
+    }
+
     #[test]
     fn test_from_file() {
         let v = vec![
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, fs::File};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+};
 
 use iff::Chunk;
 
@@ -243,19 +247,32 @@ impl TryFrom<&Chunk> for Loop {
                 "Chunk id is not 'Loop': '{}'",
                 value.id()
             )
-        } else if value.length() % 8 != 0 {
-            recoverable_error!(
-                ErrorCode::IFhdChunkLength,
-                "Chunk data length should be a multiple of 8: '{}'",
-                value.length()
-            )
         } else {
-            let data = value.data();
-            let mut offset = 0;
-            let mut entries = Vec::new();
-            while data.len() > offset {
-                entries.push(Entry::try_from(&data[offset..offset + 8])?);
-                offset += 8;
+            // Odd-length chunk data is padded, so use the chunk length
+            let data = &value.data()[..usize::min(value.length() as usize, value.data().len())];
+            // Some Blorb files pad the chunk, so only complete entries are read
+            let records = data.chunks_exact(8);
+            if !records.remainder().is_empty() {
+                warn!(
+                    target: "app::sound",
+                    "Ignoring {} trailing bytes in Loop chunk of {} bytes",
+                    records.remainder().len(),
+                    data.len()
+                );
+            }
+
+            let mut entries: Vec<Entry> = Vec::new();
+            for record in records {
+                let entry = Entry::try_from(record)?;
+                if entries.iter().any(|e| e.number() == entry.number()) {
+                    warn!(
+                        target: "app::sound",
+                        "Ignoring duplicate Loop entry for resource {}",
+                        entry.number()
+                    );
+                } else {
+                    entries.push(entry);
+                }
             }
 
             Ok(Loop::new(entries))
@@ -263,12 +280,516 @@ impl TryFrom<&Chunk> for Loop {
     }
 }
 
+/// Text descriptions of resources from the RDes chunk (Blorb 2.0), for players who can't
+/// see the pictures or hear the sounds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RDes {
+    // Usage, number, and description, in file order
+    entries: Vec<(String, u32, String)>,
+}
+
+impl RDes {
+    pub fn new(entries: Vec<(String, u32, String)>) -> RDes {
+        RDes { entries }
+    }
+
+    pub fn entries(&self) -> &Vec<(String, u32, String)> {
+        &self.entries
+    }
+
+    pub fn description(&self, usage: &str, number: u32) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(u, n, _)| u == usage && *n == number)
+            .map(|(_, _, d)| d.as_str())
+    }
+}
+
+impl TryFrom<&Chunk> for RDes {
+    type Error = RuntimeError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        if value.id() != "RDes" {
+            return recoverable_error!(
+                ErrorCode::IFFInvalidChunkId,
+                "Chunk id is not 'RDes': '{}'",
+                value.id()
+            );
+        }
+
+        let data = &value.data()[..usize::min(value.length() as usize, value.data().len())];
+        if data.len() < 4 {
+            return recoverable_error!(
+                ErrorCode::BlorbRDesEntrySize,
+                "RDes chunk is too short: {} bytes",
+                data.len()
+            );
+        }
+
+        let count = iff::vec_as_unsigned(&data[0..4]);
+        let mut entries = Vec::new();
+        let mut offset = 4;
+        for _ in 0..count {
+            // Usage, number, and text length, followed by the UTF-8 text
+            if data.len() < offset + 12 {
+                return recoverable_error!(
+                    ErrorCode::BlorbRDesEntrySize,
+                    "RDes entry {} is truncated",
+                    entries.len()
+                );
+            }
+            let usage: String = data[offset..offset + 4]
+                .iter()
+                .map(|b| *b as char)
+                .collect();
+            let number = iff::vec_as_unsigned(&data[offset + 4..offset + 8]) as u32;
+            let length = iff::vec_as_unsigned(&data[offset + 8..offset + 12]);
+            offset += 12;
+            if data.len() < offset + length {
+                return recoverable_error!(
+                    ErrorCode::BlorbRDesEntrySize,
+                    "RDes entry {} is truncated",
+                    entries.len()
+                );
+            }
+            let text = String::from_utf8_lossy(&data[offset..offset + length]).to_string();
+            offset += length;
+            entries.push((usage, number, text));
+        }
+
+        Ok(RDes::new(entries))
+    }
+}
+
+/// How a picture is scaled as the window changes size, from the Reso chunk.  Each ratio is
+/// a numerator and denominator: `ratio` applies at the standard window size, and `min` and
+/// `max` limit it at other sizes, where 0/0 means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Scaling {
+    ratio: (u32, u32),
+    min: (u32, u32),
+    max: (u32, u32),
+}
+
+impl Scaling {
+    pub fn new(ratio: (u32, u32), min: (u32, u32), max: (u32, u32)) -> Scaling {
+        Scaling { ratio, min, max }
+    }
+
+    pub fn ratio(&self) -> (u32, u32) {
+        self.ratio
+    }
+
+    pub fn min(&self) -> (u32, u32) {
+        self.min
+    }
+
+    pub fn max(&self) -> (u32, u32) {
+        self.max
+    }
+}
+
+/// Picture scaling data from the Reso chunk: the standard window size the pictures were
+/// drawn for, the smallest and largest window sizes, and the scaling of each picture that
+/// has any.  Sizes are (width, height) in pixels.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reso {
+    window: (u32, u32),
+    min_window: (u32, u32),
+    max_window: (u32, u32),
+    // Picture number and scaling, in file order
+    entries: Vec<(u32, Scaling)>,
+}
+
+impl Reso {
+    pub fn new(
+        window: (u32, u32),
+        min_window: (u32, u32),
+        max_window: (u32, u32),
+        entries: Vec<(u32, Scaling)>,
+    ) -> Reso {
+        Reso {
+            window,
+            min_window,
+            max_window,
+            entries,
+        }
+    }
+
+    pub fn window(&self) -> (u32, u32) {
+        self.window
+    }
+
+    pub fn min_window(&self) -> (u32, u32) {
+        self.min_window
+    }
+
+    pub fn max_window(&self) -> (u32, u32) {
+        self.max_window
+    }
+
+    pub fn entries(&self) -> &Vec<(u32, Scaling)> {
+        &self.entries
+    }
+
+    pub fn scaling(&self, number: u32) -> Option<&Scaling> {
+        self.entries
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, s)| s)
+    }
+
+    /// How much a picture with `scaling` is scaled in a window of `window` (width, height).
+    /// The ratio is multiplied by the elbow room factor, how much smaller or larger the
+    /// window is than the standard window, then kept within the minimum and maximum.  A
+    /// picture without scaling has a ratio of 1 and no limits.
+    pub fn scale(&self, scaling: Option<&Scaling>, window: (u32, u32)) -> f64 {
+        // 0/0 is no ratio at all
+        let ratio = |(n, d): (u32, u32)| (d > 0).then(|| n as f64 / d as f64);
+        let erf = if self.window.0 == 0 || self.window.1 == 0 {
+            1.0
+        } else {
+            f64::min(
+                window.0 as f64 / self.window.0 as f64,
+                window.1 as f64 / self.window.1 as f64,
+            )
+        };
+
+        let scaling = scaling.copied().unwrap_or_default();
+        let mut scale = erf * ratio(scaling.ratio).unwrap_or(1.0);
+        if let Some(min) = ratio(scaling.min) {
+            scale = scale.max(min);
+        }
+        if let Some(max) = ratio(scaling.max).filter(|max| *max > 0.0) {
+            scale = scale.min(max);
+        }
+        scale
+    }
+}
+
+// Pairs of 4-byte values
+fn pair(data: &[u8]) -> (u32, u32) {
+    (
+        iff::vec_as_unsigned(&data[0..4]) as u32,
+        iff::vec_as_unsigned(&data[4..8]) as u32,
+    )
+}
+
+impl TryFrom<&Chunk> for Reso {
+    type Error = RuntimeError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        if value.id() != "Reso" {
+            return recoverable_error!(
+                ErrorCode::IFFInvalidChunkId,
+                "Chunk id is not 'Reso': '{}'",
+                value.id()
+            );
+        }
+
+        // The window sizes, followed by 28 bytes for each picture
+        let data = &value.data()[..usize::min(value.length() as usize, value.data().len())];
+        if data.len() < 24 || !(data.len() - 24).is_multiple_of(28) {
+            return recoverable_error!(
+                ErrorCode::BlorbResoEntrySize,
+                "Reso chunk should be 24 bytes plus a multiple of 28 bytes: {}",
+                data.len()
+            );
+        }
+
+        let entries = data[24..]
+            .chunks_exact(28)
+            .map(|e| {
+                (
+                    iff::vec_as_unsigned(&e[0..4]) as u32,
+                    Scaling::new(pair(&e[4..12]), pair(&e[12..20]), pair(&e[20..28])),
+                )
+            })
+            .collect();
+        Ok(Reso::new(
+            pair(&data[0..8]),
+            pair(&data[8..16]),
+            pair(&data[16..24]),
+            entries,
+        ))
+    }
+}
+
+/// The format of a picture: a PNG or JPEG image, or a Rect, which is an empty space of a
+/// given size with no image at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PictureFormat {
+    Png,
+    Jpeg,
+    Rect,
+}
+
+/// A picture resource with its size in pixels and any scaling from the Reso chunk.  `data`
+/// is the PNG or JPEG file, and is empty for a Rect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Picture {
+    number: u32,
+    format: PictureFormat,
+    width: u32,
+    height: u32,
+    scaling: Option<Scaling>,
+    data: Vec<u8>,
+}
+
+impl Picture {
+    pub fn new(
+        number: u32,
+        format: PictureFormat,
+        width: u32,
+        height: u32,
+        scaling: Option<Scaling>,
+        data: Vec<u8>,
+    ) -> Picture {
+        Picture {
+            number,
+            format,
+            width,
+            height,
+            scaling,
+            data,
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn format(&self) -> PictureFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn scaling(&self) -> Option<&Scaling> {
+        self.scaling.as_ref()
+    }
+
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    // Read the picture in `chunk`, taking its size from the image header
+    fn from_chunk(number: u32, chunk: &Chunk) -> Result<Picture, RuntimeError> {
+        let data = &chunk.data()[..usize::min(chunk.length() as usize, chunk.data().len())];
+        let (format, (width, height)) = match chunk.id().as_str() {
+            "PNG " => (PictureFormat::Png, png_size(data)?),
+            "JPEG" => (PictureFormat::Jpeg, jpeg_size(data)?),
+            "Rect" if data.len() >= 8 => (PictureFormat::Rect, pair(data)),
+            "Rect" => {
+                return recoverable_error!(
+                    ErrorCode::BlorbPictureData,
+                    "Rect chunk should be 8 bytes: {}",
+                    data.len()
+                )
+            }
+            id => {
+                return recoverable_error!(
+                    ErrorCode::BlorbPictureData,
+                    "Picture {} is not a PNG, JPEG, or Rect chunk: '{}'",
+                    number,
+                    id
+                )
+            }
+        };
+        let data = if format == PictureFormat::Rect {
+            Vec::new()
+        } else {
+            data.to_vec()
+        };
+        Ok(Picture::new(number, format, width, height, None, data))
+    }
+}
+
+/// The size and scaling of each picture in a Blorb file, without the image data, so
+/// PICTURE_DATA can be answered after the file itself has gone to the sound manager
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PictureIndex {
+    reso: Option<Reso>,
+    pictures: HashMap<u32, Picture>,
+}
+
+impl PictureIndex {
+    /// The number of pictures
+    pub fn count(&self) -> usize {
+        self.pictures.len()
+    }
+
+    /// The (width, height) of picture `number` in a window of `window` (width, height),
+    /// scaled by the Reso chunk when there is one, or None when there is no such picture
+    pub fn size(&self, number: u32, window: (u32, u32)) -> Option<(u32, u32)> {
+        let picture = self.pictures.get(&number)?;
+        let scale = match &self.reso {
+            Some(reso) => reso.scale(picture.scaling(), window),
+            None => 1.0,
+        };
+        // Anything with a size is at least a unit across
+        let scaled = |n: u32| match n {
+            0 => 0,
+            _ => u32::max(1, (n as f64 * scale).round() as u32),
+        };
+        Some((scaled(picture.width), scaled(picture.height)))
+    }
+}
+
+// Width and height from the IHDR chunk, which must come first in a PNG file
+fn png_size(data: &[u8]) -> Result<(u32, u32), RuntimeError> {
+    if data.len() < 24 || data[0..8] != *b"\x89PNG\r\n\x1a\n" || data[12..16] != *b"IHDR" {
+        recoverable_error!(ErrorCode::BlorbPictureData, "PNG data has no IHDR chunk")
+    } else {
+        Ok(pair(&data[16..24]))
+    }
+}
+
+// Width and height from the first start of frame (SOFn) segment of a JPEG file
+fn jpeg_size(data: &[u8]) -> Result<(u32, u32), RuntimeError> {
+    if data.len() < 2 || data[0..2] != [0xFF, 0xD8] {
+        return recoverable_error!(ErrorCode::BlorbPictureData, "JPEG data has no SOI marker");
+    }
+
+    let mut offset = 2;
+    while offset + 4 <= data.len() && data[offset] == 0xFF {
+        let marker = data[offset + 1];
+        match marker {
+            // Fill bytes before a marker
+            0xFF => offset += 1,
+            // Markers without a segment
+            0x01 | 0xD0..=0xD9 => offset += 2,
+            // SOF0 through SOF15, except DHT, JPG, and DAC, which share the range
+            0xC0..=0xCF if ![0xC4, 0xC8, 0xCC].contains(&marker) => {
+                if offset + 9 > data.len() {
+                    break;
+                }
+                let height = iff::vec_as_unsigned(&data[offset + 5..offset + 7]) as u32;
+                let width = iff::vec_as_unsigned(&data[offset + 7..offset + 9]) as u32;
+                return Ok((width, height));
+            }
+            _ => offset += 2 + iff::vec_as_unsigned(&data[offset + 2..offset + 4]),
+        }
+    }
+
+    recoverable_error!(ErrorCode::BlorbPictureData, "JPEG data has no SOF segment")
+}
+
+impl From<&IFhd> for Chunk {
+    fn from(value: &IFhd) -> Self {
+        let mut data = Vec::new();
+        data.extend(iff::unsigned_as_vec(value.release_number as usize, 2));
+        data.extend(&value.serial_number);
+        data.extend(iff::unsigned_as_vec(value.checksum as usize, 2));
+        data.extend(iff::unsigned_as_vec(value.pc as usize, 3));
+        Chunk::new_chunk(0, "IFhd", data)
+    }
+}
+
+impl From<&RDes> for Chunk {
+    fn from(value: &RDes) -> Self {
+        let mut data = iff::unsigned_as_vec(value.entries.len(), 4);
+        for (usage, number, text) in value.entries() {
+            data.extend(usage.as_bytes());
+            data.extend(iff::unsigned_as_vec(*number as usize, 4));
+            data.extend(iff::unsigned_as_vec(text.len(), 4));
+            data.extend(text.as_bytes());
+        }
+        Chunk::new_chunk(0, "RDes", data)
+    }
+}
+
+impl From<&Reso> for Chunk {
+    fn from(value: &Reso) -> Self {
+        let mut data = Vec::new();
+        for (x, y) in [value.window, value.min_window, value.max_window] {
+            data.extend(iff::unsigned_as_vec(x as usize, 4));
+            data.extend(iff::unsigned_as_vec(y as usize, 4));
+        }
+        for (number, scaling) in value.entries() {
+            data.extend(iff::unsigned_as_vec(*number as usize, 4));
+            for (n, d) in [scaling.ratio, scaling.min, scaling.max] {
+                data.extend(iff::unsigned_as_vec(n as usize, 4));
+                data.extend(iff::unsigned_as_vec(d as usize, 4));
+            }
+        }
+        Chunk::new_chunk(0, "Reso", data)
+    }
+}
+
+impl From<&Loop> for Chunk {
+    fn from(value: &Loop) -> Self {
+        let mut data = Vec::new();
+        for entry in value.entries() {
+            data.extend(iff::unsigned_as_vec(entry.number as usize, 4));
+            data.extend(iff::unsigned_as_vec(entry.repeats as usize, 4));
+        }
+        Chunk::new_chunk(0, "Loop", data)
+    }
+}
+
+/// A resource listed in the RIdx chunk.  `format` is the id of the chunk holding it, or the
+/// FORM type (e.g. `AIFF`) for a FORM chunk, and `size` is the chunk's data length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resource {
+    usage: String,
+    number: u32,
+    format: String,
+    size: u32,
+    offset: u32,
+}
+
+impl Resource {
+    pub fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+fn chunk_format(chunk: &Chunk) -> String {
+    if chunk.id() == "FORM" {
+        chunk.sub_id()
+    } else {
+        chunk.id()
+    }
+}
+
+// Resource usage ids are 4 characters, padded with spaces
+fn usage_id(usage: &str) -> String {
+    format!("{:4}", usage)
+}
+
 #[derive(Debug)]
 pub struct Blorb {
     ridx: RIdx,
     ifhd: Option<IFhd>,
     sounds: HashMap<u32, Chunk>,
+    // Chunks for resources other than sounds and Exec, such as pictures, by offset
+    resources: HashMap<u32, Chunk>,
     loops: Option<Loop>,
+    rdes: Option<RDes>,
+    reso: Option<Reso>,
     exec: Option<Vec<u8>>,
 }
 
@@ -278,13 +799,17 @@ impl Blorb {
         ifhd: Option<IFhd>,
         sounds: HashMap<u32, Chunk>,
         loops: Option<Loop>,
+        rdes: Option<RDes>,
         exec: Option<Vec<u8>>,
     ) -> Blorb {
         Blorb {
             ridx,
             ifhd,
             sounds,
+            resources: HashMap::new(),
             loops,
+            rdes,
+            reso: None,
             exec,
         }
     }
@@ -305,15 +830,126 @@ impl Blorb {
         self.loops.as_ref()
     }
 
+    pub fn rdes(&self) -> Option<&RDes> {
+        self.rdes.as_ref()
+    }
+
+    pub fn reso(&self) -> Option<&Reso> {
+        self.reso.as_ref()
+    }
+
+    /// Picture `number` with its size and scaling, or None when there is no such picture or
+    /// its size can't be read
+    pub fn picture(&self, number: u32) -> Option<Picture> {
+        let index = self
+            .ridx
+            .indices()
+            .iter()
+            .find(|i| i.usage() == "Pict" && i.number() == number)?;
+        let chunk = self.resources.get(&index.start())?;
+        match Picture::from_chunk(number, chunk) {
+            Ok(mut picture) => {
+                picture.scaling = self.reso.as_ref().and_then(|r| r.scaling(number)).copied();
+                Some(picture)
+            }
+            Err(e) => {
+                warn!(target: "app::resource", "Ignoring picture {}: {}", number, e);
+                None
+            }
+        }
+    }
+
+    /// The size and scaling of every picture that can be read
+    pub fn picture_index(&self) -> PictureIndex {
+        let pictures = self
+            .ridx
+            .indices()
+            .iter()
+            .filter(|i| i.usage() == "Pict")
+            .filter_map(|i| self.picture(i.number()))
+            .map(|mut picture| {
+                picture.data = Vec::new();
+                (picture.number, picture)
+            })
+            .collect();
+        PictureIndex {
+            reso: self.reso.clone(),
+            pictures,
+        }
+    }
+
+    /// The text description of a resource from the RDes chunk.  `usage` is padded to 4
+    /// characters, so "Snd" finds "Snd " resources.
+    pub fn description(&self, usage: &str, number: u32) -> Option<&str> {
+        self.rdes.as_ref()?.description(&usage_id(usage), number)
+    }
+
     pub fn exec(&self) -> Option<&Vec<u8>> {
         self.exec.as_ref()
     }
-}
 
-impl TryFrom<&Chunk> for Blorb {
-    type Error = RuntimeError;
+    /// Move the Exec (ZCOD) data out of the Blorb without copying it, leaving the
+    /// sound resources in place.
+    pub fn take_exec(&mut self) -> Option<Vec<u8>> {
+        self.exec.take()
+    }
 
-    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+    /// Consume the Blorb, returning only the Exec (ZCOD) data.
+    pub fn into_exec(self) -> Option<Vec<u8>> {
+        self.exec
+    }
+
+    fn chunk(&self, offset: u32) -> Option<&Chunk> {
+        self.sounds
+            .get(&offset)
+            .or_else(|| self.resources.get(&offset))
+    }
+
+    /// The resources in the RIdx chunk that are present in the file, in index order
+    pub fn chunks(&self) -> impl Iterator<Item = Resource> + '_ {
+        self.ridx.indices().iter().filter_map(|index| {
+            let (format, size) = if index.usage() == "Exec" {
+                ("ZCOD".to_string(), self.exec.as_ref()?.len() as u32)
+            } else {
+                let chunk = self.chunk(index.start())?;
+                (chunk_format(chunk), chunk.length())
+            };
+            Some(Resource {
+                usage: index.usage().clone(),
+                number: index.number(),
+                format,
+                size,
+                offset: index.start(),
+            })
+        })
+    }
+
+    /// The contents of a resource as a file of its own: the chunk data, or the whole
+    /// chunk for a FORM such as an AIFF sound.  `usage` is padded to 4 characters, so
+    /// "Snd" finds "Snd " resources.
+    pub fn extract(&self, usage: &str, number: u32) -> Option<Vec<u8>> {
+        let usage = usage_id(usage);
+        let index = self
+            .ridx
+            .indices()
+            .iter()
+            .find(|i| *i.usage() == usage && i.number() == number)?;
+        if usage == "Exec" {
+            return self.exec.clone();
+        }
+
+        let chunk = self.chunk(index.start())?;
+        if chunk.id() == "FORM" {
+            Some(Vec::from(chunk))
+        } else {
+            let length = usize::min(chunk.length() as usize, chunk.data().len());
+            Some(chunk.data()[..length].to_vec())
+        }
+    }
+
+    // Read everything but the Exec data, returning the index of the ZCOD chunk in
+    // `value`'s child chunks so the caller can either copy or move it.
+    fn parse(value: &Chunk) -> Result<(Blorb, Option<usize>), RuntimeError> {
         if value.id() != "FORM" || value.sub_id() != "IFRS" {
             recoverable_error!(
                 ErrorCode::IFFInvalidChunkId,
@@ -349,6 +985,28 @@ impl TryFrom<&Chunk> for Blorb {
                 Some(l) => Some(Loop::try_from(l)?),
                 None => None,
             };
+            // Descriptions are optional, so a damaged RDes chunk is ignored
+            let rdes = match value.find_chunk("RDes", "") {
+                Some(r) => match RDes::try_from(r) {
+                    Ok(r) => Some(r),
+                    Err(e) => {
+                        warn!(target: "app::resource", "Ignoring RDes chunk: {}", e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            // Scaling only matters to a graphical display, so a damaged Reso chunk is too
+            let reso = match value.find_chunk("Reso", "") {
+                Some(r) => match Reso::try_from(r) {
+                    Ok(r) => Some(r),
+                    Err(e) => {
+                        warn!(target: "app::resource", "Ignoring Reso chunk: {}", e);
+                        None
+                    }
+                },
+                None => None,
+            };
             let oggv_chunks = value.find_chunks("OGGV", "");
             let aiff_chunks = value.find_chunks("FORM", "AIFF");
 
@@ -363,10 +1021,15 @@ impl TryFrom<&Chunk> for Blorb {
                     warn!("Exec index should have number '0': {}", execs[0].number());
                     None
                 } else {
-                    match value.find_chunk("ZCOD", "") {
-                        Some(e) => {
+                    match value
+                        .chunks()
+                        .iter()
+                        .position(|c| c.id() == "ZCOD" && c.sub_id().is_empty())
+                    {
+                        Some(i) => {
+                            let e = &value.chunks()[i];
                             if e.offset() == execs[0].start() {
-                                Some(e.data().clone())
+                                Some(i)
                             } else {
                                 warn!(target: "app::resource", "'Exec' resources should start at {:06x}, but the ZCOD chunk starts at {:06}, therefore ignoring it", execs[0].start, e.offset());
                                 None
@@ -390,14 +1053,193 @@ impl TryFrom<&Chunk> for Blorb {
                 sounds.insert(c.offset(), c.clone());
             }
 
-            Ok(Blorb {
-                ifhd,
-                ridx,
-                sounds,
-                loops,
+            let mut resources = HashMap::new();
+            for index in ridx.indices() {
+                if index.usage() != "Exec" && !sounds.contains_key(&index.start()) {
+                    if let Some(c) = value.chunks().iter().find(|c| c.offset() == index.start()) {
+                        resources.insert(index.start(), c.clone());
+                    }
+                }
+            }
+
+            Ok((
+                Blorb {
+                    ifhd,
+                    ridx,
+                    sounds,
+                    resources,
+                    loops,
+                    rdes,
+                    reso,
+                    exec: None,
+                },
                 exec,
-            })
+            ))
+        }
+    }
+}
+
+/// Builds a Blorb file, laying out the resource chunks and writing an RIdx chunk to match.
+///
+/// Starting from an existing [`Blorb`] keeps its resources, IFhd, Loop, RDes, and Reso
+/// chunks.
+#[derive(Debug, Default)]
+pub struct BlorbBuilder {
+    ifhd: Option<IFhd>,
+    loops: Option<Loop>,
+    rdes: Option<RDes>,
+    reso: Option<Reso>,
+    // Usage, number, and chunk of each resource, in file order
+    resources: Vec<(String, u32, Chunk)>,
+}
+
+// Bytes a chunk takes in the file: the header, data, and any padding byte
+fn chunk_size(chunk: &Chunk) -> u32 {
+    8 + chunk.length() + (chunk.length() % 2)
+}
+
+impl BlorbBuilder {
+    pub fn new() -> BlorbBuilder {
+        BlorbBuilder::default()
+    }
+
+    pub fn set_ifhd(&mut self, ifhd: Option<IFhd>) {
+        self.ifhd = ifhd;
+    }
+
+    pub fn set_loops(&mut self, loops: Option<Loop>) {
+        self.loops = loops;
+    }
+
+    pub fn set_rdes(&mut self, rdes: Option<RDes>) {
+        self.rdes = rdes;
+    }
+
+    pub fn set_reso(&mut self, reso: Option<Reso>) {
+        self.reso = reso;
+    }
+
+    /// Add a resource, replacing any resource with the same usage and number.
+    ///
+    /// `data` that is a complete FORM chunk, such as an AIFF file, is added as it is,
+    /// otherwise it becomes the data of a chunk with id `format`.
+    pub fn add(&mut self, usage: &str, number: u32, format: &str, data: Vec<u8>) {
+        let is_form = data.len() >= 12
+            && data[0..4] == *b"FORM"
+            && iff::vec_as_unsigned(&data[4..8]) + 8 == data.len();
+        let chunk = if is_form {
+            Chunk::from(&data)
+        } else {
+            Chunk::new_chunk(0, format, data)
+        };
+
+        let usage = usage_id(usage);
+        match self
+            .resources
+            .iter_mut()
+            .find(|(u, n, _)| *u == usage && *n == number)
+        {
+            Some(resource) => resource.2 = chunk,
+            None => self.resources.push((usage, number, chunk)),
+        }
+    }
+
+    /// Write the Blorb, one chunk at a time
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let ridx_size = 8 + 4 + 12 * self.resources.len() as u32;
+        let mut ridx = iff::unsigned_as_vec(self.resources.len(), 4);
+        // Resources follow the FORM header, IFRS, and the RIdx chunk
+        let mut offset = 12 + ridx_size;
+        for (usage, number, chunk) in &self.resources {
+            ridx.extend(usage.as_bytes());
+            ridx.extend(iff::unsigned_as_vec(*number as usize, 4));
+            ridx.extend(iff::unsigned_as_vec(offset as usize, 4));
+            offset += chunk_size(chunk);
+        }
+
+        let mut chunks = vec![Chunk::new_chunk(0, "RIdx", ridx)];
+        chunks.extend(self.ifhd.as_ref().map(Chunk::from));
+        chunks.extend(self.loops.as_ref().map(Chunk::from));
+        chunks.extend(self.rdes.as_ref().map(Chunk::from));
+        chunks.extend(self.reso.as_ref().map(Chunk::from));
+        let length = offset - 8 + chunks[1..].iter().map(chunk_size).sum::<u32>();
+
+        writer.write_all(b"FORM")?;
+        writer.write_all(&iff::unsigned_as_vec(length as usize, 4))?;
+        writer.write_all(b"IFRS")?;
+        chunks[0].write(writer)?;
+        for (_, _, chunk) in &self.resources {
+            chunk.write(writer)?;
+        }
+        for chunk in &chunks[1..] {
+            chunk.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        // Writing to a vector can't fail
+        self.write(&mut data).unwrap();
+        data
+    }
+}
+
+impl From<Blorb> for BlorbBuilder {
+    fn from(value: Blorb) -> Self {
+        let Blorb {
+            ridx,
+            ifhd,
+            mut sounds,
+            mut resources,
+            loops,
+            rdes,
+            reso,
+            mut exec,
+        } = value;
+
+        let mut builder = BlorbBuilder {
+            ifhd,
+            loops,
+            rdes,
+            reso,
+            resources: Vec::new(),
+        };
+        for index in ridx.indices {
+            let chunk = if index.usage == "Exec" {
+                exec.take().map(|e| Chunk::new_chunk(0, "ZCOD", e))
+            } else {
+                sounds
+                    .remove(&index.start)
+                    .or_else(|| resources.remove(&index.start))
+            };
+            if let Some(chunk) = chunk {
+                builder.resources.push((index.usage, index.number, chunk));
+            }
         }
+
+        builder
+    }
+}
+
+impl TryFrom<&Chunk> for Blorb {
+    type Error = RuntimeError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        let (mut blorb, exec) = Blorb::parse(value)?;
+        blorb.exec = exec.map(|i| value.chunks()[i].data().clone());
+        Ok(blorb)
+    }
+}
+
+impl TryFrom<Chunk> for Blorb {
+    type Error = RuntimeError;
+
+    fn try_from(value: Chunk) -> Result<Self, Self::Error> {
+        let (mut blorb, exec) = Blorb::parse(&value)?;
+        blorb.exec = exec.map(|i| value.into_chunks().swap_remove(i).into_data());
+        Ok(blorb)
     }
 }
 
@@ -406,7 +1248,9 @@ impl TryFrom<Vec<u8>> for Blorb {
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
         let chunk = Chunk::from(&value);
-        Blorb::try_from(&chunk)
+        // Release the raw data before the chunks are processed
+        drop(value);
+        Blorb::try_from(chunk)
     }
 }
 
@@ -415,7 +1259,7 @@ impl TryFrom<&mut File> for Blorb {
 
     fn try_from(value: &mut File) -> Result<Self, Self::Error> {
         match Chunk::try_from(value) {
-            Ok(c) => Blorb::try_from(&c),
+            Ok(c) => Blorb::try_from(c),
             Err(e) => recoverable_error!(ErrorCode::FileError, "Error opening file: {}", e),
         }
     }
@@ -425,7 +1269,7 @@ impl TryFrom<&mut File> for Blorb {
 mod tests {
     use std::{fs, io::Write, path::Path};
 
-    use crate::{assert_ok, assert_some_eq};
+    use crate::{assert_ok, assert_some, assert_some_eq, test_util::mock_png};
 
     use super::*;
 
@@ -647,7 +1491,7 @@ mod tests {
     }
 
     #[test]
-    fn test_loop_try_from_chunk_bad_data() {
+    fn test_loop_try_from_chunk_trailing_data() {
         let chunk = Chunk::new_chunk(
             0,
             "Loop",
@@ -656,7 +1500,271 @@ mod tests {
                 0xed, 0x0f, 0xff,
             ],
         );
-        assert!(Loop::try_from(&chunk).is_err());
+        let l = assert_ok!(Loop::try_from(&chunk));
+        assert_eq!(
+            l.entries(),
+            &vec![
+                Entry::new(0x12345678, 0x9abcdef0),
+                Entry::new(0x21436587, 0xa9cbed0f)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_try_from_chunk_empty() {
+        let chunk = Chunk::new_chunk(0, "Loop", vec![]);
+        let l = assert_ok!(Loop::try_from(&chunk));
+        assert!(l.entries().is_empty());
+    }
+
+    #[test]
+    fn test_loop_try_from_chunk_partial_entry() {
+        for len in [5, 6, 7] {
+            let chunk = Chunk::new_chunk(0, "Loop", vec![0x12; len]);
+            let l = assert_ok!(Loop::try_from(&chunk));
+            assert!(l.entries().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_loop_try_from_chunk_12_bytes() {
+        let chunk = Chunk::new_chunk(
+            0,
+            "Loop",
+            vec![
+                0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+            ],
+        );
+        let l = assert_ok!(Loop::try_from(&chunk));
+        assert_eq!(l.entries(), &vec![Entry::new(3, 0)]);
+    }
+
+    #[test]
+    fn test_loop_try_from_chunk_duplicate() {
+        let chunk = Chunk::new_chunk(
+            0,
+            "Loop",
+            vec![
+                0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+                0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x05,
+            ],
+        );
+        let l = assert_ok!(Loop::try_from(&chunk));
+        assert_eq!(l.entries(), &vec![Entry::new(3, 0), Entry::new(4, 1)]);
+    }
+
+    #[test]
+    fn test_rdes_try_from_chunk() {
+        let chunk = Chunk::new_chunk(
+            0x100,
+            "RDes",
+            vec![
+                0x00, 0x00, 0x00, 0x02, b'S', b'n', b'd', b' ', 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
+                0x00, 0x04, b'b', b'e', b'l', b'l', b'P', b'i', b'c', b't', 0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x00,
+            ],
+        );
+        let rdes = assert_ok!(RDes::try_from(&chunk));
+        assert_eq!(
+            rdes.entries(),
+            &vec![
+                ("Snd ".to_string(), 3, "bell".to_string()),
+                ("Pict".to_string(), 1, String::new())
+            ]
+        );
+        assert_some_eq!(rdes.description("Snd ", 3), "bell");
+        assert!(rdes.description("Snd ", 1).is_none());
+        // Writing the chunk gives back the same data
+        assert_eq!(Chunk::from(&rdes).data(), chunk.data());
+    }
+
+    #[test]
+    fn test_rdes_try_from_chunk_wrong_id() {
+        let chunk = Chunk::new_chunk(0x100, "RDex", vec![0, 0, 0, 0]);
+        assert!(RDes::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_rdes_try_from_chunk_truncated() {
+        let chunk = Chunk::new_chunk(
+            0x100,
+            "RDes",
+            vec![
+                0x00, 0x00, 0x00, 0x01, b'S', b'n', b'd', b' ', 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
+                0x00, 0x08, b'b', b'e', b'l', b'l',
+            ],
+        );
+        let e = RDes::try_from(&chunk).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::BlorbRDesEntrySize);
+        let chunk = Chunk::new_chunk(0x100, "RDes", vec![0x00, 0x00, 0x00, 0x01]);
+        let e = RDes::try_from(&chunk).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::BlorbRDesEntrySize);
+    }
+
+    #[test]
+    fn test_reso_try_from_chunk() {
+        let reso = Reso::new(
+            (640, 400),
+            (320, 200),
+            (0, 0),
+            vec![(1, Scaling::new((1, 1), (1, 2), (0, 0)))],
+        );
+        let chunk = Chunk::from(&reso);
+        assert_eq!(chunk.length(), 52);
+        assert_eq!(assert_ok!(Reso::try_from(&chunk)), reso);
+        assert_some_eq!(reso.scaling(1), &Scaling::new((1, 1), (1, 2), (0, 0)));
+        assert!(reso.scaling(2).is_none());
+    }
+
+    #[test]
+    fn test_reso_try_from_chunk_bad_data() {
+        let chunk = Chunk::new_chunk(0x100, "Resx", vec![0; 24]);
+        assert!(Reso::try_from(&chunk).is_err());
+        let chunk = Chunk::new_chunk(0x100, "Reso", vec![0; 20]);
+        let e = Reso::try_from(&chunk).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::BlorbResoEntrySize);
+        let chunk = Chunk::new_chunk(0x100, "Reso", vec![0; 30]);
+        let e = Reso::try_from(&chunk).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::BlorbResoEntrySize);
+    }
+
+    #[test]
+    fn test_reso_scale() {
+        let reso = Reso::new((640, 480), (0, 0), (0, 0), Vec::new());
+        // Half the standard width, and the height leaves more room than that
+        assert_eq!(reso.scale(None, (320, 400)), 0.5);
+        let double = Scaling::new((2, 1), (0, 0), (0, 0));
+        assert_eq!(reso.scale(Some(&double), (320, 400)), 1.0);
+        // Kept within the limits
+        let limited = Scaling::new((1, 1), (3, 4), (3, 2));
+        assert_eq!(reso.scale(Some(&limited), (320, 240)), 0.75);
+        assert_eq!(reso.scale(Some(&limited), (1280, 960)), 1.5);
+        assert_eq!(reso.scale(Some(&limited), (640, 480)), 1.0);
+        // No standard window to compare with
+        let reso = Reso::new((0, 0), (0, 0), (0, 0), Vec::new());
+        assert_eq!(reso.scale(Some(&double), (320, 240)), 2.0);
+    }
+
+    #[test]
+    fn test_picture_png() {
+        let chunk = Chunk::new_chunk(0x100, "PNG ", mock_png(32, 16));
+        let picture = assert_ok!(Picture::from_chunk(3, &chunk));
+        assert_eq!(picture.number(), 3);
+        assert_eq!(picture.format(), PictureFormat::Png);
+        assert_eq!((picture.width(), picture.height()), (32, 16));
+        assert!(picture.scaling().is_none());
+        assert_eq!(picture.data(), &mock_png(32, 16));
+    }
+
+    #[test]
+    fn test_picture_jpeg() {
+        // SOI, an APP0 segment, then SOF0 for a 16x8 image
+        let data = vec![
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00,
+            0x08, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00,
+        ];
+        let chunk = Chunk::new_chunk(0x100, "JPEG", data.clone());
+        let picture = assert_ok!(Picture::from_chunk(1, &chunk));
+        assert_eq!(picture.format(), PictureFormat::Jpeg);
+        assert_eq!((picture.width(), picture.height()), (16, 8));
+        assert_eq!(picture.data(), &data);
+    }
+
+    #[test]
+    fn test_picture_rect() {
+        let chunk = Chunk::new_chunk(0x100, "Rect", vec![0, 0, 1, 0, 0, 0, 0, 0x80]);
+        let picture = assert_ok!(Picture::from_chunk(2, &chunk));
+        assert_eq!(picture.format(), PictureFormat::Rect);
+        assert_eq!((picture.width(), picture.height()), (256, 128));
+        assert!(picture.data().is_empty());
+    }
+
+    #[test]
+    fn test_picture_bad_data() {
+        for (id, data) in [
+            ("PNG ", vec![0x89, b'P', b'N', b'G', 1, 2, 3]),
+            ("JPEG", vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x02]),
+            ("JPEG", mock_png(1, 1)),
+            ("Rect", vec![0, 0, 0, 1]),
+            ("GIF ", vec![0; 16]),
+        ] {
+            let chunk = Chunk::new_chunk(0x100, id, data);
+            let e = Picture::from_chunk(1, &chunk).unwrap_err();
+            assert_eq!(e.code(), ErrorCode::BlorbPictureData, "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_blorb_picture() {
+        let mut builder = mock_builder();
+        builder.add("Pict", 2, "PNG ", mock_png(64, 48));
+        builder.add("Pict", 3, "Rect", vec![0, 0, 0, 10, 0, 0, 0, 20]);
+        let scaling = Scaling::new((2, 1), (0, 0), (0, 0));
+        builder.set_reso(Some(Reso::new(
+            (640, 480),
+            (0, 0),
+            (0, 0),
+            vec![(2, scaling)],
+        )));
+        let blorb = assert_ok!(Blorb::try_from(builder.build()));
+
+        let picture = assert_some!(blorb.picture(2));
+        assert_eq!((picture.width(), picture.height()), (64, 48));
+        assert_some_eq!(picture.scaling(), &scaling);
+        let picture = assert_some!(blorb.picture(3));
+        assert_eq!((picture.width(), picture.height()), (10, 20));
+        assert!(picture.scaling().is_none());
+        // Picture 1 isn't a valid PNG
+        assert!(blorb.picture(1).is_none());
+        // Sound 4 isn't a picture
+        assert!(blorb.picture(4).is_none());
+
+        // The Reso chunk survives a rebuild
+        let rebuilt = assert_ok!(Blorb::try_from(BlorbBuilder::from(blorb).build()));
+        assert_some_eq!(rebuilt.reso().and_then(|r| r.scaling(2)), &scaling);
+    }
+
+    #[test]
+    fn test_picture_index() {
+        let mut builder = mock_builder();
+        builder.add("Pict", 2, "PNG ", mock_png(64, 48));
+        builder.add("Pict", 3, "Rect", vec![0, 0, 0, 10, 0, 0, 0, 20]);
+        let blorb = assert_ok!(Blorb::try_from(builder.build()));
+        let index = blorb.picture_index();
+        // Picture 1 isn't a valid PNG
+        assert_eq!(index.count(), 2);
+        assert_some_eq!(index.size(2, (80, 24)), (64, 48));
+        assert_some_eq!(index.size(3, (80, 24)), (10, 20));
+        assert!(index.size(1, (80, 24)).is_none());
+        assert!(index.pictures.values().all(|p| p.data().is_empty()));
+    }
+
+    #[test]
+    fn test_picture_index_scaled() {
+        let mut builder = mock_builder();
+        builder.add("Pict", 2, "PNG ", mock_png(64, 48));
+        builder.add("Pict", 3, "Rect", vec![0, 0, 0, 10, 0, 0, 0, 20]);
+        let scaling = Scaling::new((1, 1), (1, 1), (0, 0));
+        builder.set_reso(Some(Reso::new(
+            (320, 240),
+            (0, 0),
+            (0, 0),
+            vec![(3, scaling)],
+        )));
+        let blorb = assert_ok!(Blorb::try_from(builder.build()));
+        let index = blorb.picture_index();
+        // A quarter of the standard window
+        assert_some_eq!(index.size(2, (80, 60)), (16, 12));
+        // Never smaller than its own size
+        assert_some_eq!(index.size(3, (80, 60)), (10, 20));
+    }
+
+    #[test]
+    fn test_blorb_description() {
+        let blorb = assert_ok!(Blorb::try_from(mock_builder().build()));
+        assert_some_eq!(blorb.description("Snd", 3), "distant foghorn");
+        assert_some_eq!(blorb.description("Pict", 1), "A lighthouse");
+        assert!(blorb.description("Snd", 4).is_none());
     }
 
     #[test]
@@ -681,6 +1789,7 @@ mod tests {
             Some(ifhd.clone()),
             sounds.clone(),
             Some(l.clone()),
+            None,
             Some(exec.clone()),
         );
         assert_eq!(blorb.ridx(), &ridx);
@@ -1044,6 +2153,46 @@ mod tests {
         assert!(Blorb::try_from(&iff).is_err());
     }
 
+    #[test]
+    fn test_blorb_try_from_owned_chunk() {
+        let ridx = Chunk::new_chunk(
+            0x0C,
+            "RIdx",
+            vec![
+                0x00, 0x00, 0x00, 0x01, b'E', b'x', b'e', b'c', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x20,
+            ],
+        );
+        let exec = Chunk::new_chunk(0x20, "ZCOD", vec![0x11, 0x22, 0x33, 0x44]);
+        let ptr = exec.data().as_ptr();
+        let iff = Chunk::new_form(0, "IFRS", vec![ridx, exec]);
+        let mut blorb = assert_ok!(Blorb::try_from(iff));
+        // The Exec data is moved out of the chunk rather than copied
+        assert_some_eq!(blorb.exec(), &vec![0x11, 0x22, 0x33, 0x44]);
+        let exec = assert_some!(blorb.take_exec());
+        assert_eq!(exec.as_ptr(), ptr);
+        assert!(blorb.exec().is_none());
+    }
+
+    #[test]
+    fn test_blorb_into_exec() {
+        let ridx = Chunk::new_chunk(
+            0x0C,
+            "RIdx",
+            vec![
+                0x00, 0x00, 0x00, 0x01, b'E', b'x', b'e', b'c', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x20,
+            ],
+        );
+        let exec = Chunk::new_chunk(0x20, "ZCOD", vec![0x11, 0x22, 0x33, 0x44]);
+        let iff = Chunk::new_form(0, "IFRS", vec![ridx, exec]);
+        let blorb = assert_ok!(Blorb::try_from(iff));
+        let ptr = assert_some!(blorb.exec()).as_ptr();
+        let exec = assert_some!(blorb.into_exec());
+        assert_eq!(exec, vec![0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(exec.as_ptr(), ptr);
+    }
+
     #[test]
     fn test_blorb_try_from_chunk_wrong_id() {
         let iff = Chunk::new_chunk(0, "IFRS", vec![]);
@@ -1183,4 +2332,117 @@ mod tests {
         assert!(fs::remove_file("test.blorb").is_ok());
         assert!(b.is_err());
     }
+
+    // A Blorb with a picture, an Ogg sound, an odd-length AIFF sound, the story, an IFhd,
+    // and a Loop chunk
+    fn mock_builder() -> BlorbBuilder {
+        let mut builder = BlorbBuilder::new();
+        builder.set_ifhd(Some(IFhd::new(
+            0x1234,
+            &[0x32, 0x33, 0x30, 0x37, 0x32, 0x32],
+            0x5678,
+            0,
+        )));
+        builder.set_loops(Some(Loop::new(vec![Entry::new(3, 0)])));
+        builder.set_rdes(Some(RDes::new(vec![
+            ("Snd ".to_string(), 3, "distant foghorn".to_string()),
+            ("Pict".to_string(), 1, "A lighthouse".to_string()),
+        ])));
+        builder.add("Pict", 1, "PNG ", vec![0x89, b'P', b'N', b'G', 1, 2, 3]);
+        builder.add("Snd", 3, "OGGV", vec![b'O', b'g', b'g', b'S']);
+        let aiff = Vec::from(&Chunk::new_form(
+            0,
+            "AIFF",
+            vec![Chunk::new_chunk(0, "COMM", vec![1, 2])],
+        ));
+        builder.add("Snd ", 4, "AIFF", aiff);
+        builder.add("Exec", 0, "ZCOD", vec![5, 0, 0, 0]);
+        builder
+    }
+
+    #[test]
+    fn test_blorb_chunks() {
+        let data = mock_builder().build();
+        let blorb = assert_ok!(Blorb::try_from(data.clone()));
+        let chunks: Vec<Resource> = blorb.chunks().collect();
+        let list: Vec<(&str, u32, &str, u32)> = chunks
+            .iter()
+            .map(|r| (r.usage(), r.number(), r.format(), r.size()))
+            .collect();
+        assert_eq!(
+            list,
+            [
+                ("Pict", 1, "PNG ", 7),
+                ("Snd ", 3, "OGGV", 4),
+                ("Snd ", 4, "AIFF", 14),
+                ("Exec", 0, "ZCOD", 4)
+            ]
+        );
+        // Every RIdx entry points at the chunk it lists
+        for r in chunks {
+            let offset = r.offset() as usize;
+            let id: String = data[offset..offset + 4]
+                .iter()
+                .map(|b| *b as char)
+                .collect();
+            assert!(id == r.format() || id == "FORM", "{:?}", r);
+        }
+    }
+
+    #[test]
+    fn test_blorb_extract() {
+        let blorb = assert_ok!(Blorb::try_from(mock_builder().build()));
+        assert_some_eq!(
+            blorb.extract("Pict", 1),
+            vec![0x89, b'P', b'N', b'G', 1, 2, 3]
+        );
+        assert_some_eq!(blorb.extract("Snd", 3), vec![b'O', b'g', b'g', b'S']);
+        let aiff = assert_some!(blorb.extract("Snd ", 4));
+        assert_eq!(&aiff[0..4], b"FORM");
+        assert_eq!(&aiff[8..12], b"AIFF");
+        assert_some_eq!(blorb.extract("Exec", 0), vec![5, 0, 0, 0]);
+        assert!(blorb.extract("Snd", 5).is_none());
+        assert!(blorb.extract("Pict", 3).is_none());
+    }
+
+    #[test]
+    fn test_blorb_builder_round_trip() {
+        let data = mock_builder().build();
+        let blorb = assert_ok!(Blorb::try_from(data.clone()));
+        assert_eq!(iff::vec_as_unsigned(&data[4..8]) + 8, data.len());
+        assert_some_eq!(blorb.ifhd(), &IFhd::new(0x1234, b"230722", 0x5678, 0));
+        assert_some_eq!(blorb.loops(), &Loop::new(vec![Entry::new(3, 0)]));
+        // Rebuilding from the Blorb gives the same file
+        let rebuilt = BlorbBuilder::from(blorb).build();
+        assert_eq!(rebuilt, data);
+    }
+
+    #[test]
+    fn test_blorb_builder_add() {
+        let blorb = assert_ok!(Blorb::try_from(mock_builder().build()));
+        let mut builder = BlorbBuilder::from(blorb);
+        builder.add("Pict", 2, "JPEG", vec![0xFF, 0xD8]);
+        builder.add("Snd", 3, "OGGV", vec![b'O', b'g', b'g', b'S', 0]);
+        let blorb = assert_ok!(Blorb::try_from(builder.build()));
+        let list: Vec<(String, u32)> = blorb
+            .chunks()
+            .map(|r| (r.usage().to_string(), r.number()))
+            .collect();
+        assert_eq!(
+            list,
+            [
+                ("Pict".to_string(), 1),
+                ("Snd ".to_string(), 3),
+                ("Snd ".to_string(), 4),
+                ("Exec".to_string(), 0),
+                ("Pict".to_string(), 2)
+            ]
+        );
+        assert_some_eq!(blorb.extract("Pict", 2), vec![0xFF, 0xD8]);
+        assert_some_eq!(blorb.extract("Snd", 3), vec![b'O', b'g', b'g', b'S', 0]);
+        assert_some_eq!(blorb.extract("Exec", 0), vec![5, 0, 0, 0]);
+        assert!(blorb.ifhd().is_some());
+        assert!(blorb.loops().is_some());
+        assert!(blorb.rdes().is_some());
+    }
 }
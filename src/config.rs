@@ -1,10 +1,16 @@
 use serde_yaml::{self, Value};
-use std::fs::File;
+use std::{collections::HashMap, fs::File};
 
 use crate::{
     error::{ErrorCode, RuntimeError},
+    instruction::log_filter::InstructionLogFilter,
     recoverable_error,
-    zmachine::ErrorHandling,
+    zmachine::{
+        io::screen::{Attribute, Attributes, Style, StyleMap},
+        messages::Messages,
+        state::{header::STANDARD_VERSION, MAX_STRING_WORDS, UNDO_BUDGET_BYTES, UNDO_DEPTH},
+        ErrorHandling, SeedMode,
+    },
 };
 
 #[derive(Debug)]
@@ -13,7 +19,201 @@ pub struct Config {
     background: u8,
     logging: bool,
     error_handling: ErrorHandling,
+    // Error handling for particular error codes, in place of error_handling
+    error_overrides: HashMap<ErrorCode, ErrorHandling>,
     volume_factor: f32,
+    trace_calls: bool,
+    // Log the tokens found by lexical analysis
+    trace_parse: bool,
+    trace_routines: Vec<usize>,
+    // Instructions written to the instruction log
+    instruction_log_filter: InstructionLogFilter,
+    echo_scripted_input: bool,
+    echo_input: bool,
+    loop_threshold: u32,
+    predictable_seed: Option<u16>,
+    seed_mode: SeedMode,
+    capture: Option<String>,
+    // Run without a display or keyboard, as when playing a script
+    headless: bool,
+    debug_hud: bool,
+    save_name_template: Option<String>,
+    // Remember the last save file used for each game
+    remember_files: bool,
+    // Allow file names that look like story or Blorb files, see files::validate_save_name
+    allow_any_save_name: bool,
+    // Directory for interpreter state such as the recent files list
+    config_dir: Option<String>,
+    // Standard revision advertised in the header, at most STANDARD_VERSION
+    standard_version: (u8, u8),
+    // Drop pasted text that doesn't fit in the READ buffer, up to the next newline
+    discard_paste_overflow: bool,
+    // Function keys that turn the sound volume down and up during line input
+    volume_keys: (Option<u16>, Option<u16>),
+    // Function key that opens the interpreter menu during line input
+    menu_key: Option<u16>,
+    // Print a description of each sound effect as it starts, for players who can't hear it
+    describe_sounds: bool,
+    // Render font 3, the character graphics font, otherwise SET_FONT 3 reports it unavailable
+    graphics_font: bool,
+    // Longest Z-string, in words, read before reporting an unterminated string
+    max_string_words: usize,
+    // Most undo snapshots kept, and most bytes they can take up in total
+    undo_depth: usize,
+    undo_budget_bytes: usize,
+    // Terminal attributes used to draw each text style
+    style_map: StyleMap,
+    // Text of the interpreter's own messages, from messages.yml
+    messages: Messages,
+}
+
+// Parse a "major.minor" Standard revision, which can't be later than the one implemented.
+// YAML reads an unquoted 1.0 as a number, so that's accepted too.
+fn parse_standard_version(value: &Value) -> Option<(u8, u8)> {
+    let value = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => format!("{:?}", n.as_f64()?),
+        _ => return None,
+    };
+    let (major, minor) = value.split_once('.')?;
+    let version = (major.parse().ok()?, minor.parse().ok()?);
+    if version <= STANDARD_VERSION {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+// F11 and F12
+const DEFAULT_VOLUME_KEYS: (Option<u16>, Option<u16>) = (Some(143), Some(144));
+// F1
+const DEFAULT_MENU_KEY: Option<u16> = Some(133);
+
+// Parse a function key name, "F1" to "F12", to its ZSCII code, or "none" for no key
+fn parse_function_key(value: &str) -> Result<Option<u16>, RuntimeError> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    match value
+        .strip_prefix(['F', 'f'])
+        .and_then(|n| n.parse::<u16>().ok())
+    {
+        Some(n @ 1..=12) => Ok(Some(132 + n)),
+        _ => recoverable_error!(ErrorCode::ConfigError, "Invalid function key: {}", value),
+    }
+}
+
+// Parse the style_map section, which lists the terminal attributes for any of the
+// reverse, bold, italic, and fixed styles.  Styles that aren't listed keep the default.
+fn parse_style_map(value: &Value) -> Result<StyleMap, RuntimeError> {
+    let mut style_map = StyleMap::default();
+    let mapping = match value.as_mapping() {
+        Some(mapping) => mapping,
+        None => {
+            return recoverable_error!(
+                ErrorCode::ConfigError,
+                "style_map must map styles to lists of attributes"
+            )
+        }
+    };
+
+    for (key, attributes) in mapping {
+        let name = key.as_str().unwrap_or_default();
+        let style = match name {
+            "reverse" => Style::Reverse,
+            "bold" => Style::Bold,
+            "italic" => Style::Italic,
+            "fixed" => Style::Fixed,
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "Unknown style in style_map: '{}'",
+                    name
+                )
+            }
+        };
+
+        let names = match attributes.as_sequence() {
+            Some(names) => names,
+            None => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "style_map.{} must be a list of attributes",
+                    name
+                )
+            }
+        };
+        let mut list = Vec::new();
+        for attribute in names {
+            let attribute = attribute.as_str().unwrap_or_default();
+            match attribute.parse::<Attribute>() {
+                Ok(a) => list.push(a),
+                Err(_) => {
+                    return recoverable_error!(
+                        ErrorCode::ConfigError,
+                        "Invalid attribute for style_map.{}: '{}'",
+                        name,
+                        attribute
+                    )
+                }
+            }
+        }
+        style_map.set(style, Attributes::new(&list));
+    }
+
+    Ok(style_map)
+}
+
+// Parse the instruction_log_filter section: whether the log starts enabled, and the opcode
+// names and [start, end] address ranges to log
+fn parse_instruction_log_filter(value: &Value) -> Result<InstructionLogFilter, RuntimeError> {
+    if !value.is_mapping() {
+        return recoverable_error!(
+            ErrorCode::ConfigError,
+            "instruction_log_filter must be a mapping"
+        );
+    }
+
+    let enabled = value["enabled"].as_bool().unwrap_or(true);
+    let opcodes = match &value["opcodes"] {
+        Value::Null => Vec::new(),
+        v => match v.as_sequence() {
+            Some(s) => s.iter().map(|o| o.as_str().unwrap_or_default()).collect(),
+            None => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "instruction_log_filter.opcodes must be a list of opcode names"
+                )
+            }
+        },
+    };
+    let mut ranges = Vec::new();
+    if let Some(s) = value["ranges"].as_sequence() {
+        for range in s {
+            match range
+                .as_sequence()
+                .map(|r| r.iter().map(|a| a.as_u64()).collect::<Vec<Option<u64>>>())
+                .as_deref()
+            {
+                Some([Some(start), Some(end)]) => ranges.push((*start as usize, *end as usize)),
+                _ => {
+                    return recoverable_error!(
+                        ErrorCode::ConfigError,
+                        "Invalid address range in instruction_log_filter: {:?}",
+                        range
+                    )
+                }
+            }
+        }
+    } else if !value["ranges"].is_null() {
+        return recoverable_error!(
+            ErrorCode::ConfigError,
+            "instruction_log_filter.ranges must be a list of [start, end] addresses"
+        );
+    }
+
+    InstructionLogFilter::new(enabled, &opcodes, &ranges)
 }
 
 fn default_volume_factor() -> f32 {
@@ -33,7 +233,35 @@ impl Default for Config {
             background: 2,
             logging: false,
             error_handling: ErrorHandling::ContinueWarnOnce,
+            error_overrides: HashMap::new(),
             volume_factor: default_volume_factor(),
+            trace_calls: false,
+            trace_parse: false,
+            trace_routines: Vec::new(),
+            instruction_log_filter: InstructionLogFilter::default(),
+            echo_scripted_input: true,
+            echo_input: true,
+            loop_threshold: 10_000,
+            predictable_seed: None,
+            seed_mode: SeedMode::Predictable,
+            capture: None,
+            headless: false,
+            debug_hud: false,
+            save_name_template: None,
+            remember_files: true,
+            allow_any_save_name: false,
+            config_dir: None,
+            standard_version: STANDARD_VERSION,
+            discard_paste_overflow: false,
+            volume_keys: DEFAULT_VOLUME_KEYS,
+            menu_key: DEFAULT_MENU_KEY,
+            describe_sounds: false,
+            graphics_font: true,
+            max_string_words: MAX_STRING_WORDS,
+            undo_depth: UNDO_DEPTH,
+            undo_budget_bytes: UNDO_BUDGET_BYTES,
+            style_map: StyleMap::default(),
+            messages: Messages::default(),
         }
     }
 }
@@ -56,27 +284,147 @@ impl TryFrom<File> for Config {
                     Some(t) => t == "enabled",
                     None => false,
                 };
-                let error_handling = match data["error_handling"].as_str() {
-                    Some(t) => match t {
-                        "continue_warn_always" => ErrorHandling::ContinueWarnAlways,
-                        "continue_warn_once" => ErrorHandling::ContinueWarnOnce,
-                        "ignore" => ErrorHandling::Ignore,
-                        "abort" => ErrorHandling::Abort,
-                        _ => ErrorHandling::ContinueWarnOnce,
-                    },
-                    None => ErrorHandling::ContinueWarnOnce,
-                };
+                let error_handling = data["error_handling"]
+                    .as_str()
+                    .and_then(ErrorHandling::from_name)
+                    .unwrap_or(ErrorHandling::ContinueWarnOnce);
+                let mut error_overrides = HashMap::new();
+                if let Some(m) = data["error_overrides"].as_mapping() {
+                    for (k, v) in m {
+                        match (
+                            k.as_str().map(|c| c.parse::<ErrorCode>()),
+                            v.as_str().and_then(ErrorHandling::from_name),
+                        ) {
+                            (Some(Ok(code)), Some(handling)) => {
+                                error_overrides.insert(code, handling);
+                            }
+                            _ => {
+                                return recoverable_error!(
+                                    ErrorCode::ConfigError,
+                                    "Invalid error override: {:?}: {:?}",
+                                    k,
+                                    v
+                                )
+                            }
+                        }
+                    }
+                }
                 let volume_factor = match data["volume_factor"].as_f64() {
                     Some(t) => t as f32,
                     None => default_volume_factor(),
                 };
-                Ok(Config::new(
+                let trace_calls = match data["trace_calls"].as_str() {
+                    Some(t) => t == "enabled",
+                    None => false,
+                };
+                let trace_parse = match data["trace_parse"].as_str() {
+                    Some(t) => t == "enabled",
+                    None => false,
+                };
+                let trace_routines = match data["trace_routines"].as_sequence() {
+                    Some(s) => s
+                        .iter()
+                        .filter_map(|v| v.as_u64())
+                        .map(|v| v as usize)
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let instruction_log_filter = match &data["instruction_log_filter"] {
+                    Value::Null => InstructionLogFilter::default(),
+                    v => parse_instruction_log_filter(v)?,
+                };
+                let echo_scripted_input = data["echo_scripted_input"].as_bool().unwrap_or(true);
+                let echo_input = data["echo_input"].as_bool().unwrap_or(true);
+                let loop_threshold = match data["loop_threshold"].as_u64() {
+                    Some(v) => v as u32,
+                    None => 10_000,
+                };
+                let predictable_seed = data["predictable_seed"].as_u64().map(|v| v as u16);
+                let seed_mode = match data["seed_mode"].as_str() {
+                    Some("seeded") => SeedMode::Seeded,
+                    _ => SeedMode::Predictable,
+                };
+                let debug_hud = data["debug_hud"].as_bool().unwrap_or(false);
+                let save_name_template = data["save_name_template"].as_str().map(String::from);
+                let remember_files = data["remember_files"].as_bool().unwrap_or(true);
+                let allow_any_save_name = data["allow_any_save_name"].as_bool().unwrap_or(false);
+                let volume_down_key = match data["volume_down_key"].as_str() {
+                    Some(key) => parse_function_key(key)?,
+                    None => DEFAULT_VOLUME_KEYS.0,
+                };
+                let volume_up_key = match data["volume_up_key"].as_str() {
+                    Some(key) => parse_function_key(key)?,
+                    None => DEFAULT_VOLUME_KEYS.1,
+                };
+                let menu_key = match data["menu_key"].as_str() {
+                    Some(key) => parse_function_key(key)?,
+                    None => DEFAULT_MENU_KEY,
+                };
+                let describe_sounds = data["describe_sounds"].as_bool().unwrap_or(false);
+                let graphics_font = data["graphics_font"].as_bool().unwrap_or(true);
+                let max_string_words = match data["max_string_words"].as_u64() {
+                    Some(v) => v as usize,
+                    None => MAX_STRING_WORDS,
+                };
+                let undo_depth = match data["undo_depth"].as_u64() {
+                    Some(v) => v as usize,
+                    None => UNDO_DEPTH,
+                };
+                let undo_budget_bytes = match data["undo_budget_bytes"].as_u64() {
+                    Some(v) => v as usize,
+                    None => UNDO_BUDGET_BYTES,
+                };
+                let style_map = match &data["style_map"] {
+                    Value::Null => StyleMap::default(),
+                    v => parse_style_map(v)?,
+                };
+                let discard_paste_overflow =
+                    data["discard_paste_overflow"].as_bool().unwrap_or(false);
+                let standard_version = match &data["standard_version"] {
+                    Value::Null => STANDARD_VERSION,
+                    v => match parse_standard_version(v) {
+                        Some(version) => version,
+                        None => {
+                            return recoverable_error!(
+                                ErrorCode::ConfigError,
+                                "Invalid standard version: {:?}",
+                                v
+                            )
+                        }
+                    },
+                };
+                let mut config = Config::new(
                     foreground,
                     background,
                     logging,
                     error_handling,
                     volume_factor,
-                ))
+                );
+                config.error_overrides = error_overrides;
+                config.trace_calls = trace_calls;
+                config.trace_routines = trace_routines;
+                config.trace_parse = trace_parse;
+                config.instruction_log_filter = instruction_log_filter;
+                config.echo_scripted_input = echo_scripted_input;
+                config.echo_input = echo_input;
+                config.loop_threshold = loop_threshold;
+                config.predictable_seed = predictable_seed;
+                config.seed_mode = seed_mode;
+                config.debug_hud = debug_hud;
+                config.save_name_template = save_name_template;
+                config.remember_files = remember_files;
+                config.allow_any_save_name = allow_any_save_name;
+                config.standard_version = standard_version;
+                config.discard_paste_overflow = discard_paste_overflow;
+                config.volume_keys = (volume_down_key, volume_up_key);
+                config.menu_key = menu_key;
+                config.describe_sounds = describe_sounds;
+                config.graphics_font = graphics_font;
+                config.max_string_words = max_string_words;
+                config.undo_depth = undo_depth;
+                config.undo_budget_bytes = undo_budget_bytes;
+                config.style_map = style_map;
+                Ok(config)
             }
             Err(e) => recoverable_error!(ErrorCode::ConfigError, "{}", e),
         }
@@ -96,7 +444,35 @@ impl Config {
             background,
             logging,
             error_handling,
+            error_overrides: HashMap::new(),
             volume_factor,
+            trace_calls: false,
+            trace_parse: false,
+            trace_routines: Vec::new(),
+            instruction_log_filter: InstructionLogFilter::default(),
+            echo_scripted_input: true,
+            echo_input: true,
+            loop_threshold: 10_000,
+            predictable_seed: None,
+            seed_mode: SeedMode::Predictable,
+            capture: None,
+            headless: false,
+            debug_hud: false,
+            save_name_template: None,
+            remember_files: true,
+            allow_any_save_name: false,
+            config_dir: None,
+            standard_version: STANDARD_VERSION,
+            discard_paste_overflow: false,
+            volume_keys: DEFAULT_VOLUME_KEYS,
+            menu_key: DEFAULT_MENU_KEY,
+            describe_sounds: false,
+            graphics_font: true,
+            max_string_words: MAX_STRING_WORDS,
+            undo_depth: UNDO_DEPTH,
+            undo_budget_bytes: UNDO_BUDGET_BYTES,
+            style_map: StyleMap::default(),
+            messages: Messages::default(),
         }
     }
 
@@ -115,7 +491,228 @@ impl Config {
     pub fn error_handling(&self) -> ErrorHandling {
         self.error_handling
     }
+    pub fn error_overrides(&self) -> &HashMap<ErrorCode, ErrorHandling> {
+        &self.error_overrides
+    }
+
     pub fn volume_factor(&self) -> f32 {
         self.volume_factor
     }
+
+    pub fn trace_calls(&self) -> bool {
+        self.trace_calls
+    }
+
+    pub fn trace_routines(&self) -> &[usize] {
+        &self.trace_routines
+    }
+
+    pub fn trace_parse(&self) -> bool {
+        self.trace_parse
+    }
+
+    pub fn set_trace_parse(&mut self, trace_parse: bool) {
+        self.trace_parse = trace_parse;
+    }
+
+    pub fn instruction_log_filter(&self) -> &InstructionLogFilter {
+        &self.instruction_log_filter
+    }
+
+    pub fn set_instruction_log_filter(&mut self, filter: InstructionLogFilter) {
+        self.instruction_log_filter = filter;
+    }
+
+    pub fn echo_scripted_input(&self) -> bool {
+        self.echo_scripted_input
+    }
+
+    pub fn echo_input(&self) -> bool {
+        self.echo_input
+    }
+
+    pub fn loop_threshold(&self) -> u32 {
+        self.loop_threshold
+    }
+
+    pub fn predictable_seed(&self) -> Option<u16> {
+        self.predictable_seed
+    }
+
+    pub fn set_predictable_seed(&mut self, seed: u16) {
+        self.predictable_seed = Some(seed);
+    }
+
+    pub fn seed_mode(&self) -> SeedMode {
+        self.seed_mode
+    }
+
+    pub fn capture(&self) -> Option<&str> {
+        self.capture.as_deref()
+    }
+
+    pub fn set_capture(&mut self, filename: &str) {
+        self.capture = Some(filename.to_string());
+    }
+
+    pub fn headless(&self) -> bool {
+        self.headless
+    }
+
+    pub fn set_headless(&mut self, headless: bool) {
+        self.headless = headless;
+    }
+
+    pub fn debug_hud(&self) -> bool {
+        self.debug_hud
+    }
+
+    pub fn set_debug_hud(&mut self, debug_hud: bool) {
+        self.debug_hud = debug_hud;
+    }
+
+    pub fn save_name_template(&self) -> Option<&str> {
+        self.save_name_template.as_deref()
+    }
+
+    pub fn set_save_name_template(&mut self, template: Option<String>) {
+        self.save_name_template = template;
+    }
+
+    pub fn remember_files(&self) -> bool {
+        self.remember_files
+    }
+
+    pub fn set_remember_files(&mut self, remember_files: bool) {
+        self.remember_files = remember_files;
+    }
+
+    pub fn allow_any_save_name(&self) -> bool {
+        self.allow_any_save_name
+    }
+
+    pub fn set_allow_any_save_name(&mut self, allow: bool) {
+        self.allow_any_save_name = allow;
+    }
+
+    pub fn standard_version(&self) -> (u8, u8) {
+        self.standard_version
+    }
+
+    pub fn set_standard_version(&mut self, version: (u8, u8)) {
+        self.standard_version = version.min(STANDARD_VERSION);
+    }
+
+    pub fn discard_paste_overflow(&self) -> bool {
+        self.discard_paste_overflow
+    }
+
+    pub fn set_discard_paste_overflow(&mut self, discard: bool) {
+        self.discard_paste_overflow = discard;
+    }
+
+    /// ZSCII codes of the keys that turn the sound volume down and up
+    pub fn volume_keys(&self) -> (Option<u16>, Option<u16>) {
+        self.volume_keys
+    }
+
+    pub fn set_volume_keys(&mut self, down: Option<u16>, up: Option<u16>) {
+        self.volume_keys = (down, up);
+    }
+
+    /// ZSCII code of the key that opens the interpreter menu
+    pub fn menu_key(&self) -> Option<u16> {
+        self.menu_key
+    }
+
+    pub fn set_menu_key(&mut self, key: Option<u16>) {
+        self.menu_key = key;
+    }
+
+    pub fn describe_sounds(&self) -> bool {
+        self.describe_sounds
+    }
+
+    pub fn set_describe_sounds(&mut self, describe_sounds: bool) {
+        self.describe_sounds = describe_sounds;
+    }
+
+    pub fn graphics_font(&self) -> bool {
+        self.graphics_font
+    }
+
+    pub fn set_graphics_font(&mut self, graphics_font: bool) {
+        self.graphics_font = graphics_font;
+    }
+
+    pub fn max_string_words(&self) -> usize {
+        self.max_string_words
+    }
+
+    pub fn set_max_string_words(&mut self, words: usize) {
+        self.max_string_words = words;
+    }
+
+    pub fn undo_depth(&self) -> usize {
+        self.undo_depth
+    }
+
+    pub fn set_undo_depth(&mut self, depth: usize) {
+        self.undo_depth = depth;
+    }
+
+    pub fn undo_budget_bytes(&self) -> usize {
+        self.undo_budget_bytes
+    }
+
+    pub fn set_undo_budget_bytes(&mut self, bytes: usize) {
+        self.undo_budget_bytes = bytes;
+    }
+
+    pub fn style_map(&self) -> StyleMap {
+        self.style_map
+    }
+
+    pub fn set_style_map(&mut self, style_map: StyleMap) {
+        self.style_map = style_map;
+    }
+
+    pub fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    pub fn set_messages(&mut self, messages: Messages) {
+        self.messages = messages;
+    }
+
+    pub fn config_dir(&self) -> Option<&str> {
+        self.config_dir.as_deref()
+    }
+
+    pub fn set_config_dir(&mut self, dir: &str) {
+        self.config_dir = Some(dir.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_standard_version() {
+        assert_eq!(
+            parse_standard_version(&Value::String("1.0".to_string())),
+            Some((1, 0))
+        );
+        let number: Value = serde_yaml::from_str("1.0").unwrap();
+        assert_eq!(parse_standard_version(&number), Some((1, 0)));
+        let number: Value = serde_yaml::from_str("0.2").unwrap();
+        assert_eq!(parse_standard_version(&number), Some((0, 2)));
+        // Later than the interpreter implements
+        assert_eq!(
+            parse_standard_version(&Value::String("1.1".to_string())),
+            None
+        );
+        assert_eq!(parse_standard_version(&Value::Bool(true)), None);
+    }
 }
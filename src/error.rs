@@ -1,36 +1,47 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ErrorCode {
     BlorbMissingChunk,
     BlorbLoopEntrySize,
     BlorbRIdxEntrySize,
+    BlorbRDesEntrySize,
+    BlorbResoEntrySize,
+    BlorbPictureData,
     ConfigError,
     DivideByZero,
     FileError,
     FileExists,
+    FileTooLarge,
     FrameUnderflow,
     IFFInvalidChunkId,
     IFhdChunkLength,
     IllegalMemoryAccess,
+    InfiniteLoop,
     Interpreter,
+    Interrupted,
     InvalidAbbreviation,
     InvalidAddress,
+    InvalidBranch,
+    InvalidBuffer,
     InvalidColor,
     InvalidFile,
     InvalidFilename,
     InvalidInput,
+    InvalidInputStream,
     InvalidInstruction,
     InvalidLocalVariable,
     InvalidObjectAttribute,
     InvalidObjectTree,
     InvalidObjectProperty,
     InvalidObjectPropertySize,
+    InvalidOperandCount,
     InvalidOutputStream,
     InvalidRoutine,
     InvalidShift,
     InvalidSoundEffect,
     InvalidWindow,
+    InvalidWindowProperty,
     NoFrame,
     NoReadInterrupt,
     NoSoundInterrupt,
@@ -40,14 +51,92 @@ pub enum ErrorCode {
     Restore,
     ReturnNoCaller,
     Save,
+    ScreenTooSmall,
+    SaveInInterrupt,
     Stream3Table,
     SoundConversion,
     SoundPlayback,
     StackUnderflow,
     Transcript,
     UndoNoState,
+    UndoTooLarge,
     UnimplementedInstruction,
+    UnsupportedOpcode,
     UnsupportedVersion,
+    UnterminatedString,
+}
+
+// Error codes are named as they appear in error messages, e.g. `DivideByZero`
+impl FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BlorbMissingChunk" => Ok(ErrorCode::BlorbMissingChunk),
+            "BlorbLoopEntrySize" => Ok(ErrorCode::BlorbLoopEntrySize),
+            "BlorbRIdxEntrySize" => Ok(ErrorCode::BlorbRIdxEntrySize),
+            "BlorbRDesEntrySize" => Ok(ErrorCode::BlorbRDesEntrySize),
+            "BlorbResoEntrySize" => Ok(ErrorCode::BlorbResoEntrySize),
+            "BlorbPictureData" => Ok(ErrorCode::BlorbPictureData),
+            "ConfigError" => Ok(ErrorCode::ConfigError),
+            "DivideByZero" => Ok(ErrorCode::DivideByZero),
+            "FileError" => Ok(ErrorCode::FileError),
+            "FileExists" => Ok(ErrorCode::FileExists),
+            "FileTooLarge" => Ok(ErrorCode::FileTooLarge),
+            "FrameUnderflow" => Ok(ErrorCode::FrameUnderflow),
+            "IFFInvalidChunkId" => Ok(ErrorCode::IFFInvalidChunkId),
+            "IFhdChunkLength" => Ok(ErrorCode::IFhdChunkLength),
+            "IllegalMemoryAccess" => Ok(ErrorCode::IllegalMemoryAccess),
+            "InfiniteLoop" => Ok(ErrorCode::InfiniteLoop),
+            "Interpreter" => Ok(ErrorCode::Interpreter),
+            "Interrupted" => Ok(ErrorCode::Interrupted),
+            "InvalidAbbreviation" => Ok(ErrorCode::InvalidAbbreviation),
+            "InvalidAddress" => Ok(ErrorCode::InvalidAddress),
+            "InvalidBranch" => Ok(ErrorCode::InvalidBranch),
+            "InvalidBuffer" => Ok(ErrorCode::InvalidBuffer),
+            "InvalidColor" => Ok(ErrorCode::InvalidColor),
+            "InvalidFile" => Ok(ErrorCode::InvalidFile),
+            "InvalidFilename" => Ok(ErrorCode::InvalidFilename),
+            "InvalidInput" => Ok(ErrorCode::InvalidInput),
+            "InvalidInputStream" => Ok(ErrorCode::InvalidInputStream),
+            "InvalidInstruction" => Ok(ErrorCode::InvalidInstruction),
+            "InvalidLocalVariable" => Ok(ErrorCode::InvalidLocalVariable),
+            "InvalidObjectAttribute" => Ok(ErrorCode::InvalidObjectAttribute),
+            "InvalidObjectTree" => Ok(ErrorCode::InvalidObjectTree),
+            "InvalidObjectProperty" => Ok(ErrorCode::InvalidObjectProperty),
+            "InvalidObjectPropertySize" => Ok(ErrorCode::InvalidObjectPropertySize),
+            "InvalidOperandCount" => Ok(ErrorCode::InvalidOperandCount),
+            "InvalidOutputStream" => Ok(ErrorCode::InvalidOutputStream),
+            "InvalidRoutine" => Ok(ErrorCode::InvalidRoutine),
+            "InvalidShift" => Ok(ErrorCode::InvalidShift),
+            "InvalidSoundEffect" => Ok(ErrorCode::InvalidSoundEffect),
+            "InvalidWindow" => Ok(ErrorCode::InvalidWindow),
+            "InvalidWindowProperty" => Ok(ErrorCode::InvalidWindowProperty),
+            "NoFrame" => Ok(ErrorCode::NoFrame),
+            "NoReadInterrupt" => Ok(ErrorCode::NoReadInterrupt),
+            "NoSoundInterrupt" => Ok(ErrorCode::NoSoundInterrupt),
+            "Quetzal" => Ok(ErrorCode::Quetzal),
+            "ReadNothing" => Ok(ErrorCode::ReadNothing),
+            "ReadNoTerminator" => Ok(ErrorCode::ReadNoTerminator),
+            "Restore" => Ok(ErrorCode::Restore),
+            "ReturnNoCaller" => Ok(ErrorCode::ReturnNoCaller),
+            "Save" => Ok(ErrorCode::Save),
+            "ScreenTooSmall" => Ok(ErrorCode::ScreenTooSmall),
+            "SaveInInterrupt" => Ok(ErrorCode::SaveInInterrupt),
+            "Stream3Table" => Ok(ErrorCode::Stream3Table),
+            "SoundConversion" => Ok(ErrorCode::SoundConversion),
+            "SoundPlayback" => Ok(ErrorCode::SoundPlayback),
+            "StackUnderflow" => Ok(ErrorCode::StackUnderflow),
+            "Transcript" => Ok(ErrorCode::Transcript),
+            "UndoNoState" => Ok(ErrorCode::UndoNoState),
+            "UndoTooLarge" => Ok(ErrorCode::UndoTooLarge),
+            "UnimplementedInstruction" => Ok(ErrorCode::UnimplementedInstruction),
+            "UnsupportedOpcode" => Ok(ErrorCode::UnsupportedOpcode),
+            "UnsupportedVersion" => Ok(ErrorCode::UnsupportedVersion),
+            "UnterminatedString" => Ok(ErrorCode::UnterminatedString),
+            _ => Err(format!("Unknown error code {}", s)),
+        }
+    }
 }
 
 pub struct RuntimeError {
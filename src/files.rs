@@ -1,10 +1,37 @@
-use std::path::Path;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Read,
+    path::Path,
+};
+
+use serde_yaml::{Mapping, Value};
 
 use crate::{
     error::{ErrorCode, RuntimeError},
     recoverable_error,
 };
 
+/// How an existing file is treated when it is opened for writing
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OpenMode {
+    /// Replace any existing contents, as for save files
+    Truncate,
+    /// Add to the end of any existing contents, as for transcripts
+    Append,
+}
+
+impl OpenMode {
+    pub fn options(&self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        options.create(true);
+        match self {
+            OpenMode::Truncate => options.write(true).truncate(true),
+            OpenMode::Append => options.append(true),
+        };
+        options
+    }
+}
+
 fn string_to_vec_u16(s: String) -> Vec<u16> {
     s.chars().map(|c| c as u16).collect()
 }
@@ -26,6 +53,72 @@ pub fn first_available(base: &str, suffix: &str) -> Result<Vec<u16>, RuntimeErro
     }
 }
 
+/// Reduce a name to lowercase ASCII letters and digits, for use in a file name.  Returns
+/// None if nothing is left.
+pub fn sanitize_name(name: &str) -> Option<String> {
+    let s: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+/// Expand a save name template, replacing `{name}` with the game name, `{n}` with the
+/// 2-digit save number, `{turn}` with the 3-digit turn count, and `{room}` with the
+/// sanitized room name.  Returns None if the template needs a room name and there isn't
+/// one.
+pub fn expand_save_name(
+    template: &str,
+    name: &str,
+    n: usize,
+    turn: u32,
+    room: Option<&str>,
+) -> Option<String> {
+    let mut filename = template
+        .replace("{name}", name)
+        .replace("{n}", &format!("{:02}", n))
+        .replace("{turn}", &format!("{:03}", turn));
+    if filename.contains("{room}") {
+        filename = filename.replace("{room}", &sanitize_name(room?)?);
+    }
+
+    Some(filename)
+}
+
+/// First save name from the template that doesn't exist yet, counting `{n}` upwards.  A
+/// template without `{n}` only has one name.  Returns None when the template can't be
+/// expanded, so the caller can fall back to the numbered form.
+pub fn first_available_from_template(
+    template: &str,
+    name: &str,
+    turn: u32,
+    room: Option<&str>,
+    suffix: &str,
+) -> Result<Option<Vec<u16>>, RuntimeError> {
+    let mut n = 1;
+    loop {
+        let filename = match expand_save_name(template, name, n, turn, room) {
+            Some(f) => format!("{}.{}", f, suffix),
+            None => return Ok(None),
+        };
+        match Path::new(&filename).try_exists() {
+            Ok(b) => {
+                if !b || !template.contains("{n}") {
+                    return Ok(Some(string_to_vec_u16(filename)));
+                }
+            }
+            Err(e) => return recoverable_error!(ErrorCode::FileError, "{}", e),
+        }
+
+        n += 1;
+    }
+}
+
 pub fn last_existing(base: &str, suffix: &str) -> Result<Vec<u16>, RuntimeError> {
     let mut n = 1;
     loop {
@@ -62,13 +155,18 @@ fn check_config(name: &str) -> bool {
     }
 }
 
+/// The per-user configuration directory, `~/.mxyzptlk`
+pub fn default_config_dir() -> Option<String> {
+    dirs::home_dir().map(|home| format!("{}/.mxyzptlk", home.to_string_lossy()))
+}
+
 pub fn config_file(name: &str) -> Option<String> {
     if check_config(name) {
         // Check the CWD first
         Some(name.to_string())
-    } else if let Some(home) = dirs::home_dir() {
+    } else if let Some(dir) = default_config_dir() {
         // And then check ~/.mxyzptlk/{name} if not found
-        let filename = format!("{}/.mxyzptlk/{}", home.to_str().unwrap(), name);
+        let filename = format!("{}/{}", dir, name);
         if check_config(&filename) {
             Some(filename)
         } else {
@@ -79,6 +177,65 @@ pub fn config_file(name: &str) -> Option<String> {
     }
 }
 
+fn read_recent(path: &str) -> Option<Mapping> {
+    let file = File::open(path).ok()?;
+    match serde_yaml::from_reader::<File, Value>(file) {
+        Ok(Value::Mapping(m)) => Some(m),
+        Ok(_) => None,
+        Err(e) => {
+            error!(target: "app::state", "Error reading {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// The last save file used for the game `name`, from the recent files list at `path`.
+pub fn recent_file(path: &str, name: &str) -> Option<String> {
+    let recent = read_recent(path)?;
+    let entry = recent.get(name)?;
+    let filename = entry["filename"].as_str()?;
+    match entry["directory"].as_str() {
+        Some(d) if !d.is_empty() => Some(Path::new(d).join(filename).to_string_lossy().to_string()),
+        _ => Some(filename.to_string()),
+    }
+}
+
+/// Record `filename` as the last save file used for the game `name` in the recent files list
+/// at `path`.  This is best effort: errors are logged, never returned.
+pub fn remember_file(path: &str, name: &str, filename: &str) {
+    let file = Path::new(filename);
+    let directory = file
+        .parent()
+        .map(|d| d.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let filename = match file.file_name() {
+        Some(f) => f.to_string_lossy().to_string(),
+        None => return,
+    };
+
+    let mut entry = Mapping::new();
+    entry.insert(Value::from("directory"), Value::from(directory));
+    entry.insert(Value::from("filename"), Value::from(filename));
+    let mut recent = read_recent(path).unwrap_or_default();
+    recent.insert(Value::from(name), Value::Mapping(entry));
+
+    if let Some(dir) = Path::new(path).parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            error!(target: "app::state", "Error creating {}: {}", dir.display(), e);
+            return;
+        }
+    }
+
+    match File::create(path) {
+        Ok(f) => {
+            if let Err(e) = serde_yaml::to_writer(f, &Value::Mapping(recent)) {
+                error!(target: "app::state", "Error writing {}: {}", path, e);
+            }
+        }
+        Err(e) => error!(target: "app::state", "Error creating {}: {}", path, e),
+    }
+}
+
 pub fn check_existing(filename: &str) -> Option<String> {
     match Path::new(&filename).try_exists() {
         Ok(b) => {
@@ -95,13 +252,350 @@ pub fn check_existing(filename: &str) -> Option<String> {
     }
 }
 
-pub fn find_existing(base: &str, extensions: &[&str]) -> Option<String> {
-    for ext in extensions {
-        let filename = format!("{}.{}", base, ext);
-        if let Some(filename) = check_existing(&filename) {
-            return Some(filename);
+/// Every existing file named `base` with one of `extensions`, ignoring the case of the
+/// extension.  The most preferred comes first: extensions as given, in the order given,
+/// then other cases of them in the same order, then by name.
+pub fn find_all_existing(base: &str, extensions: &[&str]) -> Vec<String> {
+    let path = Path::new(base);
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Vec::new(),
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!(target: "app::state", "Error reading directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut found = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some((stem, ext)) = file_name.rsplit_once('.') {
+            if stem != name {
+                continue;
+            }
+            if let Some(rank) = extensions.iter().position(|e| e.eq_ignore_ascii_case(ext)) {
+                found.push((ext != extensions[rank], rank, format!("{}.{}", base, ext)));
+            }
+        }
+    }
+    found.sort();
+    found.into_iter().map(|(_, _, filename)| filename).collect()
+}
+
+/// The Blorb resource file for a game: `blorb` when one was named on the command line,
+/// otherwise the best match for the game file `base` with a `.blorb` or `.blb` extension.
+/// `base` is None when there's no game file location to look in, as when reading stdin.
+/// The choice, and any files passed over, are logged.
+pub fn find_blorb(base: Option<&str>, blorb: Option<&str>) -> Option<String> {
+    let mut found = match base {
+        Some(base) => find_all_existing(base, &["blorb", "blb"]),
+        None => Vec::new(),
+    };
+    let chosen = match blorb {
+        Some(blorb) => blorb.to_string(),
+        None if found.is_empty() => return None,
+        None => found.remove(0),
+    };
+    info!(target: "app::resource", "Resource file: {}", chosen);
+    for f in found.iter().filter(|f| **f != chosen) {
+        info!(target: "app::resource", "Ignoring resource file: {}", f);
+    }
+
+    Some(chosen)
+}
+
+/// Current UTC time in ISO 8601 format, for headers in text files
+pub fn timestamp() -> String {
+    let now = time::OffsetDateTime::now_utc();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+/// Reject file names that could overwrite a game or resource file: names ending
+/// in `.z1` - `.z8`, `.blb`, or `.blorb`, ignoring case, when the file exists and
+/// is a story or Blorb file.
+pub fn validate_save_name(filename: &str) -> Result<(), RuntimeError> {
+    let name = filename.to_lowercase();
+    let story = (1..=8).any(|v| name.ends_with(&format!(".z{}", v)));
+    if (story || name.ends_with(".blb") || name.ends_with(".blorb"))
+        && is_story_file(Path::new(filename))
+    {
+        recoverable_error!(
+            ErrorCode::InvalidFilename,
+            "'{}' is a game file and can't be overwritten",
+            filename
+        )
+    } else {
+        Ok(())
+    }
+}
+
+// True if `path` can be read and starts with a Z-Machine version byte or a Blorb FORM header
+fn is_story_file(path: &Path) -> bool {
+    let mut header = [0; 12];
+    match File::open(path).and_then(|mut f| f.read_exact(&mut header)) {
+        Ok(()) => {
+            (1..=8).contains(&header[0]) || (&header[0..4] == b"FORM" && &header[8..12] == b"IFRS")
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert_ok, assert_ok_eq, assert_some_eq,
+        test_util::{capture_log, log},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_some_eq!(sanitize_name("West of House"), "westofhouse");
+        assert_some_eq!(
+            sanitize_name("Maze (twisty, little passages)"),
+            "mazetwistylittlepassages"
+        );
+        assert_some_eq!(sanitize_name("Room 101"), "room101");
+        assert!(sanitize_name("").is_none());
+        assert!(sanitize_name("!?-- ").is_none());
+        assert!(sanitize_name("\u{e4}\u{f6}").is_none());
+    }
+
+    #[test]
+    fn test_expand_save_name() {
+        assert_some_eq!(
+            expand_save_name(
+                "{name}-{room}-{turn}",
+                "zork1",
+                1,
+                14,
+                Some("West of House")
+            ),
+            "zork1-westofhouse-014"
+        );
+        assert_some_eq!(
+            expand_save_name("{name}-{n}", "zork1", 3, 14, None),
+            "zork1-03"
+        );
+        assert_some_eq!(
+            expand_save_name("{name}-t{turn}", "zork1", 1, 1234, None),
+            "zork1-t1234"
+        );
+        assert!(expand_save_name("{name}-{room}", "zork1", 1, 14, None).is_none());
+        assert!(expand_save_name("{name}-{room}", "zork1", 1, 14, Some("...")).is_none());
+    }
+
+    #[test]
+    fn test_first_available_from_template() {
+        assert_ok_eq!(
+            first_available_from_template("test-t{n}", "test", 0, None, "ifzs"),
+            Some(string_to_vec_u16("test-t01.ifzs".to_string()))
+        );
+        assert!(fs::write("test-t01.ifzs", "").is_ok());
+        let n = first_available_from_template("test-t{n}", "test", 0, None, "ifzs");
+        let single = first_available_from_template("test-t01", "test", 0, None, "ifzs");
+        assert!(fs::remove_file("test-t01.ifzs").is_ok());
+        assert_ok_eq!(n, Some(string_to_vec_u16("test-t02.ifzs".to_string())));
+        assert_ok_eq!(single, Some(string_to_vec_u16("test-t01.ifzs".to_string())));
+        assert!(assert_ok!(first_available_from_template(
+            "{name}-{room}",
+            "test",
+            0,
+            None,
+            "ifzs"
+        ))
+        .is_none());
+    }
+
+    #[test]
+    fn test_validate_save_name() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut story = vec![0; 64];
+        story[0] = 5;
+        let mut blorb = b"FORM\0\0\0\x04IFRS".to_vec();
+        blorb.resize(64, 0);
+        for (name, data) in [
+            ("zork1.sav", &story),
+            ("zork1.z", &story),
+            ("zork1.z9", &story),
+            ("z5", &story),
+            ("zork1.blorbs", &blorb),
+        ] {
+            let path = dir.path().join(name);
+            assert!(fs::write(&path, data).is_ok());
+            assert!(validate_save_name(path.to_str().unwrap()).is_ok());
+        }
+        for (name, data) in [
+            ("zork1.z1", &story),
+            ("zork1.z3", &story),
+            ("zork1.z8", &story),
+            ("ZORK1.Z5", &story),
+            ("zork1.blb", &blorb),
+            ("zork1.BLB", &blorb),
+            (".blorb", &blorb),
+            ("zork1.Blorb", &story),
+        ] {
+            let path = dir.path().join(name);
+            assert!(fs::write(&path, data).is_ok());
+            let e = validate_save_name(path.to_str().unwrap()).expect_err(name);
+            assert_eq!(e.code(), ErrorCode::InvalidFilename);
+            assert!(e.is_recoverable());
         }
     }
 
-    None
+    #[test]
+    fn test_validate_save_name_not_story() {
+        let dir = assert_ok!(tempfile::tempdir());
+        // Doesn't exist
+        let path = dir.path().join("foo.z5");
+        assert!(validate_save_name(path.to_str().unwrap()).is_ok());
+        // Not a story or Blorb file
+        let path = dir.path().join("data.blb");
+        assert!(fs::write(&path, b"FORM\0\0\0\x04AIFF and some more").is_ok());
+        assert!(validate_save_name(path.to_str().unwrap()).is_ok());
+        let path = dir.path().join("notes.z3");
+        assert!(fs::write(&path, b"Notes about the game").is_ok());
+        assert!(validate_save_name(path.to_str().unwrap()).is_ok());
+        // Too short to be a story file
+        let path = dir.path().join("short.z5");
+        assert!(fs::write(&path, [5, 0, 0]).is_ok());
+        assert!(validate_save_name(path.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_recent_file() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("state").join("recent.yml");
+        let path = path.to_str().unwrap();
+        assert!(recent_file(path, "zork1").is_none());
+        remember_file(path, "zork1", "saves/zork1-03.ifzs");
+        remember_file(path, "trinity", "trinity.ifzs");
+        assert_some_eq!(
+            recent_file(path, "zork1"),
+            Path::new("saves")
+                .join("zork1-03.ifzs")
+                .to_string_lossy()
+                .to_string()
+        );
+        assert_some_eq!(recent_file(path, "trinity"), "trinity.ifzs");
+        // Later saves replace the entry for the game
+        remember_file(path, "zork1", "zork1-04.ifzs");
+        assert_some_eq!(recent_file(path, "zork1"), "zork1-04.ifzs");
+        assert_some_eq!(recent_file(path, "trinity"), "trinity.ifzs");
+    }
+
+    #[test]
+    fn test_recent_file_invalid() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("recent.yml");
+        assert!(fs::write(&path, "- not a mapping\n").is_ok());
+        let path = path.to_str().unwrap();
+        assert!(recent_file(path, "zork1").is_none());
+        // The invalid list is replaced
+        remember_file(path, "zork1", "zork1.ifzs");
+        assert_some_eq!(recent_file(path, "zork1"), "zork1.ifzs");
+    }
+
+    #[test]
+    fn test_remember_file_unwritable() {
+        let dir = assert_ok!(tempfile::tempdir());
+        // The parent "directory" is a file
+        let parent = dir.path().join("file");
+        assert!(fs::write(&parent, "").is_ok());
+        let path = parent.join("recent.yml");
+        let path = path.to_str().unwrap();
+        remember_file(path, "zork1", "zork1.ifzs");
+        assert!(recent_file(path, "zork1").is_none());
+    }
+
+    // A directory holding `files` and the game file base name in it
+    fn game_dir(files: &[&str]) -> (tempfile::TempDir, String) {
+        let dir = assert_ok!(tempfile::tempdir());
+        for f in files {
+            assert!(fs::write(dir.path().join(f), b"FORM").is_ok());
+        }
+        let base = dir.path().join("game").to_string_lossy().to_string();
+        (dir, base)
+    }
+
+    #[test]
+    fn test_find_blorb_both_present() {
+        let (_dir, base) = game_dir(&["game.z5", "game.blb", "game.blorb", "other.blorb"]);
+        assert_eq!(
+            find_all_existing(&base, &["blorb", "blb"]),
+            [format!("{}.blorb", base), format!("{}.blb", base)]
+        );
+        capture_log("app::resource");
+        assert_some_eq!(find_blorb(Some(&base), None), format!("{}.blorb", base));
+        assert_eq!(
+            log(),
+            [
+                format!("Resource file: {}.blorb", base),
+                format!("Ignoring resource file: {}.blb", base)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_blorb_case() {
+        let (_dir, base) = game_dir(&["game.BLB"]);
+        assert_some_eq!(find_blorb(Some(&base), None), format!("{}.BLB", base));
+        // An extension as given comes before other cases
+        let (_dir, base) = game_dir(&["game.BLORB", "game.blb"]);
+        assert_eq!(
+            find_all_existing(&base, &["blorb", "blb"]),
+            [format!("{}.blb", base), format!("{}.BLORB", base)]
+        );
+        assert_some_eq!(find_blorb(Some(&base), None), format!("{}.blb", base));
+        // Then the order of extensions.  The game's name has to match as it is.
+        let (_dir, base) = game_dir(&["game.Blb", "game.Blorb", "GAME.blorb"]);
+        assert_eq!(
+            find_all_existing(&base, &["blorb", "blb"]),
+            [format!("{}.Blorb", base), format!("{}.Blb", base)]
+        );
+    }
+
+    #[test]
+    fn test_find_blorb_none() {
+        let (_dir, base) = game_dir(&["game.z5", "game.blorb.bak"]);
+        assert!(find_blorb(Some(&base), None).is_none());
+        assert!(find_blorb(None, None).is_none());
+        assert!(find_blorb(Some("/no/such/dir/game"), None).is_none());
+    }
+
+    #[test]
+    fn test_find_blorb_override() {
+        let (_dir, base) = game_dir(&["game.blorb", "game.blb"]);
+        capture_log("app::resource");
+        assert_some_eq!(
+            find_blorb(Some(&base), Some("sounds.blorb")),
+            "sounds.blorb"
+        );
+        assert_eq!(
+            log(),
+            [
+                "Resource file: sounds.blorb".to_string(),
+                format!("Ignoring resource file: {}.blorb", base),
+                format!("Ignoring resource file: {}.blb", base)
+            ]
+        );
+        // Reading stdin, there's only the override
+        assert_some_eq!(find_blorb(None, Some("sounds.blorb")), "sounds.blorb");
+    }
 }
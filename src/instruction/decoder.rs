@@ -1,7 +1,11 @@
 use super::*;
 use crate::{
     error::*,
-    zmachine::{state::memory, ZMachine},
+    fatal_error,
+    zmachine::{
+        state::memory::{self, Memory},
+        ZMachine,
+    },
 };
 
 fn operand_type(type_byte: u8, operand_index: u8) -> Option<OperandType> {
@@ -107,13 +111,19 @@ const STORE_INSTRUCTIONS: &[u8] = &[
 
 const EXT_STORE_INSTRUCTIONS: &[u8] = &[0x00, 0x01, 0x02, 0x03, 0x04, 0x09, 0x0a];
 
+// GET_WIND_PROP and BUFFER_SCREEN
+const V6_EXT_STORE_INSTRUCTIONS: &[u8] = &[0x13, 0x1d];
+
 fn is_store_instruction(opcode: &Opcode) -> bool {
     match opcode.form() {
-        OpcodeForm::Ext => EXT_STORE_INSTRUCTIONS.to_vec().contains(&opcode.opcode()),
+        OpcodeForm::Ext => {
+            EXT_STORE_INSTRUCTIONS.contains(&opcode.opcode())
+                || (opcode.version() == 6 && V6_EXT_STORE_INSTRUCTIONS.contains(&opcode.opcode()))
+        }
         _ => {
             let mut v = STORE_INSTRUCTIONS.to_vec();
             match opcode.version() {
-                3 => {}
+                ..=3 => {}
                 4 => {
                     v.push(0xB5);
                     v.push(0xB6);
@@ -121,6 +131,10 @@ fn is_store_instruction(opcode: &Opcode) -> bool {
                 _ => {
                     v.push(0xB9);
                     v.push(0xE4);
+                    // V6 PULL stores the value it pulls
+                    if opcode.version() == 6 {
+                        v.push(0xE9);
+                    }
                 }
             }
 
@@ -150,6 +164,15 @@ fn branch_address(address: usize, offset: i16) -> usize {
     }
 }
 
+// Offsets 0 and 1 return false and true.  Anything else is relative to `address`, and may
+// even land on address 0 or 1 in a corrupted story.
+fn new_branch(address: usize, condition: bool, base: usize, offset: i16) -> Branch {
+    match offset {
+        0 | 1 => Branch::new(address, condition, offset as usize),
+        _ => Branch::jump_to(address, condition, branch_address(base, offset)),
+    }
+}
+
 fn branch_condition(
     address: usize,
     bytes: &[u8],
@@ -162,10 +185,11 @@ fn branch_condition(
             let b_offset = b & 0x3f;
             Ok((
                 offset + 1,
-                Some(Branch::new(
+                Some(new_branch(
                     address + offset,
                     condition,
-                    branch_address(address + offset - 1, b_offset as i16),
+                    address + offset - 1,
+                    b_offset as i16,
                 )),
             ))
         }
@@ -176,10 +200,11 @@ fn branch_condition(
             }
             Ok((
                 offset + 2,
-                Some(Branch::new(
+                Some(new_branch(
                     address + offset,
                     condition,
-                    branch_address(address + offset, b_offset as i16),
+                    address + offset,
+                    b_offset as i16,
                 )),
             ))
         }
@@ -199,12 +224,12 @@ fn branch(
         },
         _ => match opcode.operand_count() {
             OperandCount::_0OP => match (opcode.version(), opcode.instruction()) {
-                (_, 0x0d) | (_, 0x0f) => branch_condition(address, bytes, offset),
-                (3, 0x05) | (3, 0x06) => branch_condition(address, bytes, offset),
+                (_, 0x0d) | (5.., 0x0f) => branch_condition(address, bytes, offset),
+                (..=3, 0x05) | (..=3, 0x06) => branch_condition(address, bytes, offset),
                 (_, _) => Ok((offset, None)),
             },
             OperandCount::_1OP => match opcode.instruction() {
-                0x00 | 0x01 | 0x02 => branch_condition(address, bytes, offset),
+                0x00..=0x02 => branch_condition(address, bytes, offset),
                 _ => Ok((offset, None)),
             },
             OperandCount::_2OP => match opcode.instruction() {
@@ -221,7 +246,11 @@ fn branch(
     }
 }
 
-fn opcode(bytes: &[u8], version: u8, offset: usize) -> Result<(usize, Opcode), RuntimeError> {
+pub(super) fn opcode(
+    bytes: &[u8],
+    version: u8,
+    offset: usize,
+) -> Result<(usize, Opcode), RuntimeError> {
     let mut opcode = bytes[offset];
     let (offset, form) = match opcode {
         0xBE => {
@@ -269,17 +298,75 @@ fn opcode(bytes: &[u8], version: u8, offset: usize) -> Result<(usize, Opcode), R
     ))
 }
 
+/// Decode the branch bytes at `address`, returning the branch and the address that
+/// follows it.  Used to resume from a V3 SAVE, where a save file records the address of
+/// the instruction's branch byte.
+pub fn decode_branch(zmachine: &ZMachine, address: usize) -> Result<(Branch, usize), RuntimeError> {
+    let bytes = zmachine.instruction(address)?;
+    match branch_condition(address, &bytes, 0)? {
+        (offset, Some(branch)) => Ok((branch, address + offset)),
+        (_, None) => fatal_error!(
+            ErrorCode::InvalidInstruction,
+            "No branch at {:#06x}",
+            address
+        ),
+    }
+}
+
 pub fn decode_instruction(
     zmachine: &ZMachine,
     address: usize,
 ) -> Result<Instruction, RuntimeError> {
-    let version = zmachine.version();
-    let mut bytes = zmachine.instruction(address);
+    decode(
+        zmachine.instruction(address)?,
+        zmachine.version(),
+        address,
+        zmachine.memory_size(),
+    )
+}
+
+/// Decode the instruction at `address` straight from a story's memory, without a running
+/// ZMachine.  Used by the disassembler.
+pub fn decode_memory(
+    memory: &Memory,
+    version: u8,
+    address: usize,
+) -> Result<Instruction, RuntimeError> {
+    if address >= memory.size() {
+        return fatal_error!(
+            ErrorCode::InvalidAddress,
+            "Instruction address {:#06x} beyond end of memory ({:#06x})",
+            address,
+            memory.size() - 1
+        );
+    }
+
+    // Padded as ZMachine::instruction does
+    let mut bytes = memory.slice(address, 23);
+    bytes.resize(23, 0);
+    decode(bytes, version, address, memory.size())
+}
+
+// Decode the instruction at `address` from `bytes`, which start at `address` and are padded
+// to the longest an instruction can be
+fn decode(
+    mut bytes: Vec<u8>,
+    version: u8,
+    address: usize,
+    memory_size: usize,
+) -> Result<Instruction, RuntimeError> {
     let (offset, opcode) = opcode(&bytes, version, 0)?;
     let (offset, operand_types) = operand_types(&bytes, &opcode, offset)?;
     let (offset, operands) = operands(&bytes, &operand_types, offset)?;
     let (offset, store) = result_variable(address + offset, &bytes, &opcode, offset)?;
     let (offset, branch) = branch(address, &bytes, &opcode, offset)?;
+    if address + offset > memory_size {
+        return fatal_error!(
+            ErrorCode::InvalidInstruction,
+            "Instruction at {:#06x} runs past the end of memory",
+            address
+        );
+    }
 
     bytes.truncate(offset);
     Ok(Instruction::new(
@@ -295,9 +382,14 @@ pub fn decode_instruction(
 
 #[cfg(test)]
 mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
     use crate::{
         assert_ok, assert_some, assert_some_eq,
-        test_util::{mock_zmachine, test_map},
+        config::Config,
+        test_util::{mock_zmachine, random_iterations, test_map},
+        zmachine::state::memory::Memory,
     };
 
     use super::*;
@@ -653,6 +745,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_result_variable_v6() {
+        let stores = [0x00, 0x01, 0x02, 0x03, 0x04, 0x09, 0x0a, 0x13, 0x1d];
+
+        for o in 0..=0xFF {
+            let r = assert_ok!(result_variable(
+                0x1234,
+                &[0xFF, 0x56, 0xFF],
+                &mock_opcode(6, o, o, OpcodeForm::Ext, OperandCount::_VAR),
+                1,
+            ));
+            assert_eq!(r.1.is_some(), stores.contains(&o));
+        }
+
+        // PULL stores its result in V6
+        for (version, store) in [(5, false), (6, true)] {
+            let r = assert_ok!(result_variable(
+                0x1234,
+                &[0xE9, 0x56, 0xFF],
+                &mock_opcode(version, 0xE9, 0x09, OpcodeForm::Var, OperandCount::_VAR),
+                1,
+            ));
+            assert_eq!(r.1.is_some(), store);
+        }
+    }
+
     #[test]
     fn test_branch_address() {
         assert_eq!(branch_address(0x1234, 0), 0);
@@ -691,7 +809,8 @@ mod tests {
 
     #[test]
     fn test_branch_v3_zero_op() {
-        let instructions = [0x05, 0x06, 0x0D, 0x0F];
+        // PIRACY ($0F) is V5+, and illegal in V3
+        let instructions = [0x05, 0x06, 0x0D];
         for i in 0..=0xFF {
             let b = assert_ok!(branch(
                 0x1234,
@@ -711,7 +830,7 @@ mod tests {
 
     #[test]
     fn test_branch_v4_zero_op() {
-        let instructions = [0x0D, 0x0F];
+        let instructions = [0x0D];
         for i in 0..=0xFF {
             let b = assert_ok!(branch(
                 0x1234,
@@ -729,6 +848,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_branch_v5_zero_op() {
+        let instructions = [0x0D, 0x0F];
+        for i in 0..=0xFF {
+            let b = assert_ok!(branch(
+                0x1234,
+                &[0xFF, 0xFE, 0xFF],
+                &mock_opcode(5, i as u8, i as u8, OpcodeForm::Short, OperandCount::_0OP),
+                1,
+            ));
+            if instructions.contains(&i) {
+                assert_eq!(b.0, 2);
+                assert_some_eq!(b.1, mock_branch(0x1235, true, 0x1272));
+            } else {
+                assert_eq!(b.0, 1);
+                assert!(b.1.is_none());
+            }
+        }
+    }
+
     #[test]
     fn test_branch_one_op() {
         let instructions = [0x00, 0x01, 0x02];
@@ -953,7 +1092,7 @@ mod tests {
 
     #[test]
     fn test_decode_instruction_zero_op() {
-        let mut map = test_map(3);
+        let mut map = test_map(5);
         // Put instruction above dynamic memory
         // PIRACY ?(label)
         map[0x600] = 0xBF;
@@ -963,7 +1102,7 @@ mod tests {
 
         let instruction = assert_ok!(decode_instruction(&zmachine, 0x600));
         assert_eq!(instruction.address(), 0x600);
-        assert_eq!(instruction.opcode().version(), 3);
+        assert_eq!(instruction.opcode().version(), 5);
         assert_eq!(instruction.opcode().opcode(), 0xBF);
         assert_eq!(instruction.opcode().instruction(), 0xF);
         assert_eq!(instruction.opcode().form(), &OpcodeForm::Short);
@@ -976,6 +1115,101 @@ mod tests {
         assert_eq!(instruction.next_address(), 0x602);
     }
 
+    // Decode the 0OP instruction `opcode` followed by $C1 $02 in a story of `version`,
+    // returning the mnemonic and whether the instruction stores and branches
+    fn decode_zero_op(version: u8, opcode: u8) -> (&'static str, bool, bool) {
+        let mut map = test_map(version);
+        map[0x600] = opcode;
+        map[0x601] = 0xC1;
+        map[0x602] = 0x02;
+        let zmachine = mock_zmachine(map);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x600));
+        (
+            instruction.opcode().name(),
+            instruction.store().is_some(),
+            instruction.branch().is_some(),
+        )
+    }
+
+    #[test]
+    fn test_decode_instruction_zero_op_versions() {
+        assert_eq!(decode_zero_op(3, 0xB4), ("NOP", false, false));
+        assert_eq!(decode_zero_op(5, 0xB4), ("NOP", false, false));
+        assert_eq!(decode_zero_op(3, 0xB9), ("POP", false, false));
+        assert_eq!(decode_zero_op(4, 0xB9), ("POP", false, false));
+        assert_eq!(decode_zero_op(5, 0xB9), ("CATCH", true, false));
+        assert_eq!(decode_zero_op(3, 0xBC), ("SHOW_STATUS", false, false));
+        assert_eq!(decode_zero_op(5, 0xBC), ("SHOW_STATUS", false, false));
+        assert_eq!(decode_zero_op(3, 0xBF), ("UNKNOWN!", false, false));
+        assert_eq!(decode_zero_op(5, 0xBF), ("PIRACY", false, true));
+        assert_eq!(decode_zero_op(3, 0xB5), ("SAVE", false, true));
+        assert_eq!(decode_zero_op(4, 0xB5), ("SAVE", true, false));
+        assert_eq!(decode_zero_op(5, 0xB5), ("UNKNOWN!", false, false));
+    }
+
+    #[test]
+    fn test_decode_instruction_high_memory() {
+        let mut map = test_map(8);
+        map.resize(0x30000, 0);
+        // GET_PARENT_OBJECT -> (result), with the operand crossing 0x20000
+        map[0x1FFFE] = 0x83;
+        map[0x1FFFF] = 0x12;
+        map[0x20000] = 0x34;
+        map[0x20001] = 0x80;
+        // JZ ?(label) with a two byte branch offset back below 0x20000
+        map[0x20002] = 0x80;
+        map[0x20005] = 0xBF;
+        map[0x20006] = 0xF0;
+        let zmachine = mock_zmachine(map);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x1FFFE));
+        assert_eq!(
+            instruction.operands(),
+            &[operand(OperandType::LargeConstant, 0x1234)]
+        );
+        let store = assert_some!(instruction.store());
+        assert_eq!(store.address(), 0x20001);
+        assert_eq!(instruction.next_address(), 0x20002);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x20002));
+        let branch = assert_some!(instruction.branch());
+        assert_eq!(branch.address, 0x20005);
+        assert_eq!(branch.branch_address(), 0x1FFF5);
+        assert_eq!(instruction.next_address(), 0x20007);
+    }
+
+    #[test]
+    fn test_decode_instruction_end_of_memory() {
+        let mut map = test_map(3);
+        // GET_PARENT_OBJECT -> (result), missing its store variable
+        map[0x7FD] = 0x83;
+        map[0x7FE] = 0x12;
+        map[0x7FF] = 0x34;
+        let zmachine = mock_zmachine(map);
+
+        assert!(decode_instruction(&zmachine, 0x7FD).is_err());
+        assert!(decode_instruction(&zmachine, 0x800).is_err());
+        assert!(decode_instruction(&zmachine, 0x10000).is_err());
+    }
+
+    #[test]
+    fn test_decode_memory() {
+        let mut map = test_map(3);
+        // JE #12 #34 [true] RTRUE
+        map[0x400..0x404].copy_from_slice(&[0x01, 0x12, 0x34, 0xC1]);
+        // GET_PARENT_OBJECT -> (result), missing its store variable
+        map[0x7FE] = 0x93;
+        map[0x7FF] = 0x12;
+        let memory = Memory::new(map);
+        let i = assert_ok!(decode_memory(&memory, 3, 0x400));
+        assert_eq!(i.opcode().name(), "JE");
+        assert_eq!(i.next_address(), 0x404);
+        assert_some_eq!(i.branch(), &mock_branch(0x403, true, 1));
+        assert!(decode_memory(&memory, 3, 0x7FE).is_err());
+        assert!(decode_memory(&memory, 3, 0x800).is_err());
+    }
+
     // Store
     #[test]
     fn test_decode_instruction_one_op_large_const() {
@@ -1307,4 +1541,162 @@ mod tests {
         assert_eq!(instruction.store().unwrap().variable(), 0x80);
         assert_eq!(instruction.next_address(), 0x606);
     }
+
+    #[test]
+    fn test_decode_instruction_accessors_two_op() {
+        let mut map = test_map(5);
+        // JE L00 #34 ?(label)
+        map[0x600] = 0x41;
+        map[0x601] = 0x01;
+        map[0x602] = 0x34;
+        // Branch
+        map[0x603] = 0x00;
+        map[0x604] = 0x10;
+
+        let zmachine = mock_zmachine(map);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x600));
+        assert_eq!(instruction.opcode().name(), "JE");
+        assert_eq!(instruction.bytes(), &[0x41, 0x01, 0x34, 0x00, 0x10]);
+        let operands = instruction.operands();
+        assert_eq!(operands.len(), 2);
+        assert_eq!(operands[0].operand_type(), OperandType::Variable);
+        assert_eq!(operands[0].value(), 0x01);
+        assert_eq!(operands[1].operand_type(), OperandType::SmallConstant);
+        assert_eq!(operands[1].value(), 0x34);
+        assert!(instruction.store().is_none());
+        let branch = assert_some!(instruction.branch());
+        assert_eq!(branch.address(), 0x603);
+        assert!(!branch.condition());
+        assert_eq!(branch.branch_address(), 0x613);
+    }
+
+    #[test]
+    fn test_decode_instruction_accessors_var() {
+        let mut map = test_map(5);
+        // CALL_VS #1234 G10 #56 -> (SP)
+        map[0x600] = 0xE0;
+        map[0x601] = 0x27;
+        map[0x602] = 0x12;
+        map[0x603] = 0x34;
+        map[0x604] = 0x20;
+        map[0x605] = 0x56;
+        map[0x606] = 0x00;
+
+        let zmachine = mock_zmachine(map);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x600));
+        assert_eq!(instruction.opcode().name(), "CALL_VS");
+        assert_eq!(
+            instruction.bytes(),
+            &[0xE0, 0x27, 0x12, 0x34, 0x20, 0x56, 0x00]
+        );
+        let operands = instruction.operands();
+        assert_eq!(operands.len(), 3);
+        assert_eq!(operands[0].operand_type(), OperandType::LargeConstant);
+        assert_eq!(operands[0].value(), 0x1234);
+        assert_eq!(operands[1].operand_type(), OperandType::Variable);
+        assert_eq!(operands[1].value(), 0x20);
+        assert_eq!(operands[2].operand_type(), OperandType::SmallConstant);
+        assert_eq!(operands[2].value(), 0x56);
+        assert!(instruction.branch().is_none());
+        let store = assert_some!(instruction.store());
+        assert_eq!(store.address(), 0x606);
+        assert_eq!(store.variable(), 0x00);
+        assert_eq!(instruction.next_address(), 0x607);
+    }
+
+    #[test]
+    fn test_decode_instruction_accessors_ext() {
+        let mut map = test_map(5);
+        // SAVE_UNDO -> G00
+        map[0x600] = 0xBE;
+        map[0x601] = 0x09;
+        map[0x602] = 0xFF;
+        map[0x603] = 0x10;
+
+        let zmachine = mock_zmachine(map);
+
+        let instruction = assert_ok!(decode_instruction(&zmachine, 0x600));
+        assert_eq!(instruction.opcode().name(), "SAVE_UNDO");
+        assert_eq!(instruction.bytes(), &[0xBE, 0x09, 0xFF, 0x10]);
+        assert!(instruction.operands().is_empty());
+        assert!(instruction.branch().is_none());
+        assert_some_eq!(instruction.store(), &StoreResult::new(0x603, 0x10));
+        assert_eq!(instruction.next_address(), 0x604);
+    }
+
+    #[test]
+    fn test_opcode_name() {
+        assert_eq!(
+            mock_opcode(3, 0xB9, 0x9, OpcodeForm::Short, OperandCount::_0OP).name(),
+            "POP"
+        );
+        assert_eq!(
+            mock_opcode(5, 0xB9, 0x9, OpcodeForm::Short, OperandCount::_0OP).name(),
+            "CATCH"
+        );
+        assert_eq!(
+            mock_opcode(4, 0x8F, 0xF, OpcodeForm::Short, OperandCount::_1OP).name(),
+            "NOT"
+        );
+        assert_eq!(
+            mock_opcode(5, 0x8F, 0xF, OpcodeForm::Short, OperandCount::_1OP).name(),
+            "CALL_1N"
+        );
+        assert_eq!(
+            mock_opcode(5, 0x1E, 0x1E, OpcodeForm::Ext, OperandCount::_VAR).name(),
+            "UNKNOWN!"
+        );
+        assert_eq!(
+            mock_opcode(5, 0xE0, 0x0, OpcodeForm::Var, OperandCount::_VAR).to_string(),
+            "CALL_VS"
+        );
+    }
+
+    #[test]
+    fn test_decode_instruction_truncated() {
+        let mut map = test_map(5);
+        let end = map.len();
+        // VAR form with 2 large constants and a store, but only 3 bytes left
+        map[end - 3] = 0xE0;
+        map[end - 2] = 0x0F;
+        map[end - 1] = 0x12;
+        let zmachine = mock_zmachine(map);
+        let e = decode_instruction(&zmachine, end - 3).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::InvalidInstruction);
+
+        // Extended opcode prefix as the last byte
+        let mut map = test_map(5);
+        map[end - 1] = 0xBE;
+        let zmachine = mock_zmachine(map);
+        let e = decode_instruction(&zmachine, end - 1).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::InvalidInstruction);
+
+        let zmachine = mock_zmachine(test_map(5));
+        let e = decode_instruction(&zmachine, end).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::InvalidAddress);
+    }
+
+    #[test]
+    fn test_decode_instruction_random_images() {
+        // Random story images must decode or fail with an error, never panic.  Seeded so
+        // a failure is reproducible.
+        let mut rng = ChaCha8Rng::seed_from_u64(0x4d78);
+        for _ in 0..random_iterations(256) {
+            let mut map = vec![0; rng.gen_range(0x40..0x400)];
+            rng.fill(&mut map[..]);
+            map[0] = rng.gen_range(3..=8);
+            // A set transcript bit would open a transcript file in the working directory
+            map[0x11] &= !1;
+            let length = map.len();
+            if let Ok(zmachine) = ZMachine::new(Memory::new(map), Config::default(), None, "test") {
+                // The header length may have padded memory past the end of the image
+                let size = zmachine.memory_size();
+                for address in (0..length).chain(size - 24..size + 2) {
+                    let _ = decode_instruction(&zmachine, address);
+                }
+            }
+        }
+    }
 }
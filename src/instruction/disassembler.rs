@@ -0,0 +1,570 @@
+//! Disassemble Z-code into listings, with labels for the routines a listing calls and the
+//! instructions its branches and jumps continue at.
+//!
+//! A routine is decoded from its header to the first instruction that doesn't continue to
+//! the next (a return, JUMP, QUIT, ...) once every forward branch inside the routine has
+//! been passed, and that is followed by what looks like another routine.  Routines are
+//! expected to follow each other, aligned as packed addresses are.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+    text::{self, AbbrevTable},
+    zmachine::state::{header::HeaderField, memory::Memory},
+};
+
+use super::{decoder, Instruction, OperandType};
+
+/// An instruction in a listing
+#[derive(Debug)]
+pub struct Line {
+    instruction: Instruction,
+    // Text printed by PRINT and PRINT_RET, which follows the instruction
+    text: Option<String>,
+    next_address: usize,
+    jump: Option<usize>,
+    call: Option<usize>,
+}
+
+impl Line {
+    pub fn instruction(&self) -> &Instruction {
+        &self.instruction
+    }
+
+    /// The text printed by PRINT or PRINT_RET
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// The address of the following instruction, after any text
+    pub fn next_address(&self) -> usize {
+        self.next_address
+    }
+
+    /// The address a JUMP, or a branch that doesn't return, continues at
+    pub fn jump(&self) -> Option<usize> {
+        self.jump
+    }
+
+    /// The address of the routine a call with a constant routine address calls
+    pub fn call(&self) -> Option<usize> {
+        self.call
+    }
+
+    // Whether execution never continues to the next instruction
+    fn ends_flow(&self) -> bool {
+        matches!(
+            self.instruction.opcode().name(),
+            "RTRUE"
+                | "RFALSE"
+                | "PRINT_RET"
+                | "RET"
+                | "RET_POPPED"
+                | "JUMP"
+                | "QUIT"
+                | "RESTART"
+                | "THROW"
+        )
+    }
+}
+
+/// A routine: the initial values of its locals and its instructions
+#[derive(Debug)]
+pub struct Routine {
+    address: usize,
+    locals: Vec<u16>,
+    lines: Vec<Line>,
+}
+
+impl Routine {
+    /// The address of the routine header
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Initial values of the locals, which are all 0 in V5+
+    pub fn locals(&self) -> &[u16] {
+        &self.locals
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// The address following the routine's last instruction
+    pub fn end(&self) -> usize {
+        self.lines
+            .last()
+            .map_or(self.address + 1, |l| l.next_address)
+    }
+}
+
+/// Routines disassembled from a story, in address order.  Its `Display` is the formatted
+/// listing.
+#[derive(Debug)]
+pub struct Listing {
+    version: u8,
+    routines: Vec<Routine>,
+    end: usize,
+    stopped: Option<String>,
+    labels: BTreeMap<usize, String>,
+}
+
+impl Listing {
+    fn new(version: u8, routines: Vec<Routine>, end: usize, stopped: Option<String>) -> Listing {
+        // Routines are R0001, R0002, ... and the instructions jumped to are L0001, L0002, ...
+        // in address order
+        let mut labels = BTreeMap::new();
+        for (i, routine) in routines.iter().enumerate() {
+            labels.insert(routine.address, format!("R{:04}", i + 1));
+        }
+        let lines: BTreeSet<usize> = routines
+            .iter()
+            .flat_map(|r| r.lines.iter().map(|l| l.instruction.address()))
+            .collect();
+        let targets: BTreeSet<usize> = routines
+            .iter()
+            .flat_map(|r| r.lines.iter().filter_map(|l| l.jump))
+            .filter(|a| lines.contains(a))
+            .collect();
+        for (i, address) in targets.iter().enumerate() {
+            labels.insert(*address, format!("L{:04}", i + 1));
+        }
+
+        Listing {
+            version,
+            routines,
+            end,
+            stopped,
+            labels,
+        }
+    }
+
+    pub fn routines(&self) -> &[Routine] {
+        &self.routines
+    }
+
+    /// The address after the last routine, where disassembly stopped
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Why disassembly stopped before the end it was given, when it did
+    pub fn stopped(&self) -> Option<&str> {
+        self.stopped.as_deref()
+    }
+
+    /// The label for a routine or instruction in the listing
+    pub fn label(&self, address: usize) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    // A label when the address has one, otherwise the address
+    fn target(&self, address: usize) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("${:05x}", address),
+        }
+    }
+
+    fn fmt_line(&self, f: &mut fmt::Formatter, line: &Line) -> fmt::Result {
+        let instruction = &line.instruction;
+        let label = match self.label(instruction.address()) {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "{:<8}${:05x}: {}",
+            label,
+            instruction.address(),
+            instruction.opcode()
+        )?;
+        for (i, operand) in instruction.operands().iter().enumerate() {
+            match (i, line.call, line.jump) {
+                (0, Some(address), _) => write!(f, " {}", self.target(address))?,
+                (0, _, Some(address)) if instruction.branch().is_none() => {
+                    write!(f, " {}", self.target(address))?
+                }
+                _ => write!(f, " {}", operand)?,
+            }
+        }
+        if let Some(store) = instruction.store() {
+            write!(f, " -> {}", store)?;
+        }
+        if let Some(branch) = instruction.branch() {
+            write!(f, " [{}] ", branch.condition())?;
+            match branch.branch_address() {
+                address if branch.is_jump() => write!(f, "{}", self.target(address))?,
+                0 => write!(f, "RFALSE")?,
+                _ => write!(f, "RTRUE")?,
+            }
+        }
+        if let Some(text) = &line.text {
+            write!(f, " \"{}\"", text.replace('\n', "\\n"))?;
+        }
+        writeln!(f)
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, routine) in self.routines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:<8}${:05x}: routine, {} local{}",
+                format!("{}:", self.target(routine.address)),
+                routine.address,
+                routine.locals.len(),
+                if routine.locals.len() == 1 { "" } else { "s" }
+            )?;
+            // Only V1-4 routines set initial values
+            if self.version < 5 && !routine.locals.is_empty() {
+                let values: Vec<String> = routine
+                    .locals
+                    .iter()
+                    .map(|v| format!("#{:04x}", v))
+                    .collect();
+                write!(f, " ({})", values.join(" "))?;
+            }
+            writeln!(f)?;
+            for line in &routine.lines {
+                self.fmt_line(f, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// What's needed to decode a story's code
+struct Story<'a> {
+    memory: &'a Memory,
+    version: u8,
+    abbreviations: AbbrevTable,
+    alphabet: Option<[u8; 78]>,
+}
+
+impl<'a> Story<'a> {
+    fn new(memory: &'a Memory) -> Result<Story<'a>, RuntimeError> {
+        let version = memory.read_byte(HeaderField::Version as usize)?;
+        let alphabet = match memory.read_word(HeaderField::AlphabetTable as usize)? as usize {
+            0 => None,
+            address if version >= 5 => memory.slice(address, 78).try_into().ok(),
+            _ => None,
+        };
+        Ok(Story {
+            memory,
+            version,
+            abbreviations: text::abbreviations(memory)?,
+            alphabet,
+        })
+    }
+
+    // Routines start at a multiple of this
+    fn alignment(&self) -> usize {
+        match self.version {
+            1..=3 => 2,
+            4..=7 => 4,
+            _ => 8,
+        }
+    }
+
+    fn routine_address(&self, packed: u16) -> Result<usize, RuntimeError> {
+        let packed = packed as usize;
+        Ok(match self.version {
+            1..=3 => packed * 2,
+            4 | 5 => packed * 4,
+            6 | 7 => {
+                packed * 4
+                    + self
+                        .memory
+                        .read_word(HeaderField::RoutinesOffset as usize)?
+                        as usize
+                        * 8
+            }
+            _ => packed * 8,
+        })
+    }
+
+    fn line(&self, address: usize) -> Result<Line, RuntimeError> {
+        let instruction = decoder::decode_memory(self.memory, self.version, address)?;
+        let name = instruction.opcode().name();
+        // Without knowing whether an opcode stores or branches, nothing after it can be
+        // decoded
+        if name == "UNKNOWN!" {
+            return recoverable_error!(
+                ErrorCode::InvalidInstruction,
+                "Unknown opcode at ${:05x}",
+                address
+            );
+        }
+
+        let mut next_address = instruction.next_address();
+        let text = if name == "PRINT" || name == "PRINT_RET" {
+            let words = text::memory_string(self.memory, next_address)?;
+            let data = self.memory.slice(next_address, words.len() * 2);
+            next_address += data.len();
+            Some(text::decode(
+                &data,
+                &self.abbreviations,
+                self.version,
+                self.alphabet.as_ref(),
+            )?)
+        } else {
+            None
+        };
+
+        // Only constant operands can be followed
+        let constant = instruction
+            .operands()
+            .first()
+            .filter(|o| o.operand_type() != OperandType::Variable)
+            .map(|o| o.value());
+        let (jump, call) = match (name, constant) {
+            ("JUMP", Some(offset)) => (
+                Some((next_address as isize + (offset as i16) as isize - 2) as usize),
+                None,
+            ),
+            (name, Some(packed)) if name.starts_with("CALL") && packed > 0 => {
+                (None, Some(self.routine_address(packed)?))
+            }
+            _ => (
+                instruction
+                    .branch()
+                    .filter(|b| b.is_jump())
+                    .map(|b| b.branch_address()),
+                None,
+            ),
+        };
+
+        Ok(Line {
+            instruction,
+            text,
+            next_address,
+            jump,
+            call,
+        })
+    }
+
+    // Whether the code ending at `address` can be followed by another routine, or by
+    // whatever is at `end`: padding up to the next aligned address, then a plausible number
+    // of locals.  Compilers sometimes leave code nothing reaches after a return, which
+    // doesn't look like that.
+    fn routine_follows(&self, address: usize, end: usize) -> bool {
+        let next = address.next_multiple_of(self.alignment());
+        next >= end.min(self.memory.size())
+            || (self
+                .memory
+                .slice(address, next - address)
+                .iter()
+                .all(|b| *b == 0)
+                && self.memory.read_byte(next).is_ok_and(|b| b <= 15))
+    }
+
+    fn routine(&self, address: usize, end: usize) -> Result<Routine, RuntimeError> {
+        let count = self.memory.read_byte(address)? as usize;
+        if count > 15 {
+            return recoverable_error!(
+                ErrorCode::InvalidRoutine,
+                "No routine at ${:05x}: {} locals",
+                address,
+                count
+            );
+        }
+
+        let mut locals = vec![0; count];
+        let mut pc = address + 1;
+        if self.version < 5 {
+            for local in locals.iter_mut() {
+                *local = self.memory.read_word(pc)?;
+                pc += 2;
+            }
+        }
+
+        let mut lines = Vec::new();
+        // The furthest address a branch inside the routine continues at
+        let mut furthest = pc;
+        // The number of lines when the routine last could have ended, in case what follows
+        // turns out not to be code after all
+        let mut could_end = None;
+        loop {
+            let line = match self.line(pc) {
+                Ok(line) => line,
+                Err(e) => match could_end {
+                    Some(count) => {
+                        lines.truncate(count);
+                        break;
+                    }
+                    None => return Err(e),
+                },
+            };
+            pc = line.next_address;
+            if let Some(address) = line.jump {
+                furthest = furthest.max(address);
+            }
+            let ends = line.ends_flow() && pc > furthest;
+            lines.push(line);
+            if ends {
+                if self.routine_follows(pc, end) {
+                    break;
+                }
+                could_end = Some(lines.len());
+            }
+        }
+
+        Ok(Routine {
+            address,
+            locals,
+            lines,
+        })
+    }
+}
+
+/// Disassemble the routines from the one at `start` up to `end`.  Each routine is taken to
+/// follow the previous one at the next aligned address, and disassembly stops early at
+/// the first address that doesn't hold a routine.
+pub fn disassemble(memory: &Memory, start: usize, end: usize) -> Result<Listing, RuntimeError> {
+    let story = Story::new(memory)?;
+    let mut routines = vec![story.routine(start, end)?];
+    loop {
+        let address = routines[routines.len() - 1]
+            .end()
+            .next_multiple_of(story.alignment());
+        if address >= end {
+            return Ok(Listing::new(story.version, routines, address, None));
+        }
+        match story.routine(address, end) {
+            Ok(routine) => routines.push(routine),
+            Err(e) => {
+                let stopped = Some(e.message().to_string());
+                return Ok(Listing::new(story.version, routines, address, stopped));
+            }
+        }
+    }
+}
+
+/// Disassemble a story's code, from the start of high memory to the strings that follow
+/// the code, as far as [`text::strings`] finds them
+pub fn disassemble_story(memory: &Memory) -> Result<Listing, RuntimeError> {
+    let story = Story::new(memory)?;
+    let start = (memory.read_word(HeaderField::HighMark as usize)? as usize)
+        .next_multiple_of(story.alignment());
+    let end = text::strings(memory)?
+        .iter()
+        .map(|(address, _)| *address)
+        .find(|address| *address > start)
+        .unwrap_or(memory.size());
+    disassemble(memory, start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{assert_ok, assert_some, test_util::test_map};
+
+    use super::*;
+
+    // Two routines: the first calls the second, which branches and jumps over a PRINT
+    fn routines_map() -> Vec<u8> {
+        let mut map = test_map(5);
+        map[0x400..0x414].copy_from_slice(&[
+            // No locals, CALL_VS $0408 -> (SP), QUIT, padding
+            0x00, 0xE0, 0x3F, 0x01, 0x02, 0x00, 0xBA, 0x00,
+            // One local, JE L00 #05 [false] $0410, JUMP $0413
+            0x01, 0x41, 0x01, 0x05, 0x45, 0x8C, 0x00, 0x05, // PRINT "hi", RTRUE
+            0xB2, 0xB5, 0xC5, 0xB0,
+        ]);
+        map
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let memory = Memory::new(routines_map());
+        let listing = assert_ok!(disassemble(&memory, 0x400, 0x414));
+        assert_eq!(listing.routines().len(), 2);
+        assert_eq!(listing.end(), 0x414);
+        assert!(listing.stopped().is_none());
+        let routine = &listing.routines()[1];
+        assert_eq!(routine.address(), 0x408);
+        assert_eq!(routine.locals(), &[0]);
+        assert_eq!(routine.end(), 0x414);
+        assert_eq!(routine.lines()[0].jump(), Some(0x410));
+        assert_eq!(routine.lines()[1].jump(), Some(0x413));
+        assert_eq!(routine.lines()[2].text(), Some("hi"));
+        assert_eq!(routine.lines()[2].next_address(), 0x413);
+        assert_eq!(listing.routines()[0].lines()[0].call(), Some(0x408));
+        assert_eq!(listing.label(0x408), Some("R0002"));
+        assert_eq!(listing.label(0x410), Some("L0001"));
+        assert_eq!(
+            listing.to_string(),
+            [
+                "R0001:  $00400: routine, 0 locals",
+                "        $00401: CALL_VS R0002 -> -(SP)",
+                "        $00406: QUIT",
+                "",
+                "R0002:  $00408: routine, 1 local",
+                "        $00409: JE L00 #05 [false] L0001",
+                "        $0040d: JUMP L0002",
+                "L0001:  $00410: PRINT \"hi\"",
+                "L0002:  $00413: RTRUE",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_disassemble_stops() {
+        let mut map = routines_map();
+        // The second routine has too many locals
+        map[0x408] = 0x10;
+        let memory = Memory::new(map);
+        let listing = assert_ok!(disassemble(&memory, 0x400, 0x800));
+        assert_eq!(listing.routines().len(), 1);
+        assert_eq!(listing.end(), 0x408);
+        assert_eq!(listing.stopped(), Some("No routine at $00408: 16 locals"));
+        // The called routine isn't in the listing
+        assert!(listing.to_string().contains("CALL_VS $00408 -> -(SP)"));
+        assert!(disassemble(&memory, 0x408, 0x800).is_err());
+    }
+
+    #[test]
+    fn test_disassemble_v3_locals() {
+        let mut map = test_map(3);
+        // Two locals, initially 1 and $1234, RET_POPPED
+        map[0x400..0x406].copy_from_slice(&[0x02, 0x00, 0x01, 0x12, 0x34, 0xB8]);
+        let memory = Memory::new(map);
+        let listing = assert_ok!(disassemble(&memory, 0x400, 0x406));
+        assert_eq!(listing.routines()[0].locals(), &[1, 0x1234]);
+        assert_eq!(
+            listing.to_string(),
+            "R0001:  $00400: routine, 2 locals (#0001 #1234)\n        $00405: RET_POPPED\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_story() {
+        let zcode = assert_ok!(fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("zcode")
+                .join("czech.z5")
+        ));
+        let memory = Memory::new(zcode);
+        let listing = assert_ok!(disassemble_story(&memory));
+        let routines = listing.routines();
+        assert_eq!(routines[0].address(), 0x92c);
+        // The first routine calls Main, which is in the listing, and quits
+        let main = assert_some!(routines[0].lines()[0].call());
+        assert!(assert_some!(listing.label(main)).starts_with('R'));
+        assert_eq!(routines[0].lines()[1].instruction().opcode().name(), "QUIT");
+        // The code runs up to a few bytes of padding before the strings at $02744
+        assert_eq!(routines.len(), 63);
+        assert_eq!(listing.end(), 0x2730);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+};
+
+use super::{decoder, Instruction, Opcode, OpcodeForm};
+
+/// Restricts the instruction log to instructions with one of `opcodes`, at an address in
+/// one of `ranges`.  An empty list doesn't restrict anything, so the default filter logs
+/// every instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstructionLogFilter {
+    // Log instructions when execution starts, otherwise wait for the log to be turned on
+    enabled: bool,
+    // Upper-case opcode names, as shown in the instruction log
+    opcodes: Vec<String>,
+    // Inclusive address ranges
+    ranges: Vec<(usize, usize)>,
+}
+
+impl Default for InstructionLogFilter {
+    fn default() -> Self {
+        InstructionLogFilter {
+            enabled: true,
+            opcodes: Vec::new(),
+            ranges: Vec::new(),
+        }
+    }
+}
+
+// Every opcode for a version: the short, long, and variable forms keyed by their first
+// byte, then the extended forms keyed by the byte after $BE
+fn opcodes(version: u8) -> impl Iterator<Item = Opcode> {
+    let first = (0..=255u8)
+        .filter(|b| *b != 0xBE)
+        .map(move |b| decoder::opcode(&[b], version, 0));
+    let ext = (0..=255u8).map(move |b| decoder::opcode(&[0xBE, b], version, 0));
+    first
+        .chain(ext)
+        .filter_map(|r| r.ok().map(|(_, opcode)| opcode))
+}
+
+impl InstructionLogFilter {
+    pub fn new(
+        enabled: bool,
+        opcodes: &[&str],
+        ranges: &[(usize, usize)],
+    ) -> Result<InstructionLogFilter, RuntimeError> {
+        let mut names = Vec::new();
+        for name in opcodes {
+            let name = name.to_ascii_uppercase();
+            if !(3..=8).any(|v| opcodes_named(v, &name).next().is_some()) {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "Unknown opcode in instruction_log_filter: '{}'",
+                    name
+                );
+            }
+            names.push(name);
+        }
+
+        for (start, end) in ranges {
+            if start > end {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "Invalid address range in instruction_log_filter: ${:06x}-${:06x}",
+                    start,
+                    end
+                );
+            }
+        }
+
+        Ok(InstructionLogFilter {
+            enabled,
+            opcodes: names,
+            ranges: ranges.to_vec(),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn opcodes(&self) -> &[String] {
+        &self.opcodes
+    }
+
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+}
+
+fn opcodes_named(version: u8, name: &str) -> impl Iterator<Item = Opcode> + '_ {
+    opcodes(version).filter(move |o| o.name() == name)
+}
+
+// Bit set indexed by opcode byte
+#[derive(Clone, Copy, Debug, Default)]
+struct OpcodeMask([u64; 4]);
+
+impl OpcodeMask {
+    fn set(&mut self, byte: u8) {
+        self.0[byte as usize / 64] |= 1 << (byte % 64);
+    }
+
+    fn contains(&self, byte: u8) -> bool {
+        self.0[byte as usize / 64] & (1 << (byte % 64)) != 0
+    }
+}
+
+/// An [`InstructionLogFilter`] compiled for one story version, so checking an instruction
+/// is a bit test and a binary search.
+#[derive(Debug)]
+pub struct InstructionLog {
+    enabled: bool,
+    // None when any opcode is logged
+    opcodes: Option<(OpcodeMask, OpcodeMask)>,
+    // Sorted and merged, empty when any address is logged
+    ranges: Vec<(usize, usize)>,
+}
+
+impl InstructionLog {
+    pub fn new(filter: &InstructionLogFilter, version: u8) -> InstructionLog {
+        let opcodes = if filter.opcodes.is_empty() {
+            None
+        } else {
+            let mut first = OpcodeMask::default();
+            let mut ext = OpcodeMask::default();
+            for name in &filter.opcodes {
+                for opcode in opcodes_named(version, name) {
+                    match opcode.form() {
+                        OpcodeForm::Ext => ext.set(opcode.opcode()),
+                        _ => first.set(opcode.opcode()),
+                    }
+                }
+            }
+            Some((first, ext))
+        };
+
+        let mut sorted = filter.ranges.clone();
+        sorted.sort_unstable();
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in sorted {
+            match ranges.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        InstructionLog {
+            enabled: filter.enabled,
+            opcodes,
+            ranges,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Should `instruction` be written to the instruction log?
+    pub fn admits(&self, instruction: &Instruction) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        if let Some((first, ext)) = &self.opcodes {
+            let opcode = instruction.opcode();
+            let mask = match opcode.form() {
+                OpcodeForm::Ext => ext,
+                _ => first,
+            };
+            if !mask.contains(opcode.opcode()) {
+                return false;
+            }
+        }
+
+        if !self.ranges.is_empty() {
+            let address = instruction.address();
+            // The last range starting at or before the address is the only one it can be in
+            let i = self.ranges.partition_point(|(start, _)| *start <= address);
+            return i > 0 && address <= self.ranges[i - 1].1;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assert_ok, assert_ok_eq};
+
+    use super::*;
+
+    fn instruction(version: u8, bytes: &[u8], address: usize) -> Instruction {
+        let (_, opcode) = assert_ok!(decoder::opcode(bytes, version, 0));
+        Instruction::new(bytes, address, opcode, Vec::new(), None, None, address + 1)
+    }
+
+    #[test]
+    fn test_new() {
+        let filter = assert_ok!(InstructionLogFilter::new(
+            false,
+            &["call_vs", "Print"],
+            &[(0x4a00, 0x4c00)]
+        ));
+        assert!(!filter.enabled());
+        assert_eq!(filter.opcodes(), &["CALL_VS", "PRINT"]);
+        assert_eq!(filter.ranges(), &[(0x4a00, 0x4c00)]);
+    }
+
+    #[test]
+    fn test_new_unknown_opcode() {
+        let e = InstructionLogFilter::new(true, &["call_vs", "jump_around"], &[]).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ConfigError);
+    }
+
+    #[test]
+    fn test_new_invalid_range() {
+        let e = InstructionLogFilter::new(true, &[], &[(0x4c00, 0x4a00)]).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ConfigError);
+    }
+
+    #[test]
+    fn test_admits_default() {
+        let log = InstructionLog::new(&InstructionLogFilter::default(), 5);
+        assert!(log.enabled());
+        assert!(log.admits(&instruction(5, &[0xB0], 0x400)));
+        assert!(log.admits(&instruction(5, &[0xBE, 0x09], 0x12345)));
+    }
+
+    #[test]
+    fn test_admits_disabled() {
+        let filter = assert_ok!(InstructionLogFilter::new(false, &[], &[]));
+        let mut log = InstructionLog::new(&filter, 5);
+        assert!(!log.admits(&instruction(5, &[0xB0], 0x400)));
+        log.set_enabled(true);
+        assert!(log.admits(&instruction(5, &[0xB0], 0x400)));
+    }
+
+    #[test]
+    fn test_admits_opcodes() {
+        let filter = assert_ok!(InstructionLogFilter::new(
+            true,
+            &["je", "call_vs", "save_undo"],
+            &[]
+        ));
+        let log = InstructionLog::new(&filter, 5);
+        // Every form of JE
+        for byte in [0x01, 0x21, 0x41, 0x61, 0xC1] {
+            assert!(log.admits(&instruction(5, &[byte], 0x400)), "{:02x}", byte);
+        }
+        assert!(log.admits(&instruction(5, &[0xE0], 0x400)));
+        assert!(log.admits(&instruction(5, &[0xBE, 0x09], 0x400)));
+        // JL, RTRUE, RESTORE_UNDO, and a SAVE_UNDO opcode number as a 2OP
+        for bytes in [&[0x02][..], &[0xB0], &[0xBE, 0x0A], &[0x09]] {
+            assert!(!log.admits(&instruction(5, bytes, 0x400)), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_admits_opcodes_by_version() {
+        // POP in V4 is CATCH in V5
+        let filter = assert_ok!(InstructionLogFilter::new(true, &["pop"], &[]));
+        assert!(InstructionLog::new(&filter, 4).admits(&instruction(4, &[0xB9], 0x400)));
+        assert!(!InstructionLog::new(&filter, 5).admits(&instruction(5, &[0xB9], 0x400)));
+    }
+
+    #[test]
+    fn test_admits_ranges() {
+        let filter = assert_ok!(InstructionLogFilter::new(
+            true,
+            &[],
+            &[(0x5000, 0x5100), (0x4a00, 0x4c00), (0x4b00, 0x4d00)]
+        ));
+        let log = InstructionLog::new(&filter, 5);
+        assert_eq!(log.ranges, &[(0x4a00, 0x4d00), (0x5000, 0x5100)]);
+        for address in [0x4a00, 0x4c80, 0x4d00, 0x5000, 0x5100] {
+            assert!(
+                log.admits(&instruction(5, &[0xB0], address)),
+                "{:x}",
+                address
+            );
+        }
+        for address in [0x49ff, 0x4d01, 0x4fff, 0x5101] {
+            assert!(
+                !log.admits(&instruction(5, &[0xB0], address)),
+                "{:x}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_admits_opcodes_and_ranges() {
+        let filter = assert_ok!(InstructionLogFilter::new(
+            true,
+            &["rtrue"],
+            &[(0x4a00, 0x4c00)]
+        ));
+        let log = InstructionLog::new(&filter, 5);
+        assert!(log.admits(&instruction(5, &[0xB0], 0x4b00)));
+        assert!(!log.admits(&instruction(5, &[0xB1], 0x4b00)));
+        assert!(!log.admits(&instruction(5, &[0xB0], 0x4c01)));
+    }
+
+    #[test]
+    fn test_opcodes() {
+        // Every V5 opcode byte decodes, plus each EXT opcode
+        assert_eq!(opcodes(5).count(), 511);
+        assert_ok_eq!(
+            decoder::opcode(&[0xBE, 0x09], 5, 0).map(|(_, o)| o.name()),
+            "SAVE_UNDO"
+        );
+    }
+}
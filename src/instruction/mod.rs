@@ -1,7 +1,10 @@
 use std::fmt;
 
 pub mod decoder;
+pub mod disassembler;
+pub mod log_filter;
 pub mod processor;
+pub mod trace;
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum OpcodeForm {
@@ -18,6 +21,11 @@ pub enum OperandType {
     Variable,
 }
 
+/// A decoded operand.
+///
+/// `value` is the raw operand value: the constant for small and large constants, or
+/// the variable number (0 = stack, 1-15 = locals, 16-255 = globals) for variables.  The
+/// processor resolves variable operands when the instruction executes.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Operand {
     operand_type: OperandType,
@@ -50,15 +58,17 @@ impl Operand {
         }
     }
 
-    fn operand_type(&self) -> OperandType {
+    pub fn operand_type(&self) -> OperandType {
         self.operand_type
     }
 
-    fn value(&self) -> u16 {
+    pub fn value(&self) -> u16 {
         self.value
     }
 }
 
+/// Store target of an instruction: `address` is the location of the store byte in
+/// the instruction, `variable` is the variable number the result is stored to.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct StoreResult {
     address: usize,
@@ -91,44 +101,68 @@ impl StoreResult {
     }
 }
 
+/// Branch data of an instruction: `address` is the location of the branch byte(s) in
+/// the instruction, `condition` is the value the test must have for the branch to be taken.
+/// `branch_address` is the absolute destination, or 0/1 for a branch that returns
+/// false/true from the current routine.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Branch {
     address: usize,
     condition: bool,
     branch_address: usize,
+    // Set when branch_address is an address to continue at, rather than 0 or 1 to return
+    // false or true
+    jump: bool,
 }
 
 impl fmt::Display for Branch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "[{}] ", self.condition)?;
         match self.branch_address {
+            _ if self.jump => write!(f, "${:05x}", self.branch_address),
             0 => write!(f, "RFALSE"),
-            1 => write!(f, "RTRUE"),
-            _ => write!(f, "${:05x}", self.branch_address),
+            _ => write!(f, "RTRUE"),
         }
     }
 }
 
 impl Branch {
+    /// A branch to `branch_address`, or a return when it's 0 (false) or 1 (true)
     pub fn new(address: usize, condition: bool, branch_address: usize) -> Branch {
         Branch {
             address,
             condition,
             branch_address,
+            jump: branch_address > 1,
         }
     }
 
-    fn address(&self) -> usize {
+    /// A branch to `branch_address`, whatever it is
+    pub fn jump_to(address: usize, condition: bool, branch_address: usize) -> Branch {
+        Branch {
+            address,
+            condition,
+            branch_address,
+            jump: true,
+        }
+    }
+
+    pub fn address(&self) -> usize {
         self.address
     }
 
-    fn condition(&self) -> bool {
+    pub fn condition(&self) -> bool {
         self.condition
     }
 
-    fn branch_address(&self) -> usize {
+    pub fn branch_address(&self) -> usize {
         self.branch_address
     }
+
+    /// True when the branch continues at `branch_address` rather than returning
+    pub fn is_jump(&self) -> bool {
+        self.jump
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -150,172 +184,7 @@ pub struct Opcode {
 
 impl fmt::Display for Opcode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self.form {
-                OpcodeForm::Ext => match self.instruction() {
-                    0x00 => "SAVE",
-                    0x01 => "RESTORE",
-                    0x02 => "LOG_SHIFT",
-                    0x03 => "ART_SHIFT",
-                    0x04 => "SET_FONT",
-                    0x05 => "DRAW_PICTURE",
-                    0x06 => "PICTURE_DATA",
-                    0x07 => "ERASE_PICTURE",
-                    0x08 => "SET_MARGINS",
-                    0x09 => "SAVE_UNDO",
-                    0x0A => "RESTORE_UNDO",
-                    0x0B => "PRINT_UNICODE",
-                    0x0C => "CHECK_UNICODE",
-                    0x0D => "SET_TRUE_COLOUR",
-                    0x10 => "MOVE_WINDOW",
-                    0x11 => "WINDOW_SIZE",
-                    0x12 => "WINDOW_STYLE",
-                    0x13 => "GET_WIND_PROP",
-                    0x14 => "SCROLL_WINDOW",
-                    0x15 => "POP_STACK",
-                    0x16 => "READ_MOUSE",
-                    0x17 => "MOUSE_WINDOW",
-                    0x18 => "PUSH_STACK",
-                    0x19 => "PUT_WIND_PROP",
-                    0x1A => "PRINT_FORM",
-                    0x1B => "MAKE_MENU",
-                    0x1C => "PICTURE_TABLE",
-                    0x1D => "BUFFER_SCREEN",
-                    _ => "UNKNOWN!",
-                },
-                _ => match self.operand_count() {
-                    OperandCount::_0OP => match self.instruction() {
-                        0x0 => "RTRUE",
-                        0x1 => "RFALSE",
-                        0x2 => "PRINT",
-                        0x3 => "PRINT_RET",
-                        0x4 => "NOP",
-                        0x5 => "SAVE",
-                        0x6 => "RESTORE",
-                        0x7 => "RESTART",
-                        0x8 => "RET_POPPED",
-                        0x9 => {
-                            if self.version() < 5 {
-                                "POP"
-                            } else {
-                                "CATCH"
-                            }
-                        }
-                        0xA => "QUIT",
-                        0xB => "NEW_LINE",
-                        0xC => "SHOW_STATUS",
-                        0xD => "VERIFY",
-                        0xF => "PIRACY",
-                        _ => "UNKNOWN!",
-                    },
-                    OperandCount::_1OP => match self.instruction() {
-                        0x0 => "JZ",
-                        0x1 => "GET_SIBLING",
-                        0x2 => "GET_CHILD",
-                        0x3 => "GET_PARENT",
-                        0x4 => "GET_PROP_LEN",
-                        0x5 => "INC",
-                        0x6 => "DEC",
-                        0x7 => "PRINT_ADDR",
-                        0x8 => "CALL_1S",
-                        0x9 => "REMOVE_OBJ",
-                        0xA => "PRINT_OBJ",
-                        0xB => "RET",
-                        0xC => "JUMP",
-                        0xD => "PRINT_PADDR",
-                        0xE => "LOAD",
-                        0xF => {
-                            if self.version < 5 {
-                                "NOT"
-                            } else {
-                                "CALL_1N"
-                            }
-                        }
-                        _ => "UNKNOWN!",
-                    },
-                    OperandCount::_2OP => match self.instruction() {
-                        0x01 => "JE",
-                        0x02 => "JL",
-                        0x03 => "JG",
-                        0x04 => "DEC_CHK",
-                        0x05 => "INC_CHK",
-                        0x06 => "JIN",
-                        0x07 => "TEST",
-                        0x08 => "OR",
-                        0x09 => "AND",
-                        0x0A => "TEST_ATTR",
-                        0x0B => "SET_ATTR",
-                        0x0C => "CLEAR_ATTR",
-                        0x0D => "STORE",
-                        0x0E => "INSERT_OBJ",
-                        0x0F => "LOADW",
-                        0x10 => "LOADB",
-                        0x11 => "GET_PROP",
-                        0x12 => "GET_PROP_ADDR",
-                        0x13 => "GET_NEXT_PROP",
-                        0x14 => "ADD",
-                        0x15 => "SUB",
-                        0x16 => "MUL",
-                        0x17 => "DIV",
-                        0x18 => "MOD",
-                        0x19 => "CALL_2S",
-                        0x1A => "CALL_2N",
-                        0x1B => "SET_COLOUR",
-                        0x1C => "THROW",
-                        _ => "UNKNOWN!",
-                    },
-                    OperandCount::_VAR => match self.instruction() {
-                        0x00 => {
-                            if self.version < 4 {
-                                "CALL"
-                            } else {
-                                "CALL_VS"
-                            }
-                        }
-                        0x01 => "STOREW",
-                        0x02 => "STOREB",
-                        0x03 => "PUT_PROP",
-                        0x04 => {
-                            if self.version < 5 {
-                                "SREAD"
-                            } else {
-                                "AREAD"
-                            }
-                        }
-                        0x05 => "PRINT_CHAR",
-                        0x06 => "PRINT_NUM",
-                        0x07 => "RANDOM",
-                        0x08 => "PUSH",
-                        0x09 => "PULL",
-                        0x0A => "SPLIT_WINDOW",
-                        0x0B => "SET_WINDOW",
-                        0x0C => "CALL_VS2",
-                        0x0D => "ERASE_WINDOW",
-                        0x0E => "ERASE_LINE",
-                        0x0F => "SET_CURSOR",
-                        0x10 => "GET_CURSOR",
-                        0x11 => "SET_TEXT_STYLE",
-                        0x12 => "BUFFER_MODE",
-                        0x13 => "OUTPUT_STREAM",
-                        0x14 => "INPUT_STREAM",
-                        0x15 => "SOUND_EFFECT",
-                        0x16 => "READ_CHAR",
-                        0x17 => "SCAN_TABLE",
-                        0x18 => "NOT",
-                        0x19 => "CALL_VN",
-                        0x1A => "CALL_VN2",
-                        0x1B => "TOKENISE",
-                        0x1C => "ENCODE_TEXT",
-                        0x1D => "COPY_TABLE",
-                        0x1E => "PRINT_TABLE",
-                        0x1F => "CHECK_ARG_COUNT",
-                        _ => "UNKNOWN!",
-                    },
-                },
-            }
-        )
+        write!(f, "{}", self.name())
     }
 }
 
@@ -354,8 +223,181 @@ impl Opcode {
     pub fn operand_count(&self) -> &OperandCount {
         &self.operand_count
     }
+
+    /// Instruction mnemonic, taking the version into account for opcodes that
+    /// changed meaning (e.g. `POP` vs `CATCH`).  Unassigned opcodes, and opcodes that
+    /// aren't available in the version, are named `UNKNOWN!`
+    pub fn name(&self) -> &'static str {
+        match self.form {
+            OpcodeForm::Ext => match self.instruction() {
+                0x00 => "SAVE",
+                0x01 => "RESTORE",
+                0x02 => "LOG_SHIFT",
+                0x03 => "ART_SHIFT",
+                0x04 => "SET_FONT",
+                0x05 => "DRAW_PICTURE",
+                0x06 => "PICTURE_DATA",
+                0x07 => "ERASE_PICTURE",
+                0x08 => "SET_MARGINS",
+                0x09 => "SAVE_UNDO",
+                0x0A => "RESTORE_UNDO",
+                0x0B => "PRINT_UNICODE",
+                0x0C => "CHECK_UNICODE",
+                0x0D => "SET_TRUE_COLOUR",
+                0x10 => "MOVE_WINDOW",
+                0x11 => "WINDOW_SIZE",
+                0x12 => "WINDOW_STYLE",
+                0x13 => "GET_WIND_PROP",
+                0x14 => "SCROLL_WINDOW",
+                0x15 => "POP_STACK",
+                0x16 => "READ_MOUSE",
+                0x17 => "MOUSE_WINDOW",
+                0x18 => "PUSH_STACK",
+                0x19 => "PUT_WIND_PROP",
+                0x1A => "PRINT_FORM",
+                0x1B => "MAKE_MENU",
+                0x1C => "PICTURE_TABLE",
+                0x1D => "BUFFER_SCREEN",
+                _ => "UNKNOWN!",
+            },
+            _ => match self.operand_count() {
+                OperandCount::_0OP => match self.instruction() {
+                    0x0 => "RTRUE",
+                    0x1 => "RFALSE",
+                    0x2 => "PRINT",
+                    0x3 => "PRINT_RET",
+                    0x4 => "NOP",
+                    0x5 if self.version < 5 => "SAVE",
+                    0x6 if self.version < 5 => "RESTORE",
+                    0x7 => "RESTART",
+                    0x8 => "RET_POPPED",
+                    0x9 => {
+                        if self.version() < 5 {
+                            "POP"
+                        } else {
+                            "CATCH"
+                        }
+                    }
+                    0xA => "QUIT",
+                    0xB => "NEW_LINE",
+                    0xC => "SHOW_STATUS",
+                    0xD => "VERIFY",
+                    0xF if self.version > 4 => "PIRACY",
+                    _ => "UNKNOWN!",
+                },
+                OperandCount::_1OP => match self.instruction() {
+                    0x0 => "JZ",
+                    0x1 => "GET_SIBLING",
+                    0x2 => "GET_CHILD",
+                    0x3 => "GET_PARENT",
+                    0x4 => "GET_PROP_LEN",
+                    0x5 => "INC",
+                    0x6 => "DEC",
+                    0x7 => "PRINT_ADDR",
+                    0x8 => "CALL_1S",
+                    0x9 => "REMOVE_OBJ",
+                    0xA => "PRINT_OBJ",
+                    0xB => "RET",
+                    0xC => "JUMP",
+                    0xD => "PRINT_PADDR",
+                    0xE => "LOAD",
+                    0xF => {
+                        if self.version < 5 {
+                            "NOT"
+                        } else {
+                            "CALL_1N"
+                        }
+                    }
+                    _ => "UNKNOWN!",
+                },
+                OperandCount::_2OP => match self.instruction() {
+                    0x01 => "JE",
+                    0x02 => "JL",
+                    0x03 => "JG",
+                    0x04 => "DEC_CHK",
+                    0x05 => "INC_CHK",
+                    0x06 => "JIN",
+                    0x07 => "TEST",
+                    0x08 => "OR",
+                    0x09 => "AND",
+                    0x0A => "TEST_ATTR",
+                    0x0B => "SET_ATTR",
+                    0x0C => "CLEAR_ATTR",
+                    0x0D => "STORE",
+                    0x0E => "INSERT_OBJ",
+                    0x0F => "LOADW",
+                    0x10 => "LOADB",
+                    0x11 => "GET_PROP",
+                    0x12 => "GET_PROP_ADDR",
+                    0x13 => "GET_NEXT_PROP",
+                    0x14 => "ADD",
+                    0x15 => "SUB",
+                    0x16 => "MUL",
+                    0x17 => "DIV",
+                    0x18 => "MOD",
+                    0x19 => "CALL_2S",
+                    0x1A => "CALL_2N",
+                    0x1B => "SET_COLOUR",
+                    0x1C => "THROW",
+                    _ => "UNKNOWN!",
+                },
+                OperandCount::_VAR => match self.instruction() {
+                    0x00 => {
+                        if self.version < 4 {
+                            "CALL"
+                        } else {
+                            "CALL_VS"
+                        }
+                    }
+                    0x01 => "STOREW",
+                    0x02 => "STOREB",
+                    0x03 => "PUT_PROP",
+                    0x04 => {
+                        if self.version < 5 {
+                            "SREAD"
+                        } else {
+                            "AREAD"
+                        }
+                    }
+                    0x05 => "PRINT_CHAR",
+                    0x06 => "PRINT_NUM",
+                    0x07 => "RANDOM",
+                    0x08 => "PUSH",
+                    0x09 => "PULL",
+                    0x0A => "SPLIT_WINDOW",
+                    0x0B => "SET_WINDOW",
+                    0x0C => "CALL_VS2",
+                    0x0D => "ERASE_WINDOW",
+                    0x0E => "ERASE_LINE",
+                    0x0F => "SET_CURSOR",
+                    0x10 => "GET_CURSOR",
+                    0x11 => "SET_TEXT_STYLE",
+                    0x12 => "BUFFER_MODE",
+                    0x13 => "OUTPUT_STREAM",
+                    0x14 => "INPUT_STREAM",
+                    0x15 => "SOUND_EFFECT",
+                    0x16 => "READ_CHAR",
+                    0x17 => "SCAN_TABLE",
+                    0x18 => "NOT",
+                    0x19 => "CALL_VN",
+                    0x1A => "CALL_VN2",
+                    0x1B => "TOKENISE",
+                    0x1C => "ENCODE_TEXT",
+                    0x1D => "COPY_TABLE",
+                    0x1E => "PRINT_TABLE",
+                    0x1F => "CHECK_ARG_COUNT",
+                    _ => "UNKNOWN!",
+                },
+            },
+        }
+    }
 }
 
+/// A decoded instruction.
+///
+/// `bytes` are the raw instruction bytes starting at `address`, and `next_address` is the
+/// address of the following instruction.  The opcode, operands, store target, and branch
+/// are exposed as decoded, without resolving variable operands or executing anything.
 #[derive(Debug)]
 pub struct Instruction {
     bytes: Vec<u8>,
@@ -417,11 +459,15 @@ impl Instruction {
         self.address
     }
 
-    fn opcode(&self) -> &Opcode {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn opcode(&self) -> &Opcode {
         &self.opcode
     }
 
-    fn operands(&self) -> &Vec<Operand> {
+    pub fn operands(&self) -> &[Operand] {
         &self.operands
     }
 
@@ -429,7 +475,7 @@ impl Instruction {
         self.store.as_ref()
     }
 
-    fn branch(&self) -> Option<&Branch> {
+    pub fn branch(&self) -> Option<&Branch> {
         self.branch.as_ref()
     }
 
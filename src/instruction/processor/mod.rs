@@ -1,5 +1,6 @@
-use crate::zmachine::ZMachine;
-use crate::{error::*, fatal_error};
+use crate::zmachine::io::screen::InputEvent;
+use crate::zmachine::{Restored, ZMachine};
+use crate::{error::*, fatal_error, recoverable_error};
 
 use super::*;
 
@@ -21,18 +22,10 @@ fn operand_values(
     instruction: &Instruction,
 ) -> Result<Vec<u16>, RuntimeError> {
     let mut v = Vec::new();
-    let mut l = "Operand values: ".to_string();
     for o in instruction.operands() {
-        let value = operand_value(zmachine, o)?;
-        match o.operand_type {
-            OperandType::SmallConstant => l.push_str(&format!(" #{:02x}", value as u8)),
-            _ => l.push_str(&format!(" #{:04x}", value)),
-        }
-        v.push(value)
-    }
-    if !v.is_empty() {
-        debug!(target: "app::instruction", "{}", l);
+        v.push(operand_value(zmachine, o)?);
     }
+    zmachine.trace_operands(instruction, &v);
     Ok(v)
 }
 
@@ -42,28 +35,82 @@ fn branch(
     condition: bool,
 ) -> Result<usize, RuntimeError> {
     match instruction.branch() {
-        Some(b) => {
-            if condition == b.condition() {
-                match b.branch_address {
-                    0 => zmachine.return_routine(0), // return false
-                    1 => zmachine.return_routine(1), // return true,
-                    _ => Ok(b.branch_address()),
-                }
-            } else {
-                Ok(instruction.next_address())
-            }
-        }
+        Some(b) => follow_branch(
+            zmachine,
+            instruction.address(),
+            b,
+            condition,
+            instruction.next_address(),
+        ),
         None => Ok(instruction.next_address()),
     }
 }
 
+// Branch, or return, if `condition` matches the branch condition of the instruction at
+// `address`, otherwise continue at `next_address`
+fn follow_branch(
+    zmachine: &mut ZMachine,
+    address: usize,
+    b: &Branch,
+    condition: bool,
+    next_address: usize,
+) -> Result<usize, RuntimeError> {
+    if condition != b.condition() {
+        Ok(next_address)
+    } else if b.is_jump() {
+        branch_target(zmachine, address, b.branch_address())
+    } else {
+        // Return false (0) or true (1)
+        zmachine.return_routine(b.branch_address() as u16)
+    }
+}
+
+// Check that a branch or jump from the instruction at `address` lands in the story file,
+// past the 64-byte header, so a corrupted offset is reported against the instruction that
+// used it rather than as a bad read at wherever it led.  When the error is ignored, the
+// instruction carries on to the next one as though the branch wasn't taken.
+fn branch_target(
+    zmachine: &ZMachine,
+    address: usize,
+    target: usize,
+) -> Result<usize, RuntimeError> {
+    if target < 0x40 || target >= zmachine.memory_size() {
+        recoverable_error!(
+            ErrorCode::InvalidBranch,
+            "Branch from ${:05x} to ${:05x} is outside the story file",
+            address,
+            target
+        )
+    } else {
+        Ok(target)
+    }
+}
+
+/// Continue from the SAVE that made a save file, once it has been restored.  `pc` is the
+/// address of that instruction's branch byte (V3) or store byte (V4+), as recorded in the
+/// save, so nothing has to be decoded backwards from it.  V3 takes the branch as though
+/// the save had just succeeded, and V4+ stores 2.
+pub fn resume_restored(zmachine: &mut ZMachine, pc: usize) -> Result<usize, RuntimeError> {
+    if zmachine.version() == 3 {
+        let (b, next_address) = decoder::decode_branch(zmachine, pc)?;
+        follow_branch(zmachine, pc, &b, true, next_address)
+    } else {
+        let variable = zmachine.read_byte(pc)?;
+        zmachine.set_variable(variable, 2)?;
+        Ok(pc + 1)
+    }
+}
+
 fn store_result(
     zmachine: &mut ZMachine,
     instruction: &Instruction,
     value: u16,
 ) -> Result<(), RuntimeError> {
     match instruction.store() {
-        Some(s) => zmachine.set_variable(s.variable, value),
+        Some(s) => {
+            zmachine.trace_store(s, value);
+            zmachine.set_variable(s.variable, value)
+        }
         None => Ok(()),
     }
 }
@@ -72,12 +119,13 @@ fn call_fn(
     zmachine: &mut ZMachine,
     address: usize,
     return_addr: usize,
-    arguments: &Vec<u16>,
+    arguments: &[u16],
     result: Option<StoreResult>,
 ) -> Result<usize, RuntimeError> {
     match address {
         0 | 1 => {
             if let Some(r) = result {
+                zmachine.trace_store(&r, address as u16);
                 zmachine.set_variable(r.variable, address as u16)?
             }
 
@@ -87,22 +135,61 @@ fn call_fn(
     }
 }
 
+/// Complete a READ that was waiting for input, see ZMachine::advance
+pub fn complete_read(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+    operands: &[u16],
+    input: Vec<u16>,
+) -> Result<usize, RuntimeError> {
+    processor_var::complete_read(zmachine, instruction, operands, input)
+}
+
+/// Complete a READ_CHAR that was waiting for input, see ZMachine::advance
+pub fn complete_read_char(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+    operands: &[u16],
+    key: InputEvent,
+) -> Result<usize, RuntimeError> {
+    processor_var::complete_read_char(zmachine, instruction, operands, key)
+}
+
 pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
-    debug!(target: "app::instruction", "dispatch: {}", instruction);
     match instruction.opcode().form() {
         OpcodeForm::Ext => match (zmachine.version(), instruction.opcode().instruction()) {
-            // V6 opcodes have been omitted
-            (5, 0x00) | (7, 0x00) | (8, 0x00) => processor_ext::save(zmachine, instruction),
-            (5, 0x01) | (7, 0x01) | (8, 0x01) => processor_ext::restore(zmachine, instruction),
-            (5, 0x02) | (7, 0x02) | (8, 0x02) => processor_ext::log_shift(zmachine, instruction),
-            (5, 0x03) | (7, 0x03) | (8, 0x03) => processor_ext::art_shift(zmachine, instruction),
-            (5, 0x04) | (7, 0x04) | (8, 0x04) => processor_ext::set_font(zmachine, instruction),
-            (5, 0x09) | (7, 0x09) | (8, 0x09) => processor_ext::save_undo(zmachine, instruction),
-            (5, 0x0a) | (7, 0x0a) | (8, 0x0a) => processor_ext::restore_undo(zmachine, instruction),
-            //         (5, 0x0b) | (7, 0x0b) | (8, 0x0b) => processor_ext::print_unicode(context, instruction),
-            //         (5, 0x0c) | (7, 0x0c) | (8, 0x0c) => processor_ext::check_unicode(context, instruction),
-            //         (5, 0x0d) | (7, 0x0d) | (8, 0x0d) => processor_ext::set_true_colour(context, instruction),
-            (_, _) => fatal_error!(
+            (5..=8, 0x00) => processor_ext::save(zmachine, instruction),
+            (5..=8, 0x01) => processor_ext::restore(zmachine, instruction),
+            (5..=8, 0x02) => processor_ext::log_shift(zmachine, instruction),
+            (5..=8, 0x03) => processor_ext::art_shift(zmachine, instruction),
+            (5..=8, 0x04) => processor_ext::set_font(zmachine, instruction),
+            (6, 0x05) => processor_ext::draw_picture(zmachine, instruction),
+            (6, 0x06) => processor_ext::picture_data(zmachine, instruction),
+            (6, 0x07) => processor_ext::erase_picture(zmachine, instruction),
+            (6, 0x08) => processor_ext::set_margins(zmachine, instruction),
+            (5..=8, 0x09) => processor_ext::save_undo(zmachine, instruction),
+            (5..=8, 0x0a) => processor_ext::restore_undo(zmachine, instruction),
+            (6, 0x10) => processor_ext::move_window(zmachine, instruction),
+            (6, 0x11) => processor_ext::window_size(zmachine, instruction),
+            (6, 0x12) => processor_ext::window_style(zmachine, instruction),
+            (6, 0x13) => processor_ext::get_wind_prop(zmachine, instruction),
+            (6, 0x14) => processor_ext::scroll_window(zmachine, instruction),
+            (6, 0x15) => processor_ext::pop_stack(zmachine, instruction),
+            (6, 0x16) => processor_ext::read_mouse(zmachine, instruction),
+            (6, 0x17) => processor_ext::mouse_window(zmachine, instruction),
+            (6, 0x18) => processor_ext::push_stack(zmachine, instruction),
+            (6, 0x19) => processor_ext::put_wind_prop(zmachine, instruction),
+            (6, 0x1a) => processor_ext::print_form(zmachine, instruction),
+            // MAKE_MENU is a V6 opcode, but some late V5 games check for menu support with it
+            (5..=8, 0x1b) => processor_ext::make_menu(zmachine, instruction),
+            (6, 0x1c) => processor_ext::picture_table(zmachine, instruction),
+            (6, 0x1d) => processor_ext::buffer_screen(zmachine, instruction),
+            //         (5..=8, 0x0b) => processor_ext::print_unicode(context, instruction),
+            //         (5..=8, 0x0c) => processor_ext::check_unicode(context, instruction),
+            //         (5..=8, 0x0d) => processor_ext::set_true_colour(context, instruction),
+            // Unknown or unsupported EXT opcodes (e.g. Inform's custom @"EXT:nn" opcodes) decode
+            // normally, so they can be skipped according to the error handling mode
+            (_, _) => recoverable_error!(
                 ErrorCode::UnimplementedInstruction,
                 "Unimplemented EXT instruction: {}",
                 instruction.opcode()
@@ -115,17 +202,25 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
                 (_, 0x2) => processor_0op::print(zmachine, instruction),
                 (_, 0x3) => processor_0op::print_ret(zmachine, instruction),
                 (_, 0x4) => processor_0op::nop(zmachine, instruction),
-                (3, 0x5) | (4, 0x5) => processor_0op::save(zmachine, instruction),
-                (3, 0x6) | (4, 0x6) => processor_0op::restore(zmachine, instruction),
+                (..=4, 0x5) => processor_0op::save(zmachine, instruction),
+                (..=4, 0x6) => processor_0op::restore(zmachine, instruction),
                 (_, 0x7) => processor_0op::restart(zmachine, instruction),
                 (_, 0x8) => processor_0op::ret_popped(zmachine, instruction),
-                (3, 0x9) | (4, 0x9) => processor_0op::pop(zmachine, instruction),
+                (..=4, 0x9) => processor_0op::pop(zmachine, instruction),
                 (_, 0x9) => processor_0op::catch(zmachine, instruction),
                 (_, 0xa) => processor_0op::quit(zmachine, instruction),
                 (_, 0xb) => processor_0op::new_line(zmachine, instruction),
-                (3, 0xc) => processor_0op::show_status(zmachine, instruction),
+                (..=3, 0xc) => processor_0op::show_status(zmachine, instruction),
+                (_, 0xc) => processor_0op::ignore_show_status(zmachine, instruction),
                 (_, 0xd) => processor_0op::verify(zmachine, instruction),
-                (_, 0xf) => processor_0op::piracy(zmachine, instruction),
+                (5.., 0xf) => processor_0op::piracy(zmachine, instruction),
+                // SAVE and RESTORE moved to EXT in V5, and PIRACY is new in V5
+                (version, 0x5 | 0x6 | 0xf) => recoverable_error!(
+                    ErrorCode::UnsupportedOpcode,
+                    "0OP opcode ${:02x} is not available in version {}",
+                    instruction.opcode().opcode(),
+                    version
+                ),
                 (_, _) => fatal_error!(
                     ErrorCode::UnimplementedInstruction,
                     "Unimplemented instruction: {}",
@@ -141,9 +236,7 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
                 (_, 0x5) => processor_1op::inc(zmachine, instruction),
                 (_, 0x6) => processor_1op::dec(zmachine, instruction),
                 (_, 0x7) => processor_1op::print_addr(zmachine, instruction),
-                (4, 0x8) | (5, 0x8) | (7, 0x8) | (8, 0x8) => {
-                    processor_1op::call_1s(zmachine, instruction)
-                }
+                (4..=8, 0x8) => processor_1op::call_1s(zmachine, instruction),
                 (_, 0x9) => processor_1op::remove_obj(zmachine, instruction),
                 (_, 0xa) => processor_1op::print_obj(zmachine, instruction),
                 (_, 0xb) => processor_1op::ret(zmachine, instruction),
@@ -159,6 +252,14 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
                 ),
             },
             OperandCount::_2OP => match (zmachine.version(), instruction.opcode().instruction()) {
+                // The VAR form can encode 0 to 4 operands.  JE compares the first with each of
+                // the others, and the rest use the first two and ignore any more.
+                _ if instruction.operands().len() < 2 => recoverable_error!(
+                    ErrorCode::InvalidOperandCount,
+                    "{} needs at least 2 operands, found {}",
+                    instruction.opcode(),
+                    instruction.operands().len()
+                ),
                 (_, 0x01) => processor_2op::je(zmachine, instruction),
                 (_, 0x02) => processor_2op::jl(zmachine, instruction),
                 (_, 0x03) => processor_2op::jg(zmachine, instruction),
@@ -183,14 +284,10 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
                 (_, 0x16) => processor_2op::mul(zmachine, instruction),
                 (_, 0x17) => processor_2op::div(zmachine, instruction),
                 (_, 0x18) => processor_2op::modulus(zmachine, instruction),
-                (4, 0x19) | (5, 0x19) | (7, 0x19) | (8, 0x19) => {
-                    processor_2op::call_2s(zmachine, instruction)
-                }
-                (5, 0x1a) | (7, 0x1a) | (8, 0x1a) => processor_2op::call_2n(zmachine, instruction),
-                (5, 0x1b) | (7, 0x1b) | (8, 0x1b) => {
-                    processor_2op::set_colour(zmachine, instruction)
-                }
-                (5, 0x1c) | (7, 0x1c) | (8, 0x1c) => processor_2op::throw(zmachine, instruction),
+                (4..=8, 0x19) => processor_2op::call_2s(zmachine, instruction),
+                (5..=8, 0x1a) => processor_2op::call_2n(zmachine, instruction),
+                (5..=8, 0x1b) => processor_2op::set_colour(zmachine, instruction),
+                (5..=8, 0x1c) => processor_2op::throw(zmachine, instruction),
                 (_, _) => fatal_error!(
                     ErrorCode::UnimplementedInstruction,
                     "Unimplemented instruction: {}",
@@ -210,52 +307,26 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
                 (_, 0x09) => processor_var::pull(zmachine, instruction),
                 (_, 0x0a) => processor_var::split_window(zmachine, instruction),
                 (_, 0x0b) => processor_var::set_window(zmachine, instruction),
-                (4, 0x0c) | (5, 0x0c) | (7, 0x0c) | (8, 0x0c) => {
-                    processor_var::call_vs2(zmachine, instruction)
-                }
-                (4, 0x0d) | (5, 0x0d) | (7, 0x0d) | (8, 0x0d) => {
-                    processor_var::erase_window(zmachine, instruction)
-                }
-                (4, 0x0e) | (5, 0x0e) | (7, 0x0e) | (8, 0x0e) => {
-                    processor_var::erase_line(zmachine, instruction)
-                }
-                (4, 0x0f) | (5, 0x0f) | (7, 0x0f) | (8, 0x0f) => {
-                    processor_var::set_cursor(zmachine, instruction)
-                }
-                (4, 0x10) | (5, 0x10) | (7, 0x10) | (8, 0x10) => {
-                    processor_var::get_cursor(zmachine, instruction)
-                }
-                (4, 0x11) | (5, 0x11) | (7, 0x11) | (8, 0x11) => {
-                    processor_var::set_text_style(zmachine, instruction)
-                }
-                (4, 0x12) | (5, 0x12) | (7, 0x12) | (8, 0x12) => {
-                    processor_var::buffer_mode(zmachine, instruction)
-                }
+                (4..=8, 0x0c) => processor_var::call_vs2(zmachine, instruction),
+                (4..=8, 0x0d) => processor_var::erase_window(zmachine, instruction),
+                (4..=8, 0x0e) => processor_var::erase_line(zmachine, instruction),
+                (4..=8, 0x0f) => processor_var::set_cursor(zmachine, instruction),
+                (4..=8, 0x10) => processor_var::get_cursor(zmachine, instruction),
+                (4..=8, 0x11) => processor_var::set_text_style(zmachine, instruction),
+                (4..=8, 0x12) => processor_var::buffer_mode(zmachine, instruction),
                 (_, 0x13) => processor_var::output_stream(zmachine, instruction),
                 (_, 0x14) => processor_var::input_stream(zmachine, instruction),
                 (_, 0x15) => processor_var::sound_effect(zmachine, instruction),
-                (4, 0x16) | (5, 0x16) | (7, 0x16) | (8, 0x16) => {
-                    processor_var::read_char(zmachine, instruction)
-                }
-                (4, 0x17) | (5, 0x17) | (7, 0x17) | (8, 0x17) => {
-                    processor_var::scan_table(zmachine, instruction)
-                }
-                (5, 0x18) | (7, 0x18) | (8, 0x18) => processor_var::not(zmachine, instruction),
-                (5, 0x19) | (7, 0x19) | (8, 0x19) => processor_var::call_vn(zmachine, instruction),
-                (5, 0x1a) | (7, 0x1a) | (8, 0x1a) => processor_var::call_vn2(zmachine, instruction),
-                (5, 0x1b) | (7, 0x1b) | (8, 0x1b) => processor_var::tokenise(zmachine, instruction),
-                (5, 0x1c) | (7, 0x1c) | (8, 0x1c) => {
-                    processor_var::encode_text(zmachine, instruction)
-                }
-                (5, 0x1d) | (7, 0x1d) | (8, 0x1d) => {
-                    processor_var::copy_table(zmachine, instruction)
-                }
-                (5, 0x1e) | (7, 0x1e) | (8, 0x1e) => {
-                    processor_var::print_table(zmachine, instruction)
-                }
-                (5, 0x1f) | (7, 0x1f) | (8, 0x1f) => {
-                    processor_var::check_arg_count(zmachine, instruction)
-                }
+                (4..=8, 0x16) => processor_var::read_char(zmachine, instruction),
+                (4..=8, 0x17) => processor_var::scan_table(zmachine, instruction),
+                (5..=8, 0x18) => processor_var::not(zmachine, instruction),
+                (5..=8, 0x19) => processor_var::call_vn(zmachine, instruction),
+                (5..=8, 0x1a) => processor_var::call_vn2(zmachine, instruction),
+                (5..=8, 0x1b) => processor_var::tokenise(zmachine, instruction),
+                (5..=8, 0x1c) => processor_var::encode_text(zmachine, instruction),
+                (5..=8, 0x1d) => processor_var::copy_table(zmachine, instruction),
+                (5..=8, 0x1e) => processor_var::print_table(zmachine, instruction),
+                (5..=8, 0x1f) => processor_var::check_arg_count(zmachine, instruction),
                 (_, _) => fatal_error!(
                     ErrorCode::UnimplementedInstruction,
                     "Unimplemented instruction: {}",
@@ -268,10 +339,61 @@ pub fn dispatch(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<us
 
 #[cfg(test)]
 pub mod tests {
-    use crate::{assert_ok, assert_ok_eq, test_util::*};
+    use crate::{assert_ok, assert_ok_eq, assert_some, test_util::*};
 
     use super::*;
 
+    // JZ #0 at $400, branching on true by `offset` from $404
+    fn jz_branch(offset: i16) -> (ZMachine, Instruction) {
+        let mut map = test_map(5);
+        let offset = offset as u16 & 0x3FFF;
+        map[0x400..0x404].copy_from_slice(&[0x90, 0x00, 0x80 | (offset >> 8) as u8, offset as u8]);
+        let zmachine = mock_zmachine(map);
+        let i = assert_ok!(decoder::decode_instruction(&zmachine, 0x400));
+        (zmachine, i)
+    }
+
+    #[test]
+    fn test_branch_outside_story() {
+        // To $0000, into the header, and past the end of the 0x800 byte story
+        for (offset, target) in [(-0x402, 0x0), (-0x3C3, 0x3F), (0x408, 0x80A)] {
+            let (mut zmachine, i) = jz_branch(offset);
+            let b = assert_some!(i.branch());
+            assert!(b.is_jump());
+            assert_eq!(b.branch_address(), target);
+            let e = dispatch(&mut zmachine, &i).expect_err("expected an invalid branch");
+            assert_eq!(e.code(), ErrorCode::InvalidBranch);
+            assert!(e.is_recoverable());
+            assert_eq!(
+                e.message(),
+                format!(
+                    "Branch from $00400 to ${:05x} is outside the story file",
+                    target
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_branch_inside_story() {
+        for (offset, target) in [(-0x3C2, 0x40), (0x3FD, 0x7FF)] {
+            let (mut zmachine, i) = jz_branch(offset);
+            assert_ok_eq!(dispatch(&mut zmachine, &i), target);
+        }
+    }
+
+    #[test]
+    fn test_branch_return_offsets() {
+        // Offsets 0 and 1 return rather than branch to $0000 or $0001
+        for offset in [0, 1] {
+            let (mut zmachine, i) = jz_branch(offset);
+            let b = assert_some!(i.branch());
+            assert!(!b.is_jump());
+            assert_ok_eq!(zmachine.call_routine(0x600, &[], None, 0x480), 0x601);
+            assert_ok_eq!(dispatch(&mut zmachine, &i), 0x480);
+        }
+    }
+
     #[test]
     fn test_operand_value() {
         // Set up a simple memory map with global var 0x80 set
@@ -444,7 +566,7 @@ pub mod tests {
             &mut zmachine,
             0,
             0x482,
-            &vec![],
+            &[],
             Some(StoreResult::new(0, 0x80)),
         );
         assert!(a.is_ok());
@@ -463,7 +585,7 @@ pub mod tests {
             &mut zmachine,
             1,
             0x482,
-            &vec![],
+            &[],
             Some(StoreResult::new(0, 0x80)),
         );
         assert!(a.is_ok());
@@ -482,7 +604,7 @@ pub mod tests {
             &mut zmachine,
             0x500,
             0x482,
-            &vec![],
+            &[],
             Some(StoreResult::new(0, 0x80)),
         );
         assert!(a.is_ok());
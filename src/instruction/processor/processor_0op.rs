@@ -1,10 +1,11 @@
 use crate::error::{ErrorCode, RuntimeError};
-use crate::instruction::{decoder, Instruction};
+use crate::instruction::Instruction;
 use crate::zmachine::state::header::HeaderField;
-use crate::zmachine::ZMachine;
+use crate::zmachine::{Restored, ZMachine};
 use crate::{fatal_error, text};
 
 use super::branch;
+use super::resume_restored;
 use super::store_result;
 
 pub fn rtrue(zmachine: &mut ZMachine, _instruction: &Instruction) -> Result<usize, RuntimeError> {
@@ -83,31 +84,21 @@ pub fn save(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 
 pub fn restore(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     match zmachine.restore() {
-        Ok(address) => {
-            match address {
-                Some(a) => {
-                    let i = decoder::decode_instruction(zmachine, a - 1)?;
-                    if zmachine.version() == 3 {
-                        // V3 is a branch
-                        branch(zmachine, &i, true)
-                    } else {
-                        // V4 is a store
-                        store_result(zmachine, instruction, 2)?;
-                        Ok(i.next_address())
-                    }
-                }
-                None => {
-                    if zmachine.version() == 3 {
-                        branch(zmachine, instruction, false)
-                    } else {
-                        store_result(zmachine, instruction, 0)?;
-                        Ok(instruction.next_address())
-                    }
+        Ok(address) => match address {
+            Some(Restored::Save(a)) => resume_restored(zmachine, a),
+            // A save made from the interpreter menu reads again
+            Some(Restored::Read(a)) => Ok(a),
+            None => {
+                if zmachine.version() == 3 {
+                    branch(zmachine, instruction, false)
+                } else {
+                    store_result(zmachine, instruction, 0)?;
+                    Ok(instruction.next_address())
                 }
             }
-        }
+        },
         Err(e) => {
-            zmachine.print_str(format!("Error reading: {}\r", e))?;
+            zmachine.print_error("error.read", &e)?;
             if zmachine.version() == 3 {
                 branch(zmachine, instruction, false)
             } else {
@@ -160,6 +151,16 @@ pub fn show_status(
     Ok(instruction.next_address())
 }
 
+// SHOW_STATUS is V3 only, but Wishbringer r23 issues it in V5.  §15 says to treat it as
+// NOP in later versions.
+pub fn ignore_show_status(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    info!(target: "app::instruction", "SHOW_STATUS at ${:05x} ignored in version {}", instruction.address(), zmachine.version());
+    Ok(instruction.next_address())
+}
+
 pub fn verify(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let expected = zmachine.header_word(HeaderField::Checksum)?;
     let checksum = zmachine.checksum()?;
@@ -176,8 +177,11 @@ mod tests {
     use std::{fs, path::Path};
 
     use crate::{
-        assert_ok_eq, assert_print,
-        instruction::{processor::dispatch, Opcode, OpcodeForm, OperandCount},
+        assert_ok, assert_ok_eq, assert_print,
+        error::ErrorCode,
+        instruction::{
+            decoder::decode_instruction, processor::dispatch, Opcode, OpcodeForm, OperandCount,
+        },
         test_util::*,
     };
 
@@ -229,9 +233,32 @@ mod tests {
         let mut zmachine = mock_zmachine(v);
         let i = mock_instruction(0x480, vec![], opcode(3, 2), 0x481);
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x485);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Hello");
     }
 
+    #[test]
+    fn test_print_high_memory() {
+        let mut v = test_map(8);
+        v.resize(0x30000, 0);
+        // PRINT "Hello" crossing 0x10000 and 0x20000
+        for address in [0xFFFD, 0x1FFFD] {
+            v[address] = 0xB2;
+            v[address + 1] = 0x11;
+            v[address + 2] = 0xaa;
+            v[address + 3] = 0xc6;
+            v[address + 4] = 0x34;
+        }
+
+        let mut zmachine = mock_zmachine(v);
+        let i = assert_ok!(decode_instruction(&zmachine, 0xFFFD));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x10002);
+        let i = assert_ok!(decode_instruction(&zmachine, 0x1FFFD));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x20002);
+        assert!(zmachine.flush().is_ok());
+        assert_print!("HelloHello");
+    }
+
     #[test]
     fn test_print_ret() {
         let mut v = test_map(5);
@@ -302,6 +329,38 @@ mod tests {
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x482);
     }
 
+    #[test]
+    fn test_save_v3_in_interrupt() {
+        let mut map = test_map(3);
+        mock_routine(&mut map, 0x600, &[]);
+        let i = mock_branch_instruction(
+            0x607,
+            vec![],
+            opcode(3, 5),
+            0x609,
+            branch(0x608, false, 0x60B),
+        );
+        let mut zmachine = mock_zmachine(map);
+        zmachine.set_read_interrupt_pending();
+        assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x480), 0x601);
+        // SAVE fails, so a branch on failure is taken
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x60B);
+        assert!(filename_request().is_none());
+    }
+
+    #[test]
+    fn test_restore_v4_in_interrupt() {
+        let mut map = test_map(4);
+        mock_routine(&mut map, 0x600, &[]);
+        let i = mock_store_instruction(0x601, vec![], opcode(4, 6), 0x603, store(0x602, 0x80));
+        let mut zmachine = mock_zmachine(map);
+        zmachine.set_read_interrupt_pending();
+        assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x480), 0x601);
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x603);
+        assert_ok_eq!(zmachine.variable(0x80), 0);
+        assert!(filename_request().is_none());
+    }
+
     #[test]
     fn test_save_v3_bad_instruction() {
         let map = test_map(3);
@@ -355,6 +414,43 @@ mod tests {
         assert_ok_eq!(a, 0x489);
     }
 
+    // Save with the PC at a branch byte that doesn't follow a SAVE opcode, so restoring has
+    // to resume from the saved PC rather than decoding back to the instruction
+    fn restore_v3_branch(bytes: &[u8]) -> (usize, u16) {
+        let dir = assert_ok!(tempfile::tempdir());
+        let save = dir.path().join("branch.ifzs");
+        set_filename_response(save.to_str().unwrap());
+
+        let mut map = test_map(3);
+        map[0x501..0x501 + bytes.len()].copy_from_slice(bytes);
+        set_variable(&mut map, 0x80, 0x1234);
+        let mut zmachine = mock_zmachine(map);
+        assert!(zmachine.save(0x501).is_ok());
+        assert!(zmachine.set_variable(0x80, 0).is_ok());
+
+        let i = mock_branch_instruction(
+            0x600,
+            vec![],
+            opcode(3, 6),
+            0x602,
+            branch(0x601, true, 0x700),
+        );
+        let a = assert_ok!(dispatch(&mut zmachine, &i));
+        (a, assert_ok!(zmachine.variable(0x80)))
+    }
+
+    #[test]
+    fn test_restore_v3_branch_taken() {
+        // Branch on true, 2 byte offset 0x120
+        assert_eq!(restore_v3_branch(&[0x81, 0x20]), (0x621, 0x1234));
+    }
+
+    #[test]
+    fn test_restore_v3_branch_on_false() {
+        // Branch on false, 1 byte offset 9; a successful restore carries on after the branch
+        assert_eq!(restore_v3_branch(&[0x49]), (0x502, 0x1234));
+    }
+
     #[test]
     fn test_restore_v3_fail() {
         let map = test_map(3);
@@ -451,7 +547,9 @@ mod tests {
         let a = dispatch(&mut zmachine, &i2);
         assert!(fs::remove_file(Path::new("test-v4r.ifzs")).is_ok());
         assert_ok_eq!(a, 0x482);
-        assert_ok_eq!(zmachine.variable(0x81), 0x02);
+        // The result goes to the SAVE's store variable, not the RESTORE's
+        assert_ok_eq!(zmachine.variable(0x80), 0x02);
+        assert_ok_eq!(zmachine.variable(0x81), 0xFE);
     }
 
     #[test]
@@ -708,15 +806,61 @@ mod tests {
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
     }
 
+    #[test]
+    fn test_verify_truncated() {
+        let mut map = test_map(3);
+        // File length $800, one byte of which is missing from the file
+        map[0x1A] = 0x04;
+        for (i, b) in (0x40..0x7FF).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map.truncate(0x7FF);
+        map[0x1C] = 0xf3;
+        map[0x1D] = 0x21;
+
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_branch_instruction(
+            0x400,
+            vec![],
+            opcode(3, 13),
+            0x402,
+            branch(0x401, true, 0x40a),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40a);
+    }
+
+    #[test]
+    fn test_verify_trailing_bytes() {
+        let mut map = test_map(3);
+        // File length $800, followed by 512 bytes of junk
+        map[0x1A] = 0x04;
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map.extend_from_slice(&[0xAA; 0x200]);
+        map[0x1C] = 0xf4;
+        map[0x1D] = 0x20;
+
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_branch_instruction(
+            0x400,
+            vec![],
+            opcode(3, 13),
+            0x402,
+            branch(0x401, true, 0x40a),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40a);
+    }
+
     #[test]
     fn test_piracy() {
-        let map = test_map(3);
+        let map = test_map(5);
         let mut zmachine = mock_zmachine(map);
 
         let i = mock_branch_instruction(
             0x400,
             vec![],
-            opcode(3, 15),
+            opcode(5, 15),
             0x402,
             branch(0x401, true, 0x40a),
         );
@@ -725,16 +869,52 @@ mod tests {
 
     #[test]
     fn test_piracy_fail() {
-        let map = test_map(3);
+        let map = test_map(5);
         let mut zmachine = mock_zmachine(map);
 
         let i = mock_branch_instruction(
             0x400,
             vec![],
-            opcode(3, 15),
+            opcode(5, 15),
             0x402,
             branch(0x401, false, 0x40a),
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
     }
+
+    #[test]
+    fn test_unsupported_opcode() {
+        for (version, instruction) in [(3, 15), (4, 15), (5, 5), (5, 6)] {
+            let map = test_map(version);
+            let mut zmachine = mock_zmachine(map);
+            let i = mock_instruction(0x400, vec![], opcode(version, instruction), 0x401);
+            let e = dispatch(&mut zmachine, &i).expect_err("expected an unsupported opcode");
+            assert!(e.is_recoverable());
+            assert_eq!(e.code(), ErrorCode::UnsupportedOpcode);
+        }
+    }
+
+    #[test]
+    fn test_show_status_ignored() {
+        for version in [4, 5, 8] {
+            let map = test_map(version);
+            let mut zmachine = mock_zmachine(map);
+            let i = mock_instruction(0x400, vec![], opcode(version, 12), 0x401);
+            assert_ok_eq!(dispatch(&mut zmachine, &i), 0x401);
+            assert!(errors().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_pop_v4() {
+        let map = test_map(4);
+        let mut zmachine = mock_zmachine(map);
+        assert!(zmachine.push(0x1122).is_ok());
+        assert!(zmachine.push(0x3344).is_ok());
+
+        let i = mock_instruction(0x501, vec![], opcode(4, 9), 0x502);
+
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x502);
+        assert_ok_eq!(zmachine.peek_variable(0), 0x1122);
+    }
 }
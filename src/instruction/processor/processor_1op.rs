@@ -7,7 +7,7 @@ use crate::{
     zmachine::ZMachine,
 };
 
-use super::{branch, operand_values, store_result};
+use super::{branch, branch_target, operand_values, store_result};
 
 pub fn jz(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
@@ -85,12 +85,7 @@ pub fn call_1s(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usi
     let operands = operand_values(zmachine, instruction)?;
     let address = zmachine.packed_routine_address(operands[0])?;
 
-    zmachine.call_routine(
-        address,
-        &vec![],
-        instruction.store,
-        instruction.next_address(),
-    )
+    zmachine.call_routine(address, &[], instruction.store, instruction.next_address())
 }
 
 pub fn remove_obj(
@@ -151,7 +146,7 @@ pub fn ret(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 pub fn jump(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
     let address = (instruction.next_address() as isize) + (operands[0] as i16) as isize - 2;
-    Ok(address as usize)
+    branch_target(zmachine, instruction.address(), address as usize)
 }
 
 pub fn print_paddr(
@@ -182,13 +177,14 @@ pub fn not(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 pub fn call_1n(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
     let address = zmachine.packed_routine_address(operands[0])?;
-    zmachine.call_routine(address, &vec![], None, instruction.next_address())
+    zmachine.call_routine(address, &[], None, instruction.next_address())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         assert_ok_eq, assert_print,
+        error::ErrorCode,
         instruction::{processor::dispatch, Opcode, OpcodeForm, OperandCount, OperandType},
         object,
         test_util::*,
@@ -729,6 +725,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Hello");
     }
 
@@ -1034,6 +1031,7 @@ mod tests {
             0x402,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Parent");
     }
 
@@ -1050,6 +1048,7 @@ mod tests {
             0x402,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Parent");
     }
 
@@ -1115,6 +1114,30 @@ mod tests {
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x301);
     }
 
+    #[test]
+    fn test_jump_outside_story() {
+        // To $0000, and past the end of the 0x800 byte story
+        for (offset, target) in [(0xFBFE, 0x0), (0x408, 0x80A)] {
+            let map = test_map(3);
+            let mut zmachine = mock_zmachine(map);
+            let i = mock_instruction(
+                0x401,
+                vec![operand(OperandType::LargeConstant, offset)],
+                opcode(3, 12),
+                0x404,
+            );
+            let e = dispatch(&mut zmachine, &i).expect_err("expected an invalid branch");
+            assert_eq!(e.code(), ErrorCode::InvalidBranch);
+            assert_eq!(
+                e.message(),
+                format!(
+                    "Branch from $00401 to ${:05x} is outside the story file",
+                    target
+                )
+            );
+        }
+    }
+
     #[test]
     fn test_print_paddr_v3() {
         let mut map = test_map(3);
@@ -1132,6 +1155,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Hello");
     }
 
@@ -1152,6 +1176,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Hello");
     }
 
@@ -1172,6 +1197,29 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
+        assert_print!("Hello");
+    }
+
+    #[test]
+    fn test_print_paddr_v8_high_memory() {
+        let mut map = test_map(8);
+        map.resize(0x30000, 0);
+        // Hello
+        map[0x28000] = 0x11;
+        map[0x28001] = 0xaa;
+        map[0x28002] = 0xc6;
+        map[0x28003] = 0x34;
+
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_instruction(
+            0x400,
+            vec![operand(OperandType::LargeConstant, 0x5000)],
+            opcode(8, 13),
+            0x403,
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Hello");
     }
 
@@ -269,9 +269,11 @@ pub fn div(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 
     let mut value = operands[0] as i16;
     for w in operands[1..].iter() {
-        // Divide by zero
+        // Divide by zero.  Store a 0 here before returning an error so the user may
+        // opt to recover
         if *w == 0 {
-            return fatal_error!(
+            store_result(zmachine, instruction, 0)?;
+            return recoverable_error!(
                 ErrorCode::DivideByZero,
                 "Divide by zero: {}, {:?}",
                 instruction,
@@ -291,7 +293,8 @@ pub fn modulus(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usi
     let mut value = operands[0] as i16;
     for w in operands[1..].iter() {
         if *w == 0 {
-            return fatal_error!(
+            store_result(zmachine, instruction, 0)?;
+            return recoverable_error!(
                 ErrorCode::DivideByZero,
                 "Divide by zero: {}, {:?}",
                 instruction,
@@ -340,7 +343,13 @@ pub fn set_colour(
     instruction: &Instruction,
 ) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
-    zmachine.set_colors(operands[0], operands[1])?;
+    // V6 can colour a window other than the selected one
+    match operands.get(2) {
+        Some(window) if zmachine.version() == 6 => {
+            zmachine.set_window_colors(*window as i16, operands[0], operands[1])?
+        }
+        _ => zmachine.set_colors(operands[0], operands[1])?,
+    }
     Ok(instruction.next_address())
 }
 
@@ -356,8 +365,10 @@ pub fn throw(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize
 #[cfg(test)]
 mod tests {
     use crate::{
-        assert_ok_eq,
+        assert_ok, assert_ok_eq,
+        error::{ErrorCode, RuntimeError},
         instruction::{
+            decoder::decode_instruction,
             processor::{dispatch, Opcode},
             OpcodeForm, OperandCount, OperandType,
         },
@@ -461,6 +472,95 @@ mod tests {
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x406);
     }
 
+    // Decode `bytes` at $600 and run the instruction
+    fn run_var_2op(map: &mut [u8], bytes: &[u8]) -> (usize, Result<usize, RuntimeError>) {
+        map[0x600..0x600 + bytes.len()].copy_from_slice(bytes);
+        let mut zmachine = mock_zmachine(map.to_vec());
+        let i = assert_ok!(decode_instruction(&zmachine, 0x600));
+        assert_eq!(i.next_address(), 0x600 + bytes.len());
+        (i.operands().len(), dispatch(&mut zmachine, &i))
+    }
+
+    #[test]
+    fn test_je_operand_counts() {
+        // JE in VAR form with small constant operands, branching on true to $610
+        for (operands, taken) in [
+            (&[1, 1][..], true),
+            (&[1, 2], false),
+            (&[1, 2, 1], true),
+            (&[1, 1, 2], true),
+            (&[1, 2, 3], false),
+            (&[1, 2, 3, 1], true),
+            (&[1, 2, 1, 3], true),
+            (&[0xFF, 2, 3, 4], false),
+        ] {
+            let mut bytes = vec![0xC1];
+            // Operand types: small constants, then omitted
+            bytes.push((0..4).fold(0, |t, i| {
+                (t << 2) | if i < operands.len() { 0b01 } else { 0b11 }
+            }));
+            bytes.extend_from_slice(operands);
+            let offset = (0x610 - 0x600 - bytes.len() + 1) as u8;
+            bytes.push(0xC0 | offset);
+            let next = 0x600 + bytes.len();
+
+            let mut map = test_map(5);
+            let (count, a) = run_var_2op(&mut map, &bytes);
+            assert_eq!(count, operands.len());
+            assert_eq!(
+                assert_ok!(a),
+                if taken { 0x610 } else { next },
+                "{:?}",
+                operands
+            );
+        }
+    }
+
+    #[test]
+    fn test_2op_one_operand() {
+        // JE, JL, and ADD in VAR form with a single small constant
+        for bytes in [
+            &[0xC1, 0x7F, 0x01, 0xC8][..],
+            &[0xC2, 0x7F, 0x01, 0xC8],
+            &[0xD4, 0x7F, 0x01, 0x80],
+        ] {
+            let mut map = test_map(5);
+            let (count, a) = run_var_2op(&mut map, bytes);
+            assert_eq!(count, 1);
+            let e = a.expect_err("expected an operand count error");
+            assert_eq!(e.code(), ErrorCode::InvalidOperandCount);
+            assert!(e.is_recoverable());
+        }
+    }
+
+    #[test]
+    fn test_2op_extra_operands() {
+        let mut map = test_map(5);
+        // ADD #3 #4 #5 -> G00, the third operand is ignored
+        let (count, a) = run_var_2op(&mut map, &[0xD4, 0x57, 0x03, 0x04, 0x05, 0x10]);
+        assert_eq!(count, 3);
+        assert_ok_eq!(a, 0x606);
+
+        // CALL_2S $700 #7 #8 -> G00 passes only the first argument
+        mock_routine(&mut map, 0x700, &[0x1111, 0x2222]);
+        let mut zmachine = mock_zmachine(map.clone());
+        let i = mock_store_instruction(
+            0x400,
+            vec![
+                operand(OperandType::LargeConstant, 0x1C0),
+                operand(OperandType::SmallConstant, 0x07),
+                operand(OperandType::SmallConstant, 0x08),
+            ],
+            opcode_var(5, 0x19),
+            0x407,
+            store(0x406, 0x10),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x701);
+        assert_ok_eq!(zmachine.variable(1), 0x07);
+        // V5 locals start at 0
+        assert_ok_eq!(zmachine.variable(2), 0);
+    }
+
     #[test]
     fn test_jl_true() {
         let map = test_map(3);
@@ -1959,7 +2059,10 @@ mod tests {
             0x406,
             store(0x405, 0x80),
         );
-        assert!(dispatch(&mut zmachine, &i).is_err());
+        assert!(zmachine.set_variable(0x80, 0x1234).is_ok());
+        let e = dispatch(&mut zmachine, &i).expect_err("expected a divide by zero");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::DivideByZero);
         assert!(zmachine.variable(0x80).is_ok_and(|x| x == 0))
     }
 
@@ -2031,7 +2134,10 @@ mod tests {
             0x406,
             store(0x405, 0x80),
         );
-        assert!(dispatch(&mut zmachine, &i).is_err());
+        assert!(zmachine.set_variable(0x80, 0x1234).is_ok());
+        let e = dispatch(&mut zmachine, &i).expect_err("expected a divide by zero");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::DivideByZero);
         assert!(zmachine.variable(0x80).is_ok_and(|x| x == 0))
     }
 
@@ -30,18 +30,16 @@ pub fn restore(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usi
     } else {
         match zmachine.restore() {
             Ok(address) => match address {
-                Some(a) => {
-                    let i = decoder::decode_instruction(zmachine, a - 3)?;
-                    store_result(zmachine, &i, 2)?;
-                    Ok(i.next_address())
-                }
+                Some(Restored::Save(a)) => resume_restored(zmachine, a),
+                // A save made from the interpreter menu reads again
+                Some(Restored::Read(a)) => Ok(a),
                 None => {
                     store_result(zmachine, instruction, 0)?;
                     Ok(instruction.next_address())
                 }
             },
             Err(e) => {
-                zmachine.print_str(format!("Error restoring: {}\r", e))?;
+                zmachine.print_error("error.restore", &e)?;
                 store_result(zmachine, instruction, 0)?;
                 Ok(instruction.next_address())
             }
@@ -103,7 +101,13 @@ pub fn art_shift(
 
 pub fn set_font(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
-    let result = zmachine.set_font(operands[0])?;
+    // V6 can set the font of a window other than the selected one
+    let result = match operands.get(1) {
+        Some(window) if zmachine.version() == 6 => {
+            zmachine.set_window_font(*window as i16, operands[0])?
+        }
+        _ => zmachine.set_font(operands[0])?,
+    };
     store_result(zmachine, instruction, result)?;
     Ok(instruction.next_address())
 }
@@ -113,8 +117,10 @@ pub fn save_undo(
     instruction: &Instruction,
 ) -> Result<usize, RuntimeError> {
     // unwrap() should be safe here because this is a store instruction
-    zmachine.save_undo(instruction.store().unwrap().address())?;
-    store_result(zmachine, instruction, 1)?;
+    match zmachine.save_undo(instruction.store().unwrap().address()) {
+        Ok(_) => store_result(zmachine, instruction, 1)?,
+        Err(_) => store_result(zmachine, instruction, 0)?,
+    }
     Ok(instruction.next_address())
 }
 
@@ -124,11 +130,8 @@ pub fn restore_undo(
 ) -> Result<usize, RuntimeError> {
     match zmachine.restore_undo() {
         Ok(pc) => match pc {
-            Some(address) => {
-                let i = decoder::decode_instruction(zmachine, address - 3)?;
-                store_result(zmachine, &i, 2)?;
-                Ok(i.next_address())
-            }
+            // SAVE_UNDO recorded the address of its store byte
+            Some(address) => resume_restored(zmachine, address),
             None => {
                 store_result(zmachine, instruction, 0)?;
                 Ok(instruction.next_address())
@@ -141,6 +144,246 @@ pub fn restore_undo(
     }
 }
 
+pub fn make_menu(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    info!(target: "app::instruction", "MAKE_MENU {:?}: menus aren't available", operands);
+    branch(zmachine, instruction, false)
+}
+
+// The window operand of a V6 opcode, where -3 (the default) is the selected window
+fn window_operand(operands: &[u16], index: usize) -> i16 {
+    operands.get(index).map_or(-3, |w| *w as i16)
+}
+
+// Where a picture goes: the y, x operands, or the cursor when they're left out
+fn picture_position(zmachine: &mut ZMachine, operands: &[u16]) -> Result<(u16, u16), RuntimeError> {
+    match (operands.get(1), operands.get(2)) {
+        (Some(y), Some(x)) => Ok((*y, *x)),
+        _ => zmachine.cursor(),
+    }
+}
+
+pub fn draw_picture(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let at = picture_position(zmachine, &operands)?;
+    match zmachine.picture_size(operands[0]) {
+        Some((height, width)) => {
+            info!(target: "app::screen", "DRAW_PICTURE {} ({}x{}) at {:?}: pictures can't be drawn on a text terminal", operands[0], width, height, at)
+        }
+        None => debug!(target: "app::screen", "DRAW_PICTURE {}: no such picture", operands[0]),
+    }
+    Ok(instruction.next_address())
+}
+
+pub fn picture_data(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let table = operands[1] as usize;
+    // Picture 0 is the number of pictures and their release number, and branches when
+    // there are any pictures at all.  An unknown picture leaves the table alone.
+    let (data, available) = if operands[0] == 0 {
+        let (count, release) = zmachine.picture_index();
+        (Some((count, release)), count > 0)
+    } else {
+        let size = zmachine.picture_size(operands[0]);
+        (size, size.is_some())
+    };
+    if let Some((first, second)) = data {
+        zmachine.write_word(table, first)?;
+        zmachine.write_word(table + 2, second)?;
+    }
+    branch(zmachine, instruction, available)
+}
+
+pub fn erase_picture(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let at = picture_position(zmachine, &operands)?;
+    if let Some(size) = zmachine.picture_size(operands[0]) {
+        zmachine.erase_area(at, size)?;
+    }
+    Ok(instruction.next_address())
+}
+
+pub fn set_margins(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let window = window_operand(&operands, 2);
+    zmachine.set_margins(window, operands[0], operands[1])?;
+    Ok(instruction.next_address())
+}
+
+pub fn move_window(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    zmachine.move_window(operands[0] as i16, operands[1], operands[2])?;
+    Ok(instruction.next_address())
+}
+
+pub fn window_size(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    zmachine.window_size(operands[0] as i16, operands[1], operands[2])?;
+    Ok(instruction.next_address())
+}
+
+pub fn window_style(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let operation = operands.get(2).copied().unwrap_or(0);
+    zmachine.window_style(operands[0] as i16, operands[1], operation)?;
+    Ok(instruction.next_address())
+}
+
+pub fn get_wind_prop(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let value = zmachine.window_property(operands[0] as i16, operands[1])?;
+    store_result(zmachine, instruction, value)?;
+    Ok(instruction.next_address())
+}
+
+pub fn scroll_window(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    zmachine.scroll_window(operands[0] as i16, operands[1] as i16)?;
+    Ok(instruction.next_address())
+}
+
+pub fn pop_stack(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    match operands.get(1) {
+        // A user stack's first word is the number of free slots
+        Some(stack) => {
+            let free = zmachine.read_word(*stack as usize)?;
+            zmachine.write_word(*stack as usize, free.wrapping_add(operands[0]))?;
+        }
+        None => {
+            for _ in 0..operands[0] {
+                zmachine.variable(0)?;
+            }
+        }
+    }
+    Ok(instruction.next_address())
+}
+
+pub fn read_mouse(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let table = operands[0] as usize;
+    // Where the mouse was last clicked, with no buttons held and no menu selected
+    let (row, column) = zmachine.mouse_position()?;
+    for (i, value) in [row, column, 0, 0].iter().enumerate() {
+        zmachine.write_word(table + i * 2, *value)?;
+    }
+    Ok(instruction.next_address())
+}
+
+pub fn mouse_window(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    debug!(target: "app::screen", "MOUSE_WINDOW {}: clicks are reported anywhere on the screen", operands[0] as i16);
+    Ok(instruction.next_address())
+}
+
+pub fn push_stack(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    let stack = operands[1] as usize;
+    let free = zmachine.read_word(stack)?;
+    if free == 0 {
+        branch(zmachine, instruction, false)
+    } else {
+        zmachine.write_word(stack + free as usize * 2, operands[0])?;
+        zmachine.write_word(stack, free - 1)?;
+        branch(zmachine, instruction, true)
+    }
+}
+
+pub fn put_wind_prop(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    zmachine.set_window_property(operands[0] as i16, operands[1], operands[2])?;
+    Ok(instruction.next_address())
+}
+
+pub fn print_form(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    // Lines of text as written to a stream 3 table with formatting on: a length word
+    // followed by the characters, ending with a length of 0
+    let mut address = operands[0] as usize;
+    loop {
+        let length = zmachine.read_word(address)? as usize;
+        if length == 0 {
+            break;
+        }
+        let mut line = Vec::new();
+        for i in 0..length {
+            line.push(zmachine.read_byte(address + 2 + i)? as u16);
+        }
+        zmachine.print(&line)?;
+        zmachine.new_line()?;
+        address += 2 + length;
+    }
+    Ok(instruction.next_address())
+}
+
+pub fn picture_table(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    debug!(target: "app::screen", "PICTURE_TABLE ${:04x}: nothing to preload", operands[0]);
+    Ok(instruction.next_address())
+}
+
+pub fn buffer_screen(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+) -> Result<usize, RuntimeError> {
+    let operands = operand_values(zmachine, instruction)?;
+    // The terminal is always updated as output is printed, which is mode 0
+    debug!(target: "app::screen", "BUFFER_SCREEN {}", operands[0]);
+    store_result(zmachine, instruction, 0)?;
+    Ok(instruction.next_address())
+}
+
 // pub fn print_unicode(context: &mut Context, instruction: &Instruction) -> Result<usize, ContextError> {
 //     let operands = operand_values(context, instruction)?;
 //     todo!()
@@ -161,9 +404,18 @@ mod tests {
     use std::{fs, path::Path};
 
     use crate::{
-        assert_ok_eq,
-        instruction::{processor::dispatch, Opcode, OpcodeForm, OperandCount, OperandType},
+        assert_ok, assert_ok_eq, assert_some_eq,
+        blorb::{Blorb, BlorbBuilder, Reso, Scaling},
+        config::Config,
+        instruction::{
+            decoder::decode_instruction, processor::dispatch, Opcode, OpcodeForm, OperandCount,
+            OperandType,
+        },
         test_util::*,
+        zmachine::{
+            state::{header::HeaderField, memory::Memory},
+            ZMachine,
+        },
     };
 
     fn opcode(instruction: u8) -> Opcode {
@@ -201,6 +453,36 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x80), 0);
     }
 
+    #[test]
+    fn test_save_in_interrupt() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[]);
+        let i = mock_store_instruction(0x601, vec![], opcode(0), 0x604, store(0x603, 0x80));
+        let mut zmachine = mock_zmachine(map);
+        // A timer routine interrupting READ
+        zmachine.set_read_interrupt_pending();
+        assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x480), 0x601);
+        capture_log("app::state");
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x604);
+        assert_ok_eq!(zmachine.variable(0x80), 0);
+        // Nothing was saved
+        assert!(filename_request().is_none());
+        assert!(log().contains(&"SAVE in an interrupt routine".to_string()));
+    }
+
+    #[test]
+    fn test_save_in_sound_interrupt() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[]);
+        let i = mock_store_instruction(0x601, vec![], opcode(0), 0x604, store(0x603, 0x80));
+        let mut zmachine = mock_zmachine(map);
+        zmachine.set_sound_interrupt(0x600);
+        assert_ok_eq!(zmachine.call_sound_interrupt(0x480), 0x601);
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x604);
+        assert_ok_eq!(zmachine.variable(0x80), 0);
+        assert!(filename_request().is_none());
+    }
+
     #[test]
     fn test_restore_v5() {
         input(&[
@@ -241,6 +523,26 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x80), 0x02);
     }
 
+    #[test]
+    fn test_restore_v5_saved_store() {
+        // Save with the PC at a store byte that doesn't follow a SAVE opcode, so restoring
+        // has to resume from the saved PC rather than decoding back to the instruction
+        let dir = assert_ok!(tempfile::tempdir());
+        let save = dir.path().join("store.ifzs");
+        set_filename_response(save.to_str().unwrap());
+
+        let mut map = test_map(5);
+        map[0x501] = 0x82;
+        set_variable(&mut map, 0x81, 0xFE);
+        let mut zmachine = mock_zmachine(map);
+        assert!(zmachine.save(0x501).is_ok());
+
+        let i = mock_store_instruction(0x600, vec![], opcode(1), 0x604, store(0x603, 0x81));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x502);
+        assert_ok_eq!(zmachine.variable(0x82), 2);
+        assert_ok_eq!(zmachine.variable(0x81), 0xFE);
+    }
+
     #[test]
     fn test_restore_v5_fail() {
         let mut map = test_map(5);
@@ -490,6 +792,25 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x81), 1);
     }
 
+    #[test]
+    fn test_set_font_2() {
+        let map = test_map(5);
+
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_store_instruction(
+            0x400,
+            vec![operand(OperandType::SmallConstant, 2)],
+            opcode(4),
+            0x484,
+            store(0x483, 0x81),
+        );
+
+        // The picture font is never available
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x484);
+        assert_ok_eq!(zmachine.variable(0x81), 0);
+        assert_ok_eq!(zmachine.set_font(0), 1);
+    }
+
     #[test]
     fn test_set_font_invalid() {
         let map = test_map(5);
@@ -518,6 +839,18 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x81), 1);
     }
 
+    #[test]
+    fn test_save_undo_disabled() {
+        let m = Memory::new(test_map(5));
+        let mut config = Config::default();
+        config.set_undo_depth(0);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        let i = mock_store_instruction(0x400, vec![], opcode(9), 0x483, store(0x482, 0x81));
+
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x483);
+        assert_ok_eq!(zmachine.variable(0x81), 0);
+    }
+
     #[test]
     fn test_restore_undo() {
         let mut map = test_map(5);
@@ -544,6 +877,56 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x80), 2);
     }
 
+    #[test]
+    fn test_restore_undo_store_byte() {
+        let mut map = test_map(5);
+        // Only the store byte of the SAVE_UNDO is needed to resume, nothing before it
+        map[0x483] = 0x82;
+        let mut zmachine = mock_zmachine(map);
+
+        let i = mock_store_instruction(0x480, vec![], opcode(9), 0x484, store(0x483, 0x82));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x484);
+        assert_ok_eq!(zmachine.variable(0x82), 1);
+
+        let i = mock_store_instruction(0x600, vec![], opcode(10), 0x604, store(0x603, 0x81));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x484);
+        assert_ok_eq!(zmachine.variable(0x82), 2);
+        assert_ok_eq!(zmachine.variable(0x81), 0);
+    }
+
+    #[test]
+    fn test_restore_undo_in_interrupt() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[]);
+        // Put the save instruction into memory for the restore
+        map[0x601] = 0xBE;
+        map[0x602] = 0x09;
+        map[0x603] = 0xFF;
+        map[0x604] = 0x81;
+
+        let mut zmachine = mock_zmachine(map);
+        zmachine.set_read_interrupt_pending();
+        assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x480), 0x601);
+
+        // SAVE_UNDO is allowed in an interrupt routine
+        let i = mock_store_instruction(0x601, vec![], opcode(9), 0x605, store(0x604, 0x81));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x605);
+        assert_ok_eq!(zmachine.variable(0x81), 1);
+
+        // The timer routine returns and READ carries on
+        assert_ok_eq!(zmachine.return_routine(1), 0x480);
+        assert_some_eq!(zmachine.read_interrupt_result(), 1);
+
+        // Undo resumes inside the timer routine, which still returns to READ
+        let i = mock_store_instruction(0x480, vec![], opcode(10), 0x484, store(0x483, 0x81));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x605);
+        assert_eq!(zmachine.frame_count(), 2);
+        assert_ok_eq!(zmachine.variable(0x81), 2);
+        assert!(zmachine.read_interrupt_result().is_none());
+        assert_ok_eq!(zmachine.return_routine(1), 0x480);
+        assert_some_eq!(zmachine.read_interrupt_result(), 1);
+    }
+
     #[test]
     fn test_restore_undo_fail() {
         let map = test_map(5);
@@ -555,4 +938,136 @@ mod tests {
         assert_eq!(zmachine.frame_count(), 1);
         assert_ok_eq!(zmachine.variable(0x81), 0);
     }
+
+    #[test]
+    fn test_make_menu() {
+        let mut map = test_map(5);
+        // MAKE_MENU #03 #0500 ?(+5), then QUIT
+        map[0x400..0x408].copy_from_slice(&[0xBE, 0x1B, 0x4F, 0x03, 0x05, 0x00, 0xC5, 0xBA]);
+        let mut zmachine = mock_zmachine(map);
+
+        let i = assert_ok!(decode_instruction(&zmachine, 0x400));
+        assert_eq!(i.opcode().name(), "MAKE_MENU");
+        assert_eq!(i.operands().len(), 2);
+        assert_eq!(i.next_address(), 0x407);
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x407);
+        let i = assert_ok!(decode_instruction(&zmachine, 0x407));
+        assert_eq!(i.opcode().name(), "QUIT");
+    }
+
+    #[test]
+    fn test_make_menu_branch_on_false() {
+        let mut map = test_map(5);
+        // MAKE_MENU #03 #0500 ?~(+5)
+        map[0x400..0x407].copy_from_slice(&[0xBE, 0x1B, 0x4F, 0x03, 0x05, 0x00, 0x45]);
+        let mut zmachine = mock_zmachine(map);
+
+        let i = assert_ok!(decode_instruction(&zmachine, 0x400));
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40A);
+    }
+
+    #[test]
+    fn test_push_stack_pop_stack() {
+        let mut map = test_map(6);
+        // A user stack at $0380 with 2 free slots
+        map[0x381] = 2;
+        // PUSH_STACK #1234 #0380 ?(+5)
+        map[0x401..0x409].copy_from_slice(&[0xBE, 0x18, 0x0F, 0x12, 0x34, 0x03, 0x80, 0xC5]);
+        // POP_STACK #02 #0380
+        map[0x409..0x40F].copy_from_slice(&[0xBE, 0x15, 0x4F, 0x02, 0x03, 0x80]);
+        let mut zmachine = mock_zmachine(map);
+
+        let i = assert_ok!(decode_instruction(&zmachine, 0x401));
+        assert_eq!(i.opcode().name(), "PUSH_STACK");
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40C);
+        assert_ok_eq!(zmachine.read_word(0x380), 1);
+        assert_ok_eq!(zmachine.read_word(0x384), 0x1234);
+        // The stack is full
+        assert!(zmachine.write_word(0x380, 0).is_ok());
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x409);
+
+        let i = assert_ok!(decode_instruction(&zmachine, 0x409));
+        assert_eq!(i.opcode().name(), "POP_STACK");
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40F);
+        assert_ok_eq!(zmachine.read_word(0x380), 2);
+    }
+
+    #[test]
+    fn test_picture_data_no_pictures() {
+        let mut map = test_map(6);
+        map[0x380] = 0xFF;
+        // PICTURE_DATA #00 #0380 ?(+5)
+        map[0x401..0x408].copy_from_slice(&[0xBE, 0x06, 0x4F, 0x00, 0x03, 0x80, 0xC5]);
+        let mut zmachine = mock_zmachine(map);
+
+        let i = assert_ok!(decode_instruction(&zmachine, 0x401));
+        assert_eq!(i.opcode().name(), "PICTURE_DATA");
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x408);
+        assert_ok_eq!(zmachine.read_word(0x380), 0);
+        assert_ok_eq!(zmachine.read_word(0x382), 0);
+    }
+
+    #[test]
+    fn test_picture_data_blorb() {
+        let mut map = test_map(6);
+        // PICTURE_DATA #00 #0380 ?(+5)
+        map[0x401..0x408].copy_from_slice(&[0xBE, 0x06, 0x4F, 0x00, 0x03, 0x80, 0xC5]);
+        // PICTURE_DATA #02 #0384 ?(+5)
+        map[0x408..0x40F].copy_from_slice(&[0xBE, 0x06, 0x4F, 0x02, 0x03, 0x84, 0xC5]);
+        // PICTURE_DATA #03 #0388 ?(+5)
+        map[0x40F..0x416].copy_from_slice(&[0xBE, 0x06, 0x4F, 0x03, 0x03, 0x88, 0xC5]);
+        let mut zmachine = mock_zmachine(map);
+        let width = assert_ok!(zmachine.header_word(HeaderField::ScreenWidth)) as u32;
+        let height = assert_ok!(zmachine.header_word(HeaderField::ScreenHeight)) as u32;
+
+        // Picture 2 is drawn at its own size, picture 3 is half of a window twice the
+        // size of the screen
+        let mut builder = BlorbBuilder::new();
+        builder.add("Pict", 2, "PNG ", mock_png(64, 48));
+        builder.add("Pict", 3, "PNG ", mock_png(40, 20));
+        let scaling = Scaling::new((1, 2), (0, 0), (0, 0));
+        let fixed = Scaling::new((1, 1), (1, 1), (1, 1));
+        builder.set_reso(Some(Reso::new(
+            (width * 2, height * 2),
+            (0, 0),
+            (0, 0),
+            vec![(2, fixed), (3, scaling)],
+        )));
+        let blorb = assert_ok!(Blorb::try_from(builder.build()));
+        zmachine.set_pictures(blorb.picture_index());
+
+        for (address, next) in [(0x401, 0x40B), (0x408, 0x412), (0x40F, 0x419)] {
+            let i = assert_ok!(decode_instruction(&zmachine, address));
+            assert_ok_eq!(dispatch(&mut zmachine, &i), next);
+        }
+        // Two pictures, release 0
+        assert_ok_eq!(zmachine.read_word(0x380), 2);
+        assert_ok_eq!(zmachine.read_word(0x382), 0);
+        // Height, then width
+        assert_ok_eq!(zmachine.read_word(0x384), 48);
+        assert_ok_eq!(zmachine.read_word(0x386), 64);
+        assert_ok_eq!(zmachine.read_word(0x388), 5);
+        assert_ok_eq!(zmachine.read_word(0x38A), 10);
+    }
+
+    #[test]
+    fn test_put_wind_prop_get_wind_prop() {
+        let mut map = test_map(6);
+        // PUT_WIND_PROP #01 #0F #05
+        map[0x401..0x407].copy_from_slice(&[0xBE, 0x19, 0x57, 0x01, 0x0F, 0x05]);
+        // GET_WIND_PROP #01 #0F -> G00
+        map[0x407..0x40C].copy_from_slice(&[0xBE, 0x13, 0x5F, 0x01, 0x0F]);
+        map[0x40C] = 0x10;
+        // GET_WIND_PROP #00 #02 -> G01
+        map[0x40D..0x413].copy_from_slice(&[0xBE, 0x13, 0x5F, 0x00, 0x02, 0x11]);
+        let mut zmachine = mock_zmachine(map);
+
+        for (address, next) in [(0x401, 0x407), (0x407, 0x40D), (0x40D, 0x413)] {
+            let i = assert_ok!(decode_instruction(&zmachine, address));
+            assert_ok_eq!(dispatch(&mut zmachine, &i), next);
+        }
+        assert_ok_eq!(zmachine.variable(0x10), 5);
+        // Window 0 fills the screen
+        assert_ok_eq!(zmachine.variable(0x11), 24);
+    }
 }
@@ -4,7 +4,11 @@ use crate::{
     instruction::{processor::store_result, Instruction},
     object::property,
     recoverable_error, text,
-    zmachine::{io::screen::Interrupt, state::header::HeaderField, ZMachine},
+    zmachine::{
+        io::screen::{InputEvent, Interrupt},
+        state::header::HeaderField,
+        ZMachine,
+    },
 };
 
 use super::{branch, call_fn, operand_values};
@@ -80,10 +84,83 @@ pub fn to_lower_case(c: u16) -> u8 {
     }
 }
 
+// Check the READ text and parse buffers against the header, the end of dynamic memory, and
+// each other, returning the number of characters that can be read into the text buffer.  A
+// parse buffer with room for more words than fit in dynamic memory has its size clamped.
+fn check_read_buffers(zmachine: &mut ZMachine, operands: &[u16]) -> Result<usize, RuntimeError> {
+    let text_buffer = operands[0] as usize;
+    let parse = if operands.len() > 1 {
+        operands[1] as usize
+    } else {
+        0
+    };
+    let static_mark = zmachine.header_word(HeaderField::StaticMark)? as usize;
+
+    // Both buffers have two bytes before the text or words: the size and length in V5+,
+    // the size and the byte after the 0 terminator in V3/V4
+    if text_buffer < 0x40 || text_buffer + 2 >= static_mark {
+        return recoverable_error!(
+            ErrorCode::InvalidBuffer,
+            "READ text buffer ${:04x} is outside dynamic memory",
+            text_buffer
+        );
+    }
+    let size = zmachine.read_byte(text_buffer)? as usize;
+    if size == 0 {
+        return recoverable_error!(
+            ErrorCode::InvalidBuffer,
+            "READ text buffer ${:04x} has a size of 0",
+            text_buffer
+        );
+    }
+    let mut len = if zmachine.version() < 5 {
+        size - 1
+    } else {
+        size
+    };
+    if text_buffer + 2 + len > static_mark {
+        warn!(target: "app::input", "READ text buffer ${:04x} of {} characters runs past the end of dynamic memory", text_buffer, len);
+        len = static_mark - text_buffer - 2;
+    }
+
+    if parse == 0 {
+        if zmachine.version() < 5 {
+            warn!(target: "app::input", "READ with no parse buffer, input won't be analysed");
+        }
+        return Ok(len);
+    }
+
+    if parse < 0x40 || parse + 2 >= static_mark {
+        return recoverable_error!(
+            ErrorCode::InvalidBuffer,
+            "READ parse buffer ${:04x} is outside dynamic memory",
+            parse
+        );
+    }
+    let words = zmachine.read_byte(parse)? as usize;
+    let clamped = usize::min(words, (static_mark - parse - 2) / 4);
+    if text_buffer < parse + 2 + (clamped * 4) && parse < text_buffer + 2 + len {
+        return recoverable_error!(
+            ErrorCode::InvalidBuffer,
+            "READ text buffer ${:04x} overlaps parse buffer ${:04x}",
+            text_buffer,
+            parse
+        );
+    }
+
+    if clamped < words {
+        warn!(target: "app::input", "READ parse buffer ${:04x} of {} words runs past the end of dynamic memory, clamping to {} words", parse, words, clamped);
+        zmachine.write_byte(parse, clamped as u8)?;
+    }
+
+    Ok(len)
+}
+
 pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
 
     let text_buffer = operands[0] as usize;
+    let len = check_read_buffers(zmachine, &operands)?;
 
     if let Some(r) = zmachine.read_interrupt_result() {
         zmachine.clear_read_interrupt();
@@ -101,26 +178,13 @@ pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
         }
     }
 
-    let parse = if operands.len() > 1 {
-        operands[1] as usize
-    } else {
-        0
-    };
-
-    let len = if zmachine.version() < 5 {
-        zmachine.read_byte(text_buffer)? - 1
-    } else {
-        zmachine.read_byte(text_buffer)?
-    } as usize;
-
-    let timeout = if operands.len() > 2 { operands[2] } else { 0 };
-    let routine = if timeout > 0 && operands.len() > 2 {
+    // The timeout is in tenths of a second, and is ignored without a routine to call
+    let timeout = if operands.len() > 3 { operands[2] } else { 0 };
+    if timeout > 0 {
         zmachine.set_read_interrupt_pending();
-        zmachine.packed_routine_address(operands[3])?
-    } else {
-        0
-    };
+    }
 
+    zmachine.check_score()?;
     let mut existing_input = Vec::new();
 
     match zmachine.version() {
@@ -151,9 +215,50 @@ pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
     }
 
     zmachine.clear_input_interrupt_print();
+    zmachine.debug_hud()?;
+
+    let terminators = terminators(zmachine)?;
+    match zmachine.request_line(
+        instruction.address(),
+        &operands,
+        &existing_input,
+        len,
+        &terminators,
+        timeout,
+    )? {
+        Some(input_buffer) => complete_read(zmachine, instruction, &operands, input_buffer),
+        // Waiting for input
+        None => Ok(instruction.address()),
+    }
+}
+
+/// Store the input read by READ, or call the interrupt routine if the read was interrupted
+pub fn complete_read(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+    operands: &[u16],
+    input_buffer: Vec<u16>,
+) -> Result<usize, RuntimeError> {
+    // The interpreter menu restarted, restored, or quit the game while the line was read
+    if let Some(pc) = zmachine.take_menu_jump() {
+        return Ok(pc);
+    }
+
+    let text_buffer = operands[0] as usize;
+    let parse = if operands.len() > 1 {
+        operands[1] as usize
+    } else {
+        0
+    };
+
+    let timeout = if operands.len() > 3 { operands[2] } else { 0 };
+    let routine = if timeout > 0 {
+        zmachine.packed_routine_address(operands[3])?
+    } else {
+        0
+    };
 
     let terminators = terminators(zmachine)?;
-    let input_buffer = zmachine.read_line(&existing_input, len, &terminators, timeout * 100)?;
     let terminator = input_buffer.last().filter(|&x| terminators.contains(x));
 
     // If there was no terminator, then input was interrupted
@@ -175,12 +280,10 @@ pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 
         debug!(target: "app::input", "READ interrupted");
 
-        if zmachine.sound_interrupt().is_some() {
-            if !zmachine.is_sound_playing() {
-                debug!(target: "app::input", "Sound interrupt firing");
-                zmachine.clear_read_interrupt();
-                return zmachine.call_sound_interrupt(instruction.address());
-            }
+        if zmachine.sound_interrupt_due() {
+            debug!(target: "app::input", "Sound interrupt firing");
+            zmachine.clear_read_interrupt();
+            return zmachine.call_sound_interrupt(instruction.address());
         } else if routine > 0 {
             debug!(target: "app::input", "Read interrupt firing");
             return zmachine.call_read_interrupt(routine, instruction.address());
@@ -192,6 +295,8 @@ pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
         }
     }
 
+    zmachine.add_turn();
+
     let end = input_buffer.len()
         - match terminator {
             Some(_) => 1,
@@ -218,8 +323,8 @@ pub fn read(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
         }
     }
 
-    // Lexical analysis
-    if parse > 0 || zmachine.version() < 5 {
+    // Lexical analysis, unless there's no parse buffer
+    if parse > 0 {
         let dictionary = zmachine.header_word(HeaderField::Dictionary)? as usize;
         text::parse_text(zmachine, text_buffer, parse, dictionary, false)?;
     }
@@ -282,6 +387,10 @@ pub fn push(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
 
 pub fn pull(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
+    if zmachine.version() == 6 {
+        return pull_v6(zmachine, instruction, &operands);
+    }
+
     let value = zmachine.variable(0)?;
 
     // If pulling to the stack, need to remove what was underneath the
@@ -296,6 +405,27 @@ pub fn pull(zmachine: &mut ZMachine, instruction: &Instruction) -> Result<usize,
     Ok(instruction.next_address())
 }
 
+// V6 PULL stores the value, pulled from the game stack or from the user stack given as an
+// operand
+fn pull_v6(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+    operands: &[u16],
+) -> Result<usize, RuntimeError> {
+    let value = match operands.first() {
+        Some(stack) => {
+            let stack = *stack as usize;
+            let free = zmachine.read_word(stack)? + 1;
+            let value = zmachine.read_word(stack + free as usize * 2)?;
+            zmachine.write_word(stack, free)?;
+            value
+        }
+        None => zmachine.variable(0)?,
+    };
+    store_result(zmachine, instruction, value)?;
+    Ok(instruction.next_address())
+}
+
 pub fn split_window(
     zmachine: &mut ZMachine,
     instruction: &Instruction,
@@ -344,8 +474,9 @@ pub fn erase_line(
     instruction: &Instruction,
 ) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
-    if operands[0] == 1 {
-        zmachine.erase_line()?;
+    // V6 can erase a number of cells rather than to the end of the line
+    if operands[0] == 1 || (zmachine.version() == 6 && operands[0] > 1) {
+        zmachine.erase_line(operands[0])?;
     }
 
     Ok(instruction.next_address())
@@ -356,7 +487,17 @@ pub fn set_cursor(
     instruction: &Instruction,
 ) -> Result<usize, RuntimeError> {
     let operands = operand_values(zmachine, instruction)?;
-    zmachine.set_cursor(operands[0], operands[1])?;
+    if zmachine.version() == 6 {
+        // Rows -1 and -2 hide and show the cursor, which is always shown
+        if (operands[0] as i16) < 0 {
+            debug!(target: "app::screen", "SET_CURSOR {}: ignored", operands[0] as i16);
+        } else {
+            let window = operands.get(2).map_or(-3, |w| *w as i16);
+            zmachine.set_window_cursor(window, operands[0], operands[1])?;
+        }
+    } else {
+        zmachine.set_cursor(operands[0], operands[1])?;
+    }
     Ok(instruction.next_address())
 }
 
@@ -409,8 +550,8 @@ pub fn input_stream(
     zmachine: &mut ZMachine,
     instruction: &Instruction,
 ) -> Result<usize, RuntimeError> {
-    let _operands = operand_values(zmachine, instruction)?;
-    info!(target: "app::instruction", "INPUT_STREAM not implemented, instruction ignored");
+    let operands = operand_values(zmachine, instruction)?;
+    zmachine.input_stream(operands[0])?;
     Ok(instruction.next_address())
 }
 
@@ -478,15 +619,33 @@ pub fn read_char(
         }
     }
 
-    let timeout = if operands.len() > 1 { operands[1] } else { 0 };
-    let routine = if timeout > 0 && operands.len() > 2 {
+    // The timeout is in tenths of a second, and is ignored without a routine to call
+    let timeout = if operands.len() > 2 { operands[1] } else { 0 };
+    if timeout > 0 {
         zmachine.set_read_interrupt_pending();
+    }
+
+    match zmachine.request_key(instruction.address(), &operands, timeout)? {
+        Some(key) => complete_read_char(zmachine, instruction, &operands, key),
+        // Waiting for input
+        None => Ok(instruction.address()),
+    }
+}
+
+/// Store the key read by READ_CHAR, or call the interrupt routine if the read was interrupted
+pub fn complete_read_char(
+    zmachine: &mut ZMachine,
+    instruction: &Instruction,
+    operands: &[u16],
+    key: InputEvent,
+) -> Result<usize, RuntimeError> {
+    let timeout = if operands.len() > 2 { operands[1] } else { 0 };
+    let routine = if timeout > 0 {
         zmachine.packed_routine_address(operands[2])?
     } else {
         0
     };
 
-    let key = zmachine.read_key(timeout * 100)?;
     match key.zchar() {
         Some(c) => {
             store_result(zmachine, instruction, c)?;
@@ -705,9 +864,11 @@ mod tests {
 
     use crate::{
         assert_ok_eq, assert_print, assert_some_eq,
+        error::ErrorCode,
         instruction::{processor::dispatch, Opcode, OpcodeForm, OperandCount, OperandType},
         object::property,
         test_util::*,
+        zmachine::ZMachine,
     };
 
     fn opcode(version: u8, instruction: u8) -> Opcode {
@@ -808,6 +969,29 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0), 1);
     }
 
+    #[test]
+    fn test_call_vs_v8_high_memory() {
+        let mut map = test_map(8);
+        map.resize(0x30000, 0);
+        mock_routine(&mut map, 0x24000, &[1, 2]);
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_store_instruction(
+            0x401,
+            vec![
+                operand(OperandType::LargeConstant, 0x4800),
+                operand(OperandType::SmallConstant, 0x12),
+            ],
+            opcode(8, 0),
+            0x406,
+            store(0x405, 0x80),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x24001);
+        assert_ok_eq!(zmachine.variable(1), 0x12);
+        assert_ok_eq!(zmachine.variable(2), 0);
+        assert_ok_eq!(zmachine.return_routine(0xF0AD), 0x406);
+        assert_ok_eq!(zmachine.variable(0x80), 0xF0AD);
+    }
+
     #[test]
     fn test_call_vs_v5() {
         let mut map = test_map(5);
@@ -1403,6 +1587,141 @@ mod tests {
         assert_ok_eq!(zmachine.read_byte(0x3A1), 0);
     }
 
+    fn dynamic_memory(zmachine: &ZMachine) -> Vec<u8> {
+        (0..0x400).map(|a| zmachine.read_byte(a).unwrap()).collect()
+    }
+
+    // READ with a text and parse buffer that should fail with InvalidBuffer, leaving memory
+    // alone
+    fn assert_read_invalid(map: Vec<u8>, text: u16, parse: u16) {
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_instruction(
+            0x400,
+            vec![
+                operand(OperandType::LargeConstant, text),
+                operand(OperandType::LargeConstant, parse),
+            ],
+            opcode(3, 4),
+            0x405,
+        );
+        input(&['I', 'n', 'v', 'e', 'n', 't', 'o', 'r', 'y']);
+        let memory = dynamic_memory(&zmachine);
+        let e = dispatch(&mut zmachine, &i).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::InvalidBuffer);
+        assert_eq!(dynamic_memory(&zmachine), memory);
+    }
+
+    #[test]
+    fn test_sread_v3_no_parse() {
+        let mut map = test_map(3);
+        mock_dictionary(&mut map);
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_instruction(
+            0x400,
+            vec![
+                operand(OperandType::LargeConstant, 0x380),
+                operand(OperandType::LargeConstant, 0),
+            ],
+            opcode(3, 4),
+            0x405,
+        );
+
+        input(&['I', 'n', 'v', 'e', 'n', 't', 'o', 'r', 'y']);
+        capture_log("app::input");
+        let header = dynamic_memory(&zmachine)[0..0x40].to_vec();
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x405);
+        assert!(log().contains(&"READ with no parse buffer, input won't be analysed".to_string()));
+        assert_ok_eq!(zmachine.read_byte(0x381), b'i');
+        assert_ok_eq!(zmachine.read_byte(0x38a), 0);
+        // Nothing was written to the header
+        assert_eq!(dynamic_memory(&zmachine)[0..0x40], header);
+    }
+
+    #[test]
+    fn test_sread_overlapping_buffers() {
+        let mut map = test_map(3);
+        mock_dictionary(&mut map);
+        // The parse buffer starts in the text buffer
+        assert_read_invalid(map.clone(), 0x380, 0x388);
+        // and the text buffer in the parse buffer
+        map[0x3A8] = 4;
+        assert_read_invalid(map, 0x3A8, 0x3A0);
+    }
+
+    #[test]
+    fn test_sread_buffers_in_header() {
+        let mut map = test_map(3);
+        mock_dictionary(&mut map);
+        assert_read_invalid(map.clone(), 0x380, 0x10);
+        assert_read_invalid(map, 0x20, 0x3A0);
+    }
+
+    #[test]
+    fn test_sread_buffers_past_dynamic_memory() {
+        let mut map = test_map(3);
+        mock_dictionary(&mut map);
+        assert_read_invalid(map.clone(), 0x3FE, 0x3A0);
+        assert_read_invalid(map, 0x380, 0x400);
+    }
+
+    #[test]
+    fn test_sread_text_buffer_size_0() {
+        let mut map = test_map(3);
+        mock_dictionary(&mut map);
+        map[0x380] = 0;
+        assert_read_invalid(map, 0x380, 0x3A0);
+    }
+
+    fn aread(map: Vec<u8>, text: u16, parse: u16) -> ZMachine {
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_store_instruction(
+            0x400,
+            vec![
+                operand(OperandType::LargeConstant, text),
+                operand(OperandType::LargeConstant, parse),
+            ],
+            opcode(5, 4),
+            0x406,
+            store(0x405, 0x80),
+        );
+        input(&[
+            'a', ' ', 'b', ' ', 'c', ' ', 'd', ' ', 'e', ' ', 'f', ' ', 'g', ' ', 'h', ' ', 'i',
+        ]);
+        capture_log("app::input");
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x406);
+        zmachine
+    }
+
+    #[test]
+    fn test_aread_v5_clamp_text_buffer() {
+        let mut map = test_map(5);
+        mock_dictionary(&mut map);
+        // A text buffer for 100 characters with room for 14
+        map[0x3F0] = 100;
+        let zmachine = aread(map, 0x3F0, 0x3A0);
+        assert_ok_eq!(zmachine.read_byte(0x3F0), 100);
+        assert_ok_eq!(zmachine.read_byte(0x3F1), 14);
+        assert_ok_eq!(zmachine.read_byte(0x3FE), b'g');
+        assert!(log().contains(
+            &"READ text buffer $03f0 of 100 characters runs past the end of dynamic memory"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn test_aread_v5_clamp_parse_buffer() {
+        let mut map = test_map(5);
+        mock_dictionary(&mut map);
+        // A parse buffer for 10 words with room for 3
+        map[0x380] = 20;
+        map[0x3F0] = 10;
+        let zmachine = aread(map, 0x380, 0x3F0);
+        assert_ok_eq!(zmachine.read_byte(0x381), 17);
+        assert_ok_eq!(zmachine.read_byte(0x3F0), 3);
+        assert_ok_eq!(zmachine.read_byte(0x3F1), 3);
+        assert!(log().contains(&"READ parse buffer $03f0 of 10 words runs past the end of dynamic memory, clamping to 3 words".to_string()));
+    }
+
     #[test]
     fn test_aread_v5_interrupt() {
         let mut map = test_map(5);
@@ -1541,6 +1860,53 @@ mod tests {
         assert_ok_eq!(zmachine.read_byte(0x3A1), 0);
     }
 
+    #[test]
+    fn test_aread_v5_interrupt_nested_read_char() {
+        let mut map = test_map(5);
+        set_variable(&mut map, 0x80, 0xFF);
+        mock_dictionary(&mut map);
+        mock_routine(&mut map, 0x600, &[0x1234, 0x5678]);
+        let mut zmachine = mock_zmachine(map);
+        // Read with a 3 second timeout
+        let i = mock_store_instruction(
+            0x400,
+            vec![
+                operand(OperandType::LargeConstant, 0x380),
+                operand(OperandType::LargeConstant, 0x3A0),
+                operand(OperandType::LargeConstant, 30),
+                operand(OperandType::LargeConstant, 0x180),
+            ],
+            opcode(5, 4),
+            0x409,
+            store(0x408, 0x80),
+        );
+
+        input(&['I', 'n', 'v', 'e', 'n', 't', 'o', 'r', 'y']);
+        set_input_delay(501);
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x601);
+
+        // The timer routine asks a question of its own with READ_CHAR
+        let read_char = mock_store_instruction(
+            0x601,
+            vec![operand(OperandType::SmallConstant, 1)],
+            opcode(5, 22),
+            0x604,
+            store(0x603, 0x01),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &read_char), 0x604);
+        assert_ok_eq!(zmachine.variable(0x01), b'o' as u16);
+        assert_eq!(zmachine.frame_count(), 2);
+
+        // and returns true, so the READ stops
+        assert_ok_eq!(zmachine.return_routine(1), 0x400);
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x409);
+        assert_ok_eq!(zmachine.variable(0x80), 0);
+        assert_ok_eq!(zmachine.read_byte(0x381), 0);
+        assert_ok_eq!(zmachine.read_byte(0x3A1), 0);
+        assert!(!zmachine.read_interrupt_pending());
+        assert!(zmachine.read_interrupt_result().is_none());
+    }
+
     #[test]
     fn test_print_char() {
         let map = test_map(3);
@@ -1552,6 +1918,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("@");
     }
 
@@ -1566,6 +1933,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("32767");
     }
 
@@ -1580,6 +1948,7 @@ mod tests {
             0x403,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+        assert!(zmachine.flush().is_ok());
         assert_print!("-32768");
     }
 
@@ -1955,7 +2324,7 @@ mod tests {
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
         assert_eq!(split(), 0);
-        assert_eq!(erase_window(), [0]);
+        assert_eq!(erase_window(), [-1]);
     }
 
     #[test]
@@ -2138,16 +2507,53 @@ mod tests {
     }
 
     #[test]
-    fn test_input_stream_nop() {
+    fn test_input_stream_0() {
         let map = test_map(3);
         let mut zmachine = mock_zmachine(map);
         let i = mock_instruction(
             0x400,
-            vec![operand(OperandType::SmallConstant, 2)],
+            vec![operand(OperandType::SmallConstant, 0)],
+            opcode(4, 20),
+            0x402,
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
+    }
+
+    #[test]
+    fn test_input_stream_1() {
+        let map = test_map(3);
+        let mut zmachine = mock_zmachine(map);
+        zmachine.push_commands(&["look".to_string()]);
+        let i = mock_instruction(
+            0x400,
+            vec![operand(OperandType::SmallConstant, 1)],
             opcode(4, 20),
             0x402,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x402);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 10, &['\r' as u16], 0),
+            [
+                b'l' as u16,
+                b'o' as u16,
+                b'o' as u16,
+                b'k' as u16,
+                b'\r' as u16
+            ]
+        );
+    }
+
+    #[test]
+    fn test_input_stream_invalid() {
+        let map = test_map(3);
+        let mut zmachine = mock_zmachine(map);
+        let i = mock_instruction(
+            0x400,
+            vec![operand(OperandType::SmallConstant, 2)],
+            opcode(4, 20),
+            0x402,
+        );
+        assert!(dispatch(&mut zmachine, &i).is_err());
     }
 
     #[test]
@@ -3047,6 +3453,7 @@ mod tests {
             0x405,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x405);
+        assert!(zmachine.flush().is_ok());
         assert_print!("abcdefghabcdefghabcdefghabcdefghabcdefghabcdefghabcdefghabcdefgh");
         assert_ok_eq!(zmachine.cursor(), (12, 16));
     }
@@ -3074,6 +3481,7 @@ mod tests {
             0x406,
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x406);
+        assert!(zmachine.flush().is_ok());
         assert_print!("abcdabcdabcdabcdabcdabcdabcdabcd");
         assert_ok_eq!(zmachine.cursor(), (12, 12));
     }
@@ -3084,7 +3492,7 @@ mod tests {
         mock_routine(&mut map, 0x600, &[1, 2, 3, 4]);
         let mut zmachine = mock_zmachine(map);
         assert!(zmachine
-            .call_routine(0x600, &vec![0x1122, 0x2233], None, 0x400)
+            .call_routine(0x600, &[0x1122, 0x2233], None, 0x400)
             .is_ok());
         let i = mock_branch_instruction(
             0x400,
@@ -3102,7 +3510,7 @@ mod tests {
         mock_routine(&mut map, 0x600, &[1, 2, 3, 4]);
         let mut zmachine = mock_zmachine(map);
         assert!(zmachine
-            .call_routine(0x600, &vec![0x1122, 0x2233], None, 0x400)
+            .call_routine(0x600, &[0x1122, 0x2233], None, 0x400)
             .is_ok());
         let i = mock_branch_instruction(
             0x400,
@@ -3113,4 +3521,49 @@ mod tests {
         );
         assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
     }
+
+    #[test]
+    fn test_check_arg_count_zero() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[1, 2, 3, 4]);
+        let mut zmachine = mock_zmachine(map);
+        assert!(zmachine.call_routine(0x600, &[], None, 0x400).is_ok());
+        let i = mock_branch_instruction(
+            0x400,
+            vec![operand(OperandType::SmallConstant, 0)],
+            opcode(5, 31),
+            0x403,
+            branch(0x402, true, 0x40a),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x40a);
+        let i = mock_branch_instruction(
+            0x400,
+            vec![operand(OperandType::SmallConstant, 1)],
+            opcode(5, 31),
+            0x403,
+            branch(0x402, true, 0x40a),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x403);
+    }
+
+    #[test]
+    fn test_check_arg_count_read_interrupt() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[1, 2, 3, 4]);
+        mock_routine(&mut map, 0x700, &[]);
+        let mut zmachine = mock_zmachine(map);
+        assert!(zmachine
+            .call_routine(0x600, &[0x1122, 0x2233], None, 0x400)
+            .is_ok());
+        zmachine.set_read_interrupt_pending();
+        assert!(zmachine.call_read_interrupt(0x700, 0x609).is_ok());
+        let i = mock_branch_instruction(
+            0x701,
+            vec![operand(OperandType::SmallConstant, 1)],
+            opcode(5, 31),
+            0x704,
+            branch(0x703, true, 0x70b),
+        );
+        assert_ok_eq!(dispatch(&mut zmachine, &i), 0x704);
+    }
 }
@@ -0,0 +1,175 @@
+use std::{fmt, io::Write};
+
+use super::{Instruction, OperandType, StoreResult};
+
+/// Receives the instructions the interpreter executes, set with
+/// [`ZMachine::set_trace_sink`](crate::zmachine::ZMachine::set_trace_sink).  Only instructions
+/// admitted by the instruction log filter are traced; the operand values and stored result
+/// for an instruction follow the instruction itself.
+///
+/// Like an [`EventListener`](crate::zmachine::event::EventListener), the sink is called
+/// while the interpreter is mutably borrowed and can't call back into it.
+pub trait TraceSink: fmt::Debug {
+    /// Is anything traced at all?  Nothing is sent to an inactive sink.
+    fn active(&self) -> bool {
+        true
+    }
+
+    /// `instruction` is about to execute
+    fn instruction(&mut self, instruction: &Instruction);
+
+    /// The values of the operands of `instruction`, in order, once variables have been read
+    fn operands(&mut self, instruction: &Instruction, values: &[u16]);
+
+    /// `value` was stored as the result of the instruction
+    fn store(&mut self, store: &StoreResult, value: u16);
+
+    /// Write out anything buffered.  Called each time `advance` returns.
+    fn flush(&mut self) {}
+}
+
+// Operand values as hex, small constants as a byte
+fn values(instruction: &Instruction, values: &[u16]) -> String {
+    let mut s = String::new();
+    for (o, value) in instruction.operands().iter().zip(values) {
+        match o.operand_type() {
+            OperandType::SmallConstant => s.push_str(&format!(" #{:02x}", *value as u8)),
+            _ => s.push_str(&format!(" #{:04x}", value)),
+        }
+    }
+    s
+}
+
+/// The instruction log: each instruction is written to the `app::instruction` log at debug
+/// level, when it's enabled
+#[derive(Debug, Default)]
+pub struct LogSink;
+
+impl TraceSink for LogSink {
+    fn active(&self) -> bool {
+        log_enabled!(target: "app::instruction", log::Level::Debug)
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        debug!(target: "app::instruction", "dispatch: {}", instruction);
+    }
+
+    fn operands(&mut self, instruction: &Instruction, values: &[u16]) {
+        if !values.is_empty() {
+            debug!(target: "app::instruction", "Operand values: {}", self::values(instruction, values));
+        }
+    }
+
+    fn store(&mut self, store: &StoreResult, value: u16) {
+        debug!(target: "app::instruction", "Store result: {} = #{:04x}", store, value);
+    }
+}
+
+/// A plain-text trace, one line per instruction, operand values, and stored result.  The
+/// lines don't depend on timing or the terminal, so traces of the same game played with
+/// the same input and random seed can be diffed.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    // Stop writing after the first error, rather than failing every instruction
+    failed: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        TraceWriter {
+            writer,
+            failed: false,
+        }
+    }
+
+    fn write_line(&mut self, line: fmt::Arguments) {
+        if !self.failed {
+            if let Err(e) = writeln!(self.writer, "{}", line) {
+                error!(target: "app::trace", "Error writing trace: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+impl<W: Write> fmt::Debug for TraceWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceWriter")
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn active(&self) -> bool {
+        !self.failed
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        self.write_line(format_args!("{}", instruction));
+    }
+
+    fn operands(&mut self, instruction: &Instruction, values: &[u16]) {
+        if !values.is_empty() {
+            let values = self::values(instruction, values);
+            self.write_line(format_args!("  values:{}", values));
+        }
+    }
+
+    fn store(&mut self, store: &StoreResult, value: u16) {
+        self.write_line(format_args!("  store: {} = #{:04x}", store, value));
+    }
+
+    fn flush(&mut self) {
+        if !self.failed {
+            if let Err(e) = self.writer.flush() {
+                error!(target: "app::trace", "Error writing trace: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert_ok, assert_some,
+        instruction::decoder,
+        test_util::{test_map, FullDiskWriter},
+        zmachine::state::memory::Memory,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_trace_writer() {
+        let mut map = test_map(5);
+        // ADD #01 G00 -> -(SP)
+        map[0x400..0x404].copy_from_slice(&[0x34, 0x01, 0x10, 0x00]);
+        let memory = Memory::new(map);
+        let instruction = assert_ok!(decoder::decode_memory(&memory, 5, 0x400));
+        let mut writer = TraceWriter::new(Vec::new());
+        assert!(writer.active());
+        writer.instruction(&instruction);
+        writer.operands(&instruction, &[1, 0x1234]);
+        writer.store(assert_some!(instruction.store()), 0x1235);
+        assert_eq!(
+            String::from_utf8_lossy(&writer.writer),
+            "$00400: 34 01 10 00  ADD #01 G00 -> -(SP)\n  values: #01 #1234\n  store: -(SP) = #1235\n"
+        );
+    }
+
+    #[test]
+    fn test_trace_writer_error() {
+        let mut map = test_map(5);
+        map[0x400] = 0xB0;
+        let memory = Memory::new(map);
+        let instruction = assert_ok!(decoder::decode_memory(&memory, 5, 0x400));
+        let (full, _, calls) = FullDiskWriter::new(0);
+        let mut writer = TraceWriter::new(full);
+        writer.instruction(&instruction);
+        assert!(!writer.active());
+        // Nothing more is written after the error
+        writer.instruction(&instruction);
+        writer.flush();
+        assert_eq!(calls.get(), 1);
+    }
+}
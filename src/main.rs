@@ -3,9 +3,10 @@
 extern crate log;
 
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
 use std::panic;
+use std::path::Path;
 use std::process::exit;
 
 pub mod blorb;
@@ -13,8 +14,10 @@ pub mod config;
 pub mod error;
 pub mod files;
 pub mod instruction;
+pub mod mdc;
 pub mod object;
 pub mod quetzal;
+pub mod script;
 pub mod sound;
 pub mod text;
 pub mod zmachine;
@@ -25,37 +28,63 @@ pub mod test_util;
 use crate::config::Config;
 use crate::log::*;
 use blorb::Blorb;
-use sound::Manager;
+use instruction::disassembler;
+use instruction::trace::TraceWriter;
+use script::Step;
+use sound::{Manager, SoundResource};
+use zmachine::io::screen::capture::CaptureBuffer;
+use zmachine::messages::Messages;
+use zmachine::state::frame::format_backtrace;
 use zmachine::state::memory::Memory;
+use zmachine::verify::verify_story;
 use zmachine::ZMachine;
 
-fn initialize_sound_engine(
-    memory: &Memory,
-    volume_factor: f32,
-    blorb: Option<Blorb>,
-) -> Option<Manager> {
-    if let Some(blorb) = blorb {
-        if let Some(ifhd) = blorb.ifhd() {
-            // TODO: Refactor this when adding Exec chunk support
-            let release = memory.read_word(0x02).unwrap();
-            let checksum = memory.read_word(0x1C).unwrap();
-            let serial = [
-                memory.read_byte(0x12).unwrap(),
-                memory.read_byte(0x13).unwrap(),
-                memory.read_byte(0x14).unwrap(),
-                memory.read_byte(0x15).unwrap(),
-                memory.read_byte(0x16).unwrap(),
-                memory.read_byte(0x17).unwrap(),
-            ]
-            .to_vec();
-            if release != ifhd.release_number()
-                || checksum != ifhd.checksum()
-                || &serial != ifhd.serial_number()
-            {
-                error!(target: "app::sound", "Resource file does not match the game");
-                return None;
-            }
+// Configure logging from log4rs.yml, returning true if it was found
+#[cfg(feature = "log4rs-mdc")]
+fn initialize_logging() -> bool {
+    if let Some(filename) = files::config_file("log4rs.yml") {
+        if log4rs::init_file(filename, Default::default()).is_ok() {
+            mdc::insert("instruction_count", || format!("{:8x}", 0));
+        }
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(not(feature = "log4rs-mdc"))]
+fn initialize_logging() -> bool {
+    false
+}
+
+// Does the Blorb file's IFhd chunk, if it has one, match the game?
+fn blorb_matches(memory: &Memory, blorb: &Blorb) -> bool {
+    if let Some(ifhd) = blorb.ifhd() {
+        // TODO: Refactor this when adding Exec chunk support
+        let release = memory.read_word(0x02).unwrap();
+        let checksum = memory.read_word(0x1C).unwrap();
+        let serial = [
+            memory.read_byte(0x12).unwrap(),
+            memory.read_byte(0x13).unwrap(),
+            memory.read_byte(0x14).unwrap(),
+            memory.read_byte(0x15).unwrap(),
+            memory.read_byte(0x16).unwrap(),
+            memory.read_byte(0x17).unwrap(),
+        ]
+        .to_vec();
+        if release != ifhd.release_number()
+            || checksum != ifhd.checksum()
+            || &serial != ifhd.serial_number()
+        {
+            error!(target: "app::resource", "Resource file does not match the game");
+            return false;
         }
+    }
+    true
+}
+
+fn initialize_sound_engine(volume_factor: f32, blorb: Option<Blorb>) -> Option<Manager> {
+    if let Some(blorb) = blorb {
         match Manager::new(volume_factor, blorb) {
             Ok(m) => Some(m),
             Err(e) => {
@@ -69,6 +98,33 @@ fn initialize_sound_engine(
 }
 
 fn initialize_config() -> Config {
+    let mut config = load_config();
+    config.set_messages(load_messages());
+    config
+}
+
+// Interpreter messages from messages.yml, in English if there isn't one
+fn load_messages() -> Messages {
+    if let Some(filename) = files::config_file("messages.yml") {
+        match File::open(&filename) {
+            Ok(f) => match Messages::try_from(f) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    info!(target: "app::trace", "Error parsing messages from {}: {}", filename, e);
+                    Messages::default()
+                }
+            },
+            Err(e) => {
+                info!(target: "app::trace", "Error reading messages from {}: {}", filename, e);
+                Messages::default()
+            }
+        }
+    } else {
+        Messages::default()
+    }
+}
+
+fn load_config() -> Config {
     if let Some(filename) = files::config_file("config.yml") {
         match File::open(&filename) {
             Ok(f) => match Config::try_from(f) {
@@ -88,34 +144,189 @@ fn initialize_config() -> Config {
     }
 }
 
+// Play a walkthrough, then print the lower window output or compare it with the expected
+// output.  Returns the exit code.
+fn play_script(
+    zmachine: &mut ZMachine,
+    steps: Vec<Step>,
+    capture: &CaptureBuffer,
+    expect: Option<&String>,
+) -> i32 {
+    if let Err(e) = script::play(zmachine, steps) {
+        println!("{}", e);
+        for line in format_backtrace(&zmachine.backtrace()) {
+            println!("{}", line);
+        }
+        let _ = zmachine.quit();
+        return 1;
+    }
+
+    let output = script::normalize(&script::output(zmachine, capture));
+    match expect {
+        Some(filename) => match fs::read_to_string(filename) {
+            Ok(golden) => {
+                match script::unified_diff(&script::normalize(&golden), &output, filename, "output")
+                {
+                    Some(diff) => {
+                        print!("{}", diff);
+                        1
+                    }
+                    None => 0,
+                }
+            }
+            Err(e) => {
+                println!("Error reading {}: {}", filename, e);
+                1
+            }
+        },
+        None => {
+            for line in output {
+                println!("{}", line);
+            }
+            0
+        }
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+    // Options may appear anywhere on the command line; the first other argument is the game file
+    let mut predictable_seed = None;
+    let mut capture = None;
+    let mut trace = None;
+    let mut script = None;
+    let mut expect = None;
+    let mut transcript = None;
+    let mut list_resources = false;
+    let mut check = false;
+    let mut dump_strings = false;
+    let mut disassemble = false;
+    let mut blorb_file = None;
+    let mut files = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--predictable" {
+            match iter.next().and_then(|s| s.parse::<u16>().ok()) {
+                Some(seed) => predictable_seed = Some(seed),
+                None => {
+                    println!("--predictable requires a seed between 0 and 65535");
+                    exit(2);
+                }
+            }
+        } else if arg == "--list-resources" {
+            list_resources = true;
+        } else if arg == "--check" {
+            check = true;
+        } else if arg == "--dump-strings" {
+            dump_strings = true;
+        } else if arg == "--disassemble" {
+            disassemble = true;
+        } else if arg == "--transcript" {
+            match iter.next() {
+                Some(filename) => transcript = Some(filename),
+                None => {
+                    println!("--transcript requires a file name");
+                    exit(2);
+                }
+            }
+        } else if arg == "--blorb" {
+            match iter.next() {
+                Some(filename) => blorb_file = Some(filename.as_str()),
+                None => {
+                    println!("--blorb requires a file name");
+                    exit(2);
+                }
+            }
+        } else if arg == "--script" {
+            match iter.next() {
+                Some(filename) => script = Some(filename),
+                None => {
+                    println!("--script requires a file name");
+                    exit(2);
+                }
+            }
+        } else if arg == "--expect" {
+            match iter.next() {
+                Some(filename) => expect = Some(filename),
+                None => {
+                    println!("--expect requires a file name");
+                    exit(2);
+                }
+            }
+        } else if arg == "--trace" {
+            match iter.next() {
+                Some(filename) => trace = Some(filename),
+                None => {
+                    println!("--trace requires a file name");
+                    exit(2);
+                }
+            }
+        } else if arg == "--capture" {
+            match iter.next() {
+                Some(filename) => capture = Some(filename),
+                None => {
+                    println!("--capture requires a file name");
+                    exit(2);
+                }
+            }
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        println!("Usage: mxyzptlk [--predictable <seed>] [--capture <file>] [--trace <file>] [--transcript <file>] [--blorb <file>] [--script <walkthrough> [--expect <golden file>]] [--list-resources] [--check] [--dump-strings] [--disassemble] <game file | ->");
+        exit(2);
+    }
+    if expect.is_some() && script.is_none() {
+        println!("--expect requires --script");
+        exit(2);
+    }
+    let steps = script.map(|filename| {
+        match fs::read_to_string(filename)
+            .map_err(|e| e.to_string())
+            .and_then(|text| script::parse_walkthrough(&text).map_err(|e| e.message().to_string()))
+        {
+            Ok(steps) => steps,
+            Err(e) => {
+                println!("Error reading {}: {}", filename, e);
+                exit(1);
+            }
+        }
+    });
+    let filename = files[0];
+    // A filename of "-" reads the game (or Blorb) from stdin
+    let stdin = filename == "-";
     // full_name includes any path info and will be used to look for Blorb resources
     // co-located with the game file
     let full_name = filename.split('.').collect::<Vec<&str>>()[0].to_string();
-    let name = full_name
-        .split('/')
-        .collect::<Vec<&str>>()
-        .last()
-        .unwrap()
-        .to_string();
-    let config = initialize_config();
-
-    if config.logging() {
-        if let Some(filename) = files::config_file("log4rs.yml") {
-            if log4rs::init_file(filename, Default::default()).is_ok() {
-                log_mdc::insert("instruction_count", format!("{:8x}", 0));
-            }
-
-            info!(target: "app::instruction", "Start instruction log for '{}'", name);
-            info!(target: "app::resource", "Start resource log for '{}'", name);
-            info!(target: "app::screen", "Start screen log for '{}'", name);
-            info!(target: "app::sound", "Start sound log for '{}'", name);
-            info!(target: "app::state", "Start state log for '{}'", name);
-            info!(target: "app::stream", "Start stream log for '{}'", name);
-            info!(target: "app::state", "Configuration: {:?}", config);
-        }
+    let name = if stdin {
+        "stdin".to_string()
+    } else {
+        full_name
+            .split('/')
+            .collect::<Vec<&str>>()
+            .last()
+            .unwrap()
+            .to_string()
+    };
+    let mut config = initialize_config();
+    if let Some(dir) = files::default_config_dir() {
+        config.set_config_dir(&dir);
+    }
+    if let Some(seed) = predictable_seed {
+        config.set_predictable_seed(seed);
+    }
+    if let Some(filename) = capture {
+        config.set_capture(filename);
+    }
+    if config.logging() && initialize_logging() {
+        info!(target: "app::instruction", "Start instruction log for '{}'", name);
+        info!(target: "app::resource", "Start resource log for '{}'", name);
+        info!(target: "app::screen", "Start screen log for '{}'", name);
+        info!(target: "app::sound", "Start sound log for '{}'", name);
+        info!(target: "app::state", "Start state log for '{}'", name);
+        info!(target: "app::stream", "Start stream log for '{}'", name);
+        info!(target: "app::state", "Configuration: {:?}", config);
     }
 
     let prev = panic::take_hook();
@@ -132,34 +343,50 @@ fn main() {
     }));
 
     let mut data = Vec::new();
-    match File::open(filename) {
-        Ok(mut f) => match f.read_to_end(&mut data) {
-            Ok(_) => {}
+    if stdin {
+        if let Err(e) = io::stdin().read_to_end(&mut data) {
+            error!(target: "app::trace", "Error reading stdin: {}", e);
+            println!("Error reading stdin");
+            exit(1);
+        }
+    } else {
+        match File::open(filename) {
+            Ok(mut f) => match f.read_to_end(&mut data) {
+                Ok(_) => {}
+                Err(e) => {
+                    error!(target: "app::trace", "Error reading {}: {}", filename, e);
+                    println!("Error reading {}", filename);
+                    exit(1);
+                }
+            },
             Err(e) => {
                 error!(target: "app::trace", "Error reading {}: {}", filename, e);
                 println!("Error reading {}", filename);
-                exit(-1);
+                exit(1);
             }
-        },
-        Err(e) => {
-            error!(target: "app::trace", "Error reading {}: {}", filename, e);
-            println!("Error reading {}", filename);
-            exit(-1);
         }
     }
 
-    let blorb = if data[0..4] == [b'F', b'O', b'R', b'M'] {
+    if data.len() < 4 {
+        println!("Error reading {}: file is too short", filename);
+        exit(1);
+    }
+
+    // The game file's data is handed to the Blorb when it is one, otherwise kept as zcode
+    let is_blorb = data[0..4] == [b'F', b'O', b'R', b'M'];
+    let (mut blorb, data) = if is_blorb {
         info!(target: "app::trace", "Reading Blorb");
-        match Blorb::try_from(data.clone()) {
-            Ok(blorb) => Some(blorb),
+        match Blorb::try_from(data) {
+            Ok(blorb) => (Some(blorb), None),
             Err(e) => {
                 error!(target: "app::trace", "Error reading blorb {}: {}", filename, e);
-                exit(-1);
+                exit(1);
             }
         }
-    } else if let Some(filename) = files::find_existing(&full_name, &["blorb", "blb"]) {
-        info!(target: "app::sound", "Resource file: {}", filename);
-        match File::open(&filename) {
+    } else if let Some(filename) =
+        files::find_blorb((!stdin).then_some(full_name.as_str()), blorb_file)
+    {
+        let blorb = match File::open(&filename) {
             Ok(mut f) => match Blorb::try_from(&mut f) {
                 Ok(blorb) => Some(blorb),
                 Err(e) => {
@@ -171,38 +398,141 @@ fn main() {
                 error!(target: "app::trace", "Error opening blorb {}: {}", filename, e);
                 None
             }
-        }
+        };
+        (blorb, Some(data))
     } else {
-        None
+        (None, Some(data))
     };
 
-    let zcode = match &blorb {
-        Some(b) => match b.exec() {
-            Some(d) => d.clone(),
-            None => {
-                if data[0] == b'F' {
-                    error!(target: "app::trace", "No Exec chunk in blorb {}", filename);
-                    exit(-1);
-                } else {
-                    data
+    if list_resources {
+        match &blorb {
+            Some(b) => {
+                for resource in Vec::<SoundResource>::from(b) {
+                    println!("{}", resource);
                 }
             }
-        },
-        None => data,
+            None => println!("No resources found for {}", filename),
+        }
+        exit(0);
+    }
+
+    // Exec data from a Blorb is moved out, leaving the sound resources behind
+    let zcode = match blorb.as_mut().and_then(|b| b.take_exec()).or(data) {
+        Some(zcode) => zcode,
+        None => {
+            error!(target: "app::trace", "No Exec chunk in blorb {}", filename);
+            exit(1);
+        }
     };
 
+    if check {
+        // Report on the story file and exit without starting the terminal
+        let report = verify_story(&zcode);
+        println!("{}", report);
+        exit(
+            if report.checksum_matches() && report.problems().is_empty() {
+                0
+            } else {
+                1
+            },
+        );
+    }
+
     let memory = Memory::new(zcode);
-    let sound_manager = initialize_sound_engine(&memory, config.volume_factor(), blorb);
-    let mut zmachine =
-        ZMachine::new(memory, config, sound_manager, &name).expect("Error creating state");
+    if dump_strings {
+        // List the strings in high memory, one per line, and exit
+        match text::strings(&memory) {
+            Ok(strings) => {
+                for (address, text) in strings {
+                    println!("${:05x}: {}", address, text.replace('\n', "\\n"));
+                }
+                exit(0);
+            }
+            Err(e) => {
+                println!("Error reading strings from {}: {}", filename, e);
+                exit(1);
+            }
+        }
+    }
+    if disassemble {
+        // List the routines in high memory and exit
+        match disassembler::disassemble_story(&memory) {
+            Ok(listing) => {
+                print!("{}", listing);
+                if let Some(reason) = listing.stopped() {
+                    println!("\nStopped at ${:05x}: {}", listing.end(), reason);
+                }
+                exit(0);
+            }
+            Err(e) => {
+                println!("Error disassembling {}: {}", filename, e);
+                exit(1);
+            }
+        }
+    }
+
+    // Resources made for a different game are ignored
+    let blorb = blorb.filter(|b| blorb_matches(&memory, b));
+    let pictures = blorb.as_ref().map(Blorb::picture_index);
+    let sound_manager = initialize_sound_engine(config.volume_factor(), blorb);
+    // A script is played without a terminal, capturing the screen in memory
+    let started = match steps {
+        Some(_) => script::headless(memory, config, sound_manager, &name)
+            .map(|(zmachine, capture)| (zmachine, Some(capture))),
+        None => ZMachine::new(memory, config, sound_manager, &name).map(|z| (z, None)),
+    };
+    let (mut zmachine, capture) = match started {
+        Ok(started) => started,
+        Err(e) => {
+            error!(target: "app::trace", "Error starting {}: {}", filename, e);
+            println!("Error starting {}: {}", filename, e.message());
+            exit(1);
+        }
+    };
+    zmachine.set_event_listener(Box::new(|event| info!(target: "app::state", "{:?}", event)));
+    if let Some(pictures) = pictures {
+        zmachine.set_pictures(pictures);
+    }
+    if let Some(filename) = trace {
+        match File::create(filename) {
+            Ok(f) => zmachine.set_trace_sink(Box::new(TraceWriter::new(BufWriter::new(f)))),
+            Err(e) => {
+                println!("Error creating {}: {}", filename, e);
+                exit(1);
+            }
+        }
+    }
+    if let Some(filename) = transcript {
+        if let Err(e) = zmachine.start_transcript(Path::new(filename)) {
+            let _ = zmachine.print_error("error.start_transcript", &e);
+        }
+    }
+
+    if let (Some(steps), Some(capture)) = (steps, capture) {
+        exit(play_script(&mut zmachine, steps, &capture, expect));
+    }
 
     trace!("Begining execution");
 
     // If execution ended due to an error, print the error and quit
-    if let Err(r) = zmachine.run() {
-        let _ = zmachine.print_str(format!("\r{}\r", r));
-        let _ = zmachine.quit();
-    }
+    let code = match zmachine.run() {
+        Ok(_) => 0,
+        Err(r) => {
+            let message = zmachine.messages().format(
+                "error.fatal",
+                &[
+                    ("code", &format!("{:?}", r.code())),
+                    ("message", r.message()),
+                ],
+            );
+            let _ = zmachine.print_str(format!("\r{}\r", message));
+            for line in format_backtrace(&zmachine.backtrace()) {
+                let _ = zmachine.print_str(format!("{}\r", line));
+            }
+            let _ = zmachine.quit();
+            1
+        }
+    };
 
     // Clean up the terminal
     if cfg!(target_os = "macos") {
@@ -213,4 +543,6 @@ fn main() {
     } else if cfg!(target_os = "linux") {
         let _ = std::process::Command::new("/usr/bin/reset").status();
     }
+
+    exit(code);
 }
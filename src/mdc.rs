@@ -0,0 +1,34 @@
+//! Mapped diagnostic context for the log4rs patterns in `log4rs.yml`, such as the
+//! instruction count.  Without the `log4rs-mdc` feature these calls do nothing and the
+//! values are never formatted.
+
+/// Set `key` in the logging context to the value returned by `value`
+#[cfg(feature = "log4rs-mdc")]
+pub fn insert<F: FnOnce() -> String>(key: &str, value: F) {
+    log_mdc::insert(key, value());
+}
+
+/// Set `key` in the logging context to the value returned by `value`
+#[cfg(not(feature = "log4rs-mdc"))]
+pub fn insert<F: FnOnce() -> String>(_key: &str, _value: F) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "log4rs-mdc")]
+    fn test_insert() {
+        insert("test", || format!("{:8x}", 0x1234));
+        assert_eq!(
+            log_mdc::get("test", |v| v.map(String::from)),
+            Some("    1234".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "log4rs-mdc"))]
+    fn test_insert() {
+        insert("test", || panic!("value formatted without log4rs-mdc"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, ops::Range};
 
 use crate::{
     error::*,
@@ -134,6 +134,25 @@ pub fn property_length(
     }
 }
 
+/// The memory an object's short name is read from: the object's property table pointer and
+/// the text at the start of the property table, including its length byte
+pub fn short_name_ranges(
+    zmachine: &ZMachine,
+    object: usize,
+) -> Result<Vec<Range<usize>>, RuntimeError> {
+    let pointer = object_address(zmachine, object)?
+        + match zmachine.version() {
+            3 => 7,
+            _ => 12,
+        };
+    let property_table_address = property_table_address(zmachine, object)?;
+    let header_count = zmachine.read_byte(property_table_address)? as usize;
+    Ok(vec![
+        pointer..pointer + 2,
+        property_table_address..property_table_address + 1 + (header_count * 2),
+    ])
+}
+
 pub fn short_name(zmachine: &ZMachine, object: usize) -> Result<Vec<u16>, RuntimeError> {
     let property_table_address = property_table_address(zmachine, object)?;
     let header_count = zmachine.read_byte(property_table_address)? as usize;
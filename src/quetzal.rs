@@ -66,20 +66,30 @@ impl fmt::Display for IFhd {
     }
 }
 
-impl From<&Chunk> for IFhd {
-    fn from(value: &Chunk) -> IFhd {
+impl TryFrom<&Chunk> for IFhd {
+    type Error = RuntimeError;
+
+    fn try_from(value: &Chunk) -> Result<IFhd, RuntimeError> {
         let data = value.data();
+        if data.len() < 13 {
+            return recoverable_error!(
+                ErrorCode::IFhdChunkLength,
+                "IFhd chunk is {} bytes, expected 13",
+                data.len()
+            );
+        }
+
         let release_number = iff::vec_as_unsigned(&data[0..2]) as u16;
         let serial_number = data[2..8].to_vec();
         let checksum = iff::vec_as_unsigned(&data[8..10]) as u16;
         let pc = iff::vec_as_unsigned(&data[10..13]) as u32;
 
-        IFhd {
+        Ok(IFhd {
             release_number,
             serial_number,
             checksum,
             pc,
-        }
+        })
     }
 }
 
@@ -230,17 +240,34 @@ impl Stks {
     }
 }
 
-impl From<&Chunk> for Stks {
-    fn from(value: &Chunk) -> Self {
+impl TryFrom<&Chunk> for Stks {
+    type Error = RuntimeError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
         let mut stks = Vec::new();
         let mut offset = 0;
         let data = value.data();
-        while value.length() as usize > offset {
+        while data.len() > offset {
+            if data.len() < offset + 8 {
+                return recoverable_error!(
+                    ErrorCode::Quetzal,
+                    "Stks frame at {:#x} is truncated",
+                    offset
+                );
+            }
             let return_address = iff::vec_as_unsigned(&data[offset..offset + 3]) as u32;
             let flags = data[offset + 3];
             let result_variable = data[offset + 4];
             let arguments = data[offset + 5];
             let stack_size = iff::vec_as_unsigned(&data[offset + 6..offset + 8]);
+            let frame_size = 8 + ((flags as usize & 0xf) + stack_size) * 2;
+            if data.len() < offset + frame_size {
+                return recoverable_error!(
+                    ErrorCode::Quetzal,
+                    "Stks frame at {:#x} is truncated",
+                    offset
+                );
+            }
             let mut variables = Vec::new();
             for i in 0..flags as usize & 0xf {
                 let n = offset + 8 + (i * 2);
@@ -263,7 +290,7 @@ impl From<&Chunk> for Stks {
             ))
         }
 
-        Stks::new(stks)
+        Ok(Stks::new(stks))
     }
 }
 
@@ -278,16 +305,82 @@ impl From<Stks> for Chunk {
     }
 }
 
+/// Interpreter ID used to identify mxyzptlk's IntD chunk
+pub const INTERPRETER_ID: &str = "MXYZ";
+
+/// Interpreter-dependent data
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IntD {
+    interpreter_id: String,
+    data: Vec<u8>,
+}
+
+impl IntD {
+    pub fn new(interpreter_id: &str, data: Vec<u8>) -> IntD {
+        IntD {
+            interpreter_id: interpreter_id.to_string(),
+            data,
+        }
+    }
+
+    pub fn interpreter_id(&self) -> &str {
+        &self.interpreter_id
+    }
+
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+}
+
+impl From<&Chunk> for IntD {
+    fn from(value: &Chunk) -> Self {
+        // OS ID (4), flags (1), contents ID (1), reserved (2), interpreter ID (4), data
+        let data = value.data();
+        let interpreter_id =
+            String::from_utf8_lossy(&data[usize::min(8, data.len())..usize::min(12, data.len())])
+                .to_string();
+        IntD {
+            interpreter_id,
+            data: data[usize::min(12, data.len())..].to_vec(),
+        }
+    }
+}
+
+impl From<IntD> for Chunk {
+    fn from(value: IntD) -> Self {
+        // OS ID is "    " for any OS, with flags, contents ID, and reserved bytes all 0
+        let mut data = vec![b' ', b' ', b' ', b' ', 0, 0, 0, 0];
+        data.extend_from_slice(value.interpreter_id.as_bytes());
+        data.extend_from_slice(&value.data);
+        Chunk::new_chunk(0, "IntD", data)
+    }
+}
+
 #[derive(Debug)]
 pub struct Quetzal {
     ifhd: IFhd,
     mem: Mem,
     stks: Stks,
+    intd: Option<IntD>,
 }
 
 impl Quetzal {
     pub fn new(ifhd: IFhd, mem: Mem, stks: Stks) -> Quetzal {
-        Quetzal { ifhd, mem, stks }
+        Quetzal {
+            ifhd,
+            mem,
+            stks,
+            intd: None,
+        }
+    }
+
+    /// mxyzptlk's interpreter-dependent data, if any
+    pub fn intd(&self) -> Option<&IntD> {
+        self.intd.as_ref()
+    }
+
+    pub fn set_intd(&mut self, intd: Option<IntD>) {
+        self.intd = intd;
     }
 
     pub fn ifhd(&self) -> &IFhd {
@@ -301,6 +394,30 @@ impl Quetzal {
     pub fn stks(&self) -> &Stks {
         &self.stks
     }
+
+    /// Length of the save file this encodes to, without encoding it
+    pub fn size(&self) -> usize {
+        // Chunk header and data padded to an even length
+        fn chunk(length: usize) -> usize {
+            8 + length + length % 2
+        }
+
+        let ifhd = chunk(7 + self.ifhd.serial_number.len());
+        let mem = chunk(self.mem.memory.len());
+        let stks = chunk(
+            self.stks
+                .stks
+                .iter()
+                .map(|s| 8 + 2 * (s.variables.len() + s.stack.len()))
+                .sum(),
+        );
+        let intd = match &self.intd {
+            Some(intd) => chunk(8 + intd.interpreter_id.len() + intd.data.len()),
+            None => 0,
+        };
+        // FORM header and IFZS id
+        12 + ifhd + mem + stks + intd
+    }
 }
 
 impl TryFrom<Chunk> for Quetzal {
@@ -320,11 +437,20 @@ impl TryFrom<Chunk> for Quetzal {
             return recoverable_error!(ErrorCode::Quetzal, "No Stks chunk",);
         }
 
-        Ok(Quetzal::new(
-            IFhd::from(ifhd_chunk.unwrap()),
+        let mut quetzal = Quetzal::new(
+            IFhd::try_from(ifhd_chunk.unwrap())?,
             Mem::from(mem_chunk.unwrap()),
-            Stks::from(stks_chunk.unwrap()),
-        ))
+            Stks::try_from(stks_chunk.unwrap())?,
+        );
+        // Other interpreters may have written their own IntD chunks
+        quetzal.set_intd(
+            value
+                .find_chunks("IntD", "")
+                .into_iter()
+                .map(IntD::from)
+                .find(|intd| intd.interpreter_id() == INTERPRETER_ID),
+        );
+        Ok(quetzal)
     }
 }
 
@@ -341,8 +467,12 @@ impl From<Quetzal> for Chunk {
         let ifhd = Chunk::from(value.ifhd);
         let mem = Chunk::from(value.mem);
         let stks = Chunk::from(value.stks);
+        let mut chunks = vec![ifhd, mem, stks];
+        if let Some(intd) = value.intd {
+            chunks.push(Chunk::from(intd));
+        }
 
-        Chunk::new_form(0, "IFZS", vec![ifhd, mem, stks])
+        Chunk::new_form(0, "IFZS", chunks)
     }
 }
 
@@ -352,3 +482,85 @@ impl From<Quetzal> for Vec<u8> {
         Vec::from(&chunk)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::test_util::random_iterations;
+
+    use super::*;
+
+    fn save() -> Vec<u8> {
+        vec![
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x3C, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
+            0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x04,
+            0x00, 0xFF, 0xFC, 0x01, b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x0E, 0x00, 0x04,
+            0x8E, 0x01, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66,
+        ]
+    }
+
+    #[test]
+    fn test_try_from_vec() {
+        let quetzal = Quetzal::try_from(save()).unwrap();
+        assert_eq!(quetzal.ifhd().pc(), 0x9abc);
+        assert!(quetzal.mem().compressed());
+        assert_eq!(quetzal.mem().memory(), &vec![0x00, 0xFF, 0xFC, 0x01]);
+        assert_eq!(quetzal.stks().stks().len(), 1);
+        assert!(quetzal.intd().is_none());
+    }
+
+    #[test]
+    fn test_size() {
+        let quetzal = Quetzal::try_from(save()).unwrap();
+        assert_eq!(quetzal.size(), save().len());
+        let mut quetzal = Quetzal::try_from(save()).unwrap();
+        quetzal.set_intd(Some(IntD::new(INTERPRETER_ID, vec![1, 2, 3])));
+        let size = quetzal.size();
+        assert_eq!(size, Vec::from(quetzal).len());
+    }
+
+    #[test]
+    fn test_try_from_vec_short_ifhd() {
+        // IFhd with only 2 bytes of PC
+        let mut data = save();
+        data.drain(0x20..0x22);
+        data[0x07] = 0x3A;
+        data[0x13] = 0x0C;
+        let e = Quetzal::try_from(data).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::IFhdChunkLength);
+    }
+
+    #[test]
+    fn test_try_from_vec_truncated_stks() {
+        // Stks frame with 1 local and 2 stack words, cut off after the first stack word
+        let mut data = save();
+        data.truncate(data.len() - 2);
+        let e = Quetzal::try_from(data).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::Quetzal);
+
+        // Frame header cut short
+        let mut data = save();
+        data.truncate(0x3A);
+        let e = Quetzal::try_from(data).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::Quetzal);
+    }
+
+    #[test]
+    fn test_try_from_vec_random() {
+        // Damaged saves must be rejected with an error, never panic.  Seeded so a failure
+        // is reproducible.
+        let mut rng = ChaCha8Rng::seed_from_u64(0x4d78);
+        for _ in 0..random_iterations(4096) {
+            let mut data = save();
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..data.len());
+                data[i] = rng.gen();
+            }
+            data.truncate(rng.gen_range(0..=data.len()));
+            let _ = Quetzal::try_from(data);
+        }
+    }
+}
@@ -0,0 +1,589 @@
+//! Play a walkthrough without a terminal and compare the output with a golden file.
+//!
+//! [`headless`] builds a ZMachine whose screen output is captured in memory, [`play`]
+//! answers its input from a walkthrough or any other source of [`Step`]s, and [`output`]
+//! recovers the text it printed.
+//!
+//! A walkthrough has one command per line.  Lines starting with `#` are comments,
+//! `%seed <n>` reseeds the random number generator before the next command, and
+//! `%filename <name>` answers the next prompt for a file name, such as SAVE's.  Blank lines
+//! are commands too, answering prompts like "press any key".
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    config::Config,
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+    sound::Manager,
+    zmachine::{
+        input::{Advance, InputKind},
+        io::screen::{
+            capture::{CaptureBuffer, CapturingTerminal, ScreenEvent},
+            headless::HeadlessTerminal,
+            InputEvent, HEADLESS_SIZE,
+        },
+        state::memory::Memory,
+        ZMachine,
+    },
+};
+
+// Lines of unchanged text shown around each difference
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// A line of input
+    Command(String),
+    /// Reseed the RNG
+    Seed(u16),
+    /// Answer the next file name prompt
+    Filename(String),
+}
+
+/// Parse a walkthrough into the steps to play
+pub fn parse_walkthrough(text: &str) -> Result<Vec<Step>, RuntimeError> {
+    let mut steps = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some(directive) = line.trim_start().strip_prefix('%') {
+            let words: Vec<&str> = directive.split_whitespace().collect();
+            match words.as_slice() {
+                ["seed", seed] => match seed.parse::<u16>() {
+                    Ok(seed) => steps.push(Step::Seed(seed)),
+                    Err(_) => {
+                        return recoverable_error!(
+                            ErrorCode::InvalidInput,
+                            "Line {}: seed must be between 0 and 65535: {}",
+                            i + 1,
+                            seed
+                        )
+                    }
+                },
+                ["filename", name] => steps.push(Step::Filename(name.to_string())),
+                _ => {
+                    return recoverable_error!(
+                        ErrorCode::InvalidInput,
+                        "Line {}: unknown directive: {}",
+                        i + 1,
+                        line
+                    )
+                }
+            }
+        } else {
+            steps.push(Step::Command(line.to_string()));
+        }
+    }
+
+    Ok(steps)
+}
+
+// Keys for a command, as the game asked for them: the whole line for READ, the first key
+// for READ_CHAR
+fn command_keys(kind: &InputKind, command: &str) -> VecDeque<u16> {
+    let mut keys: VecDeque<u16> = command
+        .chars()
+        .map(|c| c as u16)
+        .filter(|c| (0x20..0x7f).contains(c))
+        .collect();
+    match kind {
+        InputKind::Line { .. } => keys.push_back(0x0d),
+        InputKind::Char => {
+            keys.truncate(1);
+            if keys.is_empty() {
+                keys.push_back(0x0d);
+            }
+        }
+    }
+    keys
+}
+
+/// A ZMachine with no terminal, whose screen output is captured to the returned buffer
+pub fn headless(
+    memory: Memory,
+    mut config: Config,
+    sound_manager: Option<Manager>,
+    name: &str,
+) -> Result<(ZMachine, CaptureBuffer), RuntimeError> {
+    config.set_headless(true);
+    let buffer = CaptureBuffer::default();
+    let terminal = CapturingTerminal::new(
+        Box::new(HeadlessTerminal::new(HEADLESS_SIZE.0, HEADLESS_SIZE.1)),
+        Some(Box::new(buffer.clone())),
+    );
+    let zmachine =
+        ZMachine::with_terminal(memory, config, sound_manager, name, Box::new(terminal))?;
+    Ok((zmachine, buffer))
+}
+
+/// Run the game, answering each request for input with the next command.  When the
+/// steps run out, the interpreter quits.
+pub fn play(
+    zmachine: &mut ZMachine,
+    steps: impl IntoIterator<Item = Step>,
+) -> Result<(), RuntimeError> {
+    let mut steps = steps.into_iter();
+    let mut keys = VecDeque::new();
+    let mut event = None;
+    loop {
+        match zmachine.advance(event.take())? {
+            Advance::Quit => return Ok(()),
+            Advance::Output => {}
+            Advance::NeedInput { kind, .. } => {
+                if keys.is_empty() {
+                    loop {
+                        match steps.next() {
+                            Some(Step::Seed(seed)) => zmachine.reseed(seed),
+                            Some(Step::Filename(name)) => zmachine.answer_filename(&name),
+                            Some(Step::Command(command)) => {
+                                keys = command_keys(&kind, &command);
+                                break;
+                            }
+                            None => return zmachine.quit(),
+                        }
+                    }
+                }
+                event = keys.pop_front().map(InputEvent::from_char);
+            }
+        }
+    }
+}
+
+/// The text printed in the lower window of a ZMachine built by [`headless`], from its
+/// screen capture
+pub fn output(zmachine: &ZMachine, capture: &CaptureBuffer) -> String {
+    // The V3 status line is drawn over the top row of the lower window
+    let status_rows = if zmachine.version() == 3 { 1 } else { 0 };
+    lower_window_text(&capture.contents(), status_rows)
+}
+
+/// Rebuild the text printed in the lower window from a screen capture, including text
+/// that scrolled off the screen.  Upper window output is skipped, as are the first
+/// `status_rows` rows, which hold the V3 status line.
+pub fn lower_window_text(capture: &str, status_rows: u32) -> String {
+    let mut scrolled: Vec<Vec<char>> = Vec::new();
+    let mut rows: BTreeMap<u32, Vec<char>> = BTreeMap::new();
+    for event in capture.lines().filter_map(ScreenEvent::from_json) {
+        match event {
+            ScreenEvent::Print {
+                window: 0,
+                row,
+                column,
+                text,
+                ..
+            } if row > status_rows => {
+                let line = rows.entry(row).or_default();
+                for (i, c) in text.chars().enumerate() {
+                    let column = column as usize - 1 + i;
+                    if line.len() <= column {
+                        line.resize(column + 1, ' ');
+                    }
+                    line[column] = c;
+                }
+            }
+            ScreenEvent::Scroll { row } => {
+                scrolled.push(rows.remove(&row).unwrap_or_default());
+                let below: Vec<u32> = rows.range(row..).map(|(r, _)| *r).collect();
+                for r in below {
+                    if let Some(line) = rows.remove(&r) {
+                        rows.insert(r - 1, line);
+                    }
+                }
+            }
+            ScreenEvent::Backspace { row, column } => {
+                if let Some(c) = rows
+                    .get_mut(&row)
+                    .and_then(|line| line.get_mut(column as usize - 1))
+                {
+                    *c = ' ';
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The rows still on screen follow the text that scrolled off
+    if let (Some(first), Some(last)) = (rows.keys().next(), rows.keys().last()) {
+        for row in *first..=*last {
+            scrolled.push(rows.remove(&row).unwrap_or_default());
+        }
+    }
+
+    scrolled
+        .iter()
+        .map(|line| line.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The statistics printed when the interpreter quits include the play time, which changes
+// from run to run
+fn is_session_stats(line: &str) -> bool {
+    line.starts_with("Turns: ") && line.contains(", play time: ")
+}
+
+/// Normalize output for comparison: trailing whitespace is removed from each line, runs
+/// of blank lines become a single blank line, leading and trailing blank lines are
+/// dropped, and so is the session statistics line printed on quit.
+pub fn normalize(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if is_session_stats(line) || (line.is_empty() && lines.last().is_none_or(String::is_empty))
+        {
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Edit {
+    Same,
+    Delete,
+    Insert,
+}
+
+// Largest longest-common-subsequence table `edits` builds, in entries.  Past this, the
+// differing lines are all deleted and then inserted instead of being matched up.
+const MAX_COMMON_TABLE: usize = 1 << 22;
+
+// The edits turning `from` into `to`, each with the position in both before the edit,
+// from the longest common subsequence of lines.  Lines the two start and end with are
+// matched first, so the table only covers the lines in between.
+fn edits(from: &[String], to: &[String]) -> Vec<(Edit, usize, usize)> {
+    let prefix = from.iter().zip(to).take_while(|(f, t)| f == t).count();
+    let suffix = from[prefix..]
+        .iter()
+        .rev()
+        .zip(to[prefix..].iter().rev())
+        .take_while(|(f, t)| f == t)
+        .count();
+    let (n, m) = (from.len() - prefix - suffix, to.len() - prefix - suffix);
+    let (middle_from, middle_to) = (&from[prefix..prefix + n], &to[prefix..prefix + m]);
+
+    let mut edits: Vec<(Edit, usize, usize)> = (0..prefix).map(|i| (Edit::Same, i, i)).collect();
+    if (n + 1).saturating_mul(m + 1) > MAX_COMMON_TABLE {
+        edits.extend((0..n).map(|i| (Edit::Delete, prefix + i, prefix)));
+        edits.extend((0..m).map(|j| (Edit::Insert, prefix + n, prefix + j)));
+    } else {
+        edits.extend(
+            common_edits(middle_from, middle_to)
+                .into_iter()
+                .map(|(e, i, j)| (e, prefix + i, prefix + j)),
+        );
+    }
+    edits.extend((0..suffix).map(|k| (Edit::Same, prefix + n + k, prefix + m + k)));
+    edits
+}
+
+// The edits turning `from` into `to` from the table of their longest common subsequences
+fn common_edits(from: &[String], to: &[String]) -> Vec<(Edit, usize, usize)> {
+    let (n, m) = (from.len(), to.len());
+    // common[i][j] is the length of the longest common subsequence of from[i..] and to[j..]
+    let mut common = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if from[i] == to[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                u32::max(common[i + 1][j], common[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && from[i] == to[j] {
+            edits.push((Edit::Same, i, j));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+            edits.push((Edit::Delete, i, j));
+            i += 1;
+        } else {
+            edits.push((Edit::Insert, i, j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+// Hunk range, which starts at the line before an empty range
+fn range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+/// Unified diff between `expected` and `actual`, or None when they are the same
+pub fn unified_diff(
+    expected: &[String],
+    actual: &[String],
+    expected_name: &str,
+    actual_name: &str,
+) -> Option<String> {
+    let edits = edits(expected, actual);
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|i| edits[*i].0 != Edit::Same)
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", expected_name, actual_name);
+    let mut c = 0;
+    while c < changes.len() {
+        // Changes close enough that their context would overlap share a hunk
+        let mut last = c;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= DIFF_CONTEXT * 2 {
+            last += 1;
+        }
+        let start = changes[c].saturating_sub(DIFF_CONTEXT);
+        let end = usize::min(edits.len(), changes[last] + DIFF_CONTEXT + 1);
+        let hunk = &edits[start..end];
+        let from_count = hunk.iter().filter(|e| e.0 != Edit::Insert).count();
+        let to_count = hunk.iter().filter(|e| e.0 != Edit::Delete).count();
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(hunk[0].1, from_count),
+            range(hunk[0].2, to_count)
+        ));
+        for (edit, i, j) in hunk {
+            match edit {
+                Edit::Same => diff.push_str(&format!(" {}\n", expected[*i])),
+                Edit::Delete => diff.push_str(&format!("-{}\n", expected[*i])),
+                Edit::Insert => diff.push_str(&format!("+{}\n", actual[*j])),
+            }
+        }
+        c = last + 1;
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        assert_ok, assert_ok_eq, config::Config, test_util::*, zmachine::state::memory::Memory,
+    };
+
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_walkthrough() {
+        let steps = assert_ok!(parse_walkthrough(
+            "# Start\nopen mailbox\r\n  # indented comment\n%seed 42\n\n  %seed 0\ntake leaflet\n%filename zork.ifzs\nsave"
+        ));
+        assert_eq!(
+            steps,
+            &[
+                Step::Command("open mailbox".to_string()),
+                Step::Seed(42),
+                Step::Command("".to_string()),
+                Step::Seed(0),
+                Step::Command("take leaflet".to_string()),
+                Step::Filename("zork.ifzs".to_string()),
+                Step::Command("save".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_walkthrough_bad_seed() {
+        let e = parse_walkthrough("look\n%seed 70000").expect_err("expected an error");
+        assert_eq!(e.code(), ErrorCode::InvalidInput);
+        assert_eq!(
+            e.message(),
+            "Line 2: seed must be between 0 and 65535: 70000"
+        );
+        assert!(parse_walkthrough("%seed").is_err());
+    }
+
+    #[test]
+    fn test_parse_walkthrough_unknown_directive() {
+        let e = parse_walkthrough("%wait 10").expect_err("expected an error");
+        assert_eq!(e.code(), ErrorCode::InvalidInput);
+        assert_eq!(e.message(), "Line 1: unknown directive: %wait 10");
+    }
+
+    #[test]
+    fn test_command_keys() {
+        assert_eq!(
+            command_keys(
+                &InputKind::Line {
+                    max_length: 10,
+                    terminators: vec![0x0d]
+                },
+                "go n\u{e9}"
+            ),
+            &[b'g' as u16, b'o' as u16, b' ' as u16, b'n' as u16, 0x0d]
+        );
+        assert_eq!(command_keys(&InputKind::Char, "yes"), &[b'y' as u16]);
+        assert_eq!(command_keys(&InputKind::Char, ""), &[0x0d]);
+    }
+
+    fn print(window: u8, row: u32, column: u32, text: &str) -> String {
+        ScreenEvent::Print {
+            window,
+            row,
+            column,
+            style: 0,
+            background: 2,
+            text: text.to_string(),
+        }
+        .to_json()
+    }
+
+    #[test]
+    fn test_lower_window_text() {
+        let capture = [
+            print(0, 1, 1, "West of House     Score: 0"),
+            print(0, 23, 1, "First"),
+            print(0, 24, 1, ">look"),
+            ScreenEvent::Scroll { row: 2 }.to_json(),
+            ScreenEvent::Scroll { row: 2 }.to_json(),
+            print(1, 2, 1, "Upper window"),
+            print(0, 23, 1, ">takex"),
+            ScreenEvent::Backspace { row: 23, column: 6 }.to_json(),
+            print(0, 24, 5, "Last"),
+            r#"{"event":"screen","text":""}"#.to_string(),
+        ]
+        .join("\n");
+        assert_eq!(
+            lower_window_text(&capture, 1),
+            "\n\nFirst\n>look\n>take \n    Last"
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("\n\nFirst  \n\n \n\nSecond\nTurns: 2, play time: 0:00:01\n\n"),
+            lines(&["First", "", "Second"])
+        );
+        assert!(normalize("\n \n").is_empty());
+    }
+
+    #[test]
+    fn test_unified_diff_same() {
+        let text = lines(&["a", "b"]);
+        assert!(unified_diff(&text, &text, "expected", "actual").is_none());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let expected = lines(&[
+            "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+        ]);
+        let actual = lines(&[
+            "1", "2", "3", "4", "five", "6", "7", "8", "9", "10", "11", "12", "13",
+        ]);
+        assert_eq!(
+            unified_diff(&expected, &actual, "golden.txt", "output").unwrap(),
+            "--- golden.txt\n+++ output\n\
+             @@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_merged_hunks() {
+        let expected = lines(&["a", "b", "c", "d"]);
+        let actual = lines(&["x", "b", "c", "y"]);
+        assert_eq!(
+            unified_diff(&expected, &actual, "e", "a").unwrap(),
+            "--- e\n+++ a\n@@ -1,4 +1,4 @@\n-a\n+x\n b\n c\n-d\n+y\n"
+        );
+        assert_eq!(
+            unified_diff(&[], &lines(&["new"]), "e", "a").unwrap(),
+            "--- e\n+++ a\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_large() {
+        // Too many differing lines to match up, so they are all deleted and then inserted
+        let middle = 2100;
+        let mut expected = lines(&["start"]);
+        expected.extend((0..middle).map(|i| format!("a{}", i)));
+        expected.push("end".to_string());
+        let mut actual = lines(&["start"]);
+        actual.extend((0..middle).map(|i| format!("b{}", i)));
+        actual.push("end".to_string());
+        assert!((middle + 1) * (middle + 1) > MAX_COMMON_TABLE);
+
+        let diff = unified_diff(&expected, &actual, "e", "a").unwrap();
+        assert!(diff.starts_with("--- e\n+++ a\n@@ -1,2102 +1,2102 @@\n start\n-a0\n-a1\n"));
+        assert!(diff.contains("\n-a2099\n+b0\n+b1\n"));
+        assert!(diff.ends_with("\n+b2099\n end\n"));
+    }
+
+    // STOREB #0300 #01 #00 to clear the last input, READ #0300 #0000 -> G00,
+    // RANDOM #64 -> G01, PRINT_NUM G01, NEW_LINE, JUMP back
+    fn random_map() -> Vec<u8> {
+        let mut map = test_map(5);
+        map[0x300] = 10;
+        map[0x400..0x418].copy_from_slice(&[
+            0xE2, 0x17, 0x03, 0x00, 0x01, 0x00, 0xE4, 0x0F, 0x03, 0x00, 0x00, 0x00, 0x10, 0xE7,
+            0x7F, 0x64, 0x11, 0xE6, 0xBF, 0x11, 0xBB, 0x8C, 0xFF, 0xEA,
+        ]);
+        map
+    }
+
+    #[test]
+    fn test_play() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("capture.jsonl");
+        let mut config = Config::default();
+        config.set_headless(true);
+        config.set_capture(&path.to_string_lossy());
+        let mut zmachine = assert_ok!(ZMachine::new(
+            Memory::new(random_map()),
+            config,
+            None,
+            "test"
+        ));
+        let steps = assert_ok!(parse_walkthrough(
+            "%seed 3\nlook\n# comment\nwait\nwait\n%seed 2\nwait\nwait\nwait"
+        ));
+        assert_ok!(play(&mut zmachine, steps));
+        assert_ok_eq!(zmachine.read_byte(0x302), b'w');
+        let capture = assert_ok!(fs::read_to_string(&path));
+        assert_eq!(
+            normalize(&lower_window_text(&capture, 0)),
+            lines(&["look", "1", "wait", "2", "wait", "3", "wait", "1", "wait", "2", "wait", "1"])
+        );
+    }
+
+    #[test]
+    fn test_play_headless() {
+        let mut config = Config::default();
+        config.set_predictable_seed(3);
+        let (mut zmachine, capture) =
+            assert_ok!(headless(Memory::new(random_map()), config, None, "test"));
+        let commands = ["look", "wait", "wait"];
+        assert_ok!(play(
+            &mut zmachine,
+            commands.iter().map(|c| Step::Command(c.to_string()))
+        ));
+        assert_eq!(
+            normalize(&output(&zmachine, &capture)),
+            lines(&["look", "1", "wait", "2", "wait", "3"])
+        );
+    }
+}
@@ -90,14 +90,114 @@ impl Sound {
     }
 }
 
+/// Summary of a sound resource indexed in a Blorb file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SoundResource {
+    number: u32,
+    format: String,
+    bytes: usize,
+    loop_repeats: Option<u32>,
+}
+
+impl fmt::Display for SoundResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sound {:3}: {}, {} bytes, repeats: ",
+            self.number, self.format, self.bytes
+        )?;
+        match self.loop_repeats {
+            Some(r) => write!(f, "{}", r),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl SoundResource {
+    pub fn new(number: u32, format: &str, bytes: usize, loop_repeats: Option<u32>) -> Self {
+        SoundResource {
+            number,
+            format: format.to_string(),
+            bytes,
+            loop_repeats,
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn loop_repeats(&self) -> Option<u32> {
+        self.loop_repeats
+    }
+}
+
+impl From<&Blorb> for Vec<SoundResource> {
+    fn from(value: &Blorb) -> Self {
+        let mut resources = Vec::new();
+        for index in value.ridx().indices() {
+            if index.usage().eq("Snd ") {
+                if let Some(chunk) = value.sounds().get(&(index.start())) {
+                    let format = if chunk.id() == "FORM" {
+                        chunk.sub_id()
+                    } else {
+                        chunk.id()
+                    };
+                    let loop_repeats = value.loops().and_then(|l| {
+                        l.entries()
+                            .iter()
+                            .find(|e| e.number() == index.number())
+                            .map(|e| e.repeats())
+                    });
+                    resources.push(SoundResource::new(
+                        index.number(),
+                        &format,
+                        chunk.length() as usize,
+                        loop_repeats,
+                    ));
+                }
+            }
+        }
+
+        resources
+    }
+}
+
+/// Playback volume for a game volume of 1-8, or 255 for "very loud", scaled down by
+/// `volume_factor`
+pub fn effective_volume(volume: u8, volume_factor: f32) -> f32 {
+    match volume {
+        // Louder than 8 by 25%
+        0xFF => (8.0 / volume_factor) * 1.25,
+        // range from 0.125 - 1.0 seems to work
+        (1..=8) => volume as f32 / volume_factor,
+        // assume middle of range
+        _ => 4.5 / volume_factor,
+    }
+}
+
 pub trait Player {
     fn type_name(&self) -> &str;
     fn is_playing(&mut self) -> bool;
     fn play_sound(&mut self, sound: &[u8], volume: u8, repeats: u8) -> Result<(), RuntimeError>;
     fn stop_sound(&mut self);
     fn change_volume(&mut self, volume: u8);
+    /// Change the volume factor, rescaling any sound that is playing
+    fn set_volume_factor(&mut self, volume_factor: f32);
+    /// Returns an error if the output device has gone away
+    fn check(&mut self) -> Result<(), RuntimeError>;
 }
 
+type NewPlayer = fn(f32) -> Result<Box<dyn Player>, RuntimeError>;
+
 impl fmt::Debug for dyn Player {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.type_name())
@@ -107,7 +207,16 @@ impl fmt::Debug for dyn Player {
 pub struct Manager {
     player: Option<Box<dyn Player>>,
     sounds: HashMap<u32, Sound>,
+    resources: Vec<SoundResource>,
+    // Text descriptions of sounds from the Blorb RDes chunk
+    descriptions: HashMap<u32, String>,
     current_effect: u32,
+    // Divides the game's volume to get the playback volume, and is used to re-open the
+    // output device if it fails
+    volume_factor: f32,
+    new_player: NewPlayer,
+    // Set when the output device failed and couldn't be re-opened
+    degraded: bool,
 }
 
 impl From<Blorb> for HashMap<u32, Sound> {
@@ -146,28 +255,130 @@ impl Manager {
         Ok(Manager {
             player: Some(new_player(128.0)?),
             sounds,
+            resources: Vec::new(),
+            descriptions: HashMap::new(),
             current_effect: 0,
+            volume_factor: 128.0,
+            new_player,
+            degraded: false,
         })
     }
 
+    /// A manager whose player fails after `calls` calls, and that can re-open the output
+    /// device if `reinitialize` is true
+    #[cfg(test)]
+    pub fn mock_failing(calls: usize, reinitialize: bool) -> Result<Manager, RuntimeError> {
+        let mut manager = Manager::mock()?;
+        manager.player = Some(Box::new(FailingPlayer::new(calls)));
+        if !reinitialize {
+            manager.new_player = failing_new_player;
+        }
+        Ok(manager)
+    }
+
     pub fn new(volume_factor: f32, blorb: Blorb) -> Result<Manager, RuntimeError> {
         debug!(target: "app::sound", "Initializing sound manager with volume_factor {}", volume_factor);
+        let resources = Vec::from(&blorb);
+        let descriptions = resources
+            .iter()
+            .filter_map(|r| {
+                blorb
+                    .description("Snd ", r.number())
+                    .map(|d| (r.number(), d.to_string()))
+            })
+            .collect();
+        let sounds = HashMap::from(blorb);
+        for resource in &resources {
+            if sounds.contains_key(&resource.number()) {
+                info!(target: "app::sound", "{}", resource);
+            } else {
+                info!(target: "app::sound", "{} (not loaded)", resource);
+            }
+        }
+
         Ok(Manager {
             player: Some(new_player(volume_factor)?),
-            sounds: HashMap::from(blorb),
+            sounds,
+            resources,
+            descriptions,
             current_effect: 0,
+            volume_factor,
+            new_player,
+            degraded: false,
         })
     }
 
+    pub fn resources(&self) -> Vec<SoundResource> {
+        self.resources.clone()
+    }
+
+    pub fn volume_factor(&self) -> f32 {
+        self.volume_factor
+    }
+
+    /// Change the volume factor, rescaling any sound that is playing.  A larger factor is
+    /// quieter.
+    pub fn set_volume_factor(&mut self, volume_factor: f32) {
+        debug!(target: "app::sound", "Changing volume factor to {}", volume_factor);
+        self.volume_factor = volume_factor;
+        if let Some(p) = self.player.as_mut() {
+            p.set_volume_factor(volume_factor)
+        }
+    }
+
     pub fn current_effect(&self) -> u32 {
         self.current_effect
     }
 
+    /// The Blorb's text description of a sound, if it has one
+    pub fn description(&self, effect: u16) -> Option<&str> {
+        self.descriptions.get(&(effect as u32)).map(String::as_str)
+    }
+
     pub fn sound_count(&self) -> usize {
         self.sounds.len()
     }
 
+    /// True when the output device failed and couldn't be re-opened.  Sounds are ignored.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    // Replace the player if its output device has gone away
+    fn check_player(&mut self) {
+        if let Some(Err(e)) = self.player.as_mut().map(|p| p.check()) {
+            self.recover(&e);
+        }
+    }
+
+    // Try once to re-open the default output device, returning true if it worked.  If it
+    // didn't, carry on without sound.
+    fn recover(&mut self, error: &RuntimeError) -> bool {
+        warn!(target: "app::sound", "Sound output failed: {}", error);
+        self.current_effect = 0;
+        match (self.new_player)(self.volume_factor) {
+            Ok(player) => {
+                info!(target: "app::sound", "Re-opened sound output: {:?}", player);
+                self.player = Some(player);
+                true
+            }
+            Err(e) => {
+                self.degrade(&e);
+                false
+            }
+        }
+    }
+
+    fn degrade(&mut self, error: &RuntimeError) {
+        error!(target: "app::sound", "Sound disabled: {}", error);
+        // With no player nothing is playing, so pending sound interrupts fire
+        self.player = None;
+        self.current_effect = 0;
+        self.degraded = true;
+    }
+
     pub fn is_playing(&mut self) -> bool {
+        self.check_player();
         if let Some(p) = self.player.as_mut() {
             p.is_playing()
         } else {
@@ -175,39 +386,59 @@ impl Manager {
         }
     }
 
+    /// Play a sound effect, returning true if it started.  A missing effect or a failure of
+    /// the output device is logged, not returned; see `is_degraded`.
     pub fn play_sound(
         &mut self,
         effect: u16,
         volume: u8,
         repeats: Option<u8>,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<bool, RuntimeError> {
         debug!(target: "app::sound", "Playing sound effect {}, at volume {}, with repeats {:?}", effect, volume, repeats);
-        if let Some(p) = self.player.as_mut() {
-            match self.sounds.get(&(effect as u32)) {
-                Some(sound) => {
-                    let r = if let Some(r) = repeats {
-                        if r == 255 {
-                            0
-                        } else {
-                            r
-                        }
-                    } else if let Some(r) = sound.repeats {
-                        r as u8
-                    } else {
-                        1
-                    };
+        self.check_player();
+        let sound = match self.sounds.get(&(effect as u32)) {
+            Some(sound) => sound,
+            None => {
+                error!(target: "app::sound", "Sound effect {} not found", effect);
+                return Ok(false);
+            }
+        };
 
-                    self.current_effect = effect as u32;
-                    p.play_sound(&sound.data, volume, r)
-                }
-                None => {
-                    error!(target: "app::sound", "Sound effect {} not found", effect);
-                    Ok(())
-                }
+        let r = if let Some(r) = repeats {
+            if r == 255 {
+                0
+            } else {
+                r
             }
+        } else if let Some(r) = sound.repeats {
+            r as u8
         } else {
-            Ok(())
+            1
+        };
+
+        let result = match self.player.as_mut() {
+            Some(p) => p.play_sound(&sound.data, volume, r),
+            None => return Ok(false),
+        };
+
+        if let Err(e) = result {
+            if !self.recover(&e) {
+                return Ok(false);
+            }
+
+            let sound = &self.sounds[&(effect as u32)];
+            if let Some(Err(e)) = self
+                .player
+                .as_mut()
+                .map(|p| p.play_sound(&sound.data, volume, r))
+            {
+                self.degrade(&e);
+                return Ok(false);
+            }
         }
+
+        self.current_effect = effect as u32;
+        Ok(true)
     }
 
     pub fn stop_sound(&mut self) {
@@ -230,8 +461,8 @@ impl Manager {
 #[cfg(test)]
 mod tests {
     use crate::{
-        assert_ok, assert_some, assert_some_eq,
-        test_util::{mock_blorb, play_sound},
+        assert_ok, assert_ok_eq, assert_some, assert_some_eq,
+        test_util::{self, mock_blorb, play_sound},
     };
 
     use super::*;
@@ -280,7 +511,7 @@ mod tests {
         assert_eq!(snd.number(), 1);
         assert_eq!(snd.data(), &[1, 1, 1, 1]);
         assert_some_eq!(snd.repeats(), &10);
-        assert!(map.get(&2).is_none());
+        assert!(!map.contains_key(&2));
         let snd = assert_some!(map.get(&4));
         assert_eq!(snd.number(), 4);
         assert_eq!(snd.data(), &[4, 4, 4, 4]);
@@ -294,13 +525,42 @@ mod tests {
         assert!(manager.player.is_some());
         assert_eq!(manager.sounds.len(), 2);
         assert_eq!(manager.current_effect(), 0);
+        assert_some_eq!(manager.description(1), "distant foghorn");
+        assert!(manager.description(4).is_none());
+    }
+
+    #[test]
+    fn test_manager_resources() {
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        assert_eq!(manager.sound_count(), 2);
+        assert_eq!(
+            manager.resources(),
+            vec![
+                SoundResource::new(1, "OGGV", 4, Some(10)),
+                SoundResource::new(2, "AIFF", 4, Some(20)),
+                SoundResource::new(4, "OGGV", 4, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sound_resource_display() {
+        assert_eq!(
+            SoundResource::new(3, "OGGV", 1024, Some(0)).to_string(),
+            "Sound   3: OGGV, 1024 bytes, repeats: 0"
+        );
+        assert_eq!(
+            SoundResource::new(12, "AIFF", 20, None).to_string(),
+            "Sound  12: AIFF, 20 bytes, repeats: -"
+        );
     }
 
     #[test]
     fn test_play_sound() {
         let blorb = mock_blorb();
         let mut manager = assert_ok!(Manager::new(128.0, blorb));
-        assert!(manager.play_sound(1, 8, None).is_ok());
+        assert_ok_eq!(manager.play_sound(1, 8, None), true);
         assert!(manager.is_playing());
         assert!(manager.current_effect() == 1);
         assert_eq!(play_sound(), (4, 8, 10));
@@ -320,7 +580,7 @@ mod tests {
     fn test_play_sound_invalid_effect() {
         let blorb = mock_blorb();
         let mut manager = assert_ok!(Manager::new(128.0, blorb));
-        assert!(manager.play_sound(3, 8, Some(1)).is_ok());
+        assert_ok_eq!(manager.play_sound(3, 8, Some(1)), false);
         assert!(!manager.is_playing());
         assert!(manager.current_effect() == 0);
         assert_eq!(play_sound(), (0, 0, 0));
@@ -364,6 +624,37 @@ mod tests {
         assert_eq!(play_sound(), (0, 8, 0));
     }
 
+    #[test]
+    fn test_effective_volume() {
+        assert_eq!(effective_volume(8, 128.0), 0.0625);
+        assert_eq!(effective_volume(4, 64.0), 0.0625);
+        assert_eq!(effective_volume(0xFF, 128.0), 0.078125);
+        assert_eq!(effective_volume(9, 128.0), 4.5 / 128.0);
+    }
+
+    #[test]
+    fn test_set_volume_factor() {
+        let mut manager = assert_ok!(Manager::mock());
+        assert!(manager.play_sound(3, 8, None).is_ok());
+        assert_eq!(test_util::effective_volume(), 0.0625);
+        manager.set_volume_factor(64.0);
+        assert_eq!(manager.volume_factor(), 64.0);
+        assert!(manager.is_playing());
+        assert_eq!(test_util::effective_volume(), 0.125);
+        manager.change_volume(4);
+        assert_eq!(test_util::effective_volume(), 0.0625);
+    }
+
+    #[test]
+    fn test_set_volume_factor_not_playing() {
+        let mut manager = assert_ok!(Manager::mock());
+        manager.set_volume_factor(64.0);
+        assert_eq!(test_util::effective_volume(), 0.0);
+        // The new factor applies to the next sound played
+        assert!(manager.play_sound(3, 8, None).is_ok());
+        assert_eq!(test_util::effective_volume(), 0.125);
+    }
+
     #[test]
     fn test_change_volume_not_playing() {
         let blorb = mock_blorb();
@@ -373,4 +664,50 @@ mod tests {
         assert_eq!(manager.current_effect(), 0);
         assert_eq!(play_sound(), (0, 0, 0));
     }
+
+    #[test]
+    fn test_play_sound_device_lost_reinitialize() {
+        // Checking and playing the first sound both work
+        let mut manager = assert_ok!(Manager::mock_failing(2, true));
+        assert!(manager.play_sound(3, 8, None).is_ok());
+        assert_eq!(play_sound(), (128, 8, 1));
+        // The check before playing fails, and the device is re-opened
+        assert!(manager.play_sound(4, 4, None).is_ok());
+        assert!(!manager.is_degraded());
+        assert!(manager.is_playing());
+        assert_eq!(manager.current_effect(), 4);
+        assert_eq!(play_sound(), (256, 4, 5));
+    }
+
+    #[test]
+    fn test_play_sound_device_lost_degraded() {
+        let mut manager = assert_ok!(Manager::mock_failing(2, false));
+        assert_ok_eq!(manager.play_sound(3, 8, None), true);
+        assert_ok_eq!(manager.play_sound(4, 4, None), false);
+        assert!(manager.is_degraded());
+        assert!(manager.player.is_none());
+        assert!(!manager.is_playing());
+        assert_eq!(manager.current_effect(), 0);
+        assert_eq!(play_sound(), (128, 8, 1));
+    }
+
+    #[test]
+    fn test_play_sound_error_degraded() {
+        // The player fails while playing, rather than when it is checked
+        let mut manager = assert_ok!(Manager::mock_failing(0, false));
+        manager.player = Some(Box::new(FailingPlayer::new(1)));
+        assert_ok_eq!(manager.play_sound(3, 8, None), false);
+        assert!(manager.is_degraded());
+        assert_eq!(manager.current_effect(), 0);
+    }
+
+    #[test]
+    fn test_is_playing_device_lost() {
+        let mut manager = assert_ok!(Manager::mock_failing(3, false));
+        assert!(manager.play_sound(3, 8, None).is_ok());
+        // The sound would never finish on the unplugged device
+        assert!(manager.is_playing());
+        assert!(!manager.is_playing());
+        assert!(manager.is_degraded());
+    }
 }
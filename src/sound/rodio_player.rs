@@ -1,13 +1,15 @@
 use std::io::Write;
+use std::time::{Duration, Instant};
 
 use crate::recoverable_error;
 
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::cpal::traits::HostTrait;
+use rodio::{Decoder, DeviceTrait, OutputStream, OutputStreamHandle, Sink, Source};
 use tempfile::NamedTempFile;
 
 use crate::error::{ErrorCode, RuntimeError};
 
-use super::Player;
+use super::{effective_volume, Player};
 
 pub struct RodioPlayer {
     _output_stream: Option<OutputStream>,
@@ -15,6 +17,20 @@ pub struct RodioPlayer {
     current_effect: u32,
     sink: Option<Sink>,
     volume_factor: f32,
+    // Game volume of the current sound
+    volume: u8,
+    // Name of the output device, to notice when it goes away
+    device_name: Option<String>,
+    last_check: Instant,
+}
+
+// How often to check the output device is still there
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn default_device_name() -> Option<String> {
+    rodio::cpal::default_host()
+        .default_output_device()
+        .and_then(|d| d.name().ok())
 }
 
 impl Player for RodioPlayer {
@@ -31,6 +47,7 @@ impl Player for RodioPlayer {
     }
 
     fn play_sound(&mut self, sound: &[u8], volume: u8, repeats: u8) -> Result<(), RuntimeError> {
+        self.volume = volume;
         match NamedTempFile::new() {
             Ok(mut write) => {
                 match write.reopen() {
@@ -40,7 +57,10 @@ impl Player for RodioPlayer {
                                 Ok(source) => {
                                     match self.get_sink() {
                                         Some(sink) => {
-                                            sink.set_volume(self.normalize_volume(volume));
+                                            sink.set_volume(effective_volume(
+                                                volume,
+                                                self.volume_factor,
+                                            ));
                                             // V5
                                             if repeats == 0 {
                                                 sink.append(source.repeat_infinite())
@@ -89,8 +109,34 @@ impl Player for RodioPlayer {
     }
 
     fn change_volume(&mut self, volume: u8) {
+        self.volume = volume;
+        if let Some(sink) = self.get_sink() {
+            sink.set_volume(effective_volume(volume, self.volume_factor));
+        }
+    }
+
+    fn set_volume_factor(&mut self, volume_factor: f32) {
+        self.volume_factor = volume_factor;
         if let Some(sink) = self.get_sink() {
-            sink.set_volume(self.normalize_volume(volume));
+            sink.set_volume(effective_volume(self.volume, volume_factor));
+        }
+    }
+
+    fn check(&mut self) -> Result<(), RuntimeError> {
+        // rodio doesn't report a lost stream, so check that the default output device is
+        // still the one the stream was opened on
+        if self.last_check.elapsed() < DEVICE_CHECK_INTERVAL {
+            return Ok(());
+        }
+
+        self.last_check = Instant::now();
+        match &self.device_name {
+            Some(name) if default_device_name().as_ref() != Some(name) => recoverable_error!(
+                ErrorCode::SoundPlayback,
+                "Output device '{}' is no longer available",
+                name
+            ),
+            _ => Ok(()),
         }
     }
 }
@@ -113,6 +159,9 @@ impl RodioPlayer {
                         current_effect: 0,
                         sink: Some(sink),
                         volume_factor,
+                        volume: 8,
+                        device_name: default_device_name(),
+                        last_check: Instant::now(),
                     }),
                     Err(e) => {
                         error!(target: "app::sound", "rodio: Error initializing sink: {}", e);
@@ -138,16 +187,4 @@ impl RodioPlayer {
     fn get_sink(&self) -> Option<&Sink> {
         self.sink.as_ref()
     }
-
-    fn normalize_volume(&self, volume: u8) -> f32 {
-        // Volume should range 1 - 8, with -1 being "very load"
-        match volume {
-            // Louder than 8 by 25%
-            0xFF => (8.0 / self.volume_factor) * 1.25,
-            // range from 0.125 - 1.0 seems to work
-            (1..=8) => volume as f32 / self.volume_factor,
-            // assume middle of range
-            _ => 4.5 / self.volume_factor,
-        }
-    }
 }
@@ -1,13 +1,23 @@
-use crate::{error::RuntimeError, test_util::set_play_sound};
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+    test_util::{set_effective_volume, set_play_sound, take_sound_finished},
+};
 
-use super::Player;
+use super::{effective_volume, Player};
 
 pub struct TestPlayer {
     playing: bool,
+    volume_factor: f32,
+    volume: u8,
 }
 
-pub fn new_player(_volume_factor: f32) -> Result<Box<dyn Player>, RuntimeError> {
-    Ok(Box::new(TestPlayer { playing: false }))
+pub fn new_player(volume_factor: f32) -> Result<Box<dyn Player>, RuntimeError> {
+    Ok(Box::new(TestPlayer {
+        playing: false,
+        volume_factor,
+        volume: 8,
+    }))
 }
 
 impl Player for TestPlayer {
@@ -16,6 +26,9 @@ impl Player for TestPlayer {
     }
 
     fn is_playing(&mut self) -> bool {
+        if take_sound_finished() {
+            self.playing = false;
+        }
         self.playing
     }
 
@@ -26,6 +39,8 @@ impl Player for TestPlayer {
         repeats: u8,
     ) -> Result<(), crate::error::RuntimeError> {
         set_play_sound(sound.len(), volume, repeats);
+        self.volume = volume;
+        set_effective_volume(effective_volume(volume, self.volume_factor));
         self.playing = true;
         Ok(())
     }
@@ -38,6 +53,77 @@ impl Player for TestPlayer {
     fn change_volume(&mut self, volume: u8) {
         if self.playing {
             set_play_sound(0, volume, 0);
+            self.volume = volume;
+            set_effective_volume(effective_volume(volume, self.volume_factor));
+        }
+    }
+
+    fn set_volume_factor(&mut self, volume_factor: f32) {
+        self.volume_factor = volume_factor;
+        if self.playing {
+            set_effective_volume(effective_volume(self.volume, volume_factor));
         }
     }
+
+    fn check(&mut self) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+/// A player that works for a number of calls to `play_sound` and `check`, then fails as if
+/// the output device had been unplugged.  A sound that was playing never finishes.
+pub struct FailingPlayer {
+    calls: usize,
+    playing: bool,
+}
+
+impl FailingPlayer {
+    pub fn new(calls: usize) -> FailingPlayer {
+        FailingPlayer {
+            calls,
+            playing: false,
+        }
+    }
+
+    fn call(&mut self) -> Result<(), RuntimeError> {
+        if self.calls == 0 {
+            recoverable_error!(ErrorCode::SoundPlayback, "Device unplugged")
+        } else {
+            self.calls -= 1;
+            Ok(())
+        }
+    }
+}
+
+pub fn failing_new_player(_volume_factor: f32) -> Result<Box<dyn Player>, RuntimeError> {
+    recoverable_error!(ErrorCode::SoundPlayback, "No output device")
+}
+
+impl Player for FailingPlayer {
+    fn type_name(&self) -> &str {
+        "FailingPlayer"
+    }
+
+    fn is_playing(&mut self) -> bool {
+        self.playing
+    }
+
+    fn play_sound(&mut self, sound: &[u8], volume: u8, repeats: u8) -> Result<(), RuntimeError> {
+        self.call()?;
+        set_play_sound(sound.len(), volume, repeats);
+        self.playing = true;
+        Ok(())
+    }
+
+    fn stop_sound(&mut self) {
+        self.playing = false;
+    }
+
+    fn change_volume(&mut self, _volume: u8) {}
+
+    fn set_volume_factor(&mut self, _volume_factor: f32) {}
+
+    fn check(&mut self) -> Result<(), RuntimeError> {
+        self.call()
+    }
 }
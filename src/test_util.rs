@@ -1,53 +1,127 @@
+//! Helpers for unit tests.
+//!
+//! Test builds use a scripted terminal and sound player that record what the interpreter
+//! does here instead of drawing or playing anything: queue keys with [`input`], then check
+//! the text printed with [`print`] or [`assert_print!`](crate::assert_print), and the
+//! other screen operations with their getters.  All of this state is thread-local, and
+//! each test runs on its own thread, so tests running in parallel don't interfere.
+//!
+//! [`test_map`] builds a minimal story file to load with [`mock_zmachine`] or
+//! [`mock_state`], and the `mock_*` functions add routines, objects, dictionaries, and
+//! Blorb resources to it.
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::{HashMap, VecDeque},
+    io::{self, ErrorKind, Write},
+    rc::Rc,
+    sync::Once,
 };
 
 use iff::Chunk;
 
 use crate::{
-    blorb::{Blorb, Entry, IFhd, Index, Loop, RIdx},
+    blorb::{Blorb, Entry, IFhd, Index, Loop, RDes, RIdx},
     config::Config,
     instruction::{
         Branch, Instruction, Opcode, OpcodeForm, Operand, OperandCount, OperandType, StoreResult,
     },
     sound::Manager,
     zmachine::{
+        io::screen::{Attributes, Capabilities, StyleMap},
+        messages::Messages,
         state::{memory::Memory, State},
         ZMachine,
     },
 };
 
 thread_local! {
-    pub static PRINT:RefCell<String> = RefCell::new(String::new());
-    pub static INPUT:RefCell<VecDeque<char>> = RefCell::new(VecDeque::new());
-    pub static INPUT_DELAY:RefCell<u64> = RefCell::new(0);
-    pub static INPUT_TIMEOUT:RefCell<bool> = RefCell::new(false);
-    pub static COLORS:RefCell<(u8, u8)> = RefCell::new((0, 0));
-    pub static SPLIT:RefCell<u8> = RefCell::new(0);
-    pub static WINDOW:RefCell<u8> = RefCell::new(0);
-    pub static ERASE_WINDOW:RefCell<Vec<i8>> = RefCell::new(Vec::new());
-    pub static ERASE_LINE:RefCell<bool> = RefCell::new(false);
-    pub static STYLE:RefCell<u8> = RefCell::new(0);
-    pub static BUFFER:RefCell<u16> = RefCell::new(0);
-    pub static STREAM:RefCell<(u8, Option<usize>)> = RefCell::new((0, None));
-    pub static BEEP:RefCell<bool> = RefCell::new(false);
-    pub static PLAY_SOUND:RefCell<(usize, u8, u8)> = RefCell::new((0, 0, 0));
-    pub static CURSOR:RefCell<(u32, u32)> = RefCell::new((0, 0));
-    pub static SCROLL:RefCell<u32> = RefCell::new(0);
-    pub static BACKSPACE:RefCell<(u32, u32)> = RefCell::new((0, 0));
-    pub static RESET:RefCell<bool> = RefCell::new(false);
-    pub static QUIT:RefCell<bool> = RefCell::new(false);
+    pub static PRINT:RefCell<String> = const { RefCell::new(String::new()) };
+    pub static INPUT:RefCell<VecDeque<char>> = const { RefCell::new(VecDeque::new()) };
+    pub static INPUT_DELAY:RefCell<u64> = const { RefCell::new(0) };
+    pub static INPUT_TIMEOUT:RefCell<bool> = const { RefCell::new(false) };
+    pub static CLOCK:RefCell<Option<u128>> = const { RefCell::new(None) };
+    pub static SIZE:RefCell<(u32, u32)> = const { RefCell::new((24, 80)) };
+    pub static COLORS:RefCell<(u8, u8)> = const { RefCell::new((0, 0)) };
+    pub static SPLIT:RefCell<u8> = const { RefCell::new(0) };
+    pub static WINDOW:RefCell<u8> = const { RefCell::new(0) };
+    pub static ERASE_WINDOW:RefCell<Vec<i8>> = const { RefCell::new(Vec::new()) };
+    pub static ERASE_LINE:RefCell<bool> = const { RefCell::new(false) };
+    pub static STYLE:RefCell<u8> = const { RefCell::new(0) };
+    pub static WINDOW_COLORS:RefCell<Vec<(u8, (u8, u8))>> = const { RefCell::new(Vec::new()) };
+    pub static WINDOW_STYLE:RefCell<Vec<(u8, u8)>> = const { RefCell::new(Vec::new()) };
+    pub static BUFFER:RefCell<u16> = const { RefCell::new(0) };
+    pub static STREAM:RefCell<(u8, Option<usize>)> = const { RefCell::new((0, None)) };
+    pub static BEEP:RefCell<bool> = const { RefCell::new(false) };
+    pub static PLAY_SOUND:RefCell<(usize, u8, u8)> = const { RefCell::new((0, 0, 0)) };
+    pub static EFFECTIVE_VOLUME:RefCell<f32> = const { RefCell::new(0.0) };
+    pub static SOUND_FINISHED:RefCell<bool> = const { RefCell::new(false) };
+    pub static CURSOR:RefCell<(u32, u32)> = const { RefCell::new((0, 0)) };
+    pub static SCROLL:RefCell<u32> = const { RefCell::new(0) };
+    pub static BACKSPACE:RefCell<(u32, u32)> = const { RefCell::new((0, 0)) };
+    pub static RESET:RefCell<bool> = const { RefCell::new(false) };
+    pub static QUIT:RefCell<bool> = const { RefCell::new(false) };
+    pub static INTERRUPT:RefCell<bool> = const { RefCell::new(false) };
+    pub static RESIZED:RefCell<bool> = const { RefCell::new(false) };
+    pub static ERRORS:RefCell<Vec<(String, bool)>> = const { RefCell::new(Vec::new()) };
+    pub static FILENAME_REQUEST:RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+    pub static FILENAME_RESPONSE:RefCell<Option<Option<String>>> = const { RefCell::new(None) };
+    pub static MESSAGE:RefCell<Option<String>> = const { RefCell::new(None) };
+    pub static MENU:RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    pub static LOG_TARGET:RefCell<Option<String>> = const { RefCell::new(None) };
+    pub static LOG:RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    pub static CAPABILITIES:RefCell<Option<Capabilities>> = const { RefCell::new(None) };
+    pub static INTERACTIVE:RefCell<bool> = const { RefCell::new(true) };
+    pub static STYLE_MAP:RefCell<Option<StyleMap>> = const { RefCell::new(None) };
+    pub static ATTRIBUTES:RefCell<Option<Attributes>> = const { RefCell::new(None) };
+}
+
+// Log records are captured per-thread, so tests running in parallel only see
+// the messages logged by their own thread
+struct TestLogger;
+
+impl log::Log for TestLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        LOG_TARGET.with(|x| x.borrow().as_deref() == Some(metadata.target()))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            LOG.with(|x| x.borrow_mut().push(record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: TestLogger = TestLogger;
+static LOGGER_INIT: Once = Once::new();
+
+/// Capture messages logged to `target` on this thread, dropping anything captured before
+pub fn capture_log(target: &str) {
+    LOG.with(|x| x.borrow_mut().clear());
+    LOGGER_INIT.call_once(|| {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+    });
+    LOG_TARGET.with(|x| x.swap(&RefCell::new(Some(target.to_string()))));
+}
+
+/// Messages logged to the target passed to [`capture_log`] on this thread
+pub fn log() -> Vec<String> {
+    LOG.with(|x| x.borrow().clone())
 }
 
 pub fn print_char(c: char) {
     PRINT.with(|x| x.borrow_mut().push(c));
 }
 
+/// Everything printed to the screen on this thread
 pub fn print() -> String {
     PRINT.with(|x| x.borrow().to_string())
 }
 
+/// Queue keys for the terminal to return.  Once they run out, every read returns return.
 pub fn input(i: &[char]) {
     for c in i {
         INPUT.with(|x| x.borrow_mut().push_back(*c));
@@ -58,6 +132,10 @@ pub fn input_char() -> Option<char> {
     INPUT.with(|x| x.borrow_mut().pop_front())
 }
 
+pub fn input_pending() -> bool {
+    INPUT.with(|x| !x.borrow().is_empty())
+}
+
 pub fn input_delay() -> u64 {
     INPUT_DELAY.with(|x| x.borrow().to_owned())
 }
@@ -66,6 +144,25 @@ pub fn set_input_delay(msec: u64) {
     INPUT_DELAY.with(|x| x.swap(&RefCell::new(msec)));
 }
 
+/// The time set with [`set_clock`], in milliseconds, used by the interpreter in place of
+/// the system time
+pub fn clock() -> Option<u128> {
+    CLOCK.with(|x| *x.borrow())
+}
+
+pub fn set_clock(millis: u128) {
+    CLOCK.with(|x| x.replace(Some(millis)));
+}
+
+/// How many inputs a random-input test tries: `count`, multiplied by the
+/// `MXYZPTLK_RANDOM_SCALE` environment variable when it is set, for longer runs
+pub fn random_iterations(count: usize) -> usize {
+    std::env::var("MXYZPTLK_RANDOM_SCALE")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .map_or(count, |scale| count * scale)
+}
+
 pub fn input_timeout() -> bool {
     INPUT_TIMEOUT.with(|x| x.borrow().to_owned())
 }
@@ -74,12 +171,33 @@ pub fn set_input_timeout() {
     INPUT_TIMEOUT.with(|x| x.swap(&RefCell::new(true)))
 }
 
+pub fn size() -> (u32, u32) {
+    SIZE.with(|x| x.borrow().to_owned())
+}
+
+pub fn set_size(rows: u32, columns: u32) {
+    SIZE.with(|x| x.swap(&RefCell::new((rows, columns))));
+}
+
+pub fn capabilities() -> Option<Capabilities> {
+    CAPABILITIES.with(|x| x.borrow().to_owned())
+}
+
+pub fn set_capabilities(capabilities: Capabilities) {
+    CAPABILITIES.with(|x| x.swap(&RefCell::new(Some(capabilities))));
+}
+
 pub fn colors() -> (u8, u8) {
     COLORS.with(|x| x.borrow().to_owned())
 }
 
 pub fn set_colors(colors: (u8, u8)) {
     COLORS.with(|x| x.swap(&RefCell::new(colors)));
+    WINDOW_COLORS.with(|x| x.borrow_mut().push((window(), colors)));
+}
+
+pub fn window_colors() -> Vec<(u8, (u8, u8))> {
+    WINDOW_COLORS.with(|x| x.borrow().clone())
 }
 
 pub fn split() -> u8 {
@@ -120,6 +238,11 @@ pub fn style() -> u8 {
 
 pub fn set_style(style: u8) {
     STYLE.with(|x| x.swap(&RefCell::new(style)));
+    WINDOW_STYLE.with(|x| x.borrow_mut().push((window(), style)));
+}
+
+pub fn window_style() -> Vec<(u8, u8)> {
+    WINDOW_STYLE.with(|x| x.borrow().clone())
 }
 
 pub fn buffer_mode() -> u16 {
@@ -154,6 +277,24 @@ pub fn set_play_sound(size: usize, volume: u8, repeats: u8) {
     PLAY_SOUND.with(|x| x.swap(&RefCell::new((size, volume, repeats))));
 }
 
+/// Playback volume of the sound playing, after the volume factor is applied
+pub fn effective_volume() -> f32 {
+    EFFECTIVE_VOLUME.with(|x| x.borrow().to_owned())
+}
+
+pub fn set_effective_volume(volume: f32) {
+    EFFECTIVE_VOLUME.with(|x| x.swap(&RefCell::new(volume)));
+}
+
+/// Let the sound that's playing run to its end
+pub fn finish_sound() {
+    SOUND_FINISHED.with(|x| x.swap(&RefCell::new(true)));
+}
+
+pub fn take_sound_finished() -> bool {
+    SOUND_FINISHED.with(|x| x.take())
+}
+
 pub fn cursor() -> (u32, u32) {
     CURSOR.with(|x| x.borrow().to_owned())
 }
@@ -194,11 +335,158 @@ pub fn set_quit() {
     QUIT.with(|x| x.swap(&RefCell::new(true)));
 }
 
+pub fn interrupt() -> bool {
+    INTERRUPT.with(|x| x.replace(false))
+}
+
+pub fn set_interrupt() {
+    INTERRUPT.with(|x| x.swap(&RefCell::new(true)));
+}
+
+pub fn resized() -> bool {
+    RESIZED.with(|x| x.replace(false))
+}
+
+/// Change the test terminal's size, as though its window had been resized
+pub fn set_resized(rows: u32, columns: u32) {
+    set_size(rows, columns);
+    RESIZED.with(|x| x.swap(&RefCell::new(true)));
+}
+
+pub fn errors() -> Vec<(String, bool)> {
+    ERRORS.with(|x| x.borrow().clone())
+}
+
+pub fn set_error(message: &str, recoverable: bool) {
+    ERRORS.with(|x| x.borrow_mut().push((message.to_string(), recoverable)));
+}
+
+pub fn filename_request() -> Option<(String, String)> {
+    FILENAME_REQUEST.with(|x| x.borrow().clone())
+}
+
+/// The last message the interpreter showed over the game window
+pub fn message() -> Option<String> {
+    MESSAGE.with(|x| x.borrow().clone())
+}
+
+/// The default text of the interpreter message `key`
+pub fn text(key: &str) -> String {
+    Messages::default().get(key).to_string()
+}
+
+/// Items of the last interpreter menu shown
+pub fn menu() -> Option<Vec<String>> {
+    MENU.with(|x| x.borrow().clone())
+}
+
+pub fn set_menu(items: &[String]) {
+    MENU.with(|x| x.replace(Some(items.to_vec())));
+}
+
+pub fn set_message(message: &str) {
+    MESSAGE.with(|x| x.replace(Some(message.to_string())));
+}
+
+/// Whether the test terminal claims to have a player at the keyboard; true unless a
+/// test calls `set_interactive(false)` to stand in for a headless backend
+pub fn interactive() -> bool {
+    INTERACTIVE.with(|x| *x.borrow())
+}
+
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.with(|x| x.replace(interactive));
+}
+
+/// The style map the test terminal was created with
+pub fn style_map() -> StyleMap {
+    STYLE_MAP.with(|x| x.borrow().unwrap_or_default())
+}
+
+pub fn set_style_map(map: StyleMap) {
+    STYLE_MAP.with(|x| x.replace(Some(map)));
+}
+
+/// Terminal attributes of the last character printed
+pub fn attributes() -> Attributes {
+    ATTRIBUTES.with(|x| x.borrow().unwrap_or_default())
+}
+
+pub fn set_attributes(attributes: Attributes) {
+    ATTRIBUTES.with(|x| x.replace(Some(attributes)));
+}
+
+pub fn set_filename_request(prompt: &str, default: &str) {
+    FILENAME_REQUEST.with(|x| x.replace(Some((prompt.to_string(), default.to_string()))));
+}
+
+/// The answer to the terminal's file name prompt: None when the terminal has no prompt,
+/// Some(None) when the player cancels it
+pub fn filename_response() -> Option<Option<String>> {
+    FILENAME_RESPONSE.with(|x| x.borrow().clone())
+}
+
+pub fn set_filename_response(filename: &str) {
+    FILENAME_RESPONSE.with(|x| x.replace(Some(Some(filename.to_string()))));
+}
+
+pub fn cancel_filename_response() {
+    FILENAME_RESPONSE.with(|x| x.replace(Some(None)));
+}
+
+/// A writer that accepts `limit` bytes and then fails every write as if the disk were full,
+/// counting the bytes written and the write calls made
+pub struct FullDiskWriter {
+    limit: usize,
+    written: Rc<Cell<usize>>,
+    calls: Rc<Cell<usize>>,
+}
+
+impl FullDiskWriter {
+    /// The writer, with shared counts of bytes written and write calls
+    pub fn new(limit: usize) -> (FullDiskWriter, Rc<Cell<usize>>, Rc<Cell<usize>>) {
+        let written = Rc::new(Cell::new(0));
+        let calls = Rc::new(Cell::new(0));
+        (
+            FullDiskWriter {
+                limit,
+                written: Rc::clone(&written),
+                calls: Rc::clone(&calls),
+            },
+            written,
+            calls,
+        )
+    }
+}
+
+impl Write for FullDiskWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.calls.set(self.calls.get() + 1);
+        let n = usize::min(buf.len(), self.limit - self.written.get());
+        if n == 0 && !buf.is_empty() {
+            Err(io::Error::from(ErrorKind::StorageFull))
+        } else {
+            self.written.set(self.written.get() + n);
+            Ok(n)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A 2K story file with dynamic memory below $400, globals at $100, an object table at
+/// $200, and execution starting at $400
 pub fn test_map(version: u8) -> Vec<u8> {
     let mut v = vec![0; 0x800];
     v[0] = version;
-    // Initial PC at $0400
-    v[6] = 0x4;
+    // Initial PC at $0400, or in V6 the main routine at $0400 (packed)
+    if version == 6 {
+        v[6] = 0x1;
+    } else {
+        v[6] = 0x4;
+    }
     // Object table as 0x200
     v[0x0A] = 0x02;
     // Static mark at $0400
@@ -215,6 +503,7 @@ pub fn set_variable(map: &mut [u8], variable: u8, value: u16) {
     map[address + 1] = value as u8;
 }
 
+/// A ZMachine running `map` with the default configuration and a mock sound manager
 pub fn mock_zmachine(map: Vec<u8>) -> ZMachine {
     let m = Memory::new(map);
     let manager = Manager::mock();
@@ -339,7 +628,7 @@ pub fn mock_frame(
 ) {
     let r = result.map(|x| StoreResult::new(0, x));
     assert!(zmachine
-        .call_routine(address, &vec![], r, return_address)
+        .call_routine(address, &[], r, return_address)
         .is_ok());
 }
 
@@ -896,6 +1185,15 @@ pub fn mock_properties(map: &mut [u8], object: usize, properties: &[(u8, &Vec<u8
     }
 }
 
+/// A Blorb indexing sounds 1, 2, and 4 and picture 1, with loop entries for sounds 1 and 2
+/// and a description of sound 1
+/// The start of a PNG file, up to the end of the IHDR chunk's size
+pub fn mock_png(width: u8, height: u8) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+    data.extend([0, 0, 0, width, 0, 0, 0, height, 8, 2, 0, 0, 0]);
+    data
+}
+
 pub fn mock_blorb() -> Blorb {
     let ridx = RIdx::new(vec![
         Index::new("Snd ".to_string(), 1, 0x100),
@@ -913,6 +1211,26 @@ pub fn mock_blorb() -> Blorb {
         Some(IFhd::new(0x1234, &[], 0x5678, 0x98abcd)),
         sounds,
         Some(sloop),
+        Some(RDes::new(vec![(
+            "Snd ".to_string(),
+            1,
+            "distant foghorn".to_string(),
+        )])),
         None,
     )
 }
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_log_clears() {
+        capture_log("app::test");
+        info!(target: "app::test", "before");
+        assert_eq!(log(), ["before"]);
+        capture_log("app::test");
+        assert!(log().is_empty());
+        info!(target: "app::test", "after");
+        assert_eq!(log(), ["after"]);
+    }
+}
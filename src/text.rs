@@ -2,8 +2,12 @@ use std::cmp::Ordering;
 
 use crate::{
     error::*,
-    fatal_error,
-    zmachine::{state::header::HeaderField, ZMachine},
+    fatal_error, recoverable_error,
+    zmachine::{
+        io::screen::map_output,
+        state::{header::HeaderField, memory::Memory},
+        ZMachine,
+    },
 };
 
 const ALPHABET_V3: [[char; 26]; 3] = [
@@ -21,6 +25,235 @@ const ALPHABET_V3: [[char; 26]; 3] = [
     ],
 ];
 
+// The ZSCII characters of alphabets A0, A1, and A2
+type Alphabet = [[u16; 26]; 3];
+
+/// The alphabets used to decode text: the default alphabets, or a game's own alphabet
+/// table of 78 ZSCII characters.  A2 position 1 is a new line either way.
+fn alphabet(custom: Option<&[u8; 78]>) -> Alphabet {
+    let mut alphabet = [[0; 26]; 3];
+    for (row, chars) in ALPHABET_V3.iter().enumerate() {
+        for (i, c) in chars.iter().enumerate() {
+            alphabet[row][i] = match custom {
+                Some(table) => table[(row * 26) + i] as u16,
+                None => *c as u16,
+            };
+        }
+    }
+    alphabet[2][1] = 0x0d;
+    alphabet
+}
+
+/// A story's abbreviations, decoded to ZSCII once so strings can be decoded without the
+/// rest of the story.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AbbrevTable {
+    entries: Vec<Vec<u16>>,
+}
+
+impl AbbrevTable {
+    /// The number of abbreviations: 96, 32 for a version 2 story, or none for version 1
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ZSCII text of an abbreviation
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Abbreviation table (1 - 3)
+    /// * `index` - Abbreviation table index (0 - 31)
+    pub fn get(&self, table: u8, index: u8) -> Result<&[u16], RuntimeError> {
+        let entry = match table {
+            1..=3 => self
+                .entries
+                .get((32 * (table as usize - 1)) + index as usize),
+            _ => None,
+        };
+        match entry {
+            Some(text) => Ok(text),
+            None => recoverable_error!(
+                ErrorCode::InvalidAbbreviation,
+                "No abbreviation {}:{}",
+                table,
+                index
+            ),
+        }
+    }
+}
+
+/// The words of a string in memory, up to and including the one with bit 15 set
+pub fn memory_string(memory: &Memory, address: usize) -> Result<Vec<u16>, RuntimeError> {
+    let mut words = Vec::new();
+    loop {
+        if address + (words.len() * 2) + 2 > memory.size() {
+            return recoverable_error!(
+                ErrorCode::UnterminatedString,
+                "String at ${:05x} runs past the end of memory",
+                address
+            );
+        }
+        let w = memory.read_word(address + (words.len() * 2))?;
+        words.push(w);
+        if w & 0x8000 == 0x8000 {
+            return Ok(words);
+        }
+    }
+}
+
+/// Decode every abbreviation in a story's abbreviation table
+pub fn abbreviations(memory: &Memory) -> Result<AbbrevTable, RuntimeError> {
+    let count = match memory.read_byte(HeaderField::Version as usize)? {
+        1 => 0,
+        2 => 32,
+        _ => 96,
+    };
+    let table = memory.read_word(HeaderField::AbbreviationsTable as usize)? as usize;
+    if table == 0 {
+        return Ok(AbbrevTable::default());
+    }
+    let alphabet = alphabet(None);
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let word_addr = memory.read_word(table + (i * 2))? as usize;
+        let ztext = memory_string(memory, word_addr * 2)?;
+        entries.push(decode_zchars(&ztext, &alphabet, true, &mut |_, _| {
+            Ok(Vec::new())
+        })?);
+    }
+    Ok(AbbrevTable { entries })
+}
+
+/// Convert ZSCII to a string, with ZSCII 13 as a new line
+pub fn zscii_to_string(zscii: &[u16]) -> String {
+    zscii
+        .iter()
+        .map(|z| match z {
+            0x0d => '\n',
+            _ => map_output(*z, 1),
+        })
+        .collect()
+}
+
+/// Decode a string from encoded bytes, without a running story.  Decoding stops at the
+/// word with bit 15 set or at the end of `data`.  A custom alphabet table is only used for
+/// version 5 and later; versions 1 and 2 are decoded as version 3, as the interpreter does.
+///
+/// # Arguments
+///
+/// * `data` - Encoded text
+/// * `abbreviations` - The story's abbreviations
+/// * `version` - Version (1-8)
+/// * `custom_alphabet` - The story's alphabet table, if it has one
+pub fn decode(
+    data: &[u8],
+    abbreviations: &AbbrevTable,
+    version: u8,
+    custom_alphabet: Option<&[u8; 78]>,
+) -> Result<String, RuntimeError> {
+    let mut ztext = Vec::new();
+    for w in data.chunks_exact(2) {
+        let w = u16::from_be_bytes([w[0], w[1]]);
+        ztext.push(w);
+        if w & 0x8000 == 0x8000 {
+            break;
+        }
+    }
+    let alphabet = alphabet(custom_alphabet.filter(|_| version >= 5));
+    let zscii = decode_zchars(&ztext, &alphabet, false, &mut |table, index| {
+        Ok(abbreviations.get(table, index)?.to_vec())
+    })?;
+    Ok(zscii_to_string(&zscii))
+}
+
+// The number of strings in a row, and characters in them, that are taken to be a string
+// area rather than code
+const MIN_STRING_RUN: usize = 4;
+const MIN_STRING_RUN_CHARS: usize = 40;
+
+// Whether decoded text looks like something a game would print, rather than code that
+// happens to decode: printable throughout, at least 3 characters, mostly letters, and
+// no runs of spaces (which is what zeroed bytes decode to)
+fn plausible(zscii: &[u16]) -> bool {
+    let letters = zscii
+        .iter()
+        .filter(|z| **z < 0x80 && (**z as u8).is_ascii_alphabetic())
+        .count();
+    let vowels = zscii
+        .iter()
+        .filter(|z| matches!(**z as u8 | 0x20, b'a' | b'e' | b'i' | b'o' | b'u' | b'y'))
+        .count();
+    let spaces = zscii.iter().filter(|z| **z == 0x20).count();
+    zscii.len() >= 3
+        && letters * 4 >= (zscii.len() - spaces) * 3
+        && vowels * 5 >= letters
+        && !zscii.windows(2).any(|w| w == [0x20, 0x20])
+        && zscii
+            .iter()
+            .all(|z| matches!(z, 0x0d | 0x20..=0x7e | 0x9b..=0xdf))
+}
+
+/// Scan high memory for strings, returning the address and text of each plausible one.
+/// Strings are looked for at every address a packed address can refer to.
+pub fn strings(memory: &Memory) -> Result<Vec<(usize, String)>, RuntimeError> {
+    let version = memory.read_byte(HeaderField::Version as usize)?;
+    let abbreviations = abbreviations(memory)?;
+    let custom = match memory.read_word(HeaderField::AlphabetTable as usize)? as usize {
+        0 => None,
+        address if version >= 5 => memory.slice(address, 78).try_into().ok(),
+        _ => None,
+    };
+    let alphabet = alphabet(custom.as_ref());
+    let align = match version {
+        1..=3 => 2,
+        4..=7 => 4,
+        _ => 8,
+    };
+    // The plausible string at an address and the address following it
+    let string_at = |address: usize| -> Option<(String, usize)> {
+        let ztext = memory_string(memory, address).ok()?;
+        let zscii = decode_zchars(&ztext, &alphabet, false, &mut |table, index| {
+            Ok(abbreviations.get(table, index)?.to_vec())
+        })
+        .ok()?;
+        if plausible(&zscii) {
+            Some((
+                zscii_to_string(&zscii),
+                (address + (ztext.len() * 2)).next_multiple_of(align),
+            ))
+        } else {
+            None
+        }
+    };
+
+    let mut strings = Vec::new();
+    let mut address =
+        (memory.read_word(HeaderField::HighMark as usize)? as usize).next_multiple_of(align);
+    while address + 2 <= memory.size() {
+        // Compilers store strings back to back, so only a run of strings counts; code
+        // decodes to a plausible string now and then, but rarely to several in a row
+        let mut run = Vec::new();
+        let mut next = address;
+        while let Some((text, following)) = string_at(next) {
+            run.push((next, text));
+            next = following;
+        }
+        if run.len() >= MIN_STRING_RUN
+            && run.iter().map(|(_, text)| text.len()).sum::<usize>() >= MIN_STRING_RUN_CHARS
+        {
+            strings.append(&mut run);
+            address = next;
+        } else {
+            address += align;
+        }
+    }
+    Ok(strings)
+}
+
 /// Decode an abbreviation to a string
 ///
 /// # Arguments
@@ -68,8 +301,24 @@ pub fn as_text(
 /// * `z` - Vector of ZSCII-encoded words
 pub fn from_vec(
     zmachine: &ZMachine,
-    ztext: &Vec<u16>,
+    ztext: &[u16],
+    is_abbreviation: bool,
+) -> Result<Vec<u16>, RuntimeError> {
+    decode_zchars(
+        ztext,
+        &alphabet(None),
+        is_abbreviation,
+        &mut |table, index| abbreviation(zmachine, table, index),
+    )
+}
+
+// The z-character state machine shared by the interpreter and standalone decoding.
+// `abbreviation` supplies the text of abbreviation (table, index).
+fn decode_zchars(
+    ztext: &[u16],
+    alphabet: &Alphabet,
     is_abbreviation: bool,
+    abbreviation: &mut dyn FnMut(u8, u8) -> Result<Vec<u16>, RuntimeError>,
 ) -> Result<Vec<u16>, RuntimeError> {
     let mut alphabet_shift: usize = 0;
     let mut s = Vec::new();
@@ -86,8 +335,8 @@ pub fn from_vec(
 
         for b in [b1, b2, b3] {
             if abbrev > 0 {
-                let mut abbreviation = abbreviation(zmachine, abbrev, b)?;
-                s.append(&mut abbreviation);
+                let mut text = abbreviation(abbrev, b)?;
+                s.append(&mut text);
                 abbrev = 0;
             } else if zscii_read1 {
                 zscii_b1 = b;
@@ -100,7 +349,7 @@ pub fn from_vec(
             } else {
                 match b {
                     0 => s.push(0x20),
-                    1 | 2 | 3 => {
+                    1..=3 => {
                         if !is_abbreviation {
                             abbrev = b
                         } else {
@@ -116,10 +365,10 @@ pub fn from_vec(
                         if alphabet_shift == 2 {
                             zscii_read1 = true;
                         } else {
-                            s.push(ALPHABET_V3[alphabet_shift][b as usize - 6] as u16);
+                            s.push(alphabet[alphabet_shift][b as usize - 6]);
                         }
                     }
-                    _ => s.push(ALPHABET_V3[alphabet_shift][b as usize - 6] as u16),
+                    _ => s.push(alphabet[alphabet_shift][b as usize - 6]),
                 }
             }
             if b != 4 && b != 5 {
@@ -130,7 +379,7 @@ pub fn from_vec(
     Ok(s)
 }
 
-pub fn separators(
+pub fn dictionary_separators(
     zmachine: &ZMachine,
     dictionary_address: usize,
 ) -> Result<Vec<char>, RuntimeError> {
@@ -144,28 +393,35 @@ pub fn separators(
     Ok(sep)
 }
 
+/// The word separators declared by the game's dictionary, which READ splits words at.  A
+/// story without a dictionary has none.
+pub fn separators(zmachine: &ZMachine) -> Result<Vec<u16>, RuntimeError> {
+    let dictionary = zmachine.header_word(HeaderField::Dictionary)? as usize;
+    if dictionary == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(dictionary_separators(zmachine, dictionary)?
+        .iter()
+        .map(|c| *c as u16)
+        .collect())
+}
+
 fn find_char(zchar: u16) -> Vec<u16> {
     let c = (zchar as u8) as char;
     if c == ' ' {
         return vec![0];
     }
 
-    for i in 0..26 {
-        if ALPHABET_V3[0][i] == c {
-            return vec![i as u16 + 6];
-        }
+    if let Some(i) = ALPHABET_V3[0].iter().position(|x| *x == c) {
+        return vec![i as u16 + 6];
     }
 
-    for i in 0..26 {
-        if ALPHABET_V3[1][i] == c {
-            return vec![4, i as u16 + 6];
-        }
+    if let Some(i) = ALPHABET_V3[1].iter().position(|x| *x == c) {
+        return vec![4, i as u16 + 6];
     }
 
-    for i in 0..26 {
-        if ALPHABET_V3[2][i] == c {
-            return vec![5, i as u16 + 6];
-        }
+    if let Some(i) = ALPHABET_V3[2].iter().position(|x| *x == c) {
+        return vec![5, i as u16 + 6];
     }
 
     let z1 = (c as u8 >> 5) & 0x1f;
@@ -323,6 +579,17 @@ pub fn from_dictionary(
     }
 }
 
+/// A word or separator found by lexical analysis
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Token {
+    /// Position of the first character in the text buffer
+    pub start: usize,
+    /// Length in characters
+    pub len: usize,
+    /// Address of the dictionary entry, or 0 if the word isn't in the dictionary
+    pub dict_addr: usize,
+}
+
 fn find_word(
     zmachine: &mut ZMachine,
     dictionary: usize,
@@ -331,9 +598,14 @@ fn find_word(
     parse_index: usize,
     (word_count, word_start): (usize, usize),
     word: &Vec<char>,
-) -> Result<(usize, usize), RuntimeError> {
+) -> Result<(usize, usize, Token), RuntimeError> {
     let entry = from_dictionary(zmachine, dictionary, word)?;
     let offset = if zmachine.version() < 5 { 1 } else { 2 };
+    let token = Token {
+        start: word_start + offset,
+        len: word.len(),
+        dict_addr: entry,
+    };
 
     debug!(target: "app::state", "LEXICAL ANALYSIS: {:?} => {:04x}", word, entry);
     let parse_address = parse_buffer + 2 + (4 * parse_index);
@@ -346,7 +618,7 @@ fn find_word(
             entry as u16,
         )?;
         debug!(target: "app::state", "LEXICAL ANALYSIS: store to parse buffer {:04x}", parse_address);
-        Ok((parse_index + 1, word_count + 1))
+        Ok((parse_index + 1, word_count + 1, token))
     } else if entry > 0 {
         let e = zmachine.read_word(parse_address)?;
         if e == 0 {
@@ -358,12 +630,12 @@ fn find_word(
                 entry as u16,
             )?;
             debug!(target: "app::state", "LEXICAL ANALYSIS: store to parse buffer {:04x}", parse_address);
-            Ok((parse_index + 1, word_count + 1))
+            Ok((parse_index + 1, word_count + 1, token))
         } else {
-            Ok((parse_index + 1, word_count))
+            Ok((parse_index + 1, word_count, token))
         }
     } else {
-        Ok((parse_index + 1, word_count))
+        Ok((parse_index + 1, word_count, token))
     }
 }
 
@@ -381,20 +653,48 @@ fn store_parsed_entry(
     Ok(())
 }
 
+// Log the tokens found in `data` as one line
+fn trace_parse(data: &[u8], tokens: &[Token], offset: usize) {
+    let text = |t: &Token| {
+        data.get(t.start - offset..t.start - offset + t.len)
+            .map(|w| String::from_utf8_lossy(w).to_string())
+            .unwrap_or_default()
+    };
+    let tokens = tokens
+        .iter()
+        .map(|t| {
+            format!(
+                "{{\"text\":{:?},\"start\":{},\"len\":{},\"dict\":\"${:04x}\"}}",
+                text(t),
+                t.start,
+                t.len,
+                t.dict_addr
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    info!(target: "app::stream", "{{\"parse\":{:?},\"tokens\":[{}]}}", String::from_utf8_lossy(data), tokens);
+}
+
+/// Lexical analysis of the text in `text_buffer`, storing the words found in `parse_buffer`.
+/// When `flag` is set, words that aren't in the dictionary are left unchanged in the parse
+/// buffer.  Returns the words and separators analysed, in order.
 pub fn parse_text(
     zmachine: &mut ZMachine,
     text_buffer: usize,
     parse_buffer: usize,
     dictionary: usize,
     flag: bool,
-) -> Result<(), RuntimeError> {
+) -> Result<Vec<Token>, RuntimeError> {
     debug!(target: "app::state", "LEXICAL ANALYSIS: text @ {:04x}, parse @ {:04x}, dictionary @ {:04x}, skip {}", text_buffer, parse_buffer, dictionary, flag);
-    let separators = separators(zmachine, dictionary)?;
+    let separators = dictionary_separators(zmachine, dictionary)?;
     let mut word = Vec::new();
     let mut word_start: usize = 0;
     let mut word_count: usize = 0;
     let mut words: usize = 0;
     let mut data = Vec::new();
+    let mut tokens = Vec::new();
+    let mut token;
 
     if zmachine.version() < 5 {
         // Buffer is 0 terminated
@@ -420,14 +720,14 @@ pub fn parse_text(
 
     for (i, b) in data.iter().enumerate() {
         let c = (*b as char).to_ascii_lowercase();
-        if word_count > max_words {
+        if word_count >= max_words {
             break;
         }
 
         if separators.contains(&c) {
             // Store the word
             if !word.is_empty() {
-                (word_count, words) = find_word(
+                (word_count, words, token) = find_word(
                     zmachine,
                     dictionary,
                     parse_buffer,
@@ -436,12 +736,13 @@ pub fn parse_text(
                     (words, word_start),
                     &word,
                 )?;
+                tokens.push(token);
             }
 
             // Store the separator
             if word_count < max_words {
                 let sep = vec![c];
-                (word_count, words) = find_word(
+                (word_count, words, token) = find_word(
                     zmachine,
                     dictionary,
                     parse_buffer,
@@ -450,13 +751,14 @@ pub fn parse_text(
                     (words, word_start + word.len()),
                     &sep,
                 )?;
+                tokens.push(token);
             }
             word.clear();
             word_start = i + 1;
         } else if c == ' ' {
             // Store the word but not the space
             if !word.is_empty() {
-                (word_count, words) = find_word(
+                (word_count, words, token) = find_word(
                     zmachine,
                     dictionary,
                     parse_buffer,
@@ -465,6 +767,7 @@ pub fn parse_text(
                     (words, word_start),
                     &word,
                 )?;
+                tokens.push(token);
             }
             word.clear();
             word_start = i + 1;
@@ -475,7 +778,7 @@ pub fn parse_text(
 
     // End of input, parse anything collected
     if !word.is_empty() && word_count < max_words {
-        (_, words) = find_word(
+        (_, words, token) = find_word(
             zmachine,
             dictionary,
             parse_buffer,
@@ -484,6 +787,7 @@ pub fn parse_text(
             (words, word_start),
             &word,
         )?;
+        tokens.push(token);
     }
 
     // If flag is true, then a previous analysis pass has already set the
@@ -492,14 +796,23 @@ pub fn parse_text(
         zmachine.write_byte(parse_buffer + 1, words as u8)?;
     }
 
-    Ok(())
+    if zmachine.trace_parse() {
+        trace_parse(&data, &tokens, if zmachine.version() < 5 { 1 } else { 2 });
+    }
+
+    Ok(tokens)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         assert_ok, assert_ok_eq,
-        test_util::{mock_sorted_dictionary, mock_unsorted_dictionary, mock_zmachine, test_map},
+        config::Config,
+        test_util::{
+            capture_log, log, mock_sorted_dictionary, mock_unsorted_dictionary, mock_zmachine,
+            test_map,
+        },
+        zmachine::state::memory::Memory,
     };
 
     use super::*;
@@ -845,6 +1158,169 @@ mod tests {
         );
     }
 
+    // Encode lower case text and spaces to bytes
+    fn encoded(text: &str) -> Vec<u8> {
+        let mut zscii = text.bytes().map(|b| b as u16).collect::<Vec<u16>>();
+        let words = zscii.len().div_ceil(3);
+        encode_text(&mut zscii, words)
+            .iter()
+            .flat_map(|w| w.to_be_bytes())
+            .collect()
+    }
+
+    // A version 3 map with an abbreviations table at 0x200 whose entries are all empty
+    // except 1.0 = 'the ' and 3.31 = 'else.'
+    fn abbreviation_map() -> Vec<u8> {
+        let mut map = test_map(3);
+        map[0x18] = 0x2;
+        for i in 0..96 {
+            // 0x3F0, three shifts
+            map[0x200 + (i * 2)] = 0x01;
+            map[0x201 + (i * 2)] = 0xF8;
+        }
+        map[0x3F0] = 0x94;
+        map[0x3F1] = 0xA5;
+        map[0x200] = 0x02;
+        map[0x201] = 0x00;
+        map[0x400..0x404].copy_from_slice(&encoded("the "));
+        map[0x2BE] = 0x02;
+        map[0x2BF] = 0x04;
+        //   A     11    18       A     5     12
+        // 2A38 A8B2
+        map[0x408..0x40C].copy_from_slice(&[0x2A, 0x38, 0xA8, 0xB2]);
+        map
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let abbreviations = assert_ok!(abbreviations(&Memory::new(abbreviation_map())));
+        assert_eq!(abbreviations.len(), 96);
+        assert_ok_eq!(abbreviations.get(1, 0), &[0x74, 0x68, 0x65, 0x20]);
+        assert_ok_eq!(abbreviations.get(3, 31), &[0x65, 0x6C, 0x73, 0x65, 0x2E]);
+        assert_ok_eq!(abbreviations.get(2, 5), &Vec::<u16>::new());
+        assert!(abbreviations.get(0, 0).is_err());
+        assert!(abbreviations.get(4, 0).is_err());
+    }
+
+    #[test]
+    fn test_abbreviations_version() {
+        let mut map = abbreviation_map();
+        map[0] = 2;
+        assert_eq!(
+            assert_ok!(abbreviations(&Memory::new(map.clone()))).len(),
+            32
+        );
+        map[0] = 1;
+        assert!(assert_ok!(abbreviations(&Memory::new(map.clone()))).is_empty());
+        // No table
+        map[0] = 3;
+        map[0x18] = 0;
+        assert!(assert_ok!(abbreviations(&Memory::new(map))).is_empty());
+    }
+
+    #[test]
+    fn test_abbreviations_invalid() {
+        let mut map = abbreviation_map();
+        // 1.0 = 'A1,0', which nests
+        map[0x400] = 0x84;
+        map[0x401] = 0x05;
+        assert!(abbreviations(&Memory::new(map.clone())).is_err());
+        // 1.0 runs off the end of memory
+        map[0x200] = 0x03;
+        map[0x201] = 0xFF;
+        assert!(abbreviations(&Memory::new(map)).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let abbreviations = assert_ok!(abbreviations(&Memory::new(abbreviation_map())));
+        // 'or ' 1.0 3.31
+        //   14    17    0        1     0     3        1F    5     5
+        // 0 10100 10111 00000  0 00001 00000 00011  1 11111 00101 00101
+        // 52E0 0403 FCA5
+        let mut data = vec![0x52, 0xE0, 0x04, 0x03, 0xFC, 0xA5];
+        // Decoding stops at the end of the string
+        data.extend_from_slice(&encoded("ignored"));
+        assert_ok_eq!(
+            decode(&data, &abbreviations, 3, None),
+            "or the else.".to_string()
+        );
+        // Or at the end of the data, ignoring an odd byte
+        assert_ok_eq!(
+            decode(&[0x52, 0xE0, 0x80], &abbreviations, 3, None),
+            "or ".to_string()
+        );
+        // A2 position 1 is a new line
+        assert_ok_eq!(
+            decode(&[0x94, 0xE5], &abbreviations, 3, None),
+            "\n".to_string()
+        );
+        // Abbreviations come from the table
+        assert!(decode(&data, &AbbrevTable::default(), 3, None).is_err());
+    }
+
+    #[test]
+    fn test_decode_custom_alphabet() {
+        let mut table = [0; 78];
+        for (i, c) in (b'a'..=b'z').rev().enumerate() {
+            table[i] = c;
+        }
+        for (i, c) in ALPHABET_V3[1]
+            .iter()
+            .chain(ALPHABET_V3[2].iter())
+            .enumerate()
+        {
+            table[26 + i] = *c as u8;
+        }
+        table[53] = b'x';
+        let abbreviations = AbbrevTable::default();
+        //   6     7     8        5     7     5
+        // 1 00110 00111 01000  1 00101 00111 00101
+        // 'abc' then a new line
+        let data = [0x18, 0xE8, 0x94, 0xE5];
+        assert_ok_eq!(
+            decode(&data, &abbreviations, 3, Some(&table)),
+            "abc\n".to_string()
+        );
+        assert_ok_eq!(
+            decode(&data, &abbreviations, 5, Some(&table)),
+            "zyx\n".to_string()
+        );
+    }
+
+    #[test]
+    fn test_zscii_to_string() {
+        assert_eq!(
+            zscii_to_string(&[0x48, 0x9B, 0x0d, 0x21, 0x2018]),
+            "H\u{e4}\n!\u{2018}"
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut map = abbreviation_map();
+        // High memory at 0x600
+        map[0x04] = 0x06;
+        // Strings below high memory aren't looked for
+        map[0x5E0..0x5E8].copy_from_slice(&encoded("lonely text"));
+        let mut address = 0x600;
+        let mut expected = Vec::new();
+        for text in [
+            "west of house",
+            "you are standing in an open field",
+            "there is a small mailbox here",
+            "it is pitch dark",
+        ] {
+            let data = encoded(text);
+            map[address..address + data.len()].copy_from_slice(&data);
+            expected.push((address, text.to_string()));
+            address += data.len();
+        }
+        // A string on its own isn't taken to be a string area
+        map[0x700..0x708].copy_from_slice(&encoded("alone again"));
+        assert_ok_eq!(strings(&Memory::new(map)), expected);
+    }
+
     #[test]
     fn test_separators() {
         let mut map = test_map(3);
@@ -856,7 +1332,23 @@ mod tests {
         map[0x303] = b'!';
         map[0x304] = b'?';
         let zmachine = mock_zmachine(map);
-        assert_ok_eq!(separators(&zmachine, 0x300), [',', '.', '!', '?']);
+        assert_ok_eq!(
+            dictionary_separators(&zmachine, 0x300),
+            [',', '.', '!', '?']
+        );
+        // No dictionary in the header
+        assert_ok_eq!(separators(&zmachine), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn test_separators_header_dictionary() {
+        let mut map = test_map(3);
+        map[0x08] = 0x03;
+        map[0x300] = 2;
+        map[0x301] = b',';
+        map[0x302] = b'"';
+        let zmachine = mock_zmachine(map);
+        assert_ok_eq!(separators(&zmachine), [b',' as u16, b'"' as u16]);
     }
 
     #[test]
@@ -1239,4 +1731,99 @@ mod tests {
         assert_ok_eq!(zmachine.read_byte(0x28C), 6);
         assert_ok_eq!(zmachine.read_byte(0x28D), 9);
     }
+
+    fn separator_map() -> Vec<u8> {
+        let mut map = test_map(5);
+        mock_unsorted_dictionary(&mut map);
+
+        // Text buffer is at 0x200
+        // look,sailor. inventory
+        map[0x200] = 32;
+        map[0x201] = 22;
+        for (i, b) in b"look,sailor. inventory".iter().enumerate() {
+            map[0x202 + i] = *b;
+        }
+
+        // Parse buffer is at 0x280
+        // Allow up to 5 entries
+        map[0x280] = 5;
+        map
+    }
+
+    #[test]
+    fn test_parse_text_tokens() {
+        let mut zmachine = mock_zmachine(separator_map());
+        let tokens = assert_ok!(parse_text(&mut zmachine, 0x200, 0x280, 0x300, false));
+        assert_eq!(
+            tokens,
+            [
+                Token {
+                    start: 2,
+                    len: 4,
+                    dict_addr: 0x307
+                },
+                Token {
+                    start: 6,
+                    len: 1,
+                    dict_addr: 0
+                },
+                Token {
+                    start: 7,
+                    len: 6,
+                    dict_addr: 0x33D
+                },
+                Token {
+                    start: 13,
+                    len: 1,
+                    dict_addr: 0
+                },
+                Token {
+                    start: 15,
+                    len: 9,
+                    dict_addr: 0x319
+                },
+            ]
+        );
+        // The parse buffer matches the tokens
+        assert_ok_eq!(zmachine.read_byte(0x281), 5);
+        for (i, token) in tokens.iter().enumerate() {
+            let entry = 0x282 + (i * 4);
+            assert_ok_eq!(zmachine.read_word(entry), token.dict_addr as u16);
+            assert_ok_eq!(zmachine.read_byte(entry + 2), token.len as u8);
+            assert_ok_eq!(zmachine.read_byte(entry + 3), token.start as u8);
+        }
+    }
+
+    #[test]
+    fn test_parse_text_trace() {
+        let mut config = Config::default();
+        config.set_trace_parse(true);
+        let mut zmachine = assert_ok!(ZMachine::new(
+            Memory::new(separator_map()),
+            config,
+            None,
+            "test"
+        ));
+        capture_log("app::stream");
+        assert!(parse_text(&mut zmachine, 0x200, 0x280, 0x300, false).is_ok());
+        assert_eq!(
+            log(),
+            [concat!(
+                r#"{"parse":"look,sailor. inventory","tokens":["#,
+                r#"{"text":"look","start":2,"len":4,"dict":"$0307"},"#,
+                r#"{"text":",","start":6,"len":1,"dict":"$0000"},"#,
+                r#"{"text":"sailor","start":7,"len":6,"dict":"$033d"},"#,
+                r#"{"text":".","start":13,"len":1,"dict":"$0000"},"#,
+                r#"{"text":"inventory","start":15,"len":9,"dict":"$0319"}]}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_text_no_trace() {
+        let mut zmachine = mock_zmachine(separator_map());
+        capture_log("app::stream");
+        assert!(parse_text(&mut zmachine, 0x200, 0x280, 0x300, false).is_ok());
+        assert!(log().is_empty());
+    }
 }
@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{ErrorCode, RuntimeError};
+
+use super::ErrorHandling;
+
+/// What to do with an instruction that returned an error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorDisposition {
+    /// Skip the instruction without telling the player
+    Continue,
+    /// Show the error and let the player choose to continue or quit
+    ContinueWithPrompt,
+    /// Stop execution and return the error
+    Abort,
+}
+
+/// Decides how recoverable errors are handled, based on the configured error handling,
+/// any per-code overrides, and the error codes that have already been reported.
+#[derive(Debug)]
+pub struct ErrorPolicy {
+    handling: ErrorHandling,
+    overrides: HashMap<ErrorCode, ErrorHandling>,
+    // Codes that have been reported to the player, for ContinueWarnOnce
+    seen: HashSet<ErrorCode>,
+}
+
+impl ErrorPolicy {
+    pub fn new(handling: ErrorHandling, overrides: HashMap<ErrorCode, ErrorHandling>) -> Self {
+        ErrorPolicy {
+            handling,
+            overrides,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Error handling that applies to `code`
+    pub fn handling(&self, code: ErrorCode) -> ErrorHandling {
+        *self.overrides.get(&code).unwrap_or(&self.handling)
+    }
+
+    pub fn handle(&mut self, error: &RuntimeError) -> ErrorDisposition {
+        if !error.is_recoverable() {
+            return ErrorDisposition::Abort;
+        }
+
+        match self.handling(error.code()) {
+            ErrorHandling::Abort => ErrorDisposition::Abort,
+            ErrorHandling::Ignore => ErrorDisposition::Continue,
+            ErrorHandling::ContinueWarnAlways => {
+                self.seen.insert(error.code());
+                ErrorDisposition::ContinueWithPrompt
+            }
+            ErrorHandling::ContinueWarnOnce => {
+                if self.seen.insert(error.code()) {
+                    ErrorDisposition::ContinueWithPrompt
+                } else {
+                    ErrorDisposition::Continue
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(recoverable: bool) -> RuntimeError {
+        if recoverable {
+            RuntimeError::recoverable(ErrorCode::DivideByZero, "Divide by zero".to_string())
+        } else {
+            RuntimeError::fatal(ErrorCode::DivideByZero, "Divide by zero".to_string())
+        }
+    }
+
+    #[test]
+    fn test_handle() {
+        use ErrorDisposition::*;
+        use ErrorHandling::{ContinueWarnAlways, ContinueWarnOnce, Ignore};
+
+        // handling, recoverable, seen, expected
+        let table = [
+            (ContinueWarnAlways, true, false, ContinueWithPrompt),
+            (ContinueWarnAlways, true, true, ContinueWithPrompt),
+            (ContinueWarnAlways, false, false, Abort),
+            (ContinueWarnAlways, false, true, Abort),
+            (ContinueWarnOnce, true, false, ContinueWithPrompt),
+            (ContinueWarnOnce, true, true, Continue),
+            (ContinueWarnOnce, false, false, Abort),
+            (ContinueWarnOnce, false, true, Abort),
+            (Ignore, true, false, Continue),
+            (Ignore, true, true, Continue),
+            (Ignore, false, false, Abort),
+            (Ignore, false, true, Abort),
+            (ErrorHandling::Abort, true, false, Abort),
+            (ErrorHandling::Abort, true, true, Abort),
+            (ErrorHandling::Abort, false, false, Abort),
+            (ErrorHandling::Abort, false, true, Abort),
+        ];
+
+        for (handling, recoverable, seen, expected) in table {
+            let mut policy = ErrorPolicy::new(handling, HashMap::new());
+            if seen {
+                policy.handle(&error(true));
+            }
+            assert_eq!(
+                policy.handle(&error(recoverable)),
+                expected,
+                "{:?}, recoverable: {}, seen: {}",
+                handling,
+                recoverable,
+                seen
+            );
+        }
+    }
+
+    #[test]
+    fn test_handle_override() {
+        let mut overrides = HashMap::new();
+        overrides.insert(ErrorCode::DivideByZero, ErrorHandling::Abort);
+        let mut policy = ErrorPolicy::new(ErrorHandling::Ignore, overrides);
+        assert_eq!(
+            policy.handling(ErrorCode::DivideByZero),
+            ErrorHandling::Abort
+        );
+        assert_eq!(
+            policy.handling(ErrorCode::InvalidObjectAttribute),
+            ErrorHandling::Ignore
+        );
+        assert_eq!(policy.handle(&error(true)), ErrorDisposition::Abort);
+        assert_eq!(
+            policy.handle(&RuntimeError::recoverable(
+                ErrorCode::InvalidObjectAttribute,
+                "Invalid attribute".to_string()
+            )),
+            ErrorDisposition::Continue
+        );
+    }
+
+    #[test]
+    fn test_handle_warn_once_per_code() {
+        let mut policy = ErrorPolicy::new(ErrorHandling::ContinueWarnOnce, HashMap::new());
+        assert_eq!(
+            policy.handle(&error(true)),
+            ErrorDisposition::ContinueWithPrompt
+        );
+        assert_eq!(
+            policy.handle(&RuntimeError::recoverable(
+                ErrorCode::InvalidObjectAttribute,
+                "Invalid attribute".to_string()
+            )),
+            ErrorDisposition::ContinueWithPrompt
+        );
+        assert_eq!(policy.handle(&error(true)), ErrorDisposition::Continue);
+    }
+}
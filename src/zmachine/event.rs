@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// Something that happened during play, reported to the listener set with
+/// [`ZMachine::set_event_listener`](super::ZMachine::set_event_listener)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GameEvent {
+    /// The score on the status line of a V1-3 score game changed between turns
+    ScoreChanged {
+        old: i16,
+        new: i16,
+    },
+    /// A READ completed; `n` is the turn count, including turns carried over from a restore
+    TurnCompleted {
+        n: u32,
+    },
+    /// The game was saved to `path`
+    Saved {
+        path: String,
+    },
+    /// The game was restored from `path`
+    Restored {
+        path: String,
+    },
+    Restarted,
+    /// A sound effect started playing.  Volume changes to the effect already playing don't
+    /// start it again.
+    SoundStarted {
+        effect: u16,
+    },
+    Quit,
+}
+
+/// Receives [`GameEvent`]s as they happen.
+///
+/// The listener is called while the interpreter is mutably borrowed, so it can't call back
+/// into the ZMachine; one shared through a `RefCell` will panic when it tries.  Queue
+/// anything that needs the interpreter and handle it after `advance` returns.
+pub struct EventListener(Box<dyn FnMut(GameEvent)>);
+
+impl EventListener {
+    pub fn new(listener: Box<dyn FnMut(GameEvent)>) -> EventListener {
+        EventListener(listener)
+    }
+
+    pub fn notify(&mut self, event: GameEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for EventListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EventListener")
+    }
+}
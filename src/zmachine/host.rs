@@ -0,0 +1,137 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+};
+
+/// Stores the files the interpreter writes and reads in one piece: saved games and the
+/// command files replayed by input stream 1.  Set with
+/// [`ZMachine::set_save_provider`](super::ZMachine::set_save_provider); the default,
+/// [`FileSaves`], uses the file system.
+pub trait SaveProvider: fmt::Debug {
+    /// Is there already a file called `name`?
+    fn exists(&self, name: &str) -> Result<bool, RuntimeError>;
+
+    /// Store `data` as `name`, replacing anything already there
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), RuntimeError>;
+
+    /// The contents of `name`
+    fn read(&mut self, name: &str) -> Result<Vec<u8>, RuntimeError>;
+}
+
+/// Save files on disk, named relative to the working directory
+#[derive(Debug, Default)]
+pub struct FileSaves;
+
+impl SaveProvider for FileSaves {
+    fn exists(&self, name: &str) -> Result<bool, RuntimeError> {
+        match Path::new(name).try_exists() {
+            Ok(b) => Ok(b),
+            Err(e) => recoverable_error!(
+                ErrorCode::Interpreter,
+                "Error checking if '{}' exists: {}",
+                name,
+                e
+            ),
+        }
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), RuntimeError> {
+        let mut file = match File::create(name) {
+            Ok(f) => f,
+            Err(e) => return recoverable_error!(ErrorCode::FileError, "{}", e),
+        };
+        match file.write_all(data).and_then(|_| file.flush()) {
+            Ok(_) => Ok(()),
+            Err(e) => recoverable_error!(ErrorCode::FileError, "{}", e),
+        }
+    }
+
+    fn read(&mut self, name: &str) -> Result<Vec<u8>, RuntimeError> {
+        match fs::read(name) {
+            Ok(data) => Ok(data),
+            Err(e) => recoverable_error!(ErrorCode::FileError, "{}: {}", name, e),
+        }
+    }
+}
+
+/// The time of day, for input timeouts and play time.  Set with
+/// [`ZMachine::set_clock`](super::ZMachine::set_clock); the default is [`SystemClock`].
+pub trait Clock: fmt::Debug {
+    /// Time since the Unix epoch
+    fn now(&self) -> Duration;
+}
+
+/// The system clock
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(t) => t,
+            Err(e) => {
+                error!(target: "app::state", "Error getting current system time: {}", e);
+                Duration::ZERO
+            }
+        };
+        // Tests can set the clock to check timeouts without waiting for them
+        #[cfg(test)]
+        let now = crate::test_util::clock().map_or(now, |ms| Duration::from_millis(ms as u64));
+        now
+    }
+}
+
+/// Waits between polls of the keyboard while the interpreter has nothing else to do.  Set
+/// with [`ZMachine::set_sleeper`](super::ZMachine::set_sleeper); the default is
+/// [`ThreadSleeper`].
+pub trait Sleeper: fmt::Debug {
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Sleeps the current thread
+#[derive(Debug, Default)]
+pub struct ThreadSleeper;
+
+impl Sleeper for ThreadSleeper {
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assert_ok, assert_ok_eq, test_util::set_clock};
+
+    use super::*;
+
+    #[test]
+    fn test_file_saves() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let name = dir.path().join("test.ifzs");
+        let name = name.to_str().unwrap();
+        let mut saves = FileSaves;
+        assert_ok_eq!(saves.exists(name), false);
+        assert!(saves.read(name).is_err());
+        assert!(saves.write(name, &[1, 2, 3]).is_ok());
+        assert_ok_eq!(saves.exists(name), true);
+        assert_ok_eq!(saves.read(name), vec![1, 2, 3]);
+        // Writing again replaces the file
+        assert!(saves.write(name, &[4]).is_ok());
+        assert_ok_eq!(saves.read(name), vec![4]);
+    }
+
+    #[test]
+    fn test_system_clock() {
+        set_clock(1_234_567);
+        assert_eq!(SystemClock.now(), Duration::from_millis(1_234_567));
+    }
+}
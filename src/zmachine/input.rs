@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+/// The kind of input an instruction is waiting for
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InputKind {
+    /// A line of text, for READ, of up to `max_length` characters.  Any of the
+    /// `terminators`, which always include return, ends the line.
+    Line {
+        max_length: usize,
+        terminators: Vec<u16>,
+    },
+    /// A single key, for READ_CHAR
+    Char,
+}
+
+/// Result of [`ZMachine::advance`](super::ZMachine::advance)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Advance {
+    /// Execution is waiting for input.  `timeout` is the time remaining before a timed read
+    /// is interrupted, and `preloaded` is the text already in the input buffer, which has
+    /// been printed.  Call `advance` again with the next input event, or with `None` when the
+    /// timeout elapses or to check for a sound interrupt.
+    NeedInput {
+        kind: InputKind,
+        timeout: Option<Duration>,
+        preloaded: Vec<u16>,
+    },
+    /// Instructions were executed and any output has been delivered to the screen.  Call
+    /// `advance` again to continue.
+    Output,
+    /// The game executed QUIT
+    Quit,
+}
+
+// Line input being edited
+#[derive(Debug)]
+pub(super) struct LineInput {
+    pub buffer: Vec<u16>,
+    pub len: usize,
+    pub terminators: Vec<u16>,
+    // The dictionary's word separators, where erasing a word stops
+    pub separators: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub(super) enum PendingKind {
+    Line(LineInput),
+    Char,
+}
+
+// Input a READ or READ_CHAR instruction is waiting for when driven by advance(), with what is
+// needed to complete the instruction once input arrives
+#[derive(Debug)]
+pub(super) struct PendingInput {
+    // Address of the instruction and its operand values, which may have been pulled from the
+    // stack and can't be evaluated again
+    pub pc: usize,
+    pub operands: Vec<u16>,
+    pub kind: PendingKind,
+    // Time, in milliseconds, that a timed read is interrupted, or 0
+    pub end: u128,
+    // A sound interrupt was pending when the read started
+    pub check_sound: bool,
+}
+
+impl PendingInput {
+    pub fn kind(&self) -> InputKind {
+        match &self.kind {
+            PendingKind::Line(input) => InputKind::Line {
+                max_length: input.len,
+                terminators: input.terminators.clone(),
+            },
+            PendingKind::Char => InputKind::Char,
+        }
+    }
+
+    // Block waiting for input when nothing else can end the read
+    pub fn blocking(&self) -> bool {
+        self.end == 0 && !self.check_sound
+    }
+}
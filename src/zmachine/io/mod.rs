@@ -1,4 +1,8 @@
-use std::{fs::File, io::Write};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, ErrorKind, Write},
+};
 
 use crate::{
     config::Config,
@@ -6,12 +10,18 @@ use crate::{
     fatal_error, recoverable_error,
 };
 
-use self::screen::{CellStyle, Color, InputEvent, Screen, Style};
+use self::screen::{
+    text_width, truncate_to_width, Capabilities, CellStyle, Color, InputEvent, Screen, Style,
+    Terminal,
+};
 
-use super::state::State;
+use super::{messages::Messages, state::State};
 
 pub mod screen;
 
+// Most keys queued from a single burst of input
+const BURST_LIMIT: usize = 4096;
+
 #[derive(Debug)]
 struct Stream3 {
     address: usize,
@@ -39,39 +49,89 @@ impl Stream3 {
     }
 }
 
+// The transcript file, or any other writer standing in for it
+struct Stream2(Box<dyn Write>);
+
+impl fmt::Debug for Stream2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Stream2")
+    }
+}
+
+/// A snapshot of the interpreter's output bookkeeping
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IoState {
+    /// Enabled output streams, stream 1 in bit 0
+    pub output_streams: u8,
+    /// Tables of the open stream 3 captures, innermost last
+    pub stream_3_tables: Vec<usize>,
+    pub buffered: bool,
+    pub split: bool,
+}
+
+impl IoState {
+    pub fn is_stream_enabled(&self, stream: u8) -> bool {
+        let mask = (1 << (stream - 1)) & 0xF;
+        self.output_streams & mask == mask
+    }
+}
+
 #[derive(Debug)]
 pub struct IO {
     version: u8,
     screen: Screen,
     output_streams: u8,
-    stream_2: Option<File>,
+    stream_2: Option<Stream2>,
+    // Set when stream 2 was closed because the transcript couldn't be written, until the
+    // Flags 2 transcript bit has been cleared to match
+    transcript_stopped: bool,
     stream_3: Vec<Stream3>,
     buffered: bool,
+    // The end of buffered lower window output that isn't a complete word yet.  It's held
+    // back so a word printed in pieces wraps as a whole, and printed at the next flush point.
+    pending: Vec<u16>,
+    input_stream: u8,
+    // Commands from input stream 1 that have not been read yet
+    commands: VecDeque<Vec<u16>>,
+    // Keys read from the terminal in a burst that have not been handled yet
+    keys: VecDeque<InputEvent>,
+    messages: Messages,
 }
 
 impl IO {
     pub fn new(version: u8, config: Config) -> Result<IO, RuntimeError> {
-        let screen = match version {
-            3 => Screen::new_v3(config)?,
-            4 => Screen::new_v4(config)?,
-            5 | 7 | 8 => Screen::new_v5(config)?,
-            _ => {
-                return fatal_error!(
-                    ErrorCode::UnsupportedVersion,
-                    "Version {} is not supported",
-                    version
-                )
-            }
-        };
+        let messages = config.messages().clone();
+        let screen = Screen::new(version, config)?;
+        Ok(IO::from_screen(version, screen, messages))
+    }
+
+    /// IO for a `version` game drawn on `terminal` instead of the curses terminal, for a
+    /// frontend of its own
+    pub fn with_terminal(
+        version: u8,
+        config: Config,
+        terminal: Box<dyn Terminal>,
+    ) -> Result<IO, RuntimeError> {
+        let messages = config.messages().clone();
+        let screen = Screen::with_terminal(version, config, terminal)?;
+        Ok(IO::from_screen(version, screen, messages))
+    }
 
-        Ok(IO {
+    fn from_screen(version: u8, screen: Screen, messages: Messages) -> IO {
+        IO {
             version,
             screen,
             output_streams: 0x1,
             stream_2: None,
+            transcript_stopped: false,
             stream_3: Vec::new(),
             buffered: true,
-        })
+            pending: Vec::new(),
+            input_stream: 0,
+            commands: VecDeque::new(),
+            keys: VecDeque::new(),
+            messages,
+        }
     }
 
     pub fn rows(&self) -> u32 {
@@ -86,13 +146,56 @@ impl IO {
         self.screen.default_colors()
     }
 
+    pub fn capabilities(&self) -> Capabilities {
+        self.screen.capabilities()
+    }
+
+    pub fn check_size(&self) -> Result<(), RuntimeError> {
+        self.screen.check_size()
+    }
+
+    pub fn snapshot(&self) -> IoState {
+        IoState {
+            output_streams: self.output_streams,
+            stream_3_tables: self.stream_3.iter().map(|s| s.address()).collect(),
+            buffered: self.buffered,
+            split: self.screen.is_split(),
+        }
+    }
+
+    /// Bring the output bookkeeping in line with game state that has just been restored,
+    /// restored from undo, or restarted.  Stream 3 captures are abandoned without writing
+    /// to their tables, which belong to the old state.  Stream 2 is disabled if the restored
+    /// Flags 2 transcript bit is clear; opening the transcript when it's set is up to the
+    /// caller.  Buffering is turned back on and the upper window is removed.
+    pub fn reconcile_after_restore(&mut self, state: &State) -> Result<(), RuntimeError> {
+        self.flush()?;
+        if !self.stream_3.is_empty() {
+            debug!(target: "app::stream", "Abandoning {} stream 3 table(s)", self.stream_3.len());
+            self.stream_3.clear();
+            self.output_streams &= !0x4;
+        }
+        if state.read_byte(0x11)? & 0x1 == 0 {
+            self.output_streams &= !0x2;
+        }
+        self.screen.output_stream(self.output_streams, None);
+        self.buffered = true;
+        self.screen.buffer_mode(1);
+        self.screen.unsplit_window();
+        Ok(())
+    }
+
     // Output streams
     pub fn is_stream_2_open(&self) -> bool {
         self.stream_2.is_some()
     }
 
-    pub fn set_stream_2(&mut self, file: File) {
-        self.stream_2 = Some(file)
+    pub fn set_stream_2<W: Write + 'static>(&mut self, writer: W) {
+        self.stream_2 = Some(Stream2(Box::new(writer)))
+    }
+
+    pub fn close_stream_2(&mut self) {
+        self.stream_2 = None
     }
 
     pub fn is_stream_enabled(&self, stream: u8) -> bool {
@@ -105,6 +208,7 @@ impl IO {
         stream: u8,
         table: Option<usize>,
     ) -> Result<(), RuntimeError> {
+        self.flush()?;
         if (1..4).contains(&stream) {
             let mask = (1 << (stream - 1)) & 0xF;
             self.output_streams |= mask;
@@ -141,6 +245,7 @@ impl IO {
         state: &mut State,
         stream: u8,
     ) -> Result<(), RuntimeError> {
+        self.flush()?;
         let mask = (1 << (stream - 1)) & 0xF;
         debug!(target: "app::stream", "Disable output stream {} => {:04b}", stream, self.output_streams);
         match stream {
@@ -183,11 +288,8 @@ impl IO {
                     .iter()
                     .map(|c| if *c == 0x0d { 0x0a } else { *c as u8 })
                     .collect();
-                if let Err(e) = f.write_all(&t) {
-                    error!(target: "app::stream", "Error writing to transcript file: {}", e);
-                }
-                if let Err(e) = f.flush() {
-                    error!(target: "app::stream", "Error writing to transcript file: {}", e);
+                if let Err(e) = f.0.write_all(&t).and_then(|_| f.0.flush()) {
+                    self.abandon_transcript(&e);
                 }
             } else {
                 warn!(target: "app::stream", "Stream 2 is not open");
@@ -197,6 +299,36 @@ impl IO {
         Ok(())
     }
 
+    // Writing the transcript failed, most likely because the disk is full.  Rather than fail
+    // every print that follows, close stream 2 and say so once.  Losing the transcript
+    // shouldn't end the game, so this isn't an error whatever the error handling.
+    fn abandon_transcript(&mut self, e: &io::Error) {
+        error!(target: "app::stream", "Error writing to transcript file, closing it: {}", e);
+        self.stream_2 = None;
+        self.output_streams &= !0x2;
+        self.transcript_stopped = true;
+        let reason = if e.kind() == ErrorKind::StorageFull {
+            self.messages.get("transcript.disk_full").to_string()
+        } else {
+            e.to_string()
+        };
+        let message = self
+            .messages
+            .format("transcript.stopped", &[("reason", &reason)]);
+        self.screen
+            .print_lower(&message.chars().map(|c| c as u16).collect::<Vec<u16>>());
+    }
+
+    /// The text of the interpreter's own messages
+    pub fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    /// True, once, after stream 2 was closed because the transcript couldn't be written
+    pub fn take_transcript_stopped(&mut self) -> bool {
+        std::mem::take(&mut self.transcript_stopped)
+    }
+
     pub fn print_vec(&mut self, text: &Vec<u16>) -> Result<(), RuntimeError> {
         // Stream 3 is exclusive
         if self.is_stream_enabled(3) {
@@ -215,22 +347,48 @@ impl IO {
                 );
             }
         } else if self.is_stream_enabled(1) {
-            if self.screen.selected_window() == 1 || !self.buffered {
-                self.screen.print(text);
-                if self.screen.selected_window() == 0 {
-                    self.transcript(text)?;
-                }
-            } else {
-                let words = text.split_inclusive(|c| *c == 0x20);
-                for word in words {
-                    if self.screen.columns() - self.screen.cursor().1 < word.len() as u32 {
-                        self.screen.new_line();
-                        self.transcript(&[0x0a])?;
-                    }
+            self.print_screen(text)?;
+        }
+
+        Ok(())
+    }
 
-                    let w = word.to_vec();
-                    self.screen.print(&w);
-                    self.transcript(&w)?;
+    /// Echo typed input to the screen (and the transcript), whatever output streams are
+    /// selected, so the player always sees what they type.
+    pub fn echo(&mut self, text: &[u16]) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.print(&text.to_vec());
+        if self.screen.is_transcripted() {
+            self.transcript(text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Print a message from the interpreter on a line of its own in the lower window (and
+    /// the transcript), whatever window and output streams are selected.
+    pub fn print_lower(&mut self, text: &[u16]) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.print_lower(text);
+        let mut line = text.to_vec();
+        line.push(0x0d);
+        self.transcript(&line)
+    }
+
+    fn print_screen(&mut self, text: &[u16]) -> Result<(), RuntimeError> {
+        if !self.screen.is_buffered() || !self.buffered {
+            self.screen.print(&text.to_vec());
+            if self.screen.is_transcripted() {
+                self.transcript(text)?;
+            }
+        } else {
+            let mut buffer = std::mem::take(&mut self.pending);
+            buffer.extend_from_slice(text);
+            for word in buffer.split_inclusive(|c| *c == 0x20 || *c == 0x0d) {
+                if word.ends_with(&[0x20]) || word.ends_with(&[0x0d]) {
+                    self.print_word(word)?;
+                } else {
+                    self.pending = word.to_vec();
                 }
             }
         }
@@ -238,7 +396,65 @@ impl IO {
         Ok(())
     }
 
+    fn print_word(&mut self, word: &[u16]) -> Result<(), RuntimeError> {
+        let transcripted = self.screen.is_transcripted();
+        if self.wraps(word) {
+            self.screen.new_line();
+            if transcripted {
+                self.transcript(&[0x0a])?;
+            }
+        }
+
+        let w = word.to_vec();
+        self.screen.print(&w);
+        if transcripted {
+            self.transcript(&w)?;
+        }
+        Ok(())
+    }
+
+    /// Print buffered output that is being held back until its word is complete.
+    ///
+    /// Anything that moves the cursor, changes the window or text style, or reads input
+    /// flushes first, so the text lands where, and how, it was printed.
+    pub fn flush(&mut self) -> Result<(), RuntimeError> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            let word = std::mem::take(&mut self.pending);
+            self.print_word(&word)
+        }
+    }
+
+    // Flush before an interpreter prompt or message, which has no way to report an error
+    fn flush_quietly(&mut self) {
+        if let Err(e) = self.flush() {
+            error!(target: "app::stream", "Error flushing output: {}", e);
+        }
+    }
+
+    // Whether a word has to move to the next line before it is printed.  A
+    // proportional font leaves a column spare for the word's trailing space, while
+    // in fixed pitch every character is exactly one cell, so the trailing space
+    // may take the last column.  A trailing new line takes no room at all.  Widths are
+    // in screen cells, so double-width characters count twice and combining marks not
+    // at all.
+    fn wraps(&self, word: &[u16]) -> bool {
+        let word = word.strip_suffix(&[0x0d]).unwrap_or(word);
+        let available = self
+            .screen
+            .line_end()
+            .saturating_sub(self.screen.cursor().1);
+        let width = if self.is_fixed_pitch() && word.last() == Some(&0x20) {
+            text_width(word) - 1
+        } else {
+            text_width(word)
+        };
+        available < width as u32
+    }
+
     pub fn new_line(&mut self) -> Result<(), RuntimeError> {
+        self.flush()?;
         if self.is_stream_enabled(3) {
             if let Some(s) = self.stream_3.last_mut() {
                 s.buffer.push(0xd);
@@ -252,7 +468,7 @@ impl IO {
             if self.is_stream_enabled(1) {
                 self.screen.new_line();
             }
-            if self.screen.selected_window() == 0 {
+            if self.screen.is_transcripted() {
                 self.transcript(&[0x0a])?;
             }
         }
@@ -261,6 +477,7 @@ impl IO {
     }
 
     pub fn split_window(&mut self, lines: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
         if lines == 0 {
             self.screen.unsplit_window();
             Ok(())
@@ -274,11 +491,19 @@ impl IO {
     }
 
     pub fn set_window(&mut self, window: u16) -> Result<(), RuntimeError> {
-        if window > 1 {
+        self.flush()?;
+        // V6 has windows 0 to 7, and -3 is the window already selected
+        let (window, last) = match (self.version, window as i16) {
+            (6, -3) => (self.screen.selected_window() as u16, 7),
+            (6, _) => (window, 7),
+            (_, _) => (window, 1),
+        };
+        if window > last {
             recoverable_error!(
                 ErrorCode::InvalidWindow,
-                "{} is not a valid window [0..1]",
-                window
+                "{} is not a valid window [0..{}]",
+                window,
+                last
             )
         } else {
             self.screen.select_window(window as u8)
@@ -286,13 +511,15 @@ impl IO {
     }
 
     pub fn erase_window(&mut self, window: i16) -> Result<(), RuntimeError> {
+        self.flush()?;
+        if self.version == 6 {
+            return self.screen.erase_window(window as i8);
+        }
+
         match window {
             0 => self.screen.erase_window(0),
             1 => self.screen.erase_window(1),
-            -1 => {
-                self.screen.unsplit_window();
-                self.screen.erase_window(0)
-            }
+            -1 => self.screen.erase_window(-1),
             -2 => {
                 self.screen.erase_window(1)?;
                 self.screen.erase_window(0)
@@ -305,8 +532,15 @@ impl IO {
         }
     }
 
-    pub fn erase_line(&mut self) -> Result<(), RuntimeError> {
-        self.screen.erase_line();
+    /// Erase from the cursor to the end of the line.  In V6, a `value` other than 1 erases
+    /// that many cells instead.
+    pub fn erase_line(&mut self, value: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
+        if self.version == 6 {
+            self.screen.erase_line_v6(value as u32);
+        } else {
+            self.screen.erase_line();
+        }
         Ok(())
     }
 
@@ -315,21 +549,31 @@ impl IO {
         left: &mut Vec<u16>,
         right: &mut Vec<u16>,
     ) -> Result<(), RuntimeError> {
+        self.flush()?;
         let width = self.screen.columns() as usize;
-        let available_for_left = width - right.len() - 1;
-        if left.len() > available_for_left {
-            left.truncate(available_for_left - 4);
-            left.push('.' as u16);
-            left.push('.' as u16);
-            left.push('.' as u16);
+        // Leave room for a leading space, at least one space between the left and right text,
+        // and a trailing space
+        let available_for_left = width.saturating_sub(text_width(right) + 3);
+        if text_width(left) > available_for_left {
+            if available_for_left > 3 {
+                truncate_to_width(left, available_for_left - 3);
+                left.push('.' as u16);
+                left.push('.' as u16);
+                left.push('.' as u16);
+            } else {
+                truncate_to_width(left, available_for_left);
+            }
         }
 
-        let mut spaces = vec![b' ' as u16; width - left.len() - right.len() - 2];
+        let mut spaces =
+            vec![b' ' as u16; width.saturating_sub(text_width(left) + text_width(right) + 2)];
         let mut status_line = vec![b' ' as u16];
         status_line.append(left);
         status_line.append(&mut spaces);
         status_line.append(right);
         status_line.push(b' ' as u16);
+        // On very narrow terminals, even the right text may not fit
+        truncate_to_width(&mut status_line, width);
         let mut style = CellStyle::new();
         style.set(Style::Reverse as u8);
 
@@ -338,25 +582,148 @@ impl IO {
         Ok(())
     }
 
+    /// Draw a debugging line in reverse video across the top row of the screen.
+    ///
+    /// The line is drawn directly on the screen, so it never reaches a transcript or
+    /// stream 3 table, and the cursor is put back afterwards.  Nothing is drawn in V3,
+    /// where the top row is the status line, or while the upper window covers it.
+    pub fn debug_hud(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.flush()?;
+        if self.version == 3 || self.screen.is_upper_window_row(1) {
+            return Ok(());
+        }
+
+        let width = self.screen.columns() as usize;
+        let mut hud: Vec<u16> = format!(" {}", text).chars().map(|c| c as u16).collect();
+        hud.resize(width, b' ' as u16);
+        let mut style = CellStyle::new();
+        style.set(Style::Reverse as u8);
+
+        self.screen.print_at(&hud, (1, 1), &style);
+        self.screen.reset_cursor();
+        Ok(())
+    }
+
     pub fn set_font(&mut self, font: u16) -> Result<u16, RuntimeError> {
+        self.flush()?;
         Ok(self.screen.set_font(font as u8) as u16)
     }
 
+    /// Tracks Flags 2 bit 1, the game's request for fixed-pitch text
+    pub fn set_fixed_pitch(&mut self, fixed: bool) {
+        self.screen.set_force_fixed_pitch(fixed);
+    }
+
+    /// True when either Flags 2 bit 1 or the fixed pitch text style (or font 4) is active
+    pub fn is_fixed_pitch(&self) -> bool {
+        self.screen.is_fixed_pitch()
+    }
+
     pub fn set_text_style(&mut self, style: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
         self.screen.set_style(style as u8)
     }
 
     pub fn cursor(&mut self) -> Result<(u16, u16), RuntimeError> {
+        self.flush()?;
         let c = self.screen.cursor();
         Ok((c.0 as u16, c.1 as u16))
     }
 
     pub fn set_cursor(&mut self, row: u16, column: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
         self.screen.move_cursor(row as u32, column as u32);
         Ok(())
     }
 
+    /// Move the cursor of a V6 window, where -3 is the selected window
+    pub fn set_window_cursor(
+        &mut self,
+        window: i16,
+        row: u16,
+        column: u16,
+    ) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen
+            .set_window_cursor(window, row as u32, column as u32)
+    }
+
+    pub fn move_window(&mut self, window: i16, top: u16, left: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.move_window(window, top as u32, left as u32)
+    }
+
+    pub fn window_size(
+        &mut self,
+        window: i16,
+        height: u16,
+        width: u16,
+    ) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.window_size(window, height as u32, width as u32)
+    }
+
+    pub fn window_style(
+        &mut self,
+        window: i16,
+        flags: u16,
+        operation: u16,
+    ) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.window_style(window, flags, operation)
+    }
+
+    pub fn window_property(&mut self, window: i16, property: u16) -> Result<u16, RuntimeError> {
+        self.flush()?;
+        self.screen.window_property(window, property)
+    }
+
+    pub fn set_window_property(
+        &mut self,
+        window: i16,
+        property: u16,
+        value: u16,
+    ) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.set_window_property(window, property, value)
+    }
+
+    pub fn scroll_window(&mut self, window: i16, lines: i16) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.scroll_window(window, lines)
+    }
+
+    pub fn set_margins(&mut self, window: i16, left: u16, right: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen.set_margins(window, left as u32, right as u32)
+    }
+
+    /// Erase an area of the selected window, relative to its top left corner
+    pub fn erase_area(&mut self, at: (u16, u16), size: (u16, u16)) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen
+            .erase_area((at.0 as u32, at.1 as u32), (size.0 as u32, size.1 as u32));
+        Ok(())
+    }
+
+    pub fn set_window_colors(
+        &mut self,
+        window: i16,
+        foreground: u16,
+        background: u16,
+    ) -> Result<(), RuntimeError> {
+        self.flush()?;
+        self.screen
+            .set_window_colors(window, foreground, background)
+    }
+
+    pub fn set_window_font(&mut self, window: i16, font: u16) -> Result<u16, RuntimeError> {
+        self.flush()?;
+        Ok(self.screen.set_window_font(window, font as u8)? as u16)
+    }
+
     pub fn buffer_mode(&mut self, mode: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
         self.buffered = mode != 0;
         self.screen.buffer_mode(mode);
         Ok(())
@@ -368,38 +735,168 @@ impl IO {
     }
 
     pub fn set_colors(&mut self, foreground: u16, background: u16) -> Result<(), RuntimeError> {
+        self.flush()?;
         self.screen.set_colors(foreground, background)
     }
 
+    // Input streams
+    pub fn input_stream(&self) -> u8 {
+        self.input_stream
+    }
+
+    pub fn set_input_stream(&mut self, stream: u16) -> Result<(), RuntimeError> {
+        match stream {
+            0 | 1 => {
+                debug!(target: "app::stream", "Select input stream {}", stream);
+                self.input_stream = stream as u8;
+                Ok(())
+            }
+            _ => recoverable_error!(
+                ErrorCode::InvalidInputStream,
+                "Input stream {} is not valid: [0..1]",
+                stream
+            ),
+        }
+    }
+
+    pub fn has_commands(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
+    pub fn add_commands(&mut self, commands: &[Vec<u16>]) {
+        self.commands.extend(commands.iter().cloned());
+    }
+
+    pub fn next_command(&mut self) -> Option<Vec<u16>> {
+        if self.input_stream == 1 {
+            let command = self.commands.pop_front();
+            // Revert to the keyboard once the command file is exhausted
+            if self.commands.is_empty() {
+                debug!(target: "app::stream", "Input stream 1 exhausted, select input stream 0");
+                self.input_stream = 0;
+            }
+            command
+        } else {
+            None
+        }
+    }
+
     // Input
     pub fn read_key(&mut self, wait: bool) -> InputEvent {
-        self.screen.read_key(wait)
+        self.flush_quietly();
+        match self.keys.pop_front() {
+            Some(key) => key,
+            None => self.screen.read_key(wait),
+        }
+    }
+
+    /// Queue input that is available without waiting, up to `BURST_LIMIT` keys, so a burst
+    /// of input such as pasted text can be handled without polling between keys
+    pub fn read_burst(&mut self) {
+        self.flush_quietly();
+        while self.keys.len() < BURST_LIMIT && self.screen.input_pending() {
+            let key = self.screen.read_key(false);
+            if key.zchar().is_some() {
+                self.keys.push_back(key);
+            }
+        }
+    }
+
+    /// The next queued key, without reading the terminal
+    pub fn queued_key(&mut self) -> Option<InputEvent> {
+        self.keys.pop_front()
+    }
+
+    /// Drop queued keys up to, but not including, the next return
+    pub fn discard_queued_line(&mut self) {
+        while self
+            .keys
+            .front()
+            .is_some_and(|key| key.zchar() != Some(0x0d))
+        {
+            self.keys.pop_front();
+        }
     }
 
     pub fn backspace(&mut self) -> Result<(), RuntimeError> {
+        self.flush()?;
         self.screen.backspace()
     }
 
     // Housekeeping
+    /// Close the transcript, if any, and shut down the screen
     pub fn quit(&mut self) {
+        self.flush_quietly();
+        if let Some(mut f) = self.stream_2.take() {
+            if let Err(e) = f.0.flush() {
+                error!(target: "app::stream", "Error writing to transcript file: {}", e);
+            }
+        }
         self.screen.quit()
     }
 
+    pub fn interactive(&self) -> bool {
+        self.screen.interactive()
+    }
+
     pub fn error(&mut self, instruction: &str, message: &str, recoverable: bool) -> bool {
-        self.screen.error(instruction, message, recoverable)
+        self.flush_quietly();
+        let key = self.messages.get("error.continue_key");
+        let prompt = self
+            .messages
+            .format("prompt.error_continue", &[("key", key)]);
+        let continue_key = key.chars().next().unwrap_or('c');
+        self.screen
+            .error(instruction, message, &prompt, continue_key, recoverable)
+    }
+
+    pub fn interrupted(&mut self) -> bool {
+        self.screen.interrupted()
+    }
+
+    /// The capabilities at the terminal's new size, once, after it has been resized
+    pub fn resized(&mut self) -> Option<Capabilities> {
+        if self.screen.resized() {
+            Some(self.capabilities())
+        } else {
+            None
+        }
+    }
+
+    pub fn can_request_filename(&self) -> bool {
+        self.screen.can_request_filename()
+    }
+
+    pub fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        self.flush_quietly();
+        self.screen.request_filename(prompt, default)
+    }
+
+    pub fn show_message(&mut self, message: &str) {
+        self.flush_quietly();
+        self.screen.show_message(message)
+    }
+
+    pub fn menu(&mut self, title: &str, items: &[String]) -> Option<char> {
+        self.flush_quietly();
+        self.screen.menu(title, items)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, path::Path};
+    use std::{
+        fs::{self, File},
+        path::Path,
+    };
 
     use crate::{
-        assert_ok, assert_ok_eq, assert_print,
+        assert_ok, assert_ok_eq, assert_print, assert_some_eq,
         test_util::{
-            backspace, beep, buffer_mode, colors, cursor, input, mock_state, quit, split, style,
-            test_map,
+            backspace, beep, buffer_mode, colors, cursor, input, mock_state, print, quit, set_size,
+            split, style, test_map, FullDiskWriter,
         },
+        zmachine::io::screen::headless::HeadlessTerminal,
     };
 
     use super::*;
@@ -470,6 +967,21 @@ mod tests {
         assert_eq!(io.default_colors(), (Color::White, Color::Black));
     }
 
+    #[test]
+    fn test_io_with_terminal() {
+        let terminal = HeadlessTerminal::new(30, 100);
+        let io = assert_ok!(IO::with_terminal(5, Config::default(), Box::new(terminal)));
+        assert_eq!(io.version, 5);
+        assert_eq!(io.rows(), 30);
+        assert_eq!(io.columns(), 100);
+        assert_eq!(io.screen.cursor(), (1, 1));
+        assert!(!io.interactive());
+
+        let terminal = HeadlessTerminal::new(30, 100);
+        let e = IO::with_terminal(2, Config::default(), Box::new(terminal)).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::UnsupportedVersion);
+    }
+
     #[test]
     fn test_io_stream_2() {
         let mut io = assert_ok!(IO::new(5, Config::default()));
@@ -481,6 +993,93 @@ mod tests {
         assert!(io.is_stream_2_open());
     }
 
+    #[test]
+    fn test_transcript_disk_full() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        let (writer, written, calls) = FullDiskWriter::new(10);
+        io.set_stream_2(writer);
+        assert!(io.enable_output_stream(2, None).is_ok());
+        assert!(io.buffer_mode(0).is_ok());
+        assert!(io
+            .print_vec(&"Hello, ".bytes().map(|x| x as u16).collect())
+            .is_ok());
+        assert!(!io.take_transcript_stopped());
+        // The disk fills up partway through
+        assert!(io
+            .print_vec(&"world".bytes().map(|x| x as u16).collect())
+            .is_ok());
+        assert_eq!(written.get(), 10);
+        assert!(!io.is_stream_2_open());
+        assert!(!io.is_stream_enabled(2));
+        assert!(io.take_transcript_stopped());
+        assert!(!io.take_transcript_stopped());
+
+        // Printing carries on, without trying the transcript again or repeating the message
+        let calls_at_stop = calls.get();
+        assert!(io
+            .print_vec(&"!".bytes().map(|x| x as u16).collect())
+            .is_ok());
+        assert_eq!(calls.get(), calls_at_stop);
+        let messages = Messages::default();
+        let reason = messages.get("transcript.disk_full");
+        let message = messages.format("transcript.stopped", &[("reason", reason)]);
+        assert_eq!(print().matches(&message).count(), 1);
+        assert!(print().ends_with(&format!("{}!", message)));
+    }
+
+    #[test]
+    fn test_reconcile_after_restore() {
+        let mut map = test_map(5);
+        map[0x200] = 0x12;
+        map[0x201] = 0x34;
+        let state = mock_state(map);
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.enable_output_stream(2, None).is_ok());
+        assert!(io.enable_output_stream(3, Some(0x200)).is_ok());
+        assert!(io
+            .print_vec(&"captured".bytes().map(|x| x as u16).collect())
+            .is_ok());
+        assert!(io.buffer_mode(0).is_ok());
+        assert!(io.split_window(3).is_ok());
+        assert_eq!(
+            io.snapshot(),
+            IoState {
+                output_streams: 0x7,
+                stream_3_tables: vec![0x200],
+                buffered: false,
+                split: true
+            }
+        );
+
+        // The restored transcript bit is clear
+        assert!(io.reconcile_after_restore(&state).is_ok());
+        let snapshot = io.snapshot();
+        assert_eq!(
+            snapshot,
+            IoState {
+                output_streams: 0x1,
+                stream_3_tables: vec![],
+                buffered: true,
+                split: false
+            }
+        );
+        assert!(snapshot.is_stream_enabled(1));
+        assert!(!snapshot.is_stream_enabled(3));
+        // Nothing was written to the abandoned table
+        assert_ok_eq!(state.read_word(0x200), 0x1234);
+    }
+
+    #[test]
+    fn test_reconcile_after_restore_transcript_bit_set() {
+        let mut map = test_map(5);
+        map[0x11] = 0x01;
+        let state = mock_state(map);
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.enable_output_stream(2, None).is_ok());
+        assert!(io.reconcile_after_restore(&state).is_ok());
+        assert!(io.is_stream_enabled(2));
+    }
+
     #[test]
     fn test_is_stream_enabled() {
         let io = assert_ok!(IO::new(5, Config::default()));
@@ -703,6 +1302,7 @@ mod tests {
         let mut io = assert_ok!(IO::new(5, Config::default()));
         io.buffered = true;
         assert!(io.print_vec(&"This is a very long string greater than 80 characters in length that will not be wrapped because buffering is not turned on".bytes().map(|x| x as u16).collect::<Vec<u16>>()).is_ok());
+        assert!(io.flush().is_ok());
         assert_print!("This is a very long string greater than 80 characters in length that will not be wrapped because buffering is not turned on");
         assert_ok_eq!(io.cursor(), (2, 46));
     }
@@ -717,6 +1317,7 @@ mod tests {
         assert!(Path::new("test-buffer.txt").exists());
         io.buffered = true;
         assert!(io.print_vec(&"This is a very long string greater than 80 characters in length that will not be wrapped because buffering is not turned on".bytes().map(|x| x as u16).collect::<Vec<u16>>()).is_ok());
+        assert!(io.flush().is_ok());
         let s = fs::read_to_string(Path::new("test-buffer.txt"));
         assert!(fs::remove_file(Path::new("test-buffer.txt")).is_ok());
         assert_ok_eq!(s, "This is a very long string greater than 80 characters in length that will not \nbe wrapped because buffering is not turned on");
@@ -787,6 +1388,34 @@ mod tests {
         assert_ok_eq!(state.read_byte(0x220), b'1');
     }
 
+    #[test]
+    fn test_echo_stream_1_disabled() {
+        let map = test_map(5);
+        let mut state = mock_state(map);
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.disable_output_stream(&mut state, 1).is_ok());
+        assert!(io.print_vec(&vec![b'x' as u16]).is_ok());
+        assert!(io
+            .echo(&[b'l' as u16, b'o' as u16, b'o' as u16, b'k' as u16])
+            .is_ok());
+        assert_print!("look");
+        assert_ok_eq!(io.cursor(), (1, 5));
+    }
+
+    #[test]
+    fn test_echo_stream_3() {
+        let map = test_map(5);
+        let mut state = mock_state(map);
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.enable_output_stream(3, Some(0x200)).is_ok());
+        assert!(io
+            .echo(&[b'l' as u16, b'o' as u16, b'o' as u16, b'k' as u16])
+            .is_ok());
+        assert_print!("look");
+        assert!(io.disable_output_stream(&mut state, 3).is_ok());
+        assert_ok_eq!(state.read_word(0x200), 0);
+    }
+
     #[test]
     fn test_new_line_stream_1() {
         let mut io = assert_ok!(IO::new(5, Config::default()));
@@ -921,7 +1550,7 @@ mod tests {
         let mut io = assert_ok!(IO::new(5, Config::default()));
         assert!(io.split_window(10).is_ok());
         assert!(io.set_cursor(15, 5).is_ok());
-        assert!(io.erase_line().is_ok());
+        assert!(io.erase_line(1).is_ok());
         assert_print!(&vec![' '; 75].iter().collect::<String>());
         assert_eq!(split(), 10);
     }
@@ -941,10 +1570,136 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_status_line_40_columns_truncated() {
+        set_size(24, 40);
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io
+            .status_line(
+                &mut "West of the Great Underground Reservoir"
+                    .bytes()
+                    .map(|x| x as u16)
+                    .collect::<Vec<u16>>(),
+                &mut "12:30 PM".bytes().map(|x| x as u16).collect::<Vec<u16>>()
+            )
+            .is_ok());
+        assert_print!(" West of the Great Undergro... 12:30 PM ");
+    }
+
+    #[test]
+    fn test_status_line_132_columns() {
+        set_size(24, 132);
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io
+            .status_line(
+                &mut "(Darkness)".bytes().map(|x| x as u16).collect::<Vec<u16>>(),
+                &mut "0/999".bytes().map(|x| x as u16).collect::<Vec<u16>>()
+            )
+            .is_ok());
+        assert_print!(&format!(" (Darkness){}0/999 ", " ".repeat(115)));
+    }
+
+    #[test]
+    fn test_status_line_double_width() {
+        set_size(24, 24);
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io
+            .status_line(
+                &mut "日本語の部屋"
+                    .chars()
+                    .map(|c| c as u16)
+                    .collect::<Vec<u16>>(),
+                &mut "0/1".bytes().map(|x| x as u16).collect::<Vec<u16>>()
+            )
+            .is_ok());
+        assert_print!(" 日本語の部屋       0/1 ");
+    }
+
+    #[test]
+    fn test_status_line_double_width_truncated() {
+        set_size(24, 16);
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io
+            .status_line(
+                &mut "日本語の部屋"
+                    .chars()
+                    .map(|c| c as u16)
+                    .collect::<Vec<u16>>(),
+                &mut "0/1".bytes().map(|x| x as u16).collect::<Vec<u16>>()
+            )
+            .is_ok());
+        assert_print!(" 日本語...  0/1 ");
+    }
+
+    #[test]
+    fn test_status_line_too_narrow() {
+        set_size(24, 8);
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io
+            .status_line(
+                &mut "(Darkness)".bytes().map(|x| x as u16).collect::<Vec<u16>>(),
+                &mut "999/9999".bytes().map(|x| x as u16).collect::<Vec<u16>>()
+            )
+            .is_ok());
+        assert_print!(" 999/999");
+    }
+
+    #[test]
+    fn test_debug_hud() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.set_cursor(10, 5).is_ok());
+        assert!(io.debug_hud("HUD").is_ok());
+        assert_print!(&format!(" HUD{}", " ".repeat(76)));
+        // The cursor is left where it was
+        assert_ok_eq!(io.cursor(), (10, 5));
+    }
+
+    #[test]
+    fn test_debug_hud_upper_window() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.split_window(1).is_ok());
+        assert!(io.debug_hud("HUD").is_ok());
+        assert_print!("");
+    }
+
+    #[test]
+    fn test_debug_hud_v3() {
+        let mut io = assert_ok!(IO::new(3, Config::default()));
+        assert!(io.debug_hud("HUD").is_ok());
+        assert_print!("");
+    }
+
+    #[test]
+    fn test_debug_hud_not_transcripted() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        let f = assert_ok!(File::create(Path::new("test-hud.txt")));
+        io.set_stream_2(f);
+        assert!(io.enable_output_stream(2, None).is_ok());
+        assert!(io.debug_hud("HUD").is_ok());
+        let s = fs::read_to_string(Path::new("test-hud.txt"));
+        assert!(fs::remove_file(Path::new("test-hud.txt")).is_ok());
+        assert_ok_eq!(s, "");
+    }
+
     #[test]
     fn test_set_font() {
         let mut io = assert_ok!(IO::new(5, Config::default()));
         assert_ok_eq!(io.set_font(3), 1);
+        assert_ok_eq!(io.set_font(2), 0);
+        assert_ok_eq!(io.set_font(0), 3);
+    }
+
+    #[test]
+    fn test_set_font_set_window() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.split_window(2).is_ok());
+        assert!(io.set_window(1).is_ok());
+        assert_ok_eq!(io.set_font(4), 1);
+        assert!(io.set_window(0).is_ok());
+        assert_ok_eq!(io.set_font(0), 1);
+        assert!(io.set_window(1).is_ok());
+        assert_ok_eq!(io.set_font(0), 4);
+        assert!(io.is_fixed_pitch());
     }
 
     #[test]
@@ -954,6 +1709,167 @@ mod tests {
         assert_eq!(style(), 1);
     }
 
+    #[test]
+    fn test_set_fixed_pitch() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(!io.is_fixed_pitch());
+        io.set_fixed_pitch(true);
+        assert!(io.is_fixed_pitch());
+        io.set_fixed_pitch(false);
+        assert!(!io.is_fixed_pitch());
+        assert!(io.set_text_style(Style::Fixed as u16).is_ok());
+        assert!(io.is_fixed_pitch());
+    }
+
+    fn wrap_text() -> Vec<u16> {
+        format!("{} {} next", "a".repeat(20), "b".repeat(58))
+            .bytes()
+            .map(|x| x as u16)
+            .collect()
+    }
+
+    #[test]
+    fn test_print_vec_wrap_proportional() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.print_vec(&wrap_text()).is_ok());
+        // The second word's trailing space doesn't fit in the last column
+        assert_ok_eq!(io.cursor(), (2, 64));
+    }
+
+    #[test]
+    fn test_print_vec_wrap_fixed_pitch() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        io.set_fixed_pitch(true);
+        assert!(io.print_vec(&wrap_text()).is_ok());
+        // The second word fills the line, with its trailing space in the last column
+        assert_ok_eq!(io.cursor(), (2, 5));
+    }
+
+    fn text(s: &str) -> Vec<u16> {
+        s.chars().map(|c| c as u16).collect()
+    }
+
+    #[test]
+    fn test_print_vec_wrap_double_width() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io
+            .print_vec(&text(&format!("{} ", "日".repeat(38))))
+            .is_ok());
+        assert_ok_eq!(io.cursor(), (1, 78));
+        // Two characters, but three cells with the trailing space
+        assert!(io.print_vec(&text("本 ")).is_ok());
+        assert_ok_eq!(io.cursor(), (2, 4));
+    }
+
+    #[test]
+    fn test_print_vec_word_in_pieces() {
+        let mut io = assert_ok!(IO::new(5, Config::default()));
+        assert!(io.print_vec(&text(&format!("{} ", "x".repeat(75)))).is_ok());
+        assert_ok_eq!(io.cursor(), (1, 77));
+        // "word." is held back until it's complete, then wraps as a whole
+        assert!(io.print_vec(&text("wo")).is_ok());
+        assert!(io.print_vec(&text("rd.")).is_ok());
+        assert!(!print().contains("wo"));
+        assert!(io.flush().is_ok());
+        assert!(print().ends_with("word."));
+        assert_ok_eq!(io.cursor(), (2, 6));
+    }
+
+    type Operation = fn(&mut IO) -> Result<(), RuntimeError>;
+
+    #[test]
+    fn test_print_vec_flush_points() {
+        let operations: [Operation; 9] = [
+            |io| io.set_window(0),
+            |io| io.set_cursor(1, 1),
+            |io| io.erase_window(0),
+            |io| io.erase_line(1),
+            |io| io.set_text_style(2),
+            |io| io.set_colors(3, 4),
+            |io| io.buffer_mode(0),
+            |io| io.new_line(),
+            |io| {
+                io.read_key(false);
+                Ok(())
+            },
+        ];
+        for (i, operation) in operations.iter().enumerate() {
+            let mut io = assert_ok!(IO::new(5, Config::default()));
+            let word = format!("word{}", i);
+            assert!(io.print_vec(&text(&word)).is_ok());
+            assert!(!print().contains(&word), "{}", i);
+            assert!(operation(&mut io).is_ok());
+            assert!(print().contains(&word), "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_menu_capture() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("capture.jsonl");
+        set_size(6, 20);
+        let mut config = Config::default();
+        config.set_capture(&path.to_string_lossy());
+        let mut io = assert_ok!(IO::new(5, config));
+
+        assert!(io.split_window(3).is_ok());
+        assert!(io.print_vec(&text("Choose one")).is_ok());
+        // Draw the menu
+        assert!(io.set_window(1).is_ok());
+        assert!(io.set_text_style(Style::Reverse as u16).is_ok());
+        assert!(io.set_cursor(1, 1).is_ok());
+        assert!(io.print_vec(&text(" Title ")).is_ok());
+        assert!(io.set_text_style(0).is_ok());
+        assert!(io.set_cursor(2, 3).is_ok());
+        assert!(io.print_vec(&text("Option A")).is_ok());
+        assert!(io.set_cursor(3, 3).is_ok());
+        assert!(io.print_vec(&text("Option")).is_ok());
+        assert!(io.set_text_style(Style::Reverse as u16).is_ok());
+        assert!(io.print_vec(&text(" B")).is_ok());
+        assert!(io.set_window(0).is_ok());
+        assert!(io.print_vec(&text(" now.")).is_ok());
+        io.quit();
+
+        let capture = assert_ok!(fs::read_to_string(&path));
+        let events: Vec<serde_yaml::Value> = capture
+            .lines()
+            .map(|l| assert_ok!(serde_yaml::from_str(l)))
+            .collect();
+        let (screen, prints) = events.split_last().unwrap();
+        let rows: Vec<&str> = screen["text"].as_str().unwrap().split('\n').collect();
+        assert_eq!(
+            rows,
+            [
+                " Title",
+                "  Option A",
+                "  Option B",
+                "Choose one now.",
+                "",
+                ""
+            ]
+        );
+        // Each piece of text is drawn in the window and style it was printed with
+        let drawn = |text: &str| {
+            prints
+                .iter()
+                .find(|e| e["text"].as_str() == Some(text))
+                .map(|e| {
+                    (
+                        e["window"].as_u64().unwrap(),
+                        e["row"].as_u64().unwrap(),
+                        e["column"].as_u64().unwrap(),
+                        e["style"].as_u64().unwrap(),
+                    )
+                })
+        };
+        assert_some_eq!(drawn("one"), (0, 4, 8, 0));
+        assert_some_eq!(drawn(" Title "), (1, 1, 1, 1));
+        assert_some_eq!(drawn("Option A"), (1, 2, 3, 0));
+        assert_some_eq!(drawn("Option"), (1, 3, 3, 0));
+        assert_some_eq!(drawn(" B"), (1, 3, 9, 1));
+        assert_some_eq!(drawn("now."), (0, 4, 12, 0));
+    }
+
     #[test]
     fn test_cursor() {
         let mut io = assert_ok!(IO::new(3, Config::default()));
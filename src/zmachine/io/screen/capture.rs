@@ -0,0 +1,589 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use super::{curses::map_output, Capabilities, CellStyle, Color, InputEvent, StyleMap, Terminal};
+
+/// A single change to the screen, as recorded by [`CapturingTerminal`].
+///
+/// Rows and columns are 1-based, matching the rest of the screen model.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScreenEvent {
+    /// Text printed left-to-right starting at `row`, `column`, on the Z-Machine colour
+    /// number `background`
+    Print {
+        window: u8,
+        row: u32,
+        column: u32,
+        style: u8,
+        background: u8,
+        text: String,
+    },
+    /// The line at `row` was removed and the lines below it moved up
+    Scroll { row: u32 },
+    /// The character at `row`, `column` was erased
+    Backspace { row: u32, column: u32 },
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl ScreenEvent {
+    /// Single-line JSON representation of the event
+    pub fn to_json(&self) -> String {
+        match self {
+            ScreenEvent::Print {
+                window,
+                row,
+                column,
+                style,
+                background,
+                text,
+            } => format!(
+                "{{\"event\":\"print\",\"window\":{},\"row\":{},\"column\":{},\"style\":{},\"background\":{},\"text\":{}}}",
+                window,
+                row,
+                column,
+                style,
+                background,
+                json_string(text)
+            ),
+            ScreenEvent::Scroll { row } => format!("{{\"event\":\"scroll\",\"row\":{}}}", row),
+            ScreenEvent::Backspace { row, column } => format!(
+                "{{\"event\":\"backspace\",\"row\":{},\"column\":{}}}",
+                row, column
+            ),
+        }
+    }
+
+    /// Parse an event written by [`ScreenEvent::to_json`].  Returns None for lines that
+    /// aren't events, such as the final screen contents.
+    pub fn from_json(line: &str) -> Option<ScreenEvent> {
+        // JSON is a subset of YAML, which saves bringing in a JSON parser
+        let value: serde_yaml::Value = serde_yaml::from_str(line).ok()?;
+        let number = |key: &str| value[key].as_u64().map(|n| n as u32);
+        match value["event"].as_str()? {
+            "print" => Some(ScreenEvent::Print {
+                window: number("window")? as u8,
+                row: number("row")?,
+                column: number("column")?,
+                style: number("style")? as u8,
+                background: number("background")? as u8,
+                text: value["text"].as_str()?.to_string(),
+            }),
+            "scroll" => Some(ScreenEvent::Scroll {
+                row: number("row")?,
+            }),
+            "backspace" => Some(ScreenEvent::Backspace {
+                row: number("row")?,
+                column: number("column")?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Screen capture kept in memory rather than written to a file.
+///
+/// Clones share the same buffer, so one can be handed to a [`CapturingTerminal`] and
+/// another kept to read what was captured.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl CaptureBuffer {
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Terminal that records all screen output as [`ScreenEvent`]s while passing
+/// everything through to another terminal.
+///
+/// When created with an output, such as a file or a [`CaptureBuffer`], events are
+/// written to it as JSON lines each time the screen is flushed, followed by the final
+/// screen contents on quit.
+pub struct CapturingTerminal {
+    terminal: Box<dyn Terminal>,
+    window: u8,
+    events: Vec<ScreenEvent>,
+    output: Option<Box<dyn Write>>,
+    // Number of events already written to the output file
+    written: usize,
+}
+
+impl CapturingTerminal {
+    pub fn new(terminal: Box<dyn Terminal>, output: Option<Box<dyn Write>>) -> CapturingTerminal {
+        CapturingTerminal {
+            terminal,
+            window: 0,
+            events: Vec::new(),
+            output,
+            written: 0,
+        }
+    }
+
+    pub fn events(&self) -> &[ScreenEvent] {
+        &self.events
+    }
+
+    /// Replay the captured events onto a blank screen and return the resulting
+    /// character grid, one line per row with trailing spaces removed.
+    pub fn to_plain_text(&self) -> String {
+        let (rows, columns) = self.terminal.size();
+        let blank = vec![' '; columns as usize];
+        let mut grid = vec![blank.clone(); rows as usize];
+        for event in self.events() {
+            match event {
+                ScreenEvent::Print {
+                    row, column, text, ..
+                } => {
+                    if let Some(line) = grid.get_mut(*row as usize - 1) {
+                        for (i, c) in text.chars().enumerate() {
+                            if let Some(cell) = line.get_mut(*column as usize - 1 + i) {
+                                *cell = c;
+                            }
+                        }
+                    }
+                }
+                ScreenEvent::Scroll { row } => {
+                    if (*row as usize) <= grid.len() {
+                        grid.remove(*row as usize - 1);
+                        grid.push(blank.clone());
+                    }
+                }
+                ScreenEvent::Backspace { row, column } => {
+                    if let Some(line) = grid.get_mut(*row as usize - 1) {
+                        if let Some(cell) = line.get_mut(*column as usize - 1) {
+                            *cell = ' ';
+                        }
+                    }
+                }
+            }
+        }
+
+        grid.iter()
+            .map(|line| line.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn write_events(&mut self) {
+        if let Some(f) = self.output.as_mut() {
+            for event in &self.events[self.written..] {
+                if let Err(e) = writeln!(f, "{}", event.to_json()) {
+                    error!(target: "app::screen", "Error writing screen capture: {}", e);
+                    self.output = None;
+                    return;
+                }
+            }
+            self.written = self.events.len();
+        }
+    }
+
+    fn record(&mut self, c: char, row: u32, column: u32, style: u8, background: u8) {
+        // Extend the last print event when the character follows on from it and
+        // the event hasn't been written out yet
+        if self.events.len() > self.written {
+            if let Some(ScreenEvent::Print {
+                window: w,
+                row: r,
+                column: col,
+                style: s,
+                background: b,
+                text,
+            }) = self.events.last_mut()
+            {
+                if *w == self.window
+                    && *r == row
+                    && *s == style
+                    && *b == background
+                    && *col + text.chars().count() as u32 == column
+                {
+                    text.push(c);
+                    return;
+                }
+            }
+        }
+
+        self.events.push(ScreenEvent::Print {
+            window: self.window,
+            row,
+            column,
+            style,
+            background,
+            text: c.to_string(),
+        });
+    }
+}
+
+impl Terminal for CapturingTerminal {
+    fn type_name(&self) -> &str {
+        "CapturingTerminal"
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.terminal.size()
+    }
+
+    fn print_at(
+        &mut self,
+        zchar: u16,
+        row: u32,
+        column: u32,
+        colors: (Color, Color),
+        style: &CellStyle,
+        font: u8,
+    ) {
+        self.record(
+            map_output(zchar, font),
+            row,
+            column,
+            style.mask,
+            colors.1 as u8,
+        );
+        self.terminal
+            .print_at(zchar, row, column, colors, style, font);
+    }
+
+    fn flush(&mut self) {
+        self.write_events();
+        self.terminal.flush();
+    }
+
+    fn read_key(&mut self, wait: bool) -> InputEvent {
+        self.terminal.read_key(wait)
+    }
+
+    fn scroll(&mut self, row: u32) {
+        self.events.push(ScreenEvent::Scroll { row });
+        self.terminal.scroll(row);
+    }
+
+    fn backspace(&mut self, at: (u32, u32)) {
+        self.events.push(ScreenEvent::Backspace {
+            row: at.0,
+            column: at.1,
+        });
+        self.terminal.backspace(at);
+    }
+
+    fn beep(&mut self) {
+        self.terminal.beep();
+    }
+
+    fn move_cursor(&mut self, at: (u32, u32)) {
+        self.terminal.move_cursor(at);
+    }
+
+    fn reset(&mut self) {
+        self.terminal.reset();
+    }
+
+    fn quit(&mut self) {
+        self.write_events();
+        let screen = format!(
+            "{{\"event\":\"screen\",\"text\":{}}}",
+            json_string(&self.to_plain_text())
+        );
+        if let Some(f) = self.output.as_mut() {
+            if let Err(e) = writeln!(f, "{}", screen) {
+                error!(target: "app::screen", "Error writing screen capture: {}", e);
+            }
+        }
+        self.terminal.quit();
+    }
+
+    fn set_colors(&mut self, colors: (Color, Color)) {
+        self.terminal.set_colors(colors);
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.terminal.capabilities()
+    }
+
+    fn split_window(&mut self, lines: u32) {
+        self.terminal.split_window(lines);
+    }
+
+    fn set_window(&mut self, window: u8) {
+        self.window = window;
+        self.terminal.set_window(window);
+    }
+
+    fn erase_window(&mut self, window: i8) {
+        self.terminal.erase_window(window);
+    }
+
+    fn erase_line(&mut self) {
+        self.terminal.erase_line();
+    }
+
+    fn set_style(&mut self, style: u8) {
+        self.terminal.set_style(style);
+    }
+
+    fn buffer_mode(&mut self, mode: u16) {
+        self.terminal.buffer_mode(mode);
+    }
+
+    fn output_stream(&mut self, stream: u8, table: Option<usize>) {
+        self.terminal.output_stream(stream, table);
+    }
+
+    fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        prompt: &str,
+        continue_key: char,
+        recoverable: bool,
+    ) -> bool {
+        self.terminal
+            .error(instruction, message, prompt, continue_key, recoverable)
+    }
+
+    fn interrupted(&mut self) -> bool {
+        self.terminal.interrupted()
+    }
+
+    fn suspend_requested(&mut self) -> bool {
+        self.terminal.suspend_requested()
+    }
+
+    fn suspend(&mut self) {
+        self.terminal.suspend()
+    }
+
+    fn resume(&mut self) {
+        self.terminal.resume()
+    }
+
+    fn input_pending(&mut self) -> bool {
+        self.terminal.input_pending()
+    }
+
+    fn can_request_filename(&self) -> bool {
+        self.terminal.can_request_filename()
+    }
+
+    fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        self.terminal.request_filename(prompt, default)
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.terminal.show_message(message)
+    }
+
+    fn set_style_map(&mut self, style_map: StyleMap) {
+        self.terminal.set_style_map(style_map)
+    }
+
+    fn menu(&mut self, title: &str, items: &[String]) -> Option<char> {
+        self.terminal.menu(title, items)
+    }
+
+    // Capture only records the screen; the wrapped terminal decides who is playing
+    fn interactive(&self) -> bool {
+        self.terminal.interactive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use crate::{
+        assert_ok,
+        test_util::{print, scroll},
+        zmachine::io::screen::{curses::test_terminal, Style},
+    };
+
+    use super::*;
+
+    fn print_str(terminal: &mut CapturingTerminal, s: &str, row: u32, column: u32, style: u8) {
+        let mut cell_style = CellStyle::new();
+        cell_style.set(style);
+        for (i, c) in s.chars().enumerate() {
+            terminal.print_at(
+                c as u16,
+                row,
+                column + i as u32,
+                (Color::White, Color::Black),
+                &cell_style,
+                1,
+            );
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let mut terminal = CapturingTerminal::new(test_terminal::new_terminal(), None);
+        print_str(&mut terminal, "Hello", 2, 1, 0);
+        print_str(&mut terminal, "world", 2, 7, 0);
+        terminal.set_window(1);
+        print_str(&mut terminal, "Status", 1, 1, Style::Reverse as u8);
+        terminal.scroll(2);
+        terminal.backspace((1, 6));
+        assert_eq!(
+            terminal.events(),
+            &[
+                ScreenEvent::Print {
+                    window: 0,
+                    row: 2,
+                    column: 1,
+                    style: 0,
+                    background: 2,
+                    text: "Hello".to_string()
+                },
+                ScreenEvent::Print {
+                    window: 0,
+                    row: 2,
+                    column: 7,
+                    style: 0,
+                    background: 2,
+                    text: "world".to_string()
+                },
+                ScreenEvent::Print {
+                    window: 1,
+                    row: 1,
+                    column: 1,
+                    style: 1,
+                    background: 2,
+                    text: "Status".to_string()
+                },
+                ScreenEvent::Scroll { row: 2 },
+                ScreenEvent::Backspace { row: 1, column: 6 }
+            ]
+        );
+        // Output is passed through to the wrapped terminal
+        assert_eq!(print(), "HelloworldStatus");
+        assert_eq!(scroll(), 2);
+    }
+
+    #[test]
+    fn test_to_plain_text() {
+        let mut terminal = CapturingTerminal::new(test_terminal::new_terminal(), None);
+        print_str(&mut terminal, "Status", 1, 1, Style::Reverse as u8);
+        print_str(&mut terminal, "First", 2, 1, 0);
+        print_str(&mut terminal, "Second", 3, 3, 0);
+        terminal.scroll(2);
+        terminal.backspace((2, 8));
+        let text = terminal.to_plain_text();
+        let lines: Vec<&str> = text.split('\n').collect();
+        assert_eq!(lines.len(), 24);
+        assert_eq!(lines[0], "Status");
+        assert_eq!(lines[1], "  Secon");
+        assert!(lines[2..].iter().all(|l| l.is_empty()));
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            ScreenEvent::Print {
+                window: 1,
+                row: 1,
+                column: 2,
+                style: 3,
+                background: 6,
+                text: "\"a\\b\"".to_string()
+            }
+            .to_json(),
+            r#"{"event":"print","window":1,"row":1,"column":2,"style":3,"background":6,"text":"\"a\\b\""}"#
+        );
+        assert_eq!(
+            ScreenEvent::Scroll { row: 24 }.to_json(),
+            r#"{"event":"scroll","row":24}"#
+        );
+        assert_eq!(
+            ScreenEvent::Backspace { row: 24, column: 5 }.to_json(),
+            r#"{"event":"backspace","row":24,"column":5}"#
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        let events = [
+            ScreenEvent::Print {
+                window: 1,
+                row: 1,
+                column: 2,
+                style: 3,
+                background: 6,
+                text: "\"a\\b\" \u{1b}: yes".to_string(),
+            },
+            ScreenEvent::Scroll { row: 24 },
+            ScreenEvent::Backspace { row: 24, column: 5 },
+        ];
+        for event in events {
+            assert_eq!(ScreenEvent::from_json(&event.to_json()), Some(event));
+        }
+        assert!(ScreenEvent::from_json(r#"{"event":"screen","text":"Hi"}"#).is_none());
+        assert!(ScreenEvent::from_json(r#"{"event":"scroll"}"#).is_none());
+        assert!(ScreenEvent::from_json("not json").is_none());
+    }
+
+    #[test]
+    fn test_capture_buffer() {
+        let buffer = CaptureBuffer::default();
+        let mut terminal = CapturingTerminal::new(
+            test_terminal::new_terminal(),
+            Some(Box::new(buffer.clone())),
+        );
+        print_str(&mut terminal, "Hi", 1, 1, 0);
+        assert_eq!(buffer.contents(), "");
+        terminal.flush();
+        assert_eq!(
+            buffer.contents(),
+            format!(
+                "{}\n",
+                r#"{"event":"print","window":0,"row":1,"column":1,"style":0,"background":2,"text":"Hi"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_capture_file() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("capture.jsonl");
+        let file = assert_ok!(File::create(&path));
+        let mut terminal =
+            CapturingTerminal::new(test_terminal::new_terminal(), Some(Box::new(file)));
+        print_str(&mut terminal, "Hi", 1, 1, 0);
+        terminal.flush();
+        print_str(&mut terminal, "!", 1, 3, 0);
+        terminal.quit();
+        assert_eq!(
+            assert_ok!(fs::read_to_string(&path)),
+            format!(
+                "{}\n{}\n{}\n",
+                r#"{"event":"print","window":0,"row":1,"column":1,"style":0,"background":2,"text":"Hi"}"#,
+                r#"{"event":"print","window":0,"row":1,"column":3,"style":0,"background":2,"text":"!"}"#,
+                format!(r#"{{"event":"screen","text":"Hi!{}"}}"#, "\\n".repeat(23))
+            )
+        );
+    }
+}
@@ -0,0 +1,765 @@
+use std::{
+    io::{self, Stdout, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEventKind,
+    },
+    queue,
+    style::{
+        Attribute as CtAttribute, Color as CtColor, Print, SetAttribute, SetBackgroundColor,
+        SetForegroundColor,
+    },
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle},
+};
+
+use crate::zmachine::io::screen::{
+    display_width, Attribute, Capabilities, CellStyle, Color, InputEvent, StyleMap, Terminal,
+};
+
+// Set from the SIGINT handler or when Ctrl-C is read during input
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Set from the SIGTSTP handler or when Ctrl-Z is read during input.  The terminal is only
+// suspended when the interpreter checks for it.
+static SUSPEND: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle_sigtstp(_signal: libc::c_int) {
+    SUSPEND.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_sigtstp() {
+    unsafe {
+        libc::signal(
+            libc::SIGTSTP,
+            handle_sigtstp as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+// Raw mode turns off the keys that raise signals.  Outside of input they're turned back on,
+// as curses' cbreak mode does, so Ctrl-C and Ctrl-Z work while the game is running.
+#[cfg(unix)]
+fn set_signal_keys(on: bool) {
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) == 0 {
+            if on {
+                termios.c_lflag |= libc::ISIG;
+            } else {
+                termios.c_lflag &= !libc::ISIG;
+            }
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn set_signal_keys(_on: bool) {}
+
+// Clicks closer together than this are a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+fn check(result: io::Result<()>) {
+    if let Err(e) = result {
+        error!(target: "app::screen", "Terminal error: {}", e);
+    }
+}
+
+// The right half of a double-width character, which isn't drawn
+const WIDE_TAIL: char = '\0';
+
+// A character on the screen, kept so anything drawn over the game window can be undone
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ShadowCell {
+    c: char,
+    foreground: CtColor,
+    background: CtColor,
+    attributes: &'static [CtAttribute],
+}
+
+// Every combination of attributes that can be drawn, indexed by the mask built in print_at
+const ATTRIBUTE_SETS: [&[CtAttribute]; 32] = {
+    use CtAttribute::*;
+    [
+        &[],
+        &[Bold],
+        &[Italic],
+        &[Bold, Italic],
+        &[Underlined],
+        &[Bold, Underlined],
+        &[Italic, Underlined],
+        &[Bold, Italic, Underlined],
+        &[Reverse],
+        &[Bold, Reverse],
+        &[Italic, Reverse],
+        &[Bold, Italic, Reverse],
+        &[Underlined, Reverse],
+        &[Bold, Underlined, Reverse],
+        &[Italic, Underlined, Reverse],
+        &[Bold, Italic, Underlined, Reverse],
+        &[Dim],
+        &[Bold, Dim],
+        &[Italic, Dim],
+        &[Bold, Italic, Dim],
+        &[Underlined, Dim],
+        &[Bold, Underlined, Dim],
+        &[Italic, Underlined, Dim],
+        &[Bold, Italic, Underlined, Dim],
+        &[Reverse, Dim],
+        &[Bold, Reverse, Dim],
+        &[Italic, Reverse, Dim],
+        &[Bold, Italic, Reverse, Dim],
+        &[Underlined, Reverse, Dim],
+        &[Bold, Underlined, Reverse, Dim],
+        &[Italic, Underlined, Reverse, Dim],
+        &[Bold, Italic, Underlined, Reverse, Dim],
+    ]
+};
+
+pub struct CTTerminal {
+    out: Stdout,
+    rows: u32,
+    columns: u32,
+    // What has been drawn, by row then column
+    cells: Vec<Vec<ShadowCell>>,
+    cursor: (u32, u32),
+    // Where the terminal will print the next character, and the colours and attributes it
+    // will print in, when known, so they're only sent when they change
+    print_position: Option<(u32, u32)>,
+    pen: Option<(CtColor, CtColor, &'static [CtAttribute])>,
+    colors: (Color, Color),
+    style_map: StyleMap,
+    // Top left corner and size of an interpreter message shown until the next key
+    message: Option<(u32, u32, u32, u32)>,
+    // When and where the last click was, to spot a double click
+    last_click: Option<(Instant, u16, u16)>,
+    // A key read by input_pending, returned by the next read_key
+    pending: Option<InputEvent>,
+    // Set when the terminal has changed size, until the screen asks
+    resized: bool,
+}
+
+pub fn new_terminal() -> Box<dyn Terminal> {
+    Box::new(CTTerminal::new())
+}
+
+fn as_color(color: Color, bright: bool) -> CtColor {
+    match (color, bright) {
+        (Color::Black, false) => CtColor::Black,
+        (Color::Red, false) => CtColor::DarkRed,
+        (Color::Green, false) => CtColor::DarkGreen,
+        (Color::Yellow, false) => CtColor::DarkYellow,
+        (Color::Blue, false) => CtColor::DarkBlue,
+        (Color::Magenta, false) => CtColor::DarkMagenta,
+        (Color::Cyan, false) => CtColor::DarkCyan,
+        (Color::White, false) => CtColor::Grey,
+        (Color::Black, true) => CtColor::DarkGrey,
+        (Color::Red, true) => CtColor::Red,
+        (Color::Green, true) => CtColor::Green,
+        (Color::Yellow, true) => CtColor::Yellow,
+        (Color::Blue, true) => CtColor::Blue,
+        (Color::Magenta, true) => CtColor::Magenta,
+        (Color::Cyan, true) => CtColor::Cyan,
+        (Color::White, true) => CtColor::White,
+    }
+}
+
+impl CTTerminal {
+    pub fn new() -> CTTerminal {
+        info!(target: "app::screen", "Initialize crossterm terminal");
+        let mut out = io::stdout();
+        check(terminal::enable_raw_mode());
+        set_signal_keys(true);
+        check(queue!(
+            out,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            Hide,
+            SetTitle("mxyzptlk - a rusty z-machine interpreter"),
+        ));
+
+        // Ctrl-C outside of input raises SIGINT, which is recorded and checked by the
+        // interpreter between instructions.
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        // Ctrl-Z is deferred the same way
+        #[cfg(unix)]
+        install_sigtstp();
+
+        let (columns, rows) = terminal::size().unwrap_or((80, 24));
+        let mut terminal = CTTerminal {
+            out,
+            rows: rows as u32,
+            columns: columns as u32,
+            cells: Vec::new(),
+            cursor: (1, 1),
+            print_position: None,
+            pen: None,
+            colors: (Color::White, Color::Black),
+            style_map: StyleMap::default(),
+            message: None,
+            last_click: None,
+            pending: None,
+            resized: false,
+        };
+        terminal.reset();
+        terminal.flush();
+        terminal
+    }
+
+    fn blank(&self) -> ShadowCell {
+        ShadowCell {
+            c: ' ',
+            foreground: as_color(self.colors.0, false),
+            background: as_color(self.colors.1, false),
+            attributes: ATTRIBUTE_SETS[0],
+        }
+    }
+
+    // Draw `cell` at 0-based `row`, `column` without recording it
+    fn draw(&mut self, row: u32, column: u32, cell: &ShadowCell) {
+        if cell.c == WIDE_TAIL {
+            return;
+        }
+        if self.print_position != Some((row, column)) {
+            check(queue!(self.out, MoveTo(column as u16, row as u16)));
+        }
+        let pen = (cell.foreground, cell.background, cell.attributes);
+        if self.pen != Some(pen) {
+            check(queue!(
+                self.out,
+                SetAttribute(CtAttribute::Reset),
+                SetForegroundColor(cell.foreground),
+                SetBackgroundColor(cell.background),
+            ));
+            for a in cell.attributes {
+                check(queue!(self.out, SetAttribute(*a)));
+            }
+            self.pen = Some(pen);
+        }
+        check(queue!(self.out, Print(cell.c)));
+        // Anything other than ASCII may not be one cell wide
+        self.print_position = cell.c.is_ascii().then_some((row, column + 1));
+    }
+
+    // Draw an area again from the recorded cells, after something has covered it
+    fn repaint(&mut self, top: u32, left: u32, height: u32, width: u32) {
+        for row in top..u32::min(top + height, self.rows) {
+            for column in left..u32::min(left + width, self.columns) {
+                let cell = self.cells[row as usize][column as usize];
+                self.draw(row, column, &cell);
+            }
+        }
+        self.move_cursor(self.cursor);
+        self.flush();
+    }
+
+    // Draw `text` in reverse video at 0-based `row`, `column`, over the game window
+    fn overlay(&mut self, row: u32, column: u32, text: &str) {
+        self.print_position = None;
+        self.pen = None;
+        check(queue!(
+            self.out,
+            MoveTo(column as u16, row as u16),
+            SetAttribute(CtAttribute::Reset),
+            SetForegroundColor(as_color(self.colors.0, false)),
+            SetBackgroundColor(as_color(self.colors.1, false)),
+            SetAttribute(CtAttribute::Reverse),
+            Print(text),
+            SetAttribute(CtAttribute::Reset),
+        ));
+    }
+
+    // A box in the middle of the screen with `lines` of text, returning its top left
+    // corner and size
+    fn dialog(&mut self, title: &str, lines: &[String]) -> (u32, u32, u32, u32) {
+        let width = lines
+            .iter()
+            .map(|l| l.chars().count())
+            .chain([title.chars().count()])
+            .max()
+            .unwrap_or(0) as u32
+            + 6;
+        let width = u32::min(width, self.columns);
+        let height = u32::min(lines.len() as u32 + 4, self.rows);
+        let (top, left) = ((self.rows - height) / 2, (self.columns - width) / 2);
+        for row in 0..height {
+            let text = if row == 0 || row == height - 1 {
+                format!("+{}+", "-".repeat(width as usize - 2))
+            } else {
+                let line = (row as usize)
+                    .checked_sub(2)
+                    .and_then(|i| lines.get(i))
+                    .map_or("", String::as_str);
+                format!("| {:w$} |", line, w = width as usize - 4)
+            };
+            let text: String = text.chars().take(width as usize).collect();
+            self.overlay(top + row, left, &text);
+        }
+        if !title.is_empty() {
+            let title: String = format!(" {} ", title)
+                .chars()
+                .take(width as usize - 2)
+                .collect();
+            self.overlay(top, left + 1, &title);
+        }
+        check(self.out.flush());
+        (top, left, height, width)
+    }
+
+    fn clear_message(&mut self) {
+        if let Some((top, left, height, width)) = self.message.take() {
+            self.repaint(top, left, height, width);
+        }
+    }
+
+    // Wait for a key, ignoring releases and anything that isn't a key
+    fn wait_for_key(&mut self) -> Option<KeyEvent> {
+        loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => return Some(key),
+                Ok(_) => {}
+                Err(e) => {
+                    error!(target: "app::screen", "Error reading input: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn key_to_u16(&self, key: KeyEvent) -> InputEvent {
+        match key.code {
+            KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                match c.to_ascii_lowercase() {
+                    c @ 'a'..='z' => super::char_to_u16((c as u8 - b'a' + 1) as char),
+                    _ => InputEvent::no_input(),
+                }
+            }
+            KeyCode::Char(c) => super::char_to_u16(c),
+            KeyCode::Enter => InputEvent::from_char(0x0d),
+            KeyCode::Backspace => InputEvent::from_char(8),
+            KeyCode::Up => InputEvent::from_char(129),
+            KeyCode::Down => InputEvent::from_char(130),
+            KeyCode::Left => InputEvent::from_char(131),
+            KeyCode::Right => InputEvent::from_char(132),
+            KeyCode::F(n @ 1..=12) => InputEvent::from_char(132 + n as u16),
+            _ => {
+                info!(target: "app::screen", "Unprocessed input: {:?}", key);
+                InputEvent::no_input()
+            }
+        }
+    }
+
+    fn event_to_u16(&mut self, event: Event) -> InputEvent {
+        match event {
+            Event::Key(key) if key.kind != KeyEventKind::Release => {
+                self.clear_message();
+                check(queue!(self.out, Hide));
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    if key.code == KeyCode::Char('c') {
+                        INTERRUPTED.store(true, Ordering::SeqCst);
+                    }
+                    #[cfg(unix)]
+                    if key.code == KeyCode::Char('z') {
+                        SUSPEND.store(true, Ordering::SeqCst);
+                        return InputEvent::no_input();
+                    }
+                }
+                self.key_to_u16(key)
+            }
+            Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                let (row, column) = (mouse.row + 1, mouse.column + 1);
+                let now = Instant::now();
+                let double = self.last_click.is_some_and(|(at, r, c)| {
+                    now.duration_since(at) < DOUBLE_CLICK && r == row && c == column
+                });
+                if double {
+                    self.last_click = None;
+                    InputEvent::from_mouse(253, row, column)
+                } else {
+                    self.last_click = Some((now, row, column));
+                    InputEvent::from_mouse(254, row, column)
+                }
+            }
+            Event::Resize(columns, rows) => {
+                // Keep the shadow cells the size of the terminal until the screen repaints
+                let blank = self.blank();
+                self.rows = rows as u32;
+                self.columns = columns as u32;
+                self.cells
+                    .resize(rows as usize, vec![blank; columns as usize]);
+                for row in self.cells.iter_mut() {
+                    row.resize(columns as usize, blank);
+                }
+                self.print_position = None;
+                self.resized = true;
+                InputEvent::no_input()
+            }
+            _ => InputEvent::no_input(),
+        }
+    }
+}
+
+impl Terminal for CTTerminal {
+    fn type_name(&self) -> &str {
+        "CTTerminal"
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.rows, self.columns)
+    }
+
+    fn print_at(
+        &mut self,
+        zchar: u16,
+        row: u32,
+        column: u32,
+        colors: (Color, Color),
+        style: &CellStyle,
+        font: u8,
+    ) {
+        if row == 0 || column == 0 || row > self.rows || column > self.columns {
+            return;
+        }
+
+        let style = self.style_map.attributes(style);
+        let mut mask = 0;
+        if style.contains(Attribute::Bold) {
+            mask |= 0x01;
+        }
+        if style.contains(Attribute::Italic) {
+            mask |= 0x02;
+        }
+        if style.contains(Attribute::Underline) {
+            mask |= 0x04;
+        }
+        if style.contains(Attribute::Reverse) {
+            mask |= 0x08;
+        }
+        if style.contains(Attribute::Dim) {
+            mask |= 0x10;
+        }
+        let mut attributes = ATTRIBUTE_SETS[mask];
+        if style.contains(Attribute::Blink) {
+            // Blinking text is rare enough that it isn't combined with anything else
+            attributes = &[CtAttribute::SlowBlink];
+        }
+        let cell = ShadowCell {
+            c: super::map_output(zchar, font),
+            foreground: as_color(colors.0, style.contains(Attribute::BrightForeground)),
+            background: as_color(colors.1, false),
+            attributes,
+        };
+        self.cells[row as usize - 1][column as usize - 1] = cell;
+        if display_width(zchar) == 2 && column < self.columns {
+            self.cells[row as usize - 1][column as usize].c = WIDE_TAIL;
+        }
+        self.draw(row - 1, column - 1, &cell);
+    }
+
+    fn flush(&mut self) {
+        check(self.out.flush());
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            rows: self.rows,
+            columns: self.columns,
+            ..Capabilities::default()
+        }
+    }
+
+    fn read_key(&mut self, wait: bool) -> InputEvent {
+        if let Some(key) = self.pending.take() {
+            return key;
+        }
+
+        check(queue!(self.out, Show));
+        self.move_cursor(self.cursor);
+        self.flush();
+        // Ctrl-C and Ctrl-Z are read as keys while waiting for input
+        set_signal_keys(false);
+        let event = if wait {
+            // Mouse movement and key releases are reported too, and skipped
+            loop {
+                match event::read() {
+                    Ok(e) => {
+                        let key = self.event_to_u16(e);
+                        if key != InputEvent::no_input()
+                            || SUSPEND.load(Ordering::SeqCst)
+                            || INTERRUPTED.load(Ordering::SeqCst)
+                            || self.resized
+                        {
+                            break key;
+                        }
+                    }
+                    Err(e) => {
+                        error!(target: "app::screen", "Error reading input: {}", e);
+                        break InputEvent::no_input();
+                    }
+                }
+            }
+        } else {
+            match event::poll(Duration::ZERO) {
+                Ok(true) => match event::read() {
+                    Ok(e) => self.event_to_u16(e),
+                    Err(_) => InputEvent::no_input(),
+                },
+                _ => InputEvent::no_input(),
+            }
+        };
+        set_signal_keys(true);
+        event
+    }
+
+    fn scroll(&mut self, row: u32) {
+        // Delete the line at `row`, moving everything below it up and blanking the bottom
+        // line in the current colours
+        if row == 0 || row > self.rows {
+            return;
+        }
+        let blank = self.blank();
+        let before = self.cells.clone();
+        self.cells.remove(row as usize - 1);
+        self.cells.push(vec![blank; self.columns as usize]);
+        // Only the cells that changed are drawn again
+        for (r, old) in before.iter().enumerate().skip(row as usize - 1) {
+            for (c, old) in old.iter().enumerate() {
+                let cell = self.cells[r][c];
+                if cell != *old {
+                    self.draw(r as u32, c as u32, &cell);
+                }
+            }
+        }
+        self.move_cursor(self.cursor);
+        self.flush();
+    }
+
+    fn backspace(&mut self, at: (u32, u32)) {
+        if at.0 == 0 || at.1 == 0 || at.0 > self.rows || at.1 > self.columns {
+            return;
+        }
+        let cell = &mut self.cells[at.0 as usize - 1][at.1 as usize - 1];
+        cell.c = ' ';
+        let cell = *cell;
+        self.draw(at.0 - 1, at.1 - 1, &cell);
+        self.move_cursor(at);
+    }
+
+    fn beep(&mut self) {
+        check(queue!(self.out, Print('\u{7}')));
+        self.flush();
+    }
+
+    fn move_cursor(&mut self, at: (u32, u32)) {
+        self.cursor = at;
+        self.print_position = None;
+        check(queue!(
+            self.out,
+            MoveTo(at.1.saturating_sub(1) as u16, at.0.saturating_sub(1) as u16)
+        ));
+    }
+
+    fn reset(&mut self) {
+        let blank = self.blank();
+        self.cells = vec![vec![blank; self.columns as usize]; self.rows as usize];
+        self.print_position = None;
+        self.pen = None;
+        check(queue!(
+            self.out,
+            SetAttribute(CtAttribute::Reset),
+            SetBackgroundColor(blank.background),
+            Clear(ClearType::All),
+        ));
+    }
+
+    fn quit(&mut self) {
+        info!(target: "app::screen", "Closing crossterm terminal");
+        check(queue!(
+            self.out,
+            SetAttribute(CtAttribute::Reset),
+            DisableMouseCapture,
+            Show,
+            LeaveAlternateScreen,
+        ));
+        self.flush();
+        check(terminal::disable_raw_mode());
+    }
+
+    fn set_style_map(&mut self, style_map: StyleMap) {
+        self.style_map = style_map;
+    }
+
+    fn set_colors(&mut self, colors: (Color, Color)) {
+        // Lines exposed by scrolling are blanked in the current colours
+        self.colors = colors;
+    }
+
+    fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        prompt: &str,
+        continue_key: char,
+        recoverable: bool,
+    ) -> bool {
+        let lines = [
+            message.to_string(),
+            String::new(),
+            instruction.to_string(),
+            String::new(),
+            prompt.to_string(),
+        ];
+        let (top, left, height, width) = self.dialog("", &lines);
+        set_signal_keys(false);
+        let key = self.wait_for_key();
+        set_signal_keys(true);
+        self.repaint(top, left, height, width);
+        recoverable
+            && key.is_some_and(
+                |k| matches!(k.code, KeyCode::Char(c) if super::same_key(c, continue_key)),
+            )
+    }
+
+    fn interrupted(&mut self) -> bool {
+        INTERRUPTED.swap(false, Ordering::SeqCst)
+    }
+
+    fn suspend_requested(&mut self) -> bool {
+        SUSPEND.swap(false, Ordering::SeqCst)
+    }
+
+    fn resized(&mut self) -> bool {
+        std::mem::take(&mut self.resized)
+    }
+
+    #[cfg(unix)]
+    fn suspend(&mut self) {
+        info!(target: "app::screen", "Suspending crossterm terminal");
+        self.message = None;
+        // Back to the cooked terminal the shell expects
+        self.quit();
+        // Stop with the default action, carrying on from here when continued
+        unsafe {
+            libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            libc::raise(libc::SIGTSTP);
+        }
+        install_sigtstp();
+    }
+
+    fn resume(&mut self) {
+        info!(target: "app::screen", "Resuming crossterm terminal");
+        check(terminal::enable_raw_mode());
+        set_signal_keys(true);
+        check(queue!(
+            self.out,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            Hide
+        ));
+        self.reset();
+        self.flush();
+    }
+
+    fn input_pending(&mut self) -> bool {
+        // Read a key now, if there is one, and hold it for the next read_key
+        if self.pending.is_none() && matches!(event::poll(Duration::ZERO), Ok(true)) {
+            if let Ok(e) = event::read() {
+                let key = self.event_to_u16(e);
+                if key != InputEvent::no_input() {
+                    self.pending = Some(key);
+                }
+            }
+        }
+        self.pending.is_some()
+    }
+
+    fn show_message(&mut self, message: &str) {
+        // Top right corner, over the top of the game window
+        self.clear_message();
+        let text: String = format!(" {} ", message)
+            .chars()
+            .take(self.columns as usize)
+            .collect();
+        let width = text.chars().count() as u32;
+        let left = self.columns - width;
+        self.overlay(0, left, &text);
+        self.move_cursor(self.cursor);
+        self.flush();
+        self.message = Some((0, left, 1, width));
+    }
+
+    fn can_request_filename(&self) -> bool {
+        true
+    }
+
+    fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        // Prompt on the bottom line, over the top of the game window
+        let row = self.rows - 1;
+        let mut filename = default.to_string();
+        check(queue!(self.out, Show));
+        set_signal_keys(false);
+        let filename = loop {
+            let text: String = format!("{}{}", prompt, filename)
+                .chars()
+                .take(self.columns as usize - 1)
+                .collect();
+            let cursor = text.chars().count() as u16;
+            self.overlay(row, 0, &format!("{:w$}", text, w = self.columns as usize));
+            check(queue!(self.out, MoveTo(cursor, row as u16)));
+            self.flush();
+            let Some(key) = self.wait_for_key() else {
+                break Some(filename);
+            };
+            match key.code {
+                KeyCode::Enter => break Some(filename),
+                // Escape cancels the prompt
+                KeyCode::Esc => break None,
+                KeyCode::Backspace => {
+                    filename.pop();
+                }
+                KeyCode::Char(c)
+                    if !c.is_control() && !key.modifiers.contains(KeyModifiers::CONTROL) =>
+                {
+                    filename.push(c)
+                }
+                _ => {}
+            }
+        };
+        set_signal_keys(true);
+        check(queue!(self.out, Hide));
+        // Redraw whatever the prompt covered
+        self.repaint(row, 0, 1, self.columns);
+        filename
+    }
+
+    fn menu(&mut self, title: &str, items: &[String]) -> Option<char> {
+        // A box in the middle of the screen, over the top of the game window
+        let (top, left, height, width) = self.dialog(title, items);
+        set_signal_keys(false);
+        let key = self.wait_for_key();
+        set_signal_keys(true);
+        // Redraw whatever the menu covered
+        self.repaint(top, left, height, width);
+        match key.map(|k| k.code) {
+            Some(KeyCode::Char(c)) => Some(c),
+            _ => None,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use super::InputEvent;
 
-#[cfg(not(test))]
+#[cfg(all(not(test), feature = "crossterm"))]
+pub mod crossterm;
+#[cfg(all(not(test), feature = "pancurses", not(feature = "crossterm")))]
 pub mod pancurses;
+#[cfg(all(not(test), not(feature = "pancurses"), not(feature = "crossterm")))]
+compile_error!("enable the `pancurses` or `crossterm` feature to draw on the terminal");
 #[cfg(test)]
 pub mod test_terminal;
 
@@ -10,6 +14,9 @@ fn char_to_u16(c: char) -> InputEvent {
         // Mac | Windows - slight differences in character values for backspace and return
         '\u{7f}' | '\u{08}' => InputEvent::from_char(0x08),
         '\u{0a}' | '\u{0d}' => InputEvent::from_char(0x0d),
+        // Ctrl-U, Ctrl-W line editing
+        '\u{15}' => InputEvent::from_char(0x15),
+        '\u{17}' => InputEvent::from_char(0x17),
         ' '..='~' => InputEvent::from_char(c as u16),
         '\u{e4}' => InputEvent::from_char(0x9b),
         '\u{f6}' => InputEvent::from_char(0x9c),
@@ -87,7 +94,12 @@ fn char_to_u16(c: char) -> InputEvent {
     }
 }
 
-fn map_output(zchar: u16, font: u8) -> char {
+// True if `pressed` is `key`, ignoring case, as for the continue key in the error dialog
+fn same_key(pressed: char, key: char) -> bool {
+    pressed.to_lowercase().eq(key.to_lowercase())
+}
+
+pub fn map_output(zchar: u16, font: u8) -> char {
     match font {
         1 | 4 => match zchar {
             0x18 => '\u{2191}',
@@ -164,6 +176,8 @@ fn map_output(zchar: u16, font: u8) -> char {
             0xdd => '\u{152}',
             0xde => '\u{a1}',
             0xdf => '\u{bf}',
+            // Above the ZSCII output codes, characters are Unicode
+            0x100.. => char::from_u32(zchar as u32).unwrap_or('?'),
             _ => {
                 error!(target: "app::screen", "Unmapped font {} character {:04x}", font, zchar);
                 zchar as u8 as char
@@ -195,6 +209,14 @@ mod tests {
         assert_eq!(ie, i, "{} should map to {:?}, was {:?}", c, i, ie);
     }
 
+    #[test]
+    fn test_same_key() {
+        assert!(same_key('c', 'c'));
+        assert!(same_key('C', 'c'));
+        assert!(same_key('é', 'É'));
+        assert!(!same_key('x', 'c'));
+    }
+
     #[test]
     fn test_char_to_u16() {
         // Backspace, Return
@@ -202,6 +224,9 @@ mod tests {
         assert_char_to_u16('\u{7f}', InputEvent::from_char(0x08));
         assert_char_to_u16('\u{0a}', InputEvent::from_char(0x0d));
         assert_char_to_u16('\u{0d}', InputEvent::from_char(0x0d));
+        // Ctrl-U, Ctrl-W
+        assert_char_to_u16('\u{15}', InputEvent::from_char(0x15));
+        assert_char_to_u16('\u{17}', InputEvent::from_char(0x17));
         // ASCII
         for c in ' '..='~' {
             assert_char_to_u16(c, InputEvent::from_char(c as u16));
@@ -1,9 +1,48 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use pancurses::*;
 
-use crate::zmachine::io::screen::{CellStyle, Color, InputEvent, Style, Terminal};
+use crate::zmachine::io::screen::{
+    Attribute, Capabilities, CellStyle, Color, InputEvent, StyleMap, Terminal,
+};
+
+// Set from the SIGINT handler or when Ctrl-C is read while in raw mode
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Set from the SIGTSTP handler or when Ctrl-Z is read while in raw mode.  Curses can't be
+// shut down safely from a signal handler, so the suspend waits for the interpreter to
+// check for it.
+static SUSPEND: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle_sigtstp(_signal: libc::c_int) {
+    SUSPEND.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_sigtstp() {
+    unsafe {
+        libc::signal(
+            libc::SIGTSTP,
+            handle_sigtstp as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
 
 pub struct PCTerminal {
     window: Window,
+    capabilities: Capabilities,
+    // Interpreter message shown over the game window until the next key
+    message: Option<Window>,
+    style_map: StyleMap,
+    // Whether the bright foreground color pairs could be set up
+    bright_colors: bool,
+    // Set when curses reports the terminal has changed size, until the screen asks
+    resized: bool,
 }
 
 fn cp(fg: i16, bg: i16) -> i16 {
@@ -13,6 +52,29 @@ fn cp(fg: i16, bg: i16) -> i16 {
     ((fg << 3) & 0x38) + (bg & 0x07) + 1
 }
 
+// Pairs 65 - 128 are the basic colors with a bright foreground
+fn bright_cp(fg: i16, bg: i16) -> i16 {
+    cp(fg, bg) + 64
+}
+
+// When the game file is piped in, stdin isn't the keyboard, so point it back
+// at the controlling terminal before curses starts reading input from it.
+#[cfg(unix)]
+fn reopen_tty() {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        match std::fs::File::open("/dev/tty") {
+            Ok(tty) => {
+                if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+                    error!(target: "app::screen", "Error redirecting stdin to /dev/tty");
+                }
+            }
+            Err(e) => error!(target: "app::screen", "Error opening /dev/tty: {}", e),
+        }
+    }
+}
+
 pub fn new_terminal() -> Box<dyn Terminal> {
     Box::new(PCTerminal::new())
 }
@@ -20,14 +82,28 @@ pub fn new_terminal() -> Box<dyn Terminal> {
 impl PCTerminal {
     pub fn new() -> PCTerminal {
         info!(target: "app::screen", "Initialize pancurses terminal");
+        #[cfg(unix)]
+        reopen_tty();
         let window = pancurses::initscr();
         pancurses::curs_set(0);
         pancurses::noecho();
         pancurses::cbreak();
         pancurses::start_color();
-        pancurses::mousemask(ALL_MOUSE_EVENTS, None);
+        let mouse = pancurses::mousemask(ALL_MOUSE_EVENTS, None) != 0;
         pancurses::set_title("mxyzptlk - a rusty z-machine interpreter");
 
+        // Ctrl-C outside of input raises SIGINT in cbreak mode, which is
+        // recorded and checked by the interpreter between instructions.
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        // Ctrl-Z is deferred the same way, replacing the curses handler
+        #[cfg(unix)]
+        install_sigtstp();
+
         window.keypad(true);
         window.clear();
         window.refresh();
@@ -38,8 +114,44 @@ impl PCTerminal {
                 pancurses::init_pair(cp(fg, bg), fg, bg);
             }
         }
+        let bright_colors = pancurses::COLORS() >= 16;
+        if bright_colors {
+            for fg in 0..8 {
+                for bg in 0..8 {
+                    pancurses::init_pair(bright_cp(fg, bg), fg + 8, bg);
+                }
+            }
+        }
+
+        // pancurses doesn't expose termattrs(), so italic follows the platform: macOS
+        // terminals get underline instead
+        let (rows, columns) = window.get_max_yx();
+        let capabilities = Capabilities {
+            colors: pancurses::has_colors(),
+            italic: !cfg!(target_os = "macos"),
+            mouse,
+            rows: rows as u32,
+            columns: columns as u32,
+            ..Capabilities::default()
+        };
+
+        PCTerminal {
+            window,
+            capabilities,
+            message: None,
+            style_map: StyleMap::default(),
+            bright_colors,
+            resized: false,
+        }
+    }
 
-        PCTerminal { window }
+    fn clear_message(&mut self) {
+        if let Some(message) = self.message.take() {
+            message.delwin();
+            // Redraw whatever the message covered
+            self.window.touch();
+            self.window.refresh();
+        }
     }
 
     fn as_color(&self, color: Color) -> i16 {
@@ -118,21 +230,40 @@ impl Terminal for PCTerminal {
         font: u8,
     ) {
         let c = super::map_output(zchar, font);
-        let cp = cp(self.as_color(colors.0), self.as_color(colors.1));
+        let (fg, bg) = (self.as_color(colors.0), self.as_color(colors.1));
+        let mut cp = cp(fg, bg);
+        let style = self.style_map.attributes(style);
         let mut attributes = 0;
-        if style.is_style(Style::Bold) {
+        if style.contains(Attribute::Bold) {
             attributes |= A_BOLD;
         }
-        if style.is_style(Style::Italic) {
-            if cfg!(target_os = "macos") {
-                attributes |= A_UNDERLINE;
-            } else {
+        if style.contains(Attribute::Italic) {
+            if self.capabilities.italic {
                 attributes |= A_ITALIC;
+            } else {
+                attributes |= A_UNDERLINE;
             }
         }
-        if style.is_style(Style::Reverse) {
+        if style.contains(Attribute::Underline) {
+            attributes |= A_UNDERLINE;
+        }
+        if style.contains(Attribute::Reverse) {
             attributes |= A_REVERSE;
         }
+        if style.contains(Attribute::Dim) {
+            attributes |= A_DIM;
+        }
+        if style.contains(Attribute::Blink) {
+            attributes |= A_BLINK;
+        }
+        if style.contains(Attribute::BrightForeground) {
+            // Most terminals without 16 colors show bold text in the bright color
+            if self.bright_colors {
+                cp = bright_cp(fg, bg);
+            } else {
+                attributes |= A_BOLD;
+            }
+        }
         self.window.mv(row as i32 - 1, column as i32 - 1);
         self.window.addstr(format!("{}", c));
         self.window.mv(row as i32 - 1, column as i32 - 1);
@@ -143,6 +274,15 @@ impl Terminal for PCTerminal {
         self.window.refresh();
     }
 
+    fn capabilities(&self) -> Capabilities {
+        let (rows, columns) = self.size();
+        Capabilities {
+            rows,
+            columns,
+            ..self.capabilities
+        }
+    }
+
     fn read_key(&mut self, wait: bool) -> InputEvent {
         if wait {
             self.window.nodelay(false);
@@ -152,8 +292,25 @@ impl Terminal for PCTerminal {
         pancurses::curs_set(1);
         pancurses::raw();
 
-        if let Some(i) = self.window.getch() {
+        let key = self.window.getch();
+        // Return to cbreak mode so Ctrl-C raises SIGINT while the game is running
+        pancurses::cbreak();
+        if let Some(i) = key {
+            self.clear_message();
             pancurses::curs_set(0);
+            if i == Input::Character('\u{3}') {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            }
+            #[cfg(unix)]
+            if i == Input::Character('\u{1a}') {
+                SUSPEND.store(true, Ordering::SeqCst);
+                return InputEvent::no_input();
+            }
+            if i == Input::KeyResize {
+                pancurses::resize_term(0, 0);
+                self.resized = true;
+                return InputEvent::no_input();
+            }
             self.input_to_u16(i)
         } else {
             InputEvent::no_input()
@@ -199,19 +356,34 @@ impl Terminal for PCTerminal {
         pancurses::reset_prog_mode();
     }
 
+    fn set_style_map(&mut self, style_map: StyleMap) {
+        self.style_map = style_map;
+    }
+
     fn set_colors(&mut self, colors: (Color, Color)) {
         let cp = cp(self.as_color(colors.0), self.as_color(colors.1));
         self.window.color_set(cp);
+        // Curses blanks cells with the window background, such as the line a scroll exposes,
+        // so it follows the current colours
+        self.window
+            .bkgdset(COLOR_PAIR(cp as chtype) | ' ' as chtype);
     }
 
-    fn error(&mut self, instruction: &str, message: &str, recoverable: bool) -> bool {
+    fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        prompt: &str,
+        continue_key: char,
+        recoverable: bool,
+    ) -> bool {
         let (rows, cols) = self.window.get_max_yx();
         let height = 7;
-        let prompt_str = "Press 'c' to continue or any other key to exit";
-        let width = usize::max(
-            prompt_str.len(),
-            usize::max(instruction.len(), message.len()),
-        ) as i32
+        let width = [prompt, instruction, message]
+            .iter()
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0) as i32
             + 8;
         let err_row = (rows - height) / 2;
         let err_col = (cols - width) / 2;
@@ -223,7 +395,7 @@ impl Terminal for PCTerminal {
         errwin.mv(3, 2);
         errwin.addstr(instruction);
         errwin.mv(5, 2);
-        errwin.addstr(prompt_str);
+        errwin.addstr(prompt);
         errwin.refresh();
         errwin.nodelay(false);
         pancurses::flushinp();
@@ -233,12 +405,143 @@ impl Terminal for PCTerminal {
                 self.window.touch();
                 self.window.refresh();
 
-                if recoverable && (ch == Input::Character('c') || ch == Input::Character('C')) {
-                    return true;
-                }
+                return recoverable
+                    && matches!(ch, Input::Character(c) if super::same_key(c, continue_key));
+            }
+        }
+    }
+
+    fn interrupted(&mut self) -> bool {
+        INTERRUPTED.swap(false, Ordering::SeqCst)
+    }
+
+    fn suspend_requested(&mut self) -> bool {
+        SUSPEND.swap(false, Ordering::SeqCst)
+    }
+
+    fn resized(&mut self) -> bool {
+        std::mem::take(&mut self.resized)
+    }
+
+    #[cfg(unix)]
+    fn suspend(&mut self) {
+        info!(target: "app::screen", "Suspending pancurses terminal");
+        self.clear_message();
+        // Back to the cooked terminal the shell expects
+        pancurses::endwin();
+        // Stop with the default action, carrying on from here when continued
+        unsafe {
+            libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            libc::raise(libc::SIGTSTP);
+        }
+        install_sigtstp();
+    }
+
+    fn resume(&mut self) {
+        info!(target: "app::screen", "Resuming pancurses terminal");
+        pancurses::reset_prog_mode();
+        pancurses::curs_set(0);
+        self.window.keypad(true);
+    }
 
-                return false;
+    fn input_pending(&mut self) -> bool {
+        // Peek for a key and push it back for the next read_key
+        self.window.nodelay(true);
+        match self.window.getch() {
+            Some(i) => {
+                self.window.ungetch(&i);
+                true
             }
+            None => false,
+        }
+    }
+
+    fn show_message(&mut self, message: &str) {
+        // Top right corner, over the top of the game window
+        self.clear_message();
+        let (_, cols) = self.window.get_max_yx();
+        let text = format!(" {} ", message);
+        let width = i32::min(text.chars().count() as i32, cols);
+        let messagewin = pancurses::newwin(1, width, 0, cols - width);
+        messagewin.attrset(A_REVERSE);
+        messagewin.mvaddstr(0, 0, &text);
+        messagewin.refresh();
+        self.message = Some(messagewin);
+    }
+
+    fn can_request_filename(&self) -> bool {
+        true
+    }
+
+    fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        // Prompt on the bottom line, over the top of the game window
+        let (rows, cols) = self.window.get_max_yx();
+        let promptwin = pancurses::newwin(1, cols, rows - 1, 0);
+        promptwin.attrset(A_REVERSE);
+        promptwin.keypad(true);
+        promptwin.nodelay(false);
+        pancurses::flushinp();
+        pancurses::curs_set(1);
+
+        let mut filename = default.to_string();
+        let filename = loop {
+            promptwin.erase();
+            promptwin.mvaddstr(0, 0, " ".repeat(cols as usize));
+            promptwin.mvaddstr(0, 0, format!("{}{}", prompt, filename));
+            promptwin.refresh();
+            match promptwin.getch() {
+                Some(Input::Character('\n'))
+                | Some(Input::Character('\r'))
+                | Some(Input::KeyEnter) => break Some(filename),
+                // Escape cancels the prompt
+                Some(Input::Character('\u{1b}')) => break None,
+                Some(Input::KeyBackspace)
+                | Some(Input::Character('\u{7f}'))
+                | Some(Input::Character('\u{08}')) => {
+                    filename.pop();
+                }
+                Some(Input::Character(c)) if !c.is_control() => filename.push(c),
+                _ => {}
+            }
+        };
+
+        pancurses::curs_set(0);
+        promptwin.delwin();
+        // Redraw whatever the prompt covered
+        self.window.touch();
+        self.window.refresh();
+        filename
+    }
+
+    fn menu(&mut self, title: &str, items: &[String]) -> Option<char> {
+        // A box in the middle of the screen, over the top of the game window
+        let (rows, cols) = self.window.get_max_yx();
+        let width = items
+            .iter()
+            .map(|i| i.chars().count())
+            .chain([title.chars().count()])
+            .max()
+            .unwrap_or(0) as i32
+            + 6;
+        let width = i32::min(width, cols);
+        let height = i32::min(items.len() as i32 + 4, rows);
+        let menuwin = pancurses::newwin(height, width, (rows - height) / 2, (cols - width) / 2);
+        menuwin.draw_box(0, 0);
+        menuwin.mvaddstr(0, 2, format!(" {} ", title));
+        for (i, item) in items.iter().enumerate() {
+            menuwin.mvaddstr(i as i32 + 2, 3, item);
+        }
+        menuwin.refresh();
+        menuwin.nodelay(false);
+        pancurses::flushinp();
+        let key = menuwin.getch();
+        menuwin.delwin();
+        // Redraw whatever the menu covered
+        self.window.touch();
+        self.window.refresh();
+        match key {
+            Some(Input::Character(c)) => Some(c),
+            _ => None,
         }
     }
 }
@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use crate::{
     test_util::*,
-    zmachine::io::screen::{CellStyle, Color, InputEvent, Terminal},
+    zmachine::io::screen::{Capabilities, CellStyle, Color, InputEvent, StyleMap, Terminal},
 };
 
 pub fn new_terminal() -> Box<dyn Terminal> {
@@ -18,7 +18,18 @@ impl Terminal for TestTerminal {
     }
 
     fn size(&self) -> (u32, u32) {
-        (24, 80)
+        size()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        capabilities().unwrap_or_else(|| {
+            let (rows, columns) = size();
+            Capabilities {
+                rows,
+                columns,
+                ..Capabilities::default()
+            }
+        })
     }
 
     fn print_at(
@@ -27,10 +38,15 @@ impl Terminal for TestTerminal {
         _row: u32,
         _column: u32,
         _colors: (Color, Color),
-        _style: &CellStyle,
+        style: &CellStyle,
         _font: u8,
     ) {
-        print_char((zchar as u8) as char);
+        set_attributes(style_map().attributes(style));
+        if zchar > 0xFF {
+            print_char(char::from_u32(zchar as u32).unwrap_or('?'));
+        } else {
+            print_char((zchar as u8) as char);
+        }
     }
 
     fn flush(&mut self) {}
@@ -111,7 +127,56 @@ impl Terminal for TestTerminal {
         set_output_stream(mask, table);
     }
 
-    fn error(&mut self, _instruction: &str, _message: &str, _recoverable: bool) -> bool {
-        todo!()
+    fn error(
+        &mut self,
+        _instruction: &str,
+        message: &str,
+        _prompt: &str,
+        _continue_key: char,
+        recoverable: bool,
+    ) -> bool {
+        set_error(message, recoverable);
+        recoverable
+    }
+
+    fn interrupted(&mut self) -> bool {
+        interrupt()
+    }
+
+    fn resized(&mut self) -> bool {
+        resized()
+    }
+
+    fn interactive(&self) -> bool {
+        interactive()
+    }
+
+    fn set_style_map(&mut self, map: StyleMap) {
+        set_style_map(map);
+    }
+
+    // Delayed input simulates typing, which isn't available without waiting
+    fn input_pending(&mut self) -> bool {
+        !input_timeout() && input_delay() == 0 && input_pending()
+    }
+
+    // There is a file name prompt once a test sets the answer to it
+    fn can_request_filename(&self) -> bool {
+        filename_response().is_some()
+    }
+
+    fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        set_filename_request(prompt, default);
+        filename_response().flatten()
+    }
+
+    fn show_message(&mut self, message: &str) {
+        set_message(message);
+    }
+
+    // The menu is answered with the next key from the input queue
+    fn menu(&mut self, _title: &str, items: &[String]) -> Option<char> {
+        set_menu(items);
+        input_char()
     }
 }
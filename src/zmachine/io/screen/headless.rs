@@ -0,0 +1,98 @@
+use super::{CellStyle, Color, InputEvent, Terminal};
+
+/// Terminal with no display or keyboard, for driving the interpreter from a script.
+///
+/// Output is discarded, so it is normally wrapped in a
+/// [`CapturingTerminal`](super::capture::CapturingTerminal).  Input is expected to come
+/// from the host through [`ZMachine::advance`](crate::zmachine::ZMachine::advance); a
+/// blocking read, such as an interpreter prompt, is answered with return, accepting
+/// whatever default the prompt offers.
+pub struct HeadlessTerminal {
+    rows: u32,
+    columns: u32,
+}
+
+impl HeadlessTerminal {
+    pub fn new(rows: u32, columns: u32) -> HeadlessTerminal {
+        info!(target: "app::screen", "Initialize headless {}x{} terminal", rows, columns);
+        HeadlessTerminal { rows, columns }
+    }
+}
+
+impl Terminal for HeadlessTerminal {
+    fn type_name(&self) -> &str {
+        "HeadlessTerminal"
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.rows, self.columns)
+    }
+
+    fn print_at(
+        &mut self,
+        _zchar: u16,
+        _row: u32,
+        _column: u32,
+        _colors: (Color, Color),
+        _style: &CellStyle,
+        _font: u8,
+    ) {
+    }
+
+    fn flush(&mut self) {}
+
+    fn read_key(&mut self, wait: bool) -> InputEvent {
+        if wait {
+            InputEvent::from_char(0x0d)
+        } else {
+            InputEvent::no_input()
+        }
+    }
+
+    fn scroll(&mut self, _row: u32) {}
+
+    fn backspace(&mut self, _at: (u32, u32)) {}
+
+    fn beep(&mut self) {}
+
+    fn move_cursor(&mut self, _at: (u32, u32)) {}
+
+    fn reset(&mut self) {}
+
+    fn quit(&mut self) {}
+
+    fn set_colors(&mut self, _colors: (Color, Color)) {}
+
+    // There's nobody to ask, so recoverable errors are logged and execution continues
+    fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        _prompt: &str,
+        _continue_key: char,
+        recoverable: bool,
+    ) -> bool {
+        error!(target: "app::screen", "{}: {}", instruction, message);
+        recoverable
+    }
+
+    fn interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headless_terminal() {
+        let mut terminal = HeadlessTerminal::new(24, 80);
+        assert_eq!(terminal.size(), (24, 80));
+        assert!(!terminal.interactive());
+        assert_eq!(terminal.read_key(false), InputEvent::no_input());
+        assert_eq!(terminal.read_key(true), InputEvent::from_char(0x0d));
+        assert!(terminal.error("READ", "message", "continue?", 'c', true));
+        assert!(!terminal.error("READ", "message", "continue?", 'c', false));
+    }
+}
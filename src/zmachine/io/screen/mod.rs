@@ -1,16 +1,138 @@
+pub mod capture;
 mod curses;
+pub mod headless;
+pub mod v6;
 
 use core::fmt;
+use std::{fs::File, str::FromStr};
 
 use crate::config::Config;
-use crate::{error::*, recoverable_error};
+use crate::{error::*, fatal_error, recoverable_error};
 
-#[cfg(not(test))]
+#[cfg(all(not(test), feature = "crossterm"))]
+use curses::crossterm::new_terminal;
+#[cfg(all(not(test), feature = "pancurses", not(feature = "crossterm")))]
 use curses::pancurses::new_terminal;
 
 #[cfg(test)]
 use curses::test_terminal::new_terminal;
 
+use self::capture::CapturingTerminal;
+pub use self::curses::map_output;
+use self::headless::HeadlessTerminal;
+use self::v6::{Window, WindowAttribute};
+
+/// Rows and columns of the headless terminal
+pub const HEADLESS_SIZE: (u32, u32) = (24, 80);
+
+/// Rows and columns of the smallest terminal the interpreter will start in
+pub const MIN_SIZE: (u32, u32) = (10, 24);
+
+// Characters that take no cell of their own: combining marks, and the zero-width spaces,
+// joiners, and direction marks
+const ZERO_WIDTH: [(u16, u16); 6] = [
+    (0x0300, 0x036F),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F),
+    (0x20D0, 0x20FF),
+    (0xFE20, 0xFE2F),
+];
+
+// East Asian wide and fullwidth characters, which take two cells
+const DOUBLE_WIDTH: [(u16, u16); 13] = [
+    (0x1100, 0x115F),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+];
+
+// Cell value for the right half of a double-width character, which is drawn from the cell
+// to its left
+const WIDE_TAIL: u16 = 0;
+
+/// Number of screen cells `zchar` takes.  ZSCII output codes, all below $100, are one cell
+/// wide.  Values above that are Unicode characters: East Asian wide characters take two
+/// cells and combining marks none.
+pub fn display_width(zchar: u16) -> u32 {
+    let in_ranges = |ranges: &[(u16, u16)]| ranges.iter().any(|(a, b)| (*a..=*b).contains(&zchar));
+    if zchar < 0x100 {
+        1
+    } else if in_ranges(&ZERO_WIDTH) {
+        0
+    } else if in_ranges(&DOUBLE_WIDTH) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Number of screen cells `text` takes
+pub fn text_width(text: &[u16]) -> usize {
+    text.iter().map(|c| display_width(*c) as usize).sum()
+}
+
+/// Shorten `text` to at most `width` cells, without splitting a double-width character
+pub fn truncate_to_width(text: &mut Vec<u16>, width: usize) {
+    while text_width(text) > width {
+        text.pop();
+    }
+}
+
+fn check_version(version: u8) -> Result<(), RuntimeError> {
+    if (3..=8).contains(&version) {
+        Ok(())
+    } else {
+        fatal_error!(
+            ErrorCode::UnsupportedVersion,
+            "Version {} is not supported",
+            version
+        )
+    }
+}
+
+// The headless terminal when `config` asks for it, otherwise curses
+fn default_terminal(config: &Config) -> Box<dyn Terminal> {
+    if config.headless() {
+        Box::new(HeadlessTerminal::new(HEADLESS_SIZE.0, HEADLESS_SIZE.1))
+    } else {
+        new_terminal()
+    }
+}
+
+// Wrap `terminal` in a capturing terminal when screen capture is configured, and give it
+// the style map
+fn configure_terminal(
+    terminal: Box<dyn Terminal>,
+    config: &Config,
+) -> Result<Box<dyn Terminal>, RuntimeError> {
+    let mut terminal: Box<dyn Terminal> = match config.capture() {
+        Some(filename) => match File::create(filename) {
+            Ok(f) => Box::new(CapturingTerminal::new(terminal, Some(Box::new(f)))),
+            Err(e) => {
+                return recoverable_error!(
+                    ErrorCode::FileError,
+                    "Error creating screen capture file {}: {}",
+                    filename,
+                    e
+                )
+            }
+        },
+        None => terminal,
+    };
+    terminal.set_style_map(config.style_map());
+    Ok(terminal)
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Color {
     Black = 2,
@@ -31,6 +153,101 @@ pub enum Style {
     Fixed = 8,
 }
 
+/// A terminal text attribute that Z-machine styles can be drawn with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Attribute {
+    Reverse = 0x01,
+    Bold = 0x02,
+    Italic = 0x04,
+    Underline = 0x08,
+    Dim = 0x10,
+    Blink = 0x20,
+    BrightForeground = 0x40,
+}
+
+// Attribute names as they appear in config.yml
+impl FromStr for Attribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reverse" => Ok(Attribute::Reverse),
+            "bold" => Ok(Attribute::Bold),
+            "italic" => Ok(Attribute::Italic),
+            "underline" => Ok(Attribute::Underline),
+            "dim" => Ok(Attribute::Dim),
+            "blink" => Ok(Attribute::Blink),
+            "bright-fg" => Ok(Attribute::BrightForeground),
+            _ => Err(format!("Unknown text attribute {}", s)),
+        }
+    }
+}
+
+/// A set of terminal text attributes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub fn new(attributes: &[Attribute]) -> Attributes {
+        Attributes(attributes.iter().fold(0, |mask, a| mask | *a as u8))
+    }
+
+    pub fn contains(&self, attribute: Attribute) -> bool {
+        self.0 & attribute as u8 != 0
+    }
+}
+
+/// The terminal attributes used to draw each Z-machine text style.  A combination of
+/// styles is drawn with all of their attributes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StyleMap {
+    reverse: Attributes,
+    bold: Attributes,
+    italic: Attributes,
+    fixed: Attributes,
+}
+
+impl Default for StyleMap {
+    fn default() -> Self {
+        StyleMap {
+            reverse: Attributes::new(&[Attribute::Reverse]),
+            bold: Attributes::new(&[Attribute::Bold]),
+            italic: Attributes::new(&[Attribute::Italic]),
+            fixed: Attributes::default(),
+        }
+    }
+}
+
+impl StyleMap {
+    /// Draw `style`, which must be one of reverse, bold, italic, or fixed, with `attributes`
+    pub fn set(&mut self, style: Style, attributes: Attributes) {
+        match style {
+            Style::Reverse => self.reverse = attributes,
+            Style::Bold => self.bold = attributes,
+            Style::Italic => self.italic = attributes,
+            Style::Fixed => self.fixed = attributes,
+            Style::Roman => {}
+        }
+    }
+
+    pub fn attributes(&self, style: &CellStyle) -> Attributes {
+        let mut mask = 0;
+        if style.is_style(Style::Reverse) {
+            mask |= self.reverse.0;
+        }
+        if style.is_style(Style::Bold) {
+            mask |= self.bold.0;
+        }
+        if style.is_style(Style::Italic) {
+            mask |= self.italic.0;
+        }
+        if style.is_style(Style::Fixed) {
+            mask |= self.fixed.0;
+        }
+        Attributes(mask)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct CellStyle {
     mask: u8,
@@ -65,6 +282,47 @@ impl CellStyle {
     }
 }
 
+/// What the terminal (or embedding host) can do.  The interpreter sets the header flags
+/// the game checks from these (§11.1) instead of assuming a curses terminal.
+///
+/// Text styles the terminal can't show are degraded by `Screen::set_style`: bold is
+/// dropped when it isn't available, and italic is dropped when neither italic nor
+/// underline is available.  A terminal with underline but no italic is expected to draw
+/// italic text underlined.  Reverse video and fixed pitch are always available.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    pub colors: bool,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub timed_input: bool,
+    pub sound: bool,
+    pub mouse: bool,
+    pub unicode: bool,
+    pub rows: u32,
+    pub columns: u32,
+    /// foreground, background
+    pub default_colors: (Color, Color),
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            colors: true,
+            bold: true,
+            italic: true,
+            underline: true,
+            timed_input: true,
+            sound: true,
+            mouse: true,
+            unicode: true,
+            rows: 24,
+            columns: 80,
+            default_colors: (Color::White, Color::Black),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Interrupt {
     ReadTimeout,
@@ -129,7 +387,7 @@ impl InputEvent {
     }
 }
 
-fn map_color(color: u8) -> Result<Color, RuntimeError> {
+pub(crate) fn map_color(color: u8) -> Result<Color, RuntimeError> {
     match color {
         2 => Ok(Color::Black),
         3 => Ok(Color::Red),
@@ -147,6 +405,45 @@ fn map_colors(foreground: u8, background: u8) -> Result<(Color, Color), RuntimeE
     Ok((map_color(foreground)?, map_color(background)?))
 }
 
+// A character as it was last drawn on the screen, kept so the whole screen can be
+// repainted when the terminal has lost its contents
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Cell {
+    zchar: u16,
+    colors: (Color, Color),
+    style: CellStyle,
+    font: u8,
+}
+
+impl Cell {
+    fn blank(colors: (Color, Color)) -> Cell {
+        Cell {
+            zchar: 0x20,
+            colors,
+            style: CellStyle::new(),
+            font: 1,
+        }
+    }
+}
+
+// Text colours, style, and font are tracked separately for each window (§8.7.2)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct WindowAttributes {
+    colors: (Color, Color),
+    style: CellStyle,
+    font: u8,
+}
+
+impl WindowAttributes {
+    fn new(colors: (Color, Color)) -> WindowAttributes {
+        WindowAttributes {
+            colors,
+            style: CellStyle::new(),
+            font: 1,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Screen {
     version: u8,
@@ -162,89 +459,115 @@ pub struct Screen {
     current_colors: (Color, Color),
     current_style: CellStyle,
     font: u8,
+    // Font 3 (character graphics) can be selected
+    graphics_font: bool,
+    // Shown when a screenful of output has scrolled by without input
+    more_prompt: Vec<u16>,
+    // Saved attributes for each window, swapped into current_colors,
+    // current_style, and font when the window is selected
+    window_attributes: Vec<WindowAttributes>,
+    // The V6 windows, which replace the lower and upper windows.  Empty before V6.
+    windows: Vec<Window>,
     // row, column with 1,1 as origin
     cursor_0: (u32, u32),
     cursor_1: Option<(u32, u32)>,
     terminal: Box<dyn Terminal>,
+    capabilities: Capabilities,
     lines_since_input: u32,
+    // Flags 2 bit 1, set by the game to request fixed-pitch text everywhere
+    force_fixed_pitch: bool,
+    // The upper window never scrolls: once output runs past its bottom-right corner, the
+    // cursor is pinned there and further output is discarded until the cursor is moved
+    window_1_full: bool,
+    // Everything drawn on the screen, by row then column
+    cells: Vec<Vec<Cell>>,
+    // Set when the terminal has changed size, until the game has been told
+    resized: bool,
 }
 
 impl Screen {
     pub fn new_v3(config: Config) -> Result<Screen, RuntimeError> {
-        let terminal = new_terminal();
-
-        let (rows, columns) = terminal.as_ref().size();
-        let colors = map_colors(config.foreground(), config.background())?;
-
-        Ok(Screen {
-            version: 3,
-            rows,
-            columns,
-            top: 2,
-            window_0_top: 2,
-            window_1_top: None,
-            window_1_bottom: None,
-            selected_window: 0,
-            default_colors: colors,
-            current_colors: colors,
-            current_style: CellStyle::new(),
-            font: 1,
-            cursor_0: (rows, 1),
-            cursor_1: None,
-            terminal,
-            lines_since_input: 0,
-        })
+        Screen::new(3, config)
     }
 
     pub fn new_v4(config: Config) -> Result<Screen, RuntimeError> {
-        let terminal = new_terminal();
+        Screen::new(4, config)
+    }
 
-        let (rows, columns) = terminal.as_ref().size();
-        let colors = map_colors(config.foreground(), config.background())?;
+    pub fn new_v5(config: Config) -> Result<Screen, RuntimeError> {
+        Screen::new(5, config)
+    }
 
-        Ok(Screen {
-            version: 4,
-            rows,
-            columns,
-            top: 1,
-            window_0_top: 1,
-            window_1_top: None,
-            window_1_bottom: None,
-            selected_window: 0,
-            default_colors: colors,
-            current_colors: colors,
-            current_style: CellStyle::new(),
-            font: 1,
-            cursor_0: (rows, 1),
-            cursor_1: None,
-            terminal,
-            lines_since_input: 0,
-        })
+    pub fn new_v6(config: Config) -> Result<Screen, RuntimeError> {
+        Screen::new(6, config)
     }
 
-    pub fn new_v5(config: Config) -> Result<Screen, RuntimeError> {
-        let terminal = new_terminal();
+    /// The screen for a `version` game on the curses terminal, or the headless one when
+    /// `config` asks for it
+    pub fn new(version: u8, config: Config) -> Result<Screen, RuntimeError> {
+        // Check before the terminal takes over the display
+        check_version(version)?;
+        let terminal = default_terminal(&config);
+        Screen::with_terminal(version, config, terminal)
+    }
+
+    /// The screen for a `version` game drawn on `terminal`.  Screen capture and the style
+    /// map from `config` apply as they do to the built-in terminals.
+    pub fn with_terminal(
+        version: u8,
+        config: Config,
+        terminal: Box<dyn Terminal>,
+    ) -> Result<Screen, RuntimeError> {
+        check_version(version)?;
+        let terminal = configure_terminal(terminal, &config)?;
 
         let (rows, columns) = terminal.as_ref().size();
+        let capabilities = terminal.as_ref().capabilities();
         let colors = map_colors(config.foreground(), config.background())?;
+        // V3 has a status line above the windows, and before V5 the cursor starts at the
+        // bottom of the screen
+        let top = if version == 3 { 2 } else { 1 };
+        let cursor_0 = if version < 5 { (rows, 1) } else { (1, 1) };
+        let (window_attributes, windows) = if version == 6 {
+            (
+                vec![WindowAttributes::new(colors); v6::WINDOWS],
+                Window::initial(rows, columns),
+            )
+        } else {
+            (vec![WindowAttributes::new(colors); 2], Vec::new())
+        };
 
         Ok(Screen {
-            version: 5,
+            version,
             rows,
             columns,
-            top: 1,
+            top,
+            window_0_top: top,
             window_1_top: None,
             window_1_bottom: None,
-            window_0_top: 1,
             selected_window: 0,
             default_colors: colors,
             current_colors: colors,
             current_style: CellStyle::new(),
             font: 1,
-            cursor_0: (1, 1),
+            graphics_font: config.graphics_font(),
+            more_prompt: config
+                .messages()
+                .get("prompt.more")
+                .chars()
+                .map(|c| c as u16)
+                .collect(),
+            window_attributes,
+            windows,
+            cursor_0,
             cursor_1: None,
             terminal,
+            capabilities,
             lines_since_input: 0,
+            force_fixed_pitch: false,
+            window_1_full: false,
+            cells: vec![vec![Cell::blank(colors); columns as usize]; rows as usize],
+            resized: false,
         })
     }
 
@@ -252,12 +575,45 @@ impl Screen {
         self.rows
     }
 
+    /// A fatal error if the terminal is smaller than [`MIN_SIZE`], too small for games to
+    /// lay out a status line and windows
+    pub fn check_size(&self) -> Result<(), RuntimeError> {
+        if self.rows < MIN_SIZE.0 || self.columns < MIN_SIZE.1 {
+            fatal_error!(
+                ErrorCode::ScreenTooSmall,
+                "The terminal is {} columns by {} rows, but at least {} columns by {} rows are needed",
+                self.columns,
+                self.rows,
+                MIN_SIZE.1,
+                MIN_SIZE.0
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The terminal's capabilities, with the screen size and configured default colors
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            rows: self.rows,
+            columns: self.columns,
+            default_colors: self.default_colors,
+            ..self.capabilities
+        }
+    }
+
     pub fn columns(&self) -> u32 {
         self.columns
     }
 
+    /// The cursor position in screen cells, with 1,1 at the top left.  Columns count cells
+    /// rather than characters: a double-width character moves the cursor two columns, and a
+    /// zero-width one doesn't move it at all.
+    /// In V6 the cursor is relative to the top left of the selected window.
     pub fn cursor(&self) -> (u32, u32) {
-        if self.selected_window == 0 {
+        if self.version == 6 {
+            self.windows[self.selected_window as usize].cursor
+        } else if self.selected_window == 0 {
             self.cursor_0
         } else {
             // unwrap() should be safe here because when selected_window
@@ -274,7 +630,44 @@ impl Screen {
         self.selected_window
     }
 
+    /// True when output to the selected window is buffered and word-wrapped: the lower
+    /// window, or in V6 a window with both the buffered and wrapping attributes
+    pub fn is_buffered(&self) -> bool {
+        if self.version == 6 {
+            let w = &self.windows[self.selected_window as usize];
+            w.has(WindowAttribute::Buffered) && w.has(WindowAttribute::Wrapping)
+        } else {
+            self.selected_window == 0
+        }
+    }
+
+    /// True when output to the selected window is copied to the transcript: the lower
+    /// window, or in V6 a window with the transcript attribute
+    pub fn is_transcripted(&self) -> bool {
+        if self.version == 6 {
+            self.windows[self.selected_window as usize].has(WindowAttribute::Transcript)
+        } else {
+            self.selected_window == 0
+        }
+    }
+
+    /// The last column text can be printed in, in the same terms as [`Screen::cursor`]: the
+    /// screen width, or in V6 the right margin of the selected window
+    pub fn line_end(&self) -> u32 {
+        if self.version == 6 {
+            self.windows[self.selected_window as usize].right_edge()
+        } else {
+            self.columns
+        }
+    }
+
     pub fn move_cursor(&mut self, row: u32, column: u32) {
+        if self.version == 6 {
+            // The selected window is always valid
+            let _ = self.set_window_cursor(-3, row, column);
+            return;
+        }
+
         // Constrain the column between 1 and the width of the screen
         let c = u32::max(1, u32::min(self.columns, column));
         if self.selected_window == 0 {
@@ -290,11 +683,13 @@ impl Screen {
                 u32::min(self.window_1_bottom.unwrap(), row),
             );
             self.cursor_1 = Some((r, c));
+            self.window_1_full = false;
             self.terminal.move_cursor((r, c));
         }
     }
 
-    fn map_color(&self, color: u8, current: Color, default: Color) -> Result<Color, RuntimeError> {
+    // 0 keeps the current colour and 1 restores the default (§8.3.1)
+    fn map_color(&self, color: u16, current: Color, default: Color) -> Result<Color, RuntimeError> {
         match color {
             0 => Ok(current),
             1 => Ok(default),
@@ -310,7 +705,7 @@ impl Screen {
         }
     }
 
-    fn map_colors(&self, foreground: u8, background: u8) -> Result<(Color, Color), RuntimeError> {
+    fn map_colors(&self, foreground: u16, background: u16) -> Result<(Color, Color), RuntimeError> {
         Ok((
             self.map_color(foreground, self.current_colors.0, self.default_colors.0)?,
             self.map_color(background, self.current_colors.1, self.default_colors.1)?,
@@ -318,16 +713,130 @@ impl Screen {
     }
 
     pub fn set_colors(&mut self, foreground: u16, background: u16) -> Result<(), RuntimeError> {
-        self.current_colors = self.map_colors(foreground as u8, background as u8)?;
+        self.current_colors = self.map_colors(foreground, background)?;
         self.terminal.set_colors(self.current_colors);
         Ok(())
     }
 
+    // Draw a character on the terminal, recording it in the screen cells
+    fn draw(
+        &mut self,
+        zchar: u16,
+        row: u32,
+        column: u32,
+        colors: (Color, Color),
+        style: &CellStyle,
+        font: u8,
+    ) {
+        // Drawing over either half of a double-width character blanks the other half
+        if self.cell(row, column).is_some_and(|c| c.zchar == WIDE_TAIL) {
+            self.blank_cell(row, column - 1);
+        }
+        if self
+            .cell(row, column + 1)
+            .is_some_and(|c| c.zchar == WIDE_TAIL)
+        {
+            self.blank_cell(row, column + 1);
+        }
+        if let Some(cell) = self.cell_mut(row, column) {
+            *cell = Cell {
+                zchar,
+                colors,
+                style: *style,
+                font,
+            };
+        }
+        self.terminal
+            .print_at(zchar, row, column, colors, style, font);
+    }
+
+    // Draw `zchar` at `row`, `column`, taking the cell to its right as well when it is
+    // double-width.  Zero-width characters aren't drawn, since a cell holds one character
+    // and they would replace the one they combine with.  Returns the cells taken.
+    fn draw_char(
+        &mut self,
+        zchar: u16,
+        row: u32,
+        column: u32,
+        colors: (Color, Color),
+        style: &CellStyle,
+        font: u8,
+    ) -> u32 {
+        let width = display_width(zchar);
+        if width == 0 {
+            debug!(target: "app::screen", "Zero-width character {:#06x} not drawn", zchar);
+        } else {
+            self.draw(zchar, row, column, colors, style, font);
+            if width == 2 {
+                if self
+                    .cell(row, column + 2)
+                    .is_some_and(|c| c.zchar == WIDE_TAIL)
+                {
+                    self.blank_cell(row, column + 2);
+                }
+                if let Some(cell) = self.cell_mut(row, column + 1) {
+                    *cell = Cell {
+                        zchar: WIDE_TAIL,
+                        colors,
+                        style: *style,
+                        font,
+                    };
+                }
+            }
+        }
+        width
+    }
+
+    fn cell(&self, row: u32, column: u32) -> Option<&Cell> {
+        self.cells
+            .get((row as usize).wrapping_sub(1))
+            .and_then(|r| r.get((column as usize).wrapping_sub(1)))
+    }
+
+    fn cell_mut(&mut self, row: u32, column: u32) -> Option<&mut Cell> {
+        self.cells
+            .get_mut((row as usize).wrapping_sub(1))
+            .and_then(|r| r.get_mut((column as usize).wrapping_sub(1)))
+    }
+
+    // Replace what's in a cell with a space, keeping its colours and style
+    fn blank_cell(&mut self, row: u32, column: u32) {
+        if let Some(cell) = self.cell_mut(row, column) {
+            cell.zchar = 0x20;
+            let cell = *cell;
+            self.terminal
+                .print_at(0x20, row, column, cell.colors, &cell.style, cell.font);
+        }
+    }
+
+    // Blank rows `from` through `to`, inclusive, in the current colours
+    fn clear_rows(&mut self, from: u32, to: u32) {
+        for i in from..=to {
+            for j in 1..=self.columns {
+                self.draw(0x20, i, j, self.current_colors, &CellStyle::new(), 1);
+            }
+        }
+    }
+
+    /// Set the upper window to `lines` rows.  Rows given back to the lower window when the
+    /// split shrinks keep their text until lower window output scrolls over it, which is
+    /// how Inform box quotes stay on screen.  The lower window always keeps at least its
+    /// bottom row.
     pub fn split_window(&mut self, lines: u32) {
+        if self.version == 6 {
+            return self.split_window_v6(lines);
+        }
+
+        let lines = u32::min(lines, self.rows.saturating_sub(self.top));
+        if lines == 0 {
+            return self.unsplit_window();
+        }
+
         let bottom = self.top + lines - 1;
         self.window_1_top = Some(self.top);
         self.window_1_bottom = Some(bottom);
         self.cursor_1 = Some((1, 1));
+        self.window_1_full = false;
         self.window_0_top = bottom + 1;
         if self.cursor_0.0 < self.window_0_top {
             self.cursor_0 = (self.window_0_top, self.cursor_0.1)
@@ -335,122 +844,162 @@ impl Screen {
         self.terminal.split_window(lines);
     }
 
+    /// True when the upper window has been split off
+    pub fn is_split(&self) -> bool {
+        self.window_1_top.is_some()
+    }
+
+    /// True when the upper window covers `row`
+    pub fn is_upper_window_row(&self, row: u32) -> bool {
+        match (self.window_1_top, self.window_1_bottom) {
+            (Some(top), Some(bottom)) => (top..=bottom).contains(&row),
+            _ => false,
+        }
+    }
+
+    /// Remove the upper window.  The rows it covered keep their text, as when the split
+    /// shrinks.
     pub fn unsplit_window(&mut self) {
+        if self.version == 6 {
+            return self.split_window_v6(0);
+        }
+
+        self.switch_attributes(0);
+        self.selected_window = 0;
         self.window_0_top = self.top;
         self.window_1_top = None;
         self.window_1_bottom = None;
         self.cursor_1 = None;
-        self.selected_window = 0;
+        self.window_1_full = false;
         self.terminal.split_window(0);
     }
 
+    // Save the attributes of the selected window and load those of `window`
+    fn switch_attributes(&mut self, window: u8) {
+        if window != self.selected_window {
+            self.window_attributes[self.selected_window as usize] = WindowAttributes {
+                colors: self.current_colors,
+                style: self.current_style,
+                font: self.font,
+            };
+            let attributes = self.window_attributes[window as usize];
+            self.current_colors = attributes.colors;
+            self.current_style = attributes.style;
+            self.font = attributes.font;
+        }
+    }
+
     pub fn select_window(&mut self, window: u8) -> Result<(), RuntimeError> {
+        if self.version == 6 {
+            return self.select_window_v6(window);
+        }
+
         self.lines_since_input = 0;
-        self.terminal.set_window(window);
         if window == 0 {
+            self.terminal.set_window(window);
+            self.switch_attributes(0);
             self.selected_window = 0;
-            Ok(())
         } else if self.cursor_1.is_some() {
+            self.terminal.set_window(window);
+            self.switch_attributes(1);
             self.selected_window = 1;
             self.cursor_1 = Some((self.top, 1));
-            Ok(())
+            self.window_1_full = false;
+            // By convention, V3/V4 reset the upper window style each time it is selected
+            if self.version < 5 {
+                self.current_style = CellStyle::new();
+            }
+        } else {
+            self.terminal.set_window(window);
+            return recoverable_error!(ErrorCode::InvalidWindow, "Invalid window {}", window);
+        }
+
+        self.terminal.set_colors(self.current_colors);
+        self.terminal.set_style(self.current_style.mask);
+        Ok(())
+    }
+
+    // Erasing a window resets its style and font.  Colours are kept, since the erase is
+    // painted with the current background colour.
+    fn reset_attributes(&mut self, window: u8) {
+        if window == self.selected_window {
+            self.current_style = CellStyle::new();
+            self.font = 1;
+            self.terminal.set_style(self.current_style.mask);
         } else {
-            recoverable_error!(ErrorCode::InvalidWindow, "Invalid window {}", window)
+            let attributes = &mut self.window_attributes[window as usize];
+            attributes.style = CellStyle::new();
+            attributes.font = 1;
         }
     }
 
     pub fn erase_window(&mut self, window: i8) -> Result<(), RuntimeError> {
+        if self.version == 6 {
+            return self.erase_window_v6(window as i16);
+        }
+
         self.terminal.erase_window(window);
         match window {
             0 => {
-                for i in self.window_0_top..=self.rows {
-                    for j in 1..=self.columns {
-                        self.terminal.as_mut().print_at(
-                            0x20,
-                            i,
-                            j,
-                            self.current_colors,
-                            &CellStyle::new(),
-                            1,
-                        );
-                    }
-                }
+                self.clear_rows(self.window_0_top, self.rows);
                 self.cursor_0 = if self.version == 4 {
                     (self.rows, 1)
                 } else {
                     (self.window_0_top, 1)
                 };
 
+                self.reset_attributes(0);
                 self.lines_since_input = 0;
                 Ok(())
             }
             1 => {
                 if let Some(start) = self.window_1_top {
                     if let Some(end) = self.window_1_bottom {
-                        for i in start..=end {
-                            for j in 1..=self.columns {
-                                self.terminal.as_mut().print_at(
-                                    0x20,
-                                    i,
-                                    j,
-                                    self.current_colors,
-                                    &CellStyle::new(),
-                                    1,
-                                );
-                            }
-                        }
+                        self.clear_rows(start, end);
                         self.cursor_1 = Some((start, 1))
                     }
                 }
+                self.window_1_full = false;
+                self.reset_attributes(1);
                 Ok(())
             }
             -1 => {
                 self.window_1_top = None;
                 self.window_1_bottom = None;
                 self.cursor_1 = None;
-                self.window_0_top = 1;
-                for i in self.window_0_top..=self.rows {
-                    for j in 1..=self.columns {
-                        self.terminal.as_mut().print_at(
-                            0x20,
-                            i,
-                            j,
-                            self.current_colors,
-                            &CellStyle::new(),
-                            1,
-                        );
-                    }
-                }
+                self.window_1_full = false;
+                self.window_0_top = self.top;
+                self.switch_attributes(0);
+                self.terminal.split_window(0);
+                // Both windows go back to the default colours, and the screen is cleared in them
+                self.current_colors = self.default_colors;
+                self.window_attributes[1].colors = self.default_colors;
+                self.terminal.set_colors(self.current_colors);
+                self.clear_rows(self.window_0_top, self.rows);
                 self.cursor_0 = if self.version == 4 {
                     (self.rows, 1)
                 } else {
                     (self.window_0_top, 1)
                 };
                 self.selected_window = 0;
+                self.reset_attributes(0);
+                self.reset_attributes(1);
                 self.lines_since_input = 0;
                 Ok(())
             }
             -2 => {
-                for i in 1..=self.rows {
-                    for j in 1..=self.columns {
-                        self.terminal.as_mut().print_at(
-                            0x20,
-                            i,
-                            j,
-                            self.current_colors,
-                            &CellStyle::new(),
-                            1,
-                        );
-                    }
-                    if self.cursor_1.is_some() {
-                        self.cursor_1 = Some((1, 1))
-                    }
-                    self.cursor_0 = if self.version == 4 {
-                        (self.rows, 1)
-                    } else {
-                        (self.window_0_top, 1)
-                    };
+                self.clear_rows(1, self.rows);
+                if self.cursor_1.is_some() {
+                    self.cursor_1 = Some((1, 1))
                 }
+                self.window_1_full = false;
+                self.cursor_0 = if self.version == 4 {
+                    (self.rows, 1)
+                } else {
+                    (self.window_0_top, 1)
+                };
+                self.reset_attributes(0);
+                self.reset_attributes(1);
                 self.lines_since_input = 0;
                 Ok(())
             }
@@ -472,14 +1021,7 @@ impl Screen {
             self.cursor_1.unwrap()
         };
         for i in col..self.columns {
-            self.terminal.as_mut().print_at(
-                0x20,
-                row,
-                i,
-                self.current_colors,
-                &CellStyle::new(),
-                1,
-            );
+            self.draw(0x20, row, i, self.current_colors, &CellStyle::new(), 1);
         }
     }
 
@@ -487,16 +1029,21 @@ impl Screen {
         self.lines_since_input += 1;
         if self.cursor_0.0 == self.rows {
             self.terminal.scroll(self.window_0_top);
+            self.cells.remove(self.window_0_top as usize - 1);
+            self.cells.push(vec![
+                Cell::blank(self.current_colors);
+                self.columns as usize
+            ]);
             self.cursor_0 = (self.rows, 1);
         } else {
             self.cursor_0 = (self.cursor_0.0 + 1, 1);
         }
 
-        let l = self.rows - self.window_0_top;
+        let l = u32::max(1, self.rows.saturating_sub(self.window_0_top));
         if self.lines_since_input >= l {
             let reverse = self.current_style.is_style(Style::Reverse);
             self.current_style.set(Style::Reverse as u8);
-            self.print(&"[MORE]".chars().map(|c| c as u16).collect());
+            self.print(&self.more_prompt.clone());
             if let Some(c) = self.read_key(true).zchar() {
                 if c == 0xd {
                     self.lines_since_input = l - 1;
@@ -531,8 +1078,11 @@ impl Screen {
                 if self.cursor_1.unwrap().0 < self.window_1_bottom.unwrap() {
                     // Not at the bottom of the window yet, so move to the start of the next line
                     self.cursor_1 = Some((self.cursor_1.unwrap().0 + 1, 1))
+                } else {
+                    // At the bottom right of the window, leave the cursor in place and
+                    // discard anything else printed
+                    self.window_1_full = true;
                 }
-                // If at the bottom right of the window, leave the cursor in place
             } else {
                 // Just move the cursor to the right
                 self.cursor_1 = Some((self.cursor_1.unwrap().0, self.cursor_1.unwrap().1 + 1))
@@ -548,49 +1098,90 @@ impl Screen {
     }
 
     fn print_char(&mut self, zchar: u16) {
-        if zchar == 0xd {
+        if self.version == 6 {
+            self.print_char_v6(zchar);
+        } else if zchar == 0xd {
             self.new_line();
+        } else if self.selected_window == 1 && self.window_1_full {
+            debug!(target: "app::screen", "Upper window full, discarding {:#04x}", zchar);
         } else if zchar != 0 {
-            let (r, c) = if self.selected_window == 0 {
-                self.cursor_0
-            } else {
-                self.cursor_1.unwrap()
-            };
+            let width = display_width(zchar);
+            // A double-width character that doesn't fit in the last column starts the
+            // next row
+            if width == 2 && self.cursor().1 == self.columns {
+                self.advance_cursor();
+                if self.selected_window == 1 && self.window_1_full {
+                    debug!(target: "app::screen", "Upper window full, discarding {:#04x}", zchar);
+                    return;
+                }
+            }
 
-            self.terminal.print_at(
-                zchar,
-                r,
-                c,
-                self.current_colors,
-                &self.current_style,
-                self.font,
-            );
-            self.advance_cursor();
+            let (r, c) = self.cursor();
+            let style = self.current_style;
+            self.draw_char(zchar, r, c, self.current_colors, &style, self.output_font());
+            for _ in 0..width {
+                self.advance_cursor();
+            }
         }
     }
 
+    // Select a window for output without the side effects of SET_WINDOW, such as moving the
+    // upper window cursor
+    fn switch_window(&mut self, window: u8) {
+        self.switch_attributes(window);
+        self.selected_window = window;
+        self.terminal.set_window(window);
+        self.terminal.set_colors(self.current_colors);
+        self.terminal.set_style(self.current_style.mask);
+    }
+
+    /// Print `text` on a line of its own in the lower window, whichever window is selected,
+    /// as for messages from the interpreter rather than the game
+    pub fn print_lower(&mut self, text: &[u16]) {
+        let window = self.selected_window;
+        if window != 0 {
+            self.switch_window(0);
+        }
+        if self.cursor().1 > 1 {
+            self.new_line();
+        }
+        self.print(&text.to_vec());
+        self.new_line();
+        if window != 0 {
+            self.switch_window(window);
+        }
+        self.reset_cursor();
+    }
+
     pub fn print_at(&mut self, text: &[u16], at: (u32, u32), style: &CellStyle) {
-        for (i, c) in text.iter().enumerate() {
-            self.terminal.print_at(
+        let mut column = at.1;
+        for c in text {
+            column += self.draw_char(
                 *c,
                 u32::min(self.rows, at.0),
-                u32::min(self.columns, at.1 + i as u32),
+                u32::min(self.columns, column),
                 self.current_colors,
                 style,
-                self.font,
+                self.output_font(),
             );
         }
         self.terminal.flush()
     }
 
     pub fn new_line(&mut self) {
-        if self.selected_window == 0 {
+        if self.version == 6 {
+            self.new_line_v6();
+        } else if self.selected_window == 0 {
             self.next_line();
         } else {
             // unwrap() should be safe here because when selected_window
             // is 1, cursor_1 is Some
             if self.cursor_1.unwrap().0 < self.window_1_bottom.unwrap() {
                 self.cursor_1 = Some((self.cursor_1.unwrap().0 + 1, 1));
+            } else {
+                // The upper window doesn't scroll, so pin the cursor at the end
+                self.cursor_1 = Some((self.window_1_bottom.unwrap(), self.columns));
+                self.window_1_full = true;
             }
         }
     }
@@ -602,32 +1193,153 @@ impl Screen {
 
     pub fn read_key(&mut self, wait: bool) -> InputEvent {
         self.lines_since_input = 0;
-        if self.selected_window == 0 {
-            self.terminal.move_cursor(self.cursor_0);
-        } else {
-            // unwrap() should be safe here because when selected_window
-            // is 1, cursor_1 is Some
-            self.terminal.move_cursor(self.cursor_1.unwrap());
+        for w in self.windows.iter_mut() {
+            w.line_count = 0;
         }
+        self.terminal.move_cursor(self.screen_cursor());
 
-        self.terminal.read_key(wait)
+        let key = self.terminal.read_key(wait);
+        self.suspend_if_requested();
+        if self.terminal.resized() {
+            let (rows, columns) = self.terminal.size();
+            self.resize(rows, columns);
+        }
+        key
+    }
+
+    /// Lay the screen out again after the terminal has changed size to `rows` by `columns`,
+    /// then repaint it.  What was drawn is kept where it fits.  Before V6 the upper window
+    /// is shortened to leave the lower window a row, and cursors are kept on the screen;
+    /// V6 windows are left for the game to lay out again.
+    pub fn resize(&mut self, rows: u32, columns: u32) {
+        info!(target: "app::screen", "Terminal resized to {}x{}", columns, rows);
+        let blank = Cell::blank(self.default_colors);
+        self.cells
+            .resize(rows as usize, vec![blank; columns as usize]);
+        for row in self.cells.iter_mut() {
+            row.resize(columns as usize, blank);
+        }
+        self.rows = rows;
+        self.columns = columns;
+
+        if self.version < 6 {
+            if let Some(bottom) = self.window_1_bottom {
+                let bottom = u32::min(bottom, rows.saturating_sub(1));
+                self.window_1_bottom = Some(bottom);
+                self.window_0_top = bottom + 1;
+                self.cursor_1 = self
+                    .cursor_1
+                    .map(|(r, c)| (u32::min(r, bottom), u32::min(c, columns)));
+            }
+            self.cursor_0 = (
+                self.cursor_0
+                    .0
+                    .clamp(self.window_0_top, rows.max(self.window_0_top)),
+                u32::min(self.cursor_0.1, columns),
+            );
+        }
+        self.redraw_all();
+        self.resized = true;
+    }
+
+    /// True, once, after the terminal has changed size
+    pub fn resized(&mut self) -> bool {
+        std::mem::take(&mut self.resized)
     }
 
+    // Erase the character at `at`, leaving its colours and style
+    fn erase_cell(&mut self, at: (u32, u32)) {
+        self.terminal.backspace(at);
+        if let Some(cell) = self.cell_mut(at.0, at.1) {
+            cell.zchar = 0x20;
+        }
+    }
+
+    /// Erase the character before the cursor and move back over it, two cells for a
+    /// double-width character
     pub fn backspace(&mut self) -> Result<(), RuntimeError> {
-        if self.selected_window == 0 && self.cursor_0.1 > 1 {
-            self.terminal
-                .backspace((self.cursor_0.0, self.cursor_0.1 - 1));
-            self.cursor_0 = (self.cursor_0.0, self.cursor_0.1 - 1);
-        } else if self.selected_window == 1 && self.cursor_1.unwrap().1 > 1 {
-            self.terminal
-                .backspace((self.cursor_1.unwrap().0, self.cursor_1.unwrap().1 - 1));
-            self.cursor_1 = Some((self.cursor_1.unwrap().0, self.cursor_1.unwrap().1 - 1));
+        let (row, column) = self.screen_cursor();
+        // The first column of the line, inside a V6 window's left margin
+        let first = if self.version == 6 {
+            let w = &self.windows[self.selected_window as usize];
+            w.left + w.margins.0
+        } else {
+            1
+        };
+        if column > first {
+            let width = if column > first + 1
+                && self
+                    .cell(row, column - 1)
+                    .is_some_and(|c| c.zchar == WIDE_TAIL)
+            {
+                2
+            } else {
+                1
+            };
+            for c in (column - width..column).rev() {
+                self.erase_cell((row, c));
+            }
+            if self.version == 6 {
+                self.windows[self.selected_window as usize].cursor.1 -= width;
+            } else if self.selected_window == 0 {
+                self.cursor_0 = (row, column - width);
+            } else {
+                self.cursor_1 = Some((row, column - width));
+            }
         }
         Ok(())
     }
 
+    /// Repaint the whole screen from what has been drawn on it, then put the cursor and
+    /// text attributes back, as when the terminal has been suspended and resumed
+    pub fn redraw_all(&mut self) {
+        self.terminal.reset();
+        for (i, row) in self.cells.iter().enumerate() {
+            // The right half of a double-width character is drawn with its left half
+            for (j, cell) in row.iter().enumerate().filter(|(_, c)| c.zchar != WIDE_TAIL) {
+                self.terminal.print_at(
+                    cell.zchar,
+                    i as u32 + 1,
+                    j as u32 + 1,
+                    cell.colors,
+                    &cell.style,
+                    cell.font,
+                );
+            }
+        }
+        self.terminal.set_colors(self.current_colors);
+        self.terminal.set_style(self.current_style.mask);
+        self.reset_cursor();
+        self.terminal.flush();
+    }
+
+    // When the player has asked to suspend the interpreter (Ctrl-Z), hand the terminal
+    // back to the shell until it is resumed, then repaint everything
+    fn suspend_if_requested(&mut self) {
+        if self.terminal.suspend_requested() {
+            info!(target: "app::screen", "Suspending");
+            self.terminal.suspend();
+            self.terminal.resume();
+            info!(target: "app::screen", "Resumed");
+            self.redraw_all();
+        }
+    }
+
     pub fn set_style(&mut self, style: u8) -> Result<(), RuntimeError> {
-        self.current_style.set(style);
+        let mut mapped = style;
+        if !self.capabilities.bold {
+            mapped &= !(Style::Bold as u8);
+        }
+        if !self.capabilities.italic && !self.capabilities.underline {
+            mapped &= !(Style::Italic as u8);
+        }
+        // A style that can't be shown at all leaves the current style alone rather than
+        // turning into roman
+        if style != 0 && mapped == 0 {
+            return Ok(());
+        }
+
+        self.current_style.set(mapped);
         self.terminal.set_style(self.current_style.mask);
         Ok(())
     }
@@ -641,24 +1353,58 @@ impl Screen {
     }
 
     pub fn reset_cursor(&mut self) {
-        if self.selected_window == 0 {
-            self.terminal.move_cursor(self.cursor_0);
+        self.terminal.move_cursor(self.screen_cursor());
+    }
+
+    // The cursor position on the screen, where V6 window cursors are relative to their
+    // window
+    fn screen_cursor(&self) -> (u32, u32) {
+        if self.version == 6 {
+            self.window_cursor_position()
         } else {
-            // unwrap() should be safe here because when selected_window
-            // is 1, cursor_1 is Some
-            self.terminal.move_cursor(self.cursor_1.unwrap());
+            self.cursor()
         }
     }
 
+    /// Select a font for the current window, returning the previous font, or 0 if the font
+    /// isn't available.  Font 0 returns the current font without changing it.
+    ///
+    /// Fonts 1 (normal) and 4 (fixed pitch) are both the terminal font, font 2 (pictures) is
+    /// never available, and font 3 (character graphics) only when it's enabled in the config.
     pub fn set_font(&mut self, font: u8) -> u8 {
-        match font {
-            0 => self.font,
-            1 | 3 | 4 => {
-                let result = self.font;
-                self.font = font;
-                result
-            }
-            _ => 0,
+        let available = match font {
+            0 => return self.font,
+            1 | 4 => true,
+            3 => self.graphics_font,
+            _ => false,
+        };
+
+        if available {
+            let result = self.font;
+            self.font = font;
+            result
+        } else {
+            0
+        }
+    }
+
+    pub fn set_force_fixed_pitch(&mut self, fixed: bool) {
+        self.force_fixed_pitch = fixed;
+    }
+
+    /// Fixed pitch is on when the game has set Flags 2 bit 1, selected the fixed
+    /// pitch style, or selected font 4
+    pub fn is_fixed_pitch(&self) -> bool {
+        self.force_fixed_pitch || self.current_style.is_style(Style::Fixed) || self.font == 4
+    }
+
+    // Font passed to the terminal: the normal font is swapped for the fixed-pitch
+    // font while fixed pitch is on, so set_font(1) can't select a variable pitch font
+    fn output_font(&self) -> u8 {
+        if self.font == 1 && self.is_fixed_pitch() {
+            4
+        } else {
+            self.font
         }
     }
 
@@ -674,11 +1420,54 @@ impl Screen {
         self.terminal.quit();
     }
 
-    pub fn error(&mut self, instruction: &str, message: &str, recoverable: bool) -> bool {
-        self.terminal.error(instruction, message, recoverable)
+    pub fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        prompt: &str,
+        continue_key: char,
+        recoverable: bool,
+    ) -> bool {
+        self.terminal
+            .error(instruction, message, prompt, continue_key, recoverable)
+    }
+
+    pub fn interrupted(&mut self) -> bool {
+        self.suspend_if_requested();
+        self.terminal.interrupted()
+    }
+
+    pub fn input_pending(&mut self) -> bool {
+        self.terminal.input_pending()
+    }
+
+    /// True when a player is at the keyboard to answer prompts
+    pub fn interactive(&self) -> bool {
+        self.terminal.interactive()
+    }
+
+    pub fn can_request_filename(&self) -> bool {
+        self.terminal.can_request_filename()
+    }
+
+    pub fn request_filename(&mut self, prompt: &str, default: &str) -> Option<String> {
+        self.terminal.request_filename(prompt, default)
+    }
+
+    pub fn show_message(&mut self, message: &str) {
+        self.terminal.show_message(message)
+    }
+
+    pub fn menu(&mut self, title: &str, items: &[String]) -> Option<char> {
+        self.terminal.menu(title, items)
     }
 }
 
+/// A display and keyboard for the screen model to draw on.  The screen keeps track of
+/// windows, cursors, and what is in every cell, and only asks the terminal to draw a
+/// character, move the cursor, or read a key, so another frontend can be plugged in with
+/// [`Screen::with_terminal`] by implementing the required methods.  The rest have defaults
+/// for a terminal without the feature.
 pub trait Terminal {
     fn type_name(&self) -> &str;
     fn size(&self) -> (u32, u32);
@@ -700,6 +1489,15 @@ pub trait Terminal {
     fn reset(&mut self);
     fn quit(&mut self);
     fn set_colors(&mut self, colors: (Color, Color));
+    // Everything is assumed to be available unless the terminal says otherwise
+    fn capabilities(&self) -> Capabilities {
+        let (rows, columns) = self.size();
+        Capabilities {
+            rows,
+            columns,
+            ..Capabilities::default()
+        }
+    }
     // Below are hooks used by TestTerminal as part of unit testing
     fn split_window(&mut self, _lines: u32) {}
     fn set_window(&mut self, _window: u8) {}
@@ -708,7 +1506,63 @@ pub trait Terminal {
     fn set_style(&mut self, _style: u8) {}
     fn buffer_mode(&mut self, _mode: u16) {}
     fn output_stream(&mut self, _stream: u8, _table: Option<usize>) {}
-    fn error(&mut self, instruction: &str, message: &str, recoverable: bool) -> bool;
+    // Show an error dialog with `prompt`, returning true if the error is recoverable and
+    // the player pressed `continue_key` to carry on
+    fn error(
+        &mut self,
+        instruction: &str,
+        message: &str,
+        prompt: &str,
+        continue_key: char,
+        recoverable: bool,
+    ) -> bool;
+    // Returns true, once, after the player has requested an interrupt (Ctrl-C)
+    fn interrupted(&mut self) -> bool {
+        false
+    }
+    // Returns true, once, after the player has asked to suspend the interpreter (Ctrl-Z).
+    // Signal handlers only set a flag, the terminal is suspended from here.
+    fn suspend_requested(&mut self) -> bool {
+        false
+    }
+    // Returns true, once, after the terminal has changed size.  The new size is read with
+    // size().
+    fn resized(&mut self) -> bool {
+        false
+    }
+    // Give the terminal back to the shell and stop until the interpreter is resumed
+    fn suspend(&mut self) {}
+    // Take the terminal back after a suspend.  The screen is repainted by the caller.
+    fn resume(&mut self) {}
+    // True when more input can be read without waiting, as when text has been pasted
+    fn input_pending(&mut self) -> bool {
+        false
+    }
+    // Briefly show a message from the interpreter over the game window
+    fn show_message(&mut self, _message: &str) {}
+    // How text styles should be drawn, set once when the terminal is created
+    fn set_style_map(&mut self, _style_map: StyleMap) {}
+    // False when there is no player at a keyboard to answer prompts, as with a headless
+    // backend
+    fn interactive(&self) -> bool {
+        true
+    }
+    // True when the terminal has its own file name prompt.  When it doesn't, the prompt is
+    // printed in the game window.
+    fn can_request_filename(&self) -> bool {
+        false
+    }
+    // Ask the player for a file name outside of the game window, starting from `default`.
+    // Returns None when the player cancels the prompt.
+    fn request_filename(&mut self, _prompt: &str, _default: &str) -> Option<String> {
+        None
+    }
+    // Show a menu over the game window and wait for the player to press a key, then redraw
+    // whatever the menu covered.  Returns None when no character key was pressed or the
+    // terminal can't show a menu.
+    fn menu(&mut self, _title: &str, _items: &[String]) -> Option<char> {
+        None
+    }
 }
 
 impl fmt::Debug for dyn Terminal {
@@ -719,15 +1573,19 @@ impl fmt::Debug for dyn Terminal {
 
 #[cfg(test)]
 mod tests {
+    use std::{fs, path::Path};
+
     use crate::{
         assert_ok, assert_ok_eq, assert_print, assert_some_eq,
         test_util::{
-            backspace, beep, buffer_mode, colors, cursor, input, output_stream, quit, reset,
-            scroll, split, style,
+            attributes, backspace, beep, buffer_mode, colors, cursor, input, output_stream, quit,
+            reset, scroll, set_capabilities, set_resized, set_size, split, style, text,
+            window_colors, window_style,
         },
+        zmachine::ErrorHandling,
     };
 
-    use super::*;
+    use super::{capture::ScreenEvent, *};
 
     #[test]
     fn test_cellstyle_new() {
@@ -875,6 +1733,27 @@ mod tests {
         assert_eq!(screen.lines_since_input, 0);
     }
 
+    #[test]
+    fn test_screen_with_terminal() {
+        let terminal = Box::new(HeadlessTerminal::new(30, 100));
+        let screen = assert_ok!(Screen::with_terminal(3, Config::default(), terminal));
+        assert_eq!(screen.version, 3);
+        assert_eq!((screen.rows(), screen.columns()), (30, 100));
+        assert_eq!(screen.top, 2);
+        assert_eq!(screen.cursor(), (30, 1));
+        assert_eq!(screen.terminal.type_name(), "HeadlessTerminal");
+
+        let terminal = Box::new(HeadlessTerminal::new(30, 100));
+        let screen = assert_ok!(Screen::with_terminal(6, Config::default(), terminal));
+        assert_eq!(screen.windows.len(), v6::WINDOWS);
+        assert_eq!(screen.windows[0].height, 30);
+
+        let terminal = Box::new(HeadlessTerminal::new(30, 100));
+        let e = Screen::with_terminal(9, Config::default(), terminal).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::UnsupportedVersion);
+        assert!(Screen::new(2, Config::default()).is_err());
+    }
+
     #[test]
     fn test_screen_cursor_0() {
         let screen = assert_ok!(Screen::new_v5(Config::default()));
@@ -976,6 +1855,46 @@ mod tests {
         assert_eq!(colors(), (9, 2));
     }
 
+    #[test]
+    fn test_screen_set_colors_current() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        assert!(screen.set_colors(4, 6).is_ok());
+        // 0 leaves just that channel alone
+        assert!(screen.set_colors(0, 3).is_ok());
+        assert_eq!(screen.current_colors, (Color::Green, Color::Red));
+        assert_eq!(colors(), (4, 3));
+        assert!(screen.set_colors(5, 0).is_ok());
+        assert_eq!(screen.current_colors, (Color::Yellow, Color::Red));
+        assert_eq!(colors(), (5, 3));
+    }
+
+    #[test]
+    fn test_screen_set_colors_default() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        assert!(screen.set_colors(4, 6).is_ok());
+        // 1 resets just that channel
+        assert!(screen.set_colors(1, 0).is_ok());
+        assert_eq!(screen.current_colors, (Color::White, Color::Blue));
+        assert_eq!(colors(), (9, 6));
+        assert!(screen.set_colors(4, 0).is_ok());
+        assert!(screen.set_colors(0, 1).is_ok());
+        assert_eq!(screen.current_colors, (Color::Green, Color::Black));
+        assert_eq!(colors(), (4, 2));
+    }
+
+    #[test]
+    fn test_screen_set_colors_invalid() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        assert!(screen.set_colors(4, 6).is_ok());
+        for (foreground, background) in [(10, 2), (2, 10), (0x102, 2), (2, 0xFFFF)] {
+            let e = screen.set_colors(foreground, background).unwrap_err();
+            assert_eq!(e.code(), ErrorCode::InvalidColor);
+            assert!(e.is_recoverable());
+        }
+        // Colours are unchanged
+        assert_eq!(screen.current_colors, (Color::Green, Color::Blue));
+    }
+
     #[test]
     fn test_screen_split_window_v3() {
         let mut screen = assert_ok!(Screen::new_v3(Config::default()));
@@ -1018,6 +1937,57 @@ mod tests {
         assert_eq!(split(), 12);
     }
 
+    #[test]
+    fn test_screen_split_window_clamped_v3() {
+        set_size(12, 40);
+        let mut screen = assert_ok!(Screen::new_v3(Config::default()));
+        screen.split_window(20);
+        assert_some_eq!(screen.window_1_top, 2);
+        assert_some_eq!(screen.window_1_bottom, 11);
+        assert_eq!(screen.window_0_top, 12);
+        assert_eq!(screen.cursor_0, (12, 1));
+        assert_eq!(split(), 10);
+    }
+
+    #[test]
+    fn test_screen_split_window_clamped_v5() {
+        set_size(12, 40);
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.split_window(0xFFFF);
+        assert_some_eq!(screen.window_1_top, 1);
+        assert_some_eq!(screen.window_1_bottom, 11);
+        assert_eq!(screen.window_0_top, 12);
+        assert_eq!(screen.cursor_0, (12, 1));
+        assert_eq!(split(), 11);
+
+        // A one-row lower window still scrolls, prompting for more on every line
+        input(&['\r']);
+        screen.print(&"one".chars().map(|c| c as u16).collect());
+        screen.new_line();
+        assert_eq!(screen.cursor_0, (12, 1));
+    }
+
+    #[test]
+    fn test_screen_check_size() {
+        set_size(10, 24);
+        assert!(assert_ok!(Screen::new_v5(Config::default()))
+            .check_size()
+            .is_ok());
+        set_size(9, 40);
+        let e = assert_ok!(Screen::new_v5(Config::default()))
+            .check_size()
+            .unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ScreenTooSmall);
+        assert_eq!(
+            e.message(),
+            "The terminal is 40 columns by 9 rows, but at least 24 columns by 10 rows are needed"
+        );
+        set_size(24, 23);
+        assert!(assert_ok!(Screen::new_v5(Config::default()))
+            .check_size()
+            .is_err());
+    }
+
     #[test]
     fn test_screen_unsplit_window_v3() {
         let mut screen = assert_ok!(Screen::new_v3(Config::default()));
@@ -1044,6 +2014,222 @@ mod tests {
         assert_eq!(split(), 0);
     }
 
+    // Screen 8 rows by 10 columns that captures output to a file in `dir`
+    fn capture_screen(dir: &Path) -> Screen {
+        set_size(8, 10);
+        let mut config = Config::default();
+        config.set_capture(&dir.join("capture.jsonl").to_string_lossy());
+        assert_ok!(Screen::new_v5(config))
+    }
+
+    // Fill each row of the upper window with 'U' and each row of the lower window with 'L'
+    fn paint(screen: &mut Screen) {
+        for row in 1..=screen.rows {
+            let c = if screen.is_upper_window_row(row) {
+                'U'
+            } else {
+                'L'
+            };
+            screen.print_at(&[c as u16; 10], (row, 1), &CellStyle::new());
+        }
+    }
+
+    // The captured screen contents, one string per row
+    fn screen_text(screen: &mut Screen, dir: &Path) -> Vec<String> {
+        screen.quit();
+        let capture = assert_ok!(fs::read_to_string(dir.join("capture.jsonl")));
+        let last = capture.lines().last().unwrap();
+        let event: serde_yaml::Value = assert_ok!(serde_yaml::from_str(last));
+        event["text"]
+            .as_str()
+            .unwrap()
+            .split('\n')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    // The captured background colour of each cell
+    fn screen_backgrounds(screen: &mut Screen, dir: &Path) -> Vec<Vec<u8>> {
+        screen.quit();
+        let mut grid = vec![vec![0; 10]; 8];
+        let capture = assert_ok!(fs::read_to_string(dir.join("capture.jsonl")));
+        for event in capture.lines().filter_map(ScreenEvent::from_json) {
+            if let ScreenEvent::Print {
+                row,
+                column,
+                background,
+                text,
+                ..
+            } = event
+            {
+                for i in 0..text.chars().count() {
+                    grid[row as usize - 1][column as usize - 1 + i] = background;
+                }
+            }
+        }
+        grid
+    }
+
+    const UPPER: &str = "UUUUUUUUUU";
+    const LOWER: &str = "LLLLLLLLLL";
+
+    #[test]
+    fn test_screen_capture_erase_window_0() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(3);
+        paint(&mut screen);
+        assert!(screen.erase_window(0).is_ok());
+        assert_eq!(
+            screen_text(&mut screen, dir.path()),
+            [UPPER, UPPER, UPPER, "", "", "", "", ""]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_erase_window_0_background() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(3);
+        paint(&mut screen);
+        assert!(screen.set_colors(9, 6).is_ok());
+        assert!(screen.erase_window(0).is_ok());
+        let backgrounds = screen_backgrounds(&mut screen, dir.path());
+        assert!(backgrounds[0..3].iter().flatten().all(|b| *b == 2));
+        assert!(backgrounds[3..].iter().flatten().all(|b| *b == 6));
+    }
+
+    #[test]
+    fn test_screen_capture_erase_window_1() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(3);
+        paint(&mut screen);
+        assert!(screen.erase_window(1).is_ok());
+        assert_eq!(
+            screen_text(&mut screen, dir.path()),
+            ["", "", "", LOWER, LOWER, LOWER, LOWER, LOWER]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_shrink_split() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(5);
+        paint(&mut screen);
+        screen.split_window(2);
+        // The rows given back to the lower window keep their text, like a box quote
+        assert_eq!(
+            screen_text(&mut screen, dir.path()),
+            [UPPER, UPPER, UPPER, UPPER, UPPER, LOWER, LOWER, LOWER]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_shrink_split_background() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(5);
+        paint(&mut screen);
+        assert!(screen.set_colors(9, 6).is_ok());
+        screen.split_window(2);
+        let backgrounds = screen_backgrounds(&mut screen, dir.path());
+        // The rows given back to the lower window aren't repainted in the current colours
+        assert!(backgrounds.iter().flatten().all(|b| *b == 2));
+    }
+
+    #[test]
+    fn test_screen_capture_grow_split() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(2);
+        paint(&mut screen);
+        screen.split_window(5);
+        assert_eq!(
+            screen_text(&mut screen, dir.path()),
+            [UPPER, UPPER, LOWER, LOWER, LOWER, LOWER, LOWER, LOWER]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_unsplit() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(3);
+        paint(&mut screen);
+        screen.unsplit_window();
+        assert_eq!(
+            screen_text(&mut screen, dir.path()),
+            [UPPER, UPPER, UPPER, LOWER, LOWER, LOWER, LOWER, LOWER]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_erase_window_minus_1() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(3);
+        paint(&mut screen);
+        assert!(screen.erase_window(-1).is_ok());
+        assert_eq!(screen_text(&mut screen, dir.path()), vec![""; 8]);
+        assert!(screen.window_1_top.is_none());
+        assert_eq!(screen.window_0_top, 1);
+    }
+
+    #[test]
+    fn test_screen_redraw_all() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut screen = capture_screen(dir.path());
+        screen.split_window(2);
+        paint(&mut screen);
+        assert!(screen.select_window(0).is_ok());
+        screen.cursor_0 = (8, 1);
+        screen.print(&"ab\r".chars().map(|c| c as u16).collect());
+        assert!(screen.set_style(1).is_ok());
+        screen.print(&"xyz".chars().map(|c| c as u16).collect());
+        assert!(screen.backspace().is_ok());
+
+        // Only the repaint is replayed, onto a blank screen
+        let drawn = assert_ok!(fs::read_to_string(dir.path().join("capture.jsonl")))
+            .lines()
+            .count();
+        screen.redraw_all();
+        assert_eq!(cursor(), (8, 3));
+        assert!(reset());
+        let text = screen_text(&mut screen, dir.path());
+        assert_eq!(
+            text,
+            vec![UPPER, UPPER, LOWER, LOWER, LOWER, LOWER, "abLLLLLLLL", "xy"]
+        );
+
+        let capture = assert_ok!(fs::read_to_string(dir.path().join("capture.jsonl")));
+        let events: Vec<serde_yaml::Value> = capture
+            .lines()
+            .skip(drawn)
+            .filter_map(|l| serde_yaml::from_str(l).ok())
+            .filter(|e: &serde_yaml::Value| e["event"] == "print")
+            .collect();
+        let mut grid = vec![vec![' '; 10]; 8];
+        for event in &events {
+            let row = event["row"].as_u64().unwrap() as usize;
+            let column = event["column"].as_u64().unwrap() as usize;
+            for (i, c) in event["text"].as_str().unwrap().chars().enumerate() {
+                grid[row - 1][column - 1 + i] = c;
+            }
+        }
+        let replayed: Vec<String> = grid
+            .iter()
+            .map(|r| r.iter().collect::<String>().trim_end().to_string())
+            .collect();
+        assert_eq!(replayed, text);
+        // The reverse text keeps its style
+        assert!(events.iter().any(|e| e["row"] == 8
+            && e["column"] == 1
+            && e["style"] == 1
+            && e["text"].as_str().unwrap().starts_with("xy")));
+    }
+
     #[test]
     fn test_screen_select_window_v3() {
         let mut screen = assert_ok!(Screen::new_v3(Config::default()));
@@ -1072,6 +2258,72 @@ mod tests {
         assert!(screen.select_window(1).is_err());
     }
 
+    #[test]
+    fn test_screen_select_window_attributes() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.split_window(12);
+        assert!(screen.select_window(1).is_ok());
+        assert!(screen.set_colors(4, 6).is_ok());
+        assert!(screen.set_style(Style::Bold as u8).is_ok());
+        assert_eq!(screen.set_font(4), 1);
+        assert!(screen.select_window(0).is_ok());
+        assert_eq!(screen.current_colors, (Color::White, Color::Black));
+        assert_eq!(screen.current_style.mask, 0);
+        assert_eq!(screen.font, 1);
+        assert!(screen.set_style(Style::Italic as u8).is_ok());
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.current_colors, (Color::Green, Color::Blue));
+        assert_eq!(screen.current_style.mask, Style::Bold as u8);
+        assert_eq!(screen.font, 4);
+        assert_eq!(
+            window_colors(),
+            [(1, (9, 2)), (1, (4, 6)), (0, (9, 2)), (1, (4, 6))]
+        );
+        assert_eq!(
+            window_style(),
+            [
+                (1, 0),
+                (1, Style::Bold as u8),
+                (0, 0),
+                (0, Style::Italic as u8),
+                (1, Style::Bold as u8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_select_window_resets_style_v3() {
+        let mut screen = assert_ok!(Screen::new_v3(Config::default()));
+        screen.split_window(1);
+        assert!(screen.select_window(1).is_ok());
+        assert!(screen.set_style(Style::Bold as u8).is_ok());
+        assert!(screen.select_window(0).is_ok());
+        assert_eq!(screen.current_style.mask, 0);
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.current_style.mask, 0);
+        assert_eq!(window_style().last(), Some(&(1, 0)));
+    }
+
+    #[test]
+    fn test_screen_erase_window_resets_attributes() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.split_window(10);
+        assert!(screen.select_window(1).is_ok());
+        assert!(screen.set_style(Style::Bold as u8).is_ok());
+        assert_eq!(screen.set_font(4), 1);
+        assert!(screen.select_window(0).is_ok());
+        assert!(screen.set_colors(4, 6).is_ok());
+        assert!(screen.set_style(Style::Italic as u8).is_ok());
+        assert!(screen.erase_window(1).is_ok());
+        assert_eq!(screen.current_style.mask, Style::Italic as u8);
+        assert!(screen.erase_window(0).is_ok());
+        assert_eq!(screen.current_style.mask, 0);
+        assert_eq!(screen.current_colors, (Color::Green, Color::Blue));
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.current_style.mask, 0);
+        assert_eq!(screen.font, 1);
+    }
+
     #[test]
     fn test_screen_erase_window_0_v4() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -1125,6 +2377,23 @@ mod tests {
         assert_eq!(screen.window_0_top, 1);
     }
 
+    #[test]
+    fn test_screen_erase_window_minus_1_colors() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.split_window(10);
+        assert!(screen.select_window(1).is_ok());
+        assert!(screen.set_colors(3, 4).is_ok());
+        assert!(screen.select_window(0).is_ok());
+        assert!(screen.set_colors(6, 7).is_ok());
+        assert!(screen.erase_window(-1).is_ok());
+        assert_eq!(screen.current_colors, (Color::White, Color::Black));
+        assert_eq!(colors(), (9, 2));
+        // The upper window's colours were reset too
+        screen.split_window(10);
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.current_colors, (Color::White, Color::Black));
+    }
+
     #[test]
     fn test_screen_erase_window_minus_2_v4() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -1236,7 +2505,7 @@ mod tests {
         assert_some_eq!(screen.window_1_bottom, 10);
         assert_some_eq!(screen.cursor_1, (1, 1));
         assert_eq!(screen.window_0_top, 11);
-        assert_print!("[MORE]      ");
+        assert_print!(&format!("{}      ", text("prompt.more")));
         assert_eq!(screen.lines_since_input, 0);
         assert_eq!(scroll(), 11);
     }
@@ -1422,7 +2691,26 @@ mod tests {
         assert_some_eq!(screen.cursor_1, (10, 80));
         assert_eq!(screen.window_0_top, 11);
         assert_eq!(screen.lines_since_input, 0);
-        assert_print!("aaaaaaaaaa");
+        // Text past the bottom right corner of the upper window is discarded
+        assert_print!("aaaaa");
+    }
+
+    #[test]
+    fn test_screen_print_window_1_no_scroll_v3() {
+        let mut screen = assert_ok!(Screen::new_v3(Config::default()));
+        screen.split_window(2);
+        assert!(screen.select_window(1).is_ok());
+        screen.print(&"one\rtwo\rthree".chars().map(|c| c as u16).collect());
+        // The third line is discarded rather than scrolling or spilling into window 0
+        assert_print!("onetwo");
+        assert_some_eq!(screen.cursor_1, (3, 80));
+        assert_eq!(screen.cursor_0, (24, 1));
+        assert_eq!(screen.window_0_top, 4);
+        // Moving the cursor allows output again
+        screen.move_cursor(2, 1);
+        screen.print(&vec!['x' as u16]);
+        assert_print!("onetwox");
+        assert_some_eq!(screen.cursor_1, (2, 2));
     }
 
     #[test]
@@ -1594,7 +2882,8 @@ mod tests {
         assert_eq!(screen.cursor_0, (23, 80));
         assert_some_eq!(screen.window_1_top, 1);
         assert_some_eq!(screen.window_1_bottom, 10);
-        assert_some_eq!(screen.cursor_1, (10, 7));
+        assert_some_eq!(screen.cursor_1, (10, 80));
+        assert!(screen.window_1_full);
         assert_eq!(screen.window_0_top, 11);
         assert_eq!(screen.lines_since_input, 0);
         assert_print!("");
@@ -1616,6 +2905,28 @@ mod tests {
         assert_eq!(screen.read_key(true), InputEvent::from_char(' ' as u16));
     }
 
+    #[test]
+    fn test_screen_read_key_resized() {
+        let mut screen = assert_ok!(Screen::new_v4(Config::default()));
+        screen.split_window(10);
+        screen.move_cursor(24, 80);
+        set_resized(8, 40);
+        input(&[' ']);
+        assert_eq!(screen.read_key(true), InputEvent::from_char(' ' as u16));
+        assert!(screen.resized());
+        assert!(!screen.resized());
+        assert_eq!((screen.rows(), screen.columns()), (8, 40));
+        assert_eq!(screen.cells.len(), 8);
+        assert!(screen.cells.iter().all(|row| row.len() == 40));
+        // The upper window leaves the lower window its bottom row
+        assert_eq!(screen.window_1_bottom, Some(7));
+        assert_eq!(screen.window_0_top, 8);
+        assert_eq!(screen.cursor_0, (8, 40));
+        let capabilities = screen.capabilities();
+        assert_eq!((capabilities.rows, capabilities.columns), (8, 40));
+        assert!(reset());
+    }
+
     #[test]
     fn test_screen_backspace_window_0() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -1660,6 +2971,97 @@ mod tests {
         assert_eq!(backspace(), (0, 0));
     }
 
+    // The characters drawn on a row, without the right halves of double-width characters
+    // or trailing blanks
+    fn row_text(screen: &Screen, row: u32) -> String {
+        screen.cells[row as usize - 1]
+            .iter()
+            .filter(|c| c.zchar != WIDE_TAIL)
+            .map(|c| char::from_u32(c.zchar as u32).unwrap_or('?'))
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.chars().map(|c| c as u16).collect()
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width('a' as u16), 1);
+        assert_eq!(display_width(0x9b), 1);
+        assert_eq!(display_width('é' as u16), 1);
+        assert_eq!(display_width('日' as u16), 2);
+        assert_eq!(display_width('한' as u16), 2);
+        assert_eq!(display_width('Ａ' as u16), 2);
+        assert_eq!(display_width(0x301), 0);
+        assert_eq!(display_width(0x200B), 0);
+        assert_eq!(text_width(&utf16("a日本語e\u{301}")), 8);
+        let mut text = utf16("a日本語");
+        truncate_to_width(&mut text, 4);
+        assert_eq!(text, utf16("a日"));
+    }
+
+    #[test]
+    fn test_screen_print_double_width_backspace() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.move_cursor(10, 1);
+        screen.print(&utf16("a日本語"));
+        assert_eq!(screen.cursor(), (10, 8));
+        assert_eq!(row_text(&screen, 10), "a日本語");
+        assert_print!("a日本語");
+
+        // Each backspace erases both cells of a double-width character
+        assert!(screen.backspace().is_ok());
+        assert_eq!(backspace(), (10, 6));
+        assert!(screen.backspace().is_ok());
+        assert_eq!(backspace(), (10, 4));
+        assert_eq!(screen.cursor(), (10, 4));
+        assert_eq!(row_text(&screen, 10), "a日");
+        screen.print(&utf16("x"));
+        assert_eq!(row_text(&screen, 10), "a日x");
+        assert_eq!(screen.cursor(), (10, 5));
+    }
+
+    #[test]
+    fn test_screen_print_double_width_wraps() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.move_cursor(10, 80);
+        screen.print(&utf16("日"));
+        assert_eq!(row_text(&screen, 10), "");
+        assert_eq!(row_text(&screen, 11), "日");
+        assert_eq!(screen.cursor(), (11, 3));
+
+        // Filling the row exactly moves to the next one
+        screen.move_cursor(12, 79);
+        screen.print(&utf16("本"));
+        assert_eq!(screen.cursor(), (13, 1));
+    }
+
+    #[test]
+    fn test_screen_print_zero_width() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.move_cursor(10, 1);
+        screen.print(&utf16("e\u{301}x"));
+        assert_eq!(screen.cursor(), (10, 3));
+        assert_eq!(row_text(&screen, 10), "ex");
+    }
+
+    #[test]
+    fn test_screen_overwrite_double_width() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.move_cursor(10, 1);
+        screen.print(&utf16("日本"));
+        // Printing over the right half of 日 blanks its left half
+        screen.move_cursor(10, 2);
+        screen.print(&utf16("x"));
+        assert_eq!(row_text(&screen, 10), " x本");
+        // and over the left half of 本 blanks its right half
+        screen.print(&utf16("y"));
+        assert_eq!(row_text(&screen, 10), " xy");
+    }
+
     #[test]
     fn test_screen_set_style() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -1669,6 +3071,167 @@ mod tests {
         assert_eq!(style(), Style::Italic as u8 + Style::Bold as u8);
     }
 
+    #[test]
+    fn test_attribute_from_str() {
+        assert_ok_eq!("reverse".parse::<Attribute>(), Attribute::Reverse);
+        assert_ok_eq!("bold".parse::<Attribute>(), Attribute::Bold);
+        assert_ok_eq!("italic".parse::<Attribute>(), Attribute::Italic);
+        assert_ok_eq!("underline".parse::<Attribute>(), Attribute::Underline);
+        assert_ok_eq!("dim".parse::<Attribute>(), Attribute::Dim);
+        assert_ok_eq!("blink".parse::<Attribute>(), Attribute::Blink);
+        assert_ok_eq!(
+            "bright-fg".parse::<Attribute>(),
+            Attribute::BrightForeground
+        );
+        assert!("Bold".parse::<Attribute>().is_err());
+        assert!("sparkle".parse::<Attribute>().is_err());
+    }
+
+    // Print a character in each of styles 1-15 and collect the attributes it was drawn with
+    fn style_attributes(screen: &mut Screen) -> Vec<Attributes> {
+        (1..=15)
+            .map(|style| {
+                assert!(screen.set_style(0).is_ok());
+                assert!(screen.set_style(style).is_ok());
+                screen.print(&vec![b'x' as u16]);
+                attributes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_screen_style_map_default() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        let r = Attribute::Reverse;
+        let b = Attribute::Bold;
+        let i = Attribute::Italic;
+        assert_eq!(
+            style_attributes(&mut screen),
+            [
+                Attributes::new(&[r]),
+                Attributes::new(&[b]),
+                Attributes::new(&[r, b]),
+                Attributes::new(&[i]),
+                Attributes::new(&[r, i]),
+                Attributes::new(&[b, i]),
+                Attributes::new(&[r, b, i]),
+                Attributes::new(&[]),
+                Attributes::new(&[r]),
+                Attributes::new(&[b]),
+                Attributes::new(&[r, b]),
+                Attributes::new(&[i]),
+                Attributes::new(&[r, i]),
+                Attributes::new(&[b, i]),
+                Attributes::new(&[r, b, i]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_style_map_custom() {
+        let dir = assert_ok!(tempfile::tempdir());
+        set_size(8, 10);
+        let mut config = Config::default();
+        config.set_capture(&dir.path().join("capture.jsonl").to_string_lossy());
+        let mut style_map = StyleMap::default();
+        style_map.set(
+            Style::Reverse,
+            Attributes::new(&[Attribute::Reverse, Attribute::Bold]),
+        );
+        style_map.set(Style::Bold, Attributes::new(&[Attribute::BrightForeground]));
+        style_map.set(Style::Italic, Attributes::new(&[Attribute::Underline]));
+        style_map.set(Style::Fixed, Attributes::new(&[Attribute::Dim]));
+        config.set_style_map(style_map);
+        let mut screen = assert_ok!(Screen::new_v5(config));
+
+        let r = [Attribute::Reverse, Attribute::Bold];
+        let b = [Attribute::BrightForeground];
+        let i = [Attribute::Underline];
+        let f = [Attribute::Dim];
+        let expected: Vec<Attributes> = (1..=15u8)
+            .map(|style| {
+                let mut list = Vec::new();
+                for (bit, attributes) in [(1, &r[..]), (2, &b[..]), (4, &i[..]), (8, &f[..])] {
+                    if style & bit == bit {
+                        list.extend_from_slice(attributes);
+                    }
+                }
+                Attributes::new(&list)
+            })
+            .collect();
+        assert_eq!(style_attributes(&mut screen), expected);
+        // Spot check a couple of combinations
+        assert_eq!(
+            expected[2],
+            Attributes::new(&[
+                Attribute::Reverse,
+                Attribute::Bold,
+                Attribute::BrightForeground
+            ])
+        );
+        assert_eq!(
+            expected[11],
+            Attributes::new(&[Attribute::Underline, Attribute::Dim])
+        );
+    }
+
+    #[test]
+    fn test_screen_set_style_no_italic() {
+        set_capabilities(Capabilities {
+            italic: false,
+            ..Capabilities::default()
+        });
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        // Italic is kept for the terminal to draw underlined
+        assert!(screen.set_style(Style::Italic as u8).is_ok());
+        assert_eq!(style(), Style::Italic as u8);
+    }
+
+    #[test]
+    fn test_screen_set_style_no_italic_or_underline() {
+        set_capabilities(Capabilities {
+            bold: false,
+            italic: false,
+            underline: false,
+            ..Capabilities::default()
+        });
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        assert!(screen.set_style(Style::Reverse as u8).is_ok());
+        assert_eq!(style(), Style::Reverse as u8);
+        // Italic and bold are dropped without resetting the current style
+        assert!(screen.set_style(Style::Italic as u8).is_ok());
+        assert_eq!(style(), Style::Reverse as u8);
+        assert!(screen
+            .set_style(Style::Bold as u8 | Style::Fixed as u8)
+            .is_ok());
+        assert_eq!(style(), Style::Reverse as u8 | Style::Fixed as u8);
+        assert!(screen.set_style(Style::Roman as u8).is_ok());
+        assert_eq!(style(), 0);
+    }
+
+    #[test]
+    fn test_screen_capabilities() {
+        set_capabilities(Capabilities {
+            mouse: false,
+            rows: 1,
+            columns: 1,
+            ..Capabilities::default()
+        });
+        let screen = assert_ok!(Screen::new_v5(Config::new(
+            3,
+            6,
+            false,
+            ErrorHandling::Ignore,
+            128.0
+        )));
+        let capabilities = screen.capabilities();
+        assert!(!capabilities.mouse);
+        assert!(capabilities.italic);
+        assert_eq!(capabilities.rows, 24);
+        assert_eq!(capabilities.columns, 80);
+        assert_eq!(capabilities.default_colors, (Color::Red, Color::Blue));
+    }
+
     #[test]
     fn test_screen_buffer_mode() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -1719,6 +3282,82 @@ mod tests {
         assert_eq!(screen.font, 1);
     }
 
+    #[test]
+    fn test_screen_set_font_availability() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        for (font, result) in [(0, 1), (1, 1), (2, 0), (3, 1), (4, 3), (5, 0), (255, 0)] {
+            assert_eq!(screen.set_font(font), result, "font {}", font);
+        }
+        // Fonts 2, 5 and 255 left font 4 selected
+        assert_eq!(screen.font, 4);
+    }
+
+    #[test]
+    fn test_screen_set_font_no_graphics_font() {
+        let mut config = Config::default();
+        config.set_graphics_font(false);
+        let mut screen = assert_ok!(Screen::new_v5(config));
+        assert_eq!(screen.set_font(3), 0);
+        assert_eq!(screen.font, 1);
+        assert_eq!(screen.set_font(4), 1);
+        assert_eq!(screen.set_font(3), 0);
+        assert_eq!(screen.set_font(0), 4);
+    }
+
+    #[test]
+    fn test_screen_set_font_per_window() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.split_window(4);
+        assert_eq!(screen.set_font(4), 1);
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.set_font(0), 1);
+        assert_eq!(screen.set_font(3), 1);
+        // An unavailable font leaves the window's font alone
+        assert_eq!(screen.set_font(2), 0);
+        assert!(screen.select_window(0).is_ok());
+        assert_eq!(screen.set_font(0), 4);
+        assert!(screen.select_window(1).is_ok());
+        assert_eq!(screen.set_font(0), 3);
+        assert_eq!(screen.output_font(), 3);
+    }
+
+    #[test]
+    fn test_screen_is_fixed_pitch() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        assert!(!screen.is_fixed_pitch());
+        assert_eq!(screen.output_font(), 1);
+        assert!(screen.set_style(Style::Fixed as u8).is_ok());
+        assert!(screen.is_fixed_pitch());
+        assert_eq!(screen.output_font(), 4);
+        assert!(screen.set_style(0).is_ok());
+        assert!(!screen.is_fixed_pitch());
+        assert_eq!(screen.set_font(4), 1);
+        assert!(screen.is_fixed_pitch());
+        assert_eq!(screen.set_font(1), 4);
+        assert!(!screen.is_fixed_pitch());
+    }
+
+    #[test]
+    fn test_screen_force_fixed_pitch() {
+        let mut screen = assert_ok!(Screen::new_v5(Config::default()));
+        screen.set_force_fixed_pitch(true);
+        assert!(screen.is_fixed_pitch());
+        assert!(screen.set_style(0).is_ok());
+        assert!(screen.is_fixed_pitch());
+        // Selecting the normal font still reports font 1, but output is fixed pitch
+        assert_eq!(screen.set_font(4), 1);
+        assert_eq!(screen.set_font(1), 4);
+        assert_eq!(screen.set_font(0), 1);
+        assert_eq!(screen.output_font(), 4);
+        // Other fonts are unaffected
+        assert_eq!(screen.set_font(3), 1);
+        assert_eq!(screen.output_font(), 3);
+        assert_eq!(screen.set_font(1), 3);
+        screen.set_force_fixed_pitch(false);
+        assert!(!screen.is_fixed_pitch());
+        assert_eq!(screen.output_font(), 1);
+    }
+
     #[test]
     fn test_screen_ouptut_stream() {
         let mut screen = assert_ok!(Screen::new_v4(Config::default()));
@@ -0,0 +1,769 @@
+//! The Version 6 screen model (§8.8).  Instead of a lower and an upper window, the screen
+//! has eight windows, each a rectangle that can be placed anywhere with its own cursor,
+//! margins, and attributes.  Screen units are character cells, so the font is one unit
+//! square and window positions and sizes are in rows and columns.
+
+use crate::{error::*, recoverable_error};
+
+use super::{display_width, Cell, CellStyle, Color, Screen, Style, WindowAttributes, WIDE_TAIL};
+
+/// Number of windows in the V6 screen model
+pub const WINDOWS: usize = 8;
+
+/// Window attributes, set with WINDOW_STYLE
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WindowAttribute {
+    Wrapping = 0x1,
+    Scrolling = 0x2,
+    Transcript = 0x4,
+    Buffered = 0x8,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) struct Window {
+    // Top left corner with 1,1 at the top left of the screen.  A window may extend past
+    // the edge of the screen; anything printed there is discarded.
+    pub(super) top: u32,
+    pub(super) left: u32,
+    pub(super) height: u32,
+    pub(super) width: u32,
+    // row, column with 1,1 at the top left of the window
+    pub(super) cursor: (u32, u32),
+    // left, right
+    pub(super) margins: (u32, u32),
+    // The newline interrupt routine and countdown are kept for the game to read back, but
+    // the routine is never called
+    newline_interrupt: u16,
+    interrupt_countdown: u16,
+    pub(super) attributes: u16,
+    // Lines printed since the last input or [MORE] prompt.  The game can set it negative
+    // to print more than a screenful without a prompt.
+    pub(super) line_count: u16,
+}
+
+impl Window {
+    // Window 0 starts out covering the whole screen, while the others are empty until the
+    // game places them
+    pub(super) fn initial(rows: u32, columns: u32) -> Vec<Window> {
+        let mut windows = vec![
+            Window {
+                top: 1,
+                left: 1,
+                height: 0,
+                width: 0,
+                cursor: (1, 1),
+                margins: (0, 0),
+                newline_interrupt: 0,
+                interrupt_countdown: 0,
+                attributes: WindowAttribute::Buffered as u16,
+                line_count: 0,
+            };
+            WINDOWS
+        ];
+        windows[0].height = rows;
+        windows[0].width = columns;
+        windows[0].attributes = 0xF;
+        windows
+    }
+
+    pub(super) fn has(&self, attribute: WindowAttribute) -> bool {
+        self.attributes & attribute as u16 != 0
+    }
+
+    // The last column text can be printed in, inside the right margin
+    pub(super) fn right_edge(&self) -> u32 {
+        self.width.saturating_sub(self.margins.1)
+    }
+
+    // Move the cursor to the start of the first line, inside the left margin
+    fn home(&mut self) {
+        self.cursor = (1, self.margins.0 + 1);
+    }
+}
+
+impl Screen {
+    // The window an operand refers to, where -3 is the selected window
+    fn window_index(&self, window: i16) -> Result<usize, RuntimeError> {
+        match window {
+            -3 => Ok(self.selected_window as usize),
+            0..=7 => Ok(window as usize),
+            _ => recoverable_error!(
+                ErrorCode::InvalidWindow,
+                "{} is not a valid window [0..7]",
+                window
+            ),
+        }
+    }
+
+    // The selected window's cursor position on the screen
+    pub(super) fn window_cursor_position(&self) -> (u32, u32) {
+        let w = &self.windows[self.selected_window as usize];
+        (w.top + w.cursor.0 - 1, w.left + w.cursor.1 - 1)
+    }
+
+    // Text attributes of a window, which are the current ones for the selected window
+    fn text_attributes(&self, index: usize) -> WindowAttributes {
+        if index == self.selected_window as usize {
+            WindowAttributes {
+                colors: self.current_colors,
+                style: self.current_style,
+                font: self.font,
+            }
+        } else {
+            self.window_attributes[index]
+        }
+    }
+
+    // Run `f` with window `index` selected, for opcodes that change the text attributes of
+    // a window other than the selected one
+    fn in_window<T>(&mut self, index: usize, f: impl FnOnce(&mut Screen) -> T) -> T {
+        let selected = self.selected_window;
+        self.switch_attributes(index as u8);
+        self.selected_window = index as u8;
+        let result = f(self);
+        self.switch_attributes(selected);
+        self.selected_window = selected;
+        self.terminal.set_colors(self.current_colors);
+        self.terminal.set_style(self.current_style.mask);
+        result
+    }
+
+    // Blank an area of the screen in `colors`.  Anything off the screen is ignored.
+    fn fill(&mut self, top: u32, left: u32, height: u32, width: u32, colors: (Color, Color)) {
+        for row in top..(top + height).min(self.rows + 1) {
+            for column in left..(left + width).min(self.columns + 1) {
+                self.draw(0x20, row, column, colors, &CellStyle::new(), 1);
+            }
+        }
+    }
+
+    // Scroll an area of the screen up `lines` rows, or down when `lines` is negative,
+    // blanking the rows uncovered in `colors`
+    fn scroll_area(
+        &mut self,
+        (top, left, height, width): (u32, u32, u32, u32),
+        lines: i32,
+        colors: (Color, Color),
+    ) {
+        let bottom = (top + height).min(self.rows + 1);
+        let right = (left + width).min(self.columns + 1);
+        let rows: Vec<u32> = if lines > 0 {
+            (top..bottom).collect()
+        } else {
+            (top..bottom).rev().collect()
+        };
+        for row in rows {
+            let source = row as i64 + lines as i64;
+            for column in left..right {
+                let cell = if source >= top as i64 && source < bottom as i64 {
+                    self.cell(source as u32, column).copied()
+                } else {
+                    None
+                }
+                .unwrap_or(Cell::blank(colors));
+                if let Some(c) = self.cell_mut(row, column) {
+                    *c = cell;
+                }
+                // The right half of a double-width character is drawn with its left half
+                if cell.zchar != WIDE_TAIL {
+                    self.terminal.print_at(
+                        cell.zchar,
+                        row,
+                        column,
+                        cell.colors,
+                        &cell.style,
+                        cell.font,
+                    );
+                }
+            }
+        }
+    }
+
+    pub(super) fn select_window_v6(&mut self, window: u8) -> Result<(), RuntimeError> {
+        let index = self.window_index(window as i16)?;
+        self.terminal.set_window(window);
+        self.switch_attributes(index as u8);
+        self.selected_window = index as u8;
+        self.terminal.set_colors(self.current_colors);
+        self.terminal.set_style(self.current_style.mask);
+        Ok(())
+    }
+
+    // SPLIT_WINDOW puts window 1 across the top `lines` rows of the screen and window 0
+    // below it.  Cursors keep their place on the screen, unless that's now outside their
+    // window.
+    pub(super) fn split_window_v6(&mut self, lines: u32) {
+        let lines = lines.min(self.rows);
+        let columns = self.columns;
+        let upper = &mut self.windows[1];
+        upper.cursor.0 = upper.cursor.0 + upper.top - 1;
+        upper.top = 1;
+        upper.left = 1;
+        upper.height = lines;
+        upper.width = columns;
+        if upper.cursor.0 > upper.height {
+            upper.home();
+        }
+
+        let lower = &mut self.windows[0];
+        let row = (lower.cursor.0 + lower.top) as i64 - (lines + 1) as i64;
+        lower.top = lines + 1;
+        lower.height = self.rows - lines;
+        if row < 1 {
+            lower.home();
+        } else {
+            lower.cursor.0 = row as u32;
+        }
+        self.terminal.split_window(lines);
+    }
+
+    // -1 clears the screen and joins window 1 back into window 0, -2 clears the screen
+    // leaving the windows where they are, and anything else clears that window
+    pub(super) fn erase_window_v6(&mut self, window: i16) -> Result<(), RuntimeError> {
+        match window {
+            -1 | -2 => {
+                self.terminal.erase_window(window as i8);
+                self.fill(1, 1, self.rows, self.columns, self.current_colors);
+                if window == -1 {
+                    self.split_window_v6(0);
+                    self.select_window_v6(0)?;
+                    self.windows[0].home();
+                }
+                for w in self.windows.iter_mut() {
+                    w.line_count = 0;
+                }
+            }
+            _ => {
+                let index = self.window_index(window)?;
+                self.terminal.erase_window(index as i8);
+                let w = self.windows[index];
+                let colors = self.text_attributes(index).colors;
+                self.fill(w.top, w.left, w.height, w.width, colors);
+                self.windows[index].home();
+                self.windows[index].line_count = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Erase `cells` from the cursor to the right in the selected window, or to the right
+    /// margin when `cells` is 1.  The cursor doesn't move.
+    pub fn erase_line_v6(&mut self, cells: u32) {
+        self.terminal.erase_line();
+        let w = self.windows[self.selected_window as usize];
+        if w.cursor.0 <= w.height {
+            let end = if cells == 1 {
+                w.right_edge()
+            } else {
+                u32::min(w.right_edge(), w.cursor.1 + cells - 1)
+            };
+            let (row, column) = self.window_cursor_position();
+            let width = (end + 1).saturating_sub(w.cursor.1);
+            self.fill(row, column, 1, width, self.current_colors);
+        }
+    }
+
+    pub(super) fn print_char_v6(&mut self, zchar: u16) {
+        let index = self.selected_window as usize;
+        if zchar == 0xd {
+            return self.new_line_v6();
+        } else if zchar == 0 {
+            return;
+        }
+
+        let width = display_width(zchar);
+        let w = self.windows[index];
+        if w.cursor.1 + width.max(1) - 1 > w.right_edge() && w.has(WindowAttribute::Wrapping) {
+            self.new_line_v6();
+        }
+
+        let w = self.windows[index];
+        if w.cursor.0 > w.height || w.cursor.1 + width.max(1) - 1 > w.right_edge() {
+            debug!(target: "app::screen", "Window {} full, discarding {:#04x}", index, zchar);
+            return;
+        }
+
+        let (row, column) = self.window_cursor_position();
+        if row <= self.rows && column + width.max(1) - 1 <= self.columns {
+            let style = self.current_style;
+            self.draw_char(
+                zchar,
+                row,
+                column,
+                self.current_colors,
+                &style,
+                self.output_font(),
+            );
+        }
+        self.windows[index].cursor.1 += width;
+    }
+
+    // Move to the start of the next line, scrolling a window that has the scrolling
+    // attribute when the cursor is on its last line.  Other windows don't scroll, and
+    // anything printed below their last line is discarded.
+    pub(super) fn new_line_v6(&mut self) {
+        let index = self.selected_window as usize;
+        let w = &mut self.windows[index];
+        w.cursor.1 = w.margins.0 + 1;
+        if w.cursor.0 < w.height || !w.has(WindowAttribute::Scrolling) {
+            w.cursor.0 += 1;
+        } else {
+            let w = *w;
+            let colors = self.current_colors;
+            self.scroll_area((w.top, w.left, w.height, w.width), 1, colors);
+        }
+
+        let w = &mut self.windows[index];
+        if w.has(WindowAttribute::Scrolling) && w.height > 1 {
+            let count = (w.line_count as i16).saturating_add(1);
+            w.line_count = count as u16;
+            if count >= (w.height - 1) as i16 {
+                self.more_v6(index);
+            }
+        }
+    }
+
+    // Show the [MORE] prompt on the last line of a window and wait for a key.  Return
+    // scrolls a line at a time, anything else a screenful.
+    fn more_v6(&mut self, index: usize) {
+        let w = self.windows[index];
+        let (row, column) = (w.top + w.height - 1, w.left + w.margins.0);
+        let colors = self.current_colors;
+        let mut style = CellStyle::new();
+        style.set(Style::Reverse as u8);
+        let prompt = self.more_prompt.clone();
+        for (i, c) in prompt.iter().enumerate() {
+            let column = column + i as u32;
+            if row <= self.rows && column <= self.columns {
+                self.draw(*c, row, column, colors, &style, 1);
+            }
+        }
+
+        let key = self.read_key(true).zchar();
+        self.fill(row, column, 1, prompt.len() as u32, colors);
+        self.windows[index].line_count = if key == Some(0xd) {
+            w.height as u16 - 2
+        } else {
+            0
+        };
+    }
+
+    /// Move window `window` so its top left corner is at `top`, `left`
+    pub fn move_window(&mut self, window: i16, top: u32, left: u32) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &mut self.windows[index];
+        w.top = top.max(1);
+        w.left = left.max(1);
+        Ok(())
+    }
+
+    /// Resize window `window`.  A cursor left outside the window goes back to its top left.
+    pub fn window_size(
+        &mut self,
+        window: i16,
+        height: u32,
+        width: u32,
+    ) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &mut self.windows[index];
+        w.height = height;
+        w.width = width;
+        if w.cursor.0 > height || w.cursor.1 > width {
+            w.home();
+        }
+        Ok(())
+    }
+
+    /// Change the attributes of window `window`: operation 0 sets them to `flags`, 1 sets
+    /// the bits in `flags`, 2 clears them, and 3 toggles them
+    pub fn window_style(
+        &mut self,
+        window: i16,
+        flags: u16,
+        operation: u16,
+    ) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &mut self.windows[index];
+        w.attributes = match operation {
+            0 => flags,
+            1 => w.attributes | flags,
+            2 => w.attributes & !flags,
+            3 => w.attributes ^ flags,
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::InvalidWindowProperty,
+                    "WINDOW_STYLE operation {} is not valid [0..3]",
+                    operation
+                )
+            }
+        } & 0xF;
+        Ok(())
+    }
+
+    /// Set the left and right margins of window `window`.  A cursor left outside the
+    /// margins moves to the left margin.
+    pub fn set_margins(&mut self, window: i16, left: u32, right: u32) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &mut self.windows[index];
+        w.margins = (left, right);
+        if w.cursor.1 <= left || w.cursor.1 > w.right_edge() {
+            w.cursor.1 = left + 1;
+        }
+        Ok(())
+    }
+
+    /// Move the cursor of window `window`, relative to its top left corner
+    pub fn set_window_cursor(
+        &mut self,
+        window: i16,
+        row: u32,
+        column: u32,
+    ) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        self.windows[index].cursor = (row.max(1), column.max(1));
+        if index == self.selected_window as usize {
+            self.terminal.move_cursor(self.window_cursor_position());
+        }
+        Ok(())
+    }
+
+    /// Scroll window `window` up `lines` rows, or down when `lines` is negative, blanking
+    /// the rows uncovered in its background colour.  The cursor doesn't move.
+    pub fn scroll_window(&mut self, window: i16, lines: i16) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = self.windows[index];
+        let colors = self.text_attributes(index).colors;
+        self.scroll_area((w.top, w.left, w.height, w.width), lines as i32, colors);
+        self.terminal.flush();
+        Ok(())
+    }
+
+    /// Erase an area of the selected window in its background colour, with `at` relative to
+    /// the window's top left corner, as when a picture is erased
+    pub fn erase_area(&mut self, at: (u32, u32), size: (u32, u32)) {
+        let w = self.windows[self.selected_window as usize];
+        let (row, column) = (w.top + at.0.max(1) - 1, w.left + at.1.max(1) - 1);
+        self.fill(row, column, size.0, size.1, self.current_colors);
+        self.terminal.flush();
+    }
+
+    /// Set the colours of window `window`, as for [`Screen::set_colors`]
+    pub fn set_window_colors(
+        &mut self,
+        window: i16,
+        foreground: u16,
+        background: u16,
+    ) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        self.in_window(index, |s| s.set_colors(foreground, background))
+    }
+
+    /// Select a font for window `window`, as for [`Screen::set_font`]
+    pub fn set_window_font(&mut self, window: i16, font: u8) -> Result<u8, RuntimeError> {
+        let index = self.window_index(window)?;
+        Ok(self.in_window(index, |s| s.set_font(font)))
+    }
+
+    /// Read property `property` of window `window` (§8.8.3.2, GET_WIND_PROP)
+    pub fn window_property(&self, window: i16, property: u16) -> Result<u16, RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &self.windows[index];
+        let attributes = self.text_attributes(index);
+        let value = match property {
+            0 => w.top,
+            1 => w.left,
+            2 => w.height,
+            3 => w.width,
+            4 => w.cursor.0,
+            5 => w.cursor.1,
+            6 => w.margins.0,
+            7 => w.margins.1,
+            8 => w.newline_interrupt as u32,
+            9 => w.interrupt_countdown as u32,
+            10 => attributes.style.mask as u32,
+            11 => attributes.colors.0 as u32 | (attributes.colors.1 as u32) << 8,
+            12 => attributes.font as u32,
+            // Font height and width, both one unit
+            13 => 0x0101,
+            14 => w.attributes as u32,
+            15 => w.line_count as u32,
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::InvalidWindowProperty,
+                    "Window property {} is not valid [0..15]",
+                    property
+                )
+            }
+        };
+        Ok(value as u16)
+    }
+
+    /// Write property `property` of window `window` (PUT_WIND_PROP).  The font size can't
+    /// be changed, so writing it is ignored.
+    pub fn set_window_property(
+        &mut self,
+        window: i16,
+        property: u16,
+        value: u16,
+    ) -> Result<(), RuntimeError> {
+        let index = self.window_index(window)?;
+        let w = &mut self.windows[index];
+        match property {
+            0 => w.top = value.max(1) as u32,
+            1 => w.left = value.max(1) as u32,
+            2 => w.height = value as u32,
+            3 => w.width = value as u32,
+            4 => w.cursor.0 = value.max(1) as u32,
+            5 => w.cursor.1 = value.max(1) as u32,
+            6 => w.margins.0 = value as u32,
+            7 => w.margins.1 = value as u32,
+            8 => w.newline_interrupt = value,
+            9 => w.interrupt_countdown = value,
+            10 => self.in_window(index, |s| {
+                s.current_style = CellStyle::new();
+                s.set_style(value as u8)
+            })?,
+            11 => self.in_window(index, |s| s.set_colors(value & 0xFF, value >> 8))?,
+            12 => {
+                self.in_window(index, |s| s.set_font(value as u8));
+            }
+            13 => {
+                debug!(target: "app::screen", "Ignoring font size {:04x} for window {}", value, index)
+            }
+            14 => w.attributes = value & 0xF,
+            15 => w.line_count = value,
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::InvalidWindowProperty,
+                    "Window property {} is not valid [0..15]",
+                    property
+                )
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert_ok, assert_ok_eq, assert_print,
+        config::Config,
+        test_util::{input, text},
+    };
+
+    use super::*;
+
+    fn screen() -> Screen {
+        assert_ok!(Screen::new_v6(Config::default()))
+    }
+
+    #[test]
+    fn test_initial_windows() {
+        let screen = screen();
+        assert_eq!(screen.windows.len(), WINDOWS);
+        assert_eq!(screen.windows[0].height, 24);
+        assert_eq!(screen.windows[0].width, 80);
+        assert_eq!(screen.windows[0].attributes, 0xF);
+        for w in &screen.windows[1..] {
+            assert_eq!((w.top, w.left, w.height, w.width), (1, 1, 0, 0));
+            assert!(w.has(WindowAttribute::Buffered));
+            assert!(!w.has(WindowAttribute::Wrapping));
+        }
+        assert_eq!(screen.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn test_split_window() {
+        let mut screen = screen();
+        assert!(screen.set_window_cursor(0, 5, 3).is_ok());
+        screen.split_window_v6(10);
+        assert_eq!((screen.windows[1].top, screen.windows[1].height), (1, 10));
+        assert_eq!(screen.windows[1].width, 80);
+        assert_eq!((screen.windows[0].top, screen.windows[0].height), (11, 14));
+        // The cursor was covered by window 1
+        assert_eq!(screen.windows[0].cursor, (1, 1));
+        screen.split_window_v6(0);
+        // ... and keeps its place on the screen
+        assert_eq!(screen.windows[0].cursor, (11, 1));
+    }
+
+    #[test]
+    fn test_window_index() {
+        let mut screen = screen();
+        assert!(screen.select_window_v6(3).is_ok());
+        assert_ok_eq!(screen.window_index(-3), 3);
+        assert_ok_eq!(screen.window_index(7), 7);
+        assert!(screen.window_index(8).is_err());
+        assert!(screen.window_index(-1).is_err());
+    }
+
+    #[test]
+    fn test_print_wrap() {
+        let mut screen = screen();
+        assert!(screen.move_window(2, 5, 10).is_ok());
+        assert!(screen.window_size(2, 3, 4).is_ok());
+        assert!(screen
+            .window_style(2, WindowAttribute::Wrapping as u16, 1)
+            .is_ok());
+        assert!(screen.select_window_v6(2).is_ok());
+        for c in "abcdef".chars() {
+            screen.print_char_v6(c as u16);
+        }
+        assert_eq!(screen.windows[2].cursor, (2, 3));
+        assert_eq!(screen.window_cursor_position(), (6, 12));
+        assert_print!("abcdef");
+        assert_eq!(screen.cell(5, 10).unwrap().zchar, 'a' as u16);
+        assert_eq!(screen.cell(6, 10).unwrap().zchar, 'e' as u16);
+    }
+
+    #[test]
+    fn test_print_no_wrap() {
+        let mut screen = screen();
+        assert!(screen.window_size(2, 3, 4).is_ok());
+        assert!(screen.select_window_v6(2).is_ok());
+        for c in "abcdef".chars() {
+            screen.print_char_v6(c as u16);
+        }
+        // Text past the right edge is discarded
+        assert_eq!(screen.windows[2].cursor, (1, 5));
+        assert_print!("abcd");
+    }
+
+    #[test]
+    fn test_new_line_no_scroll() {
+        let mut screen = screen();
+        assert!(screen.window_size(2, 2, 4).is_ok());
+        assert!(screen.select_window_v6(2).is_ok());
+        screen.new_line_v6();
+        screen.new_line_v6();
+        assert_eq!(screen.windows[2].cursor, (3, 1));
+        // Below the last line
+        screen.print_char_v6('a' as u16);
+        assert_print!("");
+    }
+
+    #[test]
+    fn test_new_line_scroll() {
+        let mut screen = screen();
+        assert!(screen.set_window_cursor(0, 24, 1).is_ok());
+        screen.print_char_v6('a' as u16);
+        screen.new_line_v6();
+        assert_eq!(screen.windows[0].cursor, (24, 1));
+        assert_eq!(screen.cell(23, 1).unwrap().zchar, 'a' as u16);
+        assert_eq!(screen.cell(24, 1).unwrap().zchar, 0x20);
+    }
+
+    #[test]
+    fn test_new_line_more() {
+        let mut screen = screen();
+        screen.windows[0].line_count = 22;
+        input(&[' ']);
+        screen.new_line_v6();
+        assert_print!(&format!("{}      ", text("prompt.more")));
+        assert_eq!(screen.windows[0].line_count, 0);
+    }
+
+    #[test]
+    fn test_erase_window() {
+        let mut screen = screen();
+        assert!(screen.window_size(1, 2, 3).is_ok());
+        assert!(screen.set_window_cursor(1, 2, 2).is_ok());
+        assert!(screen.erase_window_v6(1).is_ok());
+        assert_eq!(screen.windows[1].cursor, (1, 1));
+        assert_print!("      ");
+        assert!(screen.erase_window_v6(8).is_err());
+    }
+
+    #[test]
+    fn test_erase_window_minus_1() {
+        let mut screen = screen();
+        screen.split_window_v6(5);
+        assert!(screen.select_window_v6(1).is_ok());
+        assert!(screen.erase_window_v6(-1).is_ok());
+        assert_eq!(screen.selected_window, 0);
+        assert_eq!(screen.windows[0].top, 1);
+        assert_eq!(screen.windows[0].height, 24);
+        assert_eq!(screen.windows[1].height, 0);
+    }
+
+    #[test]
+    fn test_erase_line() {
+        let mut screen = screen();
+        assert!(screen.set_margins(0, 2, 70).is_ok());
+        assert!(screen.set_window_cursor(0, 3, 5).is_ok());
+        screen.erase_line_v6(1);
+        assert_print!(&" ".repeat(6));
+        screen.erase_line_v6(3);
+        assert_print!(&" ".repeat(9));
+    }
+
+    #[test]
+    fn test_set_margins() {
+        let mut screen = screen();
+        assert!(screen.set_margins(0, 4, 10).is_ok());
+        assert_eq!(screen.windows[0].cursor, (1, 5));
+        assert_eq!(screen.windows[0].right_edge(), 70);
+        screen.new_line_v6();
+        assert_eq!(screen.windows[0].cursor, (2, 5));
+    }
+
+    #[test]
+    fn test_window_style() {
+        let mut screen = screen();
+        assert!(screen.window_style(1, 0x3, 0).is_ok());
+        assert_eq!(screen.windows[1].attributes, 0x3);
+        assert!(screen.window_style(1, 0x4, 1).is_ok());
+        assert_eq!(screen.windows[1].attributes, 0x7);
+        assert!(screen.window_style(1, 0x1, 2).is_ok());
+        assert_eq!(screen.windows[1].attributes, 0x6);
+        assert!(screen.window_style(1, 0xA, 3).is_ok());
+        assert_eq!(screen.windows[1].attributes, 0xC);
+        assert!(screen.window_style(1, 0x1, 4).is_err());
+    }
+
+    #[test]
+    fn test_window_property() {
+        let mut screen = screen();
+        assert!(screen.move_window(3, 4, 5).is_ok());
+        assert!(screen.window_size(3, 6, 7).is_ok());
+        assert!(screen.set_window_property(3, 8, 0x1234).is_ok());
+        assert!(screen.set_window_property(3, 15, 0xFFFE).is_ok());
+        assert_ok_eq!(screen.window_property(3, 0), 4);
+        assert_ok_eq!(screen.window_property(3, 1), 5);
+        assert_ok_eq!(screen.window_property(3, 2), 6);
+        assert_ok_eq!(screen.window_property(3, 3), 7);
+        assert_ok_eq!(screen.window_property(3, 4), 1);
+        assert_ok_eq!(screen.window_property(3, 8), 0x1234);
+        assert_ok_eq!(screen.window_property(3, 13), 0x0101);
+        assert_ok_eq!(screen.window_property(3, 14), 0x8);
+        assert_ok_eq!(screen.window_property(3, 15), 0xFFFE);
+        assert!(screen.window_property(3, 16).is_err());
+        assert!(screen.set_window_property(3, 16, 0).is_err());
+    }
+
+    #[test]
+    fn test_window_property_colors() {
+        let mut screen = screen();
+        assert!(screen.set_window_colors(2, 4, 5).is_ok());
+        assert_ok_eq!(screen.window_property(2, 11), 0x0504);
+        // The selected window is unchanged
+        assert_eq!(screen.current_colors, (Color::White, Color::Black));
+        assert!(screen.select_window_v6(2).is_ok());
+        assert_eq!(screen.current_colors, (Color::Green, Color::Yellow));
+    }
+
+    #[test]
+    fn test_scroll_window() {
+        let mut screen = screen();
+        assert!(screen.window_size(1, 3, 2).is_ok());
+        assert!(screen.select_window_v6(1).is_ok());
+        screen.print_char_v6('a' as u16);
+        assert!(screen.scroll_window(1, -1).is_ok());
+        assert_eq!(screen.cell(1, 1).unwrap().zchar, 0x20);
+        assert_eq!(screen.cell(2, 1).unwrap().zchar, 'a' as u16);
+        assert!(screen.scroll_window(1, 1).is_ok());
+        assert_eq!(screen.cell(1, 1).unwrap().zchar, 'a' as u16);
+        assert_eq!(screen.cell(2, 1).unwrap().zchar, 0x20);
+    }
+}
@@ -0,0 +1,237 @@
+use serde_yaml::{self, Mapping, Value};
+use std::collections::HashMap;
+use std::fs::File;
+
+use crate::error::{ErrorCode, RuntimeError};
+use crate::recoverable_error;
+
+// The English text of every message the interpreter prints itself, as opposed to text
+// printed by the game.  `{name}` is replaced by the value passed for `name`.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("error.continue_key", "c"),
+    ("error.create_file", "creating file"),
+    ("error.fatal", "Fatal error - [{code}]: {message}"),
+    ("error.load_story", "Loading story"),
+    ("error.prefix", "Error"),
+    ("error.read", "reading"),
+    ("error.restore", "restoring"),
+    ("error.start_stream_2", "starting stream 2"),
+    ("error.start_transcript", "starting transcript"),
+    ("menu.other_key", "Any other key returns to the game"),
+    ("menu.instruction_log", "Instruction log"),
+    ("menu.off", "off"),
+    ("menu.on", "on"),
+    ("menu.quit", "Quit"),
+    ("menu.restart", "Restart"),
+    ("menu.restore", "Restore"),
+    ("menu.save", "Save"),
+    ("menu.sound", "Sound"),
+    ("menu.statistics", "Session statistics"),
+    ("menu.transcript", "Transcript"),
+    ("menu.volume_down", "Volume down"),
+    ("menu.volume_up", "Volume up"),
+    ("prompt.command_file", "Command file name: "),
+    (
+        "prompt.error_continue",
+        "Press '{key}' to continue or any other key to exit",
+    ),
+    ("prompt.more", "[MORE]"),
+    ("prompt.press_key", "Press any key to exit"),
+    ("prompt.restore", "Restore from: "),
+    ("prompt.save", "Save to: "),
+    ("prompt.transcript", "Transcript file name: "),
+    ("status.instruction_log_off", "Instruction log off"),
+    ("status.instruction_log_on", "Instruction log on"),
+    ("status.saved", "Saved"),
+    ("status.session_stats", "Turns: {turns}, play time: {time}"),
+    ("status.sound_off", "Sound off"),
+    ("status.sound_on", "Sound on"),
+    ("status.transcript_off", "Transcript off"),
+    ("status.transcript_on", "Transcript on"),
+    ("status.volume", "Volume factor {volume}"),
+    ("transcript.disk_full", "disk full"),
+    ("transcript.stopped", "[Transcript stopped: {reason}]"),
+    ("warning.prefix", "Warning"),
+];
+
+/// The text of messages printed by the interpreter, looked up by a stable key such as
+/// `prompt.save`.  English text is built in and can be replaced, a message at a time,
+/// from a `messages.yml` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Messages {
+    text: HashMap<&'static str, String>,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Messages {
+            text: DEFAULTS
+                .iter()
+                .map(|(key, text)| (*key, text.to_string()))
+                .collect(),
+        }
+    }
+}
+
+// Flatten nested mappings into dotted keys, so `prompt: { save: ... }` and
+// `prompt.save: ...` say the same thing
+fn flatten(
+    prefix: &str,
+    mapping: &Mapping,
+    entries: &mut Vec<(String, String)>,
+) -> Result<(), RuntimeError> {
+    for (k, v) in mapping {
+        let key = match k.as_str() {
+            Some(k) if prefix.is_empty() => k.to_string(),
+            Some(k) => format!("{}.{}", prefix, k),
+            None => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "Message keys must be strings: {:?}",
+                    k
+                )
+            }
+        };
+        match v {
+            Value::String(text) => entries.push((key, text.clone())),
+            Value::Mapping(m) => flatten(&key, m, entries)?,
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "Message '{}' must be a string",
+                    key
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+impl TryFrom<File> for Messages {
+    type Error = RuntimeError;
+
+    fn try_from(value: File) -> Result<Self, Self::Error> {
+        match serde_yaml::from_reader::<File, Value>(value) {
+            Ok(data) => Messages::from_value(&data),
+            Err(e) => recoverable_error!(ErrorCode::ConfigError, "{}", e),
+        }
+    }
+}
+
+impl Messages {
+    fn from_value(data: &Value) -> Result<Messages, RuntimeError> {
+        let mut messages = Messages::default();
+        let mut entries = Vec::new();
+        match data {
+            Value::Mapping(m) => flatten("", m, &mut entries)?,
+            // An empty file
+            Value::Null => {}
+            _ => {
+                return recoverable_error!(
+                    ErrorCode::ConfigError,
+                    "messages.yml must map message keys to text"
+                )
+            }
+        }
+        for (key, text) in entries {
+            match messages.text.get_mut(key.as_str()) {
+                Some(t) => *t = text,
+                None => warn!(target: "app::trace", "Ignoring unknown message key '{}'", key),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The text for `key`.  An unknown key is returned as it is, so a misspelt key shows up
+    /// on screen rather than disappearing.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.text.get(key) {
+            Some(text) => text,
+            None => key,
+        }
+    }
+
+    /// The text for `key` with each `{name}` replaced by its value from `args`
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut text = self.get(key).to_string();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+
+    /// An error message: `error.prefix`, what was being done, then the error itself
+    pub fn error(&self, key: &str, error: &dyn std::fmt::Display) -> String {
+        format!("{} {}: {}", self.get("error.prefix"), self.get(key), error)
+    }
+
+    /// A warning message: `warning.prefix` followed by `message`
+    pub fn warning(&self, message: &str) -> String {
+        format!("{}: {}", self.get("warning.prefix"), message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::assert_ok;
+
+    use super::*;
+
+    fn load(yaml: &str) -> Result<Messages, RuntimeError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages.yml");
+        let mut f = File::create(&path).unwrap();
+        f.write_all(yaml.as_bytes()).unwrap();
+        Messages::try_from(File::open(&path).unwrap())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let messages = Messages::default();
+        assert_eq!(messages.get("prompt.save"), "Save to: ");
+        assert_eq!(messages.get("prompt.more"), "[MORE]");
+        assert_eq!(messages.get("no.such.key"), "no.such.key");
+        // Keys are unique
+        assert_eq!(messages.text.len(), DEFAULTS.len());
+    }
+
+    #[test]
+    fn test_format() {
+        let messages = Messages::default();
+        assert_eq!(
+            messages.format("transcript.stopped", &[("reason", "disk full")]),
+            "[Transcript stopped: disk full]"
+        );
+        assert_eq!(
+            messages.error("error.restore", &"bad file"),
+            "Error restoring: bad file"
+        );
+        assert_eq!(messages.warning("careful"), "Warning: careful");
+    }
+
+    #[test]
+    fn test_load() {
+        let messages = assert_ok!(load(
+            "prompt:\n  save: \"Sauvegarder sous : \"\nerror.prefix: Erreur\nunknown.key: ignored\n"
+        ));
+        assert_eq!(messages.get("prompt.save"), "Sauvegarder sous : ");
+        assert_eq!(messages.get("error.prefix"), "Erreur");
+        // Anything not in the file stays in English
+        assert_eq!(messages.get("prompt.restore"), "Restore from: ");
+        assert_eq!(messages.get("unknown.key"), "unknown.key");
+    }
+
+    #[test]
+    fn test_load_empty() {
+        assert_eq!(assert_ok!(load("")), Messages::default());
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        assert!(load("prompt:\n  save: [1, 2]\n").is_err());
+        assert!(load("- prompt.save\n").is_err());
+        assert!(load("prompt: [\n").is_err());
+    }
+}
@@ -1,40 +1,79 @@
+mod error_policy;
+pub mod event;
+pub mod host;
+pub mod input;
 pub mod io;
+pub mod messages;
 mod rng;
 pub mod state;
+pub mod stats;
+pub mod verify;
 
-use std::collections::HashSet;
-use std::fs;
 use std::fs::File;
-use std::io::Read;
-use std::io::Write;
 use std::path::Path;
-use std::thread;
+use std::path::PathBuf;
 use std::time::Duration;
-use std::time::SystemTime;
-use std::time::UNIX_EPOCH;
 
+use crate::blorb::PictureIndex;
 use crate::config::Config;
 use crate::error::*;
-use crate::files;
+use crate::fatal_error;
+use crate::files::{self, OpenMode};
 use crate::instruction::decoder;
+use crate::instruction::log_filter::{InstructionLog, InstructionLogFilter};
 use crate::instruction::processor;
+use crate::instruction::trace::{LogSink, TraceSink};
+use crate::instruction::Instruction;
+use crate::instruction::OperandType;
 use crate::instruction::StoreResult;
+use crate::mdc;
 use crate::object::property;
+use crate::quetzal::{IntD, INTERPRETER_ID};
 use crate::recoverable_error;
 use crate::sound::Manager;
 use crate::text;
-use crate::zmachine::io::screen::Interrupt;
-use regex::Regex;
+use crate::zmachine::io::screen::{Capabilities, Interrupt, Terminal};
+use error_policy::{ErrorDisposition, ErrorPolicy};
+use event::{EventListener, GameEvent};
+use host::{Clock, FileSaves, SaveProvider, Sleeper, SystemClock, ThreadSleeper};
+use input::{Advance, LineInput, PendingInput, PendingKind};
+use messages::Messages;
 use rng::chacha_rng::ChaChaRng;
 use rng::ZRng;
 
 use self::io::screen::InputEvent;
 use self::io::IO;
+use self::state::frame::{format_backtrace, FrameInfo};
 use self::state::header;
 use self::state::header::Flags1v3;
 use self::state::header::HeaderField;
 use self::state::memory::Memory;
 use self::state::State;
+use self::stats::SessionStats;
+
+// Line editing keys handled by read_line and never passed to the game
+const KEY_ERASE_LINE: u16 = 0x15; // Ctrl-U
+const KEY_ERASE_WORD: u16 = 0x17; // Ctrl-W
+
+// Each press of a volume key scales the volume factor by this much
+const VOLUME_STEP: f32 = 1.25;
+// The volume keys keep the volume factor in this range, from twice full volume to nearly
+// silent
+const VOLUME_FACTOR_MIN: f32 = 4.0;
+const VOLUME_FACTOR_MAX: f32 = 1024.0;
+
+// A save made from the interpreter menu has this after the session statistics in its IntD
+// chunk: the saved PC is the READ the menu was opened from rather than a SAVE instruction
+const INTD_AT_READ: u8 = 1;
+
+// Instructions executed by each call to advance() that doesn't stop for input or QUIT
+const ADVANCE_INSTRUCTIONS: usize = 10000;
+
+// True if the game reads `key` itself because it terminates line input.  Terminator 255
+// means "any function key".
+fn is_terminator(key: u16, terminators: &[u16]) -> bool {
+    terminators.contains(&key) || (terminators.contains(&255) && (129..155).contains(&key))
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ErrorHandling {
@@ -44,6 +83,37 @@ pub enum ErrorHandling {
     Abort,
 }
 
+impl ErrorHandling {
+    /// Parse the configuration name of an error handling mode, e.g. `continue_warn_once`
+    pub fn from_name(name: &str) -> Option<ErrorHandling> {
+        match name {
+            "continue_warn_always" => Some(ErrorHandling::ContinueWarnAlways),
+            "continue_warn_once" => Some(ErrorHandling::ContinueWarnOnce),
+            "ignore" => Some(ErrorHandling::Ignore),
+            "abort" => Some(ErrorHandling::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// Where execution carries on after a save has been restored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restored {
+    /// After the SAVE instruction whose store or branch data is at the address
+    Save(usize),
+    /// At the READ the save was made from with the interpreter menu, which reads again
+    Read(usize),
+}
+
+// How a fixed random seed from the configuration is applied to the RNG
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedMode {
+    // Cycle 1..seed, as with a negative RANDOM argument (§2.4.3)
+    Predictable,
+    // Seed the random number generator
+    Seeded,
+}
+
 #[derive(Debug)]
 pub struct ZMachine {
     name: String,
@@ -52,10 +122,81 @@ pub struct ZMachine {
     io: IO,
     rng: Box<dyn ZRng>,
     input_interrupt: Option<u16>,
-    input_interrupt_print: bool,
     sound_manager: Option<Manager>,
-    errors: HashSet<ErrorCode>,
-    error_handling: ErrorHandling,
+    error_policy: ErrorPolicy,
+    echo_scripted_input: bool,
+    // When false, input is not echoed to the screen or transcript.  This is for games that
+    // re-print the player's command themselves; the game's own output puts the command
+    // in the transcript exactly once.
+    echo_input: bool,
+    // Drop pasted text that doesn't fit in the READ buffer, up to the next newline
+    discard_paste_overflow: bool,
+    // Log the tokens found by lexical analysis
+    trace_parse: bool,
+    // Keys that turn the sound volume down and up during line input
+    volume_keys: (Option<u16>, Option<u16>),
+    // Key that opens the interpreter menu during line input
+    menu_key: Option<u16>,
+    // Where the READ carries on after the interpreter menu restarted, restored, or quit the
+    // game, instead of storing the input.  0 quits.
+    menu_jump: Option<usize>,
+    // Answer to the next file name prompt, see answer_filename
+    filename_answer: Option<String>,
+    // Set from the interpreter menu to stop playing sounds
+    sound_muted: bool,
+    // Print a description of each sound effect as it starts
+    describe_sounds: bool,
+    // Number of consecutive executions of the same instruction, without any input, output,
+    // or memory/variable writes, before execution is halted as an infinite loop.  0 disables
+    // the check.
+    loop_threshold: u32,
+    // Set when an instruction reads input, prints output, or writes memory or variables
+    progress: bool,
+    // Fixed random seed applied at start and on restart, instead of seeding from entropy
+    predictable_seed: Option<u16>,
+    seed_mode: SeedMode,
+    // Completed READ instructions and play time, including any carried over from a restored save
+    turns: u32,
+    play_time: Duration,
+    // Clock time the current session of play started, see Clock::now
+    session_start: Duration,
+    // Draw the debug HUD before each READ
+    debug_hud: bool,
+    // Template for the default save file name, see files::expand_save_name
+    save_name_template: Option<String>,
+    // Recent files list recording the last save file used, see files::remember_file
+    recent_files: Option<String>,
+    // Skip the check that stops a save from overwriting a story or Blorb file
+    allow_any_save_name: bool,
+    // Transcript file given by start_transcript, used instead of prompting for a name
+    transcript_path: Option<PathBuf>,
+    // Instructions executed by advance(), shown on the debug HUD
+    instruction_count: usize,
+    // Which instructions are traced, and whether the one executing is
+    instruction_log: InstructionLog,
+    trace_instruction: bool,
+    // Receives traced instructions, the instruction log unless set_trace_sink replaced it
+    trace_sink: Box<dyn TraceSink>,
+    // Consecutive executions of the same instruction without progress, see loop_threshold
+    repeats: u32,
+    // Set while advance() is executing instructions, so READ and READ_CHAR wait for input
+    // events instead of blocking
+    yield_for_input: bool,
+    // Input the current instruction is waiting for
+    pending_input: Option<PendingInput>,
+    // Receives play-session events, see set_event_listener
+    event_listener: Option<EventListener>,
+    // Sizes of the pictures in the Blorb file, see set_pictures
+    pictures: PictureIndex,
+    // Score last reported by a ScoreChanged event, None until the first READ after starting,
+    // restoring, or restarting
+    last_score: Option<i16>,
+    // Where saves and command files are kept, the file system unless set_save_provider
+    // replaced it
+    saves: Box<dyn SaveProvider>,
+    clock: Box<dyn Clock>,
+    // Waits between polls of the keyboard
+    sleeper: Box<dyn Sleeper>,
 }
 
 impl ZMachine {
@@ -64,6 +205,28 @@ impl ZMachine {
         config: Config,
         sound_manager: Option<Manager>,
         name: &str,
+    ) -> Result<ZMachine, RuntimeError> {
+        ZMachine::build(memory, config, sound_manager, name, None)
+    }
+
+    /// A Z-Machine that draws on `terminal` instead of the curses terminal, so a frontend
+    /// can be plugged in without changing the screen model
+    pub fn with_terminal(
+        memory: Memory,
+        config: Config,
+        sound_manager: Option<Manager>,
+        name: &str,
+        terminal: Box<dyn Terminal>,
+    ) -> Result<ZMachine, RuntimeError> {
+        ZMachine::build(memory, config, sound_manager, name, Some(terminal))
+    }
+
+    fn build(
+        memory: Memory,
+        config: Config,
+        sound_manager: Option<Manager>,
+        name: &str,
+        terminal: Option<Box<dyn Terminal>>,
     ) -> Result<ZMachine, RuntimeError> {
         let version = memory.read_byte(HeaderField::Version as usize)?;
 
@@ -76,46 +239,149 @@ impl ZMachine {
 
         let rng = ChaChaRng::new();
 
-        let error_handling = config.error_handling();
-        let io = IO::new(version, config)?;
-
+        let error_policy =
+            ErrorPolicy::new(config.error_handling(), config.error_overrides().clone());
+        let echo_scripted_input = config.echo_scripted_input();
+        let echo_input = config.echo_input();
+        let discard_paste_overflow = config.discard_paste_overflow();
+        let trace_parse = config.trace_parse();
+        let instruction_log = InstructionLog::new(config.instruction_log_filter(), version);
+        let volume_keys = config.volume_keys();
+        let menu_key = config.menu_key();
+        let describe_sounds = config.describe_sounds();
+        let loop_threshold = config.loop_threshold();
+        let predictable_seed = config.predictable_seed();
+        let seed_mode = config.seed_mode();
+        let debug_hud = config.debug_hud();
+        let save_name_template = config.save_name_template().map(String::from);
+        let allow_any_save_name = config.allow_any_save_name();
+        let recent_files = match (config.remember_files(), config.config_dir()) {
+            (true, Some(dir)) => Some(format!("{}/recent.yml", dir)),
+            _ => None,
+        };
         let mut state = State::new(memory)?;
+        state.set_trace_calls(config.trace_calls(), config.trace_routines());
+        state.set_standard_version(config.standard_version());
+        state.set_max_string_words(config.max_string_words());
+        state.set_undo_limits(config.undo_depth(), config.undo_budget_bytes());
+
+        let io = match terminal {
+            Some(terminal) => IO::with_terminal(version, config, terminal)?,
+            None => IO::new(version, config)?,
+        };
 
-        let colors = io.default_colors();
-        state.initialize(
-            io.rows() as u8,
-            io.columns() as u8,
-            (colors.0 as u8, colors.1 as u8),
-            sounds,
-        )?;
-        Ok(ZMachine {
+        let mut capabilities = io.capabilities();
+        capabilities.sound &= sounds;
+        state.initialize(&capabilities)?;
+        let mut zmachine = ZMachine {
             name: name.to_string(),
             version,
             state,
             io,
             rng: Box::new(rng),
             input_interrupt: None,
-            input_interrupt_print: false,
             sound_manager,
-            errors: HashSet::new(),
-            error_handling,
-        })
+            error_policy,
+            echo_scripted_input,
+            echo_input,
+            discard_paste_overflow,
+            trace_parse,
+            volume_keys,
+            menu_key,
+            menu_jump: None,
+            filename_answer: None,
+            sound_muted: false,
+            describe_sounds,
+            loop_threshold,
+            progress: false,
+            predictable_seed,
+            seed_mode,
+            turns: 0,
+            play_time: Duration::ZERO,
+            session_start: SystemClock.now(),
+            debug_hud,
+            save_name_template,
+            recent_files,
+            allow_any_save_name,
+            transcript_path: None,
+            instruction_count: 0,
+            instruction_log,
+            trace_instruction: false,
+            trace_sink: Box::new(LogSink),
+            repeats: 0,
+            yield_for_input: false,
+            pending_input: None,
+            event_listener: None,
+            pictures: PictureIndex::default(),
+            last_score: None,
+            saves: Box::new(FileSaves),
+            clock: Box::new(SystemClock),
+            sleeper: Box::new(ThreadSleeper),
+        };
+        zmachine.seed_rng();
+        zmachine.sync_fixed_pitch_bit()?;
+        zmachine.sync_transcript_bit()?;
+        zmachine.check_file_size()?;
+        if let Err(e) = zmachine.io.check_size() {
+            // There's no room to show the error, so give the terminal back first
+            zmachine.io.quit();
+            return Err(e);
+        }
+        Ok(zmachine)
+    }
+
+    // Warn about a story too large for its version, which may mean it was miscompiled or
+    // corrupted, and let the error handling decide whether to run it anyway
+    fn check_file_size(&mut self) -> Result<(), RuntimeError> {
+        if let Err(e) = self.state.check_file_size() {
+            let message = self.messages().warning(e.message());
+            self.io.show_message(&message);
+            match self.error_policy.handle(&e) {
+                ErrorDisposition::Abort => return Err(e),
+                ErrorDisposition::Continue => {}
+                ErrorDisposition::ContinueWithPrompt => {
+                    if !self.io.error(
+                        &self.message("error.load_story"),
+                        e.message(),
+                        e.is_recoverable(),
+                    ) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Apply the configured seed, if any, to the RNG
+    fn seed_rng(&mut self) {
+        match (self.predictable_seed, self.seed_mode) {
+            (Some(seed), SeedMode::Predictable) => self.rng.predictable(seed),
+            (Some(seed), SeedMode::Seeded) => self.rng.seed(seed),
+            (None, _) => self.rng.seed(0),
+        }
     }
 
     pub fn version(&self) -> u8 {
         self.version
     }
 
+    /// Z-Machine Standard revision advertised to the game, as (major, minor)
+    pub fn standard_version(&self) -> (u8, u8) {
+        self.state.standard_version()
+    }
+
     pub fn input_interrupt_print(&self) -> bool {
-        self.input_interrupt_print
+        self.state.input_interrupt_print()
     }
 
     pub fn clear_input_interrupt_print(&mut self) {
-        self.input_interrupt_print = false
+        self.state.clear_input_interrupt_print()
     }
 
     pub fn set_input_interrupt_print(&mut self) {
-        self.input_interrupt_print = true
+        self.state.set_input_interrupt_print()
     }
 
     // Runtime state
@@ -130,25 +396,81 @@ impl ZMachine {
     fn update_transcript_bit(&mut self, old: u16, new: u16) -> Result<(), RuntimeError> {
         if old & 0x1 != new & 0x1 {
             if new & 0x1 == 0x1 {
-                if !self.io.is_stream_2_open() {
-                    if let Err(e) = self.start_stream_2() {
-                        self.print_str(format!("Error starting stream 2: {}\r", e))?;
-                        Err(e)
-                    } else {
-                        self.io.enable_output_stream(2, None)
-                    }
+                if let Err(e) = self.enable_stream_2() {
+                    self.print_error("error.start_stream_2", &e)?;
+                    Err(e)
                 } else {
-                    self.io.enable_output_stream(2, None)
+                    Ok(())
                 }
             } else {
-                self.io.disable_output_stream(&mut self.state, 2)
+                self.disable_stream_2()
             }
         } else {
             Ok(())
         }
     }
 
+    // Flags 2 bit 1 asks for fixed-pitch text, and may be changed by the game at any time
+    fn update_fixed_pitch_bit(&mut self, flags: u8) {
+        self.io.set_fixed_pitch(flags & 0x2 == 0x2);
+    }
+
+    // Bring the IO layer in line with Flags 2 after the header has been replaced
+    fn sync_fixed_pitch_bit(&mut self) -> Result<(), RuntimeError> {
+        let flags = self.state.read_byte(0x11)?;
+        self.update_fixed_pitch_bit(flags);
+        Ok(())
+    }
+
+    // Open or close stream 2 to match the Flags 2 transcript bit after the header has been
+    // replaced.  If the transcript can't be started, the bit is cleared so the game knows.
+    fn sync_transcript_bit(&mut self) -> Result<(), RuntimeError> {
+        let flags = self.state.read_word(0x10)?;
+        let enabled = self.io.is_stream_enabled(2);
+        if flags & 0x1 == 0x1 && !enabled {
+            if let Err(e) = self.enable_stream_2() {
+                error!(target: "app::stream", "Error starting stream 2: {}", e);
+                self.state.write_word(0x10, flags & 0xFFFE)?;
+                self.print_error("error.start_stream_2", &e)?;
+            }
+        } else if flags & 0x1 == 0 && enabled {
+            self.disable_stream_2()?;
+        }
+        Ok(())
+    }
+
+    // Reset the IO bookkeeping and header bits after the game state has been replaced by a
+    // restore or restart
+    fn reconcile_io(&mut self) -> Result<(), RuntimeError> {
+        let io = self.io.snapshot();
+        debug!(target: "app::state", "Reconciling IO: streams {:04b}, {} stream 3 table(s), buffered {}, split {}",
+            io.output_streams, io.stream_3_tables.len(), io.buffered, io.split);
+        self.io.reconcile_after_restore(&self.state)?;
+        // Stream 2 is disabled if the restored transcript bit is clear; like the game turning
+        // it off, that closes a transcript started with start_transcript
+        if !self.io.is_stream_enabled(2) && self.transcript_path.is_some() {
+            self.io.close_stream_2();
+        }
+        self.sync_fixed_pitch_bit()?;
+        self.sync_transcript_bit()
+    }
+
+    // Clear the Flags 2 transcript bit after the transcript was closed because it couldn't
+    // be written, so the game knows it has stopped
+    fn check_transcript_stopped(&mut self) -> Result<(), RuntimeError> {
+        if self.io.take_transcript_stopped() {
+            self.transcript_path = None;
+            let flags = self.state.read_word(0x10)?;
+            self.state.write_word(0x10, flags & 0xFFFE)?;
+        }
+        Ok(())
+    }
+
     pub fn write_byte(&mut self, address: usize, value: u8) -> Result<(), RuntimeError> {
+        self.progress = true;
+        if address == 0x11 {
+            self.update_fixed_pitch_bit(value);
+        }
         // Check if the transcript bit is being changed in Flags 2
         if address == 0x11
             && self
@@ -164,6 +486,12 @@ impl ZMachine {
     }
 
     pub fn write_word(&mut self, address: usize, value: u16) -> Result<(), RuntimeError> {
+        self.progress = true;
+        if address == 0x10 {
+            self.update_fixed_pitch_bit(value as u8);
+        } else if address == 0x11 {
+            self.update_fixed_pitch_bit((value >> 8) as u8);
+        }
         // Check if the transcript bit is being set in Flags 2 when writing to 0x10 or 0x11
         if address == 0x10
             && self
@@ -186,6 +514,46 @@ impl ZMachine {
         self.state.write_word(address, value)
     }
 
+    // Host access to memory, for save editors, cheats, and other tools.  Changes are seen by
+    // the game from its next instruction.
+
+    // The interpreter keeps the header in step with the screen, streams, and sound, so the
+    // host may not write to it at all
+    fn check_poke(&self, address: usize) -> Result<(), RuntimeError> {
+        if address < 0x40 {
+            recoverable_error!(
+                ErrorCode::IllegalMemoryAccess,
+                "Host write to header address ${:02x}",
+                address
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write a byte of dynamic memory outside the header
+    pub fn poke_byte(&mut self, address: usize, value: u8) -> Result<(), RuntimeError> {
+        self.check_poke(address)?;
+        self.state.write_byte(address, value)
+    }
+
+    /// Write a word of dynamic memory outside the header
+    pub fn poke_word(&mut self, address: usize, value: u16) -> Result<(), RuntimeError> {
+        self.check_poke(address)?;
+        self.state.write_word(address, value)
+    }
+
+    /// The value of a global variable, where G00 is 0
+    pub fn global(&self, global: u8) -> Result<u16, RuntimeError> {
+        self.state.global(global)
+    }
+
+    /// Set a global variable, where G00 is 0.  This works between instructions, whether or
+    /// not a routine is running.
+    pub fn set_global(&mut self, global: u8, value: u16) -> Result<(), RuntimeError> {
+        self.state.set_global(global, value)
+    }
+
     pub fn variable(&mut self, variable: u8) -> Result<u16, RuntimeError> {
         self.state.variable(variable)
     }
@@ -195,14 +563,17 @@ impl ZMachine {
     }
 
     pub fn set_variable(&mut self, variable: u8, value: u16) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.state.set_variable(variable, value)
     }
 
     pub fn set_variable_indirect(&mut self, variable: u8, value: u16) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.state.set_variable_indirect(variable, value)
     }
 
     pub fn push(&mut self, value: u16) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.state.push(value)
     }
 
@@ -222,7 +593,7 @@ impl ZMachine {
         self.state.packed_string_address(address)
     }
 
-    pub fn instruction(&self, address: usize) -> Vec<u8> {
+    pub fn instruction(&self, address: usize) -> Result<Vec<u8>, RuntimeError> {
         self.state.instruction(address)
     }
 
@@ -230,18 +601,132 @@ impl ZMachine {
         self.state.frame_count()
     }
 
+    pub fn trace_parse(&self) -> bool {
+        self.trace_parse
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.state.memory().size()
+    }
+
     pub fn checksum(&self) -> Result<u16, RuntimeError> {
         self.state.checksum()
     }
 
+    pub fn session_stats(&self) -> SessionStats {
+        let elapsed = self.clock.now().saturating_sub(self.session_start);
+        SessionStats::new(self.turns, self.play_time + elapsed)
+    }
+
+    /// Refresh the debug HUD, if it is enabled, with the instruction count, turn count,
+    /// PC, call depth, and memory used by undo snapshots
+    pub fn debug_hud(&mut self) -> Result<(), RuntimeError> {
+        if self.debug_hud {
+            let text = format!(
+                "Instructions: {}  Turns: {}  PC: ${:06x}  Depth: {}  Undo: {}K",
+                self.instruction_count,
+                self.turns,
+                self.state.pc()?,
+                self.state.frame_count(),
+                self.state.undo_memory_usage().div_ceil(1024)
+            );
+            self.io.debug_hud(&text)?;
+        }
+        Ok(())
+    }
+
+    /// Count a completed READ
+    pub fn add_turn(&mut self) {
+        self.turns = self.turns.saturating_add(1);
+        self.emit(GameEvent::TurnCompleted { n: self.turns });
+    }
+
+    /// Set the listener that receives [`GameEvent`]s during play, replacing any set before.
+    ///
+    /// The listener runs while the interpreter is mutably borrowed and must not call back into
+    /// it; see [`EventListener`].
+    pub fn set_event_listener(&mut self, listener: Box<dyn FnMut(GameEvent)>) {
+        self.event_listener = Some(EventListener::new(listener));
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        if let Some(listener) = self.event_listener.as_mut() {
+            listener.notify(event);
+        }
+    }
+
+    /// Report a change in the score of a V1-3 score game since the last READ.  Called as each
+    /// READ starts, after the game has finished its turn.
+    pub fn check_score(&mut self) -> Result<(), RuntimeError> {
+        if self.version > 3 || header::flag1(&self.state, Flags1v3::StatusLineType as u8)? != 0 {
+            return Ok(());
+        }
+
+        let score = self.state.variable(17)? as i16;
+        match self.last_score.replace(score) {
+            Some(old) if old != score => self.emit(GameEvent::ScoreChanged { old, new: score }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Continue the statistics from a restored save, or start over if the save didn't have any
+    fn restore_stats(&mut self, intd: Option<IntD>) {
+        let stats = match intd.as_ref().map(SessionStats::try_from) {
+            Some(Ok(stats)) => stats,
+            _ => SessionStats::default(),
+        };
+        debug!(target: "app::state", "Restored session statistics: {}", stats);
+        self.turns = stats.turns();
+        self.play_time = stats.play_time();
+        self.session_start = self.clock.now();
+    }
+
     pub fn save(&mut self, pc: usize) -> Result<(), RuntimeError> {
-        let save_data = self.state.save(pc)?;
-        self.prompt_and_write("Save to: ", "ifzs", &save_data, false)
+        self.write_save(pc, false)
+    }
+
+    // Save the game to resume at `pc`, which is a READ when `at_read` is true
+    fn write_save(&mut self, pc: usize, at_read: bool) -> Result<(), RuntimeError> {
+        self.check_not_in_interrupt("SAVE")?;
+        let mut intd = IntD::from(&self.session_stats());
+        if at_read {
+            let mut data = intd.data().clone();
+            data.push(INTD_AT_READ);
+            intd = IntD::new(intd.interpreter_id(), data);
+        }
+        let save_data = self.state.save(pc, Some(intd))?;
+        let prompt = self.message("prompt.save");
+        let path = self.prompt_and_write(&prompt, "ifzs", &save_data, false)?;
+        self.emit(GameEvent::Saved { path });
+        Ok(())
     }
 
-    pub fn restore(&mut self) -> Result<Option<usize>, RuntimeError> {
-        match self.prompt_and_read("Restore from: ", "ifzs") {
-            Ok(save_data) => self.state.restore(save_data),
+    pub fn restore(&mut self) -> Result<Option<Restored>, RuntimeError> {
+        self.check_not_in_interrupt("RESTORE")?;
+        let prompt = self.message("prompt.restore");
+        match self.prompt_and_read_file(&prompt, "ifzs") {
+            Ok((path, save_data)) => {
+                let (pc, intd) = self.state.restore(save_data)?;
+                // The session statistics are 8 bytes
+                let at_read = intd.as_ref().is_some_and(|intd| {
+                    intd.interpreter_id() == INTERPRETER_ID
+                        && intd.data().get(8) == Some(&INTD_AT_READ)
+                });
+                let pc = pc.map(|pc| {
+                    if at_read {
+                        Restored::Read(pc)
+                    } else {
+                        Restored::Save(pc)
+                    }
+                });
+                self.restore_stats(intd);
+                self.reconcile_io()?;
+                // The restored score isn't a change made by playing
+                self.last_score = None;
+                self.emit(GameEvent::Restored { path });
+                Ok(pc)
+            }
             Err(e) => {
                 error!(target: "app::state", "Error restoring state: {}", e);
                 Err(e)
@@ -249,28 +734,57 @@ impl ZMachine {
         }
     }
 
+    // The whole-game SAVE and RESTORE aren't allowed in an interrupt routine: a save made
+    // there would resume inside a routine nothing is waiting to return from
+    fn check_not_in_interrupt(&self, opcode: &str) -> Result<(), RuntimeError> {
+        if self.state.in_interrupt() {
+            error!(target: "app::state", "{} in an interrupt routine", opcode);
+            recoverable_error!(
+                ErrorCode::SaveInInterrupt,
+                "{} is not allowed in an interrupt routine",
+                opcode
+            )
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn save_undo(&mut self, address: usize) -> Result<(), RuntimeError> {
         self.state.save_undo(address)
     }
 
     pub fn restore_undo(&mut self) -> Result<Option<usize>, RuntimeError> {
-        self.state.restore_undo()
+        let pc = self.state.restore_undo()?;
+        self.reconcile_io()?;
+        Ok(pc)
     }
 
     pub fn restart(&mut self) -> Result<usize, RuntimeError> {
-        self.rng.seed(0);
-        self.state.restart()
+        self.seed_rng();
+        let pc = self.state.restart()?;
+        self.reconcile_io()?;
+        self.last_score = None;
+        self.emit(GameEvent::Restarted);
+        Ok(pc)
     }
 
     pub fn call_routine(
         &mut self,
         address: usize,
-        arguments: &Vec<u16>,
+        arguments: &[u16],
         result: Option<StoreResult>,
         return_address: usize,
     ) -> Result<usize, RuntimeError> {
-        self.state
-            .call_routine(address, arguments, result, return_address)
+        let pc = self
+            .state
+            .call_routine(address, arguments, result, return_address)?;
+        // A call to address 0 stores FALSE without calling anything
+        if address == 0 {
+            if let Some(r) = result {
+                self.trace_store(&r, 0);
+            }
+        }
+        Ok(pc)
     }
 
     pub fn call_read_interrupt(
@@ -310,11 +824,21 @@ impl ZMachine {
     }
 
     pub fn return_routine(&mut self, value: u16) -> Result<usize, RuntimeError> {
-        self.state.return_routine(value)
+        let store = self.state.routine_result(self.state.frame_count());
+        let pc = self.state.return_routine(value)?;
+        if let Some(r) = store {
+            self.trace_store(&r, value);
+        }
+        Ok(pc)
     }
 
     pub fn throw(&mut self, depth: u16, result: u16) -> Result<usize, RuntimeError> {
-        self.state.throw(depth, result)
+        let store = self.state.routine_result(depth as usize);
+        let pc = self.state.throw(depth, result)?;
+        if let Some(r) = store {
+            self.trace_store(&r, result);
+        }
+        Ok(pc)
     }
 
     pub fn argument_count(&self) -> Result<u8, RuntimeError> {
@@ -343,6 +867,13 @@ impl ZMachine {
         self.rng.predictable(seed)
     }
 
+    /// Replace the configured seed, as though it had been given with `--predictable`, and
+    /// reseed the RNG in the configured seed mode.  The seed also applies after a restart.
+    pub fn reseed(&mut self, seed: u16) {
+        self.predictable_seed = Some(seed);
+        self.seed_rng();
+    }
+
     // Screen I/O
     pub fn rows(&self) -> u16 {
         self.io.rows() as u16
@@ -352,31 +883,107 @@ impl ZMachine {
         self.io.columns() as u16
     }
 
-    fn start_stream_2(&mut self) -> Result<(), RuntimeError> {
-        let file = self.prompt_and_create("Transcript file name: ", "txt", false)?;
+    // Open the transcript file, appending to it if it already exists.  Returns true when
+    // the file already had something in it.
+    fn start_stream_2(&mut self) -> Result<bool, RuntimeError> {
+        let file = match &self.transcript_path {
+            Some(path) => match OpenMode::Append.options().open(path) {
+                Ok(f) => f,
+                Err(e) => {
+                    return recoverable_error!(ErrorCode::FileError, "{}: {}", path.display(), e)
+                }
+            },
+            None => {
+                let prompt = self.message("prompt.transcript");
+                self.prompt_and_create(&prompt, "txt", true, OpenMode::Append)?
+            }
+        };
+        let existing = file.metadata().map(|m| m.len() > 0).unwrap_or(false);
         self.io.set_stream_2(file);
+        Ok(existing)
+    }
+
+    // Enable stream 2, opening the transcript file first if needed, and mark the start of
+    // the session in the transcript
+    fn enable_stream_2(&mut self) -> Result<(), RuntimeError> {
+        if self.io.is_stream_enabled(2) {
+            return Ok(());
+        }
+
+        let resumed = if self.io.is_stream_2_open() {
+            true
+        } else {
+            self.start_stream_2()?
+        };
+        self.io.enable_output_stream(2, None)?;
+
+        let header = format!(
+            "{}--- Transcript {} {}, {} release {} ---\r",
+            if resumed { "\r" } else { "" },
+            if resumed { "resumed" } else { "started" },
+            files::timestamp(),
+            self.name,
+            self.header_word(HeaderField::Release)?
+        );
+        self.io
+            .transcript(&header.chars().map(|c| c as u16).collect::<Vec<u16>>())
+    }
+
+    // Disable stream 2.  A transcript started with start_transcript is closed, and opened
+    // again if the game turns transcripting back on.
+    fn disable_stream_2(&mut self) -> Result<(), RuntimeError> {
+        self.io.disable_output_stream(&mut self.state, 2)?;
+        if self.transcript_path.is_some() {
+            self.io.close_stream_2();
+        }
         Ok(())
     }
 
+    /// Transcribe the session to `path`, without prompting for a file name.  An existing file
+    /// is added to.  The Flags 2 transcript bit is set, and if the game later clears and sets
+    /// it again, the same file is closed and reopened.
+    pub fn start_transcript(&mut self, path: &Path) -> Result<(), RuntimeError> {
+        debug!(target: "app::stream", "Starting transcript to {}", path.display());
+        if self.io.is_stream_enabled(2) {
+            self.io.disable_output_stream(&mut self.state, 2)?;
+        }
+        self.io.close_stream_2();
+        self.transcript_path = Some(path.to_path_buf());
+        if let Err(e) = self.enable_stream_2() {
+            self.transcript_path = None;
+            return Err(e);
+        }
+
+        let f2 = self.state.read_word(0x10)?;
+        self.state.write_word(0x10, f2 | 1)
+    }
+
+    /// Stop transcribing and close the transcript file, clearing the Flags 2 transcript bit
+    pub fn stop_transcript(&mut self) -> Result<(), RuntimeError> {
+        debug!(target: "app::stream", "Stopping transcript");
+        self.io.disable_output_stream(&mut self.state, 2)?;
+        self.io.close_stream_2();
+        self.transcript_path = None;
+        let f2 = self.state.read_word(0x10)?;
+        self.state.write_word(0x10, f2 & 0xFFFE)
+    }
+
     pub fn output_stream(&mut self, stream: i16, table: Option<usize>) -> Result<(), RuntimeError> {
         match stream {
             1..=4 => {
                 debug!(target: "app::stream", "Enabling output stream {}", stream);
                 if stream == 2 {
-                    if !self.io.is_stream_2_open() {
-                        if let Err(e) = self.start_stream_2() {
-                            error!(target: "app::stream", "Error starting stream 2: {}", e);
-                            return recoverable_error!(
-                                ErrorCode::Transcript,
-                                "Error creating transcript file: {}",
-                                e
-                            );
-                        }
+                    if let Err(e) = self.enable_stream_2() {
+                        error!(target: "app::stream", "Error starting stream 2: {}", e);
+                        return recoverable_error!(
+                            ErrorCode::Transcript,
+                            "Error creating transcript file: {}",
+                            e
+                        );
                     }
                     // Set the transcript bit
                     let f2 = self.state.read_word(0x10)?;
-                    self.state.write_word(0x10, f2 | 1)?;
-                    self.io.enable_output_stream(stream as u8, table)
+                    self.state.write_word(0x10, f2 | 1)
                 } else {
                     self.io.enable_output_stream(stream as u8, table)
                 }
@@ -387,9 +994,11 @@ impl ZMachine {
                     // Unset the transcript bit
                     let f2 = self.state.read_word(0x10)?;
                     self.state.write_word(0x10, f2 & 0xFFFE)?;
+                    self.disable_stream_2()
+                } else {
+                    self.io
+                        .disable_output_stream(&mut self.state, i16::abs(stream) as u8)
                 }
-                self.io
-                    .disable_output_stream(&mut self.state, i16::abs(stream) as u8)
             }
             _ => recoverable_error!(
                 ErrorCode::InvalidOutputStream,
@@ -400,6 +1009,7 @@ impl ZMachine {
     }
 
     pub fn print(&mut self, text: &Vec<u16>) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.io.print_vec(text)?;
 
         if self.state.is_input_interrupt() {
@@ -409,10 +1019,33 @@ impl ZMachine {
         Ok(())
     }
 
+    /// Print output held back until the end of a word
+    pub fn flush(&mut self) -> Result<(), RuntimeError> {
+        self.io.flush()
+    }
+
     pub fn print_str(&mut self, text: String) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.io.print_vec(&text.chars().map(|c| c as u16).collect())
     }
 
+    /// The text of the interpreter's own messages
+    pub fn messages(&self) -> &Messages {
+        self.io.messages()
+    }
+
+    // The text of the interpreter message `key`
+    fn message(&self, key: &str) -> String {
+        self.messages().get(key).to_string()
+    }
+
+    /// Print the interpreter's error message `key`, for something that went wrong while
+    /// carrying out an instruction, followed by the error
+    pub fn print_error(&mut self, key: &str, e: &RuntimeError) -> Result<(), RuntimeError> {
+        let message = self.messages().error(key, e);
+        self.print_str(format!("{}\r", message))
+    }
+
     pub fn split_window(&mut self, lines: u16) -> Result<(), RuntimeError> {
         self.io.split_window(lines)
     }
@@ -425,20 +1058,20 @@ impl ZMachine {
         self.io.erase_window(window)
     }
 
-    pub fn erase_line(&mut self) -> Result<(), RuntimeError> {
-        self.io.erase_line()
+    pub fn erase_line(&mut self, value: u16) -> Result<(), RuntimeError> {
+        self.io.erase_line(value)
     }
 
     pub fn status_line(&mut self) -> Result<(), RuntimeError> {
         let status_type = header::flag1(&self.state, Flags1v3::StatusLineType as u8)?;
         let object = self.state.variable(16)? as usize;
-        let mut left = text::from_vec(self, &property::short_name(self, object)?, false)?;
+        let mut left = self.short_name(object)?;
         let mut right: Vec<u16> = if status_type == 0 {
             // Score is between -99 and 999 inclusive
             let score = i16::min(999, i16::max(-99, self.state.variable(17)? as i16));
             // Turns is between 0 and 9999 inclusive
             let turns = u16::min(9999, self.state.variable(18)?);
-            format!("{:<8}", format!("{:}/{:}", score, turns))
+            format!("{:}/{:}", score, turns)
                 .as_bytes()
                 .iter()
                 .map(|x| *x as u16)
@@ -468,6 +1101,18 @@ impl ZMachine {
         self.io.status_line(&mut left, &mut right)
     }
 
+    // The decoded short name of `object`, decoded again only when it has changed
+    fn short_name(&mut self, object: usize) -> Result<Vec<u16>, RuntimeError> {
+        if let Some(name) = self.state.cached_short_name(object) {
+            return Ok(name);
+        }
+
+        let name = text::from_vec(self, &property::short_name(self, object)?, false)?;
+        let ranges = property::short_name_ranges(self, object)?;
+        self.state.cache_short_name(object, ranges, &name);
+        Ok(name)
+    }
+
     pub fn set_font(&mut self, font: u16) -> Result<u16, RuntimeError> {
         self.io.set_font(font)
     }
@@ -488,6 +1133,76 @@ impl ZMachine {
         self.io.buffer_mode(mode)
     }
 
+    // V6 windows
+    pub fn set_window_cursor(
+        &mut self,
+        window: i16,
+        row: u16,
+        column: u16,
+    ) -> Result<(), RuntimeError> {
+        self.io.set_window_cursor(window, row, column)
+    }
+
+    pub fn move_window(&mut self, window: i16, top: u16, left: u16) -> Result<(), RuntimeError> {
+        self.io.move_window(window, top, left)
+    }
+
+    pub fn window_size(
+        &mut self,
+        window: i16,
+        height: u16,
+        width: u16,
+    ) -> Result<(), RuntimeError> {
+        self.io.window_size(window, height, width)
+    }
+
+    pub fn window_style(
+        &mut self,
+        window: i16,
+        flags: u16,
+        operation: u16,
+    ) -> Result<(), RuntimeError> {
+        self.io.window_style(window, flags, operation)
+    }
+
+    pub fn window_property(&mut self, window: i16, property: u16) -> Result<u16, RuntimeError> {
+        self.io.window_property(window, property)
+    }
+
+    pub fn set_window_property(
+        &mut self,
+        window: i16,
+        property: u16,
+        value: u16,
+    ) -> Result<(), RuntimeError> {
+        self.io.set_window_property(window, property, value)
+    }
+
+    pub fn scroll_window(&mut self, window: i16, lines: i16) -> Result<(), RuntimeError> {
+        self.io.scroll_window(window, lines)
+    }
+
+    pub fn set_margins(&mut self, window: i16, left: u16, right: u16) -> Result<(), RuntimeError> {
+        self.io.set_margins(window, left, right)
+    }
+
+    pub fn erase_area(&mut self, at: (u16, u16), size: (u16, u16)) -> Result<(), RuntimeError> {
+        self.io.erase_area(at, size)
+    }
+
+    pub fn set_window_colors(
+        &mut self,
+        window: i16,
+        foreground: u16,
+        background: u16,
+    ) -> Result<(), RuntimeError> {
+        self.io.set_window_colors(window, foreground, background)
+    }
+
+    pub fn set_window_font(&mut self, window: i16, font: u16) -> Result<u16, RuntimeError> {
+        self.io.set_window_font(window, font)
+    }
+
     pub fn beep(&mut self) -> Result<(), RuntimeError> {
         self.io.beep()
     }
@@ -497,20 +1212,9 @@ impl ZMachine {
     }
 
     // Input
+    // The current time in milliseconds, plus `timeout` tenths of a second
     fn now(&self, timeout: Option<u16>) -> u128 {
-        match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(t) => {
-                if let Some(d) = timeout {
-                    t.as_millis() + d as u128
-                } else {
-                    t.as_millis()
-                }
-            }
-            Err(e) => {
-                error!(target: "app::state", "Error getting current system time: {}", e);
-                0
-            }
-        }
+        self.clock.now().as_millis() + timeout.map_or(0, |t| t as u128 * 100)
     }
 
     fn mouse_data(&mut self, event: &InputEvent) -> Result<(), RuntimeError> {
@@ -529,6 +1233,12 @@ impl ZMachine {
             }
         };
 
+        // The click is still delivered when there's nowhere to store the coordinates
+        if header::extension_size(&self.state)? < 2 {
+            debug!(target: "app::screen", "No room in the header extension table for mouse coordinates ({},{})", column, row);
+            return Ok(());
+        }
+
         debug!(target: "app::screen", "Storing mouse coordinates ({},{})", column, row);
         header::set_extension(&mut self.state, 1, column)?;
         header::set_extension(&mut self.state, 2, row)?;
@@ -536,122 +1246,473 @@ impl ZMachine {
         Ok(())
     }
 
-    pub fn read_key(&mut self, timeout: u16) -> Result<InputEvent, RuntimeError> {
-        let end = if timeout > 0 {
-            self.now(Some(timeout))
-        } else {
-            0
-        };
+    /// Use the pictures in `pictures`, from the Pict resources of the game's Blorb file
+    pub fn set_pictures(&mut self, pictures: PictureIndex) {
+        info!(target: "app::resource", "{} pictures available", pictures.count());
+        self.pictures = pictures;
+    }
 
-        let check_sound = self.state.sound_interrupt().is_some();
-        loop {
-            // If a sound interrupt is set and there is no sound playing,
-            // return buffer and clear any pending input_interrupt
-            if self.state.sound_interrupt().is_some() {
-                if let Some(sounds) = self.sound_manager.as_mut() {
-                    if !sounds.is_playing() {
-                        debug!(target: "app::screen", "Read interrupted: sound interrupt firing");
-                        self.input_interrupt = None;
-                        return Ok(InputEvent::from_interrupt(Interrupt::Sound));
-                    }
-                }
-            }
+    /// The number of pictures available and the release number of the set, for
+    /// PICTURE_DATA 0.  The release number isn't read from the Blorb file, so it's 0.
+    pub fn picture_index(&self) -> (u16, u16) {
+        (self.pictures.count().min(u16::MAX as usize) as u16, 0)
+    }
 
-            let now = self.now(None);
-            if end > 0 && now > end {
-                debug!(target: "app::screen", "Read interrupted: timed out");
-                return Ok(InputEvent::from_interrupt(Interrupt::ReadTimeout));
+    /// The height and width of picture `number` in screen units, scaled to the screen by
+    /// the Blorb file's Reso chunk, or None when there is no such picture
+    pub fn picture_size(&self, number: u16) -> Option<(u16, u16)> {
+        let screen = (
+            header::field_word(&self.state, HeaderField::ScreenWidth).ok()? as u32,
+            header::field_word(&self.state, HeaderField::ScreenHeight).ok()? as u32,
+        );
+        match self.pictures.size(number as u32, screen) {
+            Some((width, height)) => Some((
+                height.min(u16::MAX as u32) as u16,
+                width.min(u16::MAX as u32) as u16,
+            )),
+            None => {
+                debug!(target: "app::screen", "No picture resource for picture {}", number);
+                None
             }
+        }
+    }
 
-            let key = self.io.read_key(end == 0 && !check_sound);
-            if let Some(c) = key.zchar() {
-                if c == 253 || c == 254 {
-                    self.mouse_data(&key)?;
-                }
+    /// The last mouse click recorded in the header extension table as (row, column), or 0,0
+    /// when the table has no room for it
+    pub fn mouse_position(&self) -> Result<(u16, u16), RuntimeError> {
+        if header::extension_size(&self.state)? < 2 {
+            return Ok((0, 0));
+        }
 
-                return Ok(key);
-            }
+        let table = header::field_word(&self.state, HeaderField::ExtensionTable)? as usize;
+        Ok((
+            self.state.read_word(table + 4)?,
+            self.state.read_word(table + 2)?,
+        ))
+    }
+
+    // A sound interrupt is pending when one has been set and no sound interrupt routine is
+    // running; interrupts don't nest, so a new one waits until the current routine returns
+    fn sound_interrupt_pending(&self) -> bool {
+        self.state.sound_interrupt().is_some() && !self.state.in_sound_interrupt()
+    }
 
-            thread::sleep(Duration::from_millis(10));
+    /// Is a pending sound interrupt ready to fire because the sound has finished?
+    pub fn sound_interrupt_due(&mut self) -> bool {
+        if self.sound_interrupt_pending() {
+            if let Some(sounds) = self.sound_manager.as_mut() {
+                debug!(target: "app::sound", "Sound playing? {}", sounds.is_playing());
+                return !sounds.is_playing();
+            }
         }
+
+        false
     }
 
-    pub fn read_line(
-        &mut self,
-        text: &[u16],
-        len: usize,
-        terminators: &[u16],
-        timeout: u16,
-    ) -> Result<Vec<u16>, RuntimeError> {
-        let mut input_buffer = text.to_vec();
+    // Input ends on a sound interrupt when no sound is playing, or when a timed read runs out
+    fn input_interrupt_event(&mut self, end: u128) -> Option<Interrupt> {
+        if self.sound_interrupt_due() {
+            debug!(target: "app::screen", "Read interrupted: sound interrupt firing");
+            return Some(Interrupt::Sound);
+        }
+
+        if end > 0 && self.now(None) >= end {
+            debug!(target: "app::screen", "Read interrupted: timed out");
+            return Some(Interrupt::ReadTimeout);
+        }
+
+        None
+    }
 
-        let end = if timeout > 0 {
+    fn input_end(&self, timeout: u16) -> u128 {
+        if timeout > 0 {
             self.now(Some(timeout))
         } else {
             0
-        };
+        }
+    }
 
-        let check_sound = self.state.sound_interrupt().is_some();
+    fn poll_key(&mut self, block: bool) -> Result<InputEvent, RuntimeError> {
+        let key = self.io.read_key(block);
+        if let Some(capabilities) = self.io.resized() {
+            self.resize(capabilities)?;
+        }
+        if self.io.interrupted() {
+            fatal_error!(ErrorCode::Interrupted, "Interrupted by user")
+        } else {
+            // Queue the rest of a burst of input, such as pasted text, to handle in one go
+            if key.zchar().is_some() {
+                self.io.read_burst();
+            }
+            Ok(key)
+        }
+    }
 
-        debug!(target: "app::screen", "Pending sound interrupt? {}", check_sound);
+    // Tell the game the terminal has changed size, and what it can do now
+    fn resize(&mut self, mut capabilities: Capabilities) -> Result<(), RuntimeError> {
+        capabilities.sound &= self
+            .sound_manager
+            .as_ref()
+            .is_some_and(|s| s.sound_count() > 0);
+        self.state.resize(&capabilities)
+    }
 
-        loop {
-            // If a sound interrupt is set and there is no sound playing,
-            // return buffer and clear any pending input_interrupt
-            if self.state.sound_interrupt().is_some() {
-                debug!(target: "app::screen", "Soundinterrupt pending");
-                if let Some(sounds) = self.sound_manager.as_mut() {
-                    debug!(target: "app::screen", "Sound playing? {}", sounds.is_playing());
-                    if !sounds.is_playing() {
-                        debug!(target: "app::screen", "Read interrupted: sound interrupt firing");
-                        self.state.clear_read_interrupt();
-                        return Ok(input_buffer);
-                    }
-                }
+    // Returns the key if it completes READ_CHAR
+    fn char_input_key(&mut self, key: InputEvent) -> Result<Option<InputEvent>, RuntimeError> {
+        if let Some(c) = key.zchar() {
+            if c == 253 || c == 254 {
+                self.mouse_data(&key)?;
             }
 
-            let now = self.now(None);
-            if end > 0 && now > end {
-                debug!(target: "app::screen", "Read interrupted: timed out");
-                return Ok(input_buffer);
+            if c != KEY_ERASE_LINE && c != KEY_ERASE_WORD {
+                return Ok(Some(key));
             }
+        }
 
-            let timeout = if end > 0 { end - now } else { 0 };
+        Ok(None)
+    }
 
-            trace!(target: "app::screen", "Now: {}, End: {}, Timeout: {}", now, end, timeout);
+    /// Read a key, blocking until one is read or the read is interrupted.  `timeout` is in
+    /// tenths of a second, or 0 for none.
+    pub fn read_key(&mut self, timeout: u16) -> Result<InputEvent, RuntimeError> {
+        self.progress = true;
+        let end = self.input_end(timeout);
+        let check_sound = self.sound_interrupt_pending();
+        loop {
+            // If a sound interrupt is set and there is no sound playing,
+            // return buffer and clear any pending input_interrupt
+            if let Some(interrupt) = self.input_interrupt_event(end) {
+                if interrupt == Interrupt::Sound {
+                    self.input_interrupt = None;
+                }
+                return Ok(InputEvent::from_interrupt(interrupt));
+            }
 
-            let e = self.io.read_key(end == 0 && !check_sound);
-            match e.zchar() {
-                Some(key) => {
-                    if terminators.contains(&key)
-                        // Terminator 255 means "any function key"
-                        || (terminators.contains(&255) && ((129..155).contains(&key) || key > 251))
-                    {
-                        if key == 254 || key == 253 {
-                            self.mouse_data(&e)?;
-                        }
+            let key = self.poll_key(end == 0 && !check_sound)?;
+            if let Some(key) = self.char_input_key(key)? {
+                return Ok(key);
+            }
 
-                        input_buffer.push(key);
-                        // Only print the terminator if it was the return key
-                        if key == 0x0d {
-                            self.io.print_vec(&vec![key])?;
-                        }
-                        break;
-                    } else if key == 0x08 {
-                        if !input_buffer.is_empty() {
-                            input_buffer.pop();
-                            self.backspace()?;
-                        }
-                    } else if input_buffer.len() < len && (0x20..0x7f).contains(&key) {
-                        input_buffer.push(key);
-                        self.io.print_vec(&vec![key])?;
+            self.sleeper.sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Read a key for READ_CHAR.
+    ///
+    /// When execution is driven by [`advance`](Self::advance), this returns `None` and the
+    /// instruction at `pc` waits for input; `advance` completes it when a key arrives.
+    /// Otherwise it blocks until a key is read or the read is interrupted.  `timeout` is in
+    /// tenths of a second, or 0 for none.
+    pub fn request_key(
+        &mut self,
+        pc: usize,
+        operands: &[u16],
+        timeout: u16,
+    ) -> Result<Option<InputEvent>, RuntimeError> {
+        if !self.yield_for_input {
+            return self.read_key(timeout).map(Some);
+        }
+
+        self.progress = true;
+        self.pending_input = Some(PendingInput {
+            pc,
+            operands: operands.to_vec(),
+            kind: PendingKind::Char,
+            end: self.input_end(timeout),
+            check_sound: self.sound_interrupt_pending(),
+        });
+        Ok(None)
+    }
+
+    // Input streams
+    pub fn input_stream(&mut self, stream: u16) -> Result<(), RuntimeError> {
+        if stream == 1 && !self.io.has_commands() {
+            let prompt = self.message("prompt.command_file");
+            let data = self.prompt_and_read(&prompt, "cmd")?;
+            let commands: Vec<String> = String::from_utf8_lossy(&data)
+                .lines()
+                .map(|l| l.to_string())
+                .collect();
+            self.push_commands(&commands);
+        }
+        self.io.set_input_stream(stream)
+    }
+
+    pub fn push_commands(&mut self, commands: &[String]) {
+        let commands: Vec<Vec<u16>> = commands
+            .iter()
+            .map(|c| {
+                c.trim_end_matches('\r')
+                    .chars()
+                    .map(|c| c as u16)
+                    .filter(|c| (0x20..0x7f).contains(c))
+                    .collect()
+            })
+            .collect();
+        debug!(target: "app::stream", "Queued {} command(s) on input stream 1", commands.len());
+        self.io.add_commands(&commands);
+    }
+
+    fn read_command(
+        &mut self,
+        text: &[u16],
+        len: usize,
+        command: &[u16],
+    ) -> Result<Vec<u16>, RuntimeError> {
+        let mut input_buffer = text.to_vec();
+        let available = len.saturating_sub(input_buffer.len());
+        let command = &command[..usize::min(available, command.len())];
+        input_buffer.extend_from_slice(command);
+        input_buffer.push(0x0d);
+        // Scripted commands are echoed exactly as typed input would be, which also
+        // copies them to the transcript
+        if self.echo_scripted_input && self.echo_input {
+            self.io.echo(command)?;
+            self.io.echo(&[0x0d])?;
+        }
+
+        Ok(input_buffer)
+    }
+
+    // Returns true if the key terminates the line
+    fn line_input_key(
+        &mut self,
+        input: &mut LineInput,
+        e: &InputEvent,
+    ) -> Result<bool, RuntimeError> {
+        let key = match e.zchar() {
+            Some(key) => key,
+            None => return Ok(false),
+        };
+
+        if self.volume_key(key, &input.terminators) {
+            return Ok(false);
+        }
+
+        if Some(key) == self.menu_key && !is_terminator(key, &input.terminators) {
+            return self.interpreter_menu();
+        }
+
+        let input_buffer = &mut input.buffer;
+        if input.terminators.contains(&key)
+            // Terminator 255 means "any function key"
+            || (input.terminators.contains(&255) && ((129..155).contains(&key) || key > 251))
+        {
+            if key == 254 || key == 253 {
+                self.mouse_data(e)?;
+            }
+
+            input_buffer.push(key);
+            // Only print the terminator if it was the return key
+            if key == 0x0d && self.echo_input {
+                self.io.echo(&[key])?;
+            }
+            return Ok(true);
+        } else if key == 0x08 {
+            if !input_buffer.is_empty() {
+                input_buffer.pop();
+                if self.echo_input {
+                    self.backspace()?;
+                }
+            }
+        } else if key == KEY_ERASE_LINE || key == KEY_ERASE_WORD {
+            // Erase the whole line, or back to the start of the previous word.  Words end at
+            // spaces and at the dictionary's separators, which are words of their own, as
+            // they are when the line is parsed.
+            let mut keep = input_buffer.len();
+            if key == KEY_ERASE_WORD {
+                while keep > 0 && input_buffer[keep - 1] == 0x20 {
+                    keep -= 1;
+                }
+                if keep > 0 && input.separators.contains(&input_buffer[keep - 1]) {
+                    keep -= 1;
+                } else {
+                    while keep > 0
+                        && input_buffer[keep - 1] != 0x20
+                        && !input.separators.contains(&input_buffer[keep - 1])
+                    {
+                        keep -= 1;
                     }
                 }
-                None => thread::sleep(Duration::from_millis(10)),
+            } else {
+                keep = 0;
+            }
+
+            while input_buffer.len() > keep {
+                input_buffer.pop();
+                if self.echo_input {
+                    self.backspace()?;
+                }
+            }
+        } else if input_buffer.len() < input.len && (0x20..0x7f).contains(&key) {
+            input_buffer.push(key);
+            if self.echo_input {
+                self.io.echo(&[key])?;
             }
         }
 
-        Ok(input_buffer)
+        Ok(false)
+    }
+
+    // Handle `e` and then any keys queued behind it by a burst of input.  Returns true if the
+    // line is terminated, leaving keys after the terminator queued for the next read.
+    fn line_input_keys(
+        &mut self,
+        input: &mut LineInput,
+        e: &InputEvent,
+    ) -> Result<bool, RuntimeError> {
+        let mut done = self.line_input_key(input, e)?;
+        while !done {
+            if self.discard_paste_overflow && input.buffer.len() >= input.len {
+                self.io.discard_queued_line();
+            }
+            match self.io.queued_key() {
+                Some(e) => done = self.line_input_key(input, &e)?,
+                None => break,
+            }
+        }
+
+        Ok(done)
+    }
+
+    // Returns true if a sound interrupt or timeout ends line input
+    fn line_input_interrupted(&mut self, end: u128) -> bool {
+        match self.input_interrupt_event(end) {
+            Some(Interrupt::Sound) => {
+                // Return the buffer and clear any pending input interrupt
+                self.state.clear_read_interrupt();
+                true
+            }
+            Some(Interrupt::ReadTimeout) => true,
+            None => false,
+        }
+    }
+
+    /// Read a line of input, blocking until it's terminated or the read is interrupted.
+    /// `timeout` is in tenths of a second, or 0 for none.
+    pub fn read_line(
+        &mut self,
+        text: &[u16],
+        len: usize,
+        terminators: &[u16],
+        timeout: u16,
+    ) -> Result<Vec<u16>, RuntimeError> {
+        self.progress = true;
+        if let Some(command) = self.io.next_command() {
+            debug!(target: "app::stream", "Read from input stream 1");
+            return self.read_command(text, len, &command);
+        }
+
+        let mut input = LineInput {
+            buffer: text.to_vec(),
+            len,
+            terminators: terminators.to_vec(),
+            separators: text::separators(self)?,
+        };
+
+        let end = self.input_end(timeout);
+        let check_sound = self.sound_interrupt_pending();
+
+        debug!(target: "app::screen", "Pending sound interrupt? {}", check_sound);
+
+        loop {
+            if self.line_input_interrupted(end) {
+                return Ok(input.buffer);
+            }
+
+            let e = self.poll_key(end == 0 && !check_sound)?;
+            if e.zchar().is_none() {
+                self.sleeper.sleep(Duration::from_millis(10));
+            } else if self.line_input_keys(&mut input, &e)? {
+                return Ok(input.buffer);
+            }
+        }
+    }
+
+    /// Read a line of input for READ.
+    ///
+    /// When execution is driven by [`advance`](Self::advance), this returns `None` and the
+    /// instruction at `pc` waits for input; `advance` completes it when the line is
+    /// terminated or the read is interrupted.  Otherwise it blocks like
+    /// [`read_line`](Self::read_line).  Commands from input stream 1 are always returned
+    /// immediately.  `timeout` is in tenths of a second, or 0 for none.
+    pub fn request_line(
+        &mut self,
+        pc: usize,
+        operands: &[u16],
+        text: &[u16],
+        len: usize,
+        terminators: &[u16],
+        timeout: u16,
+    ) -> Result<Option<Vec<u16>>, RuntimeError> {
+        if !self.yield_for_input {
+            return self.read_line(text, len, terminators, timeout).map(Some);
+        }
+
+        self.progress = true;
+        if let Some(command) = self.io.next_command() {
+            debug!(target: "app::stream", "Read from input stream 1");
+            return self.read_command(text, len, &command).map(Some);
+        }
+
+        self.pending_input = Some(PendingInput {
+            pc,
+            operands: operands.to_vec(),
+            kind: PendingKind::Line(LineInput {
+                buffer: text.to_vec(),
+                len,
+                terminators: terminators.to_vec(),
+                separators: text::separators(self)?,
+            }),
+            end: self.input_end(timeout),
+            check_sound: self.sound_interrupt_pending(),
+        });
+        Ok(None)
+    }
+
+    // Default save file name from the configured template, if there is one and it can be
+    // expanded.  The room is the short name of the object in global 16 (the status line
+    // location).
+    fn default_save_name(&mut self, suffix: &str) -> Result<Option<Vec<u16>>, RuntimeError> {
+        let template = match (&self.save_name_template, suffix) {
+            (Some(t), "ifzs") => t.clone(),
+            _ => return Ok(None),
+        };
+
+        let room = match self.state.variable(16)? {
+            0 => None,
+            o => property::short_name(self, o as usize)
+                .and_then(|z| text::from_vec(self, &z, false))
+                .ok(),
+        }
+        .map(|r| {
+            r.iter()
+                .map(|c| char::from_u32(*c as u32).unwrap_or('?'))
+                .collect::<String>()
+        });
+        files::first_available_from_template(
+            &template,
+            &self.name,
+            self.turns,
+            room.as_deref(),
+            suffix,
+        )
+    }
+
+    // The last save file used for this game, if it still exists
+    fn recent_file(&self, suffix: &str) -> Option<String> {
+        match (&self.recent_files, suffix) {
+            (Some(path), "ifzs") => {
+                files::recent_file(path, &self.name).and_then(|f| files::check_existing(&f))
+            }
+            _ => None,
+        }
+    }
+
+    fn remember_file(&self, filename: &str, suffix: &str) {
+        if let (Some(path), "ifzs") = (&self.recent_files, suffix) {
+            files::remember_file(path, &self.name, filename);
+        }
     }
 
     pub fn prompt_filename(
@@ -661,67 +1722,61 @@ impl ZMachine {
         overwrite: bool,
         first: bool,
     ) -> Result<String, RuntimeError> {
-        self.print_str(prompt.to_string())?;
         let n = if first {
-            files::first_available(&self.name, suffix)?
+            match self.default_save_name(suffix)? {
+                Some(n) => n,
+                None => files::first_available(&self.name, suffix)?,
+            }
         } else {
-            files::last_existing(&self.name, suffix)?
-        };
-
-        self.print(&n)?;
-
-        let f = self.read_line(&n, 32, &['\r' as u16], 0)?;
-        let filename = match String::from_utf16(&f) {
-            Ok(s) => s.trim().to_string(),
-            Err(e) => {
-                return recoverable_error!(
-                    ErrorCode::InvalidInput,
-                    "Error parsing user input: {}",
-                    e
-                )
+            match self.recent_file(suffix) {
+                Some(f) => f.chars().map(|c| c as u16).collect(),
+                None => files::last_existing(&self.name, suffix)?,
             }
         };
 
-        if !overwrite {
-            match Path::new(&filename).try_exists() {
-                Ok(b) => match b {
-                    true => {
+        // Let the terminal prompt for the name if it can, otherwise prompt in the game window
+        let filename = match self.filename_answer.take() {
+            Some(filename) => filename.trim().to_string(),
+            None if self.io.can_request_filename() => {
+                match self
+                    .io
+                    .request_filename(prompt, &String::from_utf16_lossy(&n))
+                {
+                    Some(filename) => filename.trim().to_string(),
+                    None => {
                         return recoverable_error!(
-                            ErrorCode::FileExists,
-                            "'{}' already exists.",
-                            filename
+                            ErrorCode::InvalidFilename,
+                            "No file name was given"
                         )
                     }
-                    false => {}
-                },
-                Err(e) => {
-                    return recoverable_error!(
-                        ErrorCode::Interpreter,
-                        "Error checking if '{}' exists: {}",
-                        filename,
-                        e
-                    )
                 }
             }
-        }
-
-        match Regex::new(r"^((.*\.z\d)|(.*\.blb)|(.*\.blorb))$") {
-            Ok(r) => {
-                if r.is_match(&filename) {
-                    recoverable_error!(
-                        ErrorCode::InvalidFilename,
-                        "Filenames ending in '.z#', '.blb', or '.blorb' are not allowed"
-                    )
-                } else {
-                    Ok(filename)
+            None => {
+                self.print_str(prompt.to_string())?;
+                self.print(&n)?;
+
+                let f = self.read_line(&n, 32, &['\r' as u16], 0)?;
+                match String::from_utf16(&f) {
+                    Ok(s) => s.trim().to_string(),
+                    Err(e) => {
+                        return recoverable_error!(
+                            ErrorCode::InvalidInput,
+                            "Error parsing user input: {}",
+                            e
+                        )
+                    }
                 }
             }
-            Err(e) => recoverable_error!(
-                ErrorCode::Interpreter,
-                "Interal error with regex checking filename: {}",
-                e
-            ),
+        };
+
+        if !overwrite && self.saves.exists(&filename)? {
+            return recoverable_error!(ErrorCode::FileExists, "'{}' already exists.", filename);
+        }
+
+        if !self.allow_any_save_name {
+            files::validate_save_name(&filename)?;
         }
+        Ok(filename)
     }
 
     pub fn prompt_and_create(
@@ -729,19 +1784,30 @@ impl ZMachine {
         prompt: &str,
         suffix: &str,
         overwrite: bool,
+        mode: OpenMode,
     ) -> Result<File, RuntimeError> {
+        let (_, file) = self.prompt_and_create_file(prompt, suffix, overwrite, mode)?;
+        Ok(file)
+    }
+
+    // As prompt_and_create, also returning the name of the file that was created
+    fn prompt_and_create_file(
+        &mut self,
+        prompt: &str,
+        suffix: &str,
+        overwrite: bool,
+        mode: OpenMode,
+    ) -> Result<(String, File), RuntimeError> {
         match self.prompt_filename(prompt, suffix, overwrite, true) {
-            Ok(filename) => match fs::OpenOptions::new()
-                .create(true)
-                .truncate(true)
-                .write(true)
-                .open(filename.trim())
-            {
-                Ok(f) => Ok(f),
+            Ok(filename) => match mode.options().open(filename.trim()) {
+                Ok(f) => {
+                    self.remember_file(&filename, suffix);
+                    Ok((filename.trim().to_string(), f))
+                }
                 Err(e) => recoverable_error!(ErrorCode::FileError, "{}", e),
             },
             Err(e) => {
-                self.print_str(format!("Error creating file: {}\r", e))?;
+                self.print_error("error.create_file", &e)?;
                 Err(e)
             }
         }
@@ -753,48 +1819,58 @@ impl ZMachine {
         suffix: &str,
         data: &[u8],
         overwrite: bool,
-    ) -> Result<(), RuntimeError> {
-        let mut file = self.prompt_and_create(prompt, suffix, overwrite)?;
-
-        match file.write_all(data) {
-            Ok(_) => (),
-            Err(e) => return recoverable_error!(ErrorCode::FileError, "{}", e),
+    ) -> Result<String, RuntimeError> {
+        let filename = match self.prompt_filename(prompt, suffix, overwrite, true) {
+            Ok(filename) => filename,
+            Err(e) => {
+                self.print_error("error.create_file", &e)?;
+                return Err(e);
+            }
         };
-        match file.flush() {
-            Ok(_) => Ok(()),
-            Err(e) => recoverable_error!(ErrorCode::FileError, "{}", e),
-        }
+        self.saves.write(&filename, data)?;
+        self.remember_file(&filename, suffix);
+        Ok(filename)
     }
 
     pub fn prompt_and_read(&mut self, prompt: &str, suffix: &str) -> Result<Vec<u8>, RuntimeError> {
+        let (_, data) = self.prompt_and_read_file(prompt, suffix)?;
+        Ok(data)
+    }
+
+    // As prompt_and_read, also returning the name of the file that was read
+    fn prompt_and_read_file(
+        &mut self,
+        prompt: &str,
+        suffix: &str,
+    ) -> Result<(String, Vec<u8>), RuntimeError> {
         let filename = self.prompt_filename(prompt, suffix, true, false)?;
-        let mut data = Vec::new();
-        match File::open(filename.trim()) {
-            Ok(mut file) => match file.read_to_end(&mut data) {
-                Ok(_) => Ok(data),
-                Err(e) => recoverable_error!(ErrorCode::FileError, "{}", e),
-            },
-            Err(e) => recoverable_error!(ErrorCode::FileError, "{}: {}", filename, e),
-        }
+        let data = self.saves.read(&filename)?;
+        self.remember_file(&filename, suffix);
+        Ok((filename, data))
     }
 
     // Save/restore
     // Also quit/restart
     pub fn quit(&mut self) -> Result<(), RuntimeError> {
-        self.print(
-            &"Press any key to exit"
-                .as_bytes()
-                .iter()
-                .map(|x| *x as u16)
-                .collect(),
-        )?;
-        self.read_key(0)?;
+        let stats = self.session_stats();
+        info!(target: "app::state", "{}", stats);
+        info!(target: "app::state", "Status line short names: {}", self.state.short_name_stats());
+        let text = stats.format(self.messages());
+        self.print_str(format!("\r{}\r", text))?;
+        // Nobody is waiting to press a key when there's no keyboard or input is scripted
+        if self.io.interactive() && self.io.input_stream() == 0 {
+            let prompt = self.message("prompt.press_key");
+            self.print(&prompt.chars().map(|c| c as u16).collect())?;
+            self.read_key(0)?;
+        }
 
         self.io.quit();
+        self.emit(GameEvent::Quit);
         Ok(())
     }
 
     pub fn new_line(&mut self) -> Result<(), RuntimeError> {
+        self.progress = true;
         self.io.new_line()
     }
 
@@ -810,30 +1886,62 @@ impl ZMachine {
         repeats: u8,
         routine: Option<usize>,
     ) -> Result<(), RuntimeError> {
+        // Changing the volume of the sound that's playing doesn't start it again.  The
+        // manager still names the last effect after it finishes, so check it's playing.
+        let playing = self
+            .sound_manager
+            .as_mut()
+            .is_some_and(|s| s.current_effect() as u16 == effect && s.is_playing());
+        if self.describe_sounds && !playing {
+            self.describe_sound(effect)?;
+        }
+
+        if self.sound_muted {
+            return Ok(());
+        }
+
         let r = if self.version > 4 && repeats > 0 {
             Some(repeats)
         } else {
             None
         };
 
-        if let Some(sounds) = self.sound_manager.as_mut() {
+        // A manager that lost its output device is treated as no manager at all
+        if let Some(sounds) = self.sound_manager.as_mut().filter(|s| !s.is_degraded()) {
             if let Some(address) = routine {
                 self.state.set_sound_interrupt(address);
             }
             // Sound is already playing, possibly repeating, so just
             // adjust the volume, if possible, without interrupting
             // the loop
-            if sounds.current_effect() as u16 == effect {
+            if playing {
                 sounds.change_volume(volume);
                 Ok(())
             } else {
-                sounds.play_sound(effect, volume, r)
+                if sounds.play_sound(effect, volume, r)? {
+                    self.emit(GameEvent::SoundStarted { effect });
+                }
+                Ok(())
             }
         } else {
             Ok(())
         }
     }
 
+    // Show a sound effect in the lower window, for players who can't hear it
+    fn describe_sound(&mut self, effect: u16) -> Result<(), RuntimeError> {
+        let text = match self
+            .sound_manager
+            .as_ref()
+            .and_then(|s| s.description(effect))
+        {
+            Some(description) => format!("[sound: {}]", description),
+            None => format!("[sound #{}]", effect),
+        };
+        self.io
+            .print_lower(&text.chars().map(|c| c as u16).collect::<Vec<u16>>())
+    }
+
     pub fn stop_sound(&mut self) -> Result<(), RuntimeError> {
         if let Some(sounds) = self.sound_manager.as_mut() {
             self.state.clear_sound_interrupt();
@@ -843,121 +1951,630 @@ impl ZMachine {
         Ok(())
     }
 
-    pub fn is_sound_playing(&mut self) -> bool {
+    /// Change the sound volume factor, rescaling any sound that is playing.  A larger factor
+    /// is quieter.
+    pub fn set_volume_factor(&mut self, volume_factor: f32) {
         if let Some(sounds) = self.sound_manager.as_mut() {
-            sounds.is_playing()
+            sounds.set_volume_factor(volume_factor)
+        }
+    }
+
+    // Handle a volume key during line input, returning true if `key` was one.  Keys the
+    // game uses to terminate input are left to the game.
+    fn volume_key(&mut self, key: u16, terminators: &[u16]) -> bool {
+        if is_terminator(key, terminators) {
+            return false;
+        }
+
+        if Some(key) == self.volume_keys.0 {
+            self.step_volume(VOLUME_STEP)
+        } else if Some(key) == self.volume_keys.1 {
+            self.step_volume(1.0 / VOLUME_STEP)
         } else {
             false
         }
     }
 
-    // Run
-    pub fn run(&mut self) -> Result<(), RuntimeError> {
-        let mut n = 1;
-        loop {
-            log_mdc::insert("instruction_count", format!("{:8x}", n));
-            let pc = self.state.pc()?;
-            let instruction = decoder::decode_instruction(self, pc)?;
-            match processor::dispatch(self, &instruction) {
-                Ok(pc) => {
-                    if pc == 0 {
-                        return Ok(());
-                    }
+    // Scale the volume factor by `step`, returning false if there is no sound
+    fn step_volume(&mut self, step: f32) -> bool {
+        match self.sound_manager.as_ref().map(|s| s.volume_factor()) {
+            Some(volume_factor) => {
+                let volume_factor =
+                    (volume_factor * step).clamp(VOLUME_FACTOR_MIN, VOLUME_FACTOR_MAX);
+                self.set_volume_factor(volume_factor);
+                let message = self.messages().format(
+                    "status.volume",
+                    &[("volume", &format!("{:.2}", volume_factor))],
+                );
+                self.io.show_message(&message);
+                true
+            }
+            None => false,
+        }
+    }
 
-                    if self.state.sound_interrupt().is_some() {
-                        if let Some(sounds) = self.sound_manager.as_mut() {
-                            if !sounds.is_playing() {
-                                let pc = self.state.call_sound_interrupt(pc)?;
-                                self.state.set_pc(pc)?;
-                            } else {
-                                self.state.set_pc(pc)?;
-                            }
-                        }
-                    } else {
-                        self.state.set_pc(pc)?;
-                    }
-                }
-                Err(e) => {
-                    // If the error is fatal or error handling is abort
-                    if !e.is_recoverable() || self.error_handling == ErrorHandling::Abort {
-                        return Err(e);
-                    // Error is not fatal
-                    // If error handling is ignore
-                    } else if self.error_handling == ErrorHandling::Ignore {
-                        self.state.set_pc(instruction.next_address())?;
-                    // If error handling is warn always or the code hasn't been seen yet
-                    } else if self.error_handling == ErrorHandling::ContinueWarnAlways
-                        || !self.errors.contains(&e.code())
-                    {
-                        self.errors.insert(e.code());
-                        if self.io.error(
-                            &format!("[{}]: {}", n, instruction),
-                            e.message(),
-                            e.is_recoverable(),
-                        ) {
-                            self.state.set_pc(instruction.next_address())?;
-                        } else {
-                            // Print instruction details before returning an error
-                            self.print_str(format!("\r[{}]: {}", n, instruction))?;
-                            return Err(e);
-                        }
-                    // Error handling is warn once and the code has been seen before
-                    } else {
-                        self.state.set_pc(instruction.next_address())?
-                    }
-                }
+    fn toggle_sound(&mut self) {
+        self.sound_muted = !self.sound_muted;
+        if self.sound_muted {
+            if let Some(sounds) = self.sound_manager.as_mut() {
+                sounds.stop_sound();
             }
-            n += 1;
         }
+        let message = self.message(if self.sound_muted {
+            "status.sound_off"
+        } else {
+            "status.sound_on"
+        });
+        self.io.show_message(&message);
     }
-}
 
-#[cfg(test)]
-mod tests {
+    fn toggle_transcript(&mut self) {
+        let (stream, key) = if self.io.is_stream_enabled(2) {
+            (-2, "status.transcript_off")
+        } else {
+            (2, "status.transcript_on")
+        };
+        match self.output_stream(stream, None) {
+            Ok(()) => {
+                let message = self.message(key);
+                self.io.show_message(&message)
+            }
+            Err(e) => self.io.show_message(e.message()),
+        }
+    }
 
-    use std::collections::HashMap;
+    // Show the interpreter menu during line input and carry out the player's choice.
+    // Returns true when the choice ends the line because the game was restarted, restored,
+    // or quit; the READ then carries on from `menu_jump` instead of storing the input.
+    fn interpreter_menu(&mut self) -> Result<bool, RuntimeError> {
+        let messages = self.messages();
+        let item = |key: char, label: &str| format!("{}  {}", key, messages.get(label));
+        let toggle = |key: char, label: &str, on: bool| {
+            let state = messages.get(if on { "menu.on" } else { "menu.off" });
+            format!("{} ({})", item(key, label), state)
+        };
+        let mut items = vec![
+            item('S', "menu.save"),
+            item('R', "menu.restore"),
+            item('N', "menu.restart"),
+            item('Q', "menu.quit"),
+        ];
+        items.push(toggle('T', "menu.transcript", self.io.is_stream_enabled(2)));
+        if self.sound_manager.is_some() {
+            items.push(toggle('M', "menu.sound", !self.sound_muted));
+            items.push(item('+', "menu.volume_up"));
+            items.push(item('-', "menu.volume_down"));
+        }
+        items.push(item('I', "menu.statistics"));
+        // Only offered when the instruction log is being written
+        let instruction_log = self.trace_sink.active();
+        if instruction_log {
+            items.push(toggle(
+                'L',
+                "menu.instruction_log",
+                self.instruction_log.enabled(),
+            ));
+        }
+        items.push(String::new());
+        items.push(messages.get("menu.other_key").to_string());
 
-    use iff::Chunk;
+        let key = match self.io.menu("mxyzptlk", &items) {
+            Some(key) => key.to_ascii_lowercase(),
+            None => return Ok(false),
+        };
+        match key {
+            's' => {
+                if let Err(e) = self.menu_save() {
+                    self.io.show_message(e.message());
+                }
+            }
+            'r' => return self.menu_restore(),
+            'n' => {
+                self.menu_jump = Some(self.restart()?);
+                return Ok(true);
+            }
+            'q' => {
+                self.quit()?;
+                self.menu_jump = Some(0);
+                return Ok(true);
+            }
+            't' => self.toggle_transcript(),
+            'm' if self.sound_manager.is_some() => self.toggle_sound(),
+            'l' if instruction_log => self.toggle_instruction_log(),
+            '+' | '=' => {
+                self.step_volume(1.0 / VOLUME_STEP);
+            }
+            '-' => {
+                self.step_volume(VOLUME_STEP);
+            }
+            'i' => {
+                let text = self.session_stats().format(self.messages());
+                self.io.show_message(&text);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    // Save from the interpreter menu.  The save resumes at the READ waiting for input,
+    // which reads again when the save is restored.  That can't be done if the READ took an
+    // operand from the stack, which is gone by now.
+    fn menu_save(&mut self) -> Result<(), RuntimeError> {
+        let pc = self.state.pc()?;
+        let read = decoder::decode_instruction(self, pc)?;
+        if read
+            .operands()
+            .iter()
+            .any(|o| o.operand_type() == OperandType::Variable && o.value() == 0)
+        {
+            return recoverable_error!(
+                ErrorCode::Save,
+                "The game can't be saved from the menu while reading this input"
+            );
+        }
+        self.write_save(pc, true)?;
+        let message = self.message("status.saved");
+        self.io.show_message(&message);
+        Ok(())
+    }
 
-    use crate::{
-        assert_ok, assert_ok_eq, assert_print, assert_some, assert_some_eq,
-        blorb::{Blorb, Entry, Index, Loop, RIdx},
-        test_util::{
-            backspace, beep, buffer_mode, colors, cursor, erase_line, erase_window, input,
-            mock_blorb, mock_object, mock_routine, play_sound, quit, scroll, set_input_delay,
-            set_input_timeout, split, style, test_map, window,
-        },
-        zmachine::{io::screen::Style, state::header::Flags2},
-    };
+    // Restore from the interpreter menu, ending the line being read when the game has been
+    // replaced
+    fn menu_restore(&mut self) -> Result<bool, RuntimeError> {
+        match self.restore() {
+            Ok(Some(Restored::Save(pc))) => {
+                self.menu_jump = Some(processor::resume_restored(self, pc)?);
+                Ok(true)
+            }
+            Ok(Some(Restored::Read(pc))) => {
+                self.menu_jump = Some(pc);
+                Ok(true)
+            }
+            Ok(None) => Ok(false),
+            Err(e) => {
+                self.io.show_message(e.message());
+                Ok(false)
+            }
+        }
+    }
 
-    use super::*;
+    /// Where the READ carries on, once, after the interpreter menu restarted, restored, or
+    /// quit the game while it was reading.  0 quits.
+    pub fn take_menu_jump(&mut self) -> Option<usize> {
+        self.menu_jump.take()
+    }
 
-    #[test]
-    fn test_constructor() {
-        let map = test_map(3);
-        let m = Memory::new(map);
-        let zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert_eq!(zmachine.name, "test");
-        assert_eq!(zmachine.version(), 3);
-        assert_eq!(zmachine.state.version(), 3);
-        assert_eq!(zmachine.io.columns(), 80);
-        assert_eq!(zmachine.io.rows(), 24);
-        assert!(zmachine.input_interrupt.is_none());
-        assert!(!zmachine.input_interrupt_print);
-        assert!(zmachine.sound_manager.is_none());
-        assert!(zmachine.sound_interrupt().is_none());
+    /// Answer the next prompt for a file name with `filename` instead of asking for one
+    pub fn answer_filename(&mut self, filename: &str) {
+        self.filename_answer = Some(filename.to_string());
     }
 
-    #[test]
-    fn test_input_interrupt_print() {
-        let map = test_map(3);
-        let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(!zmachine.input_interrupt_print());
-        zmachine.set_input_interrupt_print();
-        assert!(zmachine.input_interrupt_print());
-        zmachine.clear_input_interrupt_print();
-        assert!(!zmachine.input_interrupt_print());
+    /// Keep saves and command files with `saves` instead of in files.  Default file names
+    /// are still chosen by looking at the working directory.
+    pub fn set_save_provider(&mut self, saves: Box<dyn SaveProvider>) {
+        self.saves = saves;
+    }
+
+    /// Take the time from `clock` instead of the system clock.  Play time so far is kept.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.play_time = self.session_stats().play_time();
+        self.clock = clock;
+        self.session_start = self.clock.now();
+    }
+
+    /// Wait between polls of the keyboard with `sleeper` instead of sleeping the thread
+    pub fn set_sleeper(&mut self, sleeper: Box<dyn Sleeper>) {
+        self.sleeper = sleeper;
+    }
+
+    /// Send traced instructions to `sink` instead of the instruction log.  The instruction
+    /// log filter still chooses which instructions are traced.
+    pub fn set_trace_sink(&mut self, sink: Box<dyn TraceSink>) {
+        self.trace_sink = sink;
+    }
+
+    /// Trace only the instructions `filter` admits from here on
+    pub fn set_trace_filter(&mut self, filter: &InstructionLogFilter) {
+        self.instruction_log = InstructionLog::new(filter, self.version);
+    }
+
+    /// The operand values of the instruction that is executing, if it's traced
+    pub fn trace_operands(&mut self, instruction: &Instruction, values: &[u16]) {
+        if self.trace_instruction {
+            self.trace_sink.operands(instruction, values);
+        }
+    }
+
+    /// A result stored by the instruction that is executing, if it's traced
+    pub fn trace_store(&mut self, store: &StoreResult, value: u16) {
+        if self.trace_instruction {
+            self.trace_sink.store(store, value);
+        }
+    }
+
+    pub fn instruction_log_enabled(&self) -> bool {
+        self.instruction_log.enabled()
+    }
+
+    /// Turn the instruction log on or off, keeping the configured filter
+    pub fn set_instruction_log(&mut self, enabled: bool) {
+        info!(target: "app::instruction", "Instruction log {}", if enabled { "on" } else { "off" });
+        self.instruction_log.set_enabled(enabled);
+    }
+
+    fn toggle_instruction_log(&mut self) {
+        let enabled = !self.instruction_log.enabled();
+        self.set_instruction_log(enabled);
+        let message = self.message(if enabled {
+            "status.instruction_log_on"
+        } else {
+            "status.instruction_log_off"
+        });
+        self.io.show_message(&message);
+    }
+
+    pub fn is_sound_playing(&mut self) -> bool {
+        if let Some(sounds) = self.sound_manager.as_mut() {
+            sounds.is_playing()
+        } else {
+            false
+        }
+    }
+
+    // Run
+    /// Execute instructions until the game quits.
+    ///
+    /// Returns `Ok` only when the game executes QUIT.  Any fatal error, including the user
+    /// interrupting execution, is returned as an `Err`.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        let result = self.run_until_quit();
+        if let Err(e) = &result {
+            error!(target: "app::state", "{}", e);
+            for line in format_backtrace(&self.backtrace()) {
+                error!(target: "app::state", "{}", line);
+            }
+        }
+        result
+    }
+
+    fn run_until_quit(&mut self) -> Result<(), RuntimeError> {
+        let mut event = None;
+        loop {
+            match self.advance(event.take())? {
+                Advance::Quit => return Ok(()),
+                Advance::Output => {}
+                Advance::NeedInput { .. } => event = self.wait_for_input()?,
+            }
+        }
+    }
+
+    /// The routine frames on the call stack, newest first
+    pub fn backtrace(&self) -> Vec<FrameInfo> {
+        self.state.backtrace()
+    }
+
+    // Read the terminal for the input advance() is waiting for, blocking only when nothing
+    // else can end the read
+    fn wait_for_input(&mut self) -> Result<Option<InputEvent>, RuntimeError> {
+        let block = self
+            .pending_input
+            .as_ref()
+            .is_some_and(|pending| pending.blocking());
+        let e = self.poll_key(block)?;
+        if e.zchar().is_some() {
+            Ok(Some(e))
+        } else {
+            self.sleeper.sleep(Duration::from_millis(10));
+            Ok(None)
+        }
+    }
+
+    /// Execute instructions without blocking for input.
+    ///
+    /// When READ or READ_CHAR needs input, returns [`Advance::NeedInput`] and the instruction
+    /// waits; each following call passes the next input event, if any, until the input is
+    /// complete or the read is interrupted by a timeout or sound interrupt.  Otherwise up to
+    /// `ADVANCE_INSTRUCTIONS` instructions are executed before returning [`Advance::Output`],
+    /// so the host can service its own event loop.  Filename prompts and error prompts still
+    /// use the terminal's blocking input.
+    pub fn advance(&mut self, event: Option<InputEvent>) -> Result<Advance, RuntimeError> {
+        let result = self.advance_instructions(event);
+        self.trace_sink.flush();
+        // Deliver buffered output still waiting for the end of a word
+        self.io.flush()?;
+        result
+    }
+
+    fn advance_instructions(&mut self, event: Option<InputEvent>) -> Result<Advance, RuntimeError> {
+        if let Some(pending) = self.pending_input.take() {
+            if let Some(advance) = self.resume_input(pending, event)? {
+                return Ok(advance);
+            }
+        }
+
+        self.yield_for_input = true;
+        let result = self.execute();
+        self.yield_for_input = false;
+        result
+    }
+
+    fn execute(&mut self) -> Result<Advance, RuntimeError> {
+        for _ in 0..ADVANCE_INSTRUCTIONS {
+            self.instruction_count += 1;
+            let count = self.instruction_count;
+            mdc::insert("instruction_count", || format!("{:8x}", count));
+            if self.io.interrupted() {
+                return fatal_error!(ErrorCode::Interrupted, "Interrupted by user");
+            }
+
+            let pc = self.state.pc()?;
+            let instruction = decoder::decode_instruction(self, pc)?;
+            self.trace_instruction =
+                self.trace_sink.active() && self.instruction_log.admits(&instruction);
+            if self.trace_instruction {
+                self.trace_sink.instruction(&instruction);
+            }
+            self.progress = false;
+            let result = processor::dispatch(self, &instruction);
+            if let Some(pending) = self.pending_input.as_ref() {
+                return Ok(self.need_input(pending));
+            }
+
+            if let Some(advance) = self.complete_instruction(&instruction, result)? {
+                return Ok(advance);
+            }
+        }
+
+        Ok(Advance::Output)
+    }
+
+    fn need_input(&self, pending: &PendingInput) -> Advance {
+        let timeout = if pending.end > 0 {
+            let remaining = pending.end.saturating_sub(self.now(None));
+            Some(Duration::from_millis(remaining as u64))
+        } else {
+            None
+        };
+
+        let preloaded = match &pending.kind {
+            PendingKind::Line(input) => input.buffer.clone(),
+            PendingKind::Char => Vec::new(),
+        };
+
+        Advance::NeedInput {
+            kind: pending.kind(),
+            timeout,
+            preloaded,
+        }
+    }
+
+    // Apply an input event to a waiting instruction and complete it if the input is done.
+    // Returns None when execution can continue.
+    fn resume_input(
+        &mut self,
+        mut pending: PendingInput,
+        event: Option<InputEvent>,
+    ) -> Result<Option<Advance>, RuntimeError> {
+        self.progress = true;
+        let instruction = decoder::decode_instruction(self, pending.pc)?;
+        let result = match &mut pending.kind {
+            PendingKind::Line(input) => {
+                let mut done = false;
+                if let Some(e) = event {
+                    done = self.line_input_keys(input, &e)?;
+                }
+
+                if done || self.line_input_interrupted(pending.end) {
+                    let buffer = std::mem::take(&mut input.buffer);
+                    processor::complete_read(self, &instruction, &pending.operands, buffer)
+                } else {
+                    let advance = self.need_input(&pending);
+                    self.pending_input = Some(pending);
+                    return Ok(Some(advance));
+                }
+            }
+            PendingKind::Char => {
+                let key = match event {
+                    Some(e) => self.char_input_key(e)?,
+                    None => None,
+                };
+                let key = match key {
+                    Some(key) => Some(key),
+                    None => self.input_interrupt_event(pending.end).map(|interrupt| {
+                        if interrupt == Interrupt::Sound {
+                            self.input_interrupt = None;
+                        }
+                        InputEvent::from_interrupt(interrupt)
+                    }),
+                };
+
+                match key {
+                    Some(key) => {
+                        processor::complete_read_char(self, &instruction, &pending.operands, key)
+                    }
+                    None => {
+                        let advance = self.need_input(&pending);
+                        self.pending_input = Some(pending);
+                        return Ok(Some(advance));
+                    }
+                }
+            }
+        };
+
+        self.complete_instruction(&instruction, result)
+    }
+
+    // Move to the next instruction, applying the error policy if the instruction failed.
+    // Returns Some when execution has to stop.
+    fn complete_instruction(
+        &mut self,
+        instruction: &Instruction,
+        result: Result<usize, RuntimeError>,
+    ) -> Result<Option<Advance>, RuntimeError> {
+        self.check_transcript_stopped()?;
+        let pc = instruction.address();
+        match result {
+            Ok(next_pc) => {
+                if next_pc == 0 {
+                    return Ok(Some(Advance::Quit));
+                }
+
+                // An instruction that leads straight back to itself without doing anything
+                // observable will never make progress
+                if next_pc == pc && !self.progress {
+                    self.repeats += 1;
+                    if self.loop_threshold > 0 && self.repeats > self.loop_threshold {
+                        return fatal_error!(
+                            ErrorCode::InfiniteLoop,
+                            "Infinite loop detected at ${:06x}",
+                            pc
+                        );
+                    }
+                } else {
+                    self.repeats = 0;
+                }
+
+                let pc = next_pc;
+
+                if self.sound_interrupt_due() {
+                    let pc = self.state.call_sound_interrupt(pc)?;
+                    self.state.set_pc(pc)?;
+                } else {
+                    self.state.set_pc(pc)?;
+                }
+            }
+            Err(e) => match self.error_policy.handle(&e) {
+                ErrorDisposition::Abort => return Err(e),
+                ErrorDisposition::Continue => self.state.set_pc(instruction.next_address())?,
+                ErrorDisposition::ContinueWithPrompt => {
+                    let n = self.instruction_count;
+                    if self.io.error(
+                        &format!("[{}]: {}", n, instruction),
+                        e.message(),
+                        e.is_recoverable(),
+                    ) {
+                        self.state.set_pc(instruction.next_address())?;
+                    } else {
+                        // Print instruction details before returning an error
+                        self.print_str(format!("\r[{}]: {}", n, instruction))?;
+                        return Err(e);
+                    }
+                }
+            },
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        fs,
+        io::Write,
+        rc::Rc,
+    };
+
+    use iff::Chunk;
+
+    use crate::{
+        assert_ok, assert_ok_eq, assert_print, assert_some, assert_some_eq,
+        blorb::{Blorb, Entry, Index, Loop, RIdx},
+        test_util::{
+            backspace, beep, buffer_mode, cancel_filename_response, capture_log, colors, cursor,
+            effective_volume, erase_line, erase_window, errors, filename_request, finish_sound,
+            input, input_pending, log, menu, message, mock_blorb, mock_object, mock_routine,
+            play_sound, print, quit, scroll, set_clock, set_filename_response, set_input_delay,
+            set_input_timeout, set_interactive, set_interrupt, set_resized, set_size, set_variable,
+            split, style, test_map, text, window, FullDiskWriter,
+        },
+        zmachine::{
+            input::InputKind,
+            io::screen::{headless::HeadlessTerminal, Style},
+            state::header::Flags2,
+            stats::CacheStats,
+        },
+    };
+
+    use super::*;
+
+    fn item(key: char, label: &str) -> String {
+        format!("{}  {}", key, text(label))
+    }
+
+    fn toggle(key: char, label: &str, on: bool) -> String {
+        format!(
+            "{} ({})",
+            item(key, label),
+            text(if on { "menu.on" } else { "menu.off" })
+        )
+    }
+
+    fn volume_message(volume: &str) -> String {
+        Messages::default().format("status.volume", &[("volume", volume)])
+    }
+
+    fn disk_full_message() -> String {
+        let reason = text("transcript.disk_full");
+        Messages::default().format("transcript.stopped", &[("reason", &reason)])
+    }
+
+    #[test]
+    fn test_constructor() {
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert_eq!(zmachine.name, "test");
+        assert_eq!(zmachine.version(), 3);
+        assert_eq!(zmachine.state.version(), 3);
+        assert_eq!(zmachine.io.columns(), 80);
+        assert_eq!(zmachine.io.rows(), 24);
+        assert!(zmachine.input_interrupt.is_none());
+        assert!(!zmachine.input_interrupt_print());
+        assert!(zmachine.sound_manager.is_none());
+        assert!(zmachine.sound_interrupt().is_none());
+    }
+
+    #[test]
+    fn test_standard_version() {
+        let m = Memory::new(test_map(5));
+        let zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert_eq!(zmachine.standard_version(), (1, 0));
+        assert_ok_eq!(zmachine.read_byte(0x32), 1);
+        assert_ok_eq!(zmachine.read_byte(0x33), 0);
+    }
+
+    #[test]
+    fn test_standard_version_config() {
+        let mut config = Config::default();
+        config.set_standard_version((0, 2));
+        let m = Memory::new(test_map(5));
+        let zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        assert_eq!(zmachine.standard_version(), (0, 2));
+        assert_ok_eq!(zmachine.read_byte(0x32), 0);
+        assert_ok_eq!(zmachine.read_byte(0x33), 2);
+
+        // The configuration can't claim a later revision than the interpreter implements
+        let mut config = Config::default();
+        config.set_standard_version((1, 1));
+        let m = Memory::new(test_map(5));
+        let zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        assert_eq!(zmachine.standard_version(), (1, 0));
+    }
+
+    #[test]
+    fn test_input_interrupt_print() {
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(!zmachine.input_interrupt_print());
+        zmachine.set_input_interrupt_print();
+        assert!(zmachine.input_interrupt_print());
+        zmachine.clear_input_interrupt_print();
+        assert!(!zmachine.input_interrupt_print());
     }
 
     #[test]
@@ -1111,99 +2728,346 @@ mod tests {
     }
 
     #[test]
-    fn test_write_word_transcript_1() {
+    fn test_transcript_reenable_appends() {
         let mut map = test_map(3);
-        for (i, b) in (0x40..0x800).enumerate() {
-            map[i + 0x40] = b as u8;
-        }
-
+        map[0x02] = 0;
+        map[0x03] = 42;
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '3', '.', 't', 'x',
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '6', '.', 't', 'x',
             't',
         ]);
-        let f1 = assert_ok!(zmachine.read_word(0x10));
-        assert!(zmachine.write_word(0x10, f1 | 1).is_ok());
-        assert!(Path::new("test-z3.txt").exists());
-        assert!(fs::remove_file("test-z3.txt").is_ok());
-        assert_ok_eq!(zmachine.read_word(0x10), f1 | 1);
+        let f1 = assert_ok!(zmachine.read_byte(0x11));
+        assert!(zmachine.write_byte(0x11, f1 | 1).is_ok());
+        assert!(zmachine.print_str("first\r".to_string()).is_ok());
+        assert!(zmachine.write_byte(0x11, f1).is_ok());
+        assert!(zmachine.print_str("hidden\r".to_string()).is_ok());
+        assert!(zmachine.write_byte(0x11, f1 | 1).is_ok());
+        assert!(zmachine.print_str("second\r".to_string()).is_ok());
+        let text = fs::read_to_string("test-z6.txt");
+        assert!(fs::remove_file("test-z6.txt").is_ok());
+        let text = assert_ok!(text);
+        let lines: Vec<&str> = text.split('\n').collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("--- Transcript started "));
+        assert!(lines[0].ends_with(", test release 42 ---"));
+        assert_eq!(lines[1], "first");
+        assert_eq!(lines[2], "");
+        assert!(lines[3].starts_with("--- Transcript resumed "));
+        assert_eq!(lines[4], "second");
+    }
+
+    #[test]
+    fn test_transcript_existing_file_appends() {
+        assert!(fs::write("test-z7.txt", "earlier\n").is_ok());
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '7', '.', 't', 'x',
+            't',
+        ]);
+        assert!(zmachine.output_stream(2, None).is_ok());
+        assert!(zmachine.print_str("later\r".to_string()).is_ok());
+        let text = fs::read_to_string("test-z7.txt");
+        assert!(fs::remove_file("test-z7.txt").is_ok());
+        let text = assert_ok!(text);
+        let lines: Vec<&str> = text.split('\n').collect();
+        assert_eq!(lines[0], "earlier");
+        assert_eq!(lines[1], "");
+        assert!(lines[2].starts_with("--- Transcript resumed "));
+        assert_eq!(lines[3], "later");
+    }
+
+    #[test]
+    fn test_start_transcript() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("session.txt");
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.start_transcript(&path).is_ok());
+        assert_ok_eq!(header::flag2(&zmachine.state, Flags2::Transcripting), 1);
+        assert!(zmachine.print_str("hello\r".to_string()).is_ok());
+
+        // The game turns transcripting off and on again, which reopens the same file
+        assert!(zmachine.output_stream(-2, None).is_ok());
+        assert!(!zmachine.io.is_stream_2_open());
+        assert!(zmachine.print_str("hidden\r".to_string()).is_ok());
+        let flags = assert_ok!(zmachine.read_byte(0x11));
+        assert!(zmachine.write_byte(0x11, flags | 1).is_ok());
         assert!(zmachine.io.is_stream_enabled(2));
+        assert!(zmachine.print_str("again\r".to_string()).is_ok());
+
+        assert!(zmachine.stop_transcript().is_ok());
+        assert_ok_eq!(header::flag2(&zmachine.state, Flags2::Transcripting), 0);
+        assert!(!zmachine.io.is_stream_enabled(2));
+        assert!(!zmachine.io.is_stream_2_open());
+        assert!(zmachine.print_str("after\r".to_string()).is_ok());
+
+        // No file name was asked for
+        assert!(filename_request().is_none());
+        let text = assert_ok!(fs::read_to_string(&path));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("--- Transcript started "));
+        assert_eq!(lines[1], "hello");
+        assert_eq!(lines[2], "");
+        assert!(lines[3].starts_with("--- Transcript resumed "));
+        assert_eq!(lines[4], "again");
+    }
+
+    #[test]
+    fn test_start_transcript_error() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("missing").join("session.txt");
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let e = zmachine
+            .start_transcript(&path)
+            .expect_err("expected a file error");
+        assert_eq!(e.code(), ErrorCode::FileError);
+        assert_ok_eq!(header::flag2(&zmachine.state, Flags2::Transcripting), 0);
+        assert!(!zmachine.io.is_stream_enabled(2));
+        assert!(zmachine.transcript_path.is_none());
     }
 
     #[test]
-    fn test_write_word_transcript_0() {
-        let mut map = test_map(3);
-        for (i, b) in (0x40..0x800).enumerate() {
-            map[i + 0x40] = b as u8;
-        }
-
+    fn test_run_transcript() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("session.txt");
+        let mut map = test_map(5);
+        // PRINT "hi", QUIT
+        map[0x400] = 0xB2;
+        map[0x401] = 0xB5;
+        map[0x402] = 0xC5;
+        map[0x403] = 0xBA;
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.start_transcript(&path).is_ok());
+        input(&[' ']);
+        assert!(zmachine.run().is_ok());
+        let text = assert_ok!(fs::read_to_string(&path));
+        assert!(text.starts_with("--- Transcript started "));
+        assert!(text.lines().nth(1).is_some_and(|l| l.starts_with("hi")));
+        assert!(filename_request().is_none());
+    }
+
+    #[test]
+    fn test_new_transcript_bit_set() {
+        let mut map = test_map(3);
+        map[0x11] = 0x01;
         input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '4', '.', 't', 'x',
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '9', '.', 't', 'x',
             't',
         ]);
-        let f1 = assert_ok!(zmachine.read_word(0x11));
-        assert!(zmachine.write_word(0x11, f1 | 0x100).is_ok());
-        assert!(Path::new("test-z4.txt").exists());
-        assert!(fs::remove_file("test-z4.txt").is_ok());
+        let zmachine = assert_ok!(ZMachine::new(
+            Memory::new(map),
+            Config::default(),
+            None,
+            "test"
+        ));
+        assert!(Path::new("test-z9.txt").exists());
+        assert!(fs::remove_file("test-z9.txt").is_ok());
         assert!(zmachine.io.is_stream_enabled(2));
-        assert!(zmachine.write_word(0x11, f1).is_ok());
-        assert_ok_eq!(zmachine.read_word(0x11), f1);
-        assert!(!zmachine.io.is_stream_enabled(2));
+        assert_ok_eq!(zmachine.read_byte(0x11), 0x01);
     }
 
     #[test]
-    fn test_write_word_0x10_transcript_1_error() {
+    fn test_new_transcript_bit_set_error() {
         let mut map = test_map(3);
-        for (i, b) in (0x40..0x800).enumerate() {
-            map[i + 0x40] = b as u8;
-        }
-
-        let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        map[0x11] = 0x01;
         input(&[
             '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}',
             '\u{08}', '\u{08}', '\u{08}', '/', 'x', '/', 'f', 'o', 'o',
         ]);
-        let f1 = assert_ok!(zmachine.read_word(0x10));
-        assert!(zmachine.write_word(0x10, f1 | 1).is_ok());
-        assert_ok_eq!(zmachine.read_word(0x10), f1);
+        let zmachine = assert_ok!(ZMachine::new(
+            Memory::new(map),
+            Config::default(),
+            None,
+            "test"
+        ));
         assert!(!zmachine.io.is_stream_enabled(2));
+        assert_ok_eq!(zmachine.read_byte(0x11), 0);
     }
 
     #[test]
-    fn test_write_word_0x11_transcript_1_error() {
-        let mut map = test_map(3);
+    fn test_write_byte_clear_request_redraw() {
+        let mut map = test_map(5);
         for (i, b) in (0x40..0x800).enumerate() {
             map[i + 0x40] = b as u8;
         }
+        map[0x10] = 0;
+        map[0x11] = 0;
 
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}',
-            '\u{08}', '\u{08}', '\u{08}', '/', 'x', '/', 'f', 'o', 'o',
-        ]);
-        let f1 = assert_ok!(zmachine.read_word(0x11));
-        assert!(zmachine.write_word(0x11, f1 | 0x100).is_ok());
-        assert_ok_eq!(zmachine.read_word(0x11), f1);
+        assert!(zmachine.resize(Capabilities::default()).is_ok());
+        let f1 = assert_ok!(zmachine.read_byte(0x11));
+        assert_eq!(f1 & 0x04, 0x04);
+        // Clearing the redraw bit should not start or stop a transcript
+        assert!(zmachine.write_byte(0x11, f1 & !0x04).is_ok());
+        assert_ok_eq!(zmachine.read_byte(0x11), f1 & !0x04);
+        assert!(!zmachine.io.is_stream_2_open());
         assert!(!zmachine.io.is_stream_enabled(2));
+        assert!(errors().is_empty());
     }
 
-    #[test]
-    fn test_variable() {
-        let mut map = test_map(3);
+    fn fixed_pitch_zmachine(flags2: u8) -> ZMachine {
+        let mut map = test_map(5);
         for (i, b) in (0x40..0x800).enumerate() {
             map[i + 0x40] = b as u8;
         }
-        mock_routine(&mut map, 0x600, &[0x1122, 0x3344, 0x5566]);
+        map[0x10] = 0;
+        map[0x11] = flags2;
+
+        let m = Memory::new(map);
+        assert_ok!(ZMachine::new(m, Config::default(), None, "test"))
+    }
+
+    #[test]
+    fn test_new_fixed_pitch_bit() {
+        let zmachine = fixed_pitch_zmachine(0x02);
+        assert!(zmachine.io.is_fixed_pitch());
+        let zmachine = fixed_pitch_zmachine(0);
+        assert!(!zmachine.io.is_fixed_pitch());
+    }
+
+    #[test]
+    fn test_write_byte_fixed_pitch_bit() {
+        let mut zmachine = fixed_pitch_zmachine(0);
+        assert!(zmachine.write_byte(0x11, 0x02).is_ok());
+        assert!(zmachine.io.is_fixed_pitch());
+        assert_ok_eq!(zmachine.read_byte(0x11), 0x02);
+        // The transcript bit is unaffected
+        assert!(!zmachine.io.is_stream_enabled(2));
+        assert!(zmachine.write_byte(0x11, 0).is_ok());
+        assert!(!zmachine.io.is_fixed_pitch());
+        // Writes to other addresses are ignored
+        assert!(zmachine.write_byte(0x10, 0x02).is_ok());
+        assert!(!zmachine.io.is_fixed_pitch());
+    }
+
+    #[test]
+    fn test_write_word_fixed_pitch_bit() {
+        let mut zmachine = fixed_pitch_zmachine(0);
+        assert!(zmachine.write_word(0x10, 0x0002).is_ok());
+        assert!(zmachine.io.is_fixed_pitch());
+        assert!(zmachine.write_word(0x10, 0x0200).is_ok());
+        assert!(!zmachine.io.is_fixed_pitch());
+        assert!(zmachine.write_word(0x11, 0x0200).is_ok());
+        assert!(zmachine.io.is_fixed_pitch());
+        assert_ok_eq!(zmachine.read_byte(0x11), 0x02);
+        assert!(zmachine.write_word(0x11, 0x0002).is_ok());
+        assert!(!zmachine.io.is_fixed_pitch());
+        assert!(!zmachine.io.is_stream_enabled(2));
+    }
+
+    #[test]
+    fn test_fixed_pitch_bit_and_style() {
+        let mut zmachine = fixed_pitch_zmachine(0x02);
+        assert!(zmachine.set_text_style(Style::Fixed as u16).is_ok());
+        assert!(zmachine.write_byte(0x11, 0).is_ok());
+        // Still fixed pitch from the text style
+        assert!(zmachine.io.is_fixed_pitch());
+        assert!(zmachine.set_text_style(0).is_ok());
+        assert!(!zmachine.io.is_fixed_pitch());
+    }
+
+    #[test]
+    fn test_write_word_transcript_1() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '3', '.', 't', 'x',
+            't',
+        ]);
+        let f1 = assert_ok!(zmachine.read_word(0x10));
+        assert!(zmachine.write_word(0x10, f1 | 1).is_ok());
+        assert!(Path::new("test-z3.txt").exists());
+        assert!(fs::remove_file("test-z3.txt").is_ok());
+        assert_ok_eq!(zmachine.read_word(0x10), f1 | 1);
+        assert!(zmachine.io.is_stream_enabled(2));
+    }
+
+    #[test]
+    fn test_write_word_transcript_0() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '4', '.', 't', 'x',
+            't',
+        ]);
+        let f1 = assert_ok!(zmachine.read_word(0x11));
+        assert!(zmachine.write_word(0x11, f1 | 0x100).is_ok());
+        assert!(Path::new("test-z4.txt").exists());
+        assert!(fs::remove_file("test-z4.txt").is_ok());
+        assert!(zmachine.io.is_stream_enabled(2));
+        assert!(zmachine.write_word(0x11, f1).is_ok());
+        assert_ok_eq!(zmachine.read_word(0x11), f1);
+        assert!(!zmachine.io.is_stream_enabled(2));
+    }
+
+    #[test]
+    fn test_write_word_0x10_transcript_1_error() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}',
+            '\u{08}', '\u{08}', '\u{08}', '/', 'x', '/', 'f', 'o', 'o',
+        ]);
+        let f1 = assert_ok!(zmachine.read_word(0x10));
+        assert!(zmachine.write_word(0x10, f1 | 1).is_ok());
+        assert_ok_eq!(zmachine.read_word(0x10), f1);
+        assert!(!zmachine.io.is_stream_enabled(2));
+    }
+
+    #[test]
+    fn test_write_word_0x11_transcript_1_error() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}',
+            '\u{08}', '\u{08}', '\u{08}', '/', 'x', '/', 'f', 'o', 'o',
+        ]);
+        let f1 = assert_ok!(zmachine.read_word(0x11));
+        assert!(zmachine.write_word(0x11, f1 | 0x100).is_ok());
+        assert_ok_eq!(zmachine.read_word(0x11), f1);
+        assert!(!zmachine.io.is_stream_enabled(2));
+    }
+
+    #[test]
+    fn test_variable() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        mock_routine(&mut map, 0x600, &[0x1122, 0x3344, 0x5566]);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine
             .state
-            .call_routine(0x600, &vec![0x8888], None, 0x400)
+            .call_routine(0x600, &[0x8888], None, 0x400)
             .is_ok());
         assert!(zmachine.push(0x1234).is_ok());
         assert!(zmachine.push(0x5678).is_ok());
@@ -1217,6 +3081,81 @@ mod tests {
         assert_ok_eq!(zmachine.variable(0x80), 0xE0E1);
     }
 
+    #[test]
+    fn test_poke_byte() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.poke_byte(0x40, 0x12).is_ok());
+        assert!(zmachine.poke_byte(0x3FF, 0x34).is_ok());
+        assert_ok_eq!(zmachine.read_byte(0x40), 0x12);
+        assert_ok_eq!(zmachine.read_byte(0x3FF), 0x34);
+        // The header is off limits, even where the game may write
+        let e = zmachine.poke_byte(0x11, 0x01).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::IllegalMemoryAccess);
+        assert!(e.is_recoverable());
+        assert_ok_eq!(zmachine.read_byte(0x11), 0);
+        assert!(!zmachine.io.is_stream_enabled(2));
+        // As is static memory
+        assert!(zmachine.poke_byte(0x400, 0x56).is_err());
+        assert_ok_eq!(zmachine.read_byte(0x400), 0);
+    }
+
+    #[test]
+    fn test_poke_word() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.poke_word(0x40, 0x1234).is_ok());
+        assert!(zmachine.poke_word(0x3FE, 0x5678).is_ok());
+        assert_ok_eq!(zmachine.read_word(0x40), 0x1234);
+        assert_ok_eq!(zmachine.read_word(0x3FE), 0x5678);
+        assert!(zmachine.poke_word(0x10, 0x0001).is_err());
+        assert!(zmachine.poke_word(0x3F, 0x9abc).is_err());
+        assert_ok_eq!(zmachine.read_word(0x3E), 0);
+        assert!(zmachine.poke_word(0x3FF, 0x9abc).is_err());
+    }
+
+    #[test]
+    fn test_set_global_status_line() {
+        let mut map = test_map(3);
+        // Status Object
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        // An empty dictionary at $0340
+        map[0x08] = 0x03;
+        map[0x09] = 0x40;
+        map[0x341] = 7;
+        // Text and parse buffers
+        map[0x380] = 11;
+        map[0x3A0] = 2;
+        // SREAD #$0380 #$03A0, SHOW_STATUS, QUIT
+        map[0x400..0x408].copy_from_slice(&[0xE4, 0x0F, 0x03, 0x80, 0x03, 0xA0, 0xBC, 0xBA]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_global(0, 1).is_ok());
+        assert!(zmachine.set_global(1, 10).is_ok());
+        assert!(zmachine.set_global(2, 4).is_ok());
+        assert!(matches!(
+            assert_ok!(zmachine.advance(None)),
+            Advance::NeedInput { .. }
+        ));
+        assert!(print().ends_with(&format!(" Status Object{}10/4 ", " ".repeat(61))));
+
+        // A tool changes the score while the game waits for input
+        assert!(zmachine.set_global(1, 50).is_ok());
+        assert_ok_eq!(zmachine.global(1), 50);
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char(0x0d))),
+            Advance::Quit
+        );
+        assert!(print().contains(&format!(" Status Object{}50/4 ", " ".repeat(61))));
+    }
+
     #[test]
     fn test_peek_variable() {
         let mut map = test_map(3);
@@ -1228,7 +3167,7 @@ mod tests {
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine
             .state
-            .call_routine(0x600, &vec![0x8888], None, 0x400)
+            .call_routine(0x600, &[0x8888], None, 0x400)
             .is_ok());
         assert!(zmachine.push(0x1234).is_ok());
         assert!(zmachine.push(0x5678).is_ok());
@@ -1252,7 +3191,7 @@ mod tests {
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine
             .state
-            .call_routine(0x600, &vec![0x8888], None, 0x400)
+            .call_routine(0x600, &[0x8888], None, 0x400)
             .is_ok());
         assert!(zmachine.set_variable(0, 0x1234).is_ok());
         assert!(zmachine.set_variable(0, 0x5678).is_ok());
@@ -1282,7 +3221,7 @@ mod tests {
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine
             .state
-            .call_routine(0x600, &vec![0x8888], None, 0x400)
+            .call_routine(0x600, &[0x8888], None, 0x400)
             .is_ok());
         assert!(zmachine.set_variable_indirect(0, 0x1234).is_err());
         assert!(zmachine.push(0).is_ok());
@@ -1312,7 +3251,7 @@ mod tests {
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine
             .state
-            .call_routine(0x600, &vec![0x8888], None, 0x400)
+            .call_routine(0x600, &[0x8888], None, 0x400)
             .is_ok());
         assert!(zmachine.push(0x1234).is_ok());
         assert!(zmachine.push(0x5678).is_ok());
@@ -1459,7 +3398,7 @@ mod tests {
         }
         let m = Memory::new(map);
         let zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert_eq!(
+        assert_ok_eq!(
             zmachine.instruction(0x400),
             &[
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
@@ -1478,7 +3417,7 @@ mod tests {
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert_eq!(zmachine.frame_count(), 1);
-        assert!(zmachine.call_routine(0x400, &vec![], None, 0x500).is_ok());
+        assert!(zmachine.call_routine(0x400, &[], None, 0x500).is_ok());
         assert_eq!(zmachine.frame_count(), 2);
     }
 
@@ -1514,7 +3453,7 @@ mod tests {
         mock_routine(&mut map, 0x600, &[]);
         let m = Memory::new(map.clone());
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.call_routine(0x600, &vec![], None, 0x500).is_ok());
+        assert!(zmachine.call_routine(0x600, &[], None, 0x500).is_ok());
         // See state.rs tests ... change dynamic memory a little bit
         assert!(zmachine.write_byte(0x200, 0xFC).is_ok());
         assert!(zmachine.write_byte(0x280, 0x10).is_ok());
@@ -1531,13 +3470,15 @@ mod tests {
         assert_ok_eq!(
             d,
             [
-                b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x50, b'I', b'F', b'Z', b'S', b'I', b'F',
+                b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x6C, b'I', b'F', b'Z', b'S', b'I', b'F',
                 b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
                 0x56, 0x78, 0x00, 0x98, 0x76, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x17,
                 0x00, 0x00, 0x20, 0x00, 0x1B, 0x06, 0x5A, 0x00, 0x11, 0x01, 0x00, 0xFF, 0x00, 0xCC,
                 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00, b'S', b't', b'k', b's',
                 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
-                0x00, 0x10, 0x00, 0x00, 0x00, 0x00
+                0x00, 0x10, 0x00, 0x00, 0x00, 0x00, b'I', b'n', b't', b'D', 0x00, 0x00, 0x00, 0x14,
+                b' ', b' ', b' ', b' ', 0x00, 0x00, 0x00, 0x00, b'M', b'X', b'Y', b'Z', 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00
             ]
         );
     }
@@ -1570,13 +3511,14 @@ mod tests {
         assert!(header::set_flag2(&mut zmachine.state, Flags2::Transcripting).is_ok());
 
         let restore_data = vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ];
         let mut file = assert_ok!(fs::OpenOptions::new()
             .create(true)
@@ -1585,16 +3527,21 @@ mod tests {
             .open("test-z3.ifzs"));
         assert!(file.write_all(&restore_data).is_ok());
         assert!(file.flush().is_ok());
+        // The transcript bit was set without starting stream 2, so the transcript file
+        // is prompted for after the restore
         input(&[
             '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-', 'z', '3', '.', 'i', 'f', 'z',
-            's',
+            's', '\r', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '8', '.',
+            't', 'x', 't',
         ]);
         assert_eq!(zmachine.frame_count(), 1);
         let r = zmachine.restore();
         assert!(fs::remove_file("test-z3.ifzs").is_ok());
+        assert!(fs::remove_file("test-z8.txt").is_ok());
         let pc = assert_ok!(r);
-        assert_some_eq!(pc, 0x9abc);
+        assert_some_eq!(pc, Restored::Save(0x9abc));
         assert_ok_eq!(header::flag2(&zmachine.state, Flags2::Transcripting), 1);
+        assert!(zmachine.io.is_stream_enabled(2));
         assert_ok_eq!(
             header::field_byte(&zmachine.state, HeaderField::DefaultForeground),
             3
@@ -1614,7 +3561,46 @@ mod tests {
         assert_ok_eq!(zmachine.read_byte(0x200), 0xFC);
         assert_ok_eq!(zmachine.read_byte(0x280), 0x10);
         assert_ok_eq!(zmachine.read_byte(0x300), 0xFD);
-        assert_eq!(zmachine.frame_count(), 2);
+        assert_eq!(zmachine.frame_count(), 3);
+    }
+
+    #[test]
+    fn test_restore_abandons_stream_3() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let save = dir.path().join("stream3.ifzs");
+        set_filename_response(save.to_str().unwrap());
+        let mut map = test_map(5);
+        map[0x300] = 0x12;
+        map[0x301] = 0x34;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.save(0x401).is_ok());
+
+        assert!(zmachine.output_stream(3, Some(0x300)).is_ok());
+        assert!(zmachine.print_str("captured".to_string()).is_ok());
+        assert!(zmachine.io.buffer_mode(0).is_ok());
+        assert!(zmachine.io.split_window(2).is_ok());
+        assert_some_eq!(assert_ok!(zmachine.restore()), Restored::Save(0x401));
+        assert!(!zmachine.io.is_stream_enabled(3));
+        assert_ok_eq!(zmachine.read_word(0x300), 0x1234);
+        assert!(zmachine.io.snapshot().buffered);
+        assert!(!zmachine.io.snapshot().split);
+
+        // Closing the capture the game opened before the restore writes nothing either
+        assert!(zmachine.output_stream(-3, None).is_ok());
+        assert_ok_eq!(zmachine.read_word(0x300), 0x1234);
+    }
+
+    #[test]
+    fn test_restore_undo_abandons_stream_3() {
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.save_undo(0x401).is_ok());
+        assert!(zmachine.output_stream(3, Some(0x300)).is_ok());
+        assert!(zmachine.print_str("captured".to_string()).is_ok());
+        assert_some_eq!(assert_ok!(zmachine.restore_undo()), 0x401);
+        assert!(!zmachine.io.is_stream_enabled(3));
+        assert_ok_eq!(zmachine.read_word(0x300), 0);
     }
 
     #[test]
@@ -1703,7 +3689,7 @@ mod tests {
         let m = Memory::new(map.clone());
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert_eq!(zmachine.frame_count(), 1);
-        assert_ok_eq!(zmachine.call_routine(0x600, &vec![], None, 0x500), 0x605);
+        assert_ok_eq!(zmachine.call_routine(0x600, &[], None, 0x500), 0x605);
         assert_eq!(zmachine.frame_count(), 2);
     }
 
@@ -1731,6 +3717,9 @@ mod tests {
         zmachine.state.set_read_interrupt();
         assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x500), 0x605);
         assert_eq!(zmachine.frame_count(), 2);
+        // The result belongs to the interrupted read, in the caller
+        assert!(zmachine.read_interrupt_result().is_none());
+        assert_ok_eq!(zmachine.return_routine(0), 0x500);
         assert_some_eq!(zmachine.state.read_interrupt_result(), 0);
         // Test clear_read_interrupt() clears the state read_interrupt_result
         // because it's convenient to do so here
@@ -1782,11 +3771,11 @@ mod tests {
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert_ok_eq!(
-            zmachine.call_routine(0x500, &vec![0x1111, 0x2222, 0x3333], None, 0x40B),
+            zmachine.call_routine(0x500, &[0x1111, 0x2222, 0x3333], None, 0x40B),
             0x501
         );
         assert_ok_eq!(
-            zmachine.call_routine(0x600, &vec![], Some(StoreResult::new(0x40A, 2)), 0x50B),
+            zmachine.call_routine(0x600, &[], Some(StoreResult::new(0x40A, 2)), 0x50B),
             0x601
         );
         assert_eq!(zmachine.frame_count(), 3);
@@ -1803,12 +3792,12 @@ mod tests {
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert_ok_eq!(
-            zmachine.call_routine(0x500, &vec![0x1111, 0x2222, 0x3333], None, 0x40B),
+            zmachine.call_routine(0x500, &[0x1111, 0x2222, 0x3333], None, 0x40B),
             0x501
         );
         zmachine.set_read_interrupt_pending();
         assert_ok_eq!(zmachine.call_read_interrupt(0x600, 0x50B), 0x601);
-        assert_some_eq!(zmachine.state.read_interrupt_result(), 0);
+        assert!(zmachine.state.read_interrupt_result().is_none());
         assert_eq!(zmachine.frame_count(), 3);
         assert_ok_eq!(zmachine.return_routine(0x1234), 0x50B);
         assert_eq!(zmachine.frame_count(), 2);
@@ -1826,13 +3815,13 @@ mod tests {
         assert_ok_eq!(
             zmachine.call_routine(
                 0x500,
-                &vec![0x1111, 0x2222, 0x3333],
+                &[0x1111, 0x2222, 0x3333],
                 Some(StoreResult::new(0x40A, 0)),
                 0x40B
             ),
             0x501
         );
-        assert_ok_eq!(zmachine.call_routine(0x600, &vec![], None, 0x50B), 0x601);
+        assert_ok_eq!(zmachine.call_routine(0x600, &[], None, 0x50B), 0x601);
         assert_eq!(zmachine.frame_count(), 3);
         assert_ok_eq!(zmachine.throw(2, 0x1234), 0x40B);
         assert_eq!(zmachine.frame_count(), 1);
@@ -1911,6 +3900,28 @@ mod tests {
         assert_eq!(zmachine.random(100), 76);
     }
 
+    #[test]
+    fn test_random_config_seed() {
+        for seed_mode in [SeedMode::Predictable, SeedMode::Seeded] {
+            let mut sequences = Vec::new();
+            for _ in 0..2 {
+                let mut config = Config::default();
+                config.set_predictable_seed(1234);
+                let m = Memory::new(test_map(3));
+                let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+                zmachine.seed_mode = seed_mode;
+                zmachine.seed_rng();
+                let mut sequence: Vec<u16> = (0..10).map(|_| zmachine.random(100)).collect();
+                assert!(zmachine.restart().is_ok());
+                let restarted: Vec<u16> = (0..10).map(|_| zmachine.random(100)).collect();
+                assert_eq!(sequence, restarted);
+                sequence.extend(restarted);
+                sequences.push(sequence);
+            }
+            assert_eq!(sequences[0], sequences[1]);
+        }
+    }
+
     #[test]
     fn test_random_predictable() {
         let map = test_map(3);
@@ -2110,6 +4121,7 @@ mod tests {
         assert!(zmachine
             .print(&vec![b'T' as u16, b'e' as u16, b's' as u16, b't' as u16])
             .is_ok(),);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Test");
     }
 
@@ -2124,7 +4136,11 @@ mod tests {
         assert!(zmachine
             .print(&vec![b'T' as u16, b'e' as u16, b's' as u16, b't' as u16])
             .is_ok(),);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Test");
+        // Noted for the interrupted read, to print its input again
+        assert!(!zmachine.input_interrupt_print());
+        assert_ok_eq!(zmachine.return_routine(0), 0x500);
         assert!(zmachine.input_interrupt_print());
     }
 
@@ -2134,6 +4150,7 @@ mod tests {
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         assert!(zmachine.print_str("Test".to_string()).is_ok(),);
+        assert!(zmachine.flush().is_ok());
         assert_print!("Test");
     }
 
@@ -2170,7 +4187,7 @@ mod tests {
         let map = test_map(4);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.erase_line().is_ok());
+        assert!(zmachine.erase_line(1).is_ok());
         assert!(erase_line());
     }
 
@@ -2195,7 +4212,73 @@ mod tests {
         assert!(header::clear_flag1(&mut zmachine.state, Flags1v3::StatusLineType as u8).is_ok());
         assert!(zmachine.status_line().is_ok());
         assert_print!(
-            " Status Object                                                         -99/0    "
+            " Status Object                                                            -99/0 "
+        );
+    }
+
+    #[test]
+    fn test_status_line_short_name_cached() {
+        let mut map = test_map(3);
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_variable(16, 1).is_ok());
+        assert!(header::clear_flag1(&mut zmachine.state, Flags1v3::StatusLineType as u8).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_eq!(
+            zmachine.state.short_name_stats(),
+            CacheStats { hits: 2, misses: 1 }
+        );
+    }
+
+    #[test]
+    fn test_status_line_short_name_invalidated() {
+        let mut map = test_map(3);
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_variable(16, 1).is_ok());
+        assert!(header::clear_flag1(&mut zmachine.state, Flags1v3::StatusLineType as u8).is_ok());
+        let ranges = assert_ok!(property::short_name_ranges(&zmachine, 1));
+        assert!(zmachine.status_line().is_ok());
+        // "Status Object" -> "Status Objecu"
+        assert!(zmachine.write_word(ranges[1].end - 2, 0xA91A).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_eq!(
+            zmachine.state.short_name_stats(),
+            CacheStats { hits: 0, misses: 2 }
+        );
+        assert_print!(&format!(
+            "{}{}",
+            " Status Object                                                              0/0 ",
+            " Status Objecu                                                              0/0 "
+        ));
+        // Writes elsewhere leave the cache alone
+        assert!(zmachine.write_byte(ranges[1].end, 0).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_eq!(
+            zmachine.state.short_name_stats(),
+            CacheStats { hits: 1, misses: 2 }
+        );
+        // Moving the property table pointer invalidates the cache
+        let pointer = assert_ok!(zmachine.read_word(ranges[0].start));
+        assert!(zmachine.write_word(ranges[0].start, pointer).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_eq!(
+            zmachine.state.short_name_stats(),
+            CacheStats { hits: 1, misses: 3 }
         );
     }
 
@@ -2224,6 +4307,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_status_line_score_40_columns() {
+        set_size(24, 40);
+        let mut map = test_map(3);
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_variable(16, 1).is_ok());
+        assert!(zmachine.set_variable(17, 999).is_ok());
+        assert!(zmachine.set_variable(18, 9999).is_ok());
+        assert!(header::clear_flag1(&mut zmachine.state, Flags1v3::StatusLineType as u8).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_print!(" Status Object                 999/9999 ");
+    }
+
+    #[test]
+    fn test_status_line_score_132_columns() {
+        set_size(24, 132);
+        let mut map = test_map(3);
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_variable(16, 1).is_ok());
+        assert!(zmachine.set_variable(17, 999).is_ok());
+        assert!(zmachine.set_variable(18, 9999).is_ok());
+        assert!(header::clear_flag1(&mut zmachine.state, Flags1v3::StatusLineType as u8).is_ok());
+        assert!(zmachine.status_line().is_ok());
+        assert_print!(&format!(" Status Object{}999/9999 ", " ".repeat(109)));
+    }
+
     #[test]
     fn test_status_line_time_12_am() {
         let mut map = test_map(3);
@@ -2402,6 +4525,16 @@ mod tests {
         assert_ok_eq!(zmachine.read_key(0), InputEvent::from_char(' ' as u16));
     }
 
+    #[test]
+    fn test_read_key_editing_keys() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\u{17}', '\u{15}', 'a']);
+        let key = assert_ok!(zmachine.read_key(0));
+        assert_some_eq!(key.zchar(), b'a' as u16);
+    }
+
     #[test]
     fn test_read_key_with_timeout() {
         let map = test_map(5);
@@ -2409,7 +4542,7 @@ mod tests {
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
         input(&[' ']);
         set_input_delay(50);
-        assert_ok_eq!(zmachine.read_key(100), InputEvent::from_char(' ' as u16));
+        assert_ok_eq!(zmachine.read_key(1), InputEvent::from_char(' ' as u16));
     }
 
     #[test]
@@ -2420,7 +4553,7 @@ mod tests {
         input(&[' ']);
         set_input_timeout();
         assert_ok_eq!(
-            zmachine.read_key(100),
+            zmachine.read_key(1),
             InputEvent::from_interrupt(Interrupt::ReadTimeout)
         );
     }
@@ -2442,12 +4575,36 @@ mod tests {
     }
 
     #[test]
-    fn test_read_key_mouse_click() {
-        let mut map = test_map(5);
-        map[0x101] = 2;
-        let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(header::set_word(&mut zmachine.state, HeaderField::ExtensionTable, 0x100).is_ok());
+    fn test_play_sound_device_lost() {
+        let m = Memory::new(test_map(5));
+        let manager = assert_ok!(Manager::mock_failing(3, false));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(3, 8, 1, Some(0x600)).is_ok());
+        assert!(zmachine.is_sound_playing());
+        assert_some_eq!(zmachine.sound_interrupt(), 0x600);
+
+        // The device goes away while the sound is playing, so the interrupt fires
+        assert_ok_eq!(
+            zmachine.read_key(0),
+            InputEvent::from_interrupt(Interrupt::Sound)
+        );
+        assert!(!zmachine.is_sound_playing());
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert!(manager.is_degraded());
+
+        // Further sounds are ignored without an error or an interrupt
+        zmachine.state.clear_sound_interrupt();
+        assert!(zmachine.play_sound(4, 8, 1, Some(0x600)).is_ok());
+        assert!(zmachine.sound_interrupt().is_none());
+    }
+
+    #[test]
+    fn test_read_key_mouse_click() {
+        let mut map = test_map(5);
+        map[0x101] = 2;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(header::set_word(&mut zmachine.state, HeaderField::ExtensionTable, 0x100).is_ok());
         input(&['\u{FD}']);
         // test_terminal returns fixed mouse coordinates 12,18
         assert_ok_eq!(zmachine.read_key(0), InputEvent::from_mouse(0xFD, 18, 12));
@@ -2455,6 +4612,23 @@ mod tests {
         assert_ok_eq!(zmachine.read_word(0x104), 18);
     }
 
+    #[test]
+    fn test_read_key_mouse_click_short_extension_table() {
+        let mut map = test_map(5);
+        map[0x101] = 1;
+        map[0x102] = 0xAB;
+        map[0x104] = 0xCD;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(header::set_word(&mut zmachine.state, HeaderField::ExtensionTable, 0x100).is_ok());
+        input(&['\u{FD}']);
+        assert_ok_eq!(zmachine.read_key(0), InputEvent::from_mouse(0xFD, 18, 12));
+        // Nothing is stored in or after the table
+        assert_ok_eq!(zmachine.read_word(0x100), 1);
+        assert_ok_eq!(zmachine.read_word(0x102), 0xAB00);
+        assert_ok_eq!(zmachine.read_word(0x104), 0xCD00);
+    }
+
     #[test]
     fn test_read_key_mouse_double_click() {
         let mut map = test_map(5);
@@ -2493,545 +4667,2632 @@ mod tests {
     }
 
     #[test]
-    fn test_read_line_fn_terminator() {
+    fn test_read_line_resized() {
         let mut map = test_map(5);
-        map[0x101] = 2;
+        map[0x10] = 0;
+        map[0x11] = 0;
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(header::set_word(&mut zmachine.state, HeaderField::ExtensionTable, 0x100).is_ok());
-        input(&['T', 'e', 's', 't', 'i', 'n', 'g', 'x', '\u{08}', '\u{FD}']);
+        set_resized(40, 132);
+        input(&['\r']);
         assert_ok_eq!(
-            zmachine.read_line(&[], 16, &['\r' as u16, 255], 0),
-            [
-                b'T' as u16,
-                b'e' as u16,
-                b's' as u16,
-                b't' as u16,
-                b'i' as u16,
-                b'n' as u16,
-                b'g' as u16,
-                0xFD
-            ]
+            zmachine.read_line(&[], 6, &['\r' as u16], 0),
+            [b'\r' as u16]
         );
-        // The x is printed, even though it is erased by the backspace
-        assert_print!("Testingx");
-        assert_ok_eq!(zmachine.read_word(0x102), 12);
-        assert_ok_eq!(zmachine.read_word(0x104), 18);
+        assert_ok_eq!(zmachine.header_byte(HeaderField::ScreenLines), 40);
+        assert_ok_eq!(zmachine.header_byte(HeaderField::ScreenColumns), 132);
+        assert_ok_eq!(zmachine.header_word(HeaderField::ScreenHeight), 40);
+        assert_ok_eq!(zmachine.header_word(HeaderField::ScreenWidth), 132);
+        // The game is asked to redraw
+        assert_eq!(assert_ok!(zmachine.read_byte(0x11)) & 0x04, 0x04);
+        // The new size is kept for when the header is set up again
+        assert!(zmachine.restart().is_ok());
+        assert_ok_eq!(zmachine.header_byte(HeaderField::ScreenLines), 40);
+        assert_ok_eq!(zmachine.header_byte(HeaderField::ScreenColumns), 132);
     }
 
     #[test]
-    fn test_read_line_with_timeout() {
+    fn test_read_line_paste() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['T', 'e', 's', 't', 'i', 'n', 'g']);
+        input(&['n', '\r', 'u', 'p', '\r', 'x', '\r']);
         assert_ok_eq!(
-            zmachine.read_line(&[], 16, &['\r' as u16], 100),
-            [
-                b'T' as u16,
-                b'e' as u16,
-                b's' as u16,
-                b't' as u16,
-                b'i' as u16,
-                b'n' as u16,
-                b'g' as u16,
-                b'\r' as u16
-            ]
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'n' as u16, b'\r' as u16]
         );
-        assert_print!("Testing");
+        // The rest of the paste was read in one go and waits for the next READ
+        assert!(!input_pending());
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'u' as u16, b'p' as u16, b'\r' as u16]
+        );
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'x' as u16, b'\r' as u16]
+        );
+        assert_print!("nupx");
     }
+
     #[test]
-    fn test_read_line_timeout() {
+    fn test_read_line_volume_keys() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['T', 'e', 's', 't', 'i', 'n', 'g']);
-        set_input_delay(350);
-        let r = assert_ok!(zmachine.read_line(&[], 16, &['\r' as u16], 1000));
-        assert_eq!(r, [b'T' as u16, b'e' as u16, b's' as u16]);
-        assert_print!("Tes");
+        let manager = assert_ok!(Manager::mock());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(3, 8, 1, None).is_ok());
+        assert_eq!(effective_volume(), 0.0625);
+        // F12 turns the volume up without adding to the input
+        input(&['a', '\u{90}', 'b', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'a' as u16, b'b' as u16, b'\r' as u16]
+        );
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert_eq!(manager.volume_factor(), 102.4);
+        assert_eq!(effective_volume(), 8.0 / 102.4);
+        assert_some_eq!(message(), volume_message("102.40"));
+        // F11 turns it back down
+        input(&['\u{8f}', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'\r' as u16]
+        );
+        assert_eq!(effective_volume(), 0.0625);
+        assert_some_eq!(message(), volume_message("128.00"));
     }
 
     #[test]
-    fn test_read_line_sound_interrupt() {
+    fn test_step_volume_limits() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let manager = assert_ok!(Manager::new(5.0, mock_blorb()));
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        zmachine.set_sound_interrupt(0x1234);
-        let manager = assert_some!(zmachine.sound_manager.as_mut());
-        assert!(!manager.is_playing());
-        let input = assert_ok!(zmachine.read_line(&[], 16, &[b'\r' as u16], 0));
-        assert!(input.is_empty());
+        // Louder stops at the lowest factor
+        assert!(zmachine.step_volume(1.0 / VOLUME_STEP));
+        assert!(zmachine.step_volume(1.0 / VOLUME_STEP));
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert_eq!(manager.volume_factor(), VOLUME_FACTOR_MIN);
+        assert_some_eq!(message(), volume_message("4.00"));
+        // Quieter stops at the highest
+        for _ in 0..40 {
+            assert!(zmachine.step_volume(VOLUME_STEP));
+        }
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert_eq!(manager.volume_factor(), VOLUME_FACTOR_MAX);
+        assert_some_eq!(message(), volume_message("1024.00"));
     }
 
     #[test]
-    fn test_prompt_filename_first() {
+    fn test_read_line_volume_key_terminator() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
+        let manager = assert_ok!(Manager::mock());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        input(&['a', '\u{90}']);
         assert_ok_eq!(
-            zmachine.prompt_filename("Filename? ", "pf01", false, true),
-            "test-01.pf01"
+            zmachine.read_line(&[], 16, &['\r' as u16, 0x90], 0),
+            [b'a' as u16, 0x90]
         );
-        assert_print!("Filename? test-01.pf01");
+        input(&['b', '\u{8f}']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16, 255], 0),
+            [b'b' as u16, 0x8f]
+        );
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert_eq!(manager.volume_factor(), 128.0);
+        assert!(message().is_none());
     }
 
     #[test]
-    fn test_prompt_filename_first_existing() {
+    fn test_read_line_volume_keys_disabled() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pf02");
-        assert!(f.is_ok());
-        assert!(Path::new("test-01.pf02").exists());
-        let r = zmachine.prompt_filename("Filename? ", "pf02", false, true);
-        assert!(fs::remove_file("test-01.pf02").is_ok());
-        assert_ok_eq!(r, "test-02.pf02");
-        assert_print!("Filename? test-02.pf02");
+        let manager = assert_ok!(Manager::mock());
+        let mut config = Config::default();
+        config.set_volume_keys(None, None);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, Some(manager), "test"));
+        input(&['a', '\u{90}', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'a' as u16, b'\r' as u16]
+        );
+        let manager = assert_some!(zmachine.sound_manager.as_ref());
+        assert_eq!(manager.volume_factor(), 128.0);
+        assert!(message().is_none());
     }
 
+    // The menu tests set an input delay so keys arrive one at a time, as typed, and the
+    // menu reads its own key rather than taking it from buffered input
     #[test]
-    fn test_prompt_filename_first_last_none() {
+    fn test_read_line_menu_transcript() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let r = zmachine.prompt_filename("Filename? ", "pf03", true, false);
-        assert_ok_eq!(r, "test.pf03");
-        assert_print!("Filename? test.pf03");
+        set_filename_response("test-menu.txt");
+        // F1 opens the menu part way through the command, T turns the transcript on
+        set_input_delay(1);
+        input(&['l', 'o', '\u{85}', 't', 'o', 'k', '\r']);
+        let r = zmachine.read_line(&[], 16, &['\r' as u16], 0);
+        let transcript = fs::read_to_string("test-menu.txt");
+        assert!(fs::remove_file("test-menu.txt").is_ok());
+        assert_ok_eq!(
+            r,
+            [
+                b'l' as u16,
+                b'o' as u16,
+                b'o' as u16,
+                b'k' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_eq!(
+            assert_some!(menu()),
+            [
+                item('S', "menu.save"),
+                item('R', "menu.restore"),
+                item('N', "menu.restart"),
+                item('Q', "menu.quit"),
+                toggle('T', "menu.transcript", false),
+                item('I', "menu.statistics"),
+                String::new(),
+                text("menu.other_key")
+            ]
+        );
+        assert!(zmachine.io.is_stream_enabled(2));
+        assert_ok_eq!(zmachine.read_byte(0x11), 1);
+        assert_some_eq!(message(), text("status.transcript_on"));
+        // Input typed after the menu closed is transcribed
+        assert!(assert_ok!(transcript).ends_with("ok\n"));
+        // And off again
+        input(&['\u{85}', 'T', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'\r' as u16]
+        );
+        assert!(!zmachine.io.is_stream_enabled(2));
+        assert_some_eq!(message(), text("status.transcript_off"));
+    }
+
+    // AREAD text -> G00, QUIT, with room for 20 characters of input at $0300.  `text` is
+    // the operand for the text buffer, after the operand types byte.
+    fn menu_map(types: u8, text: &[u8]) -> Vec<u8> {
+        let mut map = test_map(5);
+        let mut program = vec![0xE4, types];
+        program.extend_from_slice(text);
+        program.extend_from_slice(&[0x00, 0x10, 0xBA]);
+        map[0x410..0x410 + program.len()].copy_from_slice(&program);
+        // JUMP to the READ
+        map[0x400..0x403].copy_from_slice(&[0x8C, 0x00, 0x0F]);
+        map[0x300] = 20;
+        map
     }
 
     #[test]
-    fn test_prompt_filename_first_last_existing() {
-        let map = test_map(5);
-        let m = Memory::new(map);
+    fn test_run_menu_quit() {
+        let m = Memory::new(menu_map(0x1F, &[0x03, 0x00]));
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pf04");
-        assert!(f.is_ok());
-        assert!(Path::new("test-01.pf04").exists());
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-02.pf04");
-        assert!(f.is_ok());
-        assert!(Path::new("test-02.pf04").exists());
-        let r = zmachine.prompt_filename("Filename? ", "pf04", true, false);
-        assert!(fs::remove_file("test-01.pf04").is_ok());
-        assert!(fs::remove_file("test-02.pf04").is_ok());
-        assert_ok_eq!(r, "test-02.pf04");
-        assert_print!("Filename? test-02.pf04");
+        let events = event_listener(&mut zmachine);
+        set_input_delay(1);
+        input(&['l', '\u{85}', 'q', ' ']);
+        assert!(zmachine.run().is_ok());
+        assert!(quit());
+        assert_eq!(events.borrow().last(), Some(&GameEvent::Quit));
+        // Nothing is typed into the game, and the READ doesn't store anything
+        assert_ok_eq!(zmachine.read_byte(0x301), 0);
+        assert_ok_eq!(zmachine.variable(0x10), 0);
     }
 
     #[test]
-    fn test_prompt_filename_overwrite_existing() {
-        let map = test_map(5);
-        let m = Memory::new(map);
+    fn test_run_menu_restart() {
+        let m = Memory::new(menu_map(0x1F, &[0x03, 0x00]));
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pf05");
-        assert!(f.is_ok());
-        assert!(Path::new("test-01.pf05").exists());
-        let r = zmachine.prompt_filename("Filename? ", "pf05", false, false);
-        assert!(fs::remove_file("test-01.pf05").is_ok());
-        assert!(r.is_err());
-        assert_print!("Filename? test-01.pf05");
+        let events = event_listener(&mut zmachine);
+        set_input_delay(1);
+        input(&['l', '\u{85}', 'n', 'x', '\r']);
+        assert!(zmachine.run().is_ok());
+        assert_eq!(events.borrow()[0], GameEvent::Restarted);
+        // Only the line typed after the restart is read
+        assert_ok_eq!(zmachine.read_byte(0x301), 1);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'x');
+        assert_ok_eq!(zmachine.variable(0x10), 0x0d);
     }
 
     #[test]
-    fn test_prompt_filename_invalid_filename_z() {
-        let map = test_map(5);
+    fn test_run_menu_save_restore() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let save = dir.path().join("menu.ifzs");
+        let save = save.to_str().unwrap();
+        let map = menu_map(0x1F, &[0x03, 0x00]);
+
+        let mut zmachine = assert_ok!(ZMachine::new(
+            Memory::new(map.clone()),
+            Config::default(),
+            None,
+            "test"
+        ));
+        assert!(zmachine.set_variable(0x12, 0x1234).is_ok());
+        set_filename_response(save);
+        set_input_delay(1);
+        input(&['l', '\u{85}', 's', 'o', '\r']);
+        assert!(zmachine.run().is_ok());
+        assert_some_eq!(message(), text("status.saved"));
+        // The line goes on after the save
+        assert_ok_eq!(zmachine.read_byte(0x301), 2);
+
+        // The save resumes at the READ
+        let mut zmachine = assert_ok!(ZMachine::new(
+            Memory::new(map.clone()),
+            Config::default(),
+            None,
+            "test"
+        ));
+        set_filename_response(save);
+        assert_some_eq!(assert_ok!(zmachine.restore()), Restored::Read(0x410));
+        assert_ok_eq!(zmachine.variable(0x12), 0x1234);
+
+        // Restoring from the menu reads the line again
+        let mut zmachine = assert_ok!(ZMachine::new(
+            Memory::new(map),
+            Config::default(),
+            None,
+            "test"
+        ));
+        set_filename_response(save);
+        input(&['l', '\u{85}', 'r', 'z', '\r']);
+        assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.variable(0x12), 0x1234);
+        assert_ok_eq!(zmachine.read_byte(0x301), 1);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'z');
+    }
+
+    #[test]
+    fn test_run_menu_save_stack_operand() {
+        // PUSH #0300 and JUMP to the READ, which takes the text buffer from the stack
+        let mut map = menu_map(0x9F, &[0x00]);
+        map[0x400..0x407].copy_from_slice(&[0xE8, 0x3F, 0x03, 0x00, 0x8C, 0x00, 0x0B]);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\u{08}', '\u{08}', '\u{08}', '\u{08}', 'z', '5', '\r']);
-        let r = zmachine.prompt_filename("Filename? ", "pf06", false, false);
-        assert!(r.is_err());
-        assert_print!("Filename? test.pf06z5");
+        set_input_delay(1);
+        input(&['\u{85}', 's', 'o', '\r']);
+        assert!(zmachine.run().is_ok());
+        // The save is refused, without asking for a file
+        assert_some_eq!(
+            message(),
+            "The game can't be saved from the menu while reading this input"
+        );
+        assert!(filename_request().is_none());
+        assert_ok_eq!(zmachine.read_byte(0x302), b'o');
     }
 
     #[test]
-    fn test_prompt_filename_invalid_filename_blb() {
+    fn test_read_line_menu_sound() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\u{08}', '\u{08}', '\u{08}', '\u{08}', 'b', 'l', 'b', '\r']);
-        let r = zmachine.prompt_filename("Filename? ", "pf06", false, false);
-        assert!(r.is_err());
-        assert_print!("Filename? test.pf06blb");
+        let manager = assert_ok!(Manager::mock());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(3, 8, 1, None).is_ok());
+        set_input_delay(1);
+        input(&['\u{85}', '+', '\u{85}', 'm', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'\r' as u16]
+        );
+        let items = assert_some!(menu());
+        assert!(items.contains(&toggle('M', "menu.sound", true)));
+        assert!(items.contains(&item('+', "menu.volume_up")));
+        let manager = assert_some!(zmachine.sound_manager.as_mut());
+        assert_eq!(manager.volume_factor(), 102.4);
+        assert!(!manager.is_playing());
+        assert_some_eq!(message(), text("status.sound_off"));
+        // Sounds aren't played while the sound is off
+        assert!(zmachine.play_sound(3, 8, 1, None).is_ok());
+        assert!(!zmachine.is_sound_playing());
+        input(&['\u{85}', 'M', '\r']);
+        assert!(zmachine.read_line(&[], 16, &['\r' as u16], 0).is_ok());
+        assert!(assert_some!(menu()).contains(&toggle('M', "menu.sound", false)));
+        assert!(zmachine.play_sound(3, 8, 1, None).is_ok());
+        assert!(zmachine.is_sound_playing());
     }
 
     #[test]
-    fn test_prompt_filename_invalid_filename_blorb() {
+    fn test_read_line_menu_key_terminator() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', 'b', 'l', 'o', 'r', 'b', '\r',
-        ]);
-        let r = zmachine.prompt_filename("Filename? ", "pf06", false, false);
-        assert!(r.is_err());
-        assert_print!("Filename? test.pf06blorb");
+        input(&['a', '\u{85}']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16, 255], 0),
+            [b'a' as u16, 0x85]
+        );
+        assert!(menu().is_none());
     }
 
     #[test]
-    fn test_prompt_and_create() {
+    fn test_read_line_menu_instruction_log() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let r = zmachine.prompt_and_create("Filename? ", "pc01", false);
-        assert!(Path::new("test-01.pc01").exists());
-        assert!(fs::remove_file("test-01.pc01").is_ok());
-        assert!(r.is_ok());
+        // The menu only offers the instruction log when it's being written
+        capture_log("app::instruction");
+        set_input_delay(1);
+        input(&['\u{85}', 'l', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'\r' as u16]
+        );
+        let items = assert_some!(menu());
+        assert!(items.contains(&"L  Instruction log (on)".to_string()));
+        assert!(!zmachine.instruction_log_enabled());
+        assert_some_eq!(message(), text("status.instruction_log_off"));
+        assert_eq!(log(), ["Instruction log off"]);
+        input(&['\u{85}', 'l', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'\r' as u16]
+        );
+        let items = assert_some!(menu());
+        assert!(items.contains(&"L  Instruction log (off)".to_string()));
+        assert!(zmachine.instruction_log_enabled());
+        assert_some_eq!(message(), text("status.instruction_log_on"));
     }
 
     #[test]
-    fn test_prompt_and_create_exists() {
+    fn test_read_line_menu_key_disabled() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pc02");
-        assert!(f.is_ok());
-        assert!(Path::new("test-01.pc02").exists());
-        input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
-            '0', '1', '.', 'p', 'c', '0', '2', '\r',
-        ]);
-        let r = zmachine.prompt_and_create("Filename? ", "pc02", false);
-        assert!(Path::new("test-01.pc02").exists());
-        assert!(fs::remove_file("test-01.pc02").is_ok());
-        assert!(r.is_err());
+        let mut config = Config::default();
+        config.set_menu_key(None);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        input(&['a', '\u{85}', 's', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [b'a' as u16, b's' as u16, b'\r' as u16]
+        );
+        assert!(menu().is_none());
     }
 
     #[test]
-    fn test_prompt_and_create_exists_overwrite() {
+    fn test_read_key_menu() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        let f = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pc03");
-        assert!(f.is_ok());
-        assert!(Path::new("test-01.pc03").exists());
-        input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
-            '0', '1', '.', 'p', 'c', '0', '3', '\r',
-        ]);
-        let r = zmachine.prompt_and_create("Filename? ", "pc03", true);
-        assert!(Path::new("test-01.pc03").exists());
-        assert!(fs::remove_file("test-01.pc03").is_ok());
-        assert!(r.is_ok());
+        // The game can read the menu key itself with READ_CHAR
+        input(&['\u{85}']);
+        assert_ok_eq!(zmachine.read_key(0), InputEvent::from_char(0x85));
+        assert!(menu().is_none());
     }
 
     #[test]
-    fn test_prompt_and_write() {
+    fn test_read_line_paste_overflow() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let r = zmachine.prompt_and_write("Filename? ", "pw01", &[1, 2, 3, 4], false);
-        assert!(Path::new("test-01.pw01").exists());
-        let data = fs::read("test-01.pw01");
-        assert!(fs::remove_file("test-01.pw01").is_ok());
-        assert!(r.is_ok());
-        assert_ok_eq!(data, vec![1, 2, 3, 4]);
+        input(&['a', 'b', 'c', 'd', 'e', '\u{08}', 'f', '\r', 'g', '\r']);
+        // Keys that don't fit are dropped, but editing keys still apply
+        assert_ok_eq!(
+            zmachine.read_line(&[], 4, &['\r' as u16], 0),
+            [
+                b'a' as u16,
+                b'b' as u16,
+                b'c' as u16,
+                b'f' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_ok_eq!(
+            zmachine.read_line(&[], 4, &['\r' as u16], 0),
+            [b'g' as u16, b'\r' as u16]
+        );
     }
 
     #[test]
-    fn test_prompt_and_write_exists() {
+    fn test_read_line_paste_discard_overflow() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut config = Config::default();
+        config.set_discard_paste_overflow(true);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        input(&['a', 'b', 'c', 'd', 'e', '\u{08}', 'f', '\r', 'g', '\r']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 4, &['\r' as u16], 0),
+            [
+                b'a' as u16,
+                b'b' as u16,
+                b'c' as u16,
+                b'd' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_print!("abcd");
+        // Only the rest of the overflowing line is discarded
+        assert_ok_eq!(
+            zmachine.read_line(&[], 4, &['\r' as u16], 0),
+            [b'g' as u16, b'\r' as u16]
+        );
+    }
+
+    #[test]
+    fn test_read_line_erase_word() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        let mut f = assert_ok!(fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pw02"));
-        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
-        assert!(Path::new("test-01.pw02").exists());
         input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
-            '0', '1', '.', 'p', 'w', '0', '2', '\r',
+            'g', 'e', 't', ' ', 'l', 'a', 'm', 'p', ' ', '\u{17}', 'b', 'o', 'x', '\u{0d}',
         ]);
-        let r = zmachine.prompt_and_write("Filename? ", "pw02", &[5, 6, 7, 8], false);
-        assert!(Path::new("test-01.pw02").exists());
-        let data = fs::read("test-01.pw02");
-        assert!(fs::remove_file("test-01.pw02").is_ok());
-        assert!(r.is_err());
-        assert_ok_eq!(data, vec![1, 2, 3, 4]);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [
+                b'g' as u16,
+                b'e' as u16,
+                b't' as u16,
+                b' ' as u16,
+                b'b' as u16,
+                b'o' as u16,
+                b'x' as u16,
+                b'\r' as u16
+            ]
+        );
+        // Erased characters are still printed; the test terminal doesn't remove them
+        assert_print!("get lamp box");
+        assert_eq!(backspace(), (1, 5));
     }
 
     #[test]
-    fn test_prompt_and_write_exists_overwrite() {
-        let map = test_map(5);
+    fn test_read_line_erase_word_separators() {
+        let mut map = test_map(5);
+        // A dictionary declaring ',' and '"' as separators
+        map[0x08] = 0x03;
+        map[0x300] = 2;
+        map[0x301] = b',';
+        map[0x302] = b'"';
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        let mut f = assert_ok!(fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pw04"));
-        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
-        assert!(Path::new("test-01.pw04").exists());
+        // Each separator is a word of its own, and stops erasing the word before it
         input(&[
-            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
-            '0', '1', '.', 'p', 'w', '0', '4', '\r',
+            'a', 's', 'k', ' ', 'b', 'o', 'b', ',', '"', 'h', 'i', '\u{17}', '\u{17}', '\u{17}',
+            '\u{17}', 'x', '\u{0d}',
         ]);
-        let r = zmachine.prompt_and_write("Filename? ", "pw04", &[5, 6, 7, 8], true);
-        assert!(Path::new("test-01.pw04").exists());
-        let data = fs::read("test-01.pw04");
-        assert!(fs::remove_file("test-01.pw04").is_ok());
-        assert!(r.is_ok());
-        assert_ok_eq!(data, vec![5, 6, 7, 8]);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [
+                b'a' as u16,
+                b's' as u16,
+                b'k' as u16,
+                b' ' as u16,
+                b'x' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_eq!(backspace(), (1, 5));
     }
 
     #[test]
-    fn test_prompt_and_read() {
+    fn test_read_line_erase_word_start_of_line() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        let mut f = assert_ok!(fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("test-01.pr01"));
-        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
-        assert!(Path::new("test-01.pr01").exists());
-        input(&['\r']);
-        let r = zmachine.prompt_and_read("Filename? ", "pr01");
-        assert!(fs::remove_file("test-01.pr01").is_ok());
-        assert_ok_eq!(r, [1, 2, 3, 4]);
+        input(&['\u{17}', '\u{15}', 'l', 'o', 'o', 'k', '\u{0d}']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [
+                b'l' as u16,
+                b'o' as u16,
+                b'o' as u16,
+                b'k' as u16,
+                b'\r' as u16
+            ]
+        );
+        // Nothing to erase, and the keys aren't input
+        assert_print!("look");
+        assert_eq!(backspace(), (0, 0));
     }
 
     #[test]
-    fn test_prompt_and_read_error() {
+    fn test_read_line_erase_line() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        let r = zmachine.prompt_and_read("Filename? ", "pr02");
-        assert!(r.is_err());
+        input(&[
+            'g', 'e', 't', ' ', 'l', 'a', 'm', 'p', '\u{15}', 'n', '\u{0d}',
+        ]);
+        assert_ok_eq!(
+            zmachine.read_line(&[b'x' as u16], 16, &['\r' as u16], 0),
+            [b'n' as u16, b'\r' as u16]
+        );
+        assert_eq!(backspace(), (1, 1));
     }
 
     #[test]
-    fn test_quit() {
+    fn test_read_line_fn_terminator() {
+        let mut map = test_map(5);
+        map[0x101] = 2;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(header::set_word(&mut zmachine.state, HeaderField::ExtensionTable, 0x100).is_ok());
+        input(&['T', 'e', 's', 't', 'i', 'n', 'g', 'x', '\u{08}', '\u{FD}']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16, 255], 0),
+            [
+                b'T' as u16,
+                b'e' as u16,
+                b's' as u16,
+                b't' as u16,
+                b'i' as u16,
+                b'n' as u16,
+                b'g' as u16,
+                0xFD
+            ]
+        );
+        // The x is printed, even though it is erased by the backspace
+        assert_print!("Testingx");
+        assert_ok_eq!(zmachine.read_word(0x102), 12);
+        assert_ok_eq!(zmachine.read_word(0x104), 18);
+    }
+
+    #[test]
+    fn test_read_line_with_timeout() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&['\r']);
-        assert!(zmachine.quit().is_ok());
-        assert_print!("Press any key to exit");
-        assert!(quit());
+        input(&['T', 'e', 's', 't', 'i', 'n', 'g']);
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 1),
+            [
+                b'T' as u16,
+                b'e' as u16,
+                b's' as u16,
+                b't' as u16,
+                b'i' as u16,
+                b'n' as u16,
+                b'g' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_print!("Testing");
+    }
+    #[test]
+    fn test_read_line_timeout() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['T', 'e', 's', 't', 'i', 'n', 'g']);
+        set_input_delay(350);
+        let r = assert_ok!(zmachine.read_line(&[], 16, &['\r' as u16], 10));
+        assert_eq!(r, [b'T' as u16, b'e' as u16, b's' as u16]);
+        assert_print!("Tes");
     }
 
     #[test]
-    fn test_new_line() {
-        let map = test_map(3);
+    fn test_read_line_scripted_transcript() {
+        let map = test_map(5);
+        let m = Memory::new(map.clone());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let f = assert_ok!(File::create("test-script-typed.txt"));
+        zmachine.io.set_stream_2(f);
+        assert!(zmachine.io.enable_output_stream(2, None).is_ok());
+        input(&['l', 'o', 'o', 'k', '\r', 'n', 'o', 'r', 't', 'h', '\r']);
+        let look = assert_ok!(zmachine.read_line(&[], 16, &['\r' as u16], 0));
+        let north = assert_ok!(zmachine.read_line(&[], 16, &['\r' as u16], 0));
+        let typed_print = print();
+
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.new_line().is_ok());
-        assert_eq!(scroll(), 2);
+        let f = assert_ok!(File::create("test-script-scripted.txt"));
+        zmachine.io.set_stream_2(f);
+        assert!(zmachine.io.enable_output_stream(2, None).is_ok());
+        zmachine.push_commands(&["look".to_string(), "north".to_string()]);
+        assert!(zmachine.io.set_input_stream(1).is_ok());
+        assert_ok_eq!(zmachine.read_line(&[], 16, &['\r' as u16], 0), look);
+        assert_ok_eq!(zmachine.read_line(&[], 16, &['\r' as u16], 0), north);
+        assert_eq!(zmachine.io.input_stream(), 0);
+        assert_print!(&format!("{}{}", typed_print, typed_print));
+
+        let typed = fs::read_to_string("test-script-typed.txt");
+        let scripted = fs::read_to_string("test-script-scripted.txt");
+        assert!(fs::remove_file("test-script-typed.txt").is_ok());
+        assert!(fs::remove_file("test-script-scripted.txt").is_ok());
+        let typed = assert_ok!(typed);
+        assert_eq!(typed, "look\nnorth\n");
+        assert_ok_eq!(scripted, typed);
+    }
+
+    #[test]
+    fn test_read_line_no_echo() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.echo_input = false;
+        input(&['l', 'o', 'o', 'x', '\u{08}', 'k', '\r']);
+        let look = assert_ok!(zmachine.read_line(&[], 16, &['\r' as u16], 0));
+        assert_eq!(
+            look,
+            [
+                b'l' as u16,
+                b'o' as u16,
+                b'o' as u16,
+                b'k' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_print!("");
+        assert_eq!(backspace(), (0, 0));
+        // The game re-prints the command itself
+        assert!(zmachine.print(&look).is_ok());
+        assert_print!("look");
     }
 
     #[test]
-    fn test_backspace() {
-        let map = test_map(3);
+    fn test_read_line_scripted_no_echo() {
+        let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.set_cursor(23, 2).is_ok());
-        assert!(zmachine.backspace().is_ok());
-        assert_eq!(backspace(), (23, 1));
+        zmachine.echo_scripted_input = false;
+        zmachine.push_commands(&["look".to_string()]);
+        assert!(zmachine.io.set_input_stream(1).is_ok());
+        assert_ok_eq!(
+            zmachine.read_line(&[], 16, &['\r' as u16], 0),
+            [
+                b'l' as u16,
+                b'o' as u16,
+                b'o' as u16,
+                b'k' as u16,
+                b'\r' as u16
+            ]
+        );
+        assert_print!("");
     }
 
     #[test]
-    fn test_play_sound_v3() {
-        let map = test_map(3);
+    fn test_read_line_sound_interrupt() {
+        let map = test_map(5);
         let m = Memory::new(map);
         let blorb = mock_blorb();
         let manager = assert_ok!(Manager::new(128.0, blorb));
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
-        assert_eq!(play_sound(), (4, 8, 10));
-        assert!(zmachine.is_sound_playing());
+        zmachine.set_sound_interrupt(0x1234);
+        let manager = assert_some!(zmachine.sound_manager.as_mut());
+        assert!(!manager.is_playing());
+        let input = assert_ok!(zmachine.read_line(&[], 16, &[b'\r' as u16], 0));
+        assert!(input.is_empty());
     }
 
     #[test]
-    fn test_play_sound_v5() {
+    fn test_prompt_filename_first() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let ridx = RIdx::new(vec![
-            Index::new("Snd ".to_string(), 1, 0x100),
-            Index::new("Snd ".to_string(), 2, 0x200),
-            Index::new("Pic ".to_string(), 1, 0x300),
-            Index::new("Snd ".to_string(), 4, 0x400),
-        ]);
-        let sloop = Loop::new(vec![Entry::new(1, 0), Entry::new(2, 20)]);
-        let mut sounds = HashMap::new();
-        sounds.insert(0x100, Chunk::new_chunk(0x100, "OGGV", vec![1, 1, 1, 1]));
-        sounds.insert(0x400, Chunk::new_chunk(0x400, "OGGV", vec![4, 4, 4, 4]));
-        sounds.insert(0x200, Chunk::new_form(0x200, "AIFF", vec![]));
-        let blorb = Blorb::new(ridx, None, sounds, Some(sloop), None);
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
-        assert_eq!(play_sound(), (4, 8, 0));
-        assert!(zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf01", false, true),
+            "test-01.pf01"
+        );
+        assert_print!("Filename? test-01.pf01");
     }
 
     #[test]
-    fn test_play_sound_v5_with_repeats() {
+    fn test_prompt_filename_terminal_fallback() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
-        assert_eq!(play_sound(), (4, 8, 5));
-        assert!(zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf08", false, true),
+            "test-01.pf08"
+        );
+        // The terminal has no prompt, so the prompt was printed in the game window
+        assert!(filename_request().is_none());
+        assert_print!("Filename? test-01.pf08");
     }
 
     #[test]
-    fn test_play_sound_v5_with_interrupt() {
+    fn test_prompt_filename_terminal_cancelled() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(4, 8, 5, Some(0x500)).is_ok());
-        assert_some_eq!(zmachine.sound_interrupt(), 0x500);
-        assert_eq!(play_sound(), (4, 8, 5));
-        assert!(zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        cancel_filename_response();
+        let e = zmachine
+            .prompt_filename("Filename? ", "pf11", false, true)
+            .expect_err("expected the prompt to be cancelled");
+        assert_eq!(e.code(), ErrorCode::InvalidFilename);
+        assert_some_eq!(
+            filename_request(),
+            ("Filename? ".to_string(), "test-01.pf11".to_string())
+        );
+        // Nothing was printed in the game window
+        assert_print!("");
     }
 
     #[test]
-    fn test_play_sound_change_volume() {
+    fn test_prompt_filename_terminal() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
-        assert_eq!(play_sound(), (4, 8, 5));
-        assert!(zmachine.is_sound_playing());
-        assert!(zmachine.play_sound(4, 4, 5, None).is_ok());
-        assert_eq!(play_sound(), (0, 4, 0));
-        assert!(zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_filename_response(" saved.pf09 ");
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf09", false, true),
+            "saved.pf09"
+        );
+        assert_some_eq!(
+            filename_request(),
+            ("Filename? ".to_string(), "test-01.pf09".to_string())
+        );
+        assert_print!("");
     }
 
     #[test]
-    fn test_play_sound_no_effect() {
+    fn test_prompt_filename_terminal_invalid_filename() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let ridx = RIdx::new(vec![
-            Index::new("Snd ".to_string(), 1, 0x100),
-            Index::new("Snd ".to_string(), 2, 0x200),
-            Index::new("Pic ".to_string(), 1, 0x300),
-            Index::new("Snd ".to_string(), 4, 0x400),
-        ]);
-        let sloop = Loop::new(vec![Entry::new(1, 0), Entry::new(2, 20)]);
-        let mut sounds = HashMap::new();
-        sounds.insert(0x100, Chunk::new_chunk(0x100, "OGGV", vec![1, 1, 1, 1]));
-        sounds.insert(0x400, Chunk::new_chunk(0x400, "OGGV", vec![4, 4, 4, 4]));
-        sounds.insert(0x200, Chunk::new_form(0x200, "AIFF", vec![]));
-        let blorb = Blorb::new(ridx, None, sounds, Some(sloop), None);
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(2, 8, 5, None).is_ok());
-        assert_eq!(play_sound(), (0, 0, 0));
-        assert!(!zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let story = dir.path().join("saved.z5");
+        assert!(fs::write(&story, test_map(5)).is_ok());
+        set_filename_response(story.to_str().unwrap());
+        let e = zmachine
+            .prompt_filename("Filename? ", "pf10", true, true)
+            .expect_err("expected an invalid filename error");
+        assert_eq!(e.code(), ErrorCode::InvalidFilename);
     }
 
     #[test]
-    fn test_play_sound_no_manager() {
+    fn test_prompt_filename_story_name_not_story() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.play_sound(2, 8, 5, None).is_ok());
-        assert_eq!(play_sound(), (0, 0, 0));
-        assert!(!zmachine.is_sound_playing());
+        let dir = assert_ok!(tempfile::tempdir());
+        // Doesn't exist
+        let name = dir.path().join("saved.z5");
+        let name = name.to_str().unwrap();
+        set_filename_response(name);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf10", true, true),
+            name
+        );
+        // Exists, but isn't a story file
+        assert!(fs::write(name, "aux data").is_ok());
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf10", true, true),
+            name
+        );
     }
 
     #[test]
-    fn test_stop_sound() {
+    fn test_prompt_filename_answered() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
-        assert_eq!(play_sound(), (4, 8, 5));
-        assert!(zmachine.is_sound_playing());
-        assert!(zmachine.stop_sound().is_ok());
-        assert_eq!(play_sound(), (0, 0, 0));
-        assert!(!zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let name = dir.path().join("answer.ifzs");
+        let name = name.to_str().unwrap();
+        zmachine.answer_filename(name);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "ifzs", false, true),
+            name
+        );
+        // Only the next prompt is answered, and the terminal isn't asked
+        assert!(filename_request().is_none());
+        assert!(zmachine.filename_answer.is_none());
     }
 
     #[test]
-    fn test_stop_sound_not_playing() {
+    fn test_prompt_filename_allow_any_save_name() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(!zmachine.is_sound_playing());
-        assert!(zmachine.stop_sound().is_ok());
-        assert_eq!(play_sound(), (0, 0, 0));
-        assert!(!zmachine.is_sound_playing());
+        let mut config = Config::default();
+        config.set_allow_any_save_name(true);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let story = dir.path().join("saved.z5");
+        assert!(fs::write(&story, test_map(5)).is_ok());
+        let name = story.to_str().unwrap();
+        set_filename_response(name);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pf10", true, true),
+            name
+        );
     }
 
     #[test]
-    fn test_stop_sound_no_manager() {
+    fn test_prompt_filename_first_existing() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(zmachine.stop_sound().is_ok());
-        assert_eq!(play_sound(), (0, 0, 0));
-        assert!(!zmachine.is_sound_playing());
+        input(&['\r']);
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pf02");
+        assert!(f.is_ok());
+        assert!(Path::new("test-01.pf02").exists());
+        let r = zmachine.prompt_filename("Filename? ", "pf02", false, true);
+        assert!(fs::remove_file("test-01.pf02").is_ok());
+        assert_ok_eq!(r, "test-02.pf02");
+        assert_print!("Filename? test-02.pf02");
     }
 
     #[test]
-    fn test_is_sound_playing() {
+    fn test_prompt_filename_first_last_none() {
         let map = test_map(5);
         let m = Memory::new(map);
-        let blorb = mock_blorb();
-        let manager = assert_ok!(Manager::new(128.0, blorb));
-        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
-        assert!(!zmachine.is_sound_playing());
-        assert!(zmachine.play_sound(4, 5, 5, None).is_ok());
-        assert!(zmachine.is_sound_playing());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        let r = zmachine.prompt_filename("Filename? ", "pf03", true, false);
+        assert_ok_eq!(r, "test.pf03");
+        assert_print!("Filename? test.pf03");
     }
 
     #[test]
-    fn test_is_sound_playing_no_manager() {
+    fn test_prompt_filename_recent() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut config = Config::default();
+        config.set_config_dir(dir.path().to_str().unwrap());
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        let save = dir.path().join("mine.ifzs");
+        let save = save.to_str().unwrap();
+        set_filename_response(save);
+        assert!(zmachine
+            .prompt_and_write("Save to: ", "ifzs", &[1, 2, 3], true)
+            .is_ok());
+        let recent = dir.path().join("recent.yml");
+        assert_some_eq!(files::recent_file(recent.to_str().unwrap(), "test"), save);
+
+        // Restoring suggests the remembered file
+        assert_ok_eq!(
+            zmachine.prompt_filename("Restore from: ", "ifzs", true, false),
+            save
+        );
+        assert_some_eq!(
+            filename_request(),
+            ("Restore from: ".to_string(), save.to_string())
+        );
+    }
+
+    #[test]
+    fn test_prompt_filename_recent_disabled() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut config = Config::default();
+        config.set_config_dir(dir.path().to_str().unwrap());
+        config.set_remember_files(false);
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        let save = dir.path().join("mine.ifzs");
+        set_filename_response(save.to_str().unwrap());
+        assert!(zmachine
+            .prompt_and_write("Save to: ", "ifzs", &[1, 2, 3], true)
+            .is_ok());
+        assert!(!dir.path().join("recent.yml").exists());
+        assert!(zmachine
+            .prompt_filename("Restore from: ", "ifzs", true, false)
+            .is_ok());
+        assert_some_eq!(
+            filename_request(),
+            ("Restore from: ".to_string(), "test.ifzs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_prompt_filename_first_last_existing() {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        assert!(!zmachine.is_sound_playing());
+        input(&['\r']);
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pf04");
+        assert!(f.is_ok());
+        assert!(Path::new("test-01.pf04").exists());
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-02.pf04");
+        assert!(f.is_ok());
+        assert!(Path::new("test-02.pf04").exists());
+        let r = zmachine.prompt_filename("Filename? ", "pf04", true, false);
+        assert!(fs::remove_file("test-01.pf04").is_ok());
+        assert!(fs::remove_file("test-02.pf04").is_ok());
+        assert_ok_eq!(r, "test-02.pf04");
+        assert_print!("Filename? test-02.pf04");
     }
 
     #[test]
-    fn test_run() {
-        let mut map = test_map(5);
-        // NOP and QUIT
-        map[0x400] = 0xB4;
-        map[0x401] = 0xBA;
+    fn test_prompt_filename_overwrite_existing() {
+        let map = test_map(5);
         let m = Memory::new(map);
         let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
-        input(&[' ']);
+        input(&['\r']);
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pf05");
+        assert!(f.is_ok());
+        assert!(Path::new("test-01.pf05").exists());
+        let r = zmachine.prompt_filename("Filename? ", "pf05", false, false);
+        assert!(fs::remove_file("test-01.pf05").is_ok());
+        assert!(r.is_err());
+        assert_print!("Filename? test-01.pf05");
+    }
+
+    const BLORB_HEADER: &[u8] = b"FORM\0\0\0\x04IFRS";
+
+    #[test]
+    fn test_prompt_filename_invalid_filename_z() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let name = dir.path().join("test.z5");
+        assert!(fs::write(&name, test_map(5)).is_ok());
+        set_filename_response(name.to_str().unwrap());
+        let e = zmachine
+            .prompt_filename("Filename? ", "pf06", true, false)
+            .expect_err("expected an invalid filename error");
+        assert_eq!(e.code(), ErrorCode::InvalidFilename);
+    }
+
+    #[test]
+    fn test_prompt_filename_invalid_filename_blb() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let name = dir.path().join("test.blb");
+        assert!(fs::write(&name, BLORB_HEADER).is_ok());
+        set_filename_response(name.to_str().unwrap());
+        let e = zmachine
+            .prompt_filename("Filename? ", "pf06", true, false)
+            .expect_err("expected an invalid filename error");
+        assert_eq!(e.code(), ErrorCode::InvalidFilename);
+    }
+
+    #[test]
+    fn test_prompt_filename_invalid_filename_blorb() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let dir = assert_ok!(tempfile::tempdir());
+        let name = dir.path().join("test.blorb");
+        assert!(fs::write(&name, BLORB_HEADER).is_ok());
+        set_filename_response(name.to_str().unwrap());
+        let e = zmachine
+            .prompt_filename("Filename? ", "pf06", true, false)
+            .expect_err("expected an invalid filename error");
+        assert_eq!(e.code(), ErrorCode::InvalidFilename);
+    }
+
+    #[test]
+    fn test_prompt_and_create() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        let r = zmachine.prompt_and_create("Filename? ", "pc01", false, OpenMode::Truncate);
+        assert!(Path::new("test-01.pc01").exists());
+        assert!(fs::remove_file("test-01.pc01").is_ok());
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn test_prompt_filename_save_name_template() {
+        let mut map = test_map(3);
+        // Status Object
+        mock_object(
+            &mut map,
+            1,
+            vec![0x1319, 0x1B3A, 0x6004, 0x50EF, 0xA919],
+            (0, 0, 0),
+        );
+        let mut config = Config::default();
+        config.set_save_name_template(Some("{name}-{room}-{turn}".to_string()));
+        let mut zmachine = assert_ok!(ZMachine::new(Memory::new(map), config, None, "test"));
+        assert!(zmachine.set_variable(16, 1).is_ok());
+        zmachine.turns = 14;
+        input(&['\r']);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Save to: ", "ifzs", false, true),
+            "test-statusobject-014.ifzs"
+        );
+        // Other files keep the numbered form
+        input(&['\r']);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Filename? ", "pc04", false, true),
+            "test-01.pc04"
+        );
+    }
+
+    #[test]
+    fn test_prompt_filename_save_name_template_no_room() {
+        let map = test_map(3);
+        let mut config = Config::default();
+        config.set_save_name_template(Some("{name}-{room}".to_string()));
+        let mut zmachine = assert_ok!(ZMachine::new(Memory::new(map), config, None, "test"));
+        input(&['\r']);
+        assert_ok_eq!(
+            zmachine.prompt_filename("Save to: ", "ifzs", false, true),
+            "test-01.ifzs"
+        );
+    }
+
+    #[test]
+    fn test_prompt_and_create_exists() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pc02");
+        assert!(f.is_ok());
+        assert!(Path::new("test-01.pc02").exists());
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
+            '0', '1', '.', 'p', 'c', '0', '2', '\r',
+        ]);
+        let r = zmachine.prompt_and_create("Filename? ", "pc02", false, OpenMode::Truncate);
+        assert!(Path::new("test-01.pc02").exists());
+        assert!(fs::remove_file("test-01.pc02").is_ok());
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_prompt_and_create_exists_overwrite() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pc03");
+        assert!(f.is_ok());
+        assert!(Path::new("test-01.pc03").exists());
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
+            '0', '1', '.', 'p', 'c', '0', '3', '\r',
+        ]);
+        let r = zmachine.prompt_and_create("Filename? ", "pc03", true, OpenMode::Truncate);
+        assert!(Path::new("test-01.pc03").exists());
+        assert!(fs::remove_file("test-01.pc03").is_ok());
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn test_prompt_and_write() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        let r = zmachine.prompt_and_write("Filename? ", "pw01", &[1, 2, 3, 4], false);
+        assert!(Path::new("test-01.pw01").exists());
+        let data = fs::read("test-01.pw01");
+        assert!(fs::remove_file("test-01.pw01").is_ok());
+        assert!(r.is_ok());
+        assert_ok_eq!(data, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_prompt_and_write_exists() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let mut f = assert_ok!(fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pw02"));
+        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
+        assert!(Path::new("test-01.pw02").exists());
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
+            '0', '1', '.', 'p', 'w', '0', '2', '\r',
+        ]);
+        let r = zmachine.prompt_and_write("Filename? ", "pw02", &[5, 6, 7, 8], false);
+        assert!(Path::new("test-01.pw02").exists());
+        let data = fs::read("test-01.pw02");
+        assert!(fs::remove_file("test-01.pw02").is_ok());
+        assert!(r.is_err());
+        assert_ok_eq!(data, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_prompt_and_write_exists_overwrite() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let mut f = assert_ok!(fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pw04"));
+        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
+        assert!(Path::new("test-01.pw04").exists());
+        input(&[
+            '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '\u{08}', '-',
+            '0', '1', '.', 'p', 'w', '0', '4', '\r',
+        ]);
+        let r = zmachine.prompt_and_write("Filename? ", "pw04", &[5, 6, 7, 8], true);
+        assert!(Path::new("test-01.pw04").exists());
+        let data = fs::read("test-01.pw04");
+        assert!(fs::remove_file("test-01.pw04").is_ok());
+        assert!(r.is_ok());
+        assert_ok_eq!(data, vec![5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_prompt_and_read() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let mut f = assert_ok!(fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open("test-01.pr01"));
+        assert!(f.write_all(&[1, 2, 3, 4]).is_ok());
+        assert!(Path::new("test-01.pr01").exists());
+        input(&['\r']);
+        let r = zmachine.prompt_and_read("Filename? ", "pr01");
+        assert!(fs::remove_file("test-01.pr01").is_ok());
+        assert_ok_eq!(r, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_prompt_and_read_error() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        let r = zmachine.prompt_and_read("Filename? ", "pr02");
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_messages_yml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages.yml");
+        fs::write(
+            &path,
+            concat!(
+                "prompt:\n  press_key: Appuyez sur une touche\n",
+                "error:\n  prefix: Erreur\n  restore: de la restauration\n",
+                "status:\n  session_stats: \"Tours : {turns}, temps de jeu : {time}\"\n",
+            ),
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.set_messages(assert_ok!(Messages::try_from(assert_ok!(File::open(
+            &path
+        )))));
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        let e = RuntimeError::recoverable(ErrorCode::FileError, "bad file".to_string());
+        assert!(zmachine.print_error("error.restore", &e).is_ok());
+        input(&['\r']);
+        assert!(zmachine.quit().is_ok());
+        assert_print!(&format!(
+            "Erreur de la restauration: {}Tours : 0, temps de jeu : 0:00:00Appuyez sur une touche",
+            e
+        ));
+    }
+
+    #[test]
+    fn test_quit() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['\r']);
+        assert!(zmachine.quit().is_ok());
+        assert_print!(&format!(
+            "Turns: 0, play time: 0:00:00{}",
+            text("prompt.press_key")
+        ));
+        assert!(quit());
+    }
+
+    #[test]
+    fn test_quit_headless() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_interactive(false);
+        input(&['\r']);
+        assert!(zmachine.quit().is_ok());
+        assert_print!("Turns: 0, play time: 0:00:00");
+        assert!(input_pending());
+        assert!(quit());
+    }
+
+    #[test]
+    fn test_quit_scripted() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.io.add_commands(&[vec![b'l' as u16]]);
+        assert!(zmachine.io.set_input_stream(1).is_ok());
+        input(&['\r']);
+        assert!(zmachine.quit().is_ok());
+        assert_print!("Turns: 0, play time: 0:00:00");
+        assert!(input_pending());
+        assert!(quit());
+    }
+
+    #[test]
+    fn test_quit_closes_transcript() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("quit.txt");
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_interactive(false);
+        zmachine
+            .io
+            .set_stream_2(assert_ok!(fs::File::create(&path)));
+        assert!(zmachine.quit().is_ok());
+        assert!(!zmachine.io.is_stream_2_open());
+        assert!(quit());
+    }
+
+    fn debug_hud_capture(debug_hud: bool) -> String {
+        let dir = assert_ok!(tempfile::tempdir());
+        let path = dir.path().join("capture.jsonl");
+        let mut config = Config::default();
+        config.set_capture(&path.to_string_lossy());
+        config.set_debug_hud(debug_hud);
+
+        let mut map = test_map(5);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        zmachine.add_turn();
+        assert!(zmachine.debug_hud().is_ok());
+        assert_ok!(fs::read_to_string(&path))
+    }
+
+    #[test]
+    fn test_debug_hud() {
+        let capture = debug_hud_capture(true);
+        assert!(capture.contains(
+            r#""row":1,"column":1,"style":1,"background":2,"text":" Instructions: 0  Turns: 1  PC: $000400  Depth: 1  Undo: 0K"#
+        ));
+    }
+
+    #[test]
+    fn test_debug_hud_disabled() {
+        let capture = debug_hud_capture(false);
+        assert!(!capture.contains("Instructions"));
+    }
+
+    #[test]
+    fn test_session_stats() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert_eq!(zmachine.session_stats().turns(), 0);
+        zmachine.add_turn();
+        zmachine.add_turn();
+        assert_eq!(zmachine.session_stats().turns(), 2);
+        assert!(zmachine.session_stats().play_time() < Duration::from_secs(60));
+
+        // Restoring a save continues from the saved statistics
+        let intd = IntD::from(&SessionStats::new(42, Duration::from_secs(3600)));
+        zmachine.restore_stats(Some(intd));
+        assert_eq!(zmachine.session_stats().turns(), 42);
+        assert!(zmachine.session_stats().play_time() >= Duration::from_secs(3600));
+        zmachine.add_turn();
+        assert_eq!(zmachine.session_stats().turns(), 43);
+
+        // A save without statistics starts over
+        zmachine.restore_stats(None);
+        assert_eq!(zmachine.session_stats().turns(), 0);
+        assert!(zmachine.session_stats().play_time() < Duration::from_secs(60));
+    }
+
+    // Saves kept in a map instead of files
+    #[derive(Debug)]
+    struct MemorySaves(Rc<RefCell<HashMap<String, Vec<u8>>>>);
+
+    impl SaveProvider for MemorySaves {
+        fn exists(&self, name: &str) -> Result<bool, RuntimeError> {
+            Ok(self.0.borrow().contains_key(name))
+        }
+
+        fn write(&mut self, name: &str, data: &[u8]) -> Result<(), RuntimeError> {
+            self.0.borrow_mut().insert(name.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn read(&mut self, name: &str) -> Result<Vec<u8>, RuntimeError> {
+            match self.0.borrow().get(name) {
+                Some(data) => Ok(data.clone()),
+                None => recoverable_error!(ErrorCode::FileError, "{}: not found", name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_provider() {
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let saves = Rc::new(RefCell::new(HashMap::new()));
+        zmachine.set_save_provider(Box::new(MemorySaves(Rc::clone(&saves))));
+        set_filename_response("provided.ifzs");
+        assert!(zmachine.save(0x401).is_ok());
+        assert!(saves.borrow().contains_key("provided.ifzs"));
+        assert!(!Path::new("provided.ifzs").exists());
+        // The provider says whether a save would overwrite another
+        let e = zmachine
+            .save(0x401)
+            .expect_err("expected a file exists error");
+        assert_eq!(e.code(), ErrorCode::FileExists);
+        assert_some_eq!(assert_ok!(zmachine.restore()), Restored::Save(0x401));
+    }
+
+    // A clock that only moves when the interpreter sleeps, counting the sleeps
+    #[derive(Debug)]
+    struct SteppedTime(Rc<Cell<Duration>>, Rc<Cell<u32>>);
+
+    impl Clock for SteppedTime {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    impl Sleeper for SteppedTime {
+        fn sleep(&mut self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+            self.1.set(self.1.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_clock_sleeper() {
+        let m = Memory::new(test_map(5));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let time = Rc::new(Cell::new(Duration::from_secs(1000)));
+        let sleeps = Rc::new(Cell::new(0));
+        zmachine.set_clock(Box::new(SteppedTime(Rc::clone(&time), Rc::clone(&sleeps))));
+        zmachine.set_sleeper(Box::new(SteppedTime(Rc::clone(&time), Rc::clone(&sleeps))));
+        let played = zmachine.session_stats().play_time();
+
+        // Half a second of 10ms sleeps, without waiting for any of them
+        set_input_timeout();
+        assert_ok_eq!(
+            zmachine.read_key(5),
+            InputEvent::from_interrupt(Interrupt::ReadTimeout)
+        );
+        assert_eq!(sleeps.get(), 50);
+        assert_eq!(
+            zmachine.session_stats().play_time(),
+            played + Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_transcript_disk_full() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let mut map = test_map(5);
+        // NEW_LINE x 4, QUIT
+        map[0x400..0x405].copy_from_slice(&[0xBB, 0xBB, 0xBB, 0xBB, 0xBA]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_interactive(false);
+        assert!(zmachine
+            .start_transcript(&dir.path().join("full.txt"))
+            .is_ok());
+        let (writer, written, _) = FullDiskWriter::new(2);
+        zmachine.io.set_stream_2(writer);
+
+        assert_ok_eq!(zmachine.advance(None), Advance::Quit);
+        assert_eq!(written.get(), 2);
+        assert!(!zmachine.io.is_stream_2_open());
+        assert!(!zmachine.io.is_stream_enabled(2));
+        assert_ok_eq!(zmachine.read_byte(0x11), 0);
+        assert_eq!(print().matches(&disk_full_message()).count(), 1);
+        assert!(quit());
+    }
+
+    fn event_listener(zmachine: &mut ZMachine) -> Rc<RefCell<Vec<GameEvent>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let e = Rc::clone(&events);
+        zmachine.set_event_listener(Box::new(move |event| e.borrow_mut().push(event)));
+        events
+    }
+
+    #[test]
+    fn test_event_listener() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let save = dir.path().join("events.ifzs");
+        let save = save.to_str().unwrap();
+        let m = Memory::new(test_map(3));
+        let manager = assert_ok!(Manager::new(128.0, mock_blorb()));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        set_interactive(false);
+        let events = event_listener(&mut zmachine);
+
+        // First turn: the starting score is the baseline
+        assert!(zmachine.check_score().is_ok());
+        zmachine.add_turn();
+        // Second turn scores and plays a sound, changing its volume after it starts
+        assert!(zmachine.set_variable(17, 5).is_ok());
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        assert!(zmachine.play_sound(1, 4, 0, None).is_ok());
+        // There is no sound effect 3, so nothing starts
+        assert!(zmachine.play_sound(3, 8, 0, None).is_ok());
+        assert!(zmachine.check_score().is_ok());
+        zmachine.add_turn();
+        set_filename_response(save);
+        assert!(zmachine.save(0x400).is_ok());
+        // Losing points, then restoring, only reports the loss
+        assert!(zmachine.set_variable(17, 0xFFFF).is_ok());
+        assert!(zmachine.check_score().is_ok());
+        assert!(zmachine.restore().is_ok());
+        assert!(zmachine.check_score().is_ok());
+        assert!(zmachine.restart().is_ok());
+        assert!(zmachine.quit().is_ok());
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                GameEvent::TurnCompleted { n: 1 },
+                GameEvent::SoundStarted { effect: 1 },
+                GameEvent::ScoreChanged { old: 0, new: 5 },
+                GameEvent::TurnCompleted { n: 2 },
+                GameEvent::Saved {
+                    path: save.to_string()
+                },
+                GameEvent::ScoreChanged { old: 5, new: -1 },
+                GameEvent::Restored {
+                    path: save.to_string()
+                },
+                GameEvent::Restarted,
+                GameEvent::Quit,
+            ]
+        );
+        assert_eq!(zmachine.variable(17).unwrap_or_default(), 0);
+        assert!(quit());
+    }
+
+    #[test]
+    fn test_event_listener_time_game() {
+        let mut map = test_map(3);
+        map[0x01] = Flags1v3::StatusLineType as u8;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let events = event_listener(&mut zmachine);
+        assert!(zmachine.check_score().is_ok());
+        assert!(zmachine.set_variable(17, 12).is_ok());
+        assert!(zmachine.check_score().is_ok());
+        zmachine.add_turn();
+        assert_eq!(*events.borrow(), [GameEvent::TurnCompleted { n: 1 }]);
+    }
+
+    #[test]
+    fn test_event_listener_reentry() {
+        // A listener that reaches the interpreter through a RefCell finds it already borrowed
+        let m = Memory::new(test_map(5));
+        let zmachine = Rc::new(RefCell::new(assert_ok!(ZMachine::new(
+            m,
+            Config::default(),
+            None,
+            "test"
+        ))));
+        let z = Rc::downgrade(&zmachine);
+        let reentered = Rc::new(RefCell::new(None));
+        let r = Rc::clone(&reentered);
+        zmachine.borrow_mut().set_event_listener(Box::new(move |_| {
+            r.replace(z.upgrade().map(|z| z.try_borrow_mut().is_ok()));
+        }));
+        zmachine.borrow_mut().add_turn();
+        assert_some_eq!(*reentered.borrow(), false);
+    }
+
+    #[test]
+    fn test_new_line() {
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.new_line().is_ok());
+        assert_eq!(scroll(), 2);
+    }
+
+    #[test]
+    fn test_backspace() {
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.set_cursor(23, 2).is_ok());
+        assert!(zmachine.backspace().is_ok());
+        assert_eq!(backspace(), (23, 1));
+    }
+
+    #[test]
+    fn test_play_sound_v3() {
+        let map = test_map(3);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        assert_eq!(play_sound(), (4, 8, 10));
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_v5() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let ridx = RIdx::new(vec![
+            Index::new("Snd ".to_string(), 1, 0x100),
+            Index::new("Snd ".to_string(), 2, 0x200),
+            Index::new("Pic ".to_string(), 1, 0x300),
+            Index::new("Snd ".to_string(), 4, 0x400),
+        ]);
+        let sloop = Loop::new(vec![Entry::new(1, 0), Entry::new(2, 20)]);
+        let mut sounds = HashMap::new();
+        sounds.insert(0x100, Chunk::new_chunk(0x100, "OGGV", vec![1, 1, 1, 1]));
+        sounds.insert(0x400, Chunk::new_chunk(0x400, "OGGV", vec![4, 4, 4, 4]));
+        sounds.insert(0x200, Chunk::new_form(0x200, "AIFF", vec![]));
+        let blorb = Blorb::new(ridx, None, sounds, Some(sloop), None, None);
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        assert_eq!(play_sound(), (4, 8, 0));
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_describe() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let manager = assert_ok!(Manager::new(128.0, mock_blorb()));
+        let mut config = Config::default();
+        config.set_describe_sounds(true);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, Some(manager), "test"));
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        assert_print!("[sound: distant foghorn]");
+        assert_eq!(play_sound(), (4, 8, 10));
+        // A volume change for the sound that's playing isn't described again
+        assert!(zmachine.play_sound(1, 4, 0, None).is_ok());
+        assert_print!("[sound: distant foghorn]");
+        assert!(zmachine.play_sound(4, 8, 0, None).is_ok());
+        assert_print!("[sound: distant foghorn][sound #4]");
+        assert_ok_eq!(zmachine.io.cursor(), (3, 1));
+    }
+
+    #[test]
+    fn test_play_sound_describe_after_finish() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let manager = assert_ok!(Manager::new(128.0, mock_blorb()));
+        let mut config = Config::default();
+        config.set_describe_sounds(true);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, Some(manager), "test"));
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        finish_sound();
+        assert!(!zmachine.is_sound_playing());
+        // Playing the same effect again after it finished starts, and describes, it again
+        assert!(zmachine.play_sound(1, 8, 0, None).is_ok());
+        assert_print!("[sound: distant foghorn][sound: distant foghorn]");
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_describe_no_sounds() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut config = Config::default();
+        config.set_describe_sounds(true);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        assert!(zmachine.split_window(2).is_ok());
+        assert!(zmachine.set_window(1).is_ok());
+        assert!(zmachine
+            .print(&"ab".chars().map(|c| c as u16).collect())
+            .is_ok());
+        assert!(zmachine.play_sound(3, 8, 0, None).is_ok());
+        // The description goes to the lower window, leaving the upper window cursor alone
+        assert_ok_eq!(zmachine.io.cursor(), (1, 3));
+        assert!(zmachine.set_window(0).is_ok());
+        assert_ok_eq!(zmachine.io.cursor(), (4, 1));
+        assert_print!("ab[sound #3]");
+    }
+
+    #[test]
+    fn test_play_sound_v5_with_repeats() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
+        assert_eq!(play_sound(), (4, 8, 5));
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_v5_with_interrupt() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(4, 8, 5, Some(0x500)).is_ok());
+        assert_some_eq!(zmachine.sound_interrupt(), 0x500);
+        assert_eq!(play_sound(), (4, 8, 5));
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_change_volume() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
+        assert_eq!(play_sound(), (4, 8, 5));
+        assert!(zmachine.is_sound_playing());
+        assert!(zmachine.play_sound(4, 4, 5, None).is_ok());
+        assert_eq!(play_sound(), (0, 4, 0));
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_no_effect() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let ridx = RIdx::new(vec![
+            Index::new("Snd ".to_string(), 1, 0x100),
+            Index::new("Snd ".to_string(), 2, 0x200),
+            Index::new("Pic ".to_string(), 1, 0x300),
+            Index::new("Snd ".to_string(), 4, 0x400),
+        ]);
+        let sloop = Loop::new(vec![Entry::new(1, 0), Entry::new(2, 20)]);
+        let mut sounds = HashMap::new();
+        sounds.insert(0x100, Chunk::new_chunk(0x100, "OGGV", vec![1, 1, 1, 1]));
+        sounds.insert(0x400, Chunk::new_chunk(0x400, "OGGV", vec![4, 4, 4, 4]));
+        sounds.insert(0x200, Chunk::new_form(0x200, "AIFF", vec![]));
+        let blorb = Blorb::new(ridx, None, sounds, Some(sloop), None, None);
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(2, 8, 5, None).is_ok());
+        assert_eq!(play_sound(), (0, 0, 0));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_play_sound_no_manager() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.play_sound(2, 8, 5, None).is_ok());
+        assert_eq!(play_sound(), (0, 0, 0));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_stop_sound() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.play_sound(4, 8, 5, None).is_ok());
+        assert_eq!(play_sound(), (4, 8, 5));
+        assert!(zmachine.is_sound_playing());
+        assert!(zmachine.stop_sound().is_ok());
+        assert_eq!(play_sound(), (0, 0, 0));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_stop_sound_not_playing() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(!zmachine.is_sound_playing());
+        assert!(zmachine.stop_sound().is_ok());
+        assert_eq!(play_sound(), (0, 0, 0));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_stop_sound_no_manager() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.stop_sound().is_ok());
+        assert_eq!(play_sound(), (0, 0, 0));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_is_sound_playing() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let blorb = mock_blorb();
+        let manager = assert_ok!(Manager::new(128.0, blorb));
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(!zmachine.is_sound_playing());
+        assert!(zmachine.play_sound(4, 5, 5, None).is_ok());
+        assert!(zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_is_sound_playing_no_manager() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(!zmachine.is_sound_playing());
+    }
+
+    #[test]
+    fn test_run() {
+        let mut map = test_map(5);
+        // NOP and QUIT
+        map[0x400] = 0xB4;
+        map[0x401] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&[' ']);
+        assert!(zmachine.run().is_ok());
+    }
+
+    #[test]
+    fn test_run_unknown_ext_opcode() {
+        let mut map = test_map(5);
+        // EXT 0x1D with two small constant operands, twice, then QUIT
+        for address in [0x400, 0x405] {
+            map[address] = 0xBE;
+            map[address + 1] = 0x1D;
+            map[address + 2] = 0x5F;
+            map[address + 3] = 0x01;
+            map[address + 4] = 0x02;
+        }
+        map[0x40A] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let instruction = assert_ok!(decoder::decode_instruction(&zmachine, 0x400));
+        assert_eq!(instruction.operands().len(), 2);
+        assert_eq!(instruction.next_address(), 0x405);
+        assert!(zmachine.run().is_ok());
+        assert_eq!(
+            errors(),
+            [(
+                "Unimplemented EXT instruction: BUFFER_SCREEN".to_string(),
+                true
+            )]
+        );
+    }
+
+    #[test]
+    fn test_run_print_paddr_unterminated() {
+        // High memory is all zeros, so the string never ends
+        let mut map = test_map(5);
+        map.resize(0x20000, 0);
+        // PRINT_PADDR #0200, QUIT
+        map[0x400] = 0x8D;
+        map[0x401] = 0x02;
+        map[0x402] = 0x00;
+        map[0x403] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine.run().is_ok());
+        // The error was reported and execution carried on to the QUIT
+        assert_eq!(
+            errors(),
+            [(
+                "String at $000800 is not terminated within 32768 words".to_string(),
+                true
+            )]
+        );
+    }
+
+    #[test]
+    fn test_new_screen_too_small() {
+        set_size(9, 40);
+        let e =
+            ZMachine::new(Memory::new(test_map(5)), Config::default(), None, "test").unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ScreenTooSmall);
+        assert!(!e.is_recoverable());
+        assert!(quit());
+
+        set_size(10, 24);
+        assert!(ZMachine::new(Memory::new(test_map(5)), Config::default(), None, "test").is_ok());
+    }
+
+    #[test]
+    fn test_with_terminal() {
+        let terminal = Box::new(HeadlessTerminal::new(40, 120));
+        let zmachine = assert_ok!(ZMachine::with_terminal(
+            Memory::new(test_map(5)),
+            Config::default(),
+            None,
+            "test",
+            terminal
+        ));
+        // The game is told the size of the terminal it was given
+        assert_ok_eq!(zmachine.read_byte(0x20), 40);
+        assert_ok_eq!(zmachine.read_byte(0x21), 120);
+    }
+
+    #[test]
+    fn test_new_too_large() {
+        let mut map = test_map(3);
+        map.resize(0x20001, 0);
+        let m = Memory::new(map.clone());
+        assert!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, ErrorHandling::Ignore, 128.0),
+            None,
+            "test"
+        )
+        .is_ok());
+        assert_some_eq!(
+            message(),
+            Messages::default().warning("Story file is 0x20001 bytes, larger than the 0x20000 bytes a version 3 story can address")
+        );
+        assert!(errors().is_empty());
+
+        let m = Memory::new(map.clone());
+        assert!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, ErrorHandling::ContinueWarnOnce, 128.0),
+            None,
+            "test"
+        )
+        .is_ok());
+        assert_eq!(errors().len(), 1);
+        assert!(errors()[0].1);
+
+        let m = Memory::new(map);
+        let e = ZMachine::new(
+            m,
+            Config::new(9, 2, false, ErrorHandling::Abort, 128.0),
+            None,
+            "test",
+        )
+        .expect_err("expected an oversized story");
+        assert_eq!(e.code(), ErrorCode::FileTooLarge);
+    }
+
+    #[test]
+    fn test_run_print_paddr_too_large() {
+        let mut map = test_map(7);
+        // String offset is 0xFFFF, or $7FFF8
+        map[0x2A] = 0xFF;
+        map[0x2B] = 0xFF;
+        // PRINT_PADDR #0002, QUIT
+        map[0x400] = 0x8D;
+        map[0x401] = 0x00;
+        map[0x402] = 0x02;
+        map[0x403] = 0xBA;
+        let m = Memory::new(map.clone());
+        let mut zmachine = assert_ok!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, ErrorHandling::ContinueWarnAlways, 128.0),
+            None,
+            "test"
+        ));
+        assert!(zmachine.run().is_ok());
+        assert_eq!(
+            errors(),
+            [(
+                "String packed address $0002 unpacks to $080000, beyond the version 7 limit of $080000"
+                    .to_string(),
+                true
+            )]
+        );
+
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, ErrorHandling::Abort, 128.0),
+            None,
+            "test"
+        ));
+        let e = zmachine.run().expect_err("expected an illegal access");
+        assert_eq!(e.code(), ErrorCode::IllegalMemoryAccess);
+    }
+
+    #[test]
+    fn test_run_sound_interrupt_not_nested() {
+        let mut map = test_map(5);
+        // SOUND_EFFECT #9 #2 #$0108 R, where sound 9 doesn't exist and so finishes at once
+        let sound_effect = [0xF5, 0x50, 0x09, 0x02, 0x01, 0x08, 0x01, 0x80];
+        map[0x400..0x408].copy_from_slice(&sound_effect);
+        // QUIT
+        map[0x408] = 0xBA;
+        // R: no locals
+        map[0x600] = 0x00;
+        // INC G00
+        map[0x601] = 0x95;
+        map[0x602] = 0x10;
+        // JG G00 #1 ?$612
+        map[0x603] = 0x43;
+        map[0x604] = 0x10;
+        map[0x605] = 0x01;
+        map[0x606] = 0xCD;
+        // CATCH -> G01
+        map[0x607] = 0xB9;
+        map[0x608] = 0x11;
+        // The first call plays the sound again, setting another interrupt
+        map[0x609..0x611].copy_from_slice(&sound_effect);
+        // RTRUE
+        map[0x611] = 0xB0;
+        // CATCH -> G02
+        map[0x612] = 0xB9;
+        map[0x613] = 0x12;
+        // RTRUE
+        map[0x614] = 0xB0;
+        let m = Memory::new(map);
+        let manager = assert_ok!(Manager::mock());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), Some(manager), "test"));
+        assert!(zmachine.run().is_ok());
+        // The routine ran twice, one after the other, at the same frame depth
+        assert_ok_eq!(zmachine.variable(0x10), 2);
+        assert_ok_eq!(zmachine.variable(0x11), 2);
+        assert_ok_eq!(zmachine.variable(0x12), 2);
+        assert!(zmachine.sound_interrupt().is_none());
+        assert!(!zmachine.state.in_sound_interrupt());
+    }
+
+    #[test]
+    fn test_run_stream_1_disabled() {
+        let mut map = test_map(5);
+        // OUTPUT_STREAM #-1
+        map[0x400..0x404].copy_from_slice(&[0xF3, 0x3F, 0xFF, 0xFF]);
+        // PRINT "hi"
+        map[0x404..0x407].copy_from_slice(&[0xB2, 0xB5, 0xC5]);
+        // AREAD #$0300 #0 -> G00
+        map[0x407..0x40D].copy_from_slice(&[0xE4, 0x1F, 0x03, 0x00, 0x00, 0x10]);
+        // DIV #1 #0 -> G01
+        map[0x40D..0x411].copy_from_slice(&[0x17, 0x01, 0x00, 0x11]);
+        // QUIT
+        map[0x411] = 0xBA;
+        map[0x300] = 20;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['l', 'o', 'o', 'k', '\r']);
+        assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.read_byte(0x301), 4);
+        // The player still sees what they typed and the error, but not the game's output
+        assert!(print().starts_with("look"));
+        assert!(!print().contains("hi"));
+        assert_eq!(
+            errors(),
+            [(
+                "Divide by zero: $0040d: 17 01 00 11  DIV #01 #00 -> G01, [1, 0]".to_string(),
+                true
+            )]
+        );
+    }
+
+    // Run ADD #1 #2 -> G00, STORE G01 #1, QUIT with the instruction log filtered
+    fn instruction_log(filter: InstructionLogFilter) -> Vec<String> {
+        let mut map = test_map(5);
+        map[0x400..0x408].copy_from_slice(&[0x14, 0x01, 0x02, 0x10, 0x0D, 0x11, 0x01, 0xBA]);
+        let m = Memory::new(map);
+        let mut config = Config::default();
+        config.set_instruction_log_filter(filter);
+        let mut zmachine = assert_ok!(ZMachine::new(m, config, None, "test"));
+        capture_log("app::instruction");
+        assert!(zmachine.run().is_ok());
+        log()
+    }
+
+    #[test]
+    fn test_run_instruction_log() {
+        let log = instruction_log(InstructionLogFilter::default());
+        assert_eq!(
+            log,
+            [
+                "dispatch: $00400: 14 01 02 10  ADD #01 #02 -> G00",
+                "Operand values:  #01 #02",
+                "Store result: G00 = #0003",
+                "dispatch: $00404: 0d 11 01  STORE #11 #01",
+                "Operand values:  #11 #01",
+                "dispatch: $00407: ba  QUIT",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_instruction_log_opcodes() {
+        let filter = assert_ok!(InstructionLogFilter::new(true, &["store", "quit"], &[]));
+        let log = instruction_log(filter);
+        assert_eq!(
+            log,
+            [
+                "dispatch: $00404: 0d 11 01  STORE #11 #01",
+                "Operand values:  #11 #01",
+                "dispatch: $00407: ba  QUIT",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_instruction_log_ranges() {
+        let filter = assert_ok!(InstructionLogFilter::new(true, &[], &[(0x400, 0x403)]));
+        let log = instruction_log(filter);
+        assert_eq!(
+            log,
+            [
+                "dispatch: $00400: 14 01 02 10  ADD #01 #02 -> G00",
+                "Operand values:  #01 #02",
+                "Store result: G00 = #0003",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_instruction_log_disabled() {
+        let filter = assert_ok!(InstructionLogFilter::new(false, &[], &[]));
+        assert!(instruction_log(filter).is_empty());
+    }
+
+    // Collects traced instructions, operand values, and stores as text
+    #[derive(Debug)]
+    struct TestSink(Rc<RefCell<Vec<String>>>);
+
+    impl TraceSink for TestSink {
+        fn instruction(&mut self, instruction: &Instruction) {
+            let name = instruction.opcode().name();
+            self.0
+                .borrow_mut()
+                .push(format!("{:05x} {}", instruction.address(), name));
+        }
+
+        fn operands(&mut self, _: &Instruction, values: &[u16]) {
+            self.0.borrow_mut().push(format!("{:?}", values));
+        }
+
+        fn store(&mut self, store: &StoreResult, value: u16) {
+            self.0.borrow_mut().push(format!("{} = {}", store, value));
+        }
+    }
+
+    #[test]
+    fn test_run_trace_sink() {
+        let mut map = test_map(5);
+        // ADD #1 G00 -> G01, MUL G01 #3 -> (SP), QUIT
+        map[0x400..0x409].copy_from_slice(&[0x34, 0x01, 0x10, 0x11, 0x56, 0x11, 0x03, 0x00, 0xBA]);
+        set_variable(&mut map, 0x10, 4);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        zmachine.set_trace_sink(Box::new(TestSink(trace.clone())));
+        assert!(zmachine.run().is_ok());
+        assert_eq!(
+            *trace.borrow(),
+            [
+                "00400 ADD",
+                "[1, 4]",
+                "G01 = 5",
+                "00404 MUL",
+                "[5, 3]",
+                "-(SP) = 15",
+                "00408 QUIT",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_trace_sink_return() {
+        let mut map = test_map(5);
+        // CALL_VS $500 -> G01, QUIT
+        map[0x400..0x406].copy_from_slice(&[0xE0, 0x3F, 0x01, 0x40, 0x11, 0xBA]);
+        // No locals, RET #07
+        map[0x500..0x503].copy_from_slice(&[0x00, 0x9B, 0x07]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        zmachine.set_trace_sink(Box::new(TestSink(trace.clone())));
+        assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.variable(0x11), 7);
+        assert_eq!(
+            *trace.borrow(),
+            [
+                "00400 CALL_VS",
+                "[320]",
+                "00501 RET",
+                "[7]",
+                "G01 = 7",
+                "00405 QUIT",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_trace_filter() {
+        let mut map = test_map(5);
+        // ADD #1 #2 -> G00, QUIT
+        map[0x400..0x405].copy_from_slice(&[0x14, 0x01, 0x02, 0x10, 0xBA]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        zmachine.set_trace_sink(Box::new(TestSink(trace.clone())));
+        let filter = assert_ok!(InstructionLogFilter::new(true, &["quit"], &[]));
+        zmachine.set_trace_filter(&filter);
+        assert!(zmachine.run().is_ok());
+        assert_eq!(*trace.borrow(), ["00404 QUIT"]);
+    }
+
+    // DIV/MOD #10 #0 -> G00, STORE G01 #1, QUIT
+    fn divide_by_zero(opcode: u8, handling: ErrorHandling) -> (ZMachine, Result<(), RuntimeError>) {
+        let mut map = test_map(3);
+        map[0x400] = opcode;
+        map[0x401] = 0x0A;
+        map[0x402] = 0x00;
+        map[0x403] = 0x10;
+        map[0x404] = 0x0D;
+        map[0x405] = 0x11;
+        map[0x406] = 0x01;
+        map[0x407] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, handling, 128.0),
+            None,
+            "test"
+        ));
+        assert!(zmachine.set_variable(0x10, 0x1234).is_ok());
+        let r = zmachine.run();
+        (zmachine, r)
+    }
+
+    #[test]
+    fn test_run_divide_by_zero() {
+        // DIV, MOD
+        for opcode in [0x17, 0x18] {
+            for handling in [
+                ErrorHandling::ContinueWarnAlways,
+                ErrorHandling::ContinueWarnOnce,
+                ErrorHandling::Ignore,
+            ] {
+                let (mut zmachine, r) = divide_by_zero(opcode, handling);
+                assert!(r.is_ok(), "{:02x}, {:?}", opcode, handling);
+                // 0 was stored and execution carried on with the next instruction
+                assert_ok_eq!(zmachine.variable(0x10), 0);
+                assert_ok_eq!(zmachine.variable(0x11), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_divide_by_zero_reported() {
+        let (_, r) = divide_by_zero(0x17, ErrorHandling::ContinueWarnOnce);
+        assert!(r.is_ok());
+        assert_eq!(errors().len(), 1);
+        assert!(errors()[0].0.starts_with("Divide by zero"));
+        assert!(errors()[0].1);
+    }
+
+    #[test]
+    fn test_run_divide_by_zero_ignored() {
+        let (_, r) = divide_by_zero(0x18, ErrorHandling::Ignore);
+        assert!(r.is_ok());
+        assert!(errors().is_empty());
+    }
+
+    #[test]
+    fn test_run_divide_by_zero_abort() {
+        for opcode in [0x17, 0x18] {
+            let (mut zmachine, r) = divide_by_zero(opcode, ErrorHandling::Abort);
+            let e = r.expect_err("expected a divide by zero");
+            assert_eq!(e.code(), ErrorCode::DivideByZero);
+            // Execution stopped at the division
+            assert_ok_eq!(zmachine.variable(0x11), 0);
+            assert_ok_eq!(zmachine.state.pc(), 0x400);
+        }
+    }
+
+    fn branch_outside_story(handling: ErrorHandling) -> (ZMachine, Result<(), RuntimeError>) {
+        let mut map = test_map(3);
+        // JZ #0 ?$80A, past the end of the story
+        map[0x400..0x404].copy_from_slice(&[0x90, 0x00, 0x84, 0x08]);
+        // STORE G01 #1, QUIT
+        map[0x404..0x408].copy_from_slice(&[0x0D, 0x11, 0x01, 0xBA]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(
+            m,
+            Config::new(9, 2, false, handling, 128.0),
+            None,
+            "test"
+        ));
+        let r = zmachine.run();
+        (zmachine, r)
+    }
+
+    #[test]
+    fn test_run_branch_outside_story() {
+        for handling in [
+            ErrorHandling::ContinueWarnAlways,
+            ErrorHandling::ContinueWarnOnce,
+            ErrorHandling::Ignore,
+        ] {
+            let (mut zmachine, r) = branch_outside_story(handling);
+            assert!(r.is_ok(), "{:?}", handling);
+            // The branch wasn't taken
+            assert_ok_eq!(zmachine.variable(0x11), 1);
+        }
+    }
+
+    #[test]
+    fn test_run_branch_outside_story_abort() {
+        let (mut zmachine, r) = branch_outside_story(ErrorHandling::Abort);
+        let e = r.expect_err("expected an invalid branch");
+        assert_eq!(e.code(), ErrorCode::InvalidBranch);
+        assert_ok_eq!(zmachine.variable(0x11), 0);
+        assert_ok_eq!(zmachine.state.pc(), 0x400);
+    }
+
+    #[test]
+    fn test_run_backtrace() {
+        let mut map = test_map(3);
+        mock_routine(&mut map, 0x600, &[0x1111, 0x2222]);
+        mock_routine(&mut map, 0x620, &[0x3333]);
+        mock_routine(&mut map, 0x640, &[]);
+        // RET_POPPED with nothing on the stack
+        map[0x641] = 0xB8;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert!(zmachine
+            .call_routine(0x600, &[], Some(StoreResult::new(0x403, 0x10)), 0x404)
+            .is_ok());
+        assert!(zmachine.call_routine(0x620, &[0x4444], None, 0x608).is_ok());
+        let pc = assert_ok!(zmachine.call_routine(0x640, &[], None, 0x627));
+        assert!(zmachine.state.set_pc(pc).is_ok());
+        capture_log("app::state");
+        let e = zmachine.run().expect_err("expected a stack underflow");
+        assert!(!e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::StackUnderflow);
+
+        let backtrace = zmachine.backtrace();
+        assert_eq!(
+            backtrace
+                .iter()
+                .map(|f| (f.address, f.packed_address, f.return_address))
+                .collect::<Vec<(usize, Option<u16>, usize)>>(),
+            [
+                (0x640, Some(0x320), 0x627),
+                (0x620, Some(0x310), 0x608),
+                (0x600, Some(0x300), 0x404),
+                (0, None, 0),
+            ]
+        );
+        let log = log();
+        let start = assert_some!(log
+            .iter()
+            .position(|l| l.ends_with("Popped an empty stack")));
+        assert_eq!(
+            log[start + 1..start + 5],
+            [
+                "#0 $000640 (packed $0320) locals [] -> $000627",
+                "#1 $000620 (packed $0310) locals [4444] -> $000608",
+                "#2 $000600 (packed $0300) locals [1111 2222] -> $000404 store G00",
+                "#3 main",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_infinite_loop() {
+        let mut map = test_map(5);
+        // JUMP to itself
+        map[0x400] = 0x8C;
+        map[0x401] = 0xFF;
+        map[0x402] = 0xFF;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.loop_threshold = 100;
+        let e = zmachine.run().expect_err("expected an infinite loop error");
+        assert!(!e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::InfiniteLoop);
+        assert_eq!(e.message(), "Infinite loop detected at $000400");
+    }
+
+    #[test]
+    fn test_run_counting_loop() {
+        let mut map = test_map(5);
+        // INC_CHK G00 #05 ?~(self), then QUIT
+        map[0x400] = 0x05;
+        map[0x401] = 0x10;
+        map[0x402] = 0x05;
+        map[0x403] = 0x3F;
+        map[0x404] = 0xFD;
+        map[0x405] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.loop_threshold = 1;
+        // The variable changes each time, so this isn't an infinite loop
+        assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.variable(0x10), 6);
+    }
+
+    #[test]
+    fn test_run_interrupted() {
+        let mut map = test_map(5);
+        // NOP and QUIT
+        map[0x400] = 0xB4;
+        map[0x401] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_interrupt();
+        let e = zmachine.run().expect_err("expected an interrupt");
+        assert!(!e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::Interrupted);
+        assert_eq!(e.message(), "Interrupted by user");
+    }
+
+    // READ #0300 #0000 -> G00, then QUIT
+    fn read_map() -> Vec<u8> {
+        let mut map = test_map(5);
+        map[0x300] = 10;
+        map[0x400] = 0xE4;
+        map[0x401] = 0x0F;
+        map[0x402] = 0x03;
+        map[0x403] = 0x00;
+        map[0x404] = 0x00;
+        map[0x405] = 0x00;
+        map[0x406] = 0x10;
+        map[0x407] = 0xBA;
+        map
+    }
+
+    fn need_line(preloaded: &[char]) -> Advance {
+        Advance::NeedInput {
+            kind: InputKind::Line {
+                max_length: 10,
+                terminators: vec![0x0d],
+            },
+            timeout: None,
+            preloaded: preloaded.iter().map(|c| *c as u16).collect(),
+        }
+    }
+
+    #[test]
+    fn test_advance_read() {
+        let m = Memory::new(read_map());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert_ok_eq!(zmachine.advance(None), need_line(&[]));
+        assert_ok_eq!(zmachine.state.pc(), 0x400);
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char('h' as u16))),
+            need_line(&['h'])
+        );
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char('i' as u16))),
+            need_line(&['h', 'i'])
+        );
+        assert_ok_eq!(zmachine.advance(None), need_line(&['h', 'i']));
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char(0x0d))),
+            Advance::Quit
+        );
+        assert_ok_eq!(zmachine.read_byte(0x301), 2);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'h');
+        assert_ok_eq!(zmachine.read_byte(0x303), b'i');
+        assert_ok_eq!(zmachine.variable(0x10), 0x0d);
+        assert_eq!(zmachine.turns, 1);
+    }
+
+    #[test]
+    fn test_advance_read_terminators() {
+        let mut map = read_map();
+        map[0x2E] = 0x03;
+        map[0x2F] = 0x40;
+        // Up arrow and a key that can't end input
+        map[0x340] = 129;
+        map[0x341] = b'a';
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        assert_ok_eq!(
+            zmachine.advance(None),
+            Advance::NeedInput {
+                kind: InputKind::Line {
+                    max_length: 10,
+                    terminators: vec![0x0d, 129],
+                },
+                timeout: None,
+                preloaded: Vec::new(),
+            }
+        );
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char(129))),
+            Advance::Quit
+        );
+        assert_ok_eq!(zmachine.variable(0x10), 129);
+    }
+
+    #[test]
+    fn test_advance_read_command() {
+        let m = Memory::new(read_map());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.push_commands(&["look".to_string()]);
+        assert!(zmachine.io.set_input_stream(1).is_ok());
+        // Input stream 1 doesn't wait for input
+        assert_ok_eq!(zmachine.advance(None), Advance::Quit);
+        assert_ok_eq!(zmachine.read_byte(0x301), 4);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'l');
+    }
+
+    #[test]
+    fn test_advance_read_char() {
+        let mut map = test_map(5);
+        // READ_CHAR #01 -> G00, then QUIT
+        map[0x400] = 0xF6;
+        map[0x401] = 0x7F;
+        map[0x402] = 0x01;
+        map[0x403] = 0x10;
+        map[0x404] = 0xBA;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        let need = Advance::NeedInput {
+            kind: InputKind::Char,
+            timeout: None,
+            preloaded: Vec::new(),
+        };
+        assert_ok_eq!(zmachine.advance(None), need);
+        // Line editing keys are ignored
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char(KEY_ERASE_LINE))),
+            need
+        );
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char('x' as u16))),
+            Advance::Quit
+        );
+        assert_ok_eq!(zmachine.variable(0x10), 'x' as u16);
+    }
+
+    #[test]
+    fn test_advance_read_char_timeout() {
+        let mut map = test_map(5);
+        // READ_CHAR #01 #0A #0180 -> G00, then QUIT
+        map[0x400..0x408].copy_from_slice(&[0xF6, 0x53, 0x01, 0x0A, 0x01, 0x80, 0x10, 0xBA]);
+        // RTRUE, ending the read
+        mock_routine(&mut map, 0x600, &[]);
+        map[0x601] = 0xB0;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_clock(1_000_000);
+        let need = |millis| Advance::NeedInput {
+            kind: InputKind::Char,
+            timeout: Some(Duration::from_millis(millis)),
+            preloaded: Vec::new(),
+        };
+        // The timeout is one second
+        assert_ok_eq!(zmachine.advance(None), need(1000));
+        set_clock(1_000_010);
+        assert_ok_eq!(zmachine.advance(None), need(990));
+        set_clock(1_000_999);
+        assert_ok_eq!(zmachine.advance(None), need(1));
+        set_clock(1_001_000);
+        assert_ok_eq!(zmachine.advance(None), Advance::Quit);
+        assert_ok_eq!(zmachine.variable(0x10), 0);
+    }
+
+    #[test]
+    fn test_advance_read_char_timeout_0() {
+        let mut map = test_map(5);
+        // READ_CHAR #01 #00 #0180 -> G00, then QUIT
+        map[0x400..0x408].copy_from_slice(&[0xF6, 0x53, 0x01, 0x00, 0x01, 0x80, 0x10, 0xBA]);
+        mock_routine(&mut map, 0x600, &[]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_clock(1_000_000);
+        let need = Advance::NeedInput {
+            kind: InputKind::Char,
+            timeout: None,
+            preloaded: Vec::new(),
+        };
+        assert_ok_eq!(zmachine.advance(None), need);
+        assert!(!zmachine.read_interrupt_pending());
+        set_clock(2_000_000);
+        assert_ok_eq!(zmachine.advance(None), need);
+        assert_eq!(zmachine.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_advance_read_timeout() {
+        let mut map = read_map();
+        // READ #0300 #0000 #0A #0180 -> G00, then QUIT
+        map[0x400..0x40B].copy_from_slice(&[
+            0xE4, 0x04, 0x03, 0x00, 0x00, 0x00, 0x0A, 0x01, 0x80, 0x10, 0xBA,
+        ]);
+        // RTRUE, ending the read
+        mock_routine(&mut map, 0x600, &[]);
+        map[0x601] = 0xB0;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        set_clock(1_000_000);
+        assert_ok!(zmachine.advance(None));
+        assert_ok_eq!(
+            zmachine.advance(Some(InputEvent::from_char('h' as u16))),
+            Advance::NeedInput {
+                kind: InputKind::Line {
+                    max_length: 10,
+                    terminators: vec![0x0d],
+                },
+                timeout: Some(Duration::from_millis(1000)),
+                preloaded: vec!['h' as u16],
+            }
+        );
+        set_clock(1_000_010);
+        assert_ok_eq!(
+            zmachine.advance(None),
+            Advance::NeedInput {
+                kind: InputKind::Line {
+                    max_length: 10,
+                    terminators: vec![0x0d],
+                },
+                timeout: Some(Duration::from_millis(990)),
+                preloaded: vec!['h' as u16],
+            }
+        );
+        set_clock(1_001_000);
+        assert_ok_eq!(zmachine.advance(None), Advance::Quit);
+        assert_ok_eq!(zmachine.variable(0x10), 0);
+    }
+
+    #[test]
+    fn test_advance_output() {
+        let mut map = test_map(5);
+        // JUMP to itself
+        map[0x400] = 0x8C;
+        map[0x401] = 0xFF;
+        map[0x402] = 0xFF;
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        zmachine.loop_threshold = 0;
+        assert_ok_eq!(zmachine.advance(None), Advance::Output);
+        assert_eq!(zmachine.instruction_count, ADVANCE_INSTRUCTIONS);
+    }
+
+    #[test]
+    fn test_run_read_paste() {
+        let mut map = read_map();
+        // Two more READs into their own buffers before QUIT
+        map[0x320] = 10;
+        map[0x340] = 10;
+        map[0x407..0x416].copy_from_slice(&[
+            0xE4, 0x0F, 0x03, 0x20, 0x00, 0x00, 0x11, 0xE4, 0x0F, 0x03, 0x40, 0x00, 0x00, 0x12,
+            0xBA,
+        ]);
+        let m = Memory::new(map);
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['n', '\r', 'u', 'p', '\r', 'x', '\r']);
+        assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.read_byte(0x301), 1);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'n');
+        assert_ok_eq!(zmachine.read_byte(0x321), 2);
+        assert_ok_eq!(zmachine.read_byte(0x322), b'u');
+        assert_ok_eq!(zmachine.read_byte(0x323), b'p');
+        assert_ok_eq!(zmachine.read_byte(0x341), 1);
+        assert_ok_eq!(zmachine.read_byte(0x342), b'x');
+    }
+
+    #[test]
+    fn test_run_read() {
+        let m = Memory::new(read_map());
+        let mut zmachine = assert_ok!(ZMachine::new(m, Config::default(), None, "test"));
+        input(&['h', 'i', '\r']);
         assert!(zmachine.run().is_ok());
+        assert_ok_eq!(zmachine.read_byte(0x301), 2);
+        assert_ok_eq!(zmachine.read_byte(0x302), b'h');
+        assert_ok_eq!(zmachine.read_byte(0x303), b'i');
+        assert_ok_eq!(zmachine.variable(0x10), 0x0d);
     }
 }
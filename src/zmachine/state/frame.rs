@@ -1,6 +1,8 @@
+use std::fmt;
+
 use crate::instruction::StoreResult;
 use crate::quetzal::{Stk, Stks};
-use crate::{error::*, fatal_error};
+use crate::{error::*, fatal_error, recoverable_error};
 
 #[derive(Debug)]
 pub struct Frame {
@@ -13,6 +15,67 @@ pub struct Frame {
     return_address: usize,
     input_interrupt: bool,
     sound_interrupt: bool,
+    read_interrupt: ReadInterrupt,
+}
+
+/// Bookkeeping for a timed READ or READ_CHAR, kept with the frame that issued it so that a
+/// read made by the interrupt routine has its own and leaves the outer read's alone
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReadInterrupt {
+    /// Set when the timed read starts
+    pub pending: bool,
+    /// Set when the interrupt routine is called, then to the value it returns
+    pub result: Option<u16>,
+    /// Set when the interrupt routine prints, so the input so far is printed again
+    pub printed: bool,
+}
+
+/// A snapshot of one routine frame, for backtraces
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrameInfo {
+    /// Routine address, or 0 for the dummy frame outside of any routine
+    pub address: usize,
+    /// Routine address as it would appear in a CALL operand, if it can be packed
+    pub packed_address: Option<u16>,
+    pub return_address: usize,
+    pub result: Option<StoreResult>,
+    pub local_variables: Vec<u16>,
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.address == 0 {
+            return write!(f, "main");
+        }
+
+        write!(f, "${:06x}", self.address)?;
+        if let Some(packed) = self.packed_address {
+            write!(f, " (packed ${:04x})", packed)?;
+        }
+        write!(
+            f,
+            " locals [{}] -> ${:06x}",
+            self.local_variables
+                .iter()
+                .map(|v| format!("{:04x}", v))
+                .collect::<Vec<String>>()
+                .join(" "),
+            self.return_address
+        )?;
+        if let Some(result) = self.result {
+            write!(f, " store {}", result)?;
+        }
+        Ok(())
+    }
+}
+
+/// One numbered line per frame, in the order given
+pub fn format_backtrace(backtrace: &[FrameInfo]) -> Vec<String> {
+    backtrace
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| format!("#{} {}", i, frame))
+        .collect()
 }
 
 impl From<&Stk> for Frame {
@@ -34,14 +97,45 @@ impl From<&Stk> for Frame {
     }
 }
 
-impl From<&Stks> for Vec<Frame> {
-    fn from(value: &Stks) -> Self {
-        let mut v = Vec::new();
-        for sf in value.stks() {
-            v.push(Frame::from(sf))
+/// Frames from a Quetzal Stks chunk, checking that there is at least the dummy frame (or
+/// the main routine in V6).
+///
+/// Quetzal stacks start with a dummy frame holding the evaluation stack outside of any
+/// routine, except in V6 where the first frame is the main routine (Quetzal §4.11).  The
+/// frame stack always starts with that base frame, so one is added for V6.  Outside of V6,
+/// a first frame with a return address, local variables, or arguments is rejected.
+pub fn frames_from_stks(stks: &Stks, version: u8) -> Result<Vec<Frame>, RuntimeError> {
+    if stks.stks().is_empty() {
+        return recoverable_error!(
+            ErrorCode::Quetzal,
+            "Stks chunk has no frames, expected {}",
+            if version == 6 {
+                "the main routine"
+            } else {
+                "a dummy frame"
+            }
+        );
+    }
+
+    let mut v = Vec::new();
+    if version == 6 {
+        v.push(Frame::new(0, 0, &[], 0, &[], None, 0));
+    } else {
+        let dummy = &stks.stks()[0];
+        if dummy.return_address() != 0 || !dummy.variables().is_empty() || dummy.arguments() != 0 {
+            return recoverable_error!(
+                ErrorCode::Quetzal,
+                "First Stks frame is not a dummy frame: return address ${:06x}, {} local variables, arguments {:02x}",
+                dummy.return_address(),
+                dummy.variables().len(),
+                dummy.arguments()
+            );
         }
-        v
     }
+    for sf in stks.stks() {
+        v.push(Frame::from(sf))
+    }
+    Ok(v)
 }
 
 impl Frame {
@@ -64,6 +158,7 @@ impl Frame {
             return_address,
             input_interrupt: false,
             sound_interrupt: false,
+            read_interrupt: ReadInterrupt::default(),
         }
     }
 
@@ -83,10 +178,6 @@ impl Frame {
         &self.local_variables
     }
 
-    pub fn local_variables_mut(&mut self) -> &mut Vec<u16> {
-        &mut self.local_variables
-    }
-
     pub fn argument_count(&self) -> u8 {
         self.argument_count
     }
@@ -111,6 +202,14 @@ impl Frame {
         self.sound_interrupt = v;
     }
 
+    pub fn read_interrupt(&self) -> ReadInterrupt {
+        self.read_interrupt
+    }
+
+    pub fn read_interrupt_mut(&mut self) -> &mut ReadInterrupt {
+        &mut self.read_interrupt
+    }
+
     pub fn pop(&mut self) -> Result<u16, RuntimeError> {
         if let Some(v) = self.stack.pop() {
             debug!(target: "app::state", "Pop {:04x} [{}]", v, self.stack.len());
@@ -212,7 +311,7 @@ impl Frame {
     pub fn call_routine(
         address: usize,
         initial_pc: usize,
-        arguments: &Vec<u16>,
+        arguments: &[u16],
         local_variables: Vec<u16>,
         result: Option<StoreResult>,
         return_address: usize,
@@ -243,6 +342,56 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_frame_info_display() {
+        let info = FrameInfo {
+            address: 0x600,
+            packed_address: Some(0x300),
+            return_address: 0x405,
+            result: Some(StoreResult::new(0x404, 0x11)),
+            local_variables: vec![0x1111, 0x2222],
+        };
+        assert_eq!(
+            info.to_string(),
+            "$000600 (packed $0300) locals [1111 2222] -> $000405 store G01"
+        );
+        let info = FrameInfo {
+            address: 0x601,
+            packed_address: None,
+            return_address: 0x405,
+            result: None,
+            local_variables: vec![],
+        };
+        assert_eq!(info.to_string(), "$000601 locals [] -> $000405");
+    }
+
+    #[test]
+    fn test_format_backtrace() {
+        let frames = [
+            FrameInfo {
+                address: 0x600,
+                packed_address: Some(0x300),
+                return_address: 0x405,
+                result: None,
+                local_variables: vec![1],
+            },
+            FrameInfo {
+                address: 0,
+                packed_address: None,
+                return_address: 0,
+                result: None,
+                local_variables: vec![],
+            },
+        ];
+        assert_eq!(
+            format_backtrace(&frames),
+            [
+                "#0 $000600 (packed $0300) locals [0001] -> $000405",
+                "#1 main"
+            ]
+        );
+    }
+
     #[test]
     fn test_from_stackframe() {
         let sf = Stk::new(
@@ -288,25 +437,18 @@ mod tests {
     #[test]
     fn test_vec_from_stks() {
         let stks = Stks::new(vec![
-            Stk::new(
-                0x1234,
-                0x13,
-                0x80,
-                1,
-                &[0x5678, 0x9abc, 0xdef0],
-                &[0x1111, 0x2222],
-            ),
+            Stk::new(0, 0x10, 0, 0, &[], &[0x1111, 0x2222]),
             Stk::new(0x4321, 0x02, 0x80, 2, &[0x8765, 0xcba9], &[]),
         ]);
-        let frames: Vec<Frame> = Vec::from(&stks);
+        let frames = assert_ok!(frames_from_stks(&stks, 5));
         assert_eq!(frames.len(), 2);
         assert_eq!(frames[0].address(), 0);
         assert_eq!(frames[0].pc(), 0);
-        assert_eq!(frames[0].local_variables(), &[0x5678, 0x9abc, 0xdef0]);
-        assert_eq!(frames[0].argument_count(), 0x1);
+        assert!(frames[0].local_variables().is_empty());
+        assert_eq!(frames[0].argument_count(), 0);
         assert_eq!(frames[0].stack(), &[0x1111, 0x2222]);
         assert!(frames[0].result().is_none());
-        assert_eq!(frames[0].return_address(), 0x1234);
+        assert_eq!(frames[0].return_address(), 0);
         assert_eq!(frames[1].address(), 0);
         assert_eq!(frames[1].pc(), 0);
         assert_eq!(frames[1].local_variables(), &[0x8765, 0xcba9]);
@@ -316,6 +458,41 @@ mod tests {
         assert_eq!(frames[1].return_address(), 0x4321);
     }
 
+    #[test]
+    fn test_frames_from_stks_v6() {
+        let stks = Stks::new(vec![Stk::new(0, 0x12, 0, 0, &[0x1234, 0x5678], &[0x1111])]);
+        let frames = assert_ok!(frames_from_stks(&stks, 6));
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].local_variables().is_empty());
+        assert!(frames[0].stack().is_empty());
+        assert_eq!(frames[1].local_variables(), &[0x1234, 0x5678]);
+        assert_eq!(frames[1].stack(), &[0x1111]);
+    }
+
+    #[test]
+    fn test_frames_from_stks_not_dummy() {
+        for first in [
+            Stk::new(0x1234, 0x10, 0, 0, &[], &[]),
+            Stk::new(0, 0x11, 0, 0, &[0x1234], &[]),
+            Stk::new(0, 0x10, 0, 1, &[], &[]),
+        ] {
+            let stks = Stks::new(vec![first]);
+            for version in [3, 5, 8] {
+                let e = frames_from_stks(&stks, version).expect_err("not a dummy frame");
+                assert_eq!(e.code(), ErrorCode::Quetzal);
+            }
+        }
+    }
+
+    #[test]
+    fn test_frames_from_stks_empty() {
+        let stks = Stks::new(Vec::new());
+        for version in [3, 5, 6, 8] {
+            let e = frames_from_stks(&stks, version).expect_err("no frames");
+            assert_eq!(e.code(), ErrorCode::Quetzal);
+        }
+    }
+
     #[test]
     fn test_constructor() {
         let frame = Frame::new(
@@ -526,7 +703,7 @@ mod tests {
         let frame = assert_ok!(Frame::call_routine(
             0x1234,
             0x1235,
-            &vec![0x1122, 0x3344],
+            &[0x1122, 0x3344],
             vec![0x9988, 0x7766, 0x5544, 0x3322],
             None,
             0x4321,
@@ -547,7 +724,7 @@ mod tests {
         let frame = assert_ok!(Frame::call_routine(
             0x1234,
             0x1235,
-            &vec![0x1122, 0x3344],
+            &[0x1122, 0x3344],
             vec![0x9988, 0x7766, 0x5544, 0x3322],
             Some(StoreResult::new(0x1001, 0x80)),
             0x4321,
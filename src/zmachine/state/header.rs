@@ -1,7 +1,18 @@
-use crate::error::RuntimeError;
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    recoverable_error,
+};
 
 use super::State;
 
+/// Highest Z-Machine Standard revision the interpreter complies with, as (major, minor).  It
+/// is written to the header unless the configuration asks for an earlier revision.  Some of
+/// the 1.1 additions are supported, but not all of them, so 1.0 is advertised.
+pub const STANDARD_VERSION: (u8, u8) = (1, 0);
+
+/// Interpreter number written to the header (6, IBM PC) and the interpreter version
+pub const INTERPRETER_ID: (u8, u8) = (6, b'Z');
+
 pub enum HeaderField {
     Version = 0x00,
     Flags1 = 0x01,
@@ -31,6 +42,7 @@ pub enum HeaderField {
     DefaultForeground = 0x2D,
     TerminatorTable = 0x2E,
     Revision = 0x32,
+    RevisionMinor = 0x33,
     AlphabetTable = 0x34,
     ExtensionTable = 0x36,
     InformVersion = 0x3C,
@@ -59,11 +71,138 @@ pub enum Flags1v4 {
 pub enum Flags2 {
     Transcripting = 0x0001,       // bit 0
     ForceFixedPitch = 0x0002,     // bit 1
+    RequestRedraw = 0x0004,       // bit 2
     RequestPictures = 0x0008,     // bit 3
     RequestUndo = 0x0010,         // bit 4
     RequestMouse = 0x0020,        // bit 5
     RequestColours = 0x0040,      // bit 6
     RequestSoundEffects = 0x0080, // bit 7
+    RequestMenus = 0x0100,        // bit 8
+}
+
+// Flags 2 bits owned by the interpreter rather than the game
+const INTERPRETER_FLAGS2: u16 = Flags2::Transcripting as u16 | Flags2::ForceFixedPitch as u16;
+
+/// Header values that belong to the interpreter rather than the game, which must survive
+/// a restore or restart overwriting dynamic memory (§6.1.2):
+///
+/// * the transcripting and fixed-pitch bits of Flags 2
+/// * interpreter number and version
+/// * screen lines and columns, default colours (V4+)
+/// * screen width and height, font width and height (V5+)
+/// * mouse coordinates, when the header extension table has room for them (V5+)
+/// * the standard revision
+///
+/// The interpreter number and standard revision are the ones the interpreter claims, not
+/// whatever the game may have written over them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InterpreterHeaderState {
+    flags2: u16,
+    interpreter: (u8, u8),
+    screen: (u8, u8),
+    // (foreground, background)
+    default_colors: (u8, u8),
+    // (width, height)
+    screen_size: (u16, u16),
+    font_size: (u8, u8),
+    mouse: Option<(u16, u16)>,
+    revision: (u8, u8),
+}
+
+/// Capture the interpreter-owned header values before dynamic memory is overwritten
+pub fn snapshot_interpreter_state(state: &State) -> Result<InterpreterHeaderState, RuntimeError> {
+    let mut snapshot = InterpreterHeaderState {
+        flags2: field_word(state, HeaderField::Flags2)? & INTERPRETER_FLAGS2,
+        interpreter: INTERPRETER_ID,
+        revision: state.standard_version(),
+        ..Default::default()
+    };
+
+    if state.version() > 3 {
+        snapshot.screen = (
+            field_byte(state, HeaderField::ScreenLines)?,
+            field_byte(state, HeaderField::ScreenColumns)?,
+        );
+        snapshot.default_colors = (
+            field_byte(state, HeaderField::DefaultForeground)?,
+            field_byte(state, HeaderField::DefaultBackground)?,
+        );
+    }
+
+    if state.version() > 4 {
+        snapshot.screen_size = (
+            field_word(state, HeaderField::ScreenWidth)?,
+            field_word(state, HeaderField::ScreenHeight)?,
+        );
+        snapshot.font_size = (
+            field_byte(state, HeaderField::FontWidth)?,
+            field_byte(state, HeaderField::FontHeight)?,
+        );
+        if extension_size(state)? > 1 {
+            let extension_table_address = field_word(state, HeaderField::ExtensionTable)? as usize;
+            snapshot.mouse = Some((
+                state.read_word(extension_table_address + 2)?,
+                state.read_word(extension_table_address + 4)?,
+            ));
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Write interpreter-owned header values captured by [`snapshot_interpreter_state`] back
+/// after dynamic memory has been overwritten.  The rest of Flags 2 is left as the game has it.
+pub fn apply_interpreter_state(
+    state: &mut State,
+    snapshot: &InterpreterHeaderState,
+) -> Result<(), RuntimeError> {
+    let flags2 = field_word(state, HeaderField::Flags2)?;
+    state.write_word(
+        HeaderField::Flags2 as usize,
+        (flags2 & !INTERPRETER_FLAGS2) | snapshot.flags2,
+    )?;
+    set_byte(
+        state,
+        HeaderField::InterpreterNumber,
+        snapshot.interpreter.0,
+    )?;
+    set_byte(
+        state,
+        HeaderField::InterpreterVersion,
+        snapshot.interpreter.1,
+    )?;
+    set_byte(state, HeaderField::Revision, snapshot.revision.0)?;
+    set_byte(state, HeaderField::RevisionMinor, snapshot.revision.1)?;
+
+    if state.version() > 3 {
+        set_byte(state, HeaderField::ScreenLines, snapshot.screen.0)?;
+        set_byte(state, HeaderField::ScreenColumns, snapshot.screen.1)?;
+        set_byte(
+            state,
+            HeaderField::DefaultForeground,
+            snapshot.default_colors.0,
+        )?;
+        set_byte(
+            state,
+            HeaderField::DefaultBackground,
+            snapshot.default_colors.1,
+        )?;
+    }
+
+    if state.version() > 4 {
+        set_word(state, HeaderField::ScreenWidth, snapshot.screen_size.0)?;
+        set_word(state, HeaderField::ScreenHeight, snapshot.screen_size.1)?;
+        set_byte(state, HeaderField::FontWidth, snapshot.font_size.0)?;
+        set_byte(state, HeaderField::FontHeight, snapshot.font_size.1)?;
+        if let Some((x, y)) = snapshot.mouse {
+            if extension_size(state)? > 1 {
+                set_extension(state, 1, x)?;
+                set_extension(state, 2, y)?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub fn field_byte(state: &State, field: HeaderField) -> Result<u8, RuntimeError> {
@@ -130,23 +269,41 @@ pub fn clear_flag2(state: &mut State, flag: Flags2) -> Result<(), RuntimeError>
     state.memory.write_word(HeaderField::Flags2 as usize, new)
 }
 
-pub fn set_extension(state: &mut State, index: usize, value: u16) -> Result<(), RuntimeError> {
+/// Number of words in the header extension table after its length word, or 0 when the game
+/// doesn't have one
+pub fn extension_size(state: &State) -> Result<usize, RuntimeError> {
     let extension_table_address = field_word(state, HeaderField::ExtensionTable)? as usize;
     if extension_table_address > 0 {
-        debug!(target: "app::state", "Set extension table word {} to {:04x}", index, value);
-        let table_size = state.read_word(extension_table_address)? as usize;
-        if table_size >= index {
-            state.write_word(extension_table_address + (index * 2), value)?;
-        }
+        Ok(state.read_word(extension_table_address)? as usize)
+    } else {
+        Ok(0)
     }
+}
 
-    Ok(())
+/// Set word `index` of the header extension table.  Word 0 is the table length, so it
+/// can't be set, and a word past the end of the table is an error rather than a write to
+/// whatever follows it.
+pub fn set_extension(state: &mut State, index: usize, value: u16) -> Result<(), RuntimeError> {
+    let table_size = extension_size(state)?;
+    if index == 0 || index > table_size {
+        return recoverable_error!(
+            ErrorCode::IllegalMemoryAccess,
+            "Header extension word {} is outside the table of {} words",
+            index,
+            table_size
+        );
+    }
+
+    debug!(target: "app::state", "Set extension table word {} to {:04x}", index, value);
+    let extension_table_address = field_word(state, HeaderField::ExtensionTable)? as usize;
+    state.write_word(extension_table_address + (index * 2), value)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        assert_ok_eq,
+        assert_ok, assert_ok_eq,
+        error::ErrorCode,
         test_util::{mock_state, test_map},
         zmachine::state::header::{self, Flags1v3, Flags1v4, Flags2, HeaderField},
     };
@@ -793,4 +950,117 @@ mod tests {
         assert_ok_eq!(header::flag2(&state, Flags2::RequestUndo), 0);
         assert_ok_eq!(header::flag2(&state, Flags2::Transcripting), 0);
     }
+
+    #[test]
+    fn test_set_extension() {
+        let mut map = test_map(5);
+        map[0x37] = 0x80;
+        map[0x81] = 2;
+        let mut state = mock_state(map);
+        assert_ok_eq!(header::extension_size(&state), 2);
+        assert!(header::set_extension(&mut state, 1, 0x1234).is_ok());
+        assert!(header::set_extension(&mut state, 2, 0x5678).is_ok());
+        assert_ok_eq!(state.read_word(0x80), 2);
+        assert_ok_eq!(state.read_word(0x82), 0x1234);
+        assert_ok_eq!(state.read_word(0x84), 0x5678);
+    }
+
+    #[test]
+    fn test_set_extension_out_of_range() {
+        let mut map = test_map(5);
+        map[0x37] = 0x80;
+        map[0x81] = 1;
+        map[0x84] = 0xAB;
+        let mut state = mock_state(map);
+        let e = header::set_extension(&mut state, 2, 0x5678).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::IllegalMemoryAccess);
+        assert!(header::set_extension(&mut state, 0, 5).is_err());
+        assert_ok_eq!(state.read_word(0x80), 1);
+        assert_ok_eq!(state.read_word(0x84), 0xAB00);
+    }
+
+    #[test]
+    fn test_set_extension_no_table() {
+        let mut state = mock_state(test_map(5));
+        assert_ok_eq!(header::extension_size(&state), 0);
+        assert!(header::set_extension(&mut state, 1, 0x1234).is_err());
+    }
+
+    #[test]
+    fn test_interpreter_state_v3() {
+        let mut map = test_map(3);
+        map[0x10] = 0x00;
+        map[0x11] = 0x11;
+        map[0x20] = 24;
+        let mut state = mock_state(map);
+        let snapshot = assert_ok!(header::snapshot_interpreter_state(&state));
+
+        for b in 0x10..0x34 {
+            assert!(state.write_byte(b, 0xFF).is_ok());
+        }
+        assert!(header::apply_interpreter_state(&mut state, &snapshot).is_ok());
+        // Transcripting and fixed-pitch come from the snapshot, the other bits from the game
+        assert_ok_eq!(header::field_word(&state, HeaderField::Flags2), 0xFFFD);
+        assert_ok_eq!(
+            header::field_byte(&state, HeaderField::InterpreterNumber),
+            6
+        );
+        assert_ok_eq!(
+            header::field_byte(&state, HeaderField::InterpreterVersion),
+            b'Z'
+        );
+        assert_ok_eq!(header::field_byte(&state, HeaderField::Revision), 1);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::RevisionMinor), 0);
+        // V3 has no screen metrics in the header
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenLines), 0xFF);
+        assert_ok_eq!(
+            header::field_byte(&state, HeaderField::DefaultForeground),
+            0xFF
+        );
+    }
+
+    #[test]
+    fn test_interpreter_state_v5() {
+        let mut map = test_map(5);
+        map[0x11] = 0x03;
+        map[0x20] = 24;
+        map[0x21] = 80;
+        map[0x23] = 80;
+        map[0x25] = 24;
+        map[0x26] = 1;
+        map[0x27] = 1;
+        map[0x2C] = 2;
+        map[0x2D] = 9;
+        map[0x37] = 0x80;
+        map[0x81] = 2;
+        map[0x83] = 12;
+        map[0x85] = 5;
+        let mut state = mock_state(map.clone());
+        state.set_standard_version((1, 0));
+        let snapshot = assert_ok!(header::snapshot_interpreter_state(&state));
+
+        for b in 0x10..0x34 {
+            assert!(state.write_byte(b, 0).is_ok());
+        }
+        assert!(state.write_word(0x82, 0).is_ok());
+        assert!(state.write_word(0x84, 0).is_ok());
+        assert!(header::apply_interpreter_state(&mut state, &snapshot).is_ok());
+        assert_ok_eq!(header::field_word(&state, HeaderField::Flags2), 0x03);
+        assert_ok_eq!(
+            header::field_byte(&state, HeaderField::InterpreterNumber),
+            6
+        );
+        assert_ok_eq!(
+            header::field_byte(&state, HeaderField::InterpreterVersion),
+            b'Z'
+        );
+        for (b, value) in map.iter().enumerate().take(0x2E).skip(0x20) {
+            assert_ok_eq!(state.read_byte(b), *value);
+        }
+        assert_ok_eq!(header::field_byte(&state, HeaderField::Revision), 1);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::RevisionMinor), 0);
+        assert_ok_eq!(state.read_word(0x82), 12);
+        assert_ok_eq!(state.read_word(0x84), 5);
+        assert_ok_eq!(header::snapshot_interpreter_state(&state), snapshot);
+    }
 }
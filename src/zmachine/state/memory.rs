@@ -1,13 +1,18 @@
 use std::{fmt, fs::File, io::Read};
 
-use crate::{error::*, fatal_error};
+use crate::{error::*, fatal_error, recoverable_error};
 
 use super::header::HeaderField;
 
+/// Size of the pages used to track changes to dynamic memory
+pub const PAGE_SIZE: usize = 256;
+
 pub struct Memory {
     version: u8,
     map: Vec<u8>,
     dynamic: Vec<u8>,
+    // One bit per page of dynamic memory that may differ from the original
+    dirty: Vec<u64>,
 }
 
 impl fmt::Debug for Memory {
@@ -21,6 +26,25 @@ impl fmt::Debug for Memory {
     }
 }
 
+// Multiplier for the header file length, by version
+fn length_scale(version: u8) -> usize {
+    match version {
+        3 => 2,
+        4 | 5 => 4,
+        _ => 8,
+    }
+}
+
+/// Largest story file a version can address: 128K for V3, 256K for V4-5, and 512K
+/// for later versions
+pub fn max_file_size(version: u8) -> usize {
+    match version {
+        1..=3 => 0x20000,
+        4 | 5 => 0x40000,
+        _ => 0x80000,
+    }
+}
+
 pub fn word_value(hb: u8, lb: u8) -> u16 {
     (((hb as u16) << 8) & 0xFF00) + ((lb as u16) & 0xFF)
 }
@@ -44,17 +68,80 @@ impl TryFrom<&mut File> for Memory {
 }
 
 impl Memory {
-    pub fn new(map: Vec<u8>) -> Memory {
-        let version = map[0];
-        let static_mark = word_value(
-            map[HeaderField::StaticMark as usize],
-            map[HeaderField::StaticMark as usize + 1],
-        ) as usize;
+    /// Memory for a story file.  A file shorter than the length in its header, as with
+    /// some truncated shareware copies, is padded with zeros to that length.  Bytes after
+    /// the header length are kept, but aren't part of the checksum.  A static memory mark
+    /// past the end of the file is clamped to it; [`State::new`](super::State::new) rejects
+    /// a file too short to have a header.
+    pub fn new(mut map: Vec<u8>) -> Memory {
+        let version = map.first().copied().unwrap_or(0);
+        let length = HeaderField::FileLength as usize;
+        if map.len() > length + 1 {
+            let declared =
+                word_value(map[length], map[length + 1]) as usize * length_scale(version);
+            if declared > map.len() {
+                warn!(
+                    target: "app::state",
+                    "Story file is {:#x} bytes, shorter than the header length {:#x}; padding with zeros",
+                    map.len(),
+                    declared
+                );
+                map.resize(declared, 0);
+            }
+        }
+        if map.len() > max_file_size(version) {
+            warn!(
+                target: "app::state",
+                "Story file is {:#x} bytes, larger than the {:#x} bytes a version {} story can address",
+                map.len(),
+                max_file_size(version),
+                version
+            );
+        }
+        let static_mark = match map.get(HeaderField::StaticMark as usize..) {
+            Some([hb, lb, ..]) => usize::min(word_value(*hb, *lb) as usize, map.len()),
+            _ => map.len(),
+        };
         let dynamic = map[0..static_mark].to_vec();
+        let pages = dynamic.len().div_ceil(PAGE_SIZE);
         Memory {
             version,
             map,
             dynamic,
+            dirty: vec![0; pages.div_ceil(64)],
+        }
+    }
+
+    fn mark_dirty(&mut self, address: usize) {
+        if address < self.dynamic.len() {
+            let page = address / PAGE_SIZE;
+            self.dirty[page / 64] |= 1 << (page % 64);
+        }
+    }
+
+    fn is_dirty(&self, page: usize) -> bool {
+        self.dirty[page / 64] & (1 << (page % 64)) != 0
+    }
+
+    fn page_range(&self, page: usize) -> std::ops::Range<usize> {
+        page * PAGE_SIZE..usize::min((page + 1) * PAGE_SIZE, self.dynamic.len())
+    }
+
+    /// Pages of dynamic memory that have been written to and may differ from the original
+    /// story file.
+    pub fn dirty_pages(&self) -> Vec<usize> {
+        (0..self.dynamic.len().div_ceil(PAGE_SIZE))
+            .filter(|p| self.is_dirty(*p))
+            .collect()
+    }
+
+    /// Mark pages that are identical to the original story file as clean again.
+    pub fn clear_dirty(&mut self) {
+        for page in self.dirty_pages() {
+            let range = self.page_range(page);
+            if self.map[range.clone()] == self.dynamic[range] {
+                self.dirty[page / 64] &= !(1 << (page % 64));
+            }
         }
     }
 
@@ -62,19 +149,33 @@ impl Memory {
         self.map.len()
     }
 
+    /// A recoverable error if the story is larger than its version can address.  Anything
+    /// past the limit can't be reached with a packed address.
+    pub fn check_size(&self) -> Result<(), RuntimeError> {
+        if self.map.len() > max_file_size(self.version) {
+            recoverable_error!(
+                ErrorCode::FileTooLarge,
+                "Story file is {:#x} bytes, larger than the {:#x} bytes a version {} story can address",
+                self.map.len(),
+                max_file_size(self.version),
+                self.version
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Copy up to `length` bytes starting at `start`, truncated at the end of memory
     pub fn slice(&self, start: usize, length: usize) -> Vec<u8> {
-        let end = usize::min(start + length, self.map.len());
+        let start = usize::min(start, self.map.len());
+        let end = usize::min(start.saturating_add(length), self.map.len());
         self.map[start..end].to_vec()
     }
 
     pub fn checksum(&self) -> Result<u16, RuntimeError> {
         let mut checksum = 0;
-        let size = self.read_word(HeaderField::FileLength as usize)? as usize
-            * match self.version {
-                3 => 2,
-                4 | 5 => 4,
-                _ => 8,
-            };
+        let size =
+            self.read_word(HeaderField::FileLength as usize)? as usize * length_scale(self.version);
 
         for i in 0x40..self.dynamic.len() {
             checksum = u16::overflowing_add(checksum, self.dynamic[i] as u16).0;
@@ -87,28 +188,26 @@ impl Memory {
     }
 
     pub fn read_byte(&self, address: usize) -> Result<u8, RuntimeError> {
-        if address < self.map.len() {
-            Ok(self.map[address])
-        } else {
-            fatal_error!(
+        match self.map.get(address) {
+            Some(b) => Ok(*b),
+            None => fatal_error!(
                 ErrorCode::InvalidAddress,
                 "Byte address {:#06x} beyond end of memory ({:#06x})",
                 address,
-                self.map.len() - 1
-            )
+                self.map.len().saturating_sub(1)
+            ),
         }
     }
 
     pub fn read_word(&self, address: usize) -> Result<u16, RuntimeError> {
-        if address < self.map.len() - 1 {
-            Ok(word_value(self.map[address], self.map[address + 1]))
-        } else {
-            fatal_error!(
+        match self.map.get(address..address.saturating_add(2)) {
+            Some([hb, lb]) => Ok(word_value(*hb, *lb)),
+            _ => fatal_error!(
                 ErrorCode::InvalidAddress,
                 "Word address {:#06x} beyond end of memory ({:#06x})",
                 address,
-                self.map.len() - 1
-            )
+                self.map.len().saturating_sub(1)
+            ),
         }
     }
 
@@ -116,112 +215,142 @@ impl Memory {
         if address < self.map.len() {
             debug!(target: "app::state", "Write {:#02x} to ${:04x}", value, address);
             self.map[address] = value;
+            self.mark_dirty(address);
             Ok(())
         } else {
             fatal_error!(
                 ErrorCode::InvalidAddress,
                 "Byte address {:#06x} beyond end of memory ({:#06x})",
                 address,
-                self.map.len() - 1
+                self.map.len().saturating_sub(1)
             )
         }
     }
 
     pub fn write_word(&mut self, address: usize, value: u16) -> Result<(), RuntimeError> {
-        if address < self.map.len() - 2 {
+        if address < self.map.len().saturating_sub(1) {
             debug!(target: "app::state", "Write {:#04x} to ${:04x}", value, address);
             let (hb, lb) = byte_values(value);
             self.map[address] = hb;
             self.map[address + 1] = lb;
+            self.mark_dirty(address);
+            self.mark_dirty(address + 1);
             Ok(())
         } else {
             fatal_error!(
                 ErrorCode::InvalidAddress,
                 "Word address {:#06x} beyond end of memory ({:#06x})",
                 address,
-                self.map.len() - 1
+                self.map.len().saturating_sub(1)
             )
         }
     }
 
+    /// Compress dynamic memory against the original story file (CMem).  Pages that
+    /// haven't been written to are counted as unchanged without being compared.
     pub fn compress(&self) -> Vec<u8> {
+        fn push_run(cdata: &mut Vec<u8>, run_length: &mut usize) {
+            while *run_length > 0 {
+                let n = usize::min(*run_length, 256);
+                cdata.push(0);
+                cdata.push((n - 1) as u8);
+                *run_length -= n;
+            }
+        }
+
         let mut cdata: Vec<u8> = Vec::new();
         let mut run_length = 0;
-        let dynamic_len = self.dynamic.len();
-        for i in 0..dynamic_len {
-            let b = self.map[i] ^ self.dynamic[i];
-            if b == 0 {
-                if run_length == 255 {
-                    cdata.push(0);
-                    cdata.push(run_length);
-                    run_length = 0;
-                } else {
+        for page in 0..self.dynamic.len().div_ceil(PAGE_SIZE) {
+            let range = self.page_range(page);
+            if !self.is_dirty(page) {
+                run_length += range.len();
+                continue;
+            }
+
+            for i in range {
+                let b = self.map[i] ^ self.dynamic[i];
+                if b == 0 {
                     run_length += 1;
+                } else {
+                    push_run(&mut cdata, &mut run_length);
+                    cdata.push(b);
                 }
-            } else {
-                if run_length > 0 {
-                    cdata.push(0);
-                    cdata.push(run_length - 1);
-                    run_length = 0;
-                }
-                cdata.push(b);
             }
         }
 
-        if run_length > 0 {
-            cdata.push(0);
-            cdata.push(run_length - 1);
-        }
-
+        push_run(&mut cdata, &mut run_length);
         cdata
     }
 
     pub fn reset(&mut self) {
-        self.map[..][..self.dynamic.len()].copy_from_slice(&self.dynamic)
+        self.map[..][..self.dynamic.len()].copy_from_slice(&self.dynamic);
+        self.dirty.fill(0);
     }
 
-    pub fn restore(&mut self, data: &Vec<u8>) -> Result<(), RuntimeError> {
-        if data.len() != self.dynamic.len() {
-            fatal_error!(
+    /// Restore dynamic memory from an uncompressed (UMem) image.
+    ///
+    /// An image shorter than dynamic memory is padded with zeros.  A longer image is an
+    /// error and memory is left unchanged.
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), RuntimeError> {
+        if data.len() > self.dynamic.len() {
+            recoverable_error!(
                 ErrorCode::Restore,
-                "Restore dynamic memory size doesn't match: {:04x} != {:04x}",
-                self.dynamic.len(),
-                data.len()
+                "Restore dynamic memory size is larger than the game's: {:04x} > {:04x}",
+                data.len(),
+                self.dynamic.len()
             )
         } else {
-            self.map[..][..data.len()].copy_from_slice(data);
+            let mut scratch = data.to_vec();
+            scratch.resize(self.dynamic.len(), 0);
+            self.map[..][..scratch.len()].copy_from_slice(&scratch);
+            // Any page may now differ from the original
+            self.dirty.fill(u64::MAX);
+            self.clear_dirty();
             Ok(())
         }
     }
 
-    fn decompress(&self, cdata: &[u8]) -> Vec<u8> {
+    // Expand CMem data against the original dynamic memory.  Bytes past the end of the
+    // data are unchanged from the original.
+    fn decompress(&self, cdata: &[u8]) -> Result<Vec<u8>, RuntimeError> {
         let mut data = Vec::new();
         let mut iter = cdata.iter();
-        let mut done = false;
-
-        while !done {
-            let b = iter.next();
-            match b {
-                Some(b) => {
-                    let i = data.len();
-                    if *b == 0 {
-                        let l = *iter.next().expect("Incomplete CMem 0 run") as usize;
-                        for j in 0..l + 1 {
-                            data.push(self.dynamic[i + j]);
-                        }
-                    } else {
-                        data.push(b ^ self.dynamic[i])
-                    }
+
+        while let Some(b) = iter.next() {
+            let i = data.len();
+            let run = if *b == 0 {
+                match iter.next() {
+                    Some(l) => *l as usize + 1,
+                    None => return recoverable_error!(ErrorCode::Restore, "Incomplete CMem 0 run"),
                 }
-                None => done = true,
+            } else {
+                1
+            };
+
+            if i + run > self.dynamic.len() {
+                return recoverable_error!(
+                    ErrorCode::Restore,
+                    "CMem data expands past the end of dynamic memory: {:04x}",
+                    self.dynamic.len()
+                );
+            }
+
+            if *b == 0 {
+                data.extend_from_slice(&self.dynamic[i..i + run]);
+            } else {
+                data.push(b ^ self.dynamic[i])
             }
         }
 
-        data
+        let i = data.len();
+        data.extend_from_slice(&self.dynamic[i..]);
+        Ok(data)
     }
 
+    /// Restore dynamic memory from a compressed (CMem) image.  Memory is left unchanged
+    /// if the data is invalid.
     pub fn restore_compressed(&mut self, cdata: &[u8]) -> Result<(), RuntimeError> {
-        let data = self.decompress(cdata);
+        let data = self.decompress(cdata)?;
         self.restore(&data)
     }
 }
@@ -230,7 +359,10 @@ impl Memory {
 mod tests {
     use std::{fs, io::Write, path::Path};
 
-    use crate::{assert_ok, assert_ok_eq};
+    use crate::{
+        assert_ok, assert_ok_eq,
+        test_util::{capture_log, log},
+    };
 
     use super::*;
 
@@ -341,6 +473,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_slice_end_of_memory() {
+        let mut map = vec![0; 0x800];
+        map[0] = 5;
+        map[0xE] = 0x4;
+        let m = Memory::new(map);
+        assert_eq!(m.slice(0x7F8, 0x10).len(), 8);
+        assert!(m.slice(0x800, 0x10).is_empty());
+        assert!(m.slice(0x1000, 0x10).is_empty());
+        assert!(m.slice(0x7F8, usize::MAX).len() == 8);
+    }
+
     #[test]
     fn test_checksum_v3() {
         let mut map = vec![0; 0x800];
@@ -397,6 +541,68 @@ mod tests {
         assert_ok_eq!(m.checksum(), 0xf420);
     }
 
+    #[test]
+    fn test_new_truncated() {
+        let mut map = vec![0; 0x800];
+        map[0] = 3;
+        map[0xE] = 0x4;
+        map[0x1A] = 0x4;
+        for (i, b) in (0x40..0x7FF).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map.truncate(0x7FF);
+        let m = Memory::new(map);
+        assert_eq!(m.size(), 0x800);
+        assert_ok_eq!(m.read_byte(0x7FE), 0xFE);
+        assert_ok_eq!(m.read_byte(0x7FF), 0);
+        assert_ok_eq!(m.checksum(), 0xf321);
+    }
+
+    #[test]
+    fn test_new_trailing_bytes() {
+        let mut map = vec![0; 0x800];
+        map[0] = 3;
+        map[0xE] = 0x4;
+        map[0x1A] = 0x4;
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map.extend_from_slice(&[0xAA; 0x200]);
+        let m = Memory::new(map);
+        assert_eq!(m.size(), 0xA00);
+        assert_ok_eq!(m.read_byte(0x800), 0xAA);
+        assert_ok_eq!(m.checksum(), 0xf420);
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        assert_eq!(max_file_size(3), 0x20000);
+        assert_eq!(max_file_size(4), 0x40000);
+        assert_eq!(max_file_size(5), 0x40000);
+        assert_eq!(max_file_size(7), 0x80000);
+        assert_eq!(max_file_size(8), 0x80000);
+    }
+
+    #[test]
+    fn test_new_too_large() {
+        let mut map = vec![0; 0x20000];
+        map[0] = 3;
+        map[0xE] = 0x4;
+        assert!(Memory::new(map.clone()).check_size().is_ok());
+
+        capture_log("app::state");
+        map.push(0);
+        let m = Memory::new(map);
+        assert_eq!(m.size(), 0x20001);
+        assert_eq!(
+            log(),
+            ["Story file is 0x20001 bytes, larger than the 0x20000 bytes a version 3 story can address"]
+        );
+        let e = m.check_size().expect_err("expected an oversized story");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::FileTooLarge);
+    }
+
     #[test]
     fn test_read_byte() {
         let mut map = vec![0; 0x800];
@@ -432,6 +638,27 @@ mod tests {
         }
 
         assert!(m.read_word(0x7FF).is_err());
+        assert!(m.read_word(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_new_short_image() {
+        // Too short for a header, but memory can still be created and read safely
+        let m = Memory::new(vec![]);
+        assert!(m.read_byte(0).is_err());
+        assert!(m.read_word(0).is_err());
+
+        let m = Memory::new(vec![5, 0, 0]);
+        assert_ok_eq!(m.read_byte(0), 5);
+        assert!(m.read_word(2).is_err());
+        assert_eq!(m.dynamic.len(), 3);
+
+        // Static mark past the end of the image
+        let mut map = vec![0; 0x40];
+        map[0] = 5;
+        map[0xE] = 0x4;
+        let m = Memory::new(map);
+        assert_eq!(m.dynamic.len(), 0x40);
     }
 
     #[test]
@@ -477,6 +704,9 @@ mod tests {
         }
         assert_ok_eq!(m.read_word(0x81), 0x8182);
 
+        // The last word of memory
+        assert!(m.write_word(0x7FE, 0x1234).is_ok());
+        assert_ok_eq!(m.read_word(0x7FE), 0x1234);
         assert!(m.write_word(0x7FF, 0).is_err());
     }
 
@@ -509,6 +739,100 @@ mod tests {
         );
     }
 
+    // Compress every byte of dynamic memory, ignoring dirty page tracking
+    fn compress_all(m: &Memory) -> Vec<u8> {
+        let mut cdata = Vec::new();
+        let mut run_length = 0;
+        for i in 0..m.dynamic.len() {
+            let b = m.map[i] ^ m.dynamic[i];
+            if b == 0 {
+                if run_length == 255 {
+                    cdata.push(0);
+                    cdata.push(run_length);
+                    run_length = 0;
+                } else {
+                    run_length += 1;
+                }
+            } else {
+                if run_length > 0 {
+                    cdata.push(0);
+                    cdata.push(run_length - 1);
+                    run_length = 0;
+                }
+                cdata.push(b);
+            }
+        }
+
+        if run_length > 0 {
+            cdata.push(0);
+            cdata.push(run_length - 1);
+        }
+
+        cdata
+    }
+
+    fn dirty_map() -> Vec<u8> {
+        let mut map = vec![0; 0x1000];
+        map[0] = 5;
+        // Dynamic memory ends part way through page 8
+        map[0xE] = 0x8;
+        map[0xF] = 0x80;
+        for (i, b) in (0x40..0x1000).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map
+    }
+
+    #[test]
+    fn test_dirty_pages() {
+        let mut m = Memory::new(dirty_map());
+        assert!(m.dirty_pages().is_empty());
+        assert!(m.write_byte(0x210, 0xFF).is_ok());
+        assert!(m.write_word(0x5FF, 0x1234).is_ok());
+        // Static memory isn't tracked
+        assert!(m.write_byte(0x900, 0xFF).is_ok());
+        assert_eq!(m.dirty_pages(), vec![2, 5, 6]);
+        // Writing the original value back leaves the page dirty until cleared
+        assert!(m.write_byte(0x210, 0x10).is_ok());
+        assert_eq!(m.dirty_pages(), vec![2, 5, 6]);
+        m.clear_dirty();
+        assert_eq!(m.dirty_pages(), vec![5, 6]);
+        m.reset();
+        assert!(m.dirty_pages().is_empty());
+    }
+
+    #[test]
+    fn test_compress_dirty_pages() {
+        let mut m = Memory::new(dirty_map());
+        assert_eq!(m.compress(), compress_all(&m));
+        assert!(m.write_byte(0x210, 0xFF).is_ok());
+        assert!(m.write_byte(0x87F, 0xFF).is_ok());
+        assert_eq!(m.dirty_pages(), vec![2, 8]);
+        assert_eq!(m.compress(), compress_all(&m));
+        // A change to a clean page isn't examined
+        m.map[0x400] = 0xFF;
+        assert_ne!(m.compress(), compress_all(&m));
+        m.mark_dirty(0x400);
+        assert_eq!(m.compress(), compress_all(&m));
+    }
+
+    #[test]
+    fn test_restore_dirty_pages() {
+        let map = dirty_map();
+        let mut m = Memory::new(map.clone());
+        let mut restore = map[..0x880].to_vec();
+        restore[0x300] = 0xFF;
+        restore[0x700] = 0xFF;
+        assert!(m.write_byte(0x100, 0xFF).is_ok());
+        assert!(m.restore(&restore).is_ok());
+        assert_eq!(m.dirty_pages(), vec![3, 7]);
+        assert_eq!(m.compress(), compress_all(&m));
+        let cdata = m.compress();
+        m.reset();
+        assert!(m.restore_compressed(&cdata).is_ok());
+        assert_eq!(m.dirty_pages(), vec![3, 7]);
+    }
+
     #[test]
     fn test_reset() {
         let mut map = vec![0; 0x800];
@@ -573,9 +897,9 @@ mod tests {
             map[i + 0x40] = b as u8;
         }
         let m = Memory::new(map.clone());
-        let dc = m.decompress(&[
+        let dc = assert_ok!(m.decompress(&[
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE,
-        ]);
+        ]));
         assert_eq!(dc[0..0x200], map[0..0x200]);
         assert_eq!(dc[0x200], 0xFC);
         assert_eq!(dc[0x201..0x280], map[0x201..0x280]);
@@ -648,4 +972,94 @@ mod tests {
         assert_ok_eq!(m.read_byte(0x280), 0x10);
         assert_ok_eq!(m.read_byte(0x300), 0xFD);
     }
+
+    fn restore_map() -> Vec<u8> {
+        let mut map = vec![0; 0x800];
+        map[0] = 8;
+        map[0xE] = 0x4;
+        map[0x1A] = 0x1;
+        map[0x1B] = 0;
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map
+    }
+
+    #[test]
+    fn test_restore_oversized() {
+        let map = restore_map();
+        let mut m = Memory::new(map.clone());
+        let e = m
+            .restore(&[0xFF; 0x401])
+            .expect_err("expected a restore error");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::Restore);
+        for (i, b) in map.iter().enumerate() {
+            assert_ok_eq!(m.read_byte(i), *b);
+        }
+    }
+
+    #[test]
+    fn test_restore_undersized() {
+        let map = restore_map();
+        let mut m = Memory::new(map.clone());
+        let mut restore = map[0..0x40].to_vec();
+        restore.extend_from_slice(&[0xFF; 0x1C0]);
+        assert!(m.restore(&restore).is_ok());
+        for (i, b) in map[0..0x40].iter().enumerate() {
+            assert_ok_eq!(m.read_byte(i), *b);
+        }
+        for i in 0x40..0x200 {
+            assert_ok_eq!(m.read_byte(i), 0xFF);
+        }
+        for i in 0x200..0x400 {
+            assert_ok_eq!(m.read_byte(i), 0);
+        }
+        // Static memory is untouched
+        for (i, b) in map.iter().enumerate().skip(0x400) {
+            assert_ok_eq!(m.read_byte(i), *b);
+        }
+    }
+
+    #[test]
+    fn test_restore_compressed_short() {
+        let map = restore_map();
+        let mut m = Memory::new(map.clone());
+        // 0x0000 - 0x01FF unchanged, 0x0200 changed, the rest implied unchanged
+        assert!(m
+            .restore_compressed(&[0x00, 0xFF, 0x00, 0xFF, 0xFC])
+            .is_ok());
+        assert_ok_eq!(m.read_byte(0x200), 0xFC);
+        for (i, b) in map.iter().enumerate() {
+            if i != 0x200 {
+                assert_ok_eq!(m.read_byte(i), *b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_compressed_oversized() {
+        let map = restore_map();
+        let mut m = Memory::new(map.clone());
+        assert!(m.write_byte(0x100, 0x55).is_ok());
+        // 4 runs of 256 unchanged bytes fill dynamic memory, then one more byte
+        let e = m
+            .restore_compressed(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x01])
+            .expect_err("expected a restore error");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::Restore);
+        assert_ok_eq!(m.read_byte(0x100), 0x55);
+    }
+
+    #[test]
+    fn test_restore_compressed_incomplete_run() {
+        let map = restore_map();
+        let mut m = Memory::new(map);
+        assert!(m.write_byte(0x100, 0x55).is_ok());
+        let e = m
+            .restore_compressed(&[0x00, 0xFF, 0x00])
+            .expect_err("expected a restore error");
+        assert_eq!(e.code(), ErrorCode::Restore);
+        assert_ok_eq!(m.read_byte(0x100), 0x55);
+    }
 }
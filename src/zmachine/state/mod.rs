@@ -1,38 +1,91 @@
-use std::{collections::VecDeque, fmt};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    ops::Range,
+};
 
 use crate::{
     error::{ErrorCode, RuntimeError},
     fatal_error,
-    quetzal::{IFhd, Mem, Quetzal, Stk, Stks},
+    quetzal::{IFhd, IntD, Mem, Quetzal, Stk, Stks},
     recoverable_error,
 };
 
+use super::{io::screen::Capabilities, stats::CacheStats};
+
 use self::{
-    frame::Frame,
+    frame::{Frame, FrameInfo, ReadInterrupt},
     header::{Flags1v3, Flags1v4, Flags2, HeaderField},
-    memory::Memory,
+    memory::{max_file_size, Memory},
 };
 
 use crate::instruction::StoreResult;
 
-mod frame;
+pub mod frame;
 pub mod header;
 pub mod memory;
 
+// An undo snapshot.  Quetzal doesn't record which frames are interrupt routines, so the
+// interrupt bookkeeping is kept alongside it.
+#[derive(Debug)]
+struct Undo {
+    quetzal: Quetzal,
+    // Encoded size of the Quetzal, counted against the undo budget
+    size: usize,
+    // (input interrupt, sound interrupt, timed read) for each frame
+    interrupt_frames: Vec<(bool, bool, ReadInterrupt)>,
+    sound_interrupt: Option<usize>,
+    in_sound_interrupt: bool,
+}
+
 #[derive(Debug)]
 pub struct State {
     version: u8,
     memory: Memory,
     static_mark: usize,
     frames: Vec<Frame>,
-    undo_stack: VecDeque<Quetzal>,
-    // read_interrupt_pending is set when the READ starts, read_interrupt_result is set when the interrupt routine returns
-    read_interrupt_pending: bool,
-    read_interrupt_result: Option<u16>,
+    undo_stack: VecDeque<Undo>,
+    // Most snapshots, and most total encoded bytes, kept on the undo stack
+    undo_depth: usize,
+    undo_budget: usize,
+    // Total encoded size of the snapshots on the undo stack
+    undo_bytes: usize,
     // sound_interrupt containts the address of the interrupt routine and is stored when SOUND_EFFECT is run
     sound_interrupt: Option<usize>,
+    // in_sound_interrupt is set while a sound interrupt routine is running, so a sound
+    // interrupt that becomes pending in the meantime waits until it returns
+    in_sound_interrupt: bool,
+    // trace_calls enables routine call/return logging to app::calls, optionally restricted to
+    // the routine addresses in trace_routines (an empty set traces every routine)
+    trace_calls: bool,
+    trace_routines: HashSet<usize>,
+    // What the terminal can do, kept to re-initialize the header after a restore or restart
+    capabilities: Capabilities,
+    // Standard revision written to the header, see header::STANDARD_VERSION
+    standard_version: (u8, u8),
+    // Longest Z-string, in words, that string_literal will read before giving up
+    max_string_words: usize,
+    // The last object short name decoded for the status line
+    short_name: Option<ShortName>,
+    short_name_stats: CacheStats,
+}
+
+// A decoded object short name and the memory it was decoded from.  Writing to any of
+// that memory invalidates it.
+#[derive(Debug)]
+struct ShortName {
+    object: usize,
+    ranges: Vec<Range<usize>>,
+    text: Vec<u16>,
 }
 
+/// Default limit on the length of a Z-string, in words
+pub const MAX_STRING_WORDS: usize = 32_768;
+/// Default limit on the number of undo snapshots kept
+pub const UNDO_DEPTH: usize = 10;
+/// Default limit on the total size of the undo snapshots kept, in bytes
+pub const UNDO_BUDGET_BYTES: usize = 8 * 1024 * 1024;
+
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "State: version: {}, address space: {:06x}, dynamic space: {:04x}, execution frames: {}", self. version, self.memory.size(), self.static_mark - 1, self.frames.len())
@@ -89,8 +142,25 @@ impl TryFrom<&State> for Stks {
     type Error = RuntimeError;
 
     fn try_from(value: &State) -> Result<Self, Self::Error> {
+        // The base frame is written as the dummy frame, except in V6 where there is no
+        // dummy frame and the stack starts with the main routine
+        let skip = if value.version == 6 {
+            if let Some(f) = value.frames.first() {
+                if !f.stack().is_empty() {
+                    return recoverable_error!(
+                        ErrorCode::Quetzal,
+                        "V6 stack has {} values outside of any routine",
+                        f.stack().len()
+                    );
+                }
+            }
+            1
+        } else {
+            0
+        };
+
         let mut frames = Vec::new();
-        for f in &value.frames {
+        for f in value.frames.iter().skip(skip) {
             // Flags: 0b000rvvvv
             //  r = 1 if the frame routine does not store a result
             //  vvvv = the number of local variables (0 - 15)
@@ -141,9 +211,18 @@ impl State {
             static_mark,
             frames: Vec::new(),
             undo_stack: VecDeque::new(),
-            read_interrupt_pending: false,
-            read_interrupt_result: None,
+            undo_depth: UNDO_DEPTH,
+            undo_budget: UNDO_BUDGET_BYTES,
+            undo_bytes: 0,
             sound_interrupt: None,
+            in_sound_interrupt: false,
+            trace_calls: false,
+            trace_routines: HashSet::new(),
+            capabilities: Capabilities::default(),
+            standard_version: header::STANDARD_VERSION,
+            max_string_words: MAX_STRING_WORDS,
+            short_name: None,
+            short_name_stats: CacheStats::default(),
         })
     }
 
@@ -155,10 +234,140 @@ impl State {
         &self.memory
     }
 
+    pub fn standard_version(&self) -> (u8, u8) {
+        self.standard_version
+    }
+
+    pub fn set_standard_version(&mut self, version: (u8, u8)) {
+        self.standard_version = version;
+    }
+
+    pub fn set_max_string_words(&mut self, words: usize) {
+        self.max_string_words = words;
+    }
+
+    /// Keep at most `depth` undo snapshots, totalling at most `budget` bytes
+    pub fn set_undo_limits(&mut self, depth: usize, budget: usize) {
+        self.undo_depth = depth;
+        self.undo_budget = budget;
+        self.evict_undo();
+    }
+
+    /// Total encoded size, in bytes, of the snapshots on the undo stack
+    pub fn undo_memory_usage(&self) -> usize {
+        self.undo_bytes
+    }
+
+    // Drop the oldest undo snapshots until the stack is within its depth and budget
+    fn evict_undo(&mut self) {
+        while self.undo_stack.len() > self.undo_depth || self.undo_bytes > self.undo_budget {
+            match self.undo_stack.pop_front() {
+                Some(undo) => {
+                    debug!(target: "app::state", "Dropping undo state: {} bytes", undo.size);
+                    self.undo_bytes -= undo.size;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// The cached short name of `object`, if it hasn't changed since it was cached
+    pub fn cached_short_name(&mut self, object: usize) -> Option<Vec<u16>> {
+        match &self.short_name {
+            Some(name) if name.object == object => {
+                self.short_name_stats.hits += 1;
+                Some(name.text.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache the decoded short name of `object`, which was read from `ranges` of memory
+    pub fn cache_short_name(&mut self, object: usize, ranges: Vec<Range<usize>>, text: &[u16]) {
+        self.short_name_stats.misses += 1;
+        self.short_name = Some(ShortName {
+            object,
+            ranges,
+            text: text.to_vec(),
+        });
+    }
+
+    pub fn short_name_stats(&self) -> CacheStats {
+        self.short_name_stats
+    }
+
+    // Drop the cached short name if it was read from any of `address`..`address + length`
+    fn invalidate_short_name(&mut self, address: usize, length: usize) {
+        if self.short_name.as_ref().is_some_and(|name| {
+            name.ranges
+                .iter()
+                .any(|r| address < r.end && r.start < address + length)
+        }) {
+            self.short_name = None;
+        }
+    }
+
     pub fn frame_count(&self) -> usize {
         self.frames.len()
     }
 
+    /// Where the value returned by the routine `depth` frames deep is stored.  An input
+    /// interrupt routine's value goes to the interrupted read instead of a variable.
+    pub fn routine_result(&self, depth: usize) -> Option<StoreResult> {
+        match depth.checked_sub(1).and_then(|i| self.frames.get(i)) {
+            Some(f) if !f.input_interrupt() => f.result().copied(),
+            _ => None,
+        }
+    }
+
+    /// The frame stack, newest first.  The oldest frame is the dummy frame outside of any
+    /// routine, which has address 0.
+    pub fn backtrace(&self) -> Vec<FrameInfo> {
+        self.frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, f)| {
+                let (address, packed_address) = if i == 0 {
+                    (0, None)
+                } else {
+                    (f.address(), self.packed_routine(f.address()))
+                };
+                FrameInfo {
+                    address,
+                    packed_address,
+                    return_address: f.return_address(),
+                    result: f.result().copied(),
+                    local_variables: f.local_variables().clone(),
+                }
+            })
+            .collect()
+    }
+
+    // The packed form of a routine address, the inverse of packed_routine_address
+    fn packed_routine(&self, address: usize) -> Option<u16> {
+        let (offset, scale) = match self.version {
+            3 => (0, 2),
+            4 | 5 => (0, 4),
+            6 | 7 => (
+                self.memory
+                    .read_word(HeaderField::RoutinesOffset as usize)
+                    .ok()? as usize
+                    * 8,
+                4,
+            ),
+            8 => (0, 8),
+            _ => return None,
+        };
+
+        let address = address.checked_sub(offset)?;
+        if address % scale == 0 {
+            u16::try_from(address / scale).ok()
+        } else {
+            None
+        }
+    }
+
     fn current_frame(&self) -> Result<&Frame, RuntimeError> {
         if let Some(frame) = self.frames.last() {
             Ok(frame)
@@ -175,17 +384,19 @@ impl State {
         }
     }
 
-    pub fn initialize(
-        &mut self,
-        rows: u8,
-        columns: u8,
-        default_colors: (u8, u8),
-        sound: bool,
-    ) -> Result<(), RuntimeError> {
-        // Clear any pending interrupt
-        self.read_interrupt_pending = false;
-        self.read_interrupt_result = None;
+    pub fn initialize(&mut self, capabilities: &Capabilities) -> Result<(), RuntimeError> {
+        self.capabilities = *capabilities;
+        let rows = capabilities.rows.min(255) as u8;
+        let columns = capabilities.columns.min(255) as u8;
+        let default_colors = (
+            capabilities.default_colors.0 as u8,
+            capabilities.default_colors.1 as u8,
+        );
+        let sound = capabilities.sound;
+
+        // Clear any pending interrupt.  Timed reads are kept with their frames.
         self.sound_interrupt = None;
+        self.in_sound_interrupt = false;
 
         // Set V3 flags
         if self.version < 4 {
@@ -209,18 +420,33 @@ impl State {
         // Set V5+ flags and header fields
         if self.version > 4 {
             header::clear_flag1(self, Flags1v4::PicturesAvailable as u8)?;
-            header::set_flag1(self, Flags1v4::ColoursAvailable as u8)?;
-            header::set_flag1(self, Flags1v4::BoldfaceAvailable as u8)?;
-            header::set_flag1(self, Flags1v4::ItalicAvailable as u8)?;
-            header::set_flag1(self, Flags1v4::FixedSpaceAvailable as u8)?;
-            header::set_flag1(self, Flags1v4::TimedInputAvailable as u8)?;
-            //header::clear_flag2(&mut self.memory, Flags2::RequestMouse)?;
+            for (flag, available) in [
+                (Flags1v4::ColoursAvailable, capabilities.colors),
+                (Flags1v4::BoldfaceAvailable, capabilities.bold),
+                (Flags1v4::ItalicAvailable, capabilities.italic),
+                (Flags1v4::FixedSpaceAvailable, true),
+                (Flags1v4::TimedInputAvailable, capabilities.timed_input),
+            ] {
+                if available {
+                    header::set_flag1(self, flag as u8)?;
+                } else {
+                    header::clear_flag1(self, flag as u8)?;
+                }
+            }
+            if !capabilities.colors {
+                header::clear_flag2(self, Flags2::RequestColours)?;
+            }
+            if !capabilities.mouse {
+                header::clear_flag2(self, Flags2::RequestMouse)?;
+            }
             // Graphics font 3 support is crap atm
             header::clear_flag2(self, Flags2::RequestPictures)?;
             // If sounds weren't loaded
             if !sound {
                 header::clear_flag2(self, Flags2::RequestSoundEffects)?;
             }
+            // MAKE_MENU always fails, so games shouldn't try to use menus
+            header::clear_flag2(self, Flags2::RequestMenus)?;
 
             header::set_word(self, HeaderField::ScreenHeight, rows as u16)?;
             header::set_word(self, HeaderField::ScreenWidth, columns as u16)?;
@@ -229,20 +455,35 @@ impl State {
         }
 
         // Interpreter # and version
-        self.write_byte(0x1E, 6)?;
-        self.write_byte(0x1F, b'Z')?;
+        header::set_byte(
+            self,
+            HeaderField::InterpreterNumber,
+            header::INTERPRETER_ID.0,
+        )?;
+        header::set_byte(
+            self,
+            HeaderField::InterpreterVersion,
+            header::INTERPRETER_ID.1,
+        )?;
 
         // Z-Machine standard compliance
-        self.write_byte(0x32, 1)?;
-        self.write_byte(0x33, 0)?;
+        header::set_byte(self, HeaderField::Revision, self.standard_version.0)?;
+        header::set_byte(self, HeaderField::RevisionMinor, self.standard_version.1)?;
 
         // Initializing after a restore will already have stack frames,
         // so check before pushing a dummy frame
         if self.frames.is_empty() {
             let pc = header::field_word(self, HeaderField::InitialPC)? as usize;
-            let f = Frame::new(pc, pc, &[], 0, &[], None, 0);
-            self.frames.clear();
-            self.frames.push(f);
+            if self.version == 6 {
+                // The V6 header has the packed address of the main routine, which is called
+                // from a base frame that is never returned to
+                let address = self.packed_routine_address(pc as u16)?;
+                self.frames.push(Frame::new(0, 0, &[], 0, &[], None, 0));
+                self.call_routine(address, &[], None, 0)?;
+            } else {
+                let f = Frame::new(pc, pc, &[], 0, &[], None, 0);
+                self.frames.push(f);
+            }
         }
 
         Ok(())
@@ -275,6 +516,7 @@ impl State {
 
     pub fn write_byte(&mut self, address: usize, value: u8) -> Result<(), RuntimeError> {
         if address < self.static_mark {
+            self.invalidate_short_name(address, 1);
             self.memory.write_byte(address, value)
         } else {
             fatal_error!(
@@ -288,6 +530,7 @@ impl State {
 
     pub fn write_word(&mut self, address: usize, value: u16) -> Result<(), RuntimeError> {
         if address < self.static_mark - 1 {
+            self.invalidate_short_name(address, 2);
             self.memory.write_word(address, value)?;
             Ok(())
         } else {
@@ -311,6 +554,31 @@ impl State {
         Ok(table + index)
     }
 
+    // Address of global variable `global`, where G00 is 0
+    fn global_address(&self, global: u8) -> Result<usize, RuntimeError> {
+        if global < 240 {
+            self.global_variable_address(global + 16)
+        } else {
+            recoverable_error!(
+                ErrorCode::IllegalMemoryAccess,
+                "Global variable {} is out of range [0..239]",
+                global
+            )
+        }
+    }
+
+    /// Read a global variable, where G00 is 0.  Globals don't need a routine frame.
+    pub fn global(&self, global: u8) -> Result<u16, RuntimeError> {
+        let address = self.global_address(global)?;
+        self.read_word(address)
+    }
+
+    pub fn set_global(&mut self, global: u8, value: u16) -> Result<(), RuntimeError> {
+        debug!(target: "app::state", "Set global {:02x} to {:04x}", global, value);
+        let address = self.global_address(global)?;
+        self.write_word(address, value)
+    }
+
     pub fn variable(&mut self, variable: u8) -> Result<u16, RuntimeError> {
         if variable < 16 {
             self.current_frame_mut()?.local_variable(variable)
@@ -357,14 +625,27 @@ impl State {
 
     // Helper functions to read code that may lie in high memory - instruction,
     // routines, strings
-    pub fn instruction(&self, address: usize) -> Vec<u8> {
+    pub fn instruction(&self, address: usize) -> Result<Vec<u8>, RuntimeError> {
         // An instruction may be up to 23 bytes long, excluding literal strings
         // Opcode: up to 2 bytes
         // Operand types: up to 2 bytes
         // Operands: up to 16 bytes
         // Store variable: up to 1 byte
         // Branch offset: up to 2 bytes
-        self.memory().slice(address, 23)
+        if address >= self.memory.size() {
+            return fatal_error!(
+                ErrorCode::InvalidAddress,
+                "Instruction address {:#06x} beyond end of memory ({:#06x})",
+                address,
+                self.memory.size() - 1
+            );
+        }
+
+        // Pad an instruction near the end of memory so decoding can't run off the end
+        // of the slice; the decoder checks the decoded length against the memory size
+        let mut bytes = self.memory.slice(address, 23);
+        bytes.resize(23, 0);
+        Ok(bytes)
     }
 
     fn routine_header(&self, address: usize) -> Result<(usize, Vec<u16>), RuntimeError> {
@@ -394,60 +675,123 @@ impl State {
 
     pub fn string_literal(&self, address: usize) -> Result<Vec<u16>, RuntimeError> {
         let mut d = Vec::new();
-        // Read until bit 15 of the word is set
-        loop {
+        // Read until bit 15 of the word is set, giving up on a string that runs on too long
+        // (usually a bad address pointing into zeroed memory)
+        while d.len() < self.max_string_words {
             let w = self.memory.read_word(address + (d.len() * 2))?;
             d.push(w);
             if w & 0x8000 == 0x8000 {
                 return Ok(d);
             }
         }
+
+        recoverable_error!(
+            ErrorCode::UnterminatedString,
+            "String at ${:06x} is not terminated within {} words",
+            address,
+            self.max_string_words
+        )
+    }
+
+    pub fn check_file_size(&self) -> Result<(), RuntimeError> {
+        self.memory.check_size()
     }
 
     // Unpack addresses
     pub fn packed_routine_address(&self, address: u16) -> Result<usize, RuntimeError> {
-        match self.version {
-            3 => Ok(address as usize * 2),
-            4 | 5 => Ok(address as usize * 4),
-            7 => Ok((address as usize * 4)
-                + (self
-                    .memory
-                    .read_word(HeaderField::RoutinesOffset as usize)? as usize
-                    * 8)),
-            8 => Ok(address as usize * 8),
-            _ => fatal_error!(
-                ErrorCode::UnsupportedVersion,
-                "Unsupported version: {}",
-                self.version
-            ),
+        let unpacked = match self.version {
+            3 => address as usize * 2,
+            4 | 5 => address as usize * 4,
+            6 | 7 => {
+                (address as usize * 4)
+                    + (self
+                        .memory
+                        .read_word(HeaderField::RoutinesOffset as usize)?
+                        as usize
+                        * 8)
+            }
+            8 => address as usize * 8,
+            _ => {
+                return fatal_error!(
+                    ErrorCode::UnsupportedVersion,
+                    "Unsupported version: {}",
+                    self.version
+                )
+            }
+        };
+        self.check_unpacked("Routine", address, unpacked)
+    }
+
+    // An unpacked address past the version's addressable limit would otherwise read
+    // whatever memory is there
+    fn check_unpacked(
+        &self,
+        kind: &str,
+        address: u16,
+        unpacked: usize,
+    ) -> Result<usize, RuntimeError> {
+        if unpacked < max_file_size(self.version) {
+            Ok(unpacked)
+        } else {
+            recoverable_error!(
+                ErrorCode::IllegalMemoryAccess,
+                "{} packed address ${:04x} unpacks to ${:06x}, beyond the version {} limit of ${:06x}",
+                kind,
+                address,
+                unpacked,
+                self.version,
+                max_file_size(self.version)
+            )
         }
     }
 
     pub fn packed_string_address(&self, address: u16) -> Result<usize, RuntimeError> {
-        match self.version {
-            1 | 2 | 3 => Ok(address as usize * 2),
-            4 | 5 => Ok(address as usize * 4),
-            7 => Ok((address as usize * 4)
-                + (self.memory.read_word(HeaderField::StringsOffset as usize)? as usize * 8)),
-            8 => Ok(address as usize * 8),
-            // TODO: error
-            _ => fatal_error!(
-                ErrorCode::UnsupportedVersion,
-                "Unsupported version: {}",
-                self.version
-            ),
-        }
+        let unpacked = match self.version {
+            1..=3 => address as usize * 2,
+            4 | 5 => address as usize * 4,
+            6 | 7 => {
+                (address as usize * 4)
+                    + (self.memory.read_word(HeaderField::StringsOffset as usize)? as usize * 8)
+            }
+            8 => address as usize * 8,
+            _ => {
+                return fatal_error!(
+                    ErrorCode::UnsupportedVersion,
+                    "Unsupported version: {}",
+                    self.version
+                )
+            }
+        };
+        self.check_unpacked("String", address, unpacked)
     }
 
     // Routines/Interrupts
+    pub fn set_trace_calls(&mut self, enabled: bool, routines: &[usize]) {
+        self.trace_calls = enabled;
+        self.trace_routines = routines.iter().copied().collect();
+    }
+
+    fn is_traced(&self, address: usize) -> bool {
+        self.trace_calls
+            && (self.trace_routines.is_empty() || self.trace_routines.contains(&address))
+    }
+
+    /// Is an input interrupt routine running, somewhere on the call stack?
     pub fn is_input_interrupt(&self) -> bool {
-        self.read_interrupt_result.is_some()
+        self.frames.iter().any(|f| f.input_interrupt())
+    }
+
+    /// Is an input or sound interrupt routine running, somewhere on the call stack?
+    pub fn in_interrupt(&self) -> bool {
+        self.frames
+            .iter()
+            .any(|f| f.input_interrupt() || f.sound_interrupt())
     }
 
     pub fn call_routine(
         &mut self,
         address: usize,
-        arguments: &Vec<u16>,
+        arguments: &[u16],
         result: Option<StoreResult>,
         return_address: usize,
     ) -> Result<usize, RuntimeError> {
@@ -469,6 +813,23 @@ impl State {
             )?;
             self.frames.push(frame);
 
+            if self.is_traced(address) {
+                info!(target: "app::calls", "{:indent$}call ${:06x}({}) -> {}",
+                    "",
+                    address,
+                    arguments
+                        .iter()
+                        .map(|a| format!("{:04x}", a))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    match result {
+                        Some(r) => r.to_string(),
+                        None => "-".to_string(),
+                    },
+                    indent = (self.frames.len() - 1) * 2
+                );
+            }
+
             Ok(initial_pc)
         }
     }
@@ -478,10 +839,10 @@ impl State {
         address: usize,
         return_address: usize,
     ) -> Result<usize, RuntimeError> {
-        if self.read_interrupt_pending {
+        if self.read_interrupt_pending() {
             debug!(target: "app::state", "Read interrupt routine firing: ${:06x}", address);
-            self.read_interrupt_result = Some(0);
-            let initial_pc = self.call_routine(address, &vec![], None, return_address)?;
+            self.current_frame_mut()?.read_interrupt_mut().result = Some(0);
+            let initial_pc = self.call_routine(address, &[], None, return_address)?;
             self.current_frame_mut()?.set_input_interrupt(true);
             Ok(initial_pc)
         } else {
@@ -492,21 +853,61 @@ impl State {
         }
     }
 
+    // The timed read issued by the current routine
+    fn read_interrupt(&self) -> ReadInterrupt {
+        self.frames
+            .last()
+            .map(|f| f.read_interrupt())
+            .unwrap_or_default()
+    }
+
+    fn read_interrupt_mut(&mut self) -> Option<&mut ReadInterrupt> {
+        self.frames.last_mut().map(|f| f.read_interrupt_mut())
+    }
+
     pub fn read_interrupt_pending(&self) -> bool {
-        self.read_interrupt_pending
+        self.read_interrupt().pending
     }
 
     pub fn set_read_interrupt(&mut self) {
-        self.read_interrupt_pending = true;
+        if let Some(r) = self.read_interrupt_mut() {
+            r.pending = true;
+        }
     }
 
     pub fn read_interrupt_result(&self) -> Option<u16> {
-        self.read_interrupt_result
+        self.read_interrupt().result
     }
 
     pub fn clear_read_interrupt(&mut self) {
-        self.read_interrupt_pending = false;
-        self.read_interrupt_result = None;
+        if let Some(r) = self.read_interrupt_mut() {
+            r.pending = false;
+            r.result = None;
+        }
+    }
+
+    /// Did an input interrupt routine print while the current routine's read was
+    /// interrupted?
+    pub fn input_interrupt_print(&self) -> bool {
+        self.read_interrupt().printed
+    }
+
+    /// Note that an input interrupt routine printed, against the routine whose read it
+    /// interrupted
+    pub fn set_input_interrupt_print(&mut self) {
+        let reader = match self.frames.iter().rposition(|f| f.input_interrupt()) {
+            Some(i) if i > 0 => i - 1,
+            _ => self.frames.len().saturating_sub(1),
+        };
+        if let Some(f) = self.frames.get_mut(reader) {
+            f.read_interrupt_mut().printed = true;
+        }
+    }
+
+    pub fn clear_input_interrupt_print(&mut self) {
+        if let Some(r) = self.read_interrupt_mut() {
+            r.printed = false;
+        }
     }
 
     pub fn sound_interrupt(&self) -> Option<usize> {
@@ -521,11 +922,16 @@ impl State {
         self.sound_interrupt = None;
     }
 
+    pub fn in_sound_interrupt(&self) -> bool {
+        self.in_sound_interrupt
+    }
+
     pub fn call_sound_interrupt(&mut self, return_address: usize) -> Result<usize, RuntimeError> {
         if let Some(address) = self.sound_interrupt {
-            let initial_pc = self.call_routine(address, &vec![], None, return_address)?;
+            let initial_pc = self.call_routine(address, &[], None, return_address)?;
             self.current_frame_mut()?.set_sound_interrupt(true);
             self.clear_sound_interrupt();
+            self.in_sound_interrupt = true;
             Ok(initial_pc)
         } else {
             fatal_error!(
@@ -537,12 +943,25 @@ impl State {
 
     pub fn return_routine(&mut self, value: u16) -> Result<usize, RuntimeError> {
         if let Some(f) = self.frames.pop() {
+            if self.is_traced(f.address()) {
+                info!(target: "app::calls", "{:indent$}return ${:06x} => {:04x}",
+                    "",
+                    f.address(),
+                    value,
+                    indent = self.frames.len() * 2
+                );
+            }
             let n = self.current_frame_mut()?;
             n.set_pc(f.return_address());
             debug!(target: "app::state", "Return {:04x} => {:?} to ${:06x}", value, f.result(), f.return_address());
+            if f.sound_interrupt() {
+                self.in_sound_interrupt = false;
+            }
             if f.input_interrupt() {
-                if self.read_interrupt_pending {
-                    self.read_interrupt_result = Some(value);
+                // The result goes to the read that was interrupted, in the caller
+                let read = self.current_frame_mut()?.read_interrupt_mut();
+                if read.pending {
+                    read.result = Some(value);
                 }
             } else if let Some(r) = f.result() {
                 self.set_variable(r.variable(), value)?
@@ -559,6 +978,8 @@ impl State {
 
     pub fn throw(&mut self, depth: u16, result: u16) -> Result<usize, RuntimeError> {
         self.frames.truncate(depth as usize);
+        // Throwing past a sound interrupt routine ends it
+        self.in_sound_interrupt = self.frames.iter().any(|f| f.sound_interrupt());
         self.return_routine(result)
     }
 
@@ -576,21 +997,22 @@ impl State {
     }
 
     // Save/Restore
-    pub fn save(&self, pc: usize) -> Result<Vec<u8>, RuntimeError> {
-        let quetzal = Quetzal::try_from((self, pc))?;
+    /// Encode the game state as a Quetzal save, including any interpreter-dependent data
+    pub fn save(&self, pc: usize, intd: Option<IntD>) -> Result<Vec<u8>, RuntimeError> {
+        let mut quetzal = Quetzal::try_from((self, pc))?;
+        quetzal.set_intd(intd);
         debug!(target: "app::state", "Game state encoded");
         Ok(Vec::from(quetzal))
     }
 
     fn restore_state(&mut self, quetzal: Quetzal) -> Result<Option<usize>, RuntimeError> {
-        // Capture flags 2, default colors, rows, and columns from header
-        let flags2 = header::field_word(self, HeaderField::Flags2)?;
-        let fg = header::field_byte(self, HeaderField::DefaultForeground)?;
-        let bg = header::field_byte(self, HeaderField::DefaultBackground)?;
-        let rows = header::field_byte(self, HeaderField::ScreenLines)?;
-        let columns = header::field_byte(self, HeaderField::ScreenColumns)?;
+        // Check the frames before anything is changed
+        let frames = frame::frames_from_stks(quetzal.stks(), self.version)?;
+
+        let interpreter_state = header::snapshot_interpreter_state(self)?;
 
         // Overwrite dynamic memory
+        self.short_name = None;
         if quetzal.mem().compressed() {
             self.memory.restore_compressed(quetzal.mem().memory())?
         } else {
@@ -598,19 +1020,50 @@ impl State {
         }
 
         // Reset the frame stack
-        self.frames = Vec::from(quetzal.stks());
+        self.frames = frames;
 
-        // Re-initialize the state, which will set the default colors, rows, and columns
-        // Ignore sound (for now), since it's in Flags2
-        self.initialize(rows, columns, (fg, bg), false)?;
-
-        // Restore flags 2
-        self.write_word(HeaderField::Flags2 as usize, flags2)?;
+        // Re-initialize the state to set the capability flags, then put back the header
+        // values that may have changed since, like the screen size
+        let capabilities = self.capabilities;
+        self.initialize(&capabilities)?;
+        header::apply_interpreter_state(self, &interpreter_state)?;
+        self.request_redraw()?;
 
         Ok(Some(quetzal.ifhd().pc() as usize))
     }
 
-    pub fn restore(&mut self, data: Vec<u8>) -> Result<Option<usize>, RuntimeError> {
+    /// Set the "request screen redraw" bit in Flags 2.  The game clears
+    /// the bit once it has redrawn the screen.
+    fn request_redraw(&mut self) -> Result<(), RuntimeError> {
+        header::set_flag2(self, Flags2::RequestRedraw)
+    }
+
+    /// Update the screen size in the header after the terminal has been resized, and keep
+    /// `capabilities` for when the header is set up again on restart or restore
+    pub fn resize(&mut self, capabilities: &Capabilities) -> Result<(), RuntimeError> {
+        self.capabilities = *capabilities;
+        let rows = capabilities.rows.min(255) as u8;
+        let columns = capabilities.columns.min(255) as u8;
+        debug!(target: "app::state", "Screen resized to {}x{}", rows, columns);
+        if self.version > 3 {
+            header::set_byte(self, HeaderField::ScreenLines, rows)?;
+            header::set_byte(self, HeaderField::ScreenColumns, columns)?;
+        }
+
+        if self.version > 4 {
+            header::set_word(self, HeaderField::ScreenHeight, rows as u16)?;
+            header::set_word(self, HeaderField::ScreenWidth, columns as u16)?;
+        }
+
+        self.request_redraw()
+    }
+
+    /// Restore the game state from a Quetzal save, returning the PC to resume at and any
+    /// interpreter-dependent data from the save
+    pub fn restore(
+        &mut self,
+        data: Vec<u8>,
+    ) -> Result<(Option<usize>, Option<IntD>), RuntimeError> {
         let quetzal = Quetzal::try_from(data)?;
         debug!(target: "app::state", "Restoring game state");
         // trace!(target: "app::quetzal", "{}", quetzal);
@@ -623,25 +1076,54 @@ impl State {
                 "Save file was created from a different story file"
             )
         } else {
-            self.restore_state(quetzal)
+            let intd = quetzal.intd().cloned();
+            Ok((self.restore_state(quetzal)?, intd))
         }
     }
 
     pub fn save_undo(&mut self, pc: usize) -> Result<(), RuntimeError> {
         let quetzal = Quetzal::try_from((&*self, pc))?;
-        debug!(target: "app::state", "Storing undo state");
-        self.undo_stack.push_back(quetzal);
-        while self.undo_stack.len() > 10 {
-            // Remove the first (oldest) entries
-            self.undo_stack.pop_front();
+        let size = quetzal.size();
+        debug!(target: "app::state", "Storing undo state: {} bytes", size);
+        if self.undo_depth == 0 || size > self.undo_budget {
+            warn!(target: "app::state", "Undo state of {} bytes doesn't fit, not saved", size);
+            return recoverable_error!(
+                ErrorCode::UndoTooLarge,
+                "Undo state of {} bytes doesn't fit in {} undo slots of {} bytes",
+                size,
+                self.undo_depth,
+                self.undo_budget
+            );
         }
+        self.undo_bytes += size;
+        self.undo_stack.push_back(Undo {
+            quetzal,
+            size,
+            interrupt_frames: self
+                .frames
+                .iter()
+                .map(|f| (f.input_interrupt(), f.sound_interrupt(), f.read_interrupt()))
+                .collect(),
+            sound_interrupt: self.sound_interrupt,
+            in_sound_interrupt: self.in_sound_interrupt,
+        });
+        self.evict_undo();
         Ok(())
     }
 
     pub fn restore_undo(&mut self) -> Result<Option<usize>, RuntimeError> {
-        if let Some(quetzal) = self.undo_stack.pop_back() {
+        if let Some(undo) = self.undo_stack.pop_back() {
             debug!(target: "app::state", "Restoring undo state");
-            self.restore_state(quetzal)
+            self.undo_bytes -= undo.size;
+            let pc = self.restore_state(undo.quetzal)?;
+            for (f, (input, sound, read)) in self.frames.iter_mut().zip(undo.interrupt_frames) {
+                f.set_input_interrupt(input);
+                f.set_sound_interrupt(sound);
+                *f.read_interrupt_mut() = read;
+            }
+            self.sound_interrupt = undo.sound_interrupt;
+            self.in_sound_interrupt = undo.in_sound_interrupt;
+            Ok(pc)
         } else {
             warn!(target: "app::state", "No saved state for undo");
             recoverable_error!(ErrorCode::UndoNoState, "Undo stack is empty")
@@ -649,18 +1131,15 @@ impl State {
     }
 
     pub fn restart(&mut self) -> Result<usize, RuntimeError> {
-        // Capture flags 2, default colors, rows, and columns from header
-        let flags2 = header::field_word(self, HeaderField::Flags2)?;
-        let fg = header::field_byte(self, HeaderField::DefaultForeground)?;
-        let bg = header::field_byte(self, HeaderField::DefaultBackground)?;
-        let rows = header::field_byte(self, HeaderField::ScreenLines)?;
-        let columns = header::field_byte(self, HeaderField::ScreenColumns)?;
+        let interpreter_state = header::snapshot_interpreter_state(self)?;
 
         self.memory.reset();
+        self.short_name = None;
         self.frames.clear();
 
-        self.initialize(rows, columns, (fg, bg), false)?;
-        self.write_word(HeaderField::Flags2 as usize, flags2)?;
+        let capabilities = self.capabilities;
+        self.initialize(&capabilities)?;
+        header::apply_interpreter_state(self, &interpreter_state)?;
 
         Ok(self.current_frame()?.pc())
     }
@@ -668,10 +1147,27 @@ impl State {
 
 #[cfg(test)]
 mod tests {
-    use crate::{assert_ok, assert_ok_eq, assert_some, assert_some_eq, test_util::test_map};
+    use crate::{
+        assert_ok, assert_ok_eq, assert_some, assert_some_eq,
+        test_util::{capture_log, log, mock_routine, test_map},
+        zmachine::io::screen::map_color,
+    };
 
     use super::*;
 
+    fn capabilities(rows: u32, columns: u32, colors: (u8, u8), sound: bool) -> Capabilities {
+        Capabilities {
+            rows,
+            columns,
+            default_colors: (
+                assert_ok!(map_color(colors.0)),
+                assert_ok!(map_color(colors.1)),
+            ),
+            sound,
+            ..Capabilities::default()
+        }
+    }
+
     #[test]
     fn test_quetzal_try_from() {
         let mut map = test_map(3);
@@ -723,10 +1219,7 @@ mod tests {
             &vec![0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE]
         );
         assert_eq!(quetzal.ifhd().release_number(), 0x1234);
-        assert_eq!(
-            quetzal.ifhd().serial_number(),
-            &[b'2', b'3', b'0', b'7', b'1', b'5']
-        );
+        assert_eq!(quetzal.ifhd().serial_number(), b"230715");
         assert_eq!(quetzal.ifhd().checksum(), 0x5678);
         assert_eq!(quetzal.ifhd().pc(), 0x494);
         assert_eq!(quetzal.stks().stks().len(), 2);
@@ -789,7 +1282,7 @@ mod tests {
         let state = assert_ok!(State::new(m));
         let ifhd = assert_ok!(IFhd::try_from((&state, 0x9abc)));
         assert_eq!(ifhd.release_number(), 0x1234);
-        assert_eq!(ifhd.serial_number(), &[b'2', b'3', b'0', b'7', b'1', b'5']);
+        assert_eq!(ifhd.serial_number(), b"230715");
         assert_eq!(ifhd.checksum(), 0x5678);
         assert_eq!(ifhd.pc(), 0x9abc);
     }
@@ -945,7 +1438,9 @@ mod tests {
         let map = test_map(3);
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
         assert_ok_eq!(
             header::flag1(&state, Flags1v3::StatusLineNotAvailable as u8),
             0
@@ -985,7 +1480,9 @@ mod tests {
         let map = test_map(4);
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
         assert_ok_eq!(
             header::flag1(&state, Flags1v4::SoundEffectsAvailable as u8),
             1
@@ -1027,7 +1524,9 @@ mod tests {
         let map = test_map(4);
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), false).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
         assert_ok_eq!(
             header::flag1(&state, Flags1v4::SoundEffectsAvailable as u8),
             0
@@ -1067,10 +1566,13 @@ mod tests {
     #[test]
     fn test_initialize_v5() {
         let mut map = test_map(5);
+        map[0x10] = 0x01;
         map[0x11] = 0xF8;
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
         assert_ok_eq!(header::flag1(&state, Flags1v4::PicturesAvailable as u8), 0);
         assert_ok_eq!(header::flag1(&state, Flags1v4::ColoursAvailable as u8), 1);
         assert_ok_eq!(header::flag1(&state, Flags1v4::BoldfaceAvailable as u8), 1);
@@ -1092,6 +1594,7 @@ mod tests {
         assert_ok_eq!(header::flag2(&state, Flags2::RequestMouse), 1);
         assert_ok_eq!(header::flag2(&state, Flags2::RequestColours), 1);
         assert_ok_eq!(header::flag2(&state, Flags2::RequestSoundEffects), 1);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestMenus), 0);
         assert_ok_eq!(
             header::field_byte(&state, HeaderField::DefaultBackground),
             2
@@ -1128,13 +1631,46 @@ mod tests {
         assert!(!frame.sound_interrupt());
     }
 
+    #[test]
+    fn test_initialize_v5_capabilities() {
+        let mut map = test_map(5);
+        map[0x11] = 0xF8;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        let capabilities = Capabilities {
+            colors: false,
+            italic: false,
+            timed_input: false,
+            mouse: false,
+            ..capabilities(24, 80, (9, 2), true)
+        };
+        assert!(state.initialize(&capabilities).is_ok());
+        assert_ok_eq!(header::flag1(&state, Flags1v4::ColoursAvailable as u8), 0);
+        assert_ok_eq!(header::flag1(&state, Flags1v4::BoldfaceAvailable as u8), 1);
+        assert_ok_eq!(header::flag1(&state, Flags1v4::ItalicAvailable as u8), 0);
+        assert_ok_eq!(
+            header::flag1(&state, Flags1v4::FixedSpaceAvailable as u8),
+            1
+        );
+        assert_ok_eq!(
+            header::flag1(&state, Flags1v4::TimedInputAvailable as u8),
+            0
+        );
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestUndo), 1);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestMouse), 0);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestColours), 0);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestSoundEffects), 1);
+    }
+
     #[test]
     fn test_initialize_v5_no_sounds() {
         let mut map = test_map(5);
         map[0x11] = 0xF8;
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), false).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
         assert_ok_eq!(header::flag1(&state, Flags1v4::PicturesAvailable as u8), 0);
         assert_ok_eq!(header::flag1(&state, Flags1v4::ColoursAvailable as u8), 1);
         assert_ok_eq!(header::flag1(&state, Flags1v4::BoldfaceAvailable as u8), 1);
@@ -1466,6 +2002,27 @@ mod tests {
         assert_ok_eq!(state.variable(0xFF), 0x6655);
     }
 
+    #[test]
+    fn test_global() {
+        let mut map = test_map(3);
+        // G00
+        map[0x100] = 0x11;
+        map[0x101] = 0x22;
+        // GEF
+        map[0x2DE] = 0x55;
+        map[0x2DF] = 0x66;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        // No routine is running
+        state.frames.clear();
+        assert_ok_eq!(state.global(0), 0x1122);
+        assert_ok_eq!(state.global(0xEF), 0x5566);
+        assert!(state.set_global(0x70, 0x3344).is_ok());
+        assert_ok_eq!(state.variable(0x80), 0x3344);
+        assert!(state.global(0xF0).is_err());
+        assert!(state.set_global(0xF0, 0).is_err());
+    }
+
     #[test]
     fn test_set_variable_local() {
         let mut map = test_map(3);
@@ -1674,7 +2231,7 @@ mod tests {
         }
         let m: Memory = Memory::new(map);
         let state = assert_ok!(State::new(m));
-        assert_eq!(
+        assert_ok_eq!(
             state.instruction(0x400),
             &[
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
@@ -1683,6 +2240,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_instruction_end_of_memory() {
+        let mut map = test_map(3);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        let m: Memory = Memory::new(map);
+        let state = assert_ok!(State::new(m));
+        let mut expected = vec![0xFC, 0xFD, 0xFE, 0xFF];
+        expected.resize(23, 0);
+        assert_ok_eq!(state.instruction(0x7FC), expected);
+        assert!(state.instruction(0x800).is_err());
+    }
+
     #[test]
     fn test_routine_header_v3() {
         let mut map = vec![0; 0x11000];
@@ -1766,6 +2337,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_literal_unterminated() {
+        let m = Memory::new(vec![0; 0x11000]);
+        let mut state = assert_ok!(State::new(m));
+        state.set_max_string_words(0x100);
+        let e = state.string_literal(0x10000).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::UnterminatedString);
+        assert!(e.is_recoverable());
+        assert_eq!(
+            e.message(),
+            "String at $010000 is not terminated within 256 words"
+        );
+    }
+
+    #[test]
+    fn test_string_literal_max_words() {
+        let mut map = vec![0; 0x11000];
+        map[0x101FE] = 0x80;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        // A string of exactly the maximum length is fine
+        state.set_max_string_words(0x100);
+        assert_eq!(assert_ok!(state.string_literal(0x10000)).len(), 0x100);
+        state.set_max_string_words(0xFF);
+        assert!(state.string_literal(0x10000).is_err());
+    }
+
     #[test]
     fn test_packed_routine_address_v3() {
         let map = test_map(3);
@@ -1809,11 +2407,33 @@ mod tests {
     }
 
     #[test]
-    fn test_packed_routine_address_invalid() {
-        let map = test_map(6);
+    fn test_packed_routine_address_v7_too_large() {
+        let mut map = test_map(7);
+        // Routine offset is 0xFFFF, or $7FFF8
+        map[0x28] = 0xFF;
+        map[0x29] = 0xFF;
+        let m = Memory::new(map);
+        let state = assert_ok!(State::new(m));
+        assert_ok_eq!(state.packed_routine_address(0x1), 0x7FFFC);
+        let e = state
+            .packed_routine_address(0x2)
+            .expect_err("expected an illegal access");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::IllegalMemoryAccess);
+        assert_eq!(
+            e.message(),
+            "Routine packed address $0002 unpacks to $080000, beyond the version 7 limit of $080000"
+        );
+    }
+
+    #[test]
+    fn test_packed_routine_address_v6() {
+        let mut map = test_map(6);
+        // Routine offset is 0x100;
+        map[0x28] = 0x1;
         let m = Memory::new(map);
         let state = assert_ok!(State::new(m));
-        assert!(state.packed_routine_address(0x400).is_err());
+        assert_ok_eq!(state.packed_routine_address(0x400), 0x1800);
     }
 
     #[test]
@@ -1859,11 +2479,25 @@ mod tests {
     }
 
     #[test]
-    fn test_packed_string_address_invalid() {
-        let map = test_map(6);
+    fn test_packed_string_address_v6() {
+        let mut map = test_map(6);
+        // String offset is 0x100;
+        map[0x2A] = 0x1;
         let m = Memory::new(map);
         let state = assert_ok!(State::new(m));
-        assert!(state.packed_string_address(0x400).is_err());
+        assert_ok_eq!(state.packed_string_address(0x400), 0x1800);
+    }
+
+    #[test]
+    fn test_initialize_v6_main_routine() {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(6))));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        // The main routine at $0400 is called from a base frame
+        assert_eq!(state.frame_count(), 2);
+        assert_eq!(state.frames[1].address(), 0x400);
+        assert_ok_eq!(state.pc(), 0x401);
     }
 
     #[test]
@@ -1872,7 +2506,11 @@ mod tests {
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
         assert!(!state.is_input_interrupt());
-        state.read_interrupt_result = Some(0x10);
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        assert!(!state.is_input_interrupt());
+        assert_ok!(state.current_frame_mut()).set_input_interrupt(true);
         assert!(state.is_input_interrupt());
     }
 
@@ -1887,7 +2525,7 @@ mod tests {
         assert_ok_eq!(
             state.call_routine(
                 0x10000,
-                &vec![0x1111, 0x2222, 0x3333],
+                &[0x1111, 0x2222, 0x3333],
                 Some(StoreResult::new(0x401, 0x80)),
                 0x402
             ),
@@ -1908,6 +2546,79 @@ mod tests {
         assert!(!frame.sound_interrupt());
     }
 
+    #[test]
+    fn test_backtrace() {
+        let mut map = test_map(3);
+        mock_routine(&mut map, 0x600, &[0x1111, 0x2222]);
+        mock_routine(&mut map, 0x620, &[0x3333]);
+        mock_routine(&mut map, 0x641, &[]);
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        assert!(state
+            .call_routine(0x600, &[], Some(StoreResult::new(0x403, 0x10)), 0x404)
+            .is_ok());
+        assert!(state.call_routine(0x620, &[0x4444], None, 0x608).is_ok());
+        assert!(state
+            .call_routine(0x641, &[], Some(StoreResult::new(0x626, 0)), 0x627)
+            .is_ok());
+        assert_eq!(
+            state.backtrace(),
+            [
+                FrameInfo {
+                    address: 0x641,
+                    packed_address: None,
+                    return_address: 0x627,
+                    result: Some(StoreResult::new(0x626, 0)),
+                    local_variables: vec![],
+                },
+                FrameInfo {
+                    address: 0x620,
+                    packed_address: Some(0x310),
+                    return_address: 0x608,
+                    result: None,
+                    local_variables: vec![0x4444],
+                },
+                FrameInfo {
+                    address: 0x600,
+                    packed_address: Some(0x300),
+                    return_address: 0x404,
+                    result: Some(StoreResult::new(0x403, 0x10)),
+                    local_variables: vec![0x1111, 0x2222],
+                },
+                FrameInfo {
+                    address: 0,
+                    packed_address: None,
+                    return_address: 0,
+                    result: None,
+                    local_variables: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packed_routine() {
+        let mut map = test_map(5);
+        let state = assert_ok!(State::new(Memory::new(map.clone())));
+        assert_some_eq!(state.packed_routine(0x600), 0x180);
+        assert!(state.packed_routine(0x602).is_none());
+        assert!(state.packed_routine(0x40000).is_none());
+
+        map[0] = 7;
+        // Routines offset $0040 (* 8 = $0200)
+        map[0x29] = 0x40;
+        let state = assert_ok!(State::new(Memory::new(map.clone())));
+        assert_some_eq!(state.packed_routine(0x600), 0x100);
+        assert!(state.packed_routine(0x100).is_none());
+
+        map[0] = 8;
+        let state = assert_ok!(State::new(Memory::new(map)));
+        assert_some_eq!(state.packed_routine(0x600), 0xC0);
+    }
+
     #[test]
     fn test_call_routine_no_store() {
         let mut map = vec![0; 0x11000];
@@ -1917,7 +2628,7 @@ mod tests {
         let mut state = assert_ok!(State::new(m));
         assert_eq!(state.frame_count(), 0);
         assert_ok_eq!(
-            state.call_routine(0x10000, &vec![0x1111, 0x2222, 0x3333], None, 0x402),
+            state.call_routine(0x10000, &[0x1111, 0x2222, 0x3333], None, 0x402),
             0x10001
         );
         assert_eq!(state.frame_count(), 1);
@@ -1936,22 +2647,95 @@ mod tests {
     }
 
     #[test]
-    fn test_call_routine_0() {
-        let mut map = vec![0; 0x11000];
-        map[0] = 5;
-        map[0x0E] = 0x04;
-        map[0x0C] = 0x01;
-        map[0x10000] = 0xF;
-        for (i, b) in (0x40..0x800).enumerate() {
-            map[i + 0x40] = b as u8;
-        }
+    fn test_call_routine_trace() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[0, 0]);
+        mock_routine(&mut map, 0x700, &[0]);
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert_eq!(state.frame_count(), 0);
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        state.set_trace_calls(true, &[]);
+        capture_log("app::calls");
+        assert_ok_eq!(
+            state.call_routine(
+                0x600,
+                &[0x1111, 0x2222],
+                Some(StoreResult::new(0x401, 0x10)),
+                0x402
+            ),
+            0x601
+        );
+        assert_ok_eq!(state.call_routine(0x700, &[0x3333], None, 0x610), 0x701);
+        assert_ok_eq!(state.return_routine(0x1234), 0x610);
+        assert_ok_eq!(state.return_routine(0x5678), 0x402);
+        assert_ok_eq!(state.variable(0x10), 0x5678);
+        assert_eq!(
+            log(),
+            &[
+                "  call $000600(1111, 2222) -> G00",
+                "    call $000700(3333) -> -",
+                "    return $000700 => 1234",
+                "  return $000600 => 5678"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_routine_trace_filtered() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[0, 0]);
+        mock_routine(&mut map, 0x700, &[0]);
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        state.set_trace_calls(true, &[0x700]);
+        capture_log("app::calls");
+        assert_ok_eq!(state.call_routine(0x600, &[], None, 0x402), 0x601);
+        assert_ok_eq!(state.call_routine(0x700, &[], None, 0x610), 0x701);
+        assert_ok_eq!(state.return_routine(0), 0x610);
+        assert_ok_eq!(state.return_routine(1), 0x402);
+        assert_eq!(
+            log(),
+            &["    call $000700() -> -", "    return $000700 => 0000"]
+        );
+    }
+
+    #[test]
+    fn test_call_routine_no_trace() {
+        let mut map = test_map(5);
+        mock_routine(&mut map, 0x600, &[0, 0]);
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        capture_log("app::calls");
+        assert_ok_eq!(state.call_routine(0x600, &[], None, 0x402), 0x601);
+        assert_ok_eq!(state.return_routine(0), 0x402);
+        assert!(log().is_empty());
+    }
+
+    #[test]
+    fn test_call_routine_0() {
+        let mut map = vec![0; 0x11000];
+        map[0] = 5;
+        map[0x0E] = 0x04;
+        map[0x0C] = 0x01;
+        map[0x10000] = 0xF;
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert_eq!(state.frame_count(), 0);
         assert_ok_eq!(
             state.call_routine(
                 0,
-                &vec![0x1111, 0x2222, 0x3333],
+                &[0x1111, 0x2222, 0x3333],
                 Some(StoreResult::new(0x401, 0x80)),
                 0x402
             ),
@@ -1975,7 +2759,7 @@ mod tests {
         let mut state = assert_ok!(State::new(m));
         assert_eq!(state.frame_count(), 0);
         assert_ok_eq!(
-            state.call_routine(0, &vec![0x1111, 0x2222, 0x3333], None, 0x402),
+            state.call_routine(0, &[0x1111, 0x2222, 0x3333], None, 0x402),
             0x402
         );
         assert_eq!(state.frame_count(), 0);
@@ -1989,12 +2773,16 @@ mod tests {
         map[0x10000] = 0xF;
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
-        assert_eq!(state.frame_count(), 0);
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
         state.set_read_interrupt();
         assert!(state.read_interrupt_pending());
         assert_ok_eq!(state.call_read_interrupt(0x10000, 0x402), 0x10001);
-        assert_eq!(state.frame_count(), 1);
-        assert_some_eq!(state.read_interrupt_result(), 0);
+        assert_eq!(state.frame_count(), 2);
+        // The interrupted read is the caller's
+        assert!(state.read_interrupt().result.is_none());
+        assert_some_eq!(state.frames[0].read_interrupt().result, 0);
         let frame = assert_ok!(state.current_frame());
         assert_eq!(frame.address(), 0x10000);
         assert_eq!(frame.pc(), 0x10001);
@@ -2023,8 +2811,16 @@ mod tests {
         let map = test_map(5);
         let m = Memory::new(map);
         let mut state = assert_ok!(State::new(m));
+        // Nothing to keep it with
         state.set_read_interrupt();
-        state.read_interrupt_result = Some(0);
+        assert!(!state.read_interrupt_pending());
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        state.set_read_interrupt();
+        assert_ok!(state.current_frame_mut())
+            .read_interrupt_mut()
+            .result = Some(0);
         assert!(state.read_interrupt_pending());
         assert_some_eq!(state.read_interrupt_result(), 0);
         state.clear_read_interrupt();
@@ -2032,6 +2828,39 @@ mod tests {
         assert!(state.read_interrupt_result().is_none());
     }
 
+    #[test]
+    fn test_read_interrupt_nested() {
+        let mut map = vec![0; 0x11000];
+        map[0] = 5;
+        map[0x10000] = 0xF;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        state
+            .frames
+            .push(Frame::new(0x400, 0x400, &[], 0, &[], None, 0));
+        // A timed read, interrupted
+        state.set_read_interrupt();
+        assert!(state.call_read_interrupt(0x10000, 0x402).is_ok());
+        // Printing by the interrupt routine is noted against the interrupted read
+        state.set_input_interrupt_print();
+        assert!(!state.input_interrupt_print());
+        assert!(state.frames[0].read_interrupt().printed);
+        // The interrupt routine's own timed read, interrupted in turn
+        state.set_read_interrupt();
+        assert!(state.call_read_interrupt(0x10000, 0x10010).is_ok());
+        assert_eq!(state.frame_count(), 3);
+        assert_ok_eq!(state.return_routine(1), 0x10010);
+        assert_some_eq!(state.read_interrupt_result(), 1);
+        state.clear_read_interrupt();
+        // The outer read is untouched until its own interrupt routine returns
+        assert!(state.frames[0].read_interrupt().pending);
+        assert_some_eq!(state.frames[0].read_interrupt().result, 0);
+        assert_ok_eq!(state.return_routine(1), 0x402);
+        assert!(state.read_interrupt_pending());
+        assert_some_eq!(state.read_interrupt_result(), 1);
+        assert!(state.input_interrupt_print());
+    }
+
     #[test]
     fn test_sound_interrupt() {
         let map = test_map(5);
@@ -2066,6 +2895,58 @@ mod tests {
         assert!(frame.sound_interrupt());
     }
 
+    #[test]
+    fn test_in_sound_interrupt() {
+        let mut map = vec![0; 0x11000];
+        map[0] = 5;
+        map[0x10000] = 0xF;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state.call_routine(0x10000, &[], None, 0x400).is_ok());
+        assert!(!state.in_sound_interrupt());
+        state.set_sound_interrupt(0x10000);
+        assert!(state.call_sound_interrupt(0x402).is_ok());
+        assert!(state.in_sound_interrupt());
+        // An ordinary call from the interrupt routine doesn't end it
+        assert!(state.call_routine(0x10000, &[], None, 0x10010).is_ok());
+        assert!(state.return_routine(0).is_ok());
+        assert!(state.in_sound_interrupt());
+        assert_ok_eq!(state.return_routine(1), 0x402);
+        assert!(!state.in_sound_interrupt());
+        // Throwing past the interrupt routine also ends it
+        state.set_sound_interrupt(0x10000);
+        assert!(state.call_sound_interrupt(0x402).is_ok());
+        assert!(state.call_routine(0x10000, &[], None, 0x10010).is_ok());
+        assert_eq!(state.frame_count(), 3);
+        assert!(state.throw(2, 0).is_ok());
+        assert!(!state.in_sound_interrupt());
+    }
+
+    #[test]
+    fn test_in_interrupt() {
+        let mut map = vec![0; 0x11000];
+        map[0] = 5;
+        map[0x10000] = 0xF;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state.call_routine(0x10000, &[], None, 0x400).is_ok());
+        assert!(!state.in_interrupt());
+        state.set_read_interrupt();
+        assert!(state.call_read_interrupt(0x10000, 0x402).is_ok());
+        assert!(state.in_interrupt());
+        // Including routines the interrupt routine calls
+        assert!(state.call_routine(0x10000, &[], None, 0x10010).is_ok());
+        assert!(state.in_interrupt());
+        assert!(state.return_routine(0).is_ok());
+        assert!(state.return_routine(1).is_ok());
+        assert!(!state.in_interrupt());
+        state.set_sound_interrupt(0x10000);
+        assert!(state.call_sound_interrupt(0x402).is_ok());
+        assert!(state.in_interrupt());
+        assert!(state.return_routine(1).is_ok());
+        assert!(!state.in_interrupt());
+    }
+
     #[test]
     fn test_call_sound_interrupt_not_pending() {
         let mut map = vec![0; 0x11000];
@@ -2203,7 +3084,8 @@ mod tests {
         ));
         let cf = assert_ok!(state.current_frame_mut());
         cf.set_input_interrupt(true);
-        state.set_read_interrupt();
+        // The timed read was made by the caller
+        state.frames[0].read_interrupt_mut().pending = true;
         assert_eq!(state.frame_count(), 2);
         assert_ok_eq!(state.return_routine(0x9876), 0x40E);
         assert_eq!(state.frame_count(), 1);
@@ -2308,6 +3190,31 @@ mod tests {
         assert_eq!(state.current_frame().unwrap().argument_count(), 1);
     }
 
+    #[test]
+    fn test_argument_count_interrupt() {
+        let mut map = vec![0; 0x11000];
+        map[0] = 5;
+        map[0x600] = 0xF;
+        map[0x10000] = 0xF;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state
+            .call_routine(0x600, &[0x1111, 0x2222, 0x3333], None, 0x400)
+            .is_ok());
+        assert_ok_eq!(state.argument_count(), 3);
+        // Interrupt routines are called without arguments, regardless of the interrupted frame
+        state.set_read_interrupt();
+        assert!(state.call_read_interrupt(0x10000, 0x601).is_ok());
+        assert_ok_eq!(state.argument_count(), 0);
+        assert!(state.return_routine(0).is_ok());
+        assert_ok_eq!(state.argument_count(), 3);
+        state.set_sound_interrupt(0x10000);
+        assert!(state.call_sound_interrupt(0x601).is_ok());
+        assert_ok_eq!(state.argument_count(), 0);
+        assert!(state.return_routine(0).is_ok());
+        assert_ok_eq!(state.argument_count(), 3);
+    }
+
     #[test]
     fn test_save() {
         let mut map = test_map(3);
@@ -2333,6 +3240,8 @@ mod tests {
         assert!(state.write_byte(0x280, 0x10).is_ok());
         assert!(state.write_byte(0x300, 0xFD).is_ok());
 
+        // The base frame, then two routines
+        state.frames.push(Frame::new(0, 0, &[], 0, &[], None, 0));
         state.frames.push(Frame::new(
             0x500,
             0x501,
@@ -2352,17 +3261,18 @@ mod tests {
             0x623,
         ));
 
-        let v = assert_ok!(state.save(0x9abc));
+        let v = assert_ok!(state.save(0x9abc, None));
         assert_eq!(
             v,
             [
-                b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x54, b'I', b'F', b'Z', b'S', b'I', b'F',
+                b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5C, b'I', b'F', b'Z', b'S', b'I', b'F',
                 b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
                 0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
                 0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-                b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-                0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-                0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb
+                b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+                0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+                0x88, 0x99, 0xaa, 0xbb
             ]
         );
     }
@@ -2386,20 +3296,23 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
         assert_eq!(state.frame_count(), 1);
 
         let quetzal = assert_ok!(Quetzal::try_from(vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ]));
         let pc = assert_ok!(state.restore_state(quetzal));
         assert_some_eq!(pc, 0x9abc);
@@ -2417,7 +3330,7 @@ mod tests {
         assert_ok_eq!(state.read_byte(0x200), 0xFC);
         assert_ok_eq!(state.read_byte(0x280), 0x10);
         assert_ok_eq!(state.read_byte(0x300), 0xFD);
-        assert_eq!(state.frame_count(), 2);
+        assert_eq!(state.frame_count(), 3);
     }
 
     #[test]
@@ -2439,7 +3352,9 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
@@ -2450,7 +3365,7 @@ mod tests {
         mem_data[0x280] = 0x10;
         mem_data[0x300] = 0xFD;
         let mut qvec = [
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x04, 0x49, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x04, 0x51, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'U', b'M', b'e', b'm', 0x00, 0x00, 0x04, 0x00,
         ]
@@ -2458,9 +3373,10 @@ mod tests {
         qvec.append(&mut mem_data);
         qvec.append(
             &mut [
-                b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-                0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-                0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+                b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+                0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+                0x88, 0x99, 0xaa, 0xbb,
             ]
             .to_vec(),
         );
@@ -2481,7 +3397,62 @@ mod tests {
         assert_ok_eq!(state.read_byte(0x200), 0xFC);
         assert_ok_eq!(state.read_byte(0x280), 0x10);
         assert_ok_eq!(state.read_byte(0x300), 0xFD);
-        assert_eq!(state.frame_count(), 2);
+        assert_eq!(state.frame_count(), 3);
+    }
+
+    #[test]
+    fn test_restore_state_umem_oversized() {
+        let mut map = test_map(5);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+        map[0x02] = 0x12;
+        map[0x03] = 0x34;
+        map[0x12] = b'2';
+        map[0x13] = b'3';
+        map[0x14] = b'0';
+        map[0x15] = b'7';
+        map[0x16] = b'1';
+        map[0x17] = b'5';
+        map[0x1C] = 0x56;
+        map[0x1D] = 0x78;
+
+        let m = Memory::new(map.clone());
+        let mut state = assert_ok!(State::new(m));
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
+        assert_eq!(state.frame_count(), 1);
+
+        // 2 bytes more than dynamic memory
+        let mut mem_data = map[..0x402].to_vec();
+        mem_data[0x200] = 0xFC;
+        let mut qvec = [
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x04, 0x53, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
+            0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'U', b'M', b'e', b'm', 0x00, 0x00, 0x04, 0x02,
+        ]
+        .to_vec();
+        qvec.append(&mut mem_data);
+        qvec.append(
+            &mut [
+                b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+                0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+                0x88, 0x99, 0xaa, 0xbb,
+            ]
+            .to_vec(),
+        );
+        let quetzal = assert_ok!(Quetzal::try_from(qvec));
+        let e = state
+            .restore_state(quetzal)
+            .expect_err("expected a restore error");
+        assert!(e.is_recoverable());
+        assert_eq!(e.code(), ErrorCode::Restore);
+        // The game continues from its pre-restore state
+        assert_ok_eq!(state.read_byte(0x200), 0x00);
+        assert_eq!(state.frame_count(), 1);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenColumns), 132);
     }
 
     // #[test]
@@ -2503,7 +3474,7 @@ mod tests {
 
     //     let m = Memory::new(map.clone());
     //     let mut state = assert_ok!(State::new(m));
-    //     assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+    //     assert!(state.initialize(&capabilities(40, 132, (3, 6), true)).is_ok());
     //     // Turn on transcripting ... it should survive the restore
     //     assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
@@ -2523,6 +3494,136 @@ mod tests {
     //     assert_eq!(state.frame_count(), 1);
     // }
 
+    #[test]
+    fn test_restore_state_request_redraw() {
+        let mut map = test_map(5);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestRedraw), 0);
+
+        let quetzal = assert_ok!(Quetzal::try_from(vec![
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
+            0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
+            0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
+        ]));
+        assert!(state.restore_state(quetzal).is_ok());
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestRedraw), 1);
+    }
+
+    // V5 state with a header extension table at 0x80 holding mouse coordinates, the screen
+    // resized since it was initialized, and transcripting and fixed-pitch on
+    fn interpreter_header_state() -> State {
+        let mut map = test_map(5);
+        map[0x37] = 0x80;
+        map[0x81] = 2;
+        let mut state = assert_ok!(State::new(Memory::new(map)));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
+        assert!(state.resize(&capabilities(40, 132, (9, 2), false)).is_ok());
+        assert!(header::set_extension(&mut state, 1, 12).is_ok());
+        assert!(header::set_extension(&mut state, 2, 5).is_ok());
+        assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
+        assert!(header::set_flag2(&mut state, Flags2::ForceFixedPitch).is_ok());
+        // Set by the game, so not preserved
+        assert!(header::set_flag2(&mut state, Flags2::RequestUndo).is_ok());
+        state
+    }
+
+    fn assert_interpreter_header(state: &State) {
+        assert_ok_eq!(header::flag2(state, Flags2::Transcripting), 1);
+        assert_ok_eq!(header::flag2(state, Flags2::ForceFixedPitch), 1);
+        assert_ok_eq!(header::flag2(state, Flags2::RequestUndo), 0);
+        assert_ok_eq!(header::field_byte(state, HeaderField::InterpreterNumber), 6);
+        assert_ok_eq!(
+            header::field_byte(state, HeaderField::InterpreterVersion),
+            b'Z'
+        );
+        assert_ok_eq!(header::field_byte(state, HeaderField::ScreenLines), 40);
+        assert_ok_eq!(header::field_byte(state, HeaderField::ScreenColumns), 132);
+        assert_ok_eq!(header::field_word(state, HeaderField::ScreenHeight), 40);
+        assert_ok_eq!(header::field_word(state, HeaderField::ScreenWidth), 132);
+        assert_ok_eq!(header::field_byte(state, HeaderField::FontWidth), 1);
+        assert_ok_eq!(header::field_byte(state, HeaderField::FontHeight), 1);
+        assert_ok_eq!(header::field_byte(state, HeaderField::DefaultForeground), 9);
+        assert_ok_eq!(header::field_byte(state, HeaderField::DefaultBackground), 2);
+        assert_ok_eq!(state.read_word(0x82), 12);
+        assert_ok_eq!(state.read_word(0x84), 5);
+        assert_ok_eq!(header::field_byte(state, HeaderField::Revision), 1);
+        assert_ok_eq!(header::field_byte(state, HeaderField::RevisionMinor), 0);
+    }
+
+    #[test]
+    fn test_restore_state_interpreter_header() {
+        let mut state = interpreter_header_state();
+        let snapshot = assert_ok!(header::snapshot_interpreter_state(&state));
+        let quetzal = assert_ok!(Quetzal::try_from(vec![
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
+            0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
+            0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
+        ]));
+        assert!(state.restore_state(quetzal).is_ok());
+        assert_interpreter_header(&state);
+        assert_ok_eq!(header::snapshot_interpreter_state(&state), snapshot);
+    }
+
+    #[test]
+    fn test_restart_interpreter_header() {
+        let mut state = interpreter_header_state();
+        let snapshot = assert_ok!(header::snapshot_interpreter_state(&state));
+        assert!(state.restart().is_ok());
+        assert_interpreter_header(&state);
+        assert_ok_eq!(header::snapshot_interpreter_state(&state), snapshot);
+    }
+
+    #[test]
+    fn test_resize_v3() {
+        let mut map = test_map(3);
+        map[0x20] = 24;
+        map[0x21] = 80;
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state.resize(&capabilities(40, 132, (9, 2), false)).is_ok());
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenLines), 24);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenColumns), 80);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestRedraw), 1);
+    }
+
+    #[test]
+    fn test_resize_v5() {
+        let m = Memory::new(test_map(5));
+        let mut state = assert_ok!(State::new(m));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
+        assert!(state.resize(&capabilities(40, 132, (9, 2), false)).is_ok());
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenLines), 40);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenColumns), 132);
+        assert_ok_eq!(header::field_word(&state, HeaderField::ScreenHeight), 40);
+        assert_ok_eq!(header::field_word(&state, HeaderField::ScreenWidth), 132);
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestRedraw), 1);
+        // The game clears the bit once it has redrawn the screen
+        assert!(header::clear_flag2(&mut state, Flags2::RequestRedraw).is_ok());
+        assert_ok_eq!(header::flag2(&state, Flags2::RequestRedraw), 0);
+    }
+
     #[test]
     fn test_restore() {
         let mut map = test_map(5);
@@ -2542,23 +3643,27 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
         assert_eq!(state.frame_count(), 1);
 
         let restore_data = vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ];
-        let pc = assert_ok!(state.restore(restore_data));
+        let (pc, intd) = assert_ok!(state.restore(restore_data));
         assert_some_eq!(pc, 0x9abc);
+        assert!(intd.is_none());
         assert_ok_eq!(header::flag2(&state, Flags2::Transcripting), 1);
         assert_ok_eq!(
             header::field_byte(&state, HeaderField::DefaultForeground),
@@ -2573,7 +3678,117 @@ mod tests {
         assert_ok_eq!(state.read_byte(0x200), 0xFC);
         assert_ok_eq!(state.read_byte(0x280), 0x10);
         assert_ok_eq!(state.read_byte(0x300), 0xFD);
+        assert_eq!(state.frame_count(), 3);
+    }
+
+    // Save data for the state with a hand-built Stks chunk
+    fn quetzal_with_stks(state: &State, stks: Vec<Stk>) -> Vec<u8> {
+        let ifhd = assert_ok!(IFhd::try_from((state, 0x480)));
+        let mem = assert_ok!(Mem::try_from(state));
+        Vec::from(Quetzal::new(ifhd, mem, Stks::new(stks)))
+    }
+
+    #[test]
+    fn test_restore_stks_v5() {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(5))));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        // Dummy frame with 2 values on the evaluation stack, then a routine
+        let data = quetzal_with_stks(
+            &state,
+            vec![
+                Stk::new(0, 0, 0, 0, &[], &[0x1111, 0x2222]),
+                Stk::new(0x48E, 0x02, 0x80, 0x01, &[0x1234, 0x5678], &[0x3333]),
+            ],
+        );
+        let (pc, _) = assert_ok!(state.restore(data));
+        assert_some_eq!(pc, 0x480);
         assert_eq!(state.frame_count(), 2);
+        assert_eq!(state.frames[0].stack(), &[0x1111, 0x2222]);
+        assert_eq!(state.frames[1].local_variables(), &[0x1234, 0x5678]);
+        let stks = assert_ok!(Stks::try_from(&state));
+        assert_eq!(stks.stks().len(), 2);
+        assert_eq!(stks.stks()[0].stack(), &[0x1111, 0x2222]);
+    }
+
+    #[test]
+    fn test_restore_stks_v6() {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(6))));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        // No dummy frame, the main routine comes first
+        let data = quetzal_with_stks(
+            &state,
+            vec![
+                Stk::new(0, 0x13, 0, 0, &[1, 2, 3], &[0x1111]),
+                Stk::new(0x48E, 0x01, 0x80, 0x01, &[0x1234], &[]),
+            ],
+        );
+        let (pc, _) = assert_ok!(state.restore(data));
+        assert_some_eq!(pc, 0x480);
+        assert_eq!(state.frame_count(), 3);
+        assert!(state.frames[0].stack().is_empty());
+        assert_eq!(state.frames[1].local_variables(), &[1, 2, 3]);
+        assert_eq!(state.frames[1].stack(), &[0x1111]);
+        assert_eq!(state.frames[2].local_variables(), &[0x1234]);
+        // The base frame isn't saved
+        let stks = assert_ok!(Stks::try_from(&state));
+        assert_eq!(stks.stks().len(), 2);
+        assert_eq!(stks.stks()[0].variables(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_restore_stks_no_frames() {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(5))));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        assert!(state.write_byte(0x200, 0x12).is_ok());
+        let data = quetzal_with_stks(&state, Vec::new());
+        assert!(state.write_byte(0x200, 0x34).is_ok());
+        let e = state.restore(data).expect_err("no frames");
+        assert_eq!(e.code(), ErrorCode::Quetzal);
+        // Nothing was changed
+        assert_ok_eq!(state.read_byte(0x200), 0x34);
+        assert_eq!(state.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_stks_v6_base_frame_stack() {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(6))));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        // Below the main routine
+        state.frames[0].push(0x1234);
+        let e = Stks::try_from(&state).expect_err("stack outside of a routine");
+        assert_eq!(e.code(), ErrorCode::Quetzal);
+    }
+
+    #[test]
+    fn test_save_restore_intd() {
+        let mut map = test_map(5);
+        for (i, b) in (0x40..0x800).enumerate() {
+            map[i + 0x40] = b as u8;
+        }
+
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), false))
+            .is_ok());
+        let intd = IntD::new("MXYZ", vec![1, 2, 3, 4]);
+        let save_data = assert_ok!(state.save(0x400, Some(intd.clone())));
+        let (pc, restored) = assert_ok!(state.restore(save_data));
+        assert_some_eq!(pc, 0x400);
+        assert_some_eq!(restored, intd);
+
+        // IntD data from other interpreters is ignored
+        let save_data = assert_ok!(state.save(0x400, Some(IntD::new("ABCD", vec![1, 2]))));
+        let (_, restored) = assert_ok!(state.restore(save_data));
+        assert!(restored.is_none());
     }
 
     #[test]
@@ -2595,20 +3810,23 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
         assert_eq!(state.frame_count(), 1);
 
         let restore_data = vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ];
         assert!(state.restore(restore_data).is_err());
         assert_eq!(state.frame_count(), 1);
@@ -2633,20 +3851,23 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
         assert_eq!(state.frame_count(), 1);
 
         let restore_data = vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ];
         assert!(state.restore(restore_data).is_err());
         assert_eq!(state.frame_count(), 1);
@@ -2671,20 +3892,23 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(40, 132, (3, 6), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(40, 132, (3, 6), true))
+            .is_ok());
         // Turn on transcripting ... it should survive the restore
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
 
         assert_eq!(state.frame_count(), 1);
 
         let restore_data = vec![
-            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x56, b'I', b'F', b'Z', b'S', b'I', b'F',
+            b'F', b'O', b'R', b'M', 0x00, 0x00, 0x00, 0x5E, b'I', b'F', b'Z', b'S', b'I', b'F',
             b'h', b'd', 0x00, 0x00, 0x00, 0x0D, 0x12, 0x34, 0x32, 0x33, 0x30, 0x37, 0x31, 0x35,
             0x56, 0x78, 0x00, 0x9a, 0xbc, 0x00, b'C', b'M', b'e', b'm', 0x00, 0x00, 0x00, 0x0D,
             0x00, 0xFF, 0x00, 0xFF, 0xFC, 0x00, 0x7E, 0x90, 0x00, 0x7E, 0xFD, 0x00, 0xFE, 0x00,
-            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x1E, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03,
-            0x00, 0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06,
-            0x23, 0x12, 0x00, 0x00, 0x00, 0x00, 0x88, 0x99, 0xaa, 0xbb,
+            b'S', b't', b'k', b's', 0x00, 0x00, 0x00, 0x26, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x8E, 0x03, 0x80, 0x03, 0x00, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66, 0x11, 0x11, 0x22, 0x22, 0x00, 0x06, 0x23, 0x12, 0x00, 0x00, 0x00, 0x00,
+            0x88, 0x99, 0xaa, 0xbb,
         ];
         assert!(state.restore(restore_data).is_err());
         assert_eq!(state.frame_count(), 1);
@@ -2737,7 +3961,7 @@ mod tests {
         assert_eq!(state.undo_stack.len(), 0);
         assert!(state.save_undo(0x9abc).is_ok());
         assert!(state.undo_stack.back().is_some());
-        let quetzal = assert_some!(state.undo_stack.back());
+        let quetzal = &assert_some!(state.undo_stack.back()).quetzal;
         let ifhd = quetzal.ifhd();
         assert_eq!(ifhd.release_number(), 0x1234);
         assert_eq!(ifhd.serial_number(), "230715".as_bytes());
@@ -2819,7 +4043,96 @@ mod tests {
         assert!(state
             .undo_stack
             .front()
-            .is_some_and(|x| x.ifhd().pc() == 0x2222))
+            .is_some_and(|x| x.quetzal.ifhd().pc() == 0x2222))
+    }
+
+    fn undo_state() -> State {
+        let mut state = assert_ok!(State::new(Memory::new(test_map(3))));
+        assert!(state.initialize(&Capabilities::default()).is_ok());
+        state
+    }
+
+    fn undo_pcs(state: &State) -> Vec<u32> {
+        state
+            .undo_stack
+            .iter()
+            .map(|u| u.quetzal.ifhd().pc())
+            .collect()
+    }
+
+    #[test]
+    fn test_save_undo_budget() {
+        let mut state = undo_state();
+        assert!(state.save_undo(0x401).is_ok());
+        // Each snapshot of unchanged memory is the same size
+        let size = state.undo_memory_usage();
+        assert_eq!(size, state.undo_stack[0].quetzal.size());
+        state.set_undo_limits(50, size * 3 + size / 2);
+        for pc in 0x402..0x406 {
+            assert!(state.save_undo(pc).is_ok());
+            assert!(state.undo_memory_usage() <= size * 3 + size / 2);
+        }
+        assert_eq!(undo_pcs(&state), &[0x403, 0x404, 0x405]);
+        assert_eq!(state.undo_memory_usage(), size * 3);
+
+        // Depth still applies
+        state.set_undo_limits(2, size * 10);
+        assert_eq!(undo_pcs(&state), &[0x404, 0x405]);
+        assert_eq!(state.undo_memory_usage(), size * 2);
+
+        assert_some_eq!(assert_ok!(state.restore_undo()), 0x405);
+        assert_eq!(state.undo_memory_usage(), size);
+    }
+
+    #[test]
+    fn test_save_undo_budget_sizes() {
+        let mut state = undo_state();
+        assert!(state.save_undo(0x401).is_ok());
+        let small = state.undo_memory_usage();
+        // Changed bytes spread through dynamic memory make for a bigger compressed snapshot
+        for address in (0x100..0x300).step_by(0x10) {
+            assert!(state.write_byte(address, 0xFF).is_ok());
+        }
+        assert!(state.save_undo(0x402).is_ok());
+        let big = state.undo_memory_usage() - small;
+        assert!(big > small);
+
+        state.set_undo_limits(10, small + big);
+        assert_eq!(undo_pcs(&state), &[0x401, 0x402]);
+        // Another big snapshot drops the oldest first, then the next oldest, to fit
+        assert!(state.save_undo(0x403).is_ok());
+        assert_eq!(undo_pcs(&state), &[0x403]);
+        assert_eq!(state.undo_memory_usage(), big);
+    }
+
+    #[test]
+    fn test_save_undo_over_budget() {
+        let mut state = undo_state();
+        assert!(state.save_undo(0x401).is_ok());
+        let small = state.undo_memory_usage();
+        state.set_undo_limits(10, small);
+        for address in (0x100..0x300).step_by(0x10) {
+            assert!(state.write_byte(address, 0xFF).is_ok());
+        }
+        let e = state
+            .save_undo(0x402)
+            .expect_err("expected an over-budget error");
+        assert_eq!(e.code(), ErrorCode::UndoTooLarge);
+        // The snapshot that fits is left alone
+        assert_eq!(undo_pcs(&state), &[0x401]);
+        assert_eq!(state.undo_memory_usage(), small);
+    }
+
+    #[test]
+    fn test_save_undo_no_depth() {
+        let mut state = undo_state();
+        assert!(state.save_undo(0x401).is_ok());
+        state.set_undo_limits(0, 1 << 20);
+        let e = state
+            .save_undo(0x402)
+            .expect_err("expected an undo depth error");
+        assert_eq!(e.code(), ErrorCode::UndoTooLarge);
+        assert!(state.restore_undo().is_err());
     }
 
     #[test]
@@ -2847,6 +4160,8 @@ mod tests {
         assert!(state.write_byte(0x280, 0x10).is_ok());
         assert!(state.write_byte(0x300, 0xFD).is_ok());
 
+        // The base frame, then two routines
+        state.frames.push(Frame::new(0, 0, &[], 0, &[], None, 0));
         state.frames.push(Frame::new(
             0x500,
             0x501,
@@ -2874,12 +4189,12 @@ mod tests {
         // Drop a frame
         assert!(state.frames.pop().is_some());
 
-        assert_eq!(state.frame_count(), 1);
+        assert_eq!(state.frame_count(), 2);
         assert_eq!(state.undo_stack.len(), 1);
         let pc = assert_ok!(state.restore_undo());
         assert_some_eq!(pc, 0x9876);
         assert_eq!(state.undo_stack.len(), 0);
-        assert_eq!(state.frame_count(), 2);
+        assert_eq!(state.frame_count(), 3);
         assert_ok_eq!(state.read_byte(0x200), 0xFC);
         assert_ok_eq!(state.read_byte(0x280), 0x10);
         assert_ok_eq!(state.read_byte(0x300), 0xFD);
@@ -2929,6 +4244,35 @@ mod tests {
         assert_ok_eq!(state.read_byte(0x300), 0xFD);
     }
 
+    #[test]
+    fn test_short_name_cache() {
+        let map = test_map(5);
+        let m = Memory::new(map);
+        let mut state = assert_ok!(State::new(m));
+        assert!(state.cached_short_name(1).is_none());
+        state.cache_short_name(1, vec![0x300..0x302, 0x380..0x389], &[b'a' as u16]);
+        assert_some_eq!(state.cached_short_name(1), vec![b'a' as u16]);
+        assert!(state.cached_short_name(2).is_none());
+        // Writes next to the cached ranges
+        assert!(state.write_byte(0x302, 0).is_ok());
+        assert!(state.write_word(0x37E, 0).is_ok());
+        assert!(state.write_byte(0x389, 0).is_ok());
+        assert_some_eq!(state.cached_short_name(1), vec![b'a' as u16]);
+        // A word write overlapping the end of the name
+        assert!(state.write_word(0x388, 0).is_ok());
+        assert!(state.cached_short_name(1).is_none());
+        assert_eq!(state.short_name_stats(), CacheStats { hits: 2, misses: 1 });
+
+        state.cache_short_name(1, vec![0x300..0x302, 0x380..0x389], &[b'a' as u16]);
+        assert!(state.write_byte(0x301, 0).is_ok());
+        assert!(state.cached_short_name(1).is_none());
+
+        state.cache_short_name(1, vec![0x300..0x302, 0x380..0x389], &[b'a' as u16]);
+        assert!(state.restart().is_ok());
+        assert!(state.cached_short_name(1).is_none());
+        assert_eq!(state.short_name_stats(), CacheStats { hits: 2, misses: 3 });
+    }
+
     #[test]
     fn test_restart() {
         let mut map = test_map(5);
@@ -2948,7 +4292,9 @@ mod tests {
 
         let m = Memory::new(map.clone());
         let mut state = assert_ok!(State::new(m));
-        assert!(state.initialize(24, 80, (9, 2), true).is_ok());
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
         assert!(header::set_flag2(&mut state, Flags2::Transcripting).is_ok());
         assert!(state.write_byte(0x200, 0xFC).is_ok());
         assert!(state.write_byte(0x280, 0x10).is_ok());
@@ -2968,4 +4314,26 @@ mod tests {
         assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenLines), 24);
         assert_ok_eq!(header::field_byte(&state, HeaderField::ScreenColumns), 80);
     }
+
+    #[test]
+    fn test_standard_version_restart_restore() {
+        let m = Memory::new(test_map(5));
+        let mut state = assert_ok!(State::new(m));
+        state.set_standard_version((1, 0));
+        assert!(state
+            .initialize(&capabilities(24, 80, (9, 2), true))
+            .is_ok());
+        assert_ok_eq!(header::field_byte(&state, HeaderField::Revision), 1);
+        assert_ok_eq!(header::field_byte(&state, HeaderField::RevisionMinor), 0);
+
+        // The bytes are in dynamic memory, so the game could overwrite them
+        assert!(state.save_undo(0x400).is_ok());
+        assert!(state.write_word(0x32, 0).is_ok());
+        assert!(state.restore_undo().is_ok());
+        assert_ok_eq!(state.read_word(0x32), 0x0100);
+
+        assert!(state.write_word(0x32, 0).is_ok());
+        assert!(state.restart().is_ok());
+        assert_ok_eq!(state.read_word(0x32), 0x0100);
+    }
 }
@@ -0,0 +1,139 @@
+use std::{fmt, time::Duration};
+
+use crate::{
+    error::{ErrorCode, RuntimeError},
+    quetzal::{IntD, INTERPRETER_ID},
+    recoverable_error,
+    zmachine::messages::Messages,
+};
+
+/// Turn count and play time for a game, carried across sessions in save files
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SessionStats {
+    turns: u32,
+    play_time: Duration,
+}
+
+impl SessionStats {
+    pub fn new(turns: u32, play_time: Duration) -> SessionStats {
+        SessionStats { turns, play_time }
+    }
+
+    pub fn turns(&self) -> u32 {
+        self.turns
+    }
+
+    pub fn play_time(&self) -> Duration {
+        self.play_time
+    }
+
+    /// The `status.session_stats` message for these statistics
+    pub fn format(&self, messages: &Messages) -> String {
+        let seconds = self.play_time.as_secs();
+        let time = format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            (seconds / 60) % 60,
+            seconds % 60
+        );
+        messages.format(
+            "status.session_stats",
+            &[("turns", &self.turns.to_string()), ("time", &time)],
+        )
+    }
+}
+
+impl fmt::Display for SessionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&Messages::default()))
+    }
+}
+
+/// Hit and miss counts for one of the interpreter's caches
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} hits, {} misses", self.hits, self.misses)
+    }
+}
+
+impl From<&SessionStats> for IntD {
+    fn from(value: &SessionStats) -> Self {
+        let mut data = value.turns.to_be_bytes().to_vec();
+        let seconds = u32::try_from(value.play_time.as_secs()).unwrap_or(u32::MAX);
+        data.extend_from_slice(&seconds.to_be_bytes());
+        IntD::new(INTERPRETER_ID, data)
+    }
+}
+
+impl TryFrom<&IntD> for SessionStats {
+    type Error = RuntimeError;
+
+    fn try_from(value: &IntD) -> Result<Self, Self::Error> {
+        let data = value.data();
+        if value.interpreter_id() != INTERPRETER_ID || data.len() < 8 {
+            return recoverable_error!(
+                ErrorCode::Restore,
+                "IntD chunk doesn't contain session statistics"
+            );
+        }
+
+        let turns = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let seconds = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        Ok(SessionStats::new(
+            turns,
+            Duration::from_secs(seconds as u64),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assert_ok, assert_ok_eq};
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            SessionStats::new(42, Duration::from_secs(3 * 3600 + 25 * 60 + 7)).to_string(),
+            "Turns: 42, play time: 3:25:07"
+        );
+        assert_eq!(
+            SessionStats::default().to_string(),
+            "Turns: 0, play time: 0:00:00"
+        );
+    }
+
+    #[test]
+    fn test_intd() {
+        let stats = SessionStats::new(0x1234, Duration::from_millis(0x10203040));
+        let intd = IntD::from(&stats);
+        assert_eq!(intd.interpreter_id(), "MXYZ");
+        assert_eq!(
+            intd.data(),
+            &vec![0x00, 0x00, 0x12, 0x34, 0x00, 0x04, 0x20, 0xD0]
+        );
+        assert_ok_eq!(
+            SessionStats::try_from(&intd),
+            SessionStats::new(0x1234, Duration::from_secs(0x420D0))
+        );
+    }
+
+    #[test]
+    fn test_intd_invalid() {
+        let intd = IntD::new("MXYZ", vec![0, 0, 0, 1, 0, 0, 0]);
+        assert!(SessionStats::try_from(&intd).is_err());
+        let intd = IntD::new("ZZZZ", vec![0, 0, 0, 1, 0, 0, 0, 1]);
+        assert!(SessionStats::try_from(&intd).is_err());
+        let intd = IntD::new("MXYZ", vec![0, 0, 0, 1, 0, 0, 0, 1, 0xFF]);
+        let stats = assert_ok!(SessionStats::try_from(&intd));
+        assert_eq!(stats.turns(), 1);
+        assert_eq!(stats.play_time(), Duration::from_secs(1));
+    }
+}
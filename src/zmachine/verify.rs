@@ -0,0 +1,359 @@
+use std::fmt;
+
+use super::state::{
+    header::{self, HeaderField},
+    memory::Memory,
+    State,
+};
+
+/// Summary of a story file's header and tables, built without running the game
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StoryReport {
+    version: u8,
+    release: u16,
+    serial: String,
+    file_length: usize,
+    header_checksum: u16,
+    checksum: Option<u16>,
+    objects: usize,
+    dictionary_entries: usize,
+    abbreviations: usize,
+    standard_revision: (u8, u8),
+    problems: Vec<String>,
+}
+
+impl StoryReport {
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+
+    pub fn dictionary_entries(&self) -> usize {
+        self.dictionary_entries
+    }
+
+    pub fn abbreviations(&self) -> usize {
+        self.abbreviations
+    }
+
+    pub fn standard_revision(&self) -> (u8, u8) {
+        self.standard_revision
+    }
+
+    /// Header problems found while checking the file
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    /// True when the computed checksum matches the one in the header
+    pub fn checksum_matches(&self) -> bool {
+        self.checksum == Some(self.header_checksum)
+    }
+}
+
+impl fmt::Display for StoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Version:            {}", self.version)?;
+        writeln!(f, "Release:            {}", self.release)?;
+        writeln!(f, "Serial:             {}", self.serial)?;
+        writeln!(f, "File length:        {:#x}", self.file_length)?;
+        match self.checksum {
+            Some(c) => writeln!(
+                f,
+                "Checksum:           {:#06x} (header {:#06x}) {}",
+                c,
+                self.header_checksum,
+                if self.checksum_matches() {
+                    "OK"
+                } else {
+                    "MISMATCH"
+                }
+            )?,
+            None => writeln!(f, "Checksum:           not computed")?,
+        }
+        writeln!(f, "Objects:            {}", self.objects)?;
+        writeln!(f, "Dictionary entries: {}", self.dictionary_entries)?;
+        writeln!(f, "Abbreviations:      {}", self.abbreviations)?;
+        write!(
+            f,
+            "Standard revision:  {}.{}",
+            self.standard_revision.0, self.standard_revision.1
+        )?;
+        for problem in &self.problems {
+            write!(f, "\nProblem:            {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+fn word(data: &[u8], address: usize) -> usize {
+    ((data[address] as usize) << 8) | data[address + 1] as usize
+}
+
+/// Check a story file without running it: validate the header addresses, compute the
+/// checksum, and count the objects, dictionary entries, and abbreviations.
+///
+/// Problems with the header are listed in the report rather than returned as errors, so
+/// that as much of the file as possible is checked.
+pub fn verify_story(data: &[u8]) -> StoryReport {
+    let mut report = StoryReport::default();
+    if data.len() < 0x40 {
+        report.problems.push(format!(
+            "File is too short for a header: {} bytes",
+            data.len()
+        ));
+        return report;
+    }
+
+    report.version = data[HeaderField::Version as usize];
+    if ![3, 4, 5, 6, 7, 8].contains(&report.version) {
+        report
+            .problems
+            .push(format!("Unsupported version {}", report.version));
+        return report;
+    }
+
+    let static_mark = word(data, HeaderField::StaticMark as usize);
+    if static_mark < 0x40 || static_mark > data.len() {
+        report.problems.push(format!(
+            "Static memory mark {:#06x} is outside the file",
+            static_mark
+        ));
+        return report;
+    }
+
+    let state = match State::new(Memory::new(data.to_vec())) {
+        Ok(state) => state,
+        Err(e) => {
+            report.problems.push(e.to_string());
+            return report;
+        }
+    };
+    let field = |f: HeaderField| header::field_word(&state, f).unwrap_or(0) as usize;
+
+    report.release = field(HeaderField::Release) as u16;
+    report.serial = data[0x12..0x18]
+        .iter()
+        .map(|b| {
+            if b.is_ascii_graphic() {
+                *b as char
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    report.header_checksum = field(HeaderField::Checksum) as u16;
+    report.standard_revision = (data[HeaderField::Revision as usize], data[0x33]);
+
+    // V6 starts by calling the main routine, so the initial PC is a packed routine address
+    let initial_pc = if report.version == 6 {
+        field(HeaderField::InitialPC) * 4 + field(HeaderField::RoutinesOffset) * 8
+    } else {
+        field(HeaderField::InitialPC)
+    };
+
+    // Addresses that must be inside dynamic memory, or anywhere in the file
+    for (name, address, limit) in [
+        ("Object table", field(HeaderField::ObjectTable), static_mark),
+        (
+            "Global variables",
+            field(HeaderField::GlobalTable),
+            static_mark,
+        ),
+        ("High memory mark", field(HeaderField::HighMark), data.len()),
+        ("Initial PC", initial_pc, data.len()),
+        ("Dictionary", field(HeaderField::Dictionary), data.len()),
+        (
+            "Abbreviations table",
+            field(HeaderField::AbbreviationsTable),
+            data.len(),
+        ),
+    ] {
+        if address < 0x40 || address >= limit {
+            report
+                .problems
+                .push(format!("{} address {:#06x} is out of range", name, address));
+        }
+    }
+
+    let scale = match report.version {
+        3 => 2,
+        4 | 5 => 4,
+        _ => 8,
+    };
+    report.file_length = field(HeaderField::FileLength) * scale;
+    if report.file_length > data.len() {
+        report.problems.push(format!(
+            "Header file length {:#x} is longer than the file ({:#x})",
+            report.file_length,
+            data.len()
+        ));
+    } else if report.file_length == 0 {
+        report
+            .problems
+            .push("Header file length is 0, the checksum can't be verified".to_string());
+    } else {
+        report.checksum = state.checksum().ok();
+    }
+    if let Err(e) = state.check_file_size() {
+        report.problems.push(e.message().to_string());
+    }
+
+    report.objects = count_objects(&state, report.version);
+    report.dictionary_entries = count_dictionary_entries(&state);
+    report.abbreviations = count_abbreviations(&state);
+    report
+}
+
+// The object table has no count, but the property tables conventionally follow the last
+// object, so the first object's property table address marks the end of the table
+fn count_objects(state: &State, version: u8) -> usize {
+    let table = header::field_word(state, HeaderField::ObjectTable).unwrap_or(0) as usize;
+    let (defaults, size, property_offset) = match version {
+        3 => (62, 9, 7),
+        _ => (126, 14, 12),
+    };
+    let first = table + defaults;
+    match state.read_word(first + property_offset) {
+        Ok(properties) if properties as usize > first => (properties as usize - first) / size,
+        _ => 0,
+    }
+}
+
+fn count_dictionary_entries(state: &State) -> usize {
+    let dictionary = header::field_word(state, HeaderField::Dictionary).unwrap_or(0) as usize;
+    let entries = state.memory().read_byte(dictionary).and_then(|separators| {
+        state
+            .memory()
+            .read_word(dictionary + separators as usize + 2)
+    });
+    match entries {
+        // A negative count means the entries are unsorted
+        Ok(count) => (count as i16).unsigned_abs() as usize,
+        Err(_) => 0,
+    }
+}
+
+fn count_abbreviations(state: &State) -> usize {
+    let table = header::field_word(state, HeaderField::AbbreviationsTable).unwrap_or(0) as usize;
+    if table == 0 {
+        return 0;
+    }
+
+    (0..96)
+        .filter(|i| matches!(state.memory().read_word(table + (i * 2)), Ok(a) if a != 0))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::test_map;
+
+    use super::*;
+
+    fn mock_story() -> Vec<u8> {
+        let mut map = test_map(3);
+        // Release 42, serial 240101
+        map[0x03] = 42;
+        map[0x12..0x18].copy_from_slice(b"240101");
+        // High mark, dictionary at $0400, abbreviations at $0040
+        map[0x05] = 0x40;
+        map[0x08] = 0x04;
+        map[0x18] = 0x00;
+        map[0x19] = 0x40;
+        // 2 abbreviations
+        map[0x41] = 0x80;
+        map[0x43] = 0x81;
+        // 3 objects, property tables start after the third object
+        let first = 0x200 + 62;
+        let properties = first + 27;
+        map[first + 7] = (properties >> 8) as u8;
+        map[first + 8] = properties as u8;
+        // Dictionary with 3 separators and 5 entries
+        map[0x400] = 3;
+        map[0x404] = 7;
+        map[0x406] = 5;
+        // File length is 0x800 / 2
+        map[0x1A] = 0x04;
+        map[0x1B] = 0x00;
+        map[0x7FF] = 0x10;
+        map
+    }
+
+    fn checksum(map: &[u8]) -> u16 {
+        map[0x40..]
+            .iter()
+            .fold(0, |c: u16, b| c.overflowing_add(*b as u16).0)
+    }
+
+    #[test]
+    fn test_verify_story() {
+        let mut map = mock_story();
+        let c = checksum(&map);
+        map[0x1C] = (c >> 8) as u8;
+        map[0x1D] = c as u8;
+
+        let report = verify_story(&map);
+        assert_eq!(report.problems(), &[] as &[String]);
+        assert!(report.checksum_matches());
+        assert_eq!(report.version(), 3);
+        assert_eq!(report.objects(), 3);
+        assert_eq!(report.dictionary_entries(), 5);
+        assert_eq!(report.abbreviations(), 2);
+        assert_eq!(report.standard_revision(), (0, 0));
+        let text = report.to_string();
+        assert!(text.contains("Release:            42"));
+        assert!(text.contains("Serial:             240101"));
+        assert!(text.contains("OK"));
+    }
+
+    #[test]
+    fn test_verify_story_checksum_mismatch() {
+        let mut map = mock_story();
+        let c = checksum(&map).wrapping_add(1);
+        map[0x1C] = (c >> 8) as u8;
+        map[0x1D] = c as u8;
+
+        let report = verify_story(&map);
+        assert!(!report.checksum_matches());
+        assert!(report.to_string().contains("MISMATCH"));
+    }
+
+    #[test]
+    fn test_verify_story_truncated() {
+        let mut map = mock_story();
+        map[0x1A] = 0x08;
+        let report = verify_story(&map);
+        assert!(!report.checksum_matches());
+        assert_eq!(report.problems().len(), 1);
+        assert!(report.problems()[0].contains("longer than the file"));
+    }
+
+    #[test]
+    fn test_verify_story_too_large() {
+        let mut map = mock_story();
+        map.resize(0x20001, 0);
+        let report = verify_story(&map);
+        assert_eq!(report.problems().len(), 1);
+        assert!(report.problems()[0].contains("larger than the 0x20000 bytes"));
+    }
+
+    #[test]
+    fn test_verify_story_bad_header() {
+        assert_eq!(verify_story(&[3; 0x20]).problems().len(), 1);
+        let mut map = mock_story();
+        map[0] = 2;
+        assert!(verify_story(&map).problems()[0].contains("Unsupported version"));
+        let mut map = mock_story();
+        map[0x0E] = 0x10;
+        assert!(verify_story(&map).problems()[0].contains("Static memory mark"));
+        let mut map = mock_story();
+        map[0x08] = 0x10;
+        let report = verify_story(&map);
+        assert!(report.problems()[0].contains("Dictionary"));
+        assert_eq!(report.dictionary_entries(), 0);
+    }
+}
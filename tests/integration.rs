@@ -0,0 +1,251 @@
+//! End-to-end tests: the interpreter plays the test programs in `zcode/` from a walkthrough,
+//! without a terminal, and what they print is checked at points along the way.  Unlike the
+//! unit tests, these run real compiled code through the decoder, processor, text, and
+//! screen layers together.
+
+use std::{fs, path::Path, process::Command};
+
+/// Play `story` from the `zcode/` directory with `walkthrough` as the player's input,
+/// returning the text printed in the lower window
+fn play(story: &str, walkthrough: &[&str]) -> String {
+    // Run in an empty directory, so any files the game writes don't end up in the tree
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let story = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("zcode")
+        .join(story);
+    play_in(dir.path(), &story, walkthrough)
+}
+
+// Play `story` in `dir` with `walkthrough` as the player's input
+fn play_in(dir: &Path, story: &Path, walkthrough: &[&str]) -> String {
+    let script = dir.join("walkthrough.txt");
+    let mut commands = walkthrough.join("\n");
+    commands.push('\n');
+    fs::write(&script, commands).expect("walkthrough written");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mxyzptlk"))
+        .current_dir(dir)
+        .args(["--predictable", "1", "--script"])
+        .arg(&script)
+        .arg(story)
+        .output()
+        .expect("interpreter run");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(
+        output.status.success(),
+        "{} exited with {}:\n{}",
+        story.display(),
+        output.status,
+        stdout
+    );
+    stdout
+}
+
+// Assert that `checkpoints` appear in `output`, in order
+fn assert_checkpoints(output: &str, checkpoints: &[&str]) {
+    let mut rest = output;
+    for checkpoint in checkpoints {
+        match rest.find(checkpoint) {
+            Some(i) => rest = &rest[i + checkpoint.len()..],
+            None => panic!("'{}' not found in order in:\n{}", checkpoint, output),
+        }
+    }
+}
+
+#[test]
+fn test_czech() {
+    let output = play("czech.z5", &[]);
+    assert_checkpoints(
+        &output,
+        &[
+            "print_num (0, 1, -1, 32767,-32768, -1): 0, 1, -1, 32767, -32768, -1",
+            "print_paddr (A long string that Inform will put in high memory):\nA long string that Inform will put in high memory",
+            "Abbreviations (I love 'xyzzy' [two times]): I love 'xyzzy'  I love 'xyzzy'",
+            "print_obj (Test Object #1Test Object #2): Test Object #1Test Object #2",
+            "Performed 425 tests.\nPassed: 406, Failed: 0, Print tests: 19",
+            "Didn't crash: hooray!",
+        ],
+    );
+}
+
+#[test]
+fn test_praxix() {
+    let output = play("praxix.z5", &["all"]);
+    assert_checkpoints(
+        &output,
+        &[
+            "Integer arithmetic:",
+            "Undo:",
+            "Multi-level undo:",
+            "Memory stream round-trip:",
+            "@throw/@catch:",
+            "@print_table - should print the alphabet in upper then lower case:",
+            "All tests passed.",
+        ],
+    );
+    assert!(!output.contains("FAILED"), "{}", output);
+}
+
+#[test]
+fn test_etude_line_input() {
+    let output = play("etude.z5", &["9", "Hello, sailor", "", "."]);
+    assert_checkpoints(
+        &output,
+        &[
+            "AccentedInput> Hello, sailor",
+            // Input is reduced to lower case
+            "code=104: ASCII character 'h'",
+            "code=44: ASCII character ','",
+            "code=32: ASCII character ' '",
+            "code=114: ASCII character 'r'",
+            "AccentedInput>",
+        ],
+    );
+}
+
+#[test]
+fn test_etude_undo() {
+    let output = play("etude.z5", &["13", "", "", "."]);
+    assert_checkpoints(
+        &output,
+        &[
+            "Simulating first move...\nSave succeeded.",
+            "Simulating second move...\nSave succeeded.",
+            "First undo...\nUndo succeeded (undid second move).",
+            "Your interpreter claims to support \"undo\", and it does.",
+        ],
+    );
+}
+
+#[test]
+fn test_strictz() {
+    // Each page of results waits for a key
+    let output = play("strictz.z5", &[""; 16]);
+    assert_checkpoints(
+        &output,
+        &[
+            "jin 5 6 => FALSE (correct)",
+            "jin 0 0 => TRUE (correct)",
+            "Testing insert_obj:",
+            "Testing remove_obj:",
+            "Test completed!",
+        ],
+    );
+}
+
+// Z-Machine code for a small V5 story, assembled a byte at a time
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+    // Branches waiting for their label: (offset of the branch bytes, label)
+    branches: Vec<(usize, &'static str)>,
+    labels: Vec<(&'static str, usize)>,
+}
+
+const ORIGIN: usize = 0x400;
+
+impl Assembler {
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.code.extend_from_slice(bytes);
+        self
+    }
+
+    fn label(&mut self, label: &'static str) -> &mut Self {
+        self.labels.push((label, self.code.len()));
+        self
+    }
+
+    // A branch, on true, to `label`, in the long form
+    fn branch(&mut self, label: &'static str) -> &mut Self {
+        self.branches.push((self.code.len(), label));
+        self.bytes(&[0x80, 0x00])
+    }
+
+    // PRINT_CHAR each character of `text`
+    fn print(&mut self, text: &str) -> &mut Self {
+        for c in text.bytes() {
+            let c = if c == b'\n' { 13 } else { c };
+            self.bytes(&[0xE5, 0x7F, c]);
+        }
+        self
+    }
+
+    fn story(&mut self) -> Vec<u8> {
+        for (at, label) in &self.branches {
+            let target = self.labels.iter().find(|(l, _)| l == label).unwrap().1;
+            // The branch lands at the address after it, plus the offset, minus 2
+            let offset = (target + 2 - (at + 2)) as u16;
+            self.code[*at] = 0x80 | (offset >> 8) as u8 & 0x3F;
+            self.code[*at + 1] = offset as u8;
+        }
+        let mut story = vec![0; ORIGIN];
+        story[0x00] = 5;
+        // High memory and the initial PC at the code
+        story[0x04] = (ORIGIN >> 8) as u8;
+        story[0x06] = (ORIGIN >> 8) as u8;
+        // Object table at $0200, globals at $0100, static memory from the code on
+        story[0x0A] = 0x02;
+        story[0x0C] = 0x01;
+        story[0x0E] = (ORIGIN >> 8) as u8;
+        // A READ buffer at $0300 for up to 20 characters
+        story[0x300] = 20;
+        story.extend_from_slice(&self.code);
+        story.resize(story.len().next_multiple_of(4), 0);
+        let length = (story.len() / 4) as u16;
+        story[0x1A..0x1C].copy_from_slice(&length.to_be_bytes());
+        story
+    }
+}
+
+#[test]
+fn test_save_restore() {
+    // Read a command, save with G00 = 7, change it to 9 and read another command, then
+    // restore; the restored game sees SAVE's result 2 and G00 back at 7
+    let story = Assembler::default()
+        // AREAD $0300 #0 -> G02, STORE G00 #7
+        .bytes(&[0xE4, 0x1F, 0x03, 0x00, 0x00, 0x12])
+        .bytes(&[0x0D, 0x10, 0x07])
+        // SAVE -> G01
+        .bytes(&[0xBE, 0x00, 0xFF, 0x11])
+        // JE G01 #2 ?restored, JE G01 #1 ?saved
+        .bytes(&[0x41, 0x11, 0x02])
+        .branch("restored")
+        .bytes(&[0x41, 0x11, 0x01])
+        .branch("saved")
+        .print("Save failed\n")
+        .bytes(&[0xBA])
+        .label("saved")
+        .print("Saved\n")
+        // STORE G00 #9, AREAD $0300 #0 -> G02
+        .bytes(&[0x0D, 0x10, 0x09])
+        .bytes(&[0xE4, 0x1F, 0x03, 0x00, 0x00, 0x12])
+        // RESTORE -> G02
+        .bytes(&[0xBE, 0x01, 0xFF, 0x12])
+        .print("Restore failed\n")
+        .bytes(&[0xBA])
+        .label("restored")
+        .print("Restored: ")
+        // PRINT_NUM G00, QUIT
+        .bytes(&[0xE6, 0xBF, 0x10])
+        .print("\n")
+        .bytes(&[0xBA])
+        .story();
+
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let path = dir.path().join("round.z5");
+    fs::write(&path, story).expect("story written");
+    let output = play_in(
+        dir.path(),
+        Path::new("round.z5"),
+        &[
+            "%filename saved.ifzs",
+            "save",
+            "look",
+            "%filename saved.ifzs",
+            "restore",
+        ],
+    );
+    assert_checkpoints(&output, &["save", "Saved", "look", "Restored: 7"]);
+    assert!(!output.contains("failed"), "{}", output);
+    assert!(dir.path().join("saved.ifzs").exists());
+}